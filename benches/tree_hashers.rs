@@ -0,0 +1,71 @@
+//! Compares the Poseidon backends of the tree, on single nodes and on batches
+//! appended to a tree. Run with
+//! `cargo bench --features ark-hasher --bench tree_hashers`.
+
+use criterion::measurement::WallTime;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkGroup, Criterion};
+use semaphore::merkle_tree::Hasher;
+use semaphore::poseidon_tree::PoseidonHash;
+use signup_sequencer::identity_tree::{
+    ArkPoseidonHash, CanonicalTreeBuilder, FastPoseidonHash, Hash, TreeHasher,
+};
+
+const TREE_DEPTH: usize = 20;
+const DENSE_PREFIX_DEPTH: usize = 10;
+const BATCH_SIZE: usize = 100;
+
+fn hash_node(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("hash_node");
+    let left = Hash::from(0x1234_5678_u64);
+    let right = PoseidonHash::hash_node(&left, &Hash::ZERO);
+
+    group.bench_function("semaphore", |bencher| {
+        bencher.iter(|| PoseidonHash::hash_node(black_box(&left), black_box(&right)));
+    });
+    group.bench_function("fast", |bencher| {
+        bencher.iter(|| FastPoseidonHash::hash_node(black_box(&left), black_box(&right)));
+    });
+    group.bench_function("ark", |bencher| {
+        bencher.iter(|| ArkPoseidonHash::hash_node(black_box(&left), black_box(&right)));
+    });
+
+    group.finish();
+}
+
+fn append_batch(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("append_batch");
+
+    bench_append::<PoseidonHash>(&mut group, "semaphore");
+    bench_append::<FastPoseidonHash>(&mut group, "fast");
+    bench_append::<ArkPoseidonHash>(&mut group, "ark");
+
+    group.finish();
+}
+
+/// Appends a batch of identities to the latest version of a fresh tree.
+fn bench_append<H: TreeHasher>(group: &mut BenchmarkGroup<WallTime>, name: &str) {
+    let identities: Vec<Hash> = (1..=BATCH_SIZE as u64).map(Hash::from).collect();
+
+    group.bench_function(name, |bencher| {
+        bencher.iter_batched_ref(
+            || {
+                let temp_dir = tempfile::tempdir().unwrap();
+                let (_, processed_builder) = CanonicalTreeBuilder::<H>::new_with_hasher(
+                    TREE_DEPTH,
+                    DENSE_PREFIX_DEPTH,
+                    0,
+                    Hash::ZERO,
+                    &[],
+                    temp_dir.path().join("tree").to_str().unwrap(),
+                )
+                .seal();
+                (temp_dir, processed_builder.seal())
+            },
+            |(_, latest)| latest.append_many(black_box(&identities)),
+            BatchSize::PerIteration,
+        );
+    });
+}
+
+criterion_group!(benches, hash_node, append_batch);
+criterion_main!(benches);
@@ -1,7 +1,48 @@
+/// The ABI the identity manager bindings are generated from.
+#[cfg(feature = "sequencer")]
+const IDENTITY_MANAGER_ARTIFACT: &str = "sol/WorldIDIdentityManagerImplV2.json";
+
+/// The batch calls the sequencer submits. The build fails if the ABI doesn't
+/// have them, rather than the transactions reverting once deployed.
+#[cfg(feature = "sequencer")]
+const BATCH_CALLS: &[&str] = &[
+    "registerIdentities(uint256[8],uint256,uint32,uint256[],uint256)",
+    "deleteIdentities(uint256[8],bytes,uint256,uint256)",
+];
+
 fn main() {
     cli_batteries::build_rs().expect("Failed to setup build environment");
 
     println!("cargo:rerun-if-changed=build.rs");
     println!("cargo:rerun-if-changed=schemas");
     println!("cargo:rerun-if-changed=sol");
+
+    #[cfg(feature = "sequencer")]
+    generate_identity_manager_bindings();
+}
+
+/// Generates the bindings of the identity manager from its checked-in ABI,
+/// see `src/contracts/bindings.rs`.
+#[cfg(feature = "sequencer")]
+fn generate_identity_manager_bindings() {
+    let artifact = std::fs::read_to_string(IDENTITY_MANAGER_ARTIFACT)
+        .expect("Failed to read the identity manager artifact");
+    let artifact: serde_json::Value =
+        serde_json::from_str(&artifact).expect("Failed to parse the identity manager artifact");
+
+    for signature in BATCH_CALLS {
+        assert!(
+            artifact["methodIdentifiers"].get(signature).is_some(),
+            "{IDENTITY_MANAGER_ARTIFACT} has no {signature}, the sequencer's batch calls don't \
+             match the contract"
+        );
+    }
+
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    ethers_contract_abigen::Abigen::new("WorldIdIdentityManager", IDENTITY_MANAGER_ARTIFACT)
+        .expect("Failed to load the identity manager ABI")
+        .generate()
+        .expect("Failed to generate the identity manager bindings")
+        .write_to_file(std::path::Path::new(&out_dir).join("world_id_identity_manager.rs"))
+        .expect("Failed to write the identity manager bindings");
 }
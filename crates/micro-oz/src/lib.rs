@@ -9,7 +9,7 @@ use ethers::prelude::SignerMiddleware;
 use ethers::providers::{Http, Middleware, Provider};
 use ethers::signers::{LocalWallet, Signer};
 use ethers::types::transaction::eip2718::TypedTransaction;
-use ethers::types::{Eip1559TransactionRequest, U64};
+use ethers::types::{Eip1559TransactionRequest, U256, U64};
 use oz_api::data::transactions::{RelayerTransactionBase, SendBaseTransactionRequestOwned, Status};
 use tokio::sync::{mpsc, Mutex};
 
@@ -85,6 +85,7 @@ async fn runner_inner(inner: &Arc<PinheadInner>, tx_id: String) -> Result<(), an
     };
 
     inner.signer.fill_transaction(&mut typed_tx, None).await?;
+    let nonce = typed_tx.nonce().map(U256::as_u64);
 
     let pending_tx = inner.signer.send_transaction(typed_tx, None).await?;
 
@@ -93,6 +94,7 @@ async fn runner_inner(inner: &Arc<PinheadInner>, tx_id: String) -> Result<(), an
 
         tx_guard.status = Status::Pending;
         tx_guard.hash = Some(pending_tx.tx_hash());
+        tx_guard.nonce = nonce;
     }
 
     tracing::info!("Awaiting for receipt");
@@ -163,6 +165,7 @@ impl Pinhead {
             data:           tx_request.data,
             status:         Status::Pending,
             hash:           None,
+            nonce:          None,
             valid_until:    tx_request
                 .valid_until
                 .unwrap_or(Utc::now() + chrono::Duration::hours(24)),
@@ -99,4 +99,8 @@ pub struct RelayerTransactionBase {
     pub data:           Option<Bytes>,
     pub valid_until:    DateTime<Utc>,
     pub status:         Status,
+    /// Assigned once the relayer signs the transaction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub nonce:          Option<u64>,
 }
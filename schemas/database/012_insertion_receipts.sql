@@ -0,0 +1,5 @@
+CREATE TABLE insertion_receipts (
+    commitment BYTEA       NOT NULL PRIMARY KEY,
+    pre_root   BYTEA       NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
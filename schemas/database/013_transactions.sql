@@ -0,0 +1,8 @@
+CREATE TABLE transactions (
+    transaction_id TEXT        NOT NULL PRIMARY KEY,
+    pre_root       BYTEA       NOT NULL,
+    post_root      BYTEA       NOT NULL,
+    created_at     TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX transactions_post_root ON transactions (post_root);
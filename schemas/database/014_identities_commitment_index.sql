@@ -0,0 +1,3 @@
+-- The unique constraint on commitment, and the index backing it, was dropped
+-- in 004 so that lookups by commitment scan the whole table.
+CREATE INDEX identities_commitment ON identities (commitment, id);
@@ -0,0 +1,11 @@
+-- Batches which reverted when simulated and were therefore never broadcast,
+-- kept for inspection.
+CREATE TABLE quarantined_batches (
+    pre_root   BYTEA       NOT NULL,
+    post_root  BYTEA       NOT NULL,
+    kind       VARCHAR(50) NOT NULL,
+    reason     TEXT        NOT NULL,
+    calldata   BYTEA       NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (pre_root, post_root)
+);
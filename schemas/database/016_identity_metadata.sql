@@ -0,0 +1,7 @@
+-- Opaque metadata attached to an identity on insertion. It is only ever
+-- stored here, never submitted on chain.
+CREATE TABLE identity_metadata (
+    commitment BYTEA       NOT NULL PRIMARY KEY,
+    metadata   TEXT        NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
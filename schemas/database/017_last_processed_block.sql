@@ -0,0 +1,6 @@
+CREATE TABLE last_processed_block (
+    Lock char(1)                NOT NULL DEFAULT 'X',
+    block_number                BIGINT NOT NULL,
+    constraint PK_T3            PRIMARY KEY (Lock),
+    constraint CK_T3_Locked     CHECK (Lock='X')
+);
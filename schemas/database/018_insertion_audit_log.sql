@@ -0,0 +1,10 @@
+-- Every accepted insertion, with the address that signed it if the client
+-- authenticated the submission.
+CREATE TABLE insertion_audit_log (
+    id         BIGSERIAL   PRIMARY KEY,
+    commitment BYTEA       NOT NULL,
+    signer     BYTEA,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX insertion_audit_log_commitment ON insertion_audit_log (commitment);
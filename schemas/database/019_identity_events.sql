@@ -0,0 +1,56 @@
+-- Append-only log of the lifecycle of every identity, from which its status
+-- is derived.
+CREATE TABLE identity_events (
+    id         BIGSERIAL   PRIMARY KEY,
+    commitment BYTEA       NOT NULL,
+    event      VARCHAR(16) NOT NULL,
+    message    TEXT,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX identity_events_commitment ON identity_events (commitment, id);
+
+-- Backfill the lifecycle of the identities recorded so far, in the order of
+-- the events. Deletions are recorded as the zero commitment at the leaf of the
+-- deleted identity.
+INSERT INTO identity_events (commitment, event, message, created_at)
+SELECT commitment, 'received', NULL, created_at
+FROM unprocessed_identities
+ORDER BY created_at;
+
+INSERT INTO identity_events (commitment, event, message, created_at)
+SELECT commitment, 'failed', error_message, COALESCE(processed_at, created_at)
+FROM unprocessed_identities
+WHERE status = 'failed'
+ORDER BY created_at;
+
+INSERT INTO identity_events (commitment, event, message, created_at)
+SELECT commitment, 'validated', NULL, pending_as_of
+FROM identities
+WHERE commitment <> decode(repeat('00', 32), 'hex')
+ORDER BY id;
+
+INSERT INTO identity_events (commitment, event, message, created_at)
+SELECT commitment, 'mined', NULL, COALESCE(mined_at, pending_as_of)
+FROM identities
+WHERE commitment <> decode(repeat('00', 32), 'hex')
+  AND status IN ('processed', 'mined')
+ORDER BY id;
+
+INSERT INTO identity_events (commitment, event, message, created_at)
+SELECT commitment, 'finalized', NULL, COALESCE(mined_at, pending_as_of)
+FROM identities
+WHERE commitment <> decode(repeat('00', 32), 'hex')
+  AND status = 'mined'
+ORDER BY id;
+
+INSERT INTO identity_events (commitment, event, message, created_at)
+SELECT inserted.commitment, 'deleted', NULL, COALESCE(deletion.mined_at, deletion.pending_as_of)
+FROM identities AS deletion
+JOIN identities AS inserted
+  ON inserted.leaf_index = deletion.leaf_index
+ AND inserted.id < deletion.id
+ AND inserted.commitment <> decode(repeat('00', 32), 'hex')
+WHERE deletion.commitment = decode(repeat('00', 32), 'hex')
+  AND deletion.status IN ('processed', 'mined')
+ORDER BY deletion.id;
@@ -0,0 +1,7 @@
+-- The transaction which published each mined root.
+CREATE TABLE root_transactions (
+    root             BYTEA       NOT NULL PRIMARY KEY,
+    transaction_hash BYTEA       NOT NULL,
+    block_number     BIGINT      NOT NULL,
+    block_timestamp  TIMESTAMPTZ NOT NULL
+);
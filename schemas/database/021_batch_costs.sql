@@ -0,0 +1,11 @@
+-- Gas spent on each mined batch transaction, for cost reporting.
+CREATE TABLE batch_costs (
+    transaction_hash    BYTEA       NOT NULL PRIMARY KEY,
+    kind                TEXT        NOT NULL,
+    identity_count      BIGINT      NOT NULL,
+    gas_used            BIGINT      NOT NULL,
+    effective_gas_price NUMERIC     NOT NULL,
+    block_timestamp     TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX batch_costs_block_timestamp ON batch_costs (block_timestamp);
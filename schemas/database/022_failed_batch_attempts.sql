@@ -0,0 +1,8 @@
+-- Quarantined batches double as a dead-letter store: failures are counted,
+-- and batch submission is held once a batch failed too often, until an
+-- operator retries or discards it.
+ALTER TABLE quarantined_batches
+    ADD COLUMN attempts       INT         NOT NULL DEFAULT 1,
+    ADD COLUMN last_failed_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP;
+
+UPDATE quarantined_batches SET last_failed_at = created_at;
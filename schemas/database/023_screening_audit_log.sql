@@ -0,0 +1,12 @@
+-- Every screened submission with the decision taken, including submissions
+-- which were denied and never inserted.
+CREATE TABLE screening_audit_log (
+    id           BIGSERIAL   PRIMARY KEY,
+    commitment   BYTEA       NOT NULL,
+    signer       BYTEA,
+    jurisdiction TEXT,
+    decision     TEXT        NOT NULL,
+    created_at   TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX screening_audit_log_commitment ON screening_audit_log (commitment);
@@ -0,0 +1,28 @@
+-- Accepted insertions are numbered in the order they were accepted,
+-- independently of the leaf indices they're assigned later. Existing receipts
+-- are numbered by their creation time.
+CREATE SEQUENCE insertion_sequence_numbers AS BIGINT;
+
+ALTER TABLE insertion_receipts ADD COLUMN sequence_number BIGINT;
+
+UPDATE insertion_receipts
+SET sequence_number = numbered.sequence_number
+FROM (
+    SELECT commitment, ROW_NUMBER() OVER (ORDER BY created_at, commitment) AS sequence_number
+    FROM insertion_receipts
+) AS numbered
+WHERE insertion_receipts.commitment = numbered.commitment;
+
+SELECT setval(
+    'insertion_sequence_numbers',
+    COALESCE((SELECT MAX(sequence_number) FROM insertion_receipts), 0) + 1,
+    false
+);
+
+ALTER TABLE insertion_receipts
+    ALTER COLUMN sequence_number SET DEFAULT nextval('insertion_sequence_numbers'),
+    ALTER COLUMN sequence_number SET NOT NULL;
+
+ALTER SEQUENCE insertion_sequence_numbers OWNED BY insertion_receipts.sequence_number;
+
+CREATE UNIQUE INDEX insertion_receipts_sequence_number ON insertion_receipts (sequence_number);
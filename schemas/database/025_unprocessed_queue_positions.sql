@@ -0,0 +1,27 @@
+-- Identities are assigned leaf indices in the order they were queued. Queued
+-- identities are numbered by their creation time.
+CREATE SEQUENCE unprocessed_queue_positions AS BIGINT;
+
+ALTER TABLE unprocessed_identities ADD COLUMN queue_position BIGINT;
+
+UPDATE unprocessed_identities
+SET queue_position = numbered.queue_position
+FROM (
+    SELECT commitment, ROW_NUMBER() OVER (ORDER BY created_at, commitment) AS queue_position
+    FROM unprocessed_identities
+) AS numbered
+WHERE unprocessed_identities.commitment = numbered.commitment;
+
+SELECT setval(
+    'unprocessed_queue_positions',
+    COALESCE((SELECT MAX(queue_position) FROM unprocessed_identities), 0) + 1,
+    false
+);
+
+ALTER TABLE unprocessed_identities
+    ALTER COLUMN queue_position SET DEFAULT nextval('unprocessed_queue_positions'),
+    ALTER COLUMN queue_position SET NOT NULL;
+
+ALTER SEQUENCE unprocessed_queue_positions OWNED BY unprocessed_identities.queue_position;
+
+CREATE INDEX unprocessed_identities_queue_position ON unprocessed_identities (queue_position);
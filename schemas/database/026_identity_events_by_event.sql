@@ -0,0 +1,3 @@
+-- Recently mined identities are looked up to estimate how long queued ones
+-- will take.
+CREATE INDEX identity_events_event ON identity_events (event, created_at);
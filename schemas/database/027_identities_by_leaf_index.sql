@@ -0,0 +1,2 @@
+-- Pruning looks up the later updates of the same leaf.
+CREATE INDEX identities_leaf_index ON identities (leaf_index, id);
@@ -0,0 +1,11 @@
+-- What each batch handed to the signer consisted of and took, reported along
+-- with the transaction which mined it. Batches submitted earlier have none.
+ALTER TABLE transactions
+    ADD COLUMN kind              TEXT,
+    ADD COLUMN first_leaf_index  BIGINT,
+    ADD COLUMN last_leaf_index   BIGINT,
+    ADD COLUMN identity_count    BIGINT,
+    ADD COLUMN prover_latency_ms BIGINT,
+    ADD COLUMN calldata_size     BIGINT;
+
+CREATE INDEX transactions_created_at ON transactions (created_at);
@@ -0,0 +1,8 @@
+-- The last identity event published to the status topic of the insertion
+-- queue, publishing resumes after it.
+CREATE TABLE last_published_identity_event (
+    Lock     char(1) NOT NULL DEFAULT 'X',
+    event_id BIGINT  NOT NULL,
+    constraint PK_last_published_identity_event PRIMARY KEY (Lock),
+    constraint CK_last_published_identity_event_Locked CHECK (Lock='X')
+);
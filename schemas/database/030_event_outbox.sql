@@ -0,0 +1,24 @@
+-- Lifecycle events waiting to be published to the event bus. Rows are added
+-- in the transaction logging the identity event, so that no event is lost if
+-- the sequencer crashes before publishing it, and deleted once published.
+CREATE TABLE event_outbox (
+    id         BIGINT      PRIMARY KEY,
+    commitment BYTEA       NOT NULL,
+    event      VARCHAR(16) NOT NULL,
+    message    TEXT,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE FUNCTION enqueue_lifecycle_event() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO event_outbox (id, commitment, event, message, created_at)
+    VALUES (NEW.id, NEW.commitment, NEW.event, NEW.message, NEW.created_at);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER identity_events_outbox
+    AFTER INSERT ON identity_events
+    FOR EACH ROW
+    WHEN (NEW.event IN ('received', 'submitted', 'mined', 'failed'))
+    EXECUTE FUNCTION enqueue_lifecycle_event();
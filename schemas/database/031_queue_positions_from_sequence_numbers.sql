@@ -0,0 +1,17 @@
+-- Queued identities are assigned leaf indices in the order of their sequence
+-- numbers, so queue positions are drawn from the sequence numbering accepted
+-- insertions. Identities already queued keep their positions, ahead of the
+-- ones queued from now on.
+SELECT setval(
+    'insertion_sequence_numbers',
+    GREATEST(
+        (SELECT COALESCE(MAX(sequence_number), 0) FROM insertion_receipts),
+        (SELECT COALESCE(MAX(queue_position), 0) FROM unprocessed_identities)
+    ) + 1,
+    false
+);
+
+ALTER TABLE unprocessed_identities
+    ALTER COLUMN queue_position SET DEFAULT nextval('insertion_sequence_numbers');
+
+DROP SEQUENCE unprocessed_queue_positions;
@@ -0,0 +1,2 @@
+-- `/roots` filters mined roots by the block which published them.
+CREATE INDEX root_transactions_block_number ON root_transactions (block_number);
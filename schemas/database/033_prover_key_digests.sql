@@ -0,0 +1,3 @@
+-- The digest of the proving key each prover is expected to serve, unchecked
+-- where it's unset.
+ALTER TABLE provers ADD COLUMN key_digest BYTEA;
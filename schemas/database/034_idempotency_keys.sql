@@ -0,0 +1,13 @@
+-- Responses to insertions with an `Idempotency-Key` header, replayed to
+-- retries with the same key. The response is unset while the first request
+-- is being processed.
+CREATE TABLE idempotency_keys (
+    key          TEXT        NOT NULL PRIMARY KEY,
+    fingerprint  BYTEA       NOT NULL,
+    status       SMALLINT,
+    content_type TEXT,
+    body         BYTEA,
+    created_at   TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idempotency_keys_created_at ON idempotency_keys (created_at);
@@ -0,0 +1,21 @@
+-- The block of the transaction which mined each update, so that leaves can be
+-- listed by when they were registered on chain.
+ALTER TABLE identities ADD COLUMN mined_block BIGINT;
+
+-- Updates already on chain are attributed to the first recorded root
+-- transaction at or after them. Batches whose transaction wasn't recorded are
+-- attributed to the next one that was, i.e. to a later block.
+UPDATE identities
+SET    mined_block = batches.block_number
+FROM (
+    SELECT LAG(post.id, 1, 0::BIGINT) OVER (ORDER BY post.id) AS pre_id,
+           post.id AS post_id,
+           root_transactions.block_number
+    FROM   root_transactions
+    JOIN LATERAL (
+        SELECT MIN(id) AS id FROM identities WHERE root = root_transactions.root
+    ) post ON post.id IS NOT NULL
+) batches
+WHERE  identities.id > batches.pre_id
+AND    identities.id <= batches.post_id
+AND    identities.status IN ('processed', 'mined');
@@ -0,0 +1,27 @@
+-- Receipts carry the leaf an insertion was assigned and the root that leaf was
+-- applied to, both unknown until the insertion leaves the queue. The latest
+-- root at the time of acceptance was recorded instead, which isn't the root
+-- the leaf is applied to.
+ALTER TABLE insertion_receipts ADD COLUMN leaf_index BIGINT;
+ALTER TABLE insertion_receipts ALTER COLUMN pre_root DROP NOT NULL;
+
+UPDATE insertion_receipts SET pre_root = NULL;
+
+-- Leaves already assigned are applied to the root of the update before them.
+-- The first leaf of the tree is applied to the initial root, which is left
+-- unset.
+UPDATE insertion_receipts
+SET    leaf_index = inserted.leaf_index,
+       pre_root   = (
+           SELECT   root
+           FROM     identities
+           WHERE    id < inserted.id
+           ORDER BY id DESC
+           LIMIT    1
+       )
+FROM (
+    SELECT DISTINCT ON (commitment) id, commitment, leaf_index
+    FROM   identities
+    ORDER  BY commitment, id
+) inserted
+WHERE  insertion_receipts.commitment = inserted.commitment;
@@ -0,0 +1,15 @@
+-- The leaf each identity was inserted at. Unlike its updates in identities,
+-- which are pruned once superseded, the leaf of an identity is kept after it's
+-- deleted, as leaves are never reused.
+CREATE TABLE leaf_indices (
+    commitment BYTEA  NOT NULL PRIMARY KEY,
+    leaf_index BIGINT NOT NULL
+);
+
+INSERT INTO leaf_indices (commitment, leaf_index)
+SELECT DISTINCT ON (commitment) commitment, leaf_index
+FROM   identities
+WHERE  commitment <> '\x0000000000000000000000000000000000000000000000000000000000000000'
+ORDER  BY commitment, id;
+
+CREATE INDEX leaf_indices_leaf_index ON leaf_indices (leaf_index);
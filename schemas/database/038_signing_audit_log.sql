@@ -0,0 +1,17 @@
+-- Every transaction handed to the signer and every digest signed on behalf of
+-- the sequencer, see `SigningLog`.
+CREATE TABLE signing_audit_log (
+    id             BIGSERIAL   PRIMARY KEY,
+    signer         BYTEA       NOT NULL,
+    purpose        TEXT        NOT NULL,
+    payload_digest BYTEA       NOT NULL,
+    -- Filled in once the relayer assigned it
+    nonce          NUMERIC,
+    chain_id       BIGINT      NOT NULL,
+    outcome        TEXT        NOT NULL,
+    transaction_id TEXT,
+    created_at     TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX signing_audit_log_created_at ON signing_audit_log (created_at);
+CREATE INDEX signing_audit_log_transaction_id ON signing_audit_log (transaction_id);
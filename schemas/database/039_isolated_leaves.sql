@@ -0,0 +1,5 @@
+-- A leaf isolated from a reverting insertion batch holds batch submission
+-- until an operator retries or discards it, the leaves after it can't be
+-- inserted before it.
+ALTER TABLE quarantined_batches
+    ADD COLUMN isolated BOOLEAN NOT NULL DEFAULT FALSE;
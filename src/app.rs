@@ -1,33 +1,101 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Instant;
 
-use anyhow::Result as AnyhowResult;
-use chrono::{Duration, Utc};
+use anyhow::{anyhow, ensure, Result as AnyhowResult};
+use axum::http::HeaderMap;
+use chrono::{DateTime, Duration, Utc};
 use clap::Parser;
+use ethers::types::{Address, Bytes, H256, U256};
 use ruint::Uint;
 use semaphore::poseidon_tree::LazyPoseidonTree;
 use semaphore::protocol::verify_proof;
+use serde::Deserialize;
+use tokio::task::JoinHandle;
 use tracing::{info, instrument, warn};
 
 use crate::contracts::{IdentityManager, SharedIdentityManager};
+use crate::database::types::NewInsertion;
 use crate::database::{self, Database};
+use crate::ethereum::read::circuit_breaker::BreakerStatus;
+use crate::ethereum::signing_log::SigningLog;
+use crate::ethereum::write::TransactionId;
 use crate::ethereum::{self, Ethereum};
+use crate::idempotency::{IdempotencyKeys, Reservation};
 use crate::identity_tree::{
-    CanonicalTreeBuilder, Hash, InclusionProof, ProcessedStatus, RootItem, Status, TreeState,
-    TreeUpdate, TreeVersionReadOps, UnprocessedStatus,
+    CanonicalTreeBuilder, Hash, IdentityEvent, InclusionProof, ProcessedStatus, RangeProof,
+    RemovedStatus, RootItem, Status, TreeNodes, TreeSnapshot, TreeState, TreeUpdate,
+    TreeVersionReadOps, UnprocessedStatus, VersionGcReport,
 };
+use crate::insertion_challenge::InsertionChallenge;
+use crate::insertion_screening::{InsertionScreening, ScreeningDecision, ScreeningSubject};
+use crate::insertion_signatures::InsertionAuthenticator;
+use crate::insertion_writer::InsertionWriter;
 use crate::prover::map::initialize_prover_maps;
 use crate::prover::{self, ProverConfiguration, ProverType, Provers};
+use crate::queue_estimate::QueueEstimator;
+use crate::receipts::{InsertionReceipt, NonInclusionAttestation, ReceiptSigner};
+use crate::redaction::redacted;
+use crate::secret::SecretUrl;
+use crate::serde_utils::JsonStrWrapper;
 use crate::server::data::{
-    IdentityHistoryEntry, IdentityHistoryEntryKind, IdentityHistoryEntryStatus,
-    InclusionProofResponse, ListBatchSizesResponse, VerifySemaphoreProofQuery,
-    VerifySemaphoreProofRequest, VerifySemaphoreProofResponse,
+    BatchesResponse, CheckRootResponse, CostsResponse, FailedBatchEntry, FailedBatchesResponse,
+    HealthResponse, HealthStatus, IdentityHistoryEntry, IdentityHistoryEntryKind,
+    IdentityHistoryEntryStatus, IdentityStatusEvent, IdentityStatusResponse,
+    InclusionProofResponse, LeafItem, LeavesQuery, LeavesResponse, ListBatchSizesResponse,
+    ProverKeysResponse, QueueCounts, QueueEntry, QueueEstimate, QueueResponse, RestoreTreeResponse,
+    RollBackInsertionsResponse, RootHistoryItem, RootsQuery, RootsResponse, SignerActivityResponse,
+    SignerHealth, SyncSnapshot, SyncUpdate, SyncUpdatesResponse, TreeInfoResponse,
+    VerifySemaphoreProofQuery, VerifySemaphoreProofRequest, VerifySemaphoreProofResponse,
+    VersionResponse,
 };
 use crate::server::error::Error as ServerError;
+use crate::services::{BatchService, ChainService, TreeService};
+use crate::task_monitor::tasks::monitor_balance::FundsStatus;
 use crate::task_monitor::TaskMonitor;
+use crate::tree_capacity::TreeCapacity;
+use crate::utils::cpu_pool::CpuPools;
+use crate::utils::proof_limiter::ProofLimiter;
+use crate::utils::retry;
 use crate::utils::tree_updates::dedup_tree_updates;
-use crate::{contracts, task_monitor};
+use crate::{
+    contracts, event_bus, fault_injection, idempotency, insertion_challenge, insertion_screening,
+    insertion_signatures, insertion_writer, queue_estimate, receipts, task_monitor, tree_capacity,
+};
+
+/// How often the database is checked for tree updates while a follower waits
+/// for them.
+const SYNC_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Maximum size of the metadata attached to an identity on insertion.
+pub const MAX_METADATA_SIZE: usize = 1024;
+
+const DEFAULT_QUEUE_LIMIT: i64 = 1000;
+const MAX_QUEUE_LIMIT: i64 = 10_000;
+
+/// Reported for insertions rolled back through `/admin/rollBackInsertions`.
+const ROLLED_BACK_MESSAGE: &str = "Rolled back by an administrator.";
+/// Reported for the insertions of failed batches discarded through
+/// `/admin/failedBatches`.
+const DISCARDED_MESSAGE: &str = "Discarded with its failed batch by an administrator.";
+/// Reported for the insertions which weren't mined when the tree was restored
+/// from a snapshot, through `/admin/restoreTree` or the `restore-tree` command.
+pub(crate) const RESTORED_MESSAGE: &str = "Discarded by a restore of the tree from a snapshot.";
+
+/// Maximum number of leaves proven at once by `/rangeProof`.
+const MAX_RANGE_PROOF_LEAVES: usize = 10_000;
+
+const DEFAULT_BATCHES_LIMIT: i64 = 100;
+const MAX_BATCHES_LIMIT: i64 = 1000;
+
+const DEFAULT_ROOTS_LIMIT: i64 = 100;
+const MAX_ROOTS_LIMIT: i64 = 1000;
+
+const DEFAULT_LEAVES_LIMIT: i64 = 1000;
+const MAX_LEAVES_LIMIT: i64 = 10000;
 
 #[derive(Clone, Debug, PartialEq, Parser)]
 #[group(skip)]
@@ -47,6 +115,36 @@ pub struct Options {
     #[clap(flatten)]
     pub committer: task_monitor::Options,
 
+    #[clap(flatten)]
+    pub receipts: receipts::Options,
+
+    #[clap(flatten)]
+    pub insertion_signatures: insertion_signatures::Options,
+
+    #[clap(flatten)]
+    pub insertion_challenge: insertion_challenge::Options,
+
+    #[clap(flatten)]
+    pub insertion_screening: insertion_screening::Options,
+
+    #[clap(flatten)]
+    pub tree_capacity: tree_capacity::Options,
+
+    #[clap(flatten)]
+    pub insertion_writes: insertion_writer::Options,
+
+    #[clap(flatten)]
+    pub idempotency: idempotency::Options,
+
+    #[clap(flatten)]
+    pub queue_estimate: queue_estimate::Options,
+
+    #[clap(flatten)]
+    pub event_bus: event_bus::Options,
+
+    #[clap(flatten)]
+    pub retry: retry::Options,
+
     /// Block number to start syncing from
     #[clap(long, env, default_value = "0")]
     pub starting_block: u64,
@@ -70,53 +168,315 @@ pub struct Options {
     /// If set will not use cached tree state.
     #[clap(long, env)]
     pub force_cache_purge: bool,
+
+    /// Number of threads hashing batches into the tree. 0 uses one per core.
+    #[clap(long, env, default_value = "0")]
+    pub tree_pool_threads: usize,
+
+    /// Number of threads verifying proofs and building merkle proofs. 0 uses
+    /// one per core.
+    #[clap(long, env, default_value = "0")]
+    pub proof_pool_threads: usize,
+
+    /// Maximum number of jobs queued on each of the CPU pools, callers wait for
+    /// a slot once it's full.
+    #[clap(long, env, default_value = "1024")]
+    pub cpu_pool_queue_size: usize,
+
+    /// Maximum number of proofs built or verified at once. 0 doesn't limit
+    /// them.
+    #[clap(long, env, default_value = "64")]
+    pub max_concurrent_proofs: usize,
+
+    /// Maximum number of proof requests waiting for one of the
+    /// `max_concurrent_proofs` slots. Requests beyond it are rejected with
+    /// `429 Too Many Requests`.
+    #[clap(long, env, default_value = "256")]
+    pub max_queued_proofs: usize,
+
+    /// Path of a binary snapshot of the mined tree. It's written on startup
+    /// and shutdown, and inclusion proofs of mined identities are served from
+    /// it while the tree is rebuilt on the next start.
+    #[clap(long, env)]
+    pub tree_snapshot_file: Option<String>,
+
+    /// Compresses the tree snapshot with zstd at the given level (1-22).
+    /// Compressed snapshots are decompressed into memory on startup, rather
+    /// than memory-mapped.
+    #[clap(long, env)]
+    pub tree_snapshot_zstd_level: Option<i32>,
+
+    /// zstd level of `/treeNodes` responses to clients accepting the `zstd`
+    /// encoding.
+    #[clap(long, env, default_value = "3")]
+    pub export_zstd_level: i32,
+
+    /// Number of recent blocks scanned on startup for insertion batches, so
+    /// that queued identities which were already mined aren't batched again.
+    /// Set to 0 to disable.
+    #[clap(long, env, default_value = "10000")]
+    pub startup_reconciliation_blocks: u64,
+
+    /// Root the tree must have once it's rebuilt on startup, or `chain` for
+    /// the latest root of the identity manager contract. The sequencer
+    /// refuses to start if the roots differ.
+    #[clap(long, env)]
+    pub expected_root: Option<ExpectedRoot>,
+
+    /// Trees hosted next to the one of `--identity-manager-address`, e.g. one
+    /// per app, as a JSON array of `{"id": "app", "identityManagerAddress":
+    /// "0x..", "treeDepth": 20, "database": "postgres://.."}` with optional
+    /// `initialLeafValue` and `semaphoreGroupId` (hex). Each group is served
+    /// under `/groups/<id>` and persisted in its own database.
+    #[clap(long, env, default_value = "[]")]
+    pub groups: JsonStrWrapper<Vec<GroupOptions>>,
+
+    /// Name of the environment the sequencer runs in, e.g. `staging`. Logged
+    /// at startup and reported by `/version`.
+    #[clap(long, env)]
+    pub environment: Option<String>,
 }
 
-pub struct App {
-    database:           Arc<Database>,
-    identity_manager:   SharedIdentityManager,
-    identity_committer: Arc<TaskMonitor>,
-    tree_state:         TreeState,
-    snark_scalar_field: Hash,
+/// A tree hosted in addition to the default one, see [`Options::groups`].
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupOptions {
+    /// Names the group in routes
+    pub id: String,
+    pub identity_manager_address: Address,
+    pub tree_depth: usize,
+    #[serde(default)]
+    pub initial_leaf_value: Hash,
+    #[serde(default)]
+    pub semaphore_group_id: U256,
+    pub database: SecretUrl,
 }
 
-impl App {
+impl Options {
+    /// The options of the app of each group, by group id. Groups share
+    /// everything but their contract, tree and database with the default
+    /// tree.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if a group id can't be used in routes or is not
+    /// unique, or if groups share a database.
+    pub fn group_options(&self) -> AnyhowResult<Vec<(String, Self)>> {
+        let mut ids = HashSet::new();
+        let mut databases = HashSet::from([self.database.database.expose()]);
+
+        for group in &self.groups.0 {
+            ensure!(
+                !group.id.is_empty()
+                    && group
+                        .id
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+                "Invalid group id {:?}, only letters, digits, - and _ are allowed",
+                group.id
+            );
+            ensure!(ids.insert(&group.id), "Duplicate group id {}", group.id);
+            ensure!(
+                databases.insert(group.database.expose()),
+                "Group {} shares its database with another tree",
+                group.id
+            );
+        }
+
+        Ok(self
+            .groups
+            .0
+            .iter()
+            .map(|group| (group.id.clone(), self.for_group(group)))
+            .collect())
+    }
+
+    fn for_group(&self, group: &GroupOptions) -> Self {
+        let mut options = self.clone();
+        options.groups = JsonStrWrapper(vec![]);
+
+        let contracts = &mut options.contracts;
+        contracts.identity_manager_address = group.identity_manager_address;
+        contracts.tree_depth = group.tree_depth;
+        contracts.initial_leaf_value = group.initial_leaf_value;
+        contracts.semaphore_group_id = group.semaphore_group_id;
+        // Roots are only relayed and batches only shadowed for the default
+        // tree.
+        contracts.relayed_identity_manager_addresses = JsonStrWrapper(HashMap::new());
+        contracts.shadow_identity_manager_address = None;
+
+        options.database.database = group.database.clone();
+        // Files written by the tree must not collide with the other trees'.
+        options.dense_tree_mmap_file = format!("{}.{}", self.dense_tree_mmap_file, group.id);
+        options.tree_snapshot_file = self
+            .tree_snapshot_file
+            .as_ref()
+            .map(|path| format!("{path}.{}", group.id));
+        // The expected root is the one of the default tree.
+        options.expected_root = None;
+
+        options
+    }
+}
+
+/// Root pinned with `--expected-root`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExpectedRoot {
+    Chain,
+    Root(Hash),
+}
+
+impl FromStr for ExpectedRoot {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("chain") {
+            return Ok(Self::Chain);
+        }
+
+        Hash::from_str(s)
+            .map(Self::Root)
+            .map_err(|error| anyhow!("invalid root {s}: {error}"))
+    }
+}
+
+/// Modulus of the scalar field of the snark, commitments have to be below it.
+pub(crate) fn snark_scalar_field() -> Hash {
+    // TODO Export the reduced-ness check that this is enabling from the
+    //  `semaphore-rs` library when we bump the version.
+    Hash::from_str_radix(
+        "21888242871839275222246405745257275088548364400416034343698204186575808495617",
+        10,
+    )
+    .expect("This should just parse.")
+}
+
+/// Progress of rebuilding the tree on startup, which is reported while the
+/// server already accepts requests.
+#[derive(Debug, Default)]
+pub struct StartupProgress {
+    total: AtomicUsize,
+    done:  AtomicUsize,
+}
+
+impl StartupProgress {
+    fn reset(&self, total: usize) {
+        self.total.store(total, Ordering::Relaxed);
+        self.done.store(0, Ordering::Relaxed);
+    }
+
+    fn advance(&self, count: usize) {
+        self.done.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Percentage of the tree updates applied so far.
+    #[must_use]
+    pub fn percentage(&self) -> f64 {
+        let total = self.total.load(Ordering::Relaxed);
+        let done = self.done.load(Ordering::Relaxed);
+
+        if total == 0 {
+            return 0.0;
+        }
+
+        (done.min(total) as f64 / total as f64) * 100.0
+    }
+}
+
+/// Builds an [`App`], or only the parts of it needed to serve proofs.
+pub struct AppBuilder {
+    options:        Options,
+    progress:       Arc<StartupProgress>,
+    start_batching: bool,
+    api_only:       bool,
+}
+
+impl AppBuilder {
+    #[must_use]
+    pub fn new(options: Options) -> Self {
+        Self {
+            options,
+            progress: Arc::default(),
+            start_batching: true,
+            api_only: false,
+        }
+    }
+
+    /// Reports the progress of rebuilding the tree to `progress`.
+    #[must_use]
+    pub fn with_progress(mut self, progress: Arc<StartupProgress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Doesn't start submitting batches, e.g. for an app which only serves
+    /// proofs. They can be started later through [`App::batch_service`].
+    #[must_use]
+    pub fn without_batching(mut self) -> Self {
+        self.start_batching = false;
+        self
+    }
+
+    /// Only serves the API next to a `process` instance, which owns batching
+    /// and submission. Nothing but the queues is written to the database, and
+    /// the tree follows the one written by the other instance.
+    #[must_use]
+    pub fn api_only(mut self) -> Self {
+        self.start_batching = false;
+        self.api_only = true;
+        self
+    }
+
     /// # Errors
     ///
     /// Will return `Err` if the internal Ethereum handler errors or if the
     /// `options.storage_file` is not accessible.
-    #[instrument(name = "App::new", level = "debug")]
-    pub async fn new(options: Options) -> AnyhowResult<Self> {
-        let ethereum = Ethereum::new(options.ethereum);
-        let db = Database::new(options.database);
-
-        let (ethereum, db) = tokio::try_join!(ethereum, db)?;
+    #[instrument(name = "App::new", level = "debug", skip_all)]
+    pub async fn build(self) -> AnyhowResult<App> {
+        let Self {
+            options,
+            progress,
+            start_batching,
+            api_only,
+        } = self;
+        let progress = progress.as_ref();
+
+        fault_injection::arm_from_env()?;
+
+        let retry = options.retry.policy();
+        // Signing operations are audited in the database, so it has to be
+        // connected before anything is signed.
+        let database = Arc::new(Database::new(options.database, retry).await?);
+        let signing_log = Arc::new(SigningLog::new(database.clone()));
+        let ethereum = Ethereum::new(options.ethereum, retry, signing_log.clone()).await?;
 
-        let database = Arc::new(db);
         let mut provers: HashSet<ProverConfiguration> = database.get_provers().await?;
 
-        let non_inserted_provers = Self::merge_env_provers(options.batch_provers, &mut provers);
+        let non_inserted_provers = App::merge_env_provers(options.batch_provers, &mut provers);
 
-        database.insert_provers(non_inserted_provers).await?;
+        if !api_only {
+            database.insert_provers(non_inserted_provers).await?;
+        }
 
-        let (insertion_prover_map, deletion_prover_map) = initialize_prover_maps(provers)?;
+        let (insertion_prover_map, deletion_prover_map) = initialize_prover_maps(provers, retry)?;
 
         let identity_manager = IdentityManager::new(
             options.contracts,
             ethereum.clone(),
             insertion_prover_map,
             deletion_prover_map,
+            retry,
         )
         .await?;
 
         let identity_manager = Arc::new(identity_manager);
 
-        // Await for all pending transactions
-        identity_manager.await_clean_slate().await?;
-
-        // Prefetch latest root & mark it as mined
-        let root_hash = identity_manager.latest_root().await?;
-        let root_hash = root_hash.into();
+        if !api_only {
+            tokio::spawn({
+                let identity_manager = identity_manager.clone();
+                async move { identity_manager.verify_prover_keys().await }
+            });
+        }
 
         let initial_root_hash = LazyPoseidonTree::new(
             identity_manager.tree_depth(),
@@ -124,20 +484,31 @@ impl App {
         )
         .root();
 
-        // We don't store the initial root in the database, so we have to skip this step
-        // if the contract root hash is equal to initial root hash
-        if root_hash != initial_root_hash {
-            // Note that we don't have a way of queuing a root here for finalization.
-            // so it's going to stay as "processed" until the next root is mined.
-            database.mark_root_as_processed(&root_hash).await?;
+        // The chain state is reconciled with the database by the instance
+        // processing batches.
+        let root_hash = if api_only {
+            None
         } else {
-            // Db is either empty or we're restarting with a new contract/chain
-            // so we should mark everything as pending
-            database.mark_all_as_pending().await?;
-        }
+            Some(
+                App::reconcile_chain_state(
+                    &database,
+                    &identity_manager,
+                    initial_root_hash,
+                    options.startup_reconciliation_blocks,
+                    options.committer.scanning_window_size,
+                )
+                .await?,
+            )
+        };
+
+        let cpu_pools = CpuPools::new(
+            options.tree_pool_threads,
+            options.proof_pool_threads,
+            options.cpu_pool_queue_size,
+        )?;
 
         let timer = Instant::now();
-        let mut tree_state = Self::restore_or_initialize_tree(
+        let mut tree_state = App::restore_or_initialize_tree(
             &database,
             // Poseidon tree depth is one more than the contract's tree depth
             identity_manager.tree_depth(),
@@ -147,19 +518,20 @@ impl App {
             initial_root_hash,
             &options.dense_tree_mmap_file,
             options.force_cache_purge,
+            progress,
         )
         .await?;
         info!("Tree state initialization took: {:?}", timer.elapsed());
 
         let tree_root = tree_state.get_processed_tree().get_root();
 
-        if tree_root != root_hash {
+        if root_hash.is_some_and(|root_hash| tree_root != root_hash) {
             warn!(
                 "Cached tree root is different from the contract root. Purging cache and \
                  reinitializing."
             );
 
-            tree_state = Self::restore_or_initialize_tree(
+            tree_state = App::restore_or_initialize_tree(
                 &database,
                 // Poseidon tree depth is one more than the contract's tree depth
                 identity_manager.tree_depth(),
@@ -169,40 +541,317 @@ impl App {
                 initial_root_hash,
                 &options.dense_tree_mmap_file,
                 true,
+                progress,
             )
             .await?;
         }
 
+        if let Some(expected_root) = options.expected_root {
+            App::verify_expected_root(expected_root, root_hash, &identity_manager, &tree_state)
+                .await?;
+        }
+
+        // Only instances submitting batches publish lifecycle events.
+        let event_sink = if start_batching {
+            event_bus::connect(&options.event_bus).await?
+        } else {
+            None
+        };
+
         let identity_committer = Arc::new(TaskMonitor::new(
             database.clone(),
             identity_manager.clone(),
             tree_state.clone(),
+            cpu_pools.tree.clone(),
             &options.committer,
-        ));
-
-        // TODO Export the reduced-ness check that this is enabling from the
-        //  `semaphore-rs` library when we bump the version.
-        let snark_scalar_field = Hash::from_str_radix(
-            "21888242871839275222246405745257275088548364400416034343698204186575808495617",
-            10,
-        )
-        .expect("This should just parse.");
+            &options.event_bus,
+            event_sink,
+        )?);
+
+        let snark_scalar_field = snark_scalar_field();
+
+        let receipt_signer = ReceiptSigner::new(
+            options.receipts,
+            identity_manager.chain_id().as_u64(),
+            signing_log,
+        )?;
+        let insertion_authenticator = InsertionAuthenticator::new(
+            &options.insertion_signatures,
+            identity_manager.chain_id(),
+            identity_manager.address(),
+        );
+        let insertion_challenge = InsertionChallenge::new(&options.insertion_challenge)?;
+        let insertion_screening = InsertionScreening::new(&options.insertion_screening)?;
+        let tree_capacity =
+            TreeCapacity::new(&options.tree_capacity, identity_manager.tree_depth());
+        let insertion_writer = InsertionWriter::new(
+            &options.insertion_writes,
+            database.clone(),
+            tree_capacity.capacity(),
+        );
+        let idempotency_keys = IdempotencyKeys::new(&options.idempotency, database.clone());
+        let queue_estimator = QueueEstimator::new(
+            &options.queue_estimate,
+            std::time::Duration::from_secs(options.committer.batch_timeout_seconds),
+            database.clone(),
+        );
 
         // Process to push new identities to Ethereum
-        identity_committer.start().await;
+        if start_batching {
+            identity_committer.backfill_missed_blocks().await?;
+            identity_committer.start().await;
+        } else if api_only {
+            identity_committer.start_tree_sync().await;
+        }
+
+        let version = VersionResponse::new(
+            options.environment,
+            identity_manager.address(),
+            identity_manager.chain_id().as_u64(),
+            identity_manager.tree_depth(),
+        );
+        info!(
+            environment = version.environment.as_deref().unwrap_or("unset"),
+            version = version.version,
+            commit_sha = version.commit_sha,
+            build_date = version.build_date,
+            identity_manager_address = ?version.identity_manager_address,
+            chain_id = version.chain_id,
+            tree_depth = version.tree_depth,
+            "Starting sequencer"
+        );
 
         // Sync with chain on start up
-        let app = Self {
+        let app = App {
             database,
             identity_manager,
             identity_committer,
             tree_state,
             snark_scalar_field,
+            receipt_signer,
+            insertion_authenticator,
+            insertion_challenge,
+            insertion_screening,
+            insertion_writer,
+            idempotency_keys,
+            tree_capacity,
+            queue_estimator,
+            cpu_pools,
+            proof_limiter: ProofLimiter::new(
+                options.max_concurrent_proofs,
+                options.max_queued_proofs,
+            ),
+            // Written by the instance processing batches, whose mined tree
+            // is authoritative.
+            tree_snapshot_file: if api_only {
+                None
+            } else {
+                options.tree_snapshot_file
+            },
+            tree_snapshot_zstd_level: options.tree_snapshot_zstd_level,
+            export_zstd_level: options.export_zstd_level,
+            version,
+            api_only,
+            dense_tree_prefix_depth: options.dense_tree_prefix_depth,
+            tree_gc_threshold: options.tree_gc_threshold,
+            dense_tree_mmap_file: options.dense_tree_mmap_file,
         };
 
+        // Not awaited, the snapshot is only needed on the next start.
+        app.spawn_tree_snapshot();
+
         Ok(app)
     }
 
+    /// Builds only the tree from the database, without connecting to the
+    /// chain. The tree is served as stored, it's not checked against the
+    /// contract.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database is not accessible.
+    pub async fn build_tree(self) -> AnyhowResult<TreeState> {
+        let options = self.options;
+        let database = Database::new(options.database, options.retry.policy()).await?;
+
+        let tree_depth = options.contracts.tree_depth;
+        let initial_leaf_value = options.contracts.initial_leaf_value;
+        let initial_root_hash = LazyPoseidonTree::new(tree_depth, initial_leaf_value).root();
+
+        App::restore_or_initialize_tree(
+            &database,
+            tree_depth,
+            options.dense_tree_prefix_depth,
+            options.tree_gc_threshold,
+            initial_leaf_value,
+            initial_root_hash,
+            &options.dense_tree_mmap_file,
+            options.force_cache_purge,
+            &self.progress,
+        )
+        .await
+    }
+}
+
+pub struct App {
+    database:                 Arc<Database>,
+    identity_manager:         SharedIdentityManager,
+    identity_committer:       Arc<TaskMonitor>,
+    tree_state:               TreeState,
+    snark_scalar_field:       Hash,
+    receipt_signer:           ReceiptSigner,
+    insertion_authenticator:  InsertionAuthenticator,
+    insertion_challenge:      InsertionChallenge,
+    insertion_screening:      InsertionScreening,
+    insertion_writer:         InsertionWriter,
+    idempotency_keys:         IdempotencyKeys,
+    tree_capacity:            TreeCapacity,
+    queue_estimator:          QueueEstimator,
+    cpu_pools:                CpuPools,
+    proof_limiter:            ProofLimiter,
+    tree_snapshot_file:       Option<String>,
+    tree_snapshot_zstd_level: Option<i32>,
+    export_zstd_level:        i32,
+    version:                  VersionResponse,
+    api_only:                 bool,
+    dense_tree_prefix_depth:  usize,
+    tree_gc_threshold:        usize,
+    dense_tree_mmap_file:     String,
+}
+
+impl App {
+    /// # Errors
+    ///
+    /// Will return `Err` if the internal Ethereum handler errors or if the
+    /// `options.storage_file` is not accessible.
+    pub async fn new(options: Options) -> AnyhowResult<Self> {
+        AppBuilder::new(options).build().await
+    }
+
+    /// Waits for pending transactions and brings the roots in the database up
+    /// to date with the contract, returns the latest root of the contract.
+    async fn reconcile_chain_state(
+        database: &Database,
+        identity_manager: &IdentityManager,
+        initial_root_hash: Hash,
+        reconciliation_blocks: u64,
+        window_size: u64,
+    ) -> AnyhowResult<Hash> {
+        // Await for all pending transactions
+        identity_manager.await_clean_slate().await?;
+
+        if reconciliation_blocks > 0 {
+            App::reconcile_mined_identities(
+                database,
+                identity_manager,
+                reconciliation_blocks,
+                window_size,
+            )
+            .await?;
+        }
+
+        // Prefetch latest root & mark it as mined
+        let root_hash = identity_manager.latest_root().await?;
+        let root_hash = root_hash.into();
+
+        // We don't store the initial root in the database, so we have to skip this step
+        // if the contract root hash is equal to initial root hash
+        if root_hash != initial_root_hash {
+            // Note that we don't have a way of queuing a root here for finalization.
+            // so it's going to stay as "processed" until the next root is mined.
+            database.mark_root_as_processed(&root_hash).await?;
+        } else {
+            // Db is either empty or we're restarting with a new contract/chain
+            // so we should mark everything as pending
+            database.mark_all_as_pending().await?;
+        }
+
+        Ok(root_hash)
+    }
+
+    /// Fails if the rebuilt tree doesn't have the root pinned with
+    /// `--expected-root`, so a corrupted database or cache isn't served.
+    async fn verify_expected_root(
+        expected_root: ExpectedRoot,
+        chain_root: Option<Hash>,
+        identity_manager: &IdentityManager,
+        tree_state: &TreeState,
+    ) -> AnyhowResult<()> {
+        let expected_root = match (expected_root, chain_root) {
+            (ExpectedRoot::Root(root), _) | (ExpectedRoot::Chain, Some(root)) => root,
+            (ExpectedRoot::Chain, None) => identity_manager.latest_root().await?.into(),
+        };
+
+        let root = tree_state.get_processed_tree().get_root();
+
+        anyhow::ensure!(
+            root == expected_root,
+            "Tree root {root:?} differs from the expected root {expected_root:?}, refusing to \
+             start"
+        );
+
+        info!(?root, "Tree root matches the expected root");
+
+        Ok(())
+    }
+
+    /// Removes identities from the unprocessed queue which were already
+    /// mined in a recent batch, e.g. when they were resubmitted after a crash.
+    /// Batching them again would revert the whole batch on duplicates.
+    #[instrument(level = "debug", skip(database, identity_manager))]
+    async fn reconcile_mined_identities(
+        database: &Database,
+        identity_manager: &IdentityManager,
+        lookback_blocks: u64,
+        window_size: u64,
+    ) -> AnyhowResult<()> {
+        let batches = identity_manager
+            .fetch_recent_insertions(lookback_blocks, window_size)
+            .await?;
+
+        let mut reconciled = 0;
+        for (post_root, commitments) in batches {
+            for commitment in commitments {
+                let commitment: Hash = commitment.into();
+
+                if database
+                    .get_unprocessed_commit_status(&commitment)
+                    .await?
+                    .is_none()
+                {
+                    continue;
+                }
+
+                // The identities table is brought in line with the contract by
+                // marking the latest root as processed, so only the queue
+                // needs to be cleaned up.
+                if database
+                    .get_identity_leaf_index(&commitment)
+                    .await?
+                    .is_none()
+                {
+                    warn!(
+                        commitment = ?redacted(&commitment),
+                        ?post_root,
+                        "Queued identity was mined in a batch unknown to the database."
+                    );
+                }
+
+                database.remove_unprocessed_identity(&commitment).await?;
+                reconciled += 1;
+            }
+        }
+
+        if reconciled > 0 {
+            info!(
+                reconciled,
+                "Removed already mined identities from the unprocessed queue"
+            );
+        }
+
+        Ok(())
+    }
+
     async fn restore_or_initialize_tree(
         database: &Database,
         tree_depth: usize,
@@ -212,12 +861,22 @@ impl App {
         initial_root_hash: Hash,
         mmap_file_path: &str,
         force_cache_purge: bool,
+        progress: &StartupProgress,
     ) -> AnyhowResult<TreeState> {
         let mut mined_items = database
             .get_commitments_by_status(ProcessedStatus::Mined)
             .await?;
         mined_items.sort_by_key(|item| item.leaf_index);
 
+        let processed_count = database
+            .count_identities_by_status(ProcessedStatus::Processed)
+            .await?;
+        let pending_count = database
+            .count_identities_by_status(ProcessedStatus::Pending)
+            .await?;
+        let total_count = mined_items.len() + processed_count + pending_count;
+        progress.reset(total_count);
+
         if !force_cache_purge {
             info!("Attempting to restore tree from cache");
             if let Some(tree_state) = Self::get_cached_tree_state(
@@ -229,6 +888,7 @@ impl App {
                 initial_root_hash,
                 mined_items.clone(),
                 mmap_file_path,
+                progress,
             )
             .await?
             {
@@ -238,6 +898,8 @@ impl App {
         }
 
         info!("Initializing tree from the database");
+        // Start over in case restoring from the cache got partway.
+        progress.reset(total_count);
         let tree_state = Self::initialize_tree(
             database,
             tree_depth,
@@ -246,6 +908,7 @@ impl App {
             initial_leaf_value,
             mined_items,
             mmap_file_path,
+            progress,
         )
         .await?;
 
@@ -294,7 +957,9 @@ impl App {
         initial_root_hash: Hash,
         mined_items: Vec<TreeUpdate>,
         mmap_file_path: &str,
+        progress: &StartupProgress,
     ) -> anyhow::Result<Option<TreeState>> {
+        let mined_count = mined_items.len();
         let mined_items = dedup_tree_updates(mined_items);
 
         let mut last_mined_index_in_dense: usize = 0;
@@ -317,6 +982,7 @@ impl App {
         };
 
         let (mined, mut processed_builder) = mined_builder.seal();
+        progress.advance(mined_count);
 
         match database
             .get_latest_root_by_status(ProcessedStatus::Mined)
@@ -340,6 +1006,7 @@ impl App {
 
         for processed_item in processed_items {
             processed_builder.update(&processed_item);
+            progress.advance(1);
         }
 
         let (processed, batching_builder) = processed_builder.seal_and_continue();
@@ -349,6 +1016,7 @@ impl App {
             .await?;
         for update in pending_items {
             latest_builder.update(&update);
+            progress.advance(1);
         }
         let latest = latest_builder.seal();
         Ok(Some(TreeState::new(mined, processed, batching, latest)))
@@ -362,7 +1030,10 @@ impl App {
         initial_leaf_value: Hash,
         mined_items: Vec<TreeUpdate>,
         mmap_file_path: &str,
+        progress: &StartupProgress,
     ) -> AnyhowResult<TreeState> {
+        let mined_count = mined_items.len();
+
         // Flatten the updates for initial leaves
         let mined_items = dedup_tree_updates(mined_items);
 
@@ -389,6 +1060,7 @@ impl App {
         );
 
         let (mined, mut processed_builder) = mined_builder.seal();
+        progress.advance(mined_count);
 
         let processed_items = database
             .get_commitments_by_status(ProcessedStatus::Processed)
@@ -396,6 +1068,7 @@ impl App {
 
         for processed_item in processed_items {
             processed_builder.update(&processed_item);
+            progress.advance(1);
         }
 
         let (processed, batching_builder) = processed_builder.seal_and_continue();
@@ -406,6 +1079,7 @@ impl App {
             .await?;
         for update in pending_items {
             latest_builder.update(&update);
+            progress.advance(1);
         }
 
         let latest = latest_builder.seal();
@@ -413,22 +1087,111 @@ impl App {
         Ok(TreeState::new(mined, processed, batching, latest))
     }
 
-    /// Queues an insert into the merkle tree.
+    /// Recovers the address which signed the insertion of the commitment.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if signatures are required and none was provided, or
+    /// the signature is malformed.
+    pub fn authenticate_insertion(
+        &self,
+        commitment: Hash,
+        signature: Option<&Bytes>,
+    ) -> Result<Option<Address>, ServerError> {
+        self.insertion_authenticator
+            .authenticate(commitment, signature)
+    }
+
+    /// Checks the solution to the challenge required before insertions, if
+    /// the deployment configures one.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the token is missing or doesn't solve the
+    /// challenge, or the captcha service can't be reached.
+    pub async fn verify_insertion_challenge(
+        &self,
+        commitment: Hash,
+        token: Option<&str>,
+    ) -> Result<(), ServerError> {
+        self.insertion_challenge.verify(commitment, token).await
+    }
+
+    /// Screens the submitter of an insertion, if the deployment configures
+    /// screening, and records the decision in the audit log.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the submission is denied, or can't be screened
+    /// and screening fails closed.
+    pub async fn screen_insertion(
+        &self,
+        commitment: Hash,
+        signer: Option<Address>,
+        headers: &HeaderMap,
+    ) -> Result<(), ServerError> {
+        let subject = ScreeningSubject {
+            commitment,
+            signer,
+            jurisdiction: self.insertion_screening.jurisdiction(headers),
+        };
+
+        let Some(decision) = self.insertion_screening.screen(&subject).await else {
+            return Ok(());
+        };
+
+        self.database
+            .insert_screening_audit_entry(&subject, decision)
+            .await?;
+
+        match decision {
+            ScreeningDecision::Allowed | ScreeningDecision::FailedOpen => Ok(()),
+            ScreeningDecision::Denied => {
+                warn!(
+                    commitment = ?redacted(&commitment),
+                    ?signer,
+                    "Submission denied by screening."
+                );
+                Err(ServerError::SubmissionDenied)
+            }
+            ScreeningDecision::FailedClosed => Err(ServerError::ScreeningUnavailable),
+        }
+    }
+
+    /// Queues an insert into the merkle tree and returns a receipt for it. The
+    /// signer, if the insertion was authenticated, is recorded in the audit
+    /// log.
     ///
     /// # Errors
     ///
     /// Will return `Err` if identity is already queued, or in the tree, or the
     /// queue malfunctions.
-    #[instrument(level = "debug", skip(self))]
-    pub async fn insert_identity(&self, commitment: Hash) -> Result<(), ServerError> {
+    #[instrument(
+        level = "debug",
+        skip(self, commitment),
+        fields(commitment = %redacted(&commitment)),
+    )]
+    pub async fn insert_identity(
+        &self,
+        commitment: Hash,
+        metadata: Option<String>,
+        signer: Option<Address>,
+    ) -> Result<InsertionReceipt, ServerError> {
+        if metadata
+            .as_ref()
+            .is_some_and(|metadata| metadata.len() > MAX_METADATA_SIZE)
+        {
+            return Err(ServerError::MetadataTooLarge);
+        }
+
         if commitment == self.identity_manager.initial_leaf_value() {
-            warn!(?commitment, "Attempt to insert initial leaf.");
+            warn!(commitment = ?redacted(&commitment), "Attempt to insert initial leaf.");
             return Err(ServerError::InvalidCommitment);
         }
 
         if !self.identity_manager.has_insertion_provers().await {
             warn!(
-                ?commitment,
+                commitment = ?redacted(&commitment),
                 "Identity Manager has no insertion provers. Add provers with /addBatchSize \
                  request."
             );
@@ -437,21 +1200,125 @@ impl App {
 
         if !self.identity_is_reduced(commitment) {
             warn!(
-                ?commitment,
+                commitment = ?redacted(&commitment),
                 "The provided commitment is not an element of the field."
             );
             return Err(ServerError::UnreducedCommitment);
         }
 
-        if self.database.identity_exists(commitment).await? {
-            return Err(ServerError::DuplicateCommitment);
-        }
-
-        self.database
-            .insert_new_identity(commitment, Utc::now())
+        // Failed commitments, e.g. expired ones, can be submitted again as long
+        // as they didn't make it into the tree.
+        if let Some((UnprocessedStatus::Failed, _)) = self
+            .database
+            .get_unprocessed_commit_status(&commitment)
+            .await?
+        {
+            if self
+                .database
+                .get_identity_leaf_index(&commitment)
+                .await?
+                .is_none()
+            {
+                self.database
+                    .remove_unprocessed_identity(&commitment)
+                    .await?;
+            }
+        }
+
+        if self.database.identity_exists(commitment).await? {
+            return Err(ServerError::DuplicateCommitment);
+        }
+
+        // Queued identities take up leaves as soon as they're batched.
+        let queued = self
+            .database
+            .count_unprocessed_identities_by_state()
             .await?;
+        let used =
+            self.tree_state.get_latest_tree().next_leaf() + queued.eligible + queued.held_back;
+        self.tree_capacity.check(used, signer)?;
+
+        let mut receipt = InsertionReceipt::new(commitment, None, None, Utc::now());
+
+        let sequence_number = self
+            .insertion_writer
+            .write(NewInsertion {
+                commitment,
+                metadata,
+                signer,
+                created_at: receipt.timestamp,
+            })
+            .await
+            .map_err(|error| match error {
+                // Others filled the tree since the check above.
+                database::Error::TreeFull { capacity } => ServerError::TreeFull { capacity },
+                // Another submission of the commitment was written since.
+                database::Error::DuplicateCommitment => ServerError::DuplicateCommitment,
+                error => error.into(),
+            })?;
+        receipt.sequence_number = Some(sequence_number);
+
+        Ok(self.receipt_signer.sign(receipt).await?)
+    }
 
-        Ok(())
+    /// Reserves an idempotency key for a request, or returns the response to
+    /// the first request made with it.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the key was used with a different request or is
+    /// still in use.
+    pub async fn reserve_idempotency_key(
+        &self,
+        key: String,
+        fingerprint: H256,
+    ) -> Result<Reservation, ServerError> {
+        self.idempotency_keys.reserve(key, fingerprint).await
+    }
+
+    /// Refetches the receipt issued when the identity was inserted, including
+    /// the leaf index and the root it was applied to if it has been assigned
+    /// since.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if no receipt was issued for the commitment.
+    #[instrument(
+        level = "debug",
+        skip(self, commitment),
+        fields(commitment = %redacted(commitment)),
+    )]
+    pub async fn insertion_receipt(
+        &self,
+        commitment: &Hash,
+    ) -> Result<InsertionReceipt, ServerError> {
+        let entry = self
+            .database
+            .get_insertion_receipt(commitment)
+            .await?
+            .ok_or(ServerError::IdentityCommitmentNotFound)?;
+
+        // Only the first leaf is applied to the initial root.
+        let pre_root = match (entry.leaf_index, entry.pre_root) {
+            (Some(_), None) => Some(
+                LazyPoseidonTree::new(
+                    self.identity_manager.tree_depth(),
+                    self.identity_manager.initial_leaf_value(),
+                )
+                .root(),
+            ),
+            (_, pre_root) => pre_root,
+        };
+
+        let mut receipt = InsertionReceipt::new(
+            entry.commitment,
+            entry.leaf_index.map(|leaf_index| leaf_index as u64),
+            pre_root,
+            entry.created_at,
+        );
+        receipt.sequence_number = Some(entry.sequence_number);
+
+        Ok(self.receipt_signer.sign(receipt).await?)
     }
 
     /// Queues a deletion from the merkle tree.
@@ -460,12 +1327,20 @@ impl App {
     ///
     /// Will return `Err` if identity is already queued, not in the tree, or the
     /// queue malfunctions.
-    #[instrument(level = "debug", skip(self))]
+    #[instrument(
+        level = "debug",
+        skip(self, commitment),
+        fields(commitment = %redacted(commitment)),
+    )]
     pub async fn delete_identity(&self, commitment: &Hash) -> Result<(), ServerError> {
+        if !self.identity_manager.flavor().supports_deletions() {
+            return Err(ServerError::DeletionsNotSupported);
+        }
+
         // Ensure that deletion provers exist
         if !self.identity_manager.has_deletion_provers().await {
             warn!(
-                ?commitment,
+                commitment = ?redacted(&commitment),
                 "Identity Manager has no deletion provers. Add provers with /addBatchSize request."
             );
             return Err(ServerError::NoProversOnIdDeletion);
@@ -520,7 +1395,14 @@ impl App {
     ///
     /// Will return `Err` if identity is already queued for deletion, not in the
     /// tree, or the queue malfunctions.
-    #[instrument(level = "debug", skip(self))]
+    #[instrument(
+        level = "debug",
+        skip(self, existing_commitment, new_commitment),
+        fields(
+            existing_commitment = %redacted(existing_commitment),
+            new_commitment = %redacted(new_commitment),
+        ),
+    )]
     pub async fn recover_identity(
         &self,
         existing_commitment: &Hash,
@@ -528,15 +1410,19 @@ impl App {
     ) -> Result<(), ServerError> {
         if *new_commitment == self.identity_manager.initial_leaf_value() {
             warn!(
-                ?new_commitment,
+                new_commitment = ?redacted(new_commitment),
                 "Attempt to insert initial leaf in recovery."
             );
             return Err(ServerError::InvalidCommitment);
         }
 
+        if !self.identity_manager.flavor().supports_deletions() {
+            return Err(ServerError::DeletionsNotSupported);
+        }
+
         if !self.identity_manager.has_insertion_provers().await {
             warn!(
-                ?new_commitment,
+                new_commitment = ?redacted(new_commitment),
                 "Identity Manager has no provers. Add provers with /addBatchSize request."
             );
             return Err(ServerError::NoProversOnIdInsert);
@@ -544,7 +1430,7 @@ impl App {
 
         if !self.identity_is_reduced(*new_commitment) {
             warn!(
-                ?new_commitment,
+                new_commitment = ?redacted(new_commitment),
                 "The new identity commitment is not reduced."
             );
             return Err(ServerError::UnreducedCommitment);
@@ -578,6 +1464,7 @@ impl App {
         for entry in entries {
             let mut status = match entry.status {
                 Status::Processed(ProcessedStatus::Pending) => IdentityHistoryEntryStatus::Pending,
+                Status::Processed(ProcessedStatus::Batched) => IdentityHistoryEntryStatus::Batched,
                 Status::Processed(ProcessedStatus::Processed) => IdentityHistoryEntryStatus::Mined,
                 Status::Processed(ProcessedStatus::Mined) => IdentityHistoryEntryStatus::Bridged,
                 Status::Unprocessed(UnprocessedStatus::New) => IdentityHistoryEntryStatus::Buffered,
@@ -585,6 +1472,9 @@ impl App {
                 Status::Unprocessed(UnprocessedStatus::Failed) => {
                     IdentityHistoryEntryStatus::Buffered
                 }
+                // Entries have the status of their update, deletions are
+                // told apart by their kind
+                Status::Removed(RemovedStatus::Deleted) => IdentityHistoryEntryStatus::Mined,
             };
 
             match status {
@@ -617,6 +1507,88 @@ impl App {
         Ok(history)
     }
 
+    /// Returns the events recorded for an identity and the status derived
+    /// from them.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if no events were recorded for the identity, or the
+    /// database query fails.
+    pub async fn identity_status(
+        &self,
+        commitment: &Hash,
+    ) -> Result<IdentityStatusResponse, ServerError> {
+        let entries = self.database.get_identity_events(commitment).await?;
+        if entries.is_empty() {
+            return Err(ServerError::IdentityCommitmentNotFound);
+        }
+
+        let status = Status::from_events(entries.iter().map(|entry| entry.event));
+        let received_at = entries
+            .iter()
+            .rev()
+            .find(|entry| entry.event == IdentityEvent::Received)
+            .map_or_else(Utc::now, |entry| entry.created_at);
+        let events = entries
+            .into_iter()
+            .map(|entry| IdentityStatusEvent {
+                event:     entry.event,
+                message:   entry.message,
+                timestamp: entry.created_at,
+            })
+            .collect();
+
+        let sequence_number = self
+            .database
+            .get_insertion_receipt(commitment)
+            .await?
+            .map(|entry| entry.sequence_number);
+
+        let queue_estimate = self.queue_estimate(commitment, received_at).await;
+        let metadata = self.database.get_identity_metadata(commitment).await?;
+
+        Ok(IdentityStatusResponse {
+            status,
+            sequence_number,
+            queue_estimate,
+            metadata,
+            events,
+        })
+    }
+
+    /// Estimates when the identity, received at `received_at`, will be mined.
+    /// `None` if it isn't waiting to be mined, or if the estimate failed, which
+    /// is only logged as it doesn't affect the identity.
+    pub async fn queue_estimate(
+        &self,
+        commitment: &Hash,
+        received_at: DateTime<Utc>,
+    ) -> Option<QueueEstimate> {
+        match self.queue_estimator.estimate(commitment, received_at).await {
+            Ok(estimate) => estimate,
+            Err(error) => {
+                warn!(
+                    commitment = ?redacted(commitment),
+                    ?error,
+                    "Failed to estimate when the identity will be mined."
+                );
+                None
+            }
+        }
+    }
+
+    /// Returns the metadata attached to an identity on insertion, if any.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database query fails.
+    pub async fn identity_metadata(
+        &self,
+        commitment: &Hash,
+    ) -> Result<Option<String>, ServerError> {
+        Ok(self.database.get_identity_metadata(commitment).await?)
+    }
+
     fn merge_env_provers(options: prover::Options, existing_provers: &mut Provers) -> Provers {
         let options_set: HashSet<ProverConfiguration> = options
             .prover_urls
@@ -627,9 +1599,24 @@ impl App {
                 batch_size:  opt.batch_size,
                 timeout_s:   opt.timeout_s,
                 prover_type: opt.prover_type,
+                key_digest:  opt.key_digest,
             })
             .collect();
 
+        // Expected key digests are taken from the environment for known provers
+        // as well, so that they can be rolled along with the provers.
+        for configured in options_set
+            .iter()
+            .filter(|prover| prover.key_digest.is_some())
+        {
+            if let Some(mut existing) = existing_provers.take(configured) {
+                if existing.prover_type == configured.prover_type {
+                    existing.key_digest = configured.key_digest;
+                }
+                existing_provers.insert(existing);
+            }
+        }
+
         let env_provers: HashSet<_> = options_set.difference(existing_provers).cloned().collect();
 
         for unique in &env_provers {
@@ -654,18 +1641,26 @@ impl App {
         batch_size: usize,
         timeout_seconds: u64,
         prover_type: ProverType,
+        key_digest: Option<H256>,
     ) -> Result<(), ServerError> {
         self.identity_manager
-            .add_batch_size(&url, batch_size, timeout_seconds, prover_type)
+            .add_batch_size(&url, batch_size, timeout_seconds, prover_type, key_digest)
             .await?;
 
         self.database
-            .insert_prover_configuration(batch_size, url, timeout_seconds, prover_type)
+            .insert_prover_configuration(batch_size, url, timeout_seconds, prover_type, key_digest)
             .await?;
 
         Ok(())
     }
 
+    /// Reports the batch size and proving key every prover serves, compared
+    /// with their configuration.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn prover_keys(&self) -> ProverKeysResponse {
+        ProverKeysResponse(self.identity_manager.prover_key_reports().await)
+    }
+
     /// # Errors
     ///
     /// Will return `Err` if the requested batch size does not exist.
@@ -695,13 +1690,22 @@ impl App {
         Ok(ListBatchSizesResponse::from(batches))
     }
 
+    /// Proves the inclusion of the commitment, reporting identities taken
+    /// into a batch as batched only with `report_batched`, see
+    /// [`TreeState::get_proof_for`].
+    ///
     /// # Errors
     ///
     /// Will return `Err` if the provided index is out of bounds.
-    #[instrument(level = "debug", skip(self))]
+    #[instrument(
+        level = "debug",
+        skip(self, commitment),
+        fields(commitment = %redacted(commitment)),
+    )]
     pub async fn inclusion_proof(
         &self,
         commitment: &Hash,
+        report_batched: bool,
     ) -> Result<InclusionProofResponse, ServerError> {
         if commitment == &self.identity_manager.initial_leaf_value() {
             return Err(ServerError::InvalidCommitment);
@@ -713,28 +1717,962 @@ impl App {
             .await?
         {
             return Ok(InclusionProofResponse(InclusionProof {
-                status:  status.into(),
-                root:    None,
-                proof:   None,
-                message: Some(error_message),
+                status:            status.into(),
+                root:              None,
+                proof:             None,
+                message:           Some(error_message),
+                mined_transaction: None,
+                mined_block:       None,
             }));
         }
 
-        let item = self
-            .database
-            .get_identity_leaf_index(commitment)
-            .await?
-            .ok_or(ServerError::IdentityCommitmentNotFound)?;
+        // The updates of deleted identities may have been pruned.
+        let Some(item) = self.database.get_identity_leaf_index(commitment).await? else {
+            return self
+                .deletion_proof(commitment)
+                .await?
+                .ok_or(ServerError::IdentityCommitmentNotFound);
+        };
 
-        let (leaf, proof) = self.tree_state.get_proof_for(&item);
+        let _permit = self
+            .proof_limiter
+            .acquire()
+            .await
+            .ok_or(ServerError::TooManyProofRequests)?;
+
+        let tree_state = self.tree_state.clone();
+        let (leaf, mut proof) = self
+            .cpu_pools
+            .proof
+            .run(move || tree_state.get_proof_for(&item, report_batched))
+            .await;
 
         if leaf != *commitment {
-            return Err(ServerError::InvalidCommitment);
+            return self
+                .deletion_proof(commitment)
+                .await?
+                .ok_or(ServerError::InvalidCommitment);
+        }
+
+        if proof.status == Status::Processed(ProcessedStatus::Mined) {
+            if let Some(root) = &proof.root {
+                proof.mined_transaction = self.database.get_root_transaction(root).await?;
+            }
+            proof.mined_block = self.database.get_mined_block(commitment).await?;
         }
 
         Ok(InclusionProofResponse(proof))
     }
 
+    /// The response to proof requests for a deleted identity, with the root
+    /// and transaction of its deletion. `None` if it wasn't deleted.
+    async fn deletion_proof(
+        &self,
+        commitment: &Hash,
+    ) -> Result<Option<InclusionProofResponse>, ServerError> {
+        let Some(deletion) = self.database.get_identity_deletion(commitment).await? else {
+            return Ok(None);
+        };
+
+        Ok(Some(InclusionProofResponse(InclusionProof {
+            status:            RemovedStatus::Deleted.into(),
+            root:              deletion.root,
+            proof:             None,
+            message:           None,
+            mined_transaction: deletion.mined_transaction,
+            mined_block:       None,
+        })))
+    }
+
+    /// Attests that the commitment is not part of the latest tree.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the commitment is part of the latest tree, or if no
+    /// signing key is configured, as unsigned attestations prove nothing.
+    #[instrument(
+        level = "debug",
+        skip(self, commitment),
+        fields(commitment = %redacted(commitment)),
+    )]
+    pub async fn non_inclusion_proof(
+        &self,
+        commitment: &Hash,
+    ) -> Result<NonInclusionAttestation, ServerError> {
+        if !self.receipt_signer.is_enabled() {
+            return Err(ServerError::AttestationsNotSupported);
+        }
+
+        if commitment == &self.identity_manager.initial_leaf_value() {
+            return Err(ServerError::InvalidCommitment);
+        }
+
+        // Take the root before looking the commitment up, so that an identity
+        // inserted concurrently can only be missing from a root predating it.
+        let latest_tree = self.tree_state.get_latest_tree();
+        let root = latest_tree.get_root();
+
+        if let Some(item) = self.database.get_identity_leaf_index(commitment).await? {
+            // Deleted identities stay in the database but are zeroed out in the
+            // tree.
+            if latest_tree.get_leaf(item.leaf_index) == *commitment {
+                return Err(ServerError::IdentityCommitmentIncluded);
+            }
+        }
+
+        let attestation = NonInclusionAttestation::new(*commitment, root, Utc::now());
+
+        Ok(self.receipt_signer.sign_non_inclusion(attestation).await?)
+    }
+
+    /// Reports the gas spent on the batches mined in `[from, to)`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the costs can't be read from the database.
+    pub async fn costs(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<CostsResponse, ServerError> {
+        let batches = self.database.get_batch_costs(from, to).await?;
+
+        Ok(CostsResponse::new(from, to, batches))
+    }
+
+    /// Reports the latest batches handed to the signer, newest first.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the reports can't be read from the database.
+    pub async fn batches(&self, limit: Option<i64>) -> Result<BatchesResponse, ServerError> {
+        let limit = limit
+            .unwrap_or(DEFAULT_BATCHES_LIMIT)
+            .clamp(0, MAX_BATCHES_LIMIT);
+
+        let batches = self
+            .database
+            .get_batch_reports(limit)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+
+        Ok(BatchesResponse { batches })
+    }
+
+    /// Lists the roots the tree went through in the order it reached them, a
+    /// page at a time, see [`RootsQuery`].
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the roots can't be read from the database.
+    pub async fn roots(&self, query: RootsQuery) -> Result<RootsResponse, ServerError> {
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_ROOTS_LIMIT)
+            .clamp(0, MAX_ROOTS_LIMIT);
+
+        let roots: Vec<RootHistoryItem> = self
+            .database
+            .get_root_history(
+                query.after,
+                query.status,
+                query.from_block,
+                query.to_block,
+                limit,
+            )
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+
+        // A full page may be followed by more roots.
+        let next = roots
+            .last()
+            .filter(|_| roots.len() as i64 == limit)
+            .map(|last| last.id);
+
+        Ok(RootsResponse { roots, next })
+    }
+
+    /// Lists the leaves of the mined tree, e.g. to check that identities were
+    /// registered before a block.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the leaves can't be read from the database.
+    pub async fn leaves(&self, query: LeavesQuery) -> Result<LeavesResponse, ServerError> {
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_LEAVES_LIMIT)
+            .clamp(0, MAX_LEAVES_LIMIT);
+
+        let leaves: Vec<LeafItem> = self
+            .database
+            .get_mined_leaves(query.after, query.mined_before_block, limit)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+
+        // A full page may be followed by more leaves.
+        let next = leaves
+            .last()
+            .filter(|_| leaves.len() as i64 == limit)
+            .map(|last| last.leaf_index);
+
+        Ok(LeavesResponse { leaves, next })
+    }
+
+    /// Returns the number of blocks the chain head is ahead of the last block
+    /// whose events were processed, `None` if nothing was processed yet or
+    /// the chain head can't be fetched.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the last processed block can't be read from the
+    /// database.
+    pub async fn sync_lag(&self) -> Result<Option<u64>, ServerError> {
+        let Some(last_block) = self.database.get_last_processed_block().await? else {
+            return Ok(None);
+        };
+
+        match self.identity_manager.block_number().await {
+            Ok(head) => Ok(Some(head.saturating_sub(last_block))),
+            Err(error) => {
+                warn!(?error, "Failed to fetch the chain head");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Summarizes the transactions and digests signed in `[from, to)`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the operations can't be read from the database.
+    pub async fn signer_activity(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<SignerActivityResponse, ServerError> {
+        let operations = self.identity_manager.signing_records(from, to).await?;
+
+        Ok(SignerActivityResponse::new(
+            self.identity_manager.signer_address(),
+            from,
+            to,
+            operations,
+        ))
+    }
+
+    /// Lists the batches which reverted in simulation.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the batches can't be read from the database.
+    pub async fn failed_batches(&self) -> Result<FailedBatchesResponse, ServerError> {
+        let max_attempts = self.identity_committer.max_batch_attempts();
+
+        let batches = self
+            .database
+            .get_failed_batches()
+            .await?
+            .into_iter()
+            .map(|batch| FailedBatchEntry::new(batch, max_attempts, false))
+            .collect();
+
+        Ok(FailedBatchesResponse { batches })
+    }
+
+    /// Returns a batch which reverted in simulation, including its calldata.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if no batch with the post root failed.
+    pub async fn failed_batch(&self, post_root: &Hash) -> Result<FailedBatchEntry, ServerError> {
+        let batch = self
+            .database
+            .get_failed_batch(post_root)
+            .await?
+            .ok_or(ServerError::FailedBatchNotFound)?;
+
+        Ok(FailedBatchEntry::new(
+            batch,
+            self.identity_committer.max_batch_attempts(),
+            true,
+        ))
+    }
+
+    /// Resets the failures of a batch, so that submission resumes if it was
+    /// held by it. The batch stays listed until it's discarded.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if no batch with the post root failed.
+    pub async fn retry_failed_batch(&self, post_root: &Hash) -> Result<(), ServerError> {
+        if !self.database.reset_failed_batch_attempts(post_root).await? {
+            return Err(ServerError::FailedBatchNotFound);
+        }
+
+        info!(?post_root, "Retrying failed batch");
+
+        Ok(())
+    }
+
+    /// Discards a failed batch, so that submission resumes if it was held by
+    /// it. The identities of an insertion batch which wasn't submitted are
+    /// taken out of the tree and reported as failed, and the identities
+    /// appended after them are queued again. Other batches are only
+    /// forgotten, e.g. once their cause has been dealt with.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if no batch with the post root failed, if this
+    /// instance doesn't maintain the tree, or if the database can't be
+    /// updated.
+    pub async fn discard_failed_batch(&self, post_root: &Hash) -> Result<(), ServerError> {
+        let batch = self
+            .database
+            .get_failed_batch(post_root)
+            .await?
+            .ok_or(ServerError::FailedBatchNotFound)?;
+
+        // Batches which were submitted meanwhile, or already replaced in the
+        // tree, have no identities left to drop.
+        let batching_root = self.tree_state.get_batching_tree().get_root();
+        if batch.kind != "insertion" || batch.pre_root != batching_root {
+            if !self.database.delete_failed_batch(post_root).await? {
+                return Err(ServerError::FailedBatchNotFound);
+            }
+
+            info!(?post_root, "Discarded failed batch");
+
+            return Ok(());
+        }
+
+        if self.api_only {
+            return Err(ServerError::TreeNotOwned);
+        }
+
+        // Nothing is batched or appended to the tree while the identities
+        // are taken out of it.
+        let was_running = self.identity_committer.is_running().await;
+        self.identity_committer.shutdown().await?;
+
+        let result = self.discard_failed_insertions(post_root).await;
+
+        if was_running {
+            self.identity_committer.start().await;
+        }
+
+        result
+    }
+
+    async fn discard_failed_insertions(&self, post_root: &Hash) -> Result<(), ServerError> {
+        let count = self
+            .tree_state
+            .get_latest_tree()
+            .unbatched_insertions()
+            .len();
+
+        // The tree is rolled back first, so that the database never holds a
+        // rollback the tree didn't make.
+        let tree_state = self.tree_state.clone();
+        let unbatched = tokio::task::spawn_blocking(move || tree_state.roll_back_latest(count))
+            .await
+            .map_err(|error| anyhow!("roll back task failed: {error}"))?
+            .ok_or_else(|| anyhow!("the discarded insertions were batched meanwhile"))?;
+
+        let dropped = match self
+            .database
+            .discard_failed_insertions(&unbatched, post_root, DISCARDED_MESSAGE)
+            .await
+        {
+            Ok(dropped) => dropped,
+            Err(error) => {
+                self.restore_rolled_back(&unbatched).await?;
+                return Err(error.into());
+            }
+        };
+
+        warn!(
+            ?post_root,
+            dropped,
+            requeued = count - dropped,
+            "Discarded failed batch along with its identities"
+        );
+
+        Ok(())
+    }
+
+    /// Appends insertions rolled back from the latest tree again, once the
+    /// database failed to record their rollback. Nothing was appended
+    /// meanwhile, so they get their leaves back.
+    async fn restore_rolled_back(&self, insertions: &[TreeUpdate]) -> Result<(), ServerError> {
+        let elements: Vec<_> = insertions
+            .iter()
+            .map(|insertion| insertion.element)
+            .collect();
+        let latest_tree = self.tree_state.get_latest_tree();
+        tokio::task::spawn_blocking(move || latest_tree.append_many(&elements))
+            .await
+            .map_err(|error| anyhow!("restoring the rolled back insertions failed: {error}"))?;
+
+        Ok(())
+    }
+
+    /// Replaces the tree with a snapshot whose root is the latest root of the
+    /// contract, e.g. after the database was corrupted. Batching is stopped
+    /// while the tree is replaced, and the identities which weren't mined
+    /// yet are discarded. They have to be inserted again.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the snapshot can't be read, doesn't reproduce its
+    /// root or doesn't have the root of the contract, or if the tree is
+    /// maintained by another instance.
+    pub async fn restore_tree(&self, path: &Path) -> Result<RestoreTreeResponse, ServerError> {
+        if self.api_only {
+            return Err(ServerError::TreeNotOwned);
+        }
+
+        let snapshot = TreeSnapshot::open(path)
+            .map_err(|error| ServerError::InvalidSnapshot(format!("{error:#}")))?;
+
+        let tree_depth = self.identity_manager.tree_depth();
+        if snapshot.depth() != tree_depth {
+            return Err(ServerError::InvalidSnapshot(format!(
+                "snapshot of depth {}, the tree has depth {tree_depth}",
+                snapshot.depth()
+            )));
+        }
+
+        let contract_root: Hash = self.identity_manager.latest_root().await?.into();
+        if snapshot.root() != contract_root {
+            return Err(ServerError::SnapshotRootMismatch {
+                snapshot: snapshot.root(),
+                contract: contract_root,
+            });
+        }
+
+        // The current tree keeps its file mapped until it's replaced.
+        let mmap_file_path = format!(
+            "{}.restored-{}",
+            self.dense_tree_mmap_file,
+            Utc::now().timestamp_millis()
+        );
+        let initial_leaf_value = self.identity_manager.initial_leaf_value();
+        let dense_prefix_depth = self.dense_tree_prefix_depth;
+        let gc_threshold = self.tree_gc_threshold;
+        let (leaves, replacement) = tokio::task::spawn_blocking(move || {
+            let replacement = TreeState::from_snapshot(
+                &snapshot,
+                initial_leaf_value,
+                dense_prefix_depth,
+                gc_threshold,
+                &mmap_file_path,
+            )?;
+            Ok::<_, anyhow::Error>((snapshot.leaves(), replacement))
+        })
+        .await
+        .map_err(|error| anyhow!("restore task failed: {error}"))?
+        .map_err(|error| ServerError::InvalidSnapshot(format!("{error:#}")))?;
+
+        // Nothing is batched or appended to the tree while it's replaced.
+        let was_running = self.identity_committer.is_running().await;
+        self.identity_committer.shutdown().await?;
+
+        let result = self.replace_tree(leaves, replacement, contract_root).await;
+
+        if was_running {
+            self.identity_committer.start().await;
+        }
+
+        result
+    }
+
+    async fn replace_tree(
+        &self,
+        leaves: Vec<Hash>,
+        replacement: TreeState,
+        root: Hash,
+    ) -> Result<RestoreTreeResponse, ServerError> {
+        let previous_root = self.tree_state.get_latest_tree().get_root();
+        let discarded_identities = self
+            .database
+            .replace_tree(&leaves, &root, RESTORED_MESSAGE)
+            .await?;
+
+        let tree_state = self.tree_state.clone();
+        tokio::task::spawn_blocking(move || tree_state.replace_with(&replacement))
+            .await
+            .map_err(|error| anyhow!("restore task failed: {error}"))?;
+
+        info!(
+            ?previous_root,
+            ?root,
+            leaf_count = leaves.len(),
+            discarded_identities,
+            "Restored the tree from a snapshot"
+        );
+
+        Ok(RestoreTreeResponse {
+            previous_root,
+            root,
+            leaf_count: leaves.len() as u64,
+            discarded_identities,
+        })
+    }
+
+    /// Collects the versions of the tree which were cut from the chain of
+    /// versions but are still held somewhere, and reports how the versions of
+    /// the chain are referenced.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the collection task panics.
+    pub async fn gc_tree_versions(&self) -> Result<VersionGcReport, ServerError> {
+        let tree_state = self.tree_state.clone();
+        let report = tokio::task::spawn_blocking(move || tree_state.collect_orphaned_versions())
+            .await
+            .map_err(|error| anyhow!("garbage collection task failed: {error}"))?;
+
+        Ok(report)
+    }
+
+    /// Rolls back the `count` latest insertions, as long as none of them was
+    /// taken into a batch yet, e.g. garbage submitted by a partner. Their
+    /// leaves are freed for the next insertions, and they're reported as
+    /// failed to lookups and to consumers of the event bus.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if fewer than `count` insertions weren't batched yet,
+    /// if this instance doesn't maintain the tree, or if the database can't be
+    /// updated.
+    pub async fn roll_back_insertions(
+        &self,
+        count: usize,
+    ) -> Result<RollBackInsertionsResponse, ServerError> {
+        if self.api_only {
+            return Err(ServerError::TreeNotOwned);
+        }
+
+        // Nothing is batched or appended to the tree while rolling back.
+        let was_running = self.identity_committer.is_running().await;
+        self.identity_committer.shutdown().await?;
+
+        let result = self.roll_back_latest_insertions(count).await;
+
+        if was_running {
+            self.identity_committer.start().await;
+        }
+
+        result
+    }
+
+    async fn roll_back_latest_insertions(
+        &self,
+        count: usize,
+    ) -> Result<RollBackInsertionsResponse, ServerError> {
+        let latest_tree = self.tree_state.get_latest_tree();
+        let previous_root = latest_tree.get_root();
+
+        let unbatched = latest_tree.unbatched_insertions().len();
+        if unbatched < count {
+            return Err(ServerError::TooFewUnbatchedInsertions {
+                requested: count,
+                unbatched,
+            });
+        }
+
+        // The tree is rolled back first, so that the database never holds a
+        // rollback the tree didn't make.
+        let tree_state = self.tree_state.clone();
+        let insertions = tokio::task::spawn_blocking(move || tree_state.roll_back_latest(count))
+            .await
+            .map_err(|error| anyhow!("roll back task failed: {error}"))?
+            .ok_or_else(|| anyhow!("the rolled back insertions were batched meanwhile"))?;
+
+        if let Err(error) = self
+            .database
+            .roll_back_insertions(&insertions, ROLLED_BACK_MESSAGE)
+            .await
+        {
+            self.restore_rolled_back(&insertions).await?;
+            return Err(error.into());
+        }
+
+        let root = latest_tree.get_root();
+        let next_leaf = latest_tree.next_leaf();
+        warn!(
+            count,
+            ?previous_root,
+            ?root,
+            next_leaf,
+            "Rolled back the latest insertions"
+        );
+
+        Ok(RollBackInsertionsResponse {
+            previous_root,
+            root,
+            next_leaf,
+            rolled_back: insertions
+                .iter()
+                .map(|insertion| insertion.element)
+                .collect(),
+        })
+    }
+
+    /// Lists the identities waiting to be mined, from the unprocessed queue
+    /// through to the batches submitted on chain.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database queries fail.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn queue(&self, limit: Option<i64>) -> Result<QueueResponse, ServerError> {
+        let limit = limit
+            .unwrap_or(DEFAULT_QUEUE_LIMIT)
+            .clamp(0, MAX_QUEUE_LIMIT);
+        let now = Utc::now();
+
+        let unprocessed_counts = self
+            .database
+            .count_unprocessed_identities_by_state()
+            .await?;
+        let (pending, batched) = self.database.count_pending_identities_by_state().await?;
+
+        let counts = QueueCounts {
+            buffered: unprocessed_counts.eligible,
+            queued: unprocessed_counts.held_back,
+            failed: unprocessed_counts.failed,
+            pending,
+            batched,
+        };
+
+        let mut entries = vec![];
+
+        for item in self.database.get_unprocessed_commitments(limit).await? {
+            let status = if item.eligibility_timestamp > now {
+                IdentityHistoryEntryStatus::Queued
+            } else {
+                IdentityHistoryEntryStatus::Buffered
+            };
+
+            entries.push(QueueEntry {
+                identity_commitment: item.commitment,
+                leaf_index: None,
+                status,
+                age_seconds: (now - item.created_at).num_seconds(),
+                batch_root: None,
+                transaction_hash: None,
+                error_message: item.error_message,
+                metadata: item.metadata,
+            });
+        }
+
+        let pending = self.database.get_pending_queue(limit).await?;
+
+        // Batches are identified by the relayer, resolve the hashes of their
+        // transactions once per batch.
+        let mut transaction_hashes = HashMap::new();
+        for transaction_id in pending
+            .iter()
+            .filter_map(|item| item.transaction_id.as_ref())
+        {
+            if transaction_hashes.contains_key(transaction_id) {
+                continue;
+            }
+
+            let transaction_hash = match self
+                .identity_manager
+                .transaction_hash(&TransactionId(transaction_id.clone()))
+                .await
+            {
+                Ok(transaction_hash) => transaction_hash,
+                Err(error) => {
+                    warn!(
+                        ?error,
+                        %transaction_id,
+                        "Failed to resolve transaction hash."
+                    );
+                    None
+                }
+            };
+            transaction_hashes.insert(transaction_id.clone(), transaction_hash);
+        }
+
+        for item in pending {
+            let status = if item.batch_root.is_some() {
+                IdentityHistoryEntryStatus::Batched
+            } else {
+                IdentityHistoryEntryStatus::Pending
+            };
+
+            entries.push(QueueEntry {
+                identity_commitment: item.commitment,
+                leaf_index: Some(item.leaf_index as u64),
+                status,
+                age_seconds: (now - item.pending_as_of).num_seconds(),
+                batch_root: item.batch_root,
+                transaction_hash: item
+                    .transaction_id
+                    .and_then(|transaction_id| transaction_hashes.get(&transaction_id).copied())
+                    .flatten(),
+                error_message: None,
+                metadata: item.metadata,
+            });
+        }
+
+        Ok(QueueResponse { counts, entries })
+    }
+
+    /// The build of the sequencer and the tree it serves.
+    #[must_use]
+    pub fn version(&self) -> VersionResponse {
+        self.version.clone()
+    }
+
+    #[must_use]
+    pub fn health(&self) -> HealthResponse {
+        let signer_funds = self.identity_committer.signer_funds();
+        let funds = signer_funds.status();
+
+        let provider = self.identity_manager.provider_status();
+        let prover = self.identity_committer.prover_availability().outage();
+        let foreign_root = self.identity_committer.divergence().foreign_root();
+        let contract = self.identity_committer.contract_state().suspension();
+
+        let status = match funds {
+            // Batches would build on a root the contract no longer has.
+            _ if foreign_root.is_some() => HealthStatus::Unavailable,
+            // Batches would revert until the owner reverts their action.
+            _ if contract.is_some() => HealthStatus::Unavailable,
+            // Proofs are still served from the tree, but batches can't be
+            // submitted while the provider is failing.
+            FundsStatus::Sufficient | FundsStatus::Unknown
+                if provider != BreakerStatus::Closed || prover.is_some() =>
+            {
+                HealthStatus::Degraded
+            }
+            FundsStatus::Sufficient | FundsStatus::Unknown => HealthStatus::Ok,
+            FundsStatus::Low => HealthStatus::Degraded,
+            FundsStatus::Depleted => HealthStatus::Unavailable,
+        };
+
+        HealthResponse {
+            status,
+            sync_progress: None,
+            signer: Some(SignerHealth {
+                address: self.identity_manager.signer_address(),
+                balance: signer_funds.balance(),
+                funds,
+                submissions_paused: signer_funds.submissions_paused(),
+            }),
+            provider: Some(provider),
+            prover,
+            foreign_root,
+            contract,
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if the transaction of the mined root can't be read
+    /// from the database.
+    pub async fn tree_info(&self) -> Result<TreeInfoResponse, ServerError> {
+        let latest_tree = self.tree_state.get_latest_tree();
+        let batching_root = self.tree_state.get_batching_tree().get_root();
+        let mined_root = self.tree_state.get_processed_tree().get_root();
+        let mined_transaction = self.database.get_root_transaction(&mined_root).await?;
+
+        Ok(TreeInfoResponse {
+            tree_depth: self.identity_manager.tree_depth(),
+            next_leaf_index: latest_tree.next_leaf() as u64,
+            latest_root: latest_tree.get_root(),
+            batching_root: Some(batching_root),
+            mined_root,
+            mined_transaction,
+        })
+    }
+
+    /// Proves the leaves from `start` up to `end`, exclusive, against the tree
+    /// as processed on chain, e.g. to audit a batch. All leaves are proven
+    /// against the same root, however the tree changes meanwhile.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the range is empty, extends past the processed
+    /// leaves or spans more than `MAX_RANGE_PROOF_LEAVES` leaves.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn range_proof(&self, start: usize, end: usize) -> Result<RangeProof, ServerError> {
+        let processed_tree = self.tree_state.get_processed_tree();
+        if start >= end || end > processed_tree.next_leaf() {
+            return Err(ServerError::IndexOutOfBounds);
+        }
+        if end - start > MAX_RANGE_PROOF_LEAVES {
+            return Err(ServerError::RangeTooLarge(MAX_RANGE_PROOF_LEAVES));
+        }
+
+        let _permit = self
+            .proof_limiter
+            .acquire()
+            .await
+            .ok_or(ServerError::TooManyProofRequests)?;
+
+        Ok(self
+            .cpu_pools
+            .proof
+            .run(move || processed_tree.get_range_proof(start, end))
+            .await)
+    }
+
+    /// Returns the nodes of the tree as processed on chain, level by level
+    /// from `from_level` up to the root, for mirrors replicating the tree.
+    #[must_use]
+    pub fn tree_nodes(&self, from_level: usize) -> TreeNodes {
+        self.tree_state.get_processed_tree().nodes(from_level)
+    }
+
+    /// Builds a snapshot of the mined tree from the database for followers,
+    /// so that it ends at a known row.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the tree can't be read from the database or the
+    /// snapshot can't be written.
+    pub async fn sync_snapshot(&self) -> Result<SyncSnapshot, ServerError> {
+        let mined_root = self
+            .database
+            .get_latest_root_by_status(ProcessedStatus::Mined)
+            .await?;
+
+        let (update_id, updates) = match &mined_root {
+            Some(root) => {
+                let id = self
+                    .database
+                    .get_root_id(root)
+                    .await?
+                    .ok_or(ServerError::InvalidRoot)?;
+                (id, self.database.get_tree_updates_until(id).await?)
+            }
+            None => (0, vec![]),
+        };
+
+        let tree_depth = self.identity_manager.tree_depth();
+        let initial_leaf_value = self.identity_manager.initial_leaf_value();
+        let zstd_level = self.export_zstd_level;
+
+        let (root, bytes) = tokio::task::spawn_blocking(move || {
+            let mut leaves = Vec::new();
+            for update in updates {
+                if update.leaf_index >= leaves.len() {
+                    leaves.resize(update.leaf_index + 1, initial_leaf_value);
+                }
+                leaves[update.leaf_index] = update.element;
+            }
+
+            let dir = tempfile::tempdir()?;
+            let path = dir.path().join("snapshot");
+            TreeSnapshot::write(
+                &path,
+                tree_depth,
+                initial_leaf_value,
+                leaves,
+                Some(zstd_level),
+            )?;
+            let root = TreeSnapshot::open(&path)?.root();
+
+            Ok::<_, anyhow::Error>((root, std::fs::read(&path)?))
+        })
+        .await
+        .map_err(|error| anyhow!("snapshot task failed: {error}"))??;
+
+        if let Some(mined_root) = mined_root.filter(|mined_root| *mined_root != root) {
+            return Err(anyhow!(
+                "tree rebuilt from the database has root {root}, expected {mined_root}"
+            )
+            .into());
+        }
+
+        Ok(SyncSnapshot {
+            root,
+            update_id,
+            bytes,
+        })
+    }
+
+    /// Returns the tree updates written after the row `after`, waiting up to
+    /// `wait` for some to be written if there are none yet.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the updates can't be read from the database.
+    pub async fn sync_updates(
+        &self,
+        after: usize,
+        wait: std::time::Duration,
+    ) -> Result<SyncUpdatesResponse, ServerError> {
+        let deadline = Instant::now() + wait;
+
+        loop {
+            // The roots are read first, so that the updates leading to them are
+            // always included.
+            let mined_root = self
+                .database
+                .get_latest_root_by_status(ProcessedStatus::Mined)
+                .await?;
+            let processed_root = self
+                .database
+                .get_latest_root_by_status(ProcessedStatus::Processed)
+                .await?
+                .or(mined_root);
+
+            let updates = self.database.get_tree_updates_after(after).await?;
+
+            if !updates.is_empty() || Instant::now() >= deadline {
+                return Ok(SyncUpdatesResponse {
+                    updates: updates
+                        .into_iter()
+                        .map(|entry| SyncUpdate {
+                            id:         entry.id,
+                            leaf_index: entry.update.leaf_index,
+                            element:    entry.update.element,
+                            root:       entry.root,
+                        })
+                        .collect(),
+                    processed_root,
+                    mined_root,
+                });
+            }
+
+            tokio::time::sleep(SYNC_POLL_INTERVAL).await;
+        }
+    }
+
+    /// The zstd level of exports to clients accepting compressed responses.
+    #[must_use]
+    pub const fn export_zstd_level(&self) -> i32 {
+        self.export_zstd_level
+    }
+
+    /// Returns the state of a root, including the transaction which published
+    /// it once mined.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the root isn't known to the sequencer.
+    pub async fn check_root(&self, root: &Hash) -> Result<CheckRootResponse, ServerError> {
+        let Some(root_state) = self.database.get_root_state(root).await? else {
+            return Err(ServerError::InvalidRoot);
+        };
+
+        Ok(CheckRootResponse(root_state))
+    }
+
     /// # Errors
     ///
     /// Will return `Err` if the provided proof is invalid.
@@ -753,14 +2691,28 @@ impl App {
             self.validate_root_age(max_root_age, &root_state)?;
         }
 
-        let checked = verify_proof(
-            request.root,
-            request.nullifier_hash,
-            request.signal_hash,
-            request.external_nullifier_hash,
-            &request.proof,
-            self.identity_manager.tree_depth(),
-        );
+        let _permit = self
+            .proof_limiter
+            .acquire()
+            .await
+            .ok_or(ServerError::TooManyProofRequests)?;
+
+        let request = request.clone();
+        let tree_depth = self.identity_manager.tree_depth();
+        let checked = self
+            .cpu_pools
+            .proof
+            .run(move || {
+                verify_proof(
+                    request.root,
+                    request.nullifier_hash,
+                    request.signal_hash,
+                    request.external_nullifier_hash,
+                    &request.proof,
+                    tree_depth,
+                )
+            })
+            .await;
 
         match checked {
             Ok(true) => Ok(VerifySemaphoreProofResponse(root_state)),
@@ -786,7 +2738,9 @@ impl App {
 
         match root_state.status {
             // Pending status implies the batching or latest tree
-            ProcessedStatus::Pending if latest_root == root || batching_root == root => {
+            ProcessedStatus::Pending | ProcessedStatus::Batched
+                if latest_root == root || batching_root == root =>
+            {
                 return Ok(())
             }
             // Processed status is hidden - this should never happen
@@ -800,7 +2754,7 @@ impl App {
 
         let root_age = if matches!(
             root_state.status,
-            ProcessedStatus::Pending | ProcessedStatus::Processed
+            ProcessedStatus::Pending | ProcessedStatus::Batched | ProcessedStatus::Processed
         ) {
             now - root_state.pending_valid_as_of
         } else {
@@ -817,24 +2771,72 @@ impl App {
         }
     }
 
+    #[must_use]
+    pub fn tree_service(&self) -> &dyn TreeService {
+        &self.tree_state
+    }
+
+    #[must_use]
+    pub fn chain_service(&self) -> &dyn ChainService {
+        self.identity_manager.as_ref()
+    }
+
+    #[must_use]
+    pub fn batch_service(&self) -> &dyn BatchService {
+        self.identity_committer.as_ref()
+    }
+
+    #[must_use]
+    pub(crate) fn database(&self) -> &Database {
+        &self.database
+    }
+
     /// # Errors
     ///
     /// Will return an Error if any of the components cannot be shut down
     /// gracefully.
     pub async fn shutdown(&self) -> AnyhowResult<()> {
         info!("Shutting down identity committer.");
-        self.identity_committer.shutdown().await
+        self.identity_committer.shutdown().await?;
+
+        if let Some(snapshot) = self.spawn_tree_snapshot() {
+            snapshot.await?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes a snapshot of the mined tree in the background, if configured.
+    fn spawn_tree_snapshot(&self) -> Option<JoinHandle<()>> {
+        let path = self.tree_snapshot_file.clone()?;
+        let zstd_level = self.tree_snapshot_zstd_level;
+        let mined_tree = self.tree_state.get_mined_tree();
+        let tree_depth = self.identity_manager.tree_depth();
+        let initial_leaf_value = self.identity_manager.initial_leaf_value();
+
+        Some(tokio::task::spawn_blocking(move || {
+            let leaves: Vec<_> = (0..mined_tree.next_leaf())
+                .map(|leaf_index| mined_tree.get_leaf(leaf_index))
+                .collect();
+            let leaf_count = leaves.len();
+
+            match TreeSnapshot::write(&path, tree_depth, initial_leaf_value, leaves, zstd_level) {
+                Ok(()) => info!(path, leaf_count, "Wrote tree snapshot"),
+                Err(error) => warn!(path, ?error, "Failed to write tree snapshot"),
+            }
+        }))
     }
 }
 
 #[cfg(test)]
 mod test {
+    use clap::Parser;
     use ethers::prelude::rand;
     use ethers::types::U256;
     use ruint::Uint;
 
-    use super::App;
-    use crate::identity_tree::TreeUpdate;
+    use super::{App, ExpectedRoot, Options, StartupProgress};
+    use crate::identity_tree::{Hash, TreeUpdate};
 
     pub fn generate_test_identities_with_index(identity_count: usize) -> Vec<TreeUpdate> {
         let mut identities = vec![];
@@ -901,4 +2903,95 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn startup_progress() {
+        let progress = StartupProgress::default();
+        assert_eq!(progress.percentage(), 0.0);
+
+        progress.reset(8);
+        progress.advance(2);
+        assert_eq!(progress.percentage(), 25.0);
+
+        // Never reports more than everything
+        progress.advance(10);
+        assert_eq!(progress.percentage(), 100.0);
+
+        progress.reset(8);
+        assert_eq!(progress.percentage(), 0.0);
+    }
+
+    #[test]
+    fn parses_expected_root() {
+        assert_eq!(
+            "chain".parse::<ExpectedRoot>().unwrap(),
+            ExpectedRoot::Chain
+        );
+        assert_eq!(
+            "0x2a".parse::<ExpectedRoot>().unwrap(),
+            ExpectedRoot::Root(Hash::from(42))
+        );
+        assert!("root".parse::<ExpectedRoot>().is_err());
+    }
+
+    fn options_with_groups(groups: &str) -> Result<Options, clap::Error> {
+        Options::try_parse_from([
+            "signup-sequencer",
+            "--identity-manager-address",
+            "0x0000000000000000000000000000000000000001",
+            "--database",
+            "postgres://localhost/default",
+            "--oz-api-key",
+            "",
+            "--oz-api-secret",
+            "",
+            "--oz-api-url",
+            "http://localhost:8545",
+            "--oz-address",
+            "0x0000000000000000000000000000000000000000",
+            "--dense-tree-mmap-file",
+            "tree",
+            "--groups",
+            groups,
+        ])
+    }
+
+    #[test]
+    fn groups_get_their_own_tree() {
+        let options = options_with_groups(
+            r#"[{"id": "app", "identityManagerAddress": "0x0000000000000000000000000000000000000002", "treeDepth": 16, "database": "postgres://localhost/app"}]"#,
+        )
+        .unwrap();
+
+        let groups = options.group_options().unwrap();
+        assert_eq!(groups.len(), 1);
+
+        let (id, group) = &groups[0];
+        assert_eq!(id, "app");
+        assert_eq!(
+            group.contracts.identity_manager_address,
+            "0x0000000000000000000000000000000000000002"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(group.contracts.tree_depth, 16);
+        assert_eq!(group.database.database.expose(), "postgres://localhost/app");
+        assert_eq!(group.dense_tree_mmap_file, "tree.app");
+        assert!(group.groups.0.is_empty());
+    }
+
+    #[test]
+    fn invalid_groups_are_rejected() {
+        let options = options_with_groups(
+            r#"[{"id": "app", "identityManagerAddress": "0x0000000000000000000000000000000000000002", "treeDepth": 16, "database": "postgres://localhost/default"}]"#,
+        )
+        .unwrap();
+        assert!(options.group_options().is_err());
+
+        let options = options_with_groups(
+            r#"[{"id": "../app", "identityManagerAddress": "0x0000000000000000000000000000000000000002", "treeDepth": 16, "database": "postgres://localhost/app"}]"#,
+        )
+        .unwrap();
+        assert!(options.group_options().is_err());
+    }
 }
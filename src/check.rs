@@ -0,0 +1,238 @@
+//! Pre-deploy self-test of the configuration and its dependencies.
+
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result as AnyhowResult};
+use ethers::providers::Middleware;
+use ethers::types::U256;
+use semaphore::poseidon_tree::LazyPoseidonTree;
+
+use crate::contracts::flavor::ContractAdapter;
+use crate::database::Database;
+use crate::ethereum::ReadProvider;
+use crate::utils::index_packing::padding_index;
+use crate::{config_validation, Options};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Pass,
+    Fail,
+    Skip,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pass => write!(f, "PASS"),
+            Self::Fail => write!(f, "FAIL"),
+            Self::Skip => write!(f, "SKIP"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Report {
+    results: Vec<(String, Outcome, String)>,
+}
+
+impl Report {
+    fn record(&mut self, name: impl Into<String>, outcome: Outcome, detail: impl Into<String>) {
+        self.results.push((name.into(), outcome, detail.into()));
+    }
+
+    /// Runs a check, recording its result. Returns the value produced by the
+    /// check if it passed.
+    async fn check<T>(
+        &mut self,
+        name: impl Into<String>,
+        check: impl Future<Output = AnyhowResult<(T, String)>>,
+    ) -> Option<T> {
+        match check.await {
+            Ok((value, detail)) => {
+                self.record(name, Outcome::Pass, detail);
+                Some(value)
+            }
+            Err(err) => {
+                self.record(name, Outcome::Fail, format!("{err:#}"));
+                None
+            }
+        }
+    }
+
+    fn skip(&mut self, name: impl Into<String>, reason: &str) {
+        self.record(name, Outcome::Skip, reason);
+    }
+
+    fn failed(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|(_, outcome, _)| *outcome == Outcome::Fail)
+            .count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, outcome, detail) in &self.results {
+            writeln!(f, "[{outcome}] {name}: {detail}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Validates the configuration and connectivity to the database, the chain
+/// and the provers without starting the sequencer, printing a report.
+///
+/// # Errors
+///
+/// Will return `Err` if any of the checks failed.
+pub async fn run(options: Options) -> AnyhowResult<()> {
+    let mut report = Report::default();
+
+    let contracts = &options.app.contracts;
+    let tree_depth = contracts.tree_depth;
+
+    report
+        .check("config", async {
+            config_validation::validate(&options)?;
+
+            let detail = match padding_index(tree_depth) {
+                Ok(_) => format!("tree depth {tree_depth}"),
+                Err(_) => {
+                    format!("tree depth {tree_depth}, deletions are not supported at this depth")
+                }
+            };
+
+            Ok(((), detail))
+        })
+        .await;
+
+    let database = report
+        .check(
+            "database",
+            Database::connect_for_check(&options.app.database),
+        )
+        .await;
+
+    let provider = report
+        .check("chain rpc", async {
+            let provider = ReadProvider::new(
+                options.app.ethereum.ethereum_provider.expose_url().clone(),
+                options.app.ethereum.circuit_breaker(),
+                options.app.retry.policy(),
+            )
+            .await?;
+            let block_number = provider.get_block_number().await?;
+            let detail = format!("chain id {}, at block {block_number}", provider.chain_id);
+
+            Ok((Arc::new(provider), detail))
+        })
+        .await;
+
+    if let Some(provider) = provider {
+        let address = contracts.identity_manager_address;
+        let signer = options.app.ethereum.write_options.oz_address;
+
+        let latest_root = report
+            .check("identity manager contract", async {
+                let code = provider.get_code(address, None).await?;
+                if code.as_ref().is_empty() {
+                    bail!("no contract code is deployed at {address:?}");
+                }
+
+                let contract = ContractAdapter::new(
+                    contracts.contract_flavor,
+                    address,
+                    contracts.semaphore_group_id,
+                    provider.clone(),
+                )
+                .with_tree_change_events(contracts.tree_change_events.clone());
+
+                let operator = contract.operator().await?;
+                if operator != signer {
+                    bail!("signer {signer:?} is not the identity operator {operator:?}");
+                }
+
+                let latest_root = contract.latest_root().await?;
+
+                Ok((
+                    latest_root,
+                    format!("{address:?}, operated by {operator:?}"),
+                ))
+            })
+            .await;
+
+        match (latest_root, &database) {
+            (Some(latest_root), Some(database)) => {
+                report
+                    .check("tree depth", async {
+                        let initial_root: U256 =
+                            LazyPoseidonTree::new(tree_depth, contracts.initial_leaf_value)
+                                .root()
+                                .into();
+
+                        // The depth can only be checked directly while the
+                        // contract holds the empty tree. Otherwise the root has
+                        // to have been computed by this sequencer.
+                        if latest_root == initial_root {
+                            return Ok(((), "contract holds the empty tree of this depth".into()));
+                        }
+
+                        if database
+                            .get_root_state(&latest_root.into())
+                            .await?
+                            .is_some()
+                        {
+                            return Ok((
+                                (),
+                                "latest contract root is known to the database".into(),
+                            ));
+                        }
+
+                        Err(anyhow!(
+                            "latest contract root {latest_root:#x} is neither the empty tree of \
+                             depth {tree_depth} nor known to the database"
+                        ))
+                    })
+                    .await;
+            }
+            _ => report.skip("tree depth", "requires the contract and the database"),
+        }
+    } else {
+        report.skip("identity manager contract", "requires the chain rpc");
+        report.skip("tree depth", "requires the chain rpc");
+    }
+
+    let client = reqwest::Client::new();
+    for prover in &options.app.batch_provers.prover_urls.0 {
+        let name = format!(
+            "{:?} prover (batch size {})",
+            prover.prover_type, prover.batch_size
+        );
+
+        report
+            .check(name, async {
+                // Any HTTP response means the prover is reachable, it only
+                // accepts proving requests.
+                let response = client.get(&prover.url).send().await?;
+
+                Ok((
+                    (),
+                    format!("{} responded with {}", prover.url, response.status()),
+                ))
+            })
+            .await;
+    }
+
+    println!("{report}");
+
+    let failed = report.failed();
+    if failed > 0 {
+        bail!("{failed} check(s) failed");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,270 @@
+//! Validation of the options as a whole, run before anything is initialized.
+//! Every problem is collected, so that a misconfigured deployment is fixed in
+//! one go rather than one restart per option.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use ethers::types::Address;
+use thiserror::Error;
+
+use crate::app::snark_scalar_field;
+use crate::contracts::MAX_TREE_DEPTH;
+use crate::identity_tree::Hash;
+use crate::prover::ProverType;
+use crate::task_monitor::tasks::monitor_balance::check_thresholds;
+use crate::{app, Command, Options};
+
+/// The problems found in the options.
+#[derive(Debug, Error)]
+pub struct InvalidConfig {
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for InvalidConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration:")?;
+        for problem in &self.problems {
+            write!(f, "\n  - {problem}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks the options, and combinations of them, for the command they run.
+///
+/// # Errors
+///
+/// Will return `Err` listing every problem found.
+pub fn validate(options: &Options) -> Result<(), InvalidConfig> {
+    let app = &options.app;
+    let contracts = &app.contracts;
+    let mut problems = vec![];
+
+    validate_tree(
+        &mut problems,
+        "",
+        contracts.tree_depth,
+        contracts.initial_leaf_value,
+    );
+    for group in &app.groups.0 {
+        validate_tree(
+            &mut problems,
+            &format!("group {}: ", group.id),
+            group.tree_depth,
+            group.initial_leaf_value,
+        );
+    }
+
+    if app.cpu_pool_queue_size == 0 {
+        problems.push(
+            "--cpu-pool-queue-size must be at least 1, no job could be run on the CPU pools"
+                .to_owned(),
+        );
+    }
+
+    // Instances only serving the API neither prove nor sign batches.
+    if submits_batches(options.command.as_ref()) {
+        validate_batch_sizes(&mut problems, app);
+        validate_signer(&mut problems, app);
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(InvalidConfig { problems })
+    }
+}
+
+/// Whether the command batches identities and submits them on chain, or checks
+/// a deployment which does.
+const fn submits_batches(command: Option<&Command>) -> bool {
+    matches!(command, None | Some(Command::Process | Command::Check))
+}
+
+fn validate_tree(
+    problems: &mut Vec<String>,
+    prefix: &str,
+    tree_depth: usize,
+    initial_leaf_value: Hash,
+) {
+    if tree_depth == 0 || tree_depth > MAX_TREE_DEPTH {
+        problems.push(format!(
+            "{prefix}tree depth {tree_depth} must be between 1 and {MAX_TREE_DEPTH}"
+        ));
+    }
+
+    if initial_leaf_value >= snark_scalar_field() {
+        problems.push(format!(
+            "{prefix}initial leaf value {initial_leaf_value:#x} is not below the snark scalar \
+             field, the contract can't hold it"
+        ));
+    }
+}
+
+fn validate_batch_sizes(problems: &mut Vec<String>, app: &app::Options) {
+    let provers = &app.batch_provers.prover_urls.0;
+    let committer = &app.committer;
+
+    let mut seen = HashSet::new();
+    for prover in provers {
+        if prover.batch_size == 0 {
+            problems.push(format!(
+                "--prover-urls: the {:?} prover at {} has a batch size of 0",
+                prover.prover_type, prover.url
+            ));
+        }
+        if !seen.insert((prover.prover_type, prover.batch_size)) {
+            problems.push(format!(
+                "--prover-urls: several {:?} provers have a batch size of {}",
+                prover.prover_type, prover.batch_size
+            ));
+        }
+    }
+
+    let mut insertion_sizes: Vec<usize> = provers
+        .iter()
+        .filter(|prover| prover.prover_type == ProverType::Insertion)
+        .map(|prover| prover.batch_size)
+        .collect();
+    insertion_sizes.sort_unstable();
+
+    let (Some(&smallest), Some(&largest)) = (insertion_sizes.first(), insertion_sizes.last())
+    else {
+        problems.push("--prover-urls has no insertion prover, no batch can be proven".to_owned());
+        return;
+    };
+
+    let min = committer.min_batch_size;
+    let max = committer.max_batch_size;
+    if max != 0 && min > max {
+        problems.push(format!(
+            "--min-batch-size {min} exceeds --max-batch-size {max}"
+        ));
+    }
+    if min > largest {
+        problems.push(format!(
+            "--min-batch-size {min} exceeds the largest insertion prover batch size {largest}"
+        ));
+    }
+    if max != 0 && max < smallest {
+        problems.push(format!(
+            "--max-batch-size {max} is below the smallest insertion prover batch size {smallest}"
+        ));
+    }
+
+    if let Err(error) = check_thresholds(
+        committer.signer_balance_soft_threshold,
+        committer.signer_balance_hard_threshold,
+    ) {
+        problems.push(error.to_string());
+    }
+}
+
+fn validate_signer(problems: &mut Vec<String>, app: &app::Options) {
+    let write_options = &app.ethereum.write_options;
+
+    if write_options.oz_address == Address::zero() {
+        problems.push(
+            "--oz-address is the zero address, batches can't be signed. Use the `serve` command \
+             for instances which only serve the API"
+                .to_owned(),
+        );
+    }
+
+    // Both are left empty to relay without authentication in development.
+    if !app.ethereum.fork_mode
+        && write_options.oz_api_key.is_empty() != write_options.oz_api_secret.is_empty()
+    {
+        problems.push("--oz-api-key and --oz-api-secret must be set together".to_owned());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    /// Parses the options of a sequencer submitting batches, with `overrides`
+    /// replacing or adding to the required ones.
+    fn options(overrides: &[(&str, &str)], command: Option<&str>) -> Options {
+        let mut args = vec![
+            ("--oz-api-key", ""),
+            ("--oz-api-secret", ""),
+            ("--oz-address", "0x0000000000000000000000000000000000000001"),
+            ("--database", "postgres://localhost"),
+            (
+                "--identity-manager-address",
+                "0x0000000000000000000000000000000000000002",
+            ),
+        ];
+        for &(name, value) in overrides {
+            match args.iter_mut().find(|arg| arg.0 == name) {
+                Some(arg) => arg.1 = value,
+                None => args.push((name, value)),
+            }
+        }
+
+        let args = args.into_iter().flat_map(|(name, value)| [name, value]);
+        Options::try_parse_from(
+            std::iter::once("signup-sequencer")
+                .chain(args)
+                .chain(command),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn accepts_the_defaults() {
+        validate(&options(&[], None)).unwrap();
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let error = validate(&options(
+            &[
+                ("--tree-depth", "0"),
+                (
+                    "--initial-leaf-value",
+                    "30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001",
+                ),
+                ("--min-batch-size", "5"),
+                ("--max-batch-size", "4"),
+                ("--oz-api-key", "key"),
+            ],
+            None,
+        ))
+        .unwrap_err();
+
+        assert_eq!(error.problems.len(), 5, "{error}");
+        assert!(error.problems[0].contains("tree depth 0"));
+        assert!(error.problems[1].contains("not below the snark scalar field"));
+        assert!(error.problems[2].contains("--min-batch-size 5 exceeds --max-batch-size 4"));
+        assert!(error.problems[3].contains("largest insertion prover batch size 3"));
+        assert!(error.problems[4].contains("--oz-api-secret"));
+    }
+
+    #[test]
+    fn rejects_an_empty_cpu_pool_queue() {
+        let error =
+            validate(&options(&[("--cpu-pool-queue-size", "0")], Some("serve"))).unwrap_err();
+
+        assert_eq!(error.problems.len(), 1, "{error}");
+        assert!(error.problems[0].contains("--cpu-pool-queue-size"));
+    }
+
+    #[test]
+    fn api_only_instances_need_no_signer() {
+        let no_signer = [
+            ("--oz-address", "0x0000000000000000000000000000000000000000"),
+            ("--prover-urls", "[]"),
+        ];
+
+        let error = validate(&options(&no_signer, None)).unwrap_err();
+        assert_eq!(error.problems.len(), 2, "{error}");
+
+        validate(&options(&no_signer, Some("serve"))).unwrap();
+    }
+}
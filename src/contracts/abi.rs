@@ -2,6 +2,8 @@
 
 use ethers::prelude::abigen;
 
+pub use super::bindings::{DeleteIdentitiesCall, RegisterIdentitiesCall};
+
 /// The `TreeChanged` event emitted by the `IdentityManager` contract.
 /// Maps to the following enum in the contract code:
 ///
@@ -30,16 +32,25 @@ impl From<u8> for TreeChangeKind {
     }
 }
 
+impl From<TreeChangeKind> for &str {
+    fn from(kind: TreeChangeKind) -> Self {
+        match kind {
+            TreeChangeKind::Insertion => "insertion",
+            TreeChangeKind::Deletion => "deletion",
+            TreeChangeKind::Update => "update",
+        }
+    }
+}
+
 abigen!(
     WorldId,
     r#"[
         struct RootInfo { uint256 root; uint128 supersededTimestamp; bool isValid }
         event TreeChanged(uint256 indexed preRoot, uint8 indexed kind, uint256 indexed postRoot)
-        function registerIdentities(uint256[8] calldata insertionProof, uint256 preRoot, uint32 startIndex, uint256[] calldata identityCommitments, uint256 postRoot) public virtual
-        function deleteIdentities(uint256[8] calldata deletionProof, bytes calldata packedDeletionIndices, uint256 preRoot, uint256 postRoot) public virtual
         function latestRoot() public view virtual returns (uint256 root)
         function owner() public view virtual returns (address)
         function identityOperator() public view virtual returns (address)
+        function paused() public view virtual returns (bool)
         function queryRoot(uint256 root) public view virtual returns (RootInfo memory)
         function getRootHistoryExpiry() external view returns (uint256)
     ]"#,
@@ -53,3 +64,13 @@ abigen!(
         function latestRoot() public view returns (uint256 root)
     ]"#
 );
+
+abigen!(
+    SemaphoreV3,
+    r#"[
+        event MemberAdded(uint256 indexed groupId, uint256 index, uint256 identityCommitment, uint256 merkleTreeRoot)
+        function addMembers(uint256 groupId, uint256[] calldata identityCommitments) external
+        function getMerkleTreeRoot(uint256 groupId) external view returns (uint256)
+        function groups(uint256 groupId) external view returns (address admin, uint256 merkleTreeDuration)
+    ]"#
+);
@@ -0,0 +1,6 @@
+//! Bindings of the WorldID identity manager generated by `build.rs` from its
+//! checked-in ABI, so that the calls the sequencer builds can't drift from
+//! the contract. The batch calls are only available through these.
+#![allow(clippy::all, clippy::pedantic, clippy::nursery, clippy::cargo)]
+
+include!(concat!(env!("OUT_DIR"), "/world_id_identity_manager.rs"));
@@ -0,0 +1,202 @@
+//! Calldata of the batch calls of the WorldID identity manager. Its layout has
+//! to match the contract exactly, so the bindings are generated from the ABI
+//! of the compiled contract, and checked against hand-written fixtures in the
+//! tests.
+
+use ethers::abi::{AbiDecode, AbiError};
+
+use super::abi::{DeleteIdentitiesCall, RegisterIdentitiesCall};
+
+/// Decodes the calldata of a `registerIdentities` transaction.
+///
+/// # Errors
+///
+/// Will return `Err` if the calldata is of another call, e.g. because the
+/// batch was submitted through another contract.
+pub fn decode_insertion(calldata: &[u8]) -> Result<RegisterIdentitiesCall, AbiError> {
+    RegisterIdentitiesCall::decode(calldata)
+}
+
+/// Decodes the calldata of a `deleteIdentities` transaction.
+///
+/// # Errors
+///
+/// Will return `Err` if the calldata is of another call.
+pub fn decode_deletion(calldata: &[u8]) -> Result<DeleteIdentitiesCall, AbiError> {
+    DeleteIdentitiesCall::decode(calldata)
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::abi::{Abi, AbiEncode, Function, Token};
+    use ethers::contract::EthCall;
+    use ethers::types::{Bytes, U256};
+    use serde_json::Value;
+
+    use super::*;
+
+    /// The artifact of the deployed implementation, as compiled from the
+    /// contract sources.
+    const ARTIFACT: &str = include_str!("../../sol/WorldIDIdentityManagerImplV2.json");
+
+    fn artifact() -> Value {
+        serde_json::from_str(ARTIFACT).unwrap()
+    }
+
+    fn function(name: &str) -> Function {
+        let abi: Abi = serde_json::from_value(artifact()["abi"].clone()).unwrap();
+        abi.function(name).unwrap().clone()
+    }
+
+    /// Calldata laid out by hand: the selector followed by 32 byte words.
+    fn calldata(selector: &str, words: &[U256], tail: &[u8]) -> Vec<u8> {
+        let mut calldata = hex::decode(selector).unwrap();
+        for word in words {
+            calldata.extend(word.encode());
+        }
+        calldata.extend(tail);
+        calldata
+    }
+
+    fn proof() -> [U256; 8] {
+        [1, 2, 3, 4, 5, 6, 7, 8].map(U256::from)
+    }
+
+    fn insertion() -> RegisterIdentitiesCall {
+        RegisterIdentitiesCall {
+            insertion_proof:      proof(),
+            pre_root:             U256::from(9),
+            start_index:          10,
+            identity_commitments: vec![U256::from(11), U256::from(12)],
+            post_root:            U256::from(13),
+        }
+    }
+
+    fn deletion() -> DeleteIdentitiesCall {
+        DeleteIdentitiesCall {
+            deletion_proof:          proof(),
+            packed_deletion_indices: Bytes::from(vec![0, 0, 0, 5, 0, 0, 0, 7]),
+            pre_root:                U256::from(9),
+            post_root:               U256::from(13),
+        }
+    }
+
+    #[test]
+    fn selectors_match_the_contract() {
+        let identifiers = &artifact()["methodIdentifiers"];
+
+        assert_eq!(
+            identifiers["registerIdentities(uint256[8],uint256,uint32,uint256[],uint256)"],
+            hex::encode(RegisterIdentitiesCall::selector())
+        );
+        assert_eq!(
+            identifiers["deleteIdentities(uint256[8],bytes,uint256,uint256)"],
+            hex::encode(DeleteIdentitiesCall::selector())
+        );
+    }
+
+    #[test]
+    fn insertion_matches_the_contract_abi() {
+        let call = insertion();
+        let tokens = vec![
+            Token::FixedArray(
+                call.insertion_proof
+                    .iter()
+                    .copied()
+                    .map(Token::Uint)
+                    .collect(),
+            ),
+            Token::Uint(call.pre_root),
+            Token::Uint(call.start_index.into()),
+            Token::Array(
+                call.identity_commitments
+                    .iter()
+                    .copied()
+                    .map(Token::Uint)
+                    .collect(),
+            ),
+            Token::Uint(call.post_root),
+        ];
+
+        let expected = function("registerIdentities")
+            .encode_input(&tokens)
+            .unwrap();
+        let encoded = call.clone().encode();
+
+        assert_eq!(encoded, expected);
+        assert_eq!(decode_insertion(&expected).unwrap(), call);
+    }
+
+    #[test]
+    fn insertion_matches_the_fixture() {
+        let words = [1, 2, 3, 4, 5, 6, 7, 8] // insertionProof
+            .into_iter()
+            .chain([
+                9,       // preRoot
+                10,      // startIndex
+                12 * 32, // offset of identityCommitments
+                13,      // postRoot
+                2,       // identityCommitments.length
+                11,
+                12,
+            ])
+            .map(U256::from)
+            .collect::<Vec<_>>();
+        let fixture = calldata("2217b211", &words, &[]);
+
+        assert_eq!(insertion().encode(), fixture);
+        assert_eq!(decode_insertion(&fixture).unwrap(), insertion());
+    }
+
+    #[test]
+    fn deletion_matches_the_contract_abi() {
+        let call = deletion();
+        let tokens = vec![
+            Token::FixedArray(
+                call.deletion_proof
+                    .iter()
+                    .copied()
+                    .map(Token::Uint)
+                    .collect(),
+            ),
+            Token::Bytes(call.packed_deletion_indices.to_vec()),
+            Token::Uint(call.pre_root),
+            Token::Uint(call.post_root),
+        ];
+
+        let expected = function("deleteIdentities").encode_input(&tokens).unwrap();
+        let encoded = call.clone().encode();
+
+        assert_eq!(encoded, expected);
+        assert_eq!(decode_deletion(&expected).unwrap(), call);
+    }
+
+    #[test]
+    fn deletion_matches_the_fixture() {
+        let words = [1, 2, 3, 4, 5, 6, 7, 8] // deletionProof
+            .into_iter()
+            .chain([
+                11 * 32, // offset of packedDeletionIndices
+                9,       // preRoot
+                13,      // postRoot
+                8,       // packedDeletionIndices.length
+            ])
+            .map(U256::from)
+            .collect::<Vec<_>>();
+        let mut tail = vec![0, 0, 0, 5, 0, 0, 0, 7];
+        tail.resize(32, 0);
+        let fixture = calldata("ea10fbbe", &words, &tail);
+
+        assert_eq!(deletion().encode(), fixture);
+        assert_eq!(decode_deletion(&fixture).unwrap(), deletion());
+    }
+
+    #[test]
+    fn other_calls_are_not_decoded() {
+        let mut calldata = insertion().encode();
+        calldata[0] ^= 0xff;
+
+        assert!(decode_insertion(&calldata).is_err());
+        assert!(decode_deletion(&insertion().encode()).is_err());
+    }
+}
@@ -0,0 +1,464 @@
+//! Adapters over the interfaces of the contracts identities can be inserted
+//! into.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, ensure};
+use clap::ValueEnum;
+use ethers::abi::{Event, HumanReadableParser, RawLog};
+use ethers::contract::{ContractCall, EthEvent};
+use ethers::types::{Address, Log, Topic, H256, U256};
+
+use super::abi::{MemberAddedFilter, SemaphoreV3, TreeChangeKind, TreeChangedFilter, WorldId};
+use super::bindings::WorldIdIdentityManager;
+use crate::ethereum::ReadProvider;
+
+/// The interface of the contract the tree is maintained in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ContractFlavor {
+    /// The World ID identity manager, which verifies a proof of every batch
+    /// and keeps a history of roots.
+    #[default]
+    WorldId,
+    /// A Semaphore v3 group, which recomputes the tree on chain.
+    SemaphoreV3,
+}
+
+impl ContractFlavor {
+    /// Whether batches have to be accompanied by a proof of the insertion.
+    #[must_use]
+    pub const fn requires_batch_proofs(self) -> bool {
+        matches!(self, Self::WorldId)
+    }
+
+    #[must_use]
+    pub const fn supports_deletions(self) -> bool {
+        matches!(self, Self::WorldId)
+    }
+
+    /// Whether superseded roots can still be queried from the contract.
+    #[must_use]
+    pub const fn has_root_history(self) -> bool {
+        matches!(self, Self::WorldId)
+    }
+}
+
+/// A change of the root emitted by the contract.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TreeChange {
+    /// The root before the change, if the event includes it.
+    pub pre_root:  Option<U256>,
+    pub post_root: U256,
+    pub kind:      TreeChangeKind,
+}
+
+/// An event announcing root changes, declared like in Solidity, e.g.
+/// `TreeChanged(uint256 indexed preRoot, uint8 indexed kind, uint256 indexed
+/// postRoot)`. Overrides the event of the contract flavor, so contracts whose
+/// event was renamed or changed can be followed without code changes.
+///
+/// Parameters are recognized by name. The new root is `postRoot`, `root` or
+/// `merkleTreeRoot` and is required. `preRoot`, `kind` (insertions if absent)
+/// and `groupId` (events of other groups are ignored) are optional.
+#[derive(Clone, Debug)]
+pub struct TreeChangeEvent {
+    declaration: String,
+    event:       Event,
+}
+
+const POST_ROOT_PARAMS: [&str; 3] = ["postRoot", "root", "merkleTreeRoot"];
+
+impl FromStr for TreeChangeEvent {
+    type Err = anyhow::Error;
+
+    fn from_str(declaration: &str) -> Result<Self, Self::Err> {
+        let declaration = declaration.trim();
+        let event = if declaration.starts_with("event ") {
+            HumanReadableParser::parse_event(declaration)
+        } else {
+            HumanReadableParser::parse_event(&format!("event {declaration}"))
+        }
+        .map_err(|error| anyhow!("invalid event {declaration}: {error}"))?;
+
+        ensure!(
+            event
+                .inputs
+                .iter()
+                .any(|input| POST_ROOT_PARAMS.contains(&input.name.as_str())),
+            "event {} has none of the root parameters {POST_ROOT_PARAMS:?}",
+            event.name
+        );
+
+        Ok(Self {
+            declaration: declaration.to_owned(),
+            event,
+        })
+    }
+}
+
+impl PartialEq for TreeChangeEvent {
+    fn eq(&self, other: &Self) -> bool {
+        self.declaration == other.declaration
+    }
+}
+
+impl Eq for TreeChangeEvent {}
+
+impl TreeChangeEvent {
+    #[must_use]
+    pub fn topic(&self) -> H256 {
+        self.event.signature()
+    }
+
+    fn decode(&self, raw_log: &RawLog, group_id: U256) -> Option<TreeChange> {
+        if raw_log.topics.first() != Some(&self.topic()) {
+            return None;
+        }
+
+        let log = self.event.parse_log(raw_log.clone()).ok()?;
+        let param = |names: &[&str]| {
+            log.params
+                .iter()
+                .find(|param| names.contains(&param.name.as_str()))
+                .and_then(|param| param.value.clone().into_uint())
+        };
+
+        if param(&["groupId"]).is_some_and(|id| id != group_id) {
+            return None;
+        }
+
+        let kind = match param(&["kind"]) {
+            Some(kind) if kind > U256::from(2) => return None,
+            Some(kind) => TreeChangeKind::from(kind.as_u32() as u8),
+            None => TreeChangeKind::Insertion,
+        };
+
+        Some(TreeChange {
+            pre_root: param(&["preRoot"]),
+            post_root: param(&POST_ROOT_PARAMS)?,
+            kind,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ContractAdapter {
+    abi:                ContractAbi,
+    tree_change_events: Vec<TreeChangeEvent>,
+}
+
+#[derive(Debug)]
+enum ContractAbi {
+    WorldId(WorldId<ReadProvider>),
+    SemaphoreV3 {
+        abi:      SemaphoreV3<ReadProvider>,
+        group_id: U256,
+    },
+}
+
+impl ContractAdapter {
+    #[must_use]
+    pub fn new(
+        flavor: ContractFlavor,
+        address: Address,
+        group_id: U256,
+        provider: Arc<ReadProvider>,
+    ) -> Self {
+        let abi = match flavor {
+            ContractFlavor::WorldId => ContractAbi::WorldId(WorldId::new(address, provider)),
+            ContractFlavor::SemaphoreV3 => ContractAbi::SemaphoreV3 {
+                abi: SemaphoreV3::new(address, provider),
+                group_id,
+            },
+        };
+
+        Self {
+            abi,
+            tree_change_events: vec![],
+        }
+    }
+
+    /// Follows root changes through the given events instead of the event of
+    /// the flavor, unless empty.
+    #[must_use]
+    pub fn with_tree_change_events(mut self, events: Vec<TreeChangeEvent>) -> Self {
+        self.tree_change_events = events;
+        self
+    }
+
+    #[must_use]
+    pub const fn flavor(&self) -> ContractFlavor {
+        match &self.abi {
+            ContractAbi::WorldId(_) => ContractFlavor::WorldId,
+            ContractAbi::SemaphoreV3 { .. } => ContractFlavor::SemaphoreV3,
+        }
+    }
+
+    #[must_use]
+    pub fn client(&self) -> Arc<ReadProvider> {
+        match &self.abi {
+            ContractAbi::WorldId(abi) => abi.client(),
+            ContractAbi::SemaphoreV3 { abi, .. } => abi.client(),
+        }
+    }
+
+    #[must_use]
+    pub fn address(&self) -> Address {
+        match &self.abi {
+            ContractAbi::WorldId(abi) => abi.address(),
+            ContractAbi::SemaphoreV3 { abi, .. } => abi.address(),
+        }
+    }
+
+    /// The account allowed to modify the tree, the group admin for Semaphore.
+    pub async fn operator(&self) -> anyhow::Result<Address> {
+        match &self.abi {
+            ContractAbi::WorldId(abi) => Ok(abi.identity_operator().call().await?),
+            ContractAbi::SemaphoreV3 { abi, group_id } => Ok(abi.groups(*group_id).call().await?.0),
+        }
+    }
+
+    /// Whether the owner paused the contract, `None` if it can't be paused.
+    pub async fn paused(&self) -> anyhow::Result<Option<bool>> {
+        match &self.abi {
+            ContractAbi::WorldId(abi) => match abi.paused().call().await {
+                Ok(paused) => Ok(Some(paused)),
+                // Implementations which aren't pausable revert.
+                Err(error) if error.is_revert() => Ok(None),
+                Err(error) => Err(error.into()),
+            },
+            ContractAbi::SemaphoreV3 { .. } => Ok(None),
+        }
+    }
+
+    pub async fn latest_root(&self) -> anyhow::Result<U256> {
+        match &self.abi {
+            ContractAbi::WorldId(abi) => Ok(abi.latest_root().call().await?),
+            ContractAbi::SemaphoreV3 { abi, group_id } => {
+                Ok(abi.get_merkle_tree_root(*group_id).call().await?)
+            }
+        }
+    }
+
+    /// Whether the root is known to the contract. Without a root history only
+    /// the latest root is.
+    pub async fn is_root_mined(&self, root: U256) -> anyhow::Result<bool> {
+        match &self.abi {
+            ContractAbi::WorldId(abi) => {
+                let (root_on_mainnet, ..) = abi.query_root(root).call().await?;
+
+                Ok(!root_on_mainnet.is_zero())
+            }
+            ContractAbi::SemaphoreV3 { .. } => Ok(self.latest_root().await? == root),
+        }
+    }
+
+    pub async fn root_history_expiry(&self) -> anyhow::Result<U256> {
+        match &self.abi {
+            ContractAbi::WorldId(abi) => Ok(abi.get_root_history_expiry().call().await?),
+            ContractAbi::SemaphoreV3 { abi, group_id } => Ok(abi.groups(*group_id).call().await?.1),
+        }
+    }
+
+    /// Builds the call inserting a batch of commitments.
+    #[must_use]
+    pub fn insertion_call(
+        &self,
+        proof: [U256; 8],
+        pre_root: U256,
+        start_index: u32,
+        identity_commitments: Vec<U256>,
+        post_root: U256,
+    ) -> ContractCall<ReadProvider, ()> {
+        match &self.abi {
+            ContractAbi::WorldId(abi) => {
+                let manager = WorldIdIdentityManager::new(abi.address(), abi.client());
+                manager.register_identities(
+                    proof,
+                    pre_root,
+                    start_index,
+                    identity_commitments,
+                    post_root,
+                )
+            }
+            ContractAbi::SemaphoreV3 { abi, group_id } => {
+                // Batches are padded with zeros for the prover, which would be
+                // added as members here.
+                let identity_commitments = identity_commitments
+                    .into_iter()
+                    .filter(|commitment| !commitment.is_zero())
+                    .collect();
+
+                abi.add_members(*group_id, identity_commitments)
+            }
+        }
+    }
+
+    /// Builds the call deleting a batch of identities.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the contract doesn't support deletions.
+    pub fn deletion_call(
+        &self,
+        proof: [U256; 8],
+        packed_deletion_indices: Vec<u8>,
+        pre_root: U256,
+        post_root: U256,
+    ) -> anyhow::Result<ContractCall<ReadProvider, ()>> {
+        match &self.abi {
+            ContractAbi::WorldId(abi) => {
+                let manager = WorldIdIdentityManager::new(abi.address(), abi.client());
+                Ok(manager.delete_identities(
+                    proof,
+                    packed_deletion_indices.into(),
+                    pre_root,
+                    post_root,
+                ))
+            }
+            ContractAbi::SemaphoreV3 { .. } => bail!("Semaphore v3 groups don't support deletions"),
+        }
+    }
+
+    /// The topic of the events emitted when the root changes.
+    #[must_use]
+    pub fn tree_change_topic(&self) -> Topic {
+        if !self.tree_change_events.is_empty() {
+            return Topic::Array(
+                self.tree_change_events
+                    .iter()
+                    .map(|event| Some(event.topic()))
+                    .collect(),
+            );
+        }
+
+        match &self.abi {
+            ContractAbi::WorldId(_) => Topic::from(TreeChangedFilter::signature()),
+            ContractAbi::SemaphoreV3 { .. } => Topic::from(MemberAddedFilter::signature()),
+        }
+    }
+
+    /// Decodes a root change, returns `None` for unrelated logs.
+    #[must_use]
+    pub fn decode_tree_change(&self, log: &Log) -> Option<TreeChange> {
+        let raw_log = RawLog::from((log.topics.clone(), log.data.to_vec()));
+
+        if !self.tree_change_events.is_empty() {
+            let group_id = match &self.abi {
+                ContractAbi::WorldId(_) => U256::zero(),
+                ContractAbi::SemaphoreV3 { group_id, .. } => *group_id,
+            };
+
+            return self
+                .tree_change_events
+                .iter()
+                .find_map(|event| event.decode(&raw_log, group_id));
+        }
+
+        match &self.abi {
+            ContractAbi::WorldId(_) => {
+                let event = TreeChangedFilter::decode_log(&raw_log).ok()?;
+
+                Some(TreeChange {
+                    pre_root:  Some(event.pre_root),
+                    post_root: event.post_root,
+                    kind:      TreeChangeKind::from(event.kind),
+                })
+            }
+            ContractAbi::SemaphoreV3 { group_id, .. } => decode_member_added(&raw_log, *group_id),
+        }
+    }
+}
+
+/// Every member added to a Semaphore group changes the root, so batches emit
+/// one event per commitment.
+fn decode_member_added(raw_log: &RawLog, group_id: U256) -> Option<TreeChange> {
+    let event = MemberAddedFilter::decode_log(raw_log).ok()?;
+    if event.group_id != group_id {
+        return None;
+    }
+
+    Some(TreeChange {
+        pre_root:  None,
+        post_root: event.merkle_tree_root,
+        kind:      TreeChangeKind::Insertion,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::abi::{encode, Token};
+
+    use super::*;
+
+    #[test]
+    fn semaphore_flavor_skips_proofs_and_deletions() {
+        let flavor = ContractFlavor::SemaphoreV3;
+
+        assert!(!flavor.requires_batch_proofs());
+        assert!(!flavor.supports_deletions());
+        assert!(!flavor.has_root_history());
+        assert!(ContractFlavor::default().requires_batch_proofs());
+    }
+
+    #[test]
+    fn decodes_member_added_for_the_group() {
+        let member_added = |group_id: u64| {
+            RawLog::from((
+                vec![
+                    MemberAddedFilter::signature(),
+                    H256::from_low_u64_be(group_id),
+                ],
+                encode(&[
+                    Token::Uint(U256::from(3)),
+                    Token::Uint(U256::from(42)),
+                    Token::Uint(U256::from(1234)),
+                ]),
+            ))
+        };
+
+        assert_eq!(
+            decode_member_added(&member_added(7), U256::from(7)),
+            Some(TreeChange {
+                pre_root:  None,
+                post_root: U256::from(1234),
+                kind:      TreeChangeKind::Insertion,
+            })
+        );
+        assert_eq!(decode_member_added(&member_added(8), U256::from(7)), None);
+    }
+
+    #[test]
+    fn decodes_configured_tree_change_events() {
+        let tree_changed: TreeChangeEvent = "TreeChanged(uint256 indexed preRoot, uint8 indexed \
+                                             kind, uint256 indexed postRoot)"
+            .parse()
+            .unwrap();
+        assert_eq!(tree_changed.topic(), TreeChangedFilter::signature());
+
+        let root_updated: TreeChangeEvent = "event RootUpdated(uint256 indexed preRoot, uint256 \
+                                             root)"
+            .parse()
+            .unwrap();
+        let log = RawLog::from((
+            vec![root_updated.topic(), H256::from_low_u64_be(1)],
+            encode(&[Token::Uint(U256::from(2))]),
+        ));
+
+        assert_eq!(
+            root_updated.decode(&log, U256::zero()),
+            Some(TreeChange {
+                pre_root:  Some(U256::from(1)),
+                post_root: U256::from(2),
+                kind:      TreeChangeKind::Insertion,
+            })
+        );
+        assert_eq!(tree_changed.decode(&log, U256::zero()), None);
+
+        assert!("RootUpdated(uint256 indexed preRoot)"
+            .parse::<TreeChangeEvent>()
+            .is_err());
+        assert!("not an event".parse::<TreeChangeEvent>().is_err());
+    }
+}
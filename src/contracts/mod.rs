@@ -1,27 +1,50 @@
 //! Functionality for interacting with smart contracts deployed on chain.
 pub mod abi;
+pub mod bindings;
+pub mod encoding;
+pub mod flavor;
 pub mod scanner;
+pub mod shadow;
 
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
 use clap::Parser;
+use ethers::contract::ContractCall;
 use ethers::providers::Middleware;
-use ethers::types::{Address, H256, U256};
+use ethers::types::{Address, Bytes, Topic, ValueOrArray, H256, U256, U64};
+use futures::future::join_all;
 use semaphore::Field;
+use thiserror::Error;
 use tokio::sync::RwLockReadGuard;
 use tracing::{error, info, instrument, warn};
 
-use self::abi::{BridgedWorldId, DeleteIdentitiesCall, WorldId};
+use self::abi::{BridgedWorldId, RegisterIdentitiesCall, TreeChangeKind};
+use self::flavor::{ContractAdapter, ContractFlavor, TreeChangeEvent};
+use self::scanner::BlockScanner;
+use self::shadow::{BatchKind, ShadowBatch, ShadowIdentityManager};
+use crate::database::Error as DatabaseError;
+use crate::ethereum::read::cache::ChainReadCache;
+use crate::ethereum::read::circuit_breaker::BreakerStatus;
+use crate::ethereum::signing_log::{SigningPurpose, SigningRecord};
 use crate::ethereum::write::TransactionId;
 use crate::ethereum::{Ethereum, ReadProvider};
+use crate::fault_injection::{self, FaultPoint};
 use crate::prover::identity::Identity;
-use crate::prover::map::{DeletionProverMap, InsertionProverMap, ReadOnlyInsertionProver};
-use crate::prover::{Proof, Prover, ProverConfiguration, ProverType, ReadOnlyProver};
+use crate::prover::map::{DeletionProverMap, InsertionProverMap};
+use crate::prover::{
+    KeyStatus, Proof, Prover, ProverConfiguration, ProverKeyReport, ProverType, ReadOnlyProver,
+};
 use crate::serde_utils::JsonStrWrapper;
 use crate::server::error::Error as ServerError;
-use crate::utils::index_packing::unpack_indices;
+use crate::utils::index_packing::{padding_index, unpack_indices};
+use crate::utils::retry::RetryPolicy;
+
+/// The deepest tree whose leaf indices can still be persisted.
+pub const MAX_TREE_DEPTH: usize = 63;
 
 /// Configuration options for the component responsible for interacting with the
 /// contract.
@@ -51,6 +74,54 @@ pub struct Options {
         default_value = "0000000000000000000000000000000000000000000000000000000000000000"
     )]
     pub initial_leaf_value: Field,
+
+    /// The address of a shadow identity manager contract. If set, every batch
+    /// is also submitted to this contract without affecting the sequencer
+    /// state, and root divergences are reported through metrics.
+    #[clap(long, env)]
+    pub shadow_identity_manager_address: Option<Address>,
+
+    /// The interface of the identity manager contract. Semaphore groups
+    /// recompute the tree on chain, so no batch proofs are generated for them,
+    /// but provers are still needed to determine the batch sizes.
+    #[clap(long, env, value_enum, default_value = "world-id")]
+    pub contract_flavor: ContractFlavor,
+
+    /// The group identities are added to, for the Semaphore flavors.
+    #[clap(long, env, value_parser = U256::from_dec_str, default_value = "0")]
+    pub semaphore_group_id: U256,
+
+    /// Events announcing root changes, to follow instead of the event of the
+    /// contract flavor, separated by `;`. Each is declared like in Solidity,
+    /// e.g. `TreeChanged(uint256 indexed preRoot, uint8 indexed kind, uint256
+    /// indexed postRoot)`, with the new root in a `postRoot`, `root` or
+    /// `merkleTreeRoot` parameter and optional `preRoot`, `kind` and `groupId`
+    /// parameters. Listing several follows contracts across an upgrade which
+    /// changed the event.
+    #[clap(long, env, value_delimiter = ';')]
+    pub tree_change_events: Vec<TreeChangeEvent>,
+
+    /// How long the block number, the gas price and the latest root of the
+    /// contract are cached (milliseconds). Over websockets they're also
+    /// refreshed on every new block. 0 disables the cache.
+    #[clap(long, env, default_value = "1000")]
+    pub chain_read_cache_ttl_ms: u64,
+}
+
+/// A batch which reverted when simulated before being broadcast.
+#[derive(Clone, Debug, Error)]
+#[error("batch reverted in simulation: {reason}")]
+pub struct SimulatedRevert {
+    pub reason:   String,
+    pub calldata: Bytes,
+}
+
+/// A batch handed to the signer.
+#[derive(Clone, Debug)]
+pub struct SentBatch {
+    pub transaction_id: TransactionId,
+    /// Size of the calldata of the transaction in bytes
+    pub calldata_size:  usize,
 }
 
 /// A structure representing the interface to the batch-based identity manager
@@ -60,16 +131,19 @@ pub struct IdentityManager {
     ethereum:             Ethereum,
     insertion_prover_map: InsertionProverMap,
     deletion_prover_map:  DeletionProverMap,
-    abi:                  WorldId<ReadProvider>,
+    contract:             ContractAdapter,
     secondary_abis:       Vec<BridgedWorldId<ReadProvider>>,
     initial_leaf_value:   Field,
     tree_depth:           usize,
+    shadow:               Option<ShadowIdentityManager>,
+    prover_retry:         RetryPolicy,
+    chain_reads:          ChainReadCache,
 }
 
 impl IdentityManager {
     // TODO: I don't like these public getters
-    pub fn abi(&self) -> &WorldId<ReadProvider> {
-        &self.abi
+    pub fn contract(&self) -> &ContractAdapter {
+        &self.contract
     }
 
     pub fn secondary_abis(&self) -> &[BridgedWorldId<ReadProvider>] {
@@ -82,6 +156,7 @@ impl IdentityManager {
         ethereum: Ethereum,
         insertion_prover_map: InsertionProverMap,
         deletion_prover_map: DeletionProverMap,
+        prover_retry: RetryPolicy,
     ) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -97,12 +172,15 @@ impl IdentityManager {
         }
 
         // Connect to the running batching contract.
-        let abi = WorldId::new(
+        let contract = ContractAdapter::new(
+            options.contract_flavor,
             options.identity_manager_address,
+            options.semaphore_group_id,
             ethereum.provider().clone(),
-        );
+        )
+        .with_tree_change_events(options.tree_change_events);
 
-        let operator = abi.identity_operator().call().await?;
+        let operator = contract.operator().await?;
         if operator != ethereum.address() {
             error!(?operator, signer = ?ethereum.address(), "Signer is not the identity operator of the identity manager contract.");
             panic!("Cannot currently continue in read-only mode.")
@@ -111,6 +189,7 @@ impl IdentityManager {
         info!(
             ?address,
             ?operator,
+            flavor = ?options.contract_flavor,
             "Connected to the WorldID Identity Manager"
         );
 
@@ -118,6 +197,15 @@ impl IdentityManager {
 
         let mut secondary_abis = Vec::new();
         for (chain_id, address) in options.relayed_identity_manager_addresses.0 {
+            if ethereum.fork_mode() {
+                warn!(
+                    chain_id,
+                    ?address,
+                    "Ignoring relayed identity manager in fork mode"
+                );
+                continue;
+            }
+
             let provider = secondary_providers
                 .get(&chain_id)
                 .ok_or_else(|| anyhow!("No provider for chain id: {}", chain_id))?;
@@ -126,17 +214,45 @@ impl IdentityManager {
             secondary_abis.push(abi);
         }
 
+        let shadow = match options.shadow_identity_manager_address {
+            Some(address) => Some(ShadowIdentityManager::new(address, &ethereum).await?),
+            None => None,
+        };
+
         let initial_leaf_value = options.initial_leaf_value;
         let tree_depth = options.tree_depth;
 
+        // Leaf indices are persisted as signed 64 bit integers.
+        if tree_depth > MAX_TREE_DEPTH {
+            return Err(anyhow!(
+                "Tree depth {tree_depth} exceeds the maximum supported depth of {MAX_TREE_DEPTH}"
+            ));
+        }
+
+        if padding_index(tree_depth).is_err() {
+            warn!(
+                tree_depth,
+                "Deletions and insertions beyond leaf index 2^32 can't be encoded for the \
+                 identity manager contract at this tree depth."
+            );
+        }
+
+        let chain_reads = ChainReadCache::new(
+            Duration::from_millis(options.chain_read_cache_ttl_ms),
+            ethereum.provider(),
+        );
+
         let identity_manager = Self {
             ethereum,
             insertion_prover_map,
             deletion_prover_map,
-            abi,
+            contract,
             secondary_abis,
             initial_leaf_value,
             tree_depth,
+            shadow,
+            prover_retry,
+            chain_reads,
         };
 
         Ok(identity_manager)
@@ -147,6 +263,41 @@ impl IdentityManager {
         self.tree_depth
     }
 
+    /// The chain the identity manager contract is deployed on.
+    #[must_use]
+    pub fn chain_id(&self) -> U256 {
+        self.ethereum.provider().chain_id
+    }
+
+    #[must_use]
+    pub fn address(&self) -> Address {
+        self.contract.address()
+    }
+
+    #[must_use]
+    pub fn signer_address(&self) -> Address {
+        self.ethereum.address()
+    }
+
+    /// The state of the circuit breaker in front of the Ethereum provider.
+    #[must_use]
+    pub fn provider_status(&self) -> BreakerStatus {
+        self.ethereum.provider().breaker_status()
+    }
+
+    /// The operations of the signer recorded in `[from, to)`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the operations can't be read from the database.
+    pub async fn signing_records(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SigningRecord>, DatabaseError> {
+        self.ethereum.signing_log().records(from, to).await
+    }
+
     pub async fn max_insertion_batch_size(&self) -> usize {
         self.insertion_prover_map.read().await.max_batch_size()
     }
@@ -155,6 +306,19 @@ impl IdentityManager {
         self.deletion_prover_map.read().await.max_batch_size()
     }
 
+    pub async fn insertion_batch_sizes(&self) -> Vec<usize> {
+        self.insertion_prover_map.read().await.batch_sizes()
+    }
+
+    pub async fn deletion_batch_sizes(&self) -> Vec<usize> {
+        self.deletion_prover_map.read().await.batch_sizes()
+    }
+
+    #[must_use]
+    pub const fn flavor(&self) -> ContractFlavor {
+        self.contract.flavor()
+    }
+
     #[must_use]
     pub const fn initial_leaf_value(&self) -> Field {
         self.initial_leaf_value
@@ -205,12 +369,12 @@ impl IdentityManager {
     }
 
     pub async fn root_history_expiry(&self) -> anyhow::Result<U256> {
-        Ok(self.abi.get_root_history_expiry().call().await?)
+        self.contract.root_history_expiry().await
     }
 
     #[instrument(level = "debug", skip(prover, identity_commitments))]
     pub async fn prepare_insertion_proof(
-        prover: ReadOnlyInsertionProver<'_>,
+        prover: &Prover,
         start_index: usize,
         pre_root: U256,
         identity_commitments: &[Identity],
@@ -226,6 +390,9 @@ impl IdentityManager {
             prover.batch_size()
         );
 
+        // Proofs by another key are rejected by the verifier.
+        prover.verify_key().await?;
+
         let proof_data: Proof = prover
             .generate_insertion_proof(
                 actual_start_index,
@@ -252,6 +419,8 @@ impl IdentityManager {
             prover.batch_size()
         );
 
+        prover.verify_key().await?;
+
         let proof_data: Proof = prover
             .generate_deletion_proof(pre_root, post_root, deletion_indices, identity_commitments)
             .await?;
@@ -267,7 +436,82 @@ impl IdentityManager {
         post_root: U256,
         identity_commitments: Vec<Identity>,
         proof_data: Proof,
-    ) -> anyhow::Result<TransactionId> {
+    ) -> anyhow::Result<SentBatch> {
+        let register_identities_call = self.insertion_call(
+            start_index,
+            pre_root,
+            post_root,
+            &identity_commitments,
+            proof_data,
+        )?;
+
+        self.simulate_batch(&register_identities_call).await?;
+
+        // We want to send the transaction through our ethereum provider rather than
+        // directly now. To that end, we create it, and then send it later, waiting for
+        // it to complete.
+        let register_identities_transaction = register_identities_call.tx;
+        let calldata_size = register_identities_transaction
+            .data()
+            .map_or(0, |data| data.len());
+
+        let transaction_id = self
+            .ethereum
+            .send_transaction(
+                register_identities_transaction.clone(),
+                true,
+                SigningPurpose::InsertionBatch,
+            )
+            .await
+            .map_err(|tx_err| anyhow!("{}", tx_err.to_string()))?;
+
+        fault_injection::inject(FaultPoint::AfterTxSend)?;
+
+        self.mirror_to_shadow(ShadowBatch {
+            kind: BatchKind::Insertion,
+            pre_root,
+            post_root,
+            tx: register_identities_transaction,
+        });
+
+        Ok(SentBatch {
+            transaction_id,
+            calldata_size,
+        })
+    }
+
+    /// Simulates an insertion batch without broadcasting it.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`SimulatedRevert`] if the batch reverts.
+    pub async fn simulate_insertion(
+        &self,
+        start_index: usize,
+        pre_root: U256,
+        post_root: U256,
+        identity_commitments: &[Identity],
+        proof_data: Proof,
+    ) -> anyhow::Result<()> {
+        let call = self.insertion_call(
+            start_index,
+            pre_root,
+            post_root,
+            identity_commitments,
+            proof_data,
+        )?;
+
+        self.simulate_batch(&call).await
+    }
+
+    fn insertion_call(
+        &self,
+        start_index: usize,
+        pre_root: U256,
+        post_root: U256,
+        identity_commitments: &[Identity],
+        proof_data: Proof,
+    ) -> anyhow::Result<ContractCall<ReadProvider, ()>> {
         let actual_start_index: u32 = start_index.try_into()?;
 
         let proof_points_array: [U256; 8] = proof_data.into();
@@ -276,24 +520,13 @@ impl IdentityManager {
             .map(|id| id.commitment)
             .collect();
 
-        // We want to send the transaction through our ethereum provider rather than
-        // directly now. To that end, we create it, and then send it later, waiting for
-        // it to complete.
-        let register_identities_transaction = self
-            .abi
-            .register_identities(
-                proof_points_array,
-                pre_root,
-                actual_start_index,
-                identities,
-                post_root,
-            )
-            .tx;
-
-        self.ethereum
-            .send_transaction(register_identities_transaction, true)
-            .await
-            .map_err(|tx_err| anyhow!("{}", tx_err.to_string()))
+        Ok(self.contract.insertion_call(
+            proof_points_array,
+            pre_root,
+            actual_start_index,
+            identities,
+            post_root,
+        ))
     }
 
     // TODO: docs
@@ -304,23 +537,78 @@ impl IdentityManager {
         packed_deletion_indices: Vec<u8>,
         pre_root: U256,
         post_root: U256,
-    ) -> anyhow::Result<TransactionId> {
+    ) -> anyhow::Result<SentBatch> {
         let proof_points_array: [U256; 8] = deletion_proof.into();
 
-        let register_identities_transaction = self
-            .abi
-            .delete_identities(
-                proof_points_array,
-                packed_deletion_indices.into(),
-                pre_root,
-                post_root,
+        let delete_identities_call = self.contract.deletion_call(
+            proof_points_array,
+            packed_deletion_indices,
+            pre_root,
+            post_root,
+        )?;
+
+        self.simulate_batch(&delete_identities_call).await?;
+
+        let register_identities_transaction = delete_identities_call.tx;
+        let calldata_size = register_identities_transaction
+            .data()
+            .map_or(0, |data| data.len());
+
+        let transaction_id = self
+            .ethereum
+            .send_transaction(
+                register_identities_transaction.clone(),
+                true,
+                SigningPurpose::DeletionBatch,
             )
-            .tx;
-
-        self.ethereum
-            .send_transaction(register_identities_transaction, true)
             .await
-            .map_err(|tx_err| anyhow!("{}", tx_err.to_string()))
+            .map_err(|tx_err| anyhow!("{}", tx_err.to_string()))?;
+
+        fault_injection::inject(FaultPoint::AfterTxSend)?;
+
+        self.mirror_to_shadow(ShadowBatch {
+            kind: BatchKind::Deletion,
+            pre_root,
+            post_root,
+            tx: register_identities_transaction,
+        });
+
+        Ok(SentBatch {
+            transaction_id,
+            calldata_size,
+        })
+    }
+
+    /// Executes a batch transaction with `eth_call` from the signer, so that a
+    /// batch which would revert is never broadcast.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`SimulatedRevert`] if the batch reverts.
+    async fn simulate_batch(&self, call: &ContractCall<ReadProvider, ()>) -> anyhow::Result<()> {
+        let call = call.clone().from(self.ethereum.address());
+
+        match call.call().await {
+            Ok(()) => Ok(()),
+            Err(err) if err.is_revert() => {
+                let reason = err
+                    .decode_revert::<String>()
+                    .unwrap_or_else(|| err.to_string());
+
+                Err(SimulatedRevert {
+                    reason,
+                    calldata: call.calldata().unwrap_or_default(),
+                }
+                .into())
+            }
+            Err(err) => Err(err).context("Failed to simulate batch"),
+        }
+    }
+
+    fn mirror_to_shadow(&self, batch: ShadowBatch) {
+        if let Some(shadow) = &self.shadow {
+            shadow.mirror_batch(batch);
+        }
     }
 
     #[instrument(level = "debug", skip(self))]
@@ -330,6 +618,16 @@ impl IdentityManager {
         Ok(result)
     }
 
+    /// The hash of a submitted batch transaction, `None` while it isn't
+    /// broadcast yet.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn transaction_hash(
+        &self,
+        transaction_id: &TransactionId,
+    ) -> anyhow::Result<Option<H256>> {
+        Ok(self.ethereum.transaction_hash(transaction_id).await?)
+    }
+
     #[instrument(level = "debug", skip_all)]
     pub async fn fetch_pending_identities(&self) -> anyhow::Result<Vec<TransactionId>> {
         let pending_identities = self.ethereum.fetch_pending_transactions().await?;
@@ -352,11 +650,21 @@ impl IdentityManager {
         Ok(())
     }
 
+    /// Whether the owner paused the contract, `None` if it can't be paused.
+    pub async fn is_paused(&self) -> anyhow::Result<Option<bool>> {
+        self.contract.paused().await
+    }
+
+    /// The account the contract currently accepts batches from.
+    pub async fn operator(&self) -> anyhow::Result<Address> {
+        self.contract.operator().await
+    }
+
     #[instrument(level = "debug", skip_all)]
     pub async fn latest_root(&self) -> anyhow::Result<U256> {
-        let latest_root = self.abi.latest_root().call().await?;
-
-        Ok(latest_root)
+        self.chain_reads
+            .latest_root(self.contract.latest_root())
+            .await
     }
 
     /// Fetches the identity commitments from a
@@ -373,13 +681,12 @@ impl IdentityManager {
             .await?
             .context("Missing tx")?;
 
-        use ethers::abi::AbiDecode;
-        let delete_identities = DeleteIdentitiesCall::decode(&tx.input)?;
+        let delete_identities = encoding::decode_deletion(&tx.input)?;
 
         let packed_deletion_indices: &[u8] = delete_identities.packed_deletion_indices.as_ref();
         let indices = unpack_indices(packed_deletion_indices);
 
-        let padding_index = 2u32.pow(self.tree_depth as u32);
+        let padding_index = padding_index(self.tree_depth)?;
 
         Ok(indices
             .into_iter()
@@ -388,22 +695,132 @@ impl IdentityManager {
             .collect())
     }
 
+    /// Fetches the `registerIdentities` call of a transaction by tx hash, e.g.
+    /// to apply an insertion batch which was submitted by someone else.
     #[instrument(level = "debug", skip_all)]
-    pub async fn is_root_mined(&self, root: U256) -> anyhow::Result<bool> {
-        let (root_on_mainnet, ..) = self.abi.query_root(root).call().await?;
+    pub async fn fetch_insertion_from_tx(
+        &self,
+        tx_hash: H256,
+    ) -> anyhow::Result<RegisterIdentitiesCall> {
+        anyhow::ensure!(
+            self.flavor() == ContractFlavor::WorldId,
+            "Only registerIdentities calldata can be decoded"
+        );
 
-        if root_on_mainnet.is_zero() {
-            return Ok(false);
+        let tx = self
+            .ethereum
+            .provider()
+            .get_transaction(tx_hash)
+            .await?
+            .context("Missing tx")?;
+
+        Ok(encoding::decode_insertion(&tx.input)?)
+    }
+
+    /// Fetches the insertion batches mined within the last `lookback_blocks`
+    /// blocks, returning the post root and the inserted identity commitments
+    /// of each batch in the order they were mined.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn fetch_recent_insertions(
+        &self,
+        lookback_blocks: u64,
+        window_size: u64,
+    ) -> anyhow::Result<Vec<(U256, Vec<U256>)>> {
+        if self.flavor() != ContractFlavor::WorldId {
+            // Only `registerIdentities` calldata can be decoded.
+            return Ok(vec![]);
         }
 
-        Ok(true)
+        let provider = self.ethereum.provider();
+
+        let latest_block = provider.get_block_number().await?.as_u64();
+        let mut scanner = BlockScanner::new_from(
+            provider.clone(),
+            latest_block.saturating_sub(lookback_blocks),
+            window_size,
+        );
+
+        let address = Some(ValueOrArray::Value(self.contract.address()));
+        let topics = [Some(self.contract.tree_change_topic()), None, None, None];
+
+        let mut batches = vec![];
+        while scanner.current_block() <= latest_block {
+            for log in scanner.next(address.clone(), topics.clone()).await? {
+                let event = self
+                    .contract
+                    .decode_tree_change(&log)
+                    .context("Failed to decode tree change")?;
+
+                if event.kind != TreeChangeKind::Insertion {
+                    continue;
+                }
+
+                let tx_hash = log.transaction_hash.context("Missing tx hash")?;
+                let tx = provider
+                    .get_transaction(tx_hash)
+                    .await?
+                    .context("Missing tx")?;
+
+                let Ok(register_identities) = encoding::decode_insertion(&tx.input) else {
+                    // The batch was submitted through another contract, so
+                    // its commitments can't be recovered from the calldata.
+                    warn!(?tx_hash, "Failed to decode insertion batch calldata.");
+                    continue;
+                };
+
+                batches.push((event.post_root, register_identities.identity_commitments));
+            }
+        }
+
+        Ok(batches)
     }
 
+    /// Returns the latest block number and the balance of the signer at that
+    /// block.
     #[instrument(level = "debug", skip_all)]
-    pub async fn is_root_mined_multi_chain(&self, root: U256) -> anyhow::Result<bool> {
-        let (root_on_mainnet, ..) = self.abi.query_root(root).call().await?;
+    pub async fn signer_balance(&self) -> anyhow::Result<(U64, U256)> {
+        let provider = self.ethereum.provider();
+
+        let block_number = provider.get_block_number().await?;
+        let balance = provider
+            .get_balance(self.ethereum.address(), Some(block_number.into()))
+            .await?;
+
+        Ok((block_number, balance))
+    }
+
+    /// Returns the latest block number of the chain batches are submitted to.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn block_number(&self) -> anyhow::Result<u64> {
+        self.chain_reads
+            .block_number(async { Ok(self.ethereum.provider().get_block_number().await?.as_u64()) })
+            .await
+    }
 
-        if root_on_mainnet.is_zero() {
+    /// Returns the current gas price of the chain batches are submitted to.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn gas_price(&self) -> anyhow::Result<U256> {
+        self.chain_reads
+            .gas_price(async { Ok(self.ethereum.provider().get_gas_price().await?) })
+            .await
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn is_root_mined(&self, root: U256) -> anyhow::Result<bool> {
+        self.contract.is_root_mined(root).await
+    }
+
+    /// Compares a mined root against the shadow identity manager, if one is
+    /// configured.
+    pub async fn compare_shadow_root(&self, root: U256) {
+        if let Some(shadow) = &self.shadow {
+            shadow.compare_root(root).await;
+        }
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn is_root_mined_multi_chain(&self, root: U256) -> anyhow::Result<bool> {
+        if !self.contract.is_root_mined(root).await? {
             return Ok(false);
         }
 
@@ -433,6 +850,7 @@ impl IdentityManager {
         batch_size: usize,
         timeout_seconds: u64,
         prover_type: ProverType,
+        key_digest: Option<H256>,
     ) -> Result<(), ServerError> {
         let mut map = match prover_type {
             ProverType::Insertion => self.insertion_prover_map.write().await,
@@ -448,7 +866,9 @@ impl IdentityManager {
             batch_size,
             prover_type,
             timeout_s: timeout_seconds,
-        })?;
+            key_digest,
+        })?
+        .with_retry(self.prover_retry);
 
         map.add(batch_size, prover);
 
@@ -492,6 +912,43 @@ impl IdentityManager {
         Ok(provers)
     }
 
+    /// Compares the proving keys of the provers with their configuration.
+    pub async fn prover_key_reports(&self) -> Vec<ProverKeyReport> {
+        // The provers aren't locked while they're asked.
+        let mut provers = self.insertion_prover_map.read().await.provers();
+        provers.extend(self.deletion_prover_map.read().await.provers());
+
+        join_all(provers.iter().map(Prover::key_report)).await
+    }
+
+    /// Logs whether the provers serve the proving keys they're expected to.
+    /// Batches aren't submitted to provers serving other keys.
+    pub async fn verify_prover_keys(&self) {
+        for report in self.prover_key_reports().await {
+            match report.status {
+                KeyStatus::Match => info!(
+                    url = report.url,
+                    batch_size = report.batch_size,
+                    "Prover serves the expected proving key."
+                ),
+                KeyStatus::Mismatch => error!(
+                    url = report.url,
+                    batch_size = report.batch_size,
+                    expected = ?report.expected_key_digest,
+                    info = ?report.info,
+                    "Prover serves another proving key, its batches won't be submitted."
+                ),
+                KeyStatus::Unknown => warn!(
+                    url = report.url,
+                    batch_size = report.batch_size,
+                    error = ?report.error,
+                    "Prover didn't report its proving key."
+                ),
+                KeyStatus::Unchecked => {}
+            }
+        }
+    }
+
     pub async fn has_insertion_provers(&self) -> bool {
         self.insertion_prover_map.read().await.len() > 0
     }
@@ -28,6 +28,23 @@ where
         })
     }
 
+    /// Creates a scanner starting at the given block instead of the chain
+    /// head.
+    pub fn new_from(read_provider: T, from_block: u64, window_size: u64) -> Self {
+        Self {
+            read_provider,
+            current_block: from_block,
+            window_size,
+            chain_head_offset: 0,
+        }
+    }
+
+    /// The first block that will be scanned by the next call to
+    /// [`Self::next`].
+    pub fn current_block(&self) -> u64 {
+        self.current_block
+    }
+
     pub fn with_offset(mut self, chain_head_offset: u64) -> Self {
         self.chain_head_offset = chain_head_offset;
         self
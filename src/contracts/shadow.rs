@@ -0,0 +1,287 @@
+//! Mirroring of submitted batches to a shadow identity manager contract.
+//!
+//! This is used to exercise a new contract deployment with production traffic
+//! before cutting over to it. Nothing that happens on the shadow contract is
+//! allowed to affect the state of the sequencer, so all failures are only
+//! logged and reported through metrics. Batches are mirrored in the background,
+//! in the order they were submitted, so that they don't hold up the submission
+//! of the next batch to the identity manager.
+
+use std::future::Future;
+
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Address, U256};
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_gauge, register_int_counter, register_int_counter_vec, Gauge, IntCounter,
+    IntCounterVec,
+};
+use tokio::sync::mpsc;
+use tracing::{error, info, instrument, warn};
+
+use super::abi::WorldId;
+use crate::ethereum::signing_log::SigningPurpose;
+use crate::ethereum::{Ethereum, ReadProvider};
+
+static SHADOW_SUBMISSIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "shadow_submissions",
+        "Batches mirrored to the shadow identity manager",
+        &["kind", "result"]
+    )
+    .unwrap()
+});
+
+static SHADOW_ROOT_DIVERGENCES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "shadow_root_divergences",
+        "Mined roots which the shadow identity manager doesn't know about"
+    )
+    .unwrap()
+});
+
+static SHADOW_ROOT_IN_SYNC: Lazy<Gauge> = Lazy::new(|| {
+    register_gauge!(
+        "shadow_root_in_sync",
+        "Whether the shadow identity manager knew the last mined root (1) or not (0)"
+    )
+    .unwrap()
+});
+
+#[derive(Debug, Clone, Copy)]
+pub enum BatchKind {
+    Insertion,
+    Deletion,
+}
+
+impl BatchKind {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Insertion => "insertion",
+            Self::Deletion => "deletion",
+        }
+    }
+}
+
+/// Batches waiting to be mirrored. Batches submitted while this many are
+/// waiting are dropped, as the shadow contract can't catch up anyway.
+const MAX_PENDING_BATCHES: usize = 16;
+
+/// A copy of a batch to be submitted to the shadow contract.
+#[derive(Debug)]
+pub struct ShadowBatch {
+    pub kind:      BatchKind,
+    pub pre_root:  U256,
+    pub post_root: U256,
+    pub tx:        TypedTransaction,
+}
+
+/// A secondary identity manager contract which receives a copy of every
+/// batch.
+#[derive(Debug)]
+pub struct ShadowIdentityManager {
+    abi:     WorldId<ReadProvider>,
+    batches: mpsc::Sender<ShadowBatch>,
+}
+
+impl ShadowIdentityManager {
+    pub async fn new(address: Address, ethereum: &Ethereum) -> anyhow::Result<Self> {
+        let abi = WorldId::new(address, ethereum.provider().clone());
+
+        let operator = abi.identity_operator().call().await?;
+        if operator != ethereum.address() {
+            // Unlike for the primary contract this is not fatal, the mirrored
+            // batches will simply fail and be reported.
+            warn!(
+                ?address,
+                ?operator,
+                signer = ?ethereum.address(),
+                "Signer is not the identity operator of the shadow identity manager contract."
+            );
+        }
+
+        info!(?address, "Mirroring batches to shadow identity manager");
+
+        let ethereum = ethereum.clone();
+        let batches = spawn_mirroring(move |batch| {
+            let ethereum = ethereum.clone();
+            async move { submit_batch(&ethereum, address, batch).await }
+        });
+
+        Ok(Self { abi, batches })
+    }
+
+    /// Queues a copy of a batch to be submitted to the shadow contract, without
+    /// waiting for it to be submitted.
+    pub fn mirror_batch(&self, batch: ShadowBatch) {
+        queue_batch(&self.batches, batch);
+    }
+
+    /// Compares a root mined on the identity manager against the shadow
+    /// contract, reporting a divergence if the shadow contract never reached
+    /// it.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn compare_root(&self, root: U256) {
+        let (shadow_root, ..) = match self.abi.query_root(root).call().await {
+            Ok(root_info) => root_info,
+            Err(err) => {
+                error!(?err, "Failed to query the shadow identity manager root.");
+                return;
+            }
+        };
+
+        if shadow_root.is_zero() {
+            warn!(
+                ?root,
+                "Shadow identity manager diverged from the mined root."
+            );
+            SHADOW_ROOT_DIVERGENCES.inc();
+            SHADOW_ROOT_IN_SYNC.set(0.0);
+        } else {
+            SHADOW_ROOT_IN_SYNC.set(1.0);
+        }
+    }
+}
+
+/// Starts the task submitting queued batches one after the other, until the
+/// returned sender is dropped.
+fn spawn_mirroring<F, Fut>(mut submit: F) -> mpsc::Sender<ShadowBatch>
+where
+    F: FnMut(ShadowBatch) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    let (sender, mut receiver) = mpsc::channel(MAX_PENDING_BATCHES);
+
+    tokio::spawn(async move {
+        while let Some(batch) = receiver.recv().await {
+            submit(batch).await;
+        }
+    });
+
+    sender
+}
+
+fn queue_batch(batches: &mpsc::Sender<ShadowBatch>, batch: ShadowBatch) {
+    let kind = batch.kind;
+
+    if let Err(err) = batches.try_send(batch) {
+        error!(%err, "Failed to queue batch for the shadow identity manager.");
+        SHADOW_SUBMISSIONS
+            .with_label_values(&[kind.as_str(), "dropped"])
+            .inc();
+    }
+}
+
+#[instrument(level = "debug", skip(ethereum, batch))]
+async fn submit_batch(ethereum: &Ethereum, address: Address, batch: ShadowBatch) {
+    let ShadowBatch {
+        kind,
+        pre_root,
+        post_root,
+        mut tx,
+    } = batch;
+    tx.set_to(address);
+
+    match ethereum
+        .send_transaction(tx, true, SigningPurpose::ShadowBatch)
+        .await
+    {
+        Ok(transaction_id) => {
+            info!(
+                ?pre_root,
+                ?post_root,
+                ?transaction_id,
+                "Shadow batch submitted"
+            );
+            SHADOW_SUBMISSIONS
+                .with_label_values(&[kind.as_str(), "submitted"])
+                .inc();
+        }
+        Err(err) => {
+            error!(?err, "Failed to submit batch to shadow identity manager.");
+            SHADOW_SUBMISSIONS
+                .with_label_values(&[kind.as_str(), "error"])
+                .inc();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ethers::types::TransactionRequest;
+    use tokio::sync::Semaphore;
+
+    use super::*;
+
+    fn batch(post_root: u64) -> ShadowBatch {
+        ShadowBatch {
+            kind:      BatchKind::Insertion,
+            pre_root:  U256::from(post_root - 1),
+            post_root: U256::from(post_root),
+            tx:        TransactionRequest::new().into(),
+        }
+    }
+
+    /// Mirrors batches with submissions hanging until `release` has permits,
+    /// and returns the post roots of the submitted batches.
+    fn mirroring(
+        release: Arc<Semaphore>,
+    ) -> (mpsc::Sender<ShadowBatch>, mpsc::UnboundedReceiver<U256>) {
+        let (submitted, receiver) = mpsc::unbounded_channel();
+        let batches = spawn_mirroring(move |batch| {
+            let release = release.clone();
+            let submitted = submitted.clone();
+            async move {
+                release.acquire().await.unwrap().forget();
+                submitted.send(batch.post_root).unwrap();
+            }
+        });
+
+        (batches, receiver)
+    }
+
+    #[tokio::test]
+    async fn batches_are_mirrored_in_order_without_waiting() {
+        let release = Arc::new(Semaphore::new(0));
+        let (batches, mut submitted) = mirroring(release.clone());
+
+        for post_root in 1..=3 {
+            queue_batch(&batches, batch(post_root));
+        }
+        tokio::task::yield_now().await;
+        assert!(submitted.try_recv().is_err());
+
+        release.add_permits(3);
+        for post_root in 1..=3 {
+            assert_eq!(submitted.recv().await, Some(U256::from(post_root)));
+        }
+    }
+
+    #[tokio::test]
+    async fn batches_past_the_queue_are_dropped() {
+        let release = Arc::new(Semaphore::new(0));
+        let (batches, mut submitted) = mirroring(release.clone());
+
+        // The first batch is taken by the hanging submission, the next ones
+        // fill the queue and the rest are dropped.
+        queue_batch(&batches, batch(1));
+        tokio::task::yield_now().await;
+        let queued = MAX_PENDING_BATCHES as u64 + 1;
+        for post_root in 2..=queued + 5 {
+            queue_batch(&batches, batch(post_root));
+        }
+
+        release.add_permits(queued as usize + 5);
+        drop(batches);
+
+        let mut mirrored = vec![];
+        while let Some(post_root) = submitted.recv().await {
+            mirrored.push(post_root);
+        }
+
+        let expected: Vec<_> = (1..=queued).map(U256::from).collect();
+        assert_eq!(mirrored, expected);
+    }
+}
@@ -4,31 +4,58 @@
     clippy::cast_possible_wrap
 )]
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+use std::time::Duration;
 
 use anyhow::{anyhow, Context, Error as ErrReport};
 use chrono::{DateTime, Utc};
 use clap::Parser;
+use ethers::types::{Address, H256, U256};
 use sqlx::migrate::{Migrate, MigrateDatabase, Migrator};
-use sqlx::pool::PoolOptions;
-use sqlx::{Executor, Pool, Postgres, Row};
+use sqlx::pool::{PoolConnection, PoolOptions};
+use sqlx::postgres::PgRow;
+use sqlx::{Executor, Pool, Postgres, Row, Transaction};
 use thiserror::Error;
 use tracing::{error, info, instrument, warn};
 
-use self::types::{CommitmentHistoryEntry, DeletionEntry, LatestDeletionEntry, RecoveryEntry};
+use self::types::{
+    BatchCost, BatchDetails, BatchReport, BatchSubmissionEntry, CommitmentHistoryEntry,
+    DeletionEntry, FailedBatch, IdempotencyKeyEntry, IdempotentResponse, IdentityDeletion,
+    IdentityEventEntry, InsertionLatency, InsertionReceiptEntry, LatestDeletionEntry,
+    LoggedIdentityEvent, MinedLeafEntry, QueuedIdentityEntry, RecoveryEntry, RootHistoryEntry,
+    TreeUpdateEntry, UnprocessedIdentityCounts,
+};
+use crate::contracts::abi::TreeChangeKind;
+use crate::ethereum::signing_log::SigningRecord;
 use crate::identity_tree::{
-    Hash, ProcessedStatus, RootItem, TreeItem, TreeUpdate, UnprocessedStatus,
+    Hash, IdentityEvent, MinedTransaction, ProcessedStatus, RootItem, TreeItem, TreeUpdate,
+    UnprocessedStatus,
 };
 
 pub mod types;
+use crate::insertion_screening::{ScreeningDecision, ScreeningSubject};
 use crate::prover::{ProverConfiguration, ProverType, Provers};
+use crate::redaction;
 use crate::secret::SecretUrl;
+use crate::utils::retry::{RetryPolicy, Retryable};
 
 // Statically link in migration files
 static MIGRATOR: Migrator = sqlx::migrate!("schemas/database");
 
 const MAX_UNPROCESSED_FETCH_COUNT: i64 = 10_000;
 
+/// The number of signing operations reported at once, see
+/// [`Database::get_signing_records`].
+const MAX_SIGNING_RECORDS: i64 = 10_000;
+
+/// Key of the advisory lock serializing the queueing of identities, so that
+/// queue positions become visible in the order they were allocated.
+const QUEUE_LOCK_KEY: i64 = 0x7175_6575; // "queu"
+
+/// SQLSTATE of the violation of a unique constraint.
+const UNIQUE_VIOLATION: &str = "23505";
+
 #[derive(Clone, Debug, PartialEq, Eq, Parser)]
 pub struct Options {
     /// Database server connection string.
@@ -45,13 +72,22 @@ pub struct Options {
     pub database_max_connections: u32,
 }
 
+impl Retryable for sqlx::Error {
+    fn is_retryable(&self) -> bool {
+        matches!(self, Self::PoolTimedOut | Self::Io(_) | Self::Tls(_))
+    }
+}
+
 pub struct Database {
-    pool: Pool<Postgres>,
+    pool:         Pool<Postgres>,
+    retry:        RetryPolicy,
+    /// Leaves of the identities looked up so far, see [`Self::leaf_index_of`].
+    leaf_indices: RwLock<HashMap<Hash, usize>>,
 }
 
 impl Database {
     #[instrument(skip_all)]
-    pub async fn new(options: Options) -> Result<Self, ErrReport> {
+    pub async fn new(options: Options, retry: RetryPolicy) -> Result<Self, ErrReport> {
         info!(url = %&options.database, "Connecting to database");
 
         // Create database if requested and does not exist
@@ -62,9 +98,12 @@ impl Database {
         }
 
         // Create a connection pool
-        let pool = PoolOptions::<Postgres>::new()
-            .max_connections(options.database_max_connections)
-            .connect(options.database.expose())
+        let pool = retry
+            .retry("database", || {
+                PoolOptions::<Postgres>::new()
+                    .max_connections(options.database_max_connections)
+                    .connect(options.database.expose())
+            })
             .await
             .context("error connecting to database")?;
 
@@ -130,7 +169,66 @@ impl Database {
             return Err(anyhow!("Could not get database version."));
         }
 
-        Ok(Self { pool })
+        Ok(Self {
+            pool,
+            retry,
+            leaf_indices: RwLock::default(),
+        })
+    }
+
+    /// A connection from the pool, retried if the pool is exhausted or the
+    /// connection to the server fails. Queries themselves aren't retried, as
+    /// they may have been applied.
+    async fn connection(&self) -> Result<PoolConnection<Postgres>, sqlx::Error> {
+        self.retry.retry("database", || self.pool.acquire()).await
+    }
+
+    async fn begin(&self) -> Result<Transaction<'static, Postgres>, sqlx::Error> {
+        self.retry.retry("database", || self.pool.begin()).await
+    }
+
+    /// Connects to the database without creating or migrating it, and
+    /// describes the state of the schema.
+    ///
+    /// Pending migrations are not an error here, as they are applied on the
+    /// next start with `--database-migrate`.
+    #[instrument(skip_all)]
+    pub async fn connect_for_check(options: &Options) -> Result<(Self, String), ErrReport> {
+        let pool = PoolOptions::<Postgres>::new()
+            .max_connections(1)
+            .connect(options.database.expose())
+            .await
+            .context("error connecting to database")?;
+
+        let latest = MIGRATOR
+            .migrations
+            .last()
+            .expect("Missing migrations")
+            .version;
+
+        #[allow(deprecated)] // HACK: No good alternative to `version()`?
+        let status = match pool.acquire().await?.version().await? {
+            Some((_, true)) => return Err(anyhow!("Database is in incomplete migration state.")),
+            Some((version, false)) if version > latest => {
+                return Err(anyhow!(
+                    "Database schema version {version} is newer than this version of the software \
+                     ({latest})."
+                ))
+            }
+            Some((version, false)) if version < latest => {
+                format!("schema version {version}, migrations up to {latest} pending")
+            }
+            Some((version, false)) => format!("schema version {version} is up to date"),
+            None => format!("empty database, migrations up to {latest} pending"),
+        };
+
+        Ok((
+            Self {
+                pool,
+                retry: RetryPolicy::default(),
+            },
+            status,
+        ))
     }
 
     pub async fn insert_pending_identity(
@@ -139,26 +237,111 @@ impl Database {
         identity: &Hash,
         root: &Hash,
     ) -> Result<(), Error> {
-        let mut tx = self.pool.begin().await?;
+        let mut tx = self.begin().await?;
 
         let insert_pending_identity_query = sqlx::query(
             r#"
             INSERT INTO identities (leaf_index, commitment, root, status, pending_as_of)
             VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP)
+            RETURNING id
             "#,
         )
-        .bind(leaf_index as i64)
+        .bind(leaf_index_to_db(leaf_index)?)
         .bind(identity)
         .bind(root)
         .bind(<&str>::from(ProcessedStatus::Pending));
 
-        tx.execute(insert_pending_identity_query).await?;
+        let id = tx
+            .fetch_one(insert_pending_identity_query)
+            .await?
+            .get::<i64, _>(0);
+
+        // Deletions are recorded once mined.
+        if *identity != Hash::ZERO {
+            Self::append_event(&mut tx, identity, IdentityEvent::Validated, None).await?;
+
+            // The leaf is applied to the root of the update before it, none
+            // for the first leaf, which is applied to the initial root.
+            let assign_receipt_leaf_query = sqlx::query(
+                r#"
+                UPDATE insertion_receipts
+                SET leaf_index = $1,
+                    pre_root = (
+                        SELECT root FROM identities WHERE id < $2 ORDER BY id DESC LIMIT 1
+                    )
+                WHERE commitment = $3
+                "#,
+            )
+            .bind(leaf_index_to_db(leaf_index)?)
+            .bind(id)
+            .bind(identity);
+
+            tx.execute(assign_receipt_leaf_query).await?;
+
+            let insert_leaf_index_query = sqlx::query(
+                r#"
+                INSERT INTO leaf_indices (commitment, leaf_index)
+                VALUES ($1, $2)
+                ON CONFLICT (commitment) DO NOTHING
+                "#,
+            )
+            .bind(identity)
+            .bind(leaf_index_to_db(leaf_index)?);
+
+            tx.execute(insert_leaf_index_query).await?;
+        }
 
         tx.commit().await?;
 
+        if *identity != Hash::ZERO {
+            self.leaf_indices
+                .write()
+                .expect("no lock poisoning")
+                .insert(*identity, leaf_index);
+        }
+
         Ok(())
     }
 
+    /// The leaf an identity was inserted at, whether or not it was deleted
+    /// since.
+    ///
+    /// Leaves are cached once looked up, as they only change when insertions
+    /// are rolled back, which clears them from the cache.
+    pub async fn leaf_index_of(&self, identity: &Hash) -> Result<Option<usize>, Error> {
+        if let Some(leaf_index) = self
+            .leaf_indices
+            .read()
+            .expect("no lock poisoning")
+            .get(identity)
+        {
+            return Ok(Some(*leaf_index));
+        }
+
+        let query =
+            sqlx::query("SELECT leaf_index FROM leaf_indices WHERE commitment = $1").bind(identity);
+
+        let Some(row) = self.connection().await?.fetch_optional(query).await? else {
+            return Ok(None);
+        };
+        let leaf_index = leaf_index_from_db(row.get::<i64, _>(0));
+
+        self.leaf_indices
+            .write()
+            .expect("no lock poisoning")
+            .insert(*identity, leaf_index);
+
+        Ok(Some(leaf_index))
+    }
+
+    /// Clears the cached leaves from `first_leaf` onwards.
+    fn forget_leaf_indices(&self, first_leaf: usize) {
+        self.leaf_indices
+            .write()
+            .expect("no lock poisoning")
+            .retain(|_, leaf_index| *leaf_index < first_leaf);
+    }
+
     pub async fn get_id_by_root(
         tx: impl Executor<'_, Database = Postgres>,
         root: &Hash,
@@ -182,6 +365,11 @@ impl Database {
         Ok(Some(root_id as usize))
     }
 
+    /// Returns the id of the first row resulting in the root.
+    pub async fn get_root_id(&self, root: &Hash) -> Result<Option<usize>, Error> {
+        Self::get_id_by_root(&mut self.connection().await?, root).await
+    }
+
     /// Marks the identities and roots from before a given root hash as mined
     /// Also marks following roots as pending
     #[instrument(skip(self), level = "debug")]
@@ -190,7 +378,7 @@ impl Database {
         let processed_status = ProcessedStatus::Processed;
         let pending_status = ProcessedStatus::Pending;
 
-        let mut tx = self.pool.begin().await?;
+        let mut tx = self.begin().await?;
 
         let root_id = Self::get_id_by_root(&mut tx, root).await?;
 
@@ -200,23 +388,47 @@ impl Database {
 
         let root_id = root_id as i64;
         // TODO: Can I get rid of line `AND    status <> $2
+        // Zero commitments are deletions, the event is recorded for the
+        // commitment which previously held the leaf.
         let update_previous_roots = sqlx::query(
             r#"
-            UPDATE identities
-            SET    status = $2, mined_at = CURRENT_TIMESTAMP
-            WHERE  id <= $1
-            AND    status <> $2
-            AND    status <> $3
+            WITH updated AS (
+                UPDATE identities
+                SET    status = $2, mined_at = CURRENT_TIMESTAMP
+                WHERE  id <= $1
+                AND    status <> $2
+                AND    status <> $3
+                RETURNING id, leaf_index, commitment
+            )
+            INSERT INTO identity_events (commitment, event, created_at)
+            SELECT commitment, $4, CURRENT_TIMESTAMP
+            FROM   updated
+            WHERE  commitment <> $6
+            UNION ALL
+            SELECT deleted.commitment, $5, CURRENT_TIMESTAMP
+            FROM   updated
+            JOIN LATERAL (
+                SELECT   prev.commitment
+                FROM     identities prev
+                WHERE    prev.leaf_index = updated.leaf_index
+                AND      prev.id < updated.id
+                ORDER BY prev.id DESC
+                LIMIT    1
+            ) deleted ON deleted.commitment <> $6
+            WHERE  updated.commitment = $6
             "#,
         )
         .bind(root_id)
         .bind(<&str>::from(processed_status))
-        .bind(<&str>::from(mined_status));
+        .bind(<&str>::from(mined_status))
+        .bind(<&str>::from(IdentityEvent::Mined))
+        .bind(<&str>::from(IdentityEvent::Deleted))
+        .bind(Hash::ZERO);
 
         let update_next_roots = sqlx::query(
             r#"
             UPDATE identities
-            SET    status = $2, mined_at = NULL
+            SET    status = $2, mined_at = NULL, mined_block = NULL
             WHERE  id > $1
             "#,
         )
@@ -239,13 +451,280 @@ impl Database {
         let update_all_identities = sqlx::query(
             r#"
             UPDATE identities
-            SET    status = $1, mined_at = NULL
+            SET    status = $1, mined_at = NULL, mined_block = NULL
             WHERE  status <> $1
             "#,
         )
         .bind(<&str>::from(pending_status));
 
-        self.pool.execute(update_all_identities).await?;
+        self.connection()
+            .await?
+            .execute(update_all_identities)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Replaces the identities with the leaves of a restored tree, zero for
+    /// deleted leaves, all mined under `root`. The identities that weren't
+    /// mined yet and aren't part of the restored tree are kept as failed with
+    /// `message`, and their receipts lose the leaves they were assigned.
+    /// Returns how many identities were discarded.
+    #[instrument(skip(self, leaves), level = "debug")]
+    pub async fn replace_tree(
+        &self,
+        leaves: &[Hash],
+        root: &Hash,
+        message: &str,
+    ) -> Result<u64, Error> {
+        // Well below the limit of bind parameters of a statement.
+        const CHUNK_SIZE: usize = 10_000;
+
+        let mut tx = self.begin().await?;
+
+        let not_mined = sqlx::query(
+            r#"
+            SELECT commitment FROM identities
+            WHERE  status <> $1
+            AND    commitment <> $2
+            "#,
+        )
+        .bind(<&str>::from(ProcessedStatus::Mined))
+        .bind(Hash::ZERO);
+        let restored: HashSet<&Hash> = leaves.iter().collect();
+        let discarded: Vec<Hash> = tx
+            .fetch_all(not_mined)
+            .await?
+            .into_iter()
+            .map(|row| row.get::<Hash, _>(0))
+            .filter(|commitment| !restored.contains(commitment))
+            .collect();
+
+        for commitment in &discarded {
+            // Their leaves now hold other commitments.
+            let query = sqlx::query(
+                r#"
+                UPDATE insertion_receipts
+                SET    leaf_index = NULL,
+                       pre_root = NULL
+                WHERE  commitment = $1
+                "#,
+            )
+            .bind(commitment);
+            tx.execute(query).await?;
+
+            Self::fail_rolled_back_insertion(&mut tx, commitment, message).await?;
+        }
+
+        tx.execute(sqlx::query("DELETE FROM identities")).await?;
+        tx.execute(sqlx::query("DELETE FROM leaf_indices")).await?;
+
+        for (chunk_index, chunk) in leaves.chunks(CHUNK_SIZE).enumerate() {
+            let first_leaf = chunk_index * CHUNK_SIZE;
+            let mut query_builder = sqlx::QueryBuilder::new(
+                "INSERT INTO identities (leaf_index, commitment, root, status, pending_as_of, \
+                 mined_at) ",
+            );
+            query_builder.push_values(chunk.iter().enumerate(), |mut b, (offset, leaf)| {
+                b.push_bind((first_leaf + offset) as i64)
+                    .push_bind(leaf)
+                    .push_bind(root)
+                    .push_bind(<&str>::from(ProcessedStatus::Mined))
+                    .push("CURRENT_TIMESTAMP")
+                    .push("CURRENT_TIMESTAMP");
+            });
+            tx.execute(query_builder.build()).await?;
+
+            let mut query_builder =
+                sqlx::QueryBuilder::new("INSERT INTO leaf_indices (commitment, leaf_index) ");
+            let inserted = chunk
+                .iter()
+                .enumerate()
+                .filter(|(_, leaf)| **leaf != Hash::ZERO);
+            if inserted.clone().next().is_some() {
+                query_builder.push_values(inserted, |mut b, (offset, leaf)| {
+                    b.push_bind(leaf).push_bind((first_leaf + offset) as i64);
+                });
+                query_builder.push(" ON CONFLICT (commitment) DO NOTHING");
+                tx.execute(query_builder.build()).await?;
+            }
+        }
+
+        tx.commit().await?;
+
+        self.forget_leaf_indices(0);
+
+        Ok(discarded.len() as u64)
+    }
+
+    /// Rolls back the latest `insertions` to the tree, which must be all the
+    /// pending identities from the first of them onwards. They're kept as
+    /// failed with `message`, so that they're reported as such and can't be
+    /// inserted again. Nothing is rolled back if they don't match.
+    #[instrument(skip(self, insertions), level = "debug")]
+    pub async fn roll_back_insertions(
+        &self,
+        insertions: &[TreeUpdate],
+        message: &str,
+    ) -> Result<(), Error> {
+        let Some(first) = insertions.first() else {
+            return Ok(());
+        };
+
+        let mut tx = self.begin().await?;
+
+        Self::delete_pending_insertions(&mut tx, insertions).await?;
+        for insertion in insertions {
+            Self::fail_rolled_back_insertion(&mut tx, &insertion.element, message).await?;
+        }
+
+        tx.commit().await?;
+
+        self.forget_leaf_indices(first.leaf_index);
+
+        Ok(())
+    }
+
+    /// Rolls back a failed insertion batch, which ends with the identity
+    /// whose root is `post_root`, along with the pending identities after it.
+    /// `insertions` are all the pending identities from the first of the
+    /// batch onwards. The identities of the batch are kept as failed with
+    /// `message`, those after it are queued again with their sequence numbers.
+    /// The batch is forgotten. Returns the number of identities of the batch.
+    #[instrument(skip(self, insertions), level = "debug")]
+    pub async fn discard_failed_insertions(
+        &self,
+        insertions: &[TreeUpdate],
+        post_root: &Hash,
+        message: &str,
+    ) -> Result<usize, Error> {
+        let Some(first) = insertions.first() else {
+            return Err(Error::MissingRoot { root: *post_root });
+        };
+
+        let mut tx = self.begin().await?;
+
+        let query = sqlx::query(
+            r#"
+            SELECT leaf_index
+            FROM   identities
+            WHERE  root = $1
+            AND    status = $2
+            ORDER BY id DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(post_root)
+        .bind(<&str>::from(ProcessedStatus::Pending));
+        let last_leaf = tx
+            .fetch_optional(query)
+            .await?
+            .map(|row| leaf_index_from_db(row.get::<i64, _>(0)))
+            .ok_or(Error::MissingRoot { root: *post_root })?;
+        let batch_len = insertions
+            .iter()
+            .position(|insertion| insertion.leaf_index == last_leaf)
+            .ok_or(Error::MissingRoot { root: *post_root })?
+            + 1;
+
+        Self::delete_pending_insertions(&mut tx, insertions).await?;
+
+        let (batch, later) = insertions.split_at(batch_len);
+        for insertion in batch {
+            Self::fail_rolled_back_insertion(&mut tx, &insertion.element, message).await?;
+        }
+        for insertion in later {
+            let query = sqlx::query(
+                r#"
+                INSERT INTO unprocessed_identities
+                    (commitment, status, created_at, eligibility, queue_position)
+                VALUES ($1, $2, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, COALESCE(
+                    (SELECT sequence_number FROM insertion_receipts WHERE commitment = $1),
+                    nextval('unprocessed_queue_positions')
+                ))
+                "#,
+            )
+            .bind(insertion.element)
+            .bind(<&str>::from(UnprocessedStatus::New));
+
+            tx.execute(query).await?;
+            Self::append_event(&mut tx, &insertion.element, IdentityEvent::Received, None).await?;
+        }
+
+        let query =
+            sqlx::query("DELETE FROM quarantined_batches WHERE post_root = $1").bind(post_root);
+        tx.execute(query).await?;
+
+        tx.commit().await?;
+
+        self.forget_leaf_indices(first.leaf_index);
+
+        Ok(batch_len)
+    }
+
+    /// Deletes `insertions`, which must be all the pending identities from the
+    /// first of them onwards, from the tree.
+    async fn delete_pending_insertions(
+        tx: &mut Transaction<'_, Postgres>,
+        insertions: &[TreeUpdate],
+    ) -> Result<(), Error> {
+        let Some(first) = insertions.first() else {
+            return Ok(());
+        };
+
+        let query = sqlx::query(
+            r#"
+            DELETE FROM identities
+            WHERE  leaf_index >= $1
+            AND    status = $2
+            RETURNING leaf_index, commitment
+            "#,
+        )
+        .bind(leaf_index_to_db(first.leaf_index)?)
+        .bind(<&str>::from(ProcessedStatus::Pending));
+        let mut deleted: Vec<_> = tx
+            .fetch_all(query)
+            .await?
+            .into_iter()
+            .map(|row| TreeUpdate {
+                leaf_index: leaf_index_from_db(row.get::<i64, _>(0)),
+                element:    row.get::<Hash, _>(1),
+            })
+            .collect();
+        deleted.sort_by_key(|update| update.leaf_index);
+        if deleted != insertions {
+            return Err(Error::RollBackMismatch {
+                expected: insertions.len(),
+                pending:  deleted.len(),
+            });
+        }
+
+        let forget_leaf_indices_query =
+            sqlx::query("DELETE FROM leaf_indices WHERE leaf_index >= $1")
+                .bind(leaf_index_to_db(first.leaf_index)?);
+        tx.execute(forget_leaf_indices_query).await?;
+
+        Ok(())
+    }
+
+    async fn fail_rolled_back_insertion(
+        tx: &mut Transaction<'_, Postgres>,
+        commitment: &Hash,
+        message: &str,
+    ) -> Result<(), Error> {
+        let query = sqlx::query(
+            r#"
+            INSERT INTO unprocessed_identities
+                (commitment, status, created_at, eligibility, error_message)
+            VALUES ($1, $2, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, $3)
+            "#,
+        )
+        .bind(commitment)
+        .bind(<&str>::from(UnprocessedStatus::Failed))
+        .bind(message);
+
+        tx.execute(query).await?;
+        Self::append_event(tx, commitment, IdentityEvent::Failed, Some(message)).await?;
 
         Ok(())
     }
@@ -256,7 +735,7 @@ impl Database {
     pub async fn mark_root_as_mined(&self, root: &Hash) -> Result<(), Error> {
         let mined_status = ProcessedStatus::Mined;
 
-        let mut tx = self.pool.begin().await?;
+        let mut tx = self.begin().await?;
 
         let root_id = Self::get_id_by_root(&mut tx, root).await?;
 
@@ -268,14 +747,23 @@ impl Database {
 
         let update_previous_roots = sqlx::query(
             r#"
-            UPDATE identities
-            SET    status = $2
-            WHERE  id <= $1
-            AND    status <> $2
+            WITH updated AS (
+                UPDATE identities
+                SET    status = $2
+                WHERE  id <= $1
+                AND    status <> $2
+                RETURNING commitment
+            )
+            INSERT INTO identity_events (commitment, event, created_at)
+            SELECT commitment, $3, CURRENT_TIMESTAMP
+            FROM   updated
+            WHERE  commitment <> $4
             "#,
         )
         .bind(root_id)
-        .bind(<&str>::from(mined_status));
+        .bind(<&str>::from(mined_status))
+        .bind(<&str>::from(IdentityEvent::Finalized))
+        .bind(Hash::ZERO);
 
         tx.execute(update_previous_roots).await?;
 
@@ -293,43 +781,104 @@ impl Database {
             "#,
         );
 
-        let row = self.pool.fetch_optional(query).await?;
+        let row = self.connection().await?.fetch_optional(query).await?;
 
         let Some(row) = row else { return Ok(0) };
-        let leaf_index = row.get::<i64, _>(0);
+        let leaf_index = leaf_index_from_db(row.get::<i64, _>(0));
 
-        Ok((leaf_index + 1) as usize)
+        Ok(leaf_index + 1)
     }
 
     pub async fn get_identity_leaf_index(
         &self,
         identity: &Hash,
     ) -> Result<Option<TreeItem>, Error> {
+        let Some(leaf_index) = self.leaf_index_of(identity).await? else {
+            return Ok(None);
+        };
+
+        // The insertion may have been pruned after the identity was deleted.
         let query = sqlx::query(
             r#"
-            SELECT leaf_index, status
+            SELECT status
             FROM identities
-            WHERE commitment = $1
+            WHERE leaf_index = $1
+            AND commitment = $2
             ORDER BY id DESC
             LIMIT 1;
             "#,
         )
+        .bind(leaf_index_to_db(leaf_index)?)
         .bind(identity);
 
-        let Some(row) = self.pool.fetch_optional(query).await? else {
+        let Some(row) = self.connection().await?.fetch_optional(query).await? else {
             return Ok(None);
         };
 
-        let leaf_index = row.get::<i64, _>(0) as usize;
-
         let status = row
-            .get::<&str, _>(1)
+            .get::<&str, _>(0)
             .parse()
             .expect("Status is unreadable, database is corrupt");
 
         Ok(Some(TreeItem { status, leaf_index }))
     }
 
+    /// Finds the mined deletion of an identity, `None` if it wasn't deleted
+    /// or its deletion isn't mined yet.
+    pub async fn get_identity_deletion(
+        &self,
+        identity: &Hash,
+    ) -> Result<Option<IdentityDeletion>, Error> {
+        // Leaves are never reused, so the first zero written to the leaf of
+        // the identity deleted it.
+        let query = sqlx::query(
+            r#"
+            SELECT deletion.root, tx.transaction_hash, tx.block_number, tx.block_timestamp
+            FROM   identities inserted
+            JOIN   identities deletion
+            ON     deletion.leaf_index = inserted.leaf_index
+            AND    deletion.id > inserted.id
+            LEFT JOIN root_transactions tx ON tx.root = deletion.root
+            WHERE  inserted.commitment = $1
+            AND    deletion.commitment = $2
+            AND    deletion.status IN ($3, $4)
+            ORDER BY deletion.id ASC
+            LIMIT 1
+            "#,
+        )
+        .bind(identity)
+        .bind(Hash::ZERO)
+        .bind(<&str>::from(ProcessedStatus::Processed))
+        .bind(<&str>::from(ProcessedStatus::Mined));
+
+        if let Some(row) = self.connection().await?.fetch_optional(query).await? {
+            return Ok(Some(IdentityDeletion {
+                root:              Some(row.get::<Hash, _>(0)),
+                mined_transaction: Self::read_mined_transaction(&row, 1),
+            }));
+        }
+
+        // The updates of deleted identities may have been pruned, the event
+        // of the deletion is kept.
+        let query = sqlx::query(
+            r#"SELECT exists(SELECT 1 FROM identity_events WHERE commitment = $1 AND event = $2)"#,
+        )
+        .bind(identity)
+        .bind(<&str>::from(IdentityEvent::Deleted));
+
+        let deleted = self
+            .connection()
+            .await?
+            .fetch_one(query)
+            .await?
+            .get::<bool, _>(0);
+
+        Ok(deleted.then_some(IdentityDeletion {
+            root:              None,
+            mined_transaction: None,
+        }))
+    }
+
     pub async fn get_commitments_by_status(
         &self,
         status: ProcessedStatus,
@@ -344,17 +893,97 @@ impl Database {
         )
         .bind(<&str>::from(status));
 
-        let rows = self.pool.fetch_all(query).await?;
+        let rows = self.connection().await?.fetch_all(query).await?;
 
         Ok(rows
             .into_iter()
             .map(|row| TreeUpdate {
-                leaf_index: row.get::<i64, _>(0) as usize,
+                leaf_index: leaf_index_from_db(row.get::<i64, _>(0)),
                 element:    row.get::<Hash, _>(1),
             })
             .collect::<Vec<_>>())
     }
 
+    /// Returns the tree updates written after the row `id`, in order.
+    pub async fn get_tree_updates_after(&self, id: usize) -> Result<Vec<TreeUpdateEntry>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT id, leaf_index, commitment, root
+            FROM identities
+            WHERE id > $1
+            ORDER BY id ASC;
+            "#,
+        )
+        .bind(id as i64);
+
+        let rows = self.connection().await?.fetch_all(query).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| TreeUpdateEntry {
+                id:     row.get::<i64, _>(0) as usize,
+                update: TreeUpdate {
+                    leaf_index: leaf_index_from_db(row.get::<i64, _>(1)),
+                    element:    row.get::<Hash, _>(2),
+                },
+                root:   row.get::<Hash, _>(3),
+            })
+            .collect())
+    }
+
+    /// Returns the tree updates up to and including the row `id`, in order.
+    pub async fn get_tree_updates_until(&self, id: usize) -> Result<Vec<TreeUpdate>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT leaf_index, commitment
+            FROM identities
+            WHERE id <= $1
+            ORDER BY id ASC;
+            "#,
+        )
+        .bind(id as i64);
+
+        let rows = self.connection().await?.fetch_all(query).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| TreeUpdate {
+                leaf_index: leaf_index_from_db(row.get::<i64, _>(0)),
+                element:    row.get::<Hash, _>(1),
+            })
+            .collect())
+    }
+
+    /// Deletes the updates superseded by a later update of the same leaf which
+    /// is already final, as only the latest update of a leaf is needed to
+    /// rebuild the tree. Such updates are kept while the update superseding
+    /// them was finalized after `finalized_before`, or is among the latest
+    /// `keep_updates` ones. Returns the number of updates deleted.
+    pub async fn prune_superseded_updates(
+        &self,
+        finalized_before: Option<DateTime<Utc>>,
+        keep_updates: Option<u64>,
+    ) -> Result<u64, Error> {
+        let query = sqlx::query(
+            r#"
+            DELETE FROM identities pruned
+            USING identities superseding
+            WHERE superseding.leaf_index = pruned.leaf_index
+            AND   superseding.id > pruned.id
+            AND   superseding.status = $1
+            AND   ($2::TIMESTAMPTZ IS NULL OR superseding.mined_at < $2)
+            AND   ($3::BIGINT IS NULL OR superseding.id <= (SELECT MAX(id) FROM identities) - $3)
+            "#,
+        )
+        .bind(<&str>::from(ProcessedStatus::Mined))
+        .bind(finalized_before)
+        .bind(keep_updates.map(|updates| updates as i64));
+
+        let result = self.connection().await?.execute(query).await?;
+
+        Ok(result.rows_affected())
+    }
+
     pub async fn get_identity_history_entries(
         &self,
         commitment: &Hash,
@@ -368,7 +997,7 @@ impl Database {
         )
         .bind(commitment);
 
-        let rows = self.pool.fetch_all(unprocessed).await?;
+        let rows = self.connection().await?.fetch_all(unprocessed).await?;
         let unprocessed_updates = rows
             .into_iter()
             .map(|row| {
@@ -399,9 +1028,13 @@ impl Database {
             WHERE leaf_index = $1
             "#,
         )
-        .bind(leaf_index.leaf_index as i64);
+        .bind(leaf_index_to_db(leaf_index.leaf_index)?);
 
-        let rows = self.pool.fetch_all(identity_deletions).await?;
+        let rows = self
+            .connection()
+            .await?
+            .fetch_all(identity_deletions)
+            .await?;
         let deletions = rows
             .into_iter()
             .map(|_row| CommitmentHistoryEntry {
@@ -420,9 +1053,13 @@ impl Database {
             ORDER BY id ASC
             "#,
         )
-        .bind(leaf_index.leaf_index as i64);
+        .bind(leaf_index_to_db(leaf_index.leaf_index)?);
 
-        let rows = self.pool.fetch_all(processed_updates).await?;
+        let rows = self
+            .connection()
+            .await?
+            .fetch_all(processed_updates)
+            .await?;
         let processed_updates: Vec<CommitmentHistoryEntry> = rows
             .into_iter()
             .map(|row| CommitmentHistoryEntry {
@@ -453,7 +1090,7 @@ impl Database {
         )
         .bind(<&str>::from(status));
 
-        let row = self.pool.fetch_optional(query).await?;
+        let row = self.connection().await?.fetch_optional(query).await?;
 
         Ok(row.map(|r| r.get::<Hash, _>(0)))
     }
@@ -466,8 +1103,12 @@ impl Database {
             SELECT
                 status,
                 pending_as_of as pending_valid_as_of,
-                mined_at as mined_valid_as_of
+                mined_at as mined_valid_as_of,
+                transaction_hash,
+                block_number,
+                block_timestamp
             FROM identities
+            LEFT JOIN root_transactions USING (root)
             WHERE root = $1
             ORDER BY id
             LIMIT 1
@@ -475,7 +1116,7 @@ impl Database {
         )
         .bind(root);
 
-        let row = self.pool.fetch_optional(query).await?;
+        let row = self.connection().await?.fetch_optional(query).await?;
 
         Ok(row.map(|r| {
             let status = r
@@ -485,62 +1126,432 @@ impl Database {
 
             let pending_valid_as_of = r.get::<_, _>(1);
             let mined_valid_as_of = r.get::<_, _>(2);
+            let mined_transaction = Self::read_mined_transaction(&r, 3);
 
             RootItem {
                 root: *root,
                 status,
                 pending_valid_as_of,
                 mined_valid_as_of,
+                mined_transaction,
             }
         }))
     }
 
-    pub async fn get_latest_insertion_timestamp(&self) -> Result<Option<DateTime<Utc>>, Error> {
+    /// Returns up to `limit` roots the tree went through after the row
+    /// `after`, in the order they were reached. Roots are only listed once,
+    /// as of the first row leading to them. Only mined roots are published in
+    /// a block, so filtering by block leaves out the others.
+    pub async fn get_root_history(
+        &self,
+        after: usize,
+        status: Option<ProcessedStatus>,
+        from_block: Option<u64>,
+        to_block: Option<u64>,
+        limit: i64,
+    ) -> Result<Vec<RootHistoryEntry>, Error> {
         let query = sqlx::query(
             r#"
-            SELECT insertion_timestamp
-            FROM latest_insertion_timestamp
-            WHERE Lock = 'X';"#,
-        );
+            SELECT
+                identities.id,
+                identities.root,
+                identities.status,
+                identities.pending_as_of,
+                identities.mined_at,
+                root_transactions.transaction_hash,
+                root_transactions.block_number,
+                root_transactions.block_timestamp
+            FROM identities
+            LEFT JOIN root_transactions USING (root)
+            WHERE identities.id > $1
+              AND NOT EXISTS (
+                  SELECT 1 FROM identities AS earlier
+                  WHERE earlier.root = identities.root AND earlier.id < identities.id
+              )
+              AND ($2::TEXT IS NULL OR identities.status = $2)
+              AND ($3::BIGINT IS NULL OR root_transactions.block_number >= $3)
+              AND ($4::BIGINT IS NULL OR root_transactions.block_number <= $4)
+            ORDER BY identities.id ASC
+            LIMIT $5
+            "#,
+        )
+        .bind(after as i64)
+        .bind(status.map(<&str>::from))
+        .bind(from_block.map(|block| block as i64))
+        .bind(to_block.map(|block| block as i64))
+        .bind(limit);
 
-        let row = self.pool.fetch_optional(query).await?;
+        let rows = self.connection().await?.fetch_all(query).await?;
 
-        Ok(row.map(|r| r.get::<DateTime<Utc>, _>(0)))
+        Ok(rows
+            .into_iter()
+            .map(|row| RootHistoryEntry {
+                id:   row.get::<i64, _>(0) as usize,
+                root: RootItem {
+                    root:                row.get::<Hash, _>(1),
+                    status:              row
+                        .get::<&str, _>(2)
+                        .parse()
+                        .expect("Status is unreadable, database is corrupt"),
+                    pending_valid_as_of: row.get::<_, _>(3),
+                    mined_valid_as_of:   row.get::<_, _>(4),
+                    mined_transaction:   Self::read_mined_transaction(&row, 5),
+                },
+            })
+            .collect())
     }
 
-    pub async fn count_unprocessed_identities(&self) -> Result<i32, Error> {
+    pub async fn insert_root_transaction(
+        &self,
+        root: &Hash,
+        transaction: &MinedTransaction,
+    ) -> Result<(), Error> {
+        // A root mined again after a reorg is published by another transaction.
         let query = sqlx::query(
             r#"
-            SELECT COUNT(*) as unprocessed
-            FROM unprocessed_identities
+            INSERT INTO root_transactions (root, transaction_hash, block_number, block_timestamp)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (root) DO UPDATE
+            SET transaction_hash = EXCLUDED.transaction_hash,
+                block_number = EXCLUDED.block_number,
+                block_timestamp = EXCLUDED.block_timestamp
             "#,
-        );
-        let result = self.pool.fetch_one(query).await?;
-        Ok(result.get::<i64, _>(0) as i32)
+        )
+        .bind(root)
+        .bind(transaction.transaction_hash.as_bytes())
+        .bind(transaction.block_number as i64)
+        .bind(transaction.block_timestamp);
+
+        self.connection().await?.execute(query).await?;
+        Ok(())
     }
 
-    pub async fn count_pending_identities(&self) -> Result<i32, Error> {
+    pub async fn get_root_transaction(
+        &self,
+        root: &Hash,
+    ) -> Result<Option<MinedTransaction>, Error> {
         let query = sqlx::query(
             r#"
-            SELECT COUNT(*) as pending
-            FROM identities
+            SELECT transaction_hash, block_number, block_timestamp
+            FROM root_transactions
+            WHERE root = $1
+            "#,
+        )
+        .bind(root);
+
+        let row = self.connection().await?.fetch_optional(query).await?;
+
+        Ok(row.and_then(|row| Self::read_mined_transaction(&row, 0)))
+    }
+
+    /// Reads the transaction columns starting at `offset`, which are null if
+    /// no transaction was recorded.
+    fn read_mined_transaction(row: &PgRow, offset: usize) -> Option<MinedTransaction> {
+        let transaction_hash = row.get::<Option<Vec<u8>>, _>(offset)?;
+
+        Some(MinedTransaction {
+            transaction_hash: H256::from_slice(&transaction_hash),
+            block_number:     row.get::<i64, _>(offset + 1) as u64,
+            block_timestamp:  row.get::<_, _>(offset + 2),
+        })
+    }
+
+    /// Counts the tree updates from `pre_root` up to `post_root`. Batches
+    /// applied to the initial root, which isn't stored, start at the first row.
+    pub async fn count_updates_between_roots(
+        &self,
+        pre_root: &Hash,
+        post_root: &Hash,
+    ) -> Result<u64, Error> {
+        let query = sqlx::query(
+            r#"
+            WITH
+                pre AS (SELECT MIN(id) AS id FROM identities WHERE root = $1),
+                post AS (SELECT MIN(id) AS id FROM identities WHERE root = $2)
+            SELECT COUNT(*)
+            FROM identities, pre, post
+            WHERE identities.id > COALESCE(pre.id, 0) AND identities.id <= post.id
+            "#,
+        )
+        .bind(pre_root)
+        .bind(post_root);
+
+        let row = self.connection().await?.fetch_one(query).await?;
+
+        Ok(row.get::<i64, _>(0) as u64)
+    }
+
+    /// Records the block of the transaction which mined the updates from
+    /// `pre_root` up to `post_root`. Batches applied to the initial root,
+    /// which isn't stored, start at the first row.
+    pub async fn set_mined_block(
+        &self,
+        pre_root: Option<&Hash>,
+        post_root: &Hash,
+        block_number: u64,
+    ) -> Result<u64, Error> {
+        let query = sqlx::query(
+            r#"
+            WITH
+                pre AS (SELECT MIN(id) AS id FROM identities WHERE root = $1),
+                post AS (SELECT MIN(id) AS id FROM identities WHERE root = $2)
+            UPDATE identities
+            SET    mined_block = $3
+            FROM   pre, post
+            WHERE  identities.id > COALESCE(pre.id, 0) AND identities.id <= post.id
+            "#,
+        )
+        .bind(pre_root)
+        .bind(post_root)
+        .bind(block_number as i64);
+
+        let result = self.connection().await?.execute(query).await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// The block in which the latest insertion of the commitment was mined,
+    /// `None` if it isn't mined or the block wasn't recorded.
+    pub async fn get_mined_block(&self, commitment: &Hash) -> Result<Option<u64>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT mined_block
+            FROM identities
+            WHERE commitment = $1
+            ORDER BY id DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(commitment);
+
+        let row = self.connection().await?.fetch_optional(query).await?;
+
+        Ok(row
+            .and_then(|row| row.get::<Option<i64>, _>(0))
+            .map(|block| block as u64))
+    }
+
+    /// Lists the leaves of the mined tree after the leaf index `after`, in
+    /// order, optionally only those mined before `mined_before_block`.
+    /// Deleted leaves aren't listed.
+    pub async fn get_mined_leaves(
+        &self,
+        after: Option<usize>,
+        mined_before_block: Option<u64>,
+        limit: i64,
+    ) -> Result<Vec<MinedLeafEntry>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT leaf_index, commitment, mined_block
+            FROM (
+                SELECT DISTINCT ON (leaf_index) leaf_index, commitment, mined_block
+                FROM identities
+                WHERE status = $1
+                  AND ($2::BIGINT IS NULL OR leaf_index > $2)
+                ORDER BY leaf_index, id DESC
+            ) leaves
+            WHERE commitment <> $3
+              AND ($4::BIGINT IS NULL OR mined_block < $4)
+            ORDER BY leaf_index
+            LIMIT $5
+            "#,
+        )
+        .bind(<&str>::from(ProcessedStatus::Mined))
+        .bind(after.map(|leaf_index| leaf_index as i64))
+        .bind(Hash::ZERO)
+        .bind(mined_before_block.map(|block| block as i64))
+        .bind(limit);
+
+        let rows = self.connection().await?.fetch_all(query).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| MinedLeafEntry {
+                leaf_index:  leaf_index_from_db(row.get::<i64, _>(0)),
+                commitment:  row.get::<Hash, _>(1),
+                mined_block: row.get::<Option<i64>, _>(2).map(|block| block as u64),
+            })
+            .collect())
+    }
+
+    /// Counts the roots published by a transaction.
+    pub async fn count_roots_of_transaction(&self, transaction_hash: &H256) -> Result<u64, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT COUNT(*)
+            FROM root_transactions
+            WHERE transaction_hash = $1
+            "#,
+        )
+        .bind(transaction_hash.as_bytes());
+
+        let row = self.connection().await?.fetch_one(query).await?;
+
+        Ok(row.get::<i64, _>(0) as u64)
+    }
+
+    /// Records the cost of a batch, returns whether it wasn't recorded before.
+    /// The identity count of a batch recorded again is updated.
+    pub async fn insert_batch_cost(&self, cost: &BatchCost) -> Result<bool, Error> {
+        let query = sqlx::query(
+            r#"
+            INSERT INTO batch_costs (
+                transaction_hash,
+                kind,
+                identity_count,
+                gas_used,
+                effective_gas_price,
+                block_timestamp
+            )
+            VALUES ($1, $2, $3, $4, $5::NUMERIC, $6)
+            ON CONFLICT (transaction_hash) DO UPDATE
+            SET identity_count = EXCLUDED.identity_count
+            RETURNING (xmax = 0)
+            "#,
+        )
+        .bind(cost.transaction_hash.as_bytes())
+        .bind(&cost.kind)
+        .bind(cost.identity_count as i64)
+        .bind(cost.gas_used as i64)
+        .bind(cost.effective_gas_price.to_string())
+        .bind(cost.block_timestamp);
+
+        let row = self.connection().await?.fetch_one(query).await?;
+
+        Ok(row.get::<bool, _>(0))
+    }
+
+    /// Returns the costs of the batches mined in `[from, to)`, oldest first.
+    pub async fn get_batch_costs(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<BatchCost>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT
+                transaction_hash,
+                kind,
+                identity_count,
+                gas_used,
+                effective_gas_price::TEXT,
+                block_timestamp
+            FROM batch_costs
+            WHERE ($1::TIMESTAMPTZ IS NULL OR block_timestamp >= $1)
+              AND ($2::TIMESTAMPTZ IS NULL OR block_timestamp < $2)
+            ORDER BY block_timestamp ASC, transaction_hash ASC
+            "#,
+        )
+        .bind(from)
+        .bind(to);
+
+        let rows = self.connection().await?.fetch_all(query).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| BatchCost {
+                transaction_hash:    H256::from_slice(&row.get::<Vec<u8>, _>(0)),
+                kind:                row.get::<String, _>(1),
+                identity_count:      row.get::<i64, _>(2) as u64,
+                gas_used:            row.get::<i64, _>(3) as u64,
+                effective_gas_price: U256::from_dec_str(row.get::<&str, _>(4))
+                    .expect("Gas price is unreadable, database is corrupt"),
+                block_timestamp:     row.get::<_, _>(5),
+            })
+            .collect())
+    }
+
+    pub async fn get_latest_insertion_timestamp(&self) -> Result<Option<DateTime<Utc>>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT insertion_timestamp
+            FROM latest_insertion_timestamp
+            WHERE Lock = 'X';"#,
+        );
+
+        let row = self.connection().await?.fetch_optional(query).await?;
+
+        Ok(row.map(|r| r.get::<DateTime<Utc>, _>(0)))
+    }
+
+    /// The last block whose identity manager events have been processed.
+    pub async fn get_last_processed_block(&self) -> Result<Option<u64>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT block_number
+            FROM last_processed_block
+            WHERE Lock = 'X';"#,
+        );
+
+        let row = self.connection().await?.fetch_optional(query).await?;
+
+        Ok(row.map(|r| r.get::<i64, _>(0) as u64))
+    }
+
+    pub async fn update_last_processed_block(&self, block_number: u64) -> Result<(), Error> {
+        let query = sqlx::query(
+            r#"
+            INSERT INTO last_processed_block (Lock, block_number)
+            VALUES ('X', $1)
+            ON CONFLICT (Lock)
+            DO UPDATE SET block_number = EXCLUDED.block_number;
+            "#,
+        )
+        .bind(block_number as i64);
+
+        self.connection().await?.execute(query).await?;
+        Ok(())
+    }
+
+    pub async fn count_unprocessed_identities(&self) -> Result<i32, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT COUNT(*) as unprocessed
+            FROM unprocessed_identities
+            "#,
+        );
+        let result = self.connection().await?.fetch_one(query).await?;
+        Ok(result.get::<i64, _>(0) as i32)
+    }
+
+    pub async fn count_pending_identities(&self) -> Result<i32, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT COUNT(*) as pending
+            FROM identities
             WHERE status = $1
             "#,
         )
         .bind(<&str>::from(ProcessedStatus::Pending));
-        let result = self.pool.fetch_one(query).await?;
+        let result = self.connection().await?.fetch_one(query).await?;
         Ok(result.get::<i64, _>(0) as i32)
     }
 
+    pub async fn count_identities_by_status(
+        &self,
+        status: ProcessedStatus,
+    ) -> Result<usize, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT COUNT(*)
+            FROM identities
+            WHERE status = $1
+            "#,
+        )
+        .bind(<&str>::from(status));
+        let result = self.connection().await?.fetch_one(query).await?;
+        Ok(result.get::<i64, _>(0) as usize)
+    }
+
     pub async fn get_provers(&self) -> Result<Provers, Error> {
         let query = sqlx::query(
             r#"
-                SELECT batch_size, url, timeout_s, prover_type
+                SELECT batch_size, url, timeout_s, prover_type, key_digest
                 FROM provers
             "#,
         );
 
-        let result = self.pool.fetch_all(query).await?;
+        let result = self.connection().await?.fetch_all(query).await?;
 
         Ok(result
             .iter()
@@ -549,11 +1560,15 @@ impl Database {
                 let url = row.get::<String, _>(1);
                 let timeout_s = row.get::<i64, _>(2) as u64;
                 let prover_type = row.get::<ProverType, _>(3);
+                let key_digest = row
+                    .get::<Option<Vec<u8>>, _>(4)
+                    .map(|digest| H256::from_slice(&digest));
                 ProverConfiguration {
                     url,
                     timeout_s,
                     batch_size,
                     prover_type,
+                    key_digest,
                 }
             })
             .collect::<Provers>())
@@ -565,21 +1580,23 @@ impl Database {
         url: impl ToString,
         timeout_seconds: u64,
         prover_type: ProverType,
+        key_digest: Option<H256>,
     ) -> Result<(), Error> {
         let url = url.to_string();
 
         let query = sqlx::query(
             r#"
-                INSERT INTO provers (batch_size, url, timeout_s, prover_type)
-                VALUES ($1, $2, $3, $4)
+                INSERT INTO provers (batch_size, url, timeout_s, prover_type, key_digest)
+                VALUES ($1, $2, $3, $4, $5)
             "#,
         )
         .bind(batch_size as i64)
         .bind(url)
         .bind(timeout_seconds as i64)
-        .bind(prover_type);
+        .bind(prover_type)
+        .bind(key_digest.map(|digest| digest.as_bytes().to_vec()));
 
-        self.pool.execute(query).await?;
+        self.connection().await?.execute(query).await?;
 
         Ok(())
     }
@@ -591,7 +1608,7 @@ impl Database {
 
         let mut query_builder = sqlx::QueryBuilder::new(
             r#"
-                  INSERT INTO provers (batch_size, url, timeout_s, prover_type)
+                  INSERT INTO provers (batch_size, url, timeout_s, prover_type, key_digest)
             "#,
         );
 
@@ -599,12 +1616,13 @@ impl Database {
             b.push_bind(prover.batch_size as i64)
                 .push_bind(prover.url)
                 .push_bind(prover.timeout_s as i64)
-                .push_bind(prover.prover_type);
+                .push_bind(prover.prover_type)
+                .push_bind(prover.key_digest.map(|digest| digest.as_bytes().to_vec()));
         });
 
         let query = query_builder.build();
 
-        self.pool.execute(query).await?;
+        self.connection().await?.execute(query).await?;
         Ok(())
     }
 
@@ -621,7 +1639,7 @@ impl Database {
         .bind(batch_size as i64)
         .bind(prover_type);
 
-        self.pool.execute(query).await?;
+        self.connection().await?.execute(query).await?;
 
         Ok(())
     }
@@ -631,6 +1649,13 @@ impl Database {
         identity: Hash,
         eligibility_timestamp: sqlx::types::chrono::DateTime<Utc>,
     ) -> Result<Hash, Error> {
+        let mut tx = self.begin().await?;
+
+        // Without the lock a concurrent insertion could commit a later
+        // position first, and be assigned a leaf ahead of this one.
+        tx.execute(sqlx::query("SELECT pg_advisory_xact_lock($1)").bind(QUEUE_LOCK_KEY))
+            .await?;
+
         let query = sqlx::query(
             r#"
             INSERT INTO unprocessed_identities (commitment, status, created_at, eligibility)
@@ -641,616 +1666,2830 @@ impl Database {
         .bind(<&str>::from(UnprocessedStatus::New))
         .bind(eligibility_timestamp);
 
-        self.pool.execute(query).await?;
+        tx.execute(query).await?;
+        Self::append_event(&mut tx, &identity, IdentityEvent::Received, None).await?;
+
+        tx.commit().await?;
         Ok(identity)
     }
 
-    pub async fn insert_new_recovery(
+    /// Queues the insertions in a single transaction, with their metadata,
+    /// audit entries and receipts, and returns their sequence numbers in the
+    /// same order. Either all of them are queued or none, none if they don't
+    /// fit into the `capacity` leaves of the tree.
+    pub async fn insert_new_identities(
         &self,
-        existing_commitment: &Hash,
-        new_commitment: &Hash,
+        insertions: &[NewInsertion],
+        capacity: usize,
+    ) -> Result<Vec<u64>, Error> {
+        if insertions.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut tx = self.begin().await?;
+
+        // See `insert_new_identity`. Checking the capacity under the lock keeps
+        // concurrent insertions from overfilling the tree.
+        tx.execute(sqlx::query("SELECT pg_advisory_xact_lock($1)").bind(QUEUE_LOCK_KEY))
+            .await?;
+        Self::ensure_capacity(&mut tx, insertions.len(), capacity).await?;
+
+        // Resubmitted commitments keep the sequence number they were first
+        // accepted with, the select doesn't see the rows inserted alongside.
+        let mut query_builder = sqlx::QueryBuilder::new(
+            "WITH inserted AS (INSERT INTO insertion_receipts (commitment, created_at) ",
+        );
+        query_builder.push_values(insertions, |mut b, insertion| {
+            b.push_bind(insertion.commitment)
+                .push_bind(insertion.created_at);
+        });
+        query_builder.push(
+            " ON CONFLICT (commitment) DO NOTHING RETURNING commitment, sequence_number) SELECT \
+             commitment, sequence_number FROM inserted UNION ALL SELECT commitment, \
+             sequence_number FROM insertion_receipts WHERE commitment IN (",
+        );
+        let mut commitments = query_builder.separated(", ");
+        for insertion in insertions {
+            commitments.push_bind(insertion.commitment);
+        }
+        query_builder.push(")");
+
+        let rows = tx.fetch_all(query_builder.build()).await?;
+
+        let sequence_numbers: HashMap<Hash, u64> = rows
+            .into_iter()
+            .map(|row| (row.get::<Hash, _>(0), row.get::<i64, _>(1) as u64))
+            .collect();
+
+        // Queued identities are assigned leaves in the order of their queue
+        // positions, which are their sequence numbers.
+        let mut query_builder = sqlx::QueryBuilder::new(
+            "INSERT INTO unprocessed_identities (commitment, status, created_at, eligibility, \
+             queue_position) ",
+        );
+        query_builder.push_values(insertions, |mut b, insertion| {
+            b.push_bind(insertion.commitment)
+                .push_bind(<&str>::from(UnprocessedStatus::New))
+                .push("CURRENT_TIMESTAMP")
+                .push_bind(insertion.created_at)
+                .push_bind(sequence_numbers[&insertion.commitment] as i64);
+        });
+        // Concurrent submissions of a commitment both pass the check for
+        // existing ones, the later one fails here.
+        tx.execute(query_builder.build())
+            .await
+            .map_err(|error| match &error {
+                sqlx::Error::Database(db_error)
+                    if db_error.code().as_deref() == Some(UNIQUE_VIOLATION) =>
+                {
+                    Error::DuplicateCommitment
+                }
+                _ => error.into(),
+            })?;
+
+        let mut query_builder = sqlx::QueryBuilder::new(
+            "INSERT INTO identity_events (commitment, event, message, created_at) ",
+        );
+        query_builder.push_values(insertions, |mut b, insertion| {
+            b.push_bind(insertion.commitment)
+                .push_bind(<&str>::from(IdentityEvent::Received))
+                .push("NULL")
+                .push("CURRENT_TIMESTAMP");
+        });
+        tx.execute(query_builder.build()).await?;
+
+        let with_metadata: Vec<_> = insertions
+            .iter()
+            .filter_map(|insertion| Some((insertion.commitment, insertion.metadata.as_ref()?)))
+            .collect();
+        if !with_metadata.is_empty() {
+            let mut query_builder = sqlx::QueryBuilder::new(
+                "INSERT INTO identity_metadata (commitment, metadata, created_at) ",
+            );
+            query_builder.push_values(with_metadata, |mut b, (commitment, metadata)| {
+                b.push_bind(commitment)
+                    .push_bind(metadata)
+                    .push("CURRENT_TIMESTAMP");
+            });
+            query_builder.push(" ON CONFLICT (commitment) DO NOTHING");
+            tx.execute(query_builder.build()).await?;
+        }
+
+        let mut query_builder = sqlx::QueryBuilder::new(
+            "INSERT INTO insertion_audit_log (commitment, signer, created_at) ",
+        );
+        query_builder.push_values(insertions, |mut b, insertion| {
+            b.push_bind(insertion.commitment)
+                .push_bind(insertion.signer.map(|signer| signer.as_bytes().to_vec()))
+                .push("CURRENT_TIMESTAMP");
+        });
+        tx.execute(query_builder.build()).await?;
+
+        tx.commit().await?;
+
+        Ok(insertions
+            .iter()
+            .map(|insertion| sequence_numbers[&insertion.commitment])
+            .collect())
+    }
+
+    /// Fails if `count` more insertions don't fit into the `capacity` leaves of
+    /// the tree, next to the leaves assigned and the identities queued.
+    async fn ensure_capacity(
+        tx: &mut Transaction<'_, Postgres>,
+        count: usize,
+        capacity: usize,
     ) -> Result<(), Error> {
         let query = sqlx::query(
             r#"
-            INSERT INTO recoveries (existing_commitment, new_commitment)
-            VALUES ($1, $2)
+            SELECT
+                (SELECT COALESCE(MAX(leaf_index) + 1, 0) FROM identities),
+                (SELECT COUNT(*) FROM unprocessed_identities WHERE status = $1)
             "#,
         )
-        .bind(existing_commitment)
-        .bind(new_commitment);
-        self.pool.execute(query).await?;
-        Ok(())
-    }
-
-    pub async fn get_latest_deletion(&self) -> Result<LatestDeletionEntry, Error> {
-        let query =
-            sqlx::query("SELECT deletion_timestamp FROM latest_deletion_root WHERE Lock = 'X';");
+        .bind(<&str>::from(UnprocessedStatus::New));
 
-        let row = self.pool.fetch_optional(query).await?;
+        let row = tx.fetch_one(query).await?;
+        let used = row.get::<i64, _>(0) as usize + row.get::<i64, _>(1) as usize;
 
-        if let Some(row) = row {
-            Ok(LatestDeletionEntry {
-                timestamp: row.get(0),
-            })
-        } else {
-            Ok(LatestDeletionEntry {
-                timestamp: Utc::now(),
-            })
+        if count > capacity.saturating_sub(used) {
+            return Err(Error::TreeFull { capacity });
         }
+
+        Ok(())
     }
 
-    pub async fn update_latest_insertion_timestamp(
-        &self,
-        insertion_timestamp: DateTime<Utc>,
+    async fn append_event(
+        tx: impl Executor<'_, Database = Postgres>,
+        commitment: &Hash,
+        event: IdentityEvent,
+        message: Option<&str>,
     ) -> Result<(), Error> {
         let query = sqlx::query(
             r#"
-            INSERT INTO latest_insertion_timestamp (Lock, insertion_timestamp)
-            VALUES ('X', $1)
-            ON CONFLICT (Lock)
-            DO UPDATE SET insertion_timestamp = EXCLUDED.insertion_timestamp;
+            INSERT INTO identity_events (commitment, event, message, created_at)
+            VALUES ($1, $2, $3, CURRENT_TIMESTAMP)
             "#,
         )
-        .bind(insertion_timestamp);
+        .bind(commitment)
+        .bind(<&str>::from(event))
+        .bind(message);
 
-        self.pool.execute(query).await?;
+        tx.execute(query).await?;
         Ok(())
     }
 
-    pub async fn update_latest_deletion(
+    /// Records the same event for each of the commitments, e.g. when they
+    /// are batched together.
+    pub async fn append_identity_events(
         &self,
-        deletion_timestamp: DateTime<Utc>,
+        commitments: &[Hash],
+        event: IdentityEvent,
     ) -> Result<(), Error> {
-        let query = sqlx::query(
-            r#"
-            INSERT INTO latest_deletion_root (Lock, deletion_timestamp)
-            VALUES ('X', $1)
-            ON CONFLICT (Lock)
-            DO UPDATE SET deletion_timestamp = EXCLUDED.deletion_timestamp;
-            "#,
-        )
-        .bind(deletion_timestamp);
+        let mut tx = self.begin().await?;
+
+        for commitment in commitments {
+            Self::append_event(&mut tx, commitment, event, None).await?;
+        }
 
-        self.pool.execute(query).await?;
+        tx.commit().await?;
         Ok(())
     }
 
-    // TODO: consider using a larger value than i64 for leaf index, ruint should
-    // have postgres compatibility for u256
-    pub async fn get_recoveries(&self) -> Result<Vec<RecoveryEntry>, Error> {
+    /// Returns the events of an identity, oldest first.
+    pub async fn get_identity_events(
+        &self,
+        commitment: &Hash,
+    ) -> Result<Vec<IdentityEventEntry>, Error> {
         let query = sqlx::query(
             r#"
-            SELECT *
-            FROM recoveries
+            SELECT event, message, created_at
+            FROM identity_events
+            WHERE commitment = $1
+            ORDER BY id ASC
             "#,
-        );
+        )
+        .bind(commitment);
 
-        let result = self.pool.fetch_all(query).await?;
+        let rows = self.connection().await?.fetch_all(query).await?;
 
-        Ok(result
+        Ok(rows
             .into_iter()
-            .map(|row| RecoveryEntry {
-                existing_commitment: row.get::<Hash, _>(0),
-                new_commitment:      row.get::<Hash, _>(1),
+            .map(|row| IdentityEventEntry {
+                event:      row
+                    .get::<&str, _>(0)
+                    .parse()
+                    .expect("Failed to parse identity event"),
+                message:    row.get::<Option<String>, _>(1),
+                created_at: row.get::<_, _>(2),
             })
-            .collect::<Vec<RecoveryEntry>>())
+            .collect())
     }
 
-    pub async fn insert_new_deletion(
+    /// Returns up to `limit` events of any identity logged after the event
+    /// `after`, oldest first.
+    pub async fn get_identity_events_after(
         &self,
-        leaf_index: usize,
-        identity: &Hash,
-    ) -> Result<(), Error> {
+        after: u64,
+        limit: i64,
+    ) -> Result<Vec<LoggedIdentityEvent>, Error> {
         let query = sqlx::query(
             r#"
-            INSERT INTO deletions (leaf_index, commitment)
-            VALUES ($1, $2)
+            SELECT id, commitment, event, message, created_at
+            FROM identity_events
+            WHERE id > $1
+            ORDER BY id ASC
+            LIMIT $2
             "#,
         )
-        .bind(leaf_index as i64)
-        .bind(identity);
-
-        self.pool.execute(query).await?;
-        Ok(())
-    }
-
-    // TODO: consider using a larger value than i64 for leaf index, ruint should
-    // have postgres compatibility for u256
-    pub async fn get_deletions(&self) -> Result<Vec<DeletionEntry>, Error> {
-        let query = sqlx::query(
-            r#"
-            SELECT *
-            FROM deletions
-            "#,
-        );
+        .bind(after as i64)
+        .bind(limit);
 
-        let result = self.pool.fetch_all(query).await?;
+        let rows = self.connection().await?.fetch_all(query).await?;
 
-        Ok(result
+        Ok(rows
             .into_iter()
-            .map(|row| DeletionEntry {
-                leaf_index: row.get::<i64, _>(0) as usize,
+            .map(|row| LoggedIdentityEvent {
+                id:         row.get::<i64, _>(0) as u64,
                 commitment: row.get::<Hash, _>(1),
+                event:      IdentityEventEntry {
+                    event:      row
+                        .get::<&str, _>(2)
+                        .parse()
+                        .expect("Failed to parse identity event"),
+                    message:    row.get::<Option<String>, _>(3),
+                    created_at: row.get::<_, _>(4),
+                },
             })
-            .collect::<Vec<DeletionEntry>>())
+            .collect())
     }
 
-    /// Remove a list of entries from the deletions table
-    pub async fn remove_deletions(&self, commitments: Vec<Hash>) -> Result<(), Error> {
-        let placeholders: String = commitments
-            .iter()
-            .enumerate()
-            .map(|(i, _)| format!("${}", i + 1))
-            .collect::<Vec<String>>()
-            .join(", ");
+    /// The id of the latest identity event, 0 if there is none.
+    pub async fn get_latest_identity_event_id(&self) -> Result<u64, Error> {
+        let query = sqlx::query("SELECT COALESCE(MAX(id), 0) FROM identity_events");
 
-        let query = format!(
-            "DELETE FROM deletions WHERE commitment IN ({})",
-            placeholders
+        let row = self.connection().await?.fetch_one(query).await?;
+
+        Ok(row.get::<i64, _>(0) as u64)
+    }
+
+    /// The last identity event published to the status topic of the
+    /// insertion queue.
+    pub async fn get_last_published_identity_event(&self) -> Result<Option<u64>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT event_id
+            FROM last_published_identity_event
+            WHERE Lock = 'X';"#,
         );
 
-        let mut query = sqlx::query(&query);
+        let row = self.connection().await?.fetch_optional(query).await?;
 
-        for commitment in &commitments {
-            query = query.bind(commitment);
-        }
+        Ok(row.map(|r| r.get::<i64, _>(0) as u64))
+    }
 
-        query.execute(&self.pool).await?;
+    pub async fn update_last_published_identity_event(&self, event_id: u64) -> Result<(), Error> {
+        let query = sqlx::query(
+            r#"
+            INSERT INTO last_published_identity_event (Lock, event_id)
+            VALUES ('X', $1)
+            ON CONFLICT (Lock)
+            DO UPDATE SET event_id = EXCLUDED.event_id;
+            "#,
+        )
+        .bind(event_id as i64);
 
+        self.connection().await?.execute(query).await?;
         Ok(())
     }
 
-    pub async fn get_eligible_unprocessed_commitments(
-        &self,
-        status: UnprocessedStatus,
-    ) -> Result<Vec<types::UnprocessedCommitment>, Error> {
+    /// Returns up to `limit` lifecycle events waiting to be published to the
+    /// event bus, oldest first.
+    pub async fn get_outbox_events(&self, limit: i64) -> Result<Vec<LoggedIdentityEvent>, Error> {
         let query = sqlx::query(
             r#"
-                SELECT * FROM unprocessed_identities
-                WHERE status = $1 AND CURRENT_TIMESTAMP > eligibility
-                LIMIT $2
+            SELECT id, commitment, event, message, created_at
+            FROM event_outbox
+            ORDER BY id ASC
+            LIMIT $1
             "#,
         )
-        .bind(<&str>::from(status))
-        .bind(MAX_UNPROCESSED_FETCH_COUNT);
+        .bind(limit);
 
-        let result = self.pool.fetch_all(query).await?;
+        let rows = self.connection().await?.fetch_all(query).await?;
 
-        Ok(result
+        Ok(rows
             .into_iter()
-            .map(|row| types::UnprocessedCommitment {
-                commitment: row.get::<Hash, _>(0),
-                status,
-                created_at: row.get::<_, _>(2),
-                processed_at: row.get::<_, _>(3),
-                error_message: row.get::<_, _>(4),
-                eligibility_timestamp: row.get::<_, _>(5),
+            .map(|row| LoggedIdentityEvent {
+                id:         row.get::<i64, _>(0) as u64,
+                commitment: row.get::<Hash, _>(1),
+                event:      IdentityEventEntry {
+                    event:      row
+                        .get::<&str, _>(2)
+                        .parse()
+                        .expect("Failed to parse identity event"),
+                    message:    row.get::<Option<String>, _>(3),
+                    created_at: row.get::<_, _>(4),
+                },
             })
-            .collect::<Vec<_>>())
+            .collect())
     }
 
-    pub async fn get_unprocessed_commit_status(
+    /// Removes lifecycle events from the outbox once they were published,
+    /// returns how many were removed. Events are removed by id rather than up
+    /// to one, as identity events may commit out of the order of their ids.
+    pub async fn delete_outbox_events(&self, ids: &[u64]) -> Result<u64, Error> {
+        let ids: Vec<i64> = ids.iter().map(|&id| id as i64).collect();
+        let query = sqlx::query("DELETE FROM event_outbox WHERE id = ANY($1)").bind(&ids);
+
+        let result = self.connection().await?.execute(query).await?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn insert_insertion_receipt(
         &self,
         commitment: &Hash,
-    ) -> Result<Option<(UnprocessedStatus, String)>, Error> {
+        created_at: DateTime<Utc>,
+    ) -> Result<u64, Error> {
+        // A resubmitted commitment keeps the sequence number it was first
+        // accepted with.
         let query = sqlx::query(
             r#"
-                SELECT status, error_message FROM unprocessed_identities WHERE commitment = $1
+            WITH inserted AS (
+                INSERT INTO insertion_receipts (commitment, created_at)
+                VALUES ($1, $2)
+                ON CONFLICT (commitment) DO NOTHING
+                RETURNING sequence_number
+            )
+            SELECT sequence_number FROM inserted
+            UNION ALL
+            SELECT sequence_number FROM insertion_receipts WHERE commitment = $1
+            LIMIT 1
             "#,
         )
-        .bind(commitment);
+        .bind(commitment)
+        .bind(created_at);
 
-        let result = self.pool.fetch_optional(query).await?;
+        let row = self.connection().await?.fetch_one(query).await?;
+        Ok(row.get::<i64, _>(0) as u64)
+    }
 
-        if let Some(row) = result {
-            return Ok(Some((
-                row.get::<&str, _>(0).parse().expect("couldn't read status"),
-                row.get::<Option<String>, _>(1).unwrap_or_default(),
-            )));
-        };
-        Ok(None)
+    pub async fn insert_identity_metadata(
+        &self,
+        commitment: &Hash,
+        metadata: &str,
+    ) -> Result<(), Error> {
+        let query = sqlx::query(
+            r#"
+            INSERT INTO identity_metadata (commitment, metadata, created_at)
+            VALUES ($1, $2, CURRENT_TIMESTAMP)
+            ON CONFLICT (commitment) DO NOTHING
+            "#,
+        )
+        .bind(commitment)
+        .bind(metadata);
+
+        self.connection().await?.execute(query).await?;
+        Ok(())
     }
 
-    pub async fn remove_unprocessed_identity(&self, commitment: &Hash) -> Result<(), Error> {
+    pub async fn get_identity_metadata(&self, commitment: &Hash) -> Result<Option<String>, Error> {
         let query = sqlx::query(
             r#"
-                DELETE FROM unprocessed_identities WHERE commitment = $1
+            SELECT metadata
+            FROM identity_metadata
+            WHERE commitment = $1
             "#,
         )
         .bind(commitment);
 
-        self.pool.execute(query).await?;
+        let row = self.connection().await?.fetch_optional(query).await?;
 
-        Ok(())
+        Ok(row.map(|row| row.get::<String, _>(0)))
     }
 
-    pub async fn update_err_unprocessed_commitment(
+    pub async fn insert_insertion_audit_entry(
         &self,
-        commitment: Hash,
-        message: String,
+        commitment: &Hash,
+        signer: Option<Address>,
     ) -> Result<(), Error> {
         let query = sqlx::query(
             r#"
-                UPDATE unprocessed_identities SET error_message = $1, status = $2
-                WHERE commitment = $3
+            INSERT INTO insertion_audit_log (commitment, signer, created_at)
+            VALUES ($1, $2, CURRENT_TIMESTAMP)
             "#,
         )
-        .bind(message)
-        .bind(<&str>::from(UnprocessedStatus::Failed))
-        .bind(commitment);
-
-        self.pool.execute(query).await?;
+        .bind(commitment)
+        .bind(signer.map(|signer| signer.as_bytes().to_vec()));
 
+        self.connection().await?.execute(query).await?;
         Ok(())
     }
 
-    pub async fn identity_exists(&self, commitment: Hash) -> Result<bool, Error> {
-        let query_unprocessed_identity = sqlx::query(
-            r#"SELECT exists(SELECT 1 FROM unprocessed_identities where commitment = $1)"#,
-        )
-        .bind(commitment);
+    pub async fn insert_screening_audit_entry(
+        &self,
+        subject: &ScreeningSubject,
+        decision: ScreeningDecision,
+    ) -> Result<(), Error> {
+        let query = sqlx::query(
+            r#"
+            INSERT INTO screening_audit_log (commitment, signer, jurisdiction, decision, created_at)
+            VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP)
+            "#,
+        );
 
-        let row_unprocessed = self.pool.fetch_one(query_unprocessed_identity).await?;
+        // Unlike the insertion audit log, which signers are looked up in,
+        // nothing reads the commitment back, so it's redacted like the logs.
+        let query = match redaction::current().audit_bytes(&subject.commitment) {
+            Some(redacted) => query.bind(redacted),
+            None => query.bind(subject.commitment),
+        }
+        .bind(subject.signer.map(|signer| signer.as_bytes().to_vec()))
+        .bind(subject.jurisdiction.as_deref())
+        .bind(decision.as_str());
 
-        let query_processed_identity =
-            sqlx::query(r#"SELECT exists(SELECT 1 FROM identities where commitment = $1)"#)
-                .bind(commitment);
+        self.connection().await?.execute(query).await?;
+        Ok(())
+    }
 
-        let row_processed = self.pool.fetch_one(query_processed_identity).await?;
+    pub async fn insert_signing_record(&self, record: &SigningRecord) -> Result<(), Error> {
+        let query = sqlx::query(
+            r#"
+            INSERT INTO signing_audit_log (
+                signer,
+                purpose,
+                payload_digest,
+                nonce,
+                chain_id,
+                outcome,
+                transaction_id,
+                created_at
+            )
+            VALUES ($1, $2, $3, $4::NUMERIC, $5, $6, $7, $8)
+            "#,
+        )
+        .bind(record.signer.as_bytes())
+        .bind(record.purpose.as_str())
+        .bind(record.payload_digest.as_bytes())
+        .bind(record.nonce.map(|nonce| nonce.to_string()))
+        .bind(record.chain_id as i64)
+        .bind(record.outcome.as_str())
+        .bind(record.transaction_id.as_deref())
+        .bind(record.timestamp);
+
+        self.connection().await?.execute(query).await?;
+        Ok(())
+    }
 
-        let exists = row_unprocessed.get::<bool, _>(0) || row_processed.get::<bool, _>(0);
+    /// Fills in the nonce of the operations which submitted the transaction,
+    /// unless it's already known.
+    pub async fn set_signing_record_nonce(
+        &self,
+        transaction_id: &str,
+        nonce: U256,
+    ) -> Result<(), Error> {
+        let query = sqlx::query(
+            r#"
+            UPDATE signing_audit_log
+            SET nonce = $2::NUMERIC
+            WHERE transaction_id = $1 AND nonce IS NULL
+            "#,
+        )
+        .bind(transaction_id)
+        .bind(nonce.to_string());
 
-        Ok(exists)
+        self.connection().await?.execute(query).await?;
+        Ok(())
     }
 
-    // TODO: add docs
-    pub async fn identity_is_queued_for_deletion(&self, commitment: &Hash) -> Result<bool, Error> {
-        let query_queued_deletion =
-            sqlx::query(r#"SELECT exists(SELECT 1 FROM deletions where commitment = $1)"#)
-                .bind(commitment);
-        let row_unprocessed = self.pool.fetch_one(query_queued_deletion).await?;
-        Ok(row_unprocessed.get::<bool, _>(0))
-    }
-}
+    /// The signing operations recorded in `[from, to)`, oldest first. At most
+    /// the most recent [`MAX_SIGNING_RECORDS`] are returned.
+    pub async fn get_signing_records(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SigningRecord>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT * FROM (
+                SELECT
+                    id,
+                    signer,
+                    purpose,
+                    payload_digest,
+                    nonce::TEXT,
+                    chain_id,
+                    outcome,
+                    transaction_id,
+                    created_at
+                FROM signing_audit_log
+                WHERE ($1::TIMESTAMPTZ IS NULL OR created_at >= $1)
+                  AND ($2::TIMESTAMPTZ IS NULL OR created_at < $2)
+                ORDER BY id DESC
+                LIMIT $3
+            ) AS recent
+            ORDER BY id ASC
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .bind(MAX_SIGNING_RECORDS);
 
-#[derive(Debug, Error)]
-pub enum Error {
-    #[error("database error: {0}")]
-    InternalError(#[from] sqlx::Error),
+        let rows = self.connection().await?.fetch_all(query).await?;
 
-    #[error("Tried to mine missing root {root:?}")]
-    MissingRoot { root: Hash },
-}
+        Ok(rows
+            .into_iter()
+            .map(|row| SigningRecord {
+                signer:         Address::from_slice(&row.get::<Vec<u8>, _>(1)),
+                purpose:        row
+                    .get::<&str, _>(2)
+                    .parse()
+                    .expect("Failed to parse signing purpose"),
+                payload_digest: H256::from_slice(&row.get::<Vec<u8>, _>(3)),
+                nonce:          row.get::<Option<&str>, _>(4).map(|nonce| {
+                    U256::from_dec_str(nonce).expect("Nonce is unreadable, database is corrupt")
+                }),
+                chain_id:       row.get::<i64, _>(5) as u64,
+                outcome:        row
+                    .get::<&str, _>(6)
+                    .parse()
+                    .expect("Failed to parse signing outcome"),
+                transaction_id: row.get::<Option<String>, _>(7),
+                timestamp:      row.get::<_, _>(8),
+            })
+            .collect())
+    }
 
-#[cfg(test)]
-mod test {
-    use std::collections::HashSet;
-    use std::str::FromStr;
-    use std::time::Duration;
+    /// Returns the signer recorded for the latest insertion of the
+    /// commitment, `None` if it wasn't inserted or the insertion was unsigned.
+    pub async fn get_insertion_signer(&self, commitment: &Hash) -> Result<Option<Address>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT signer
+            FROM insertion_audit_log
+            WHERE commitment = $1
+            ORDER BY id DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(commitment);
 
-    use anyhow::Context;
-    use chrono::{Days, Utc};
-    use ethers::types::U256;
-    use postgres_docker_utils::DockerContainerGuard;
-    use ruint::Uint;
-    use semaphore::Field;
+        let row = self.connection().await?.fetch_optional(query).await?;
 
-    use super::{Database, Options};
-    use crate::identity_tree::{Hash, ProcessedStatus, Status, UnprocessedStatus};
-    use crate::prover::{ProverConfiguration, ProverType};
-    use crate::secret::SecretUrl;
+        Ok(row
+            .and_then(|row| row.get::<Option<Vec<u8>>, _>(0))
+            .map(|signer| Address::from_slice(&signer)))
+    }
 
-    macro_rules! assert_same_time {
-        ($a:expr, $b:expr, $diff:expr) => {
-            assert!(
-                abs_duration($a - $b) < $diff,
-                "Difference between {} and {} is larger than {:?}",
-                $a,
-                $b,
-                $diff
-            );
-        };
+    pub async fn get_insertion_receipt(
+        &self,
+        commitment: &Hash,
+    ) -> Result<Option<InsertionReceiptEntry>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT leaf_index, pre_root, created_at, sequence_number
+            FROM insertion_receipts
+            WHERE commitment = $1
+            "#,
+        )
+        .bind(commitment);
 
-        ($a:expr, $b:expr) => {
-            assert_same_time!($a, $b, chrono::Duration::milliseconds(500));
+        let Some(row) = self.connection().await?.fetch_optional(query).await? else {
+            return Ok(None);
         };
-    }
 
-    fn abs_duration(x: chrono::Duration) -> chrono::Duration {
-        chrono::Duration::milliseconds(x.num_milliseconds().abs())
+        Ok(Some(InsertionReceiptEntry {
+            commitment:      *commitment,
+            leaf_index:      row.get::<Option<i64>, _>(0).map(leaf_index_from_db),
+            pre_root:        row.get::<Option<Hash>, _>(1),
+            created_at:      row.get::<_, _>(2),
+            sequence_number: row.get::<i64, _>(3) as u64,
+        }))
     }
 
-    // TODO: we should probably consolidate all tests that propagate errors to
-    // TODO: either use anyhow or eyre
-    async fn setup_db() -> anyhow::Result<(Database, DockerContainerGuard)> {
-        let db_container = postgres_docker_utils::setup().await?;
-        let db_socket_addr = db_container.address();
-        let url = format!("postgres://postgres:postgres@{db_socket_addr}/database");
+    /// Reserves `key` for a request with the given fingerprint, taking over
+    /// keys first used before `expired_before`. Returns `None` if the key was
+    /// reserved, or the first request made with it otherwise.
+    pub async fn reserve_idempotency_key(
+        &self,
+        key: &str,
+        fingerprint: &H256,
+        expired_before: DateTime<Utc>,
+    ) -> Result<Option<IdempotencyKeyEntry>, Error> {
+        let reserve = sqlx::query(
+            r#"
+            INSERT INTO idempotency_keys (key, fingerprint, created_at)
+            VALUES ($1, $2, CURRENT_TIMESTAMP)
+            ON CONFLICT (key) DO UPDATE
+            SET fingerprint = EXCLUDED.fingerprint,
+                status = NULL,
+                content_type = NULL,
+                body = NULL,
+                created_at = EXCLUDED.created_at
+            WHERE idempotency_keys.created_at < $3
+            RETURNING key
+            "#,
+        )
+        .bind(key)
+        .bind(fingerprint.as_bytes())
+        .bind(expired_before);
 
-        let db = Database::new(Options {
-            database:                 SecretUrl::from_str(&url)?,
-            database_migrate:         true,
-            database_max_connections: 1,
-        })
-        .await?;
+        if self
+            .connection()
+            .await?
+            .fetch_optional(reserve)
+            .await?
+            .is_some()
+        {
+            return Ok(None);
+        }
 
-        Ok((db, db_container))
-    }
+        let query = sqlx::query(
+            r#"
+            SELECT fingerprint, status, content_type, body
+            FROM idempotency_keys
+            WHERE key = $1
+            "#,
+        )
+        .bind(key);
+
+        // A key released in the meantime is reported as in progress, the
+        // request can be retried with it.
+        let Some(row) = self.connection().await?.fetch_optional(query).await? else {
+            return Ok(Some(IdempotencyKeyEntry {
+                fingerprint: *fingerprint,
+                response:    None,
+            }));
+        };
 
-    fn mock_roots(n: usize) -> Vec<Field> {
-        (1..=n).map(Field::from).collect()
+        let response = row
+            .get::<Option<i16>, _>(1)
+            .map(|status| IdempotentResponse {
+                status:       status as u16,
+                content_type: row.get::<Option<String>, _>(2),
+                body:         row.get::<Option<Vec<u8>>, _>(3).unwrap_or_default(),
+            });
+
+        Ok(Some(IdempotencyKeyEntry {
+            fingerprint: H256::from_slice(&row.get::<Vec<u8>, _>(0)),
+            response,
+        }))
     }
 
-    fn mock_zero_roots(n: usize) -> Vec<Field> {
-        const ZERO_ROOT_OFFSET: usize = 10_000_000;
+    /// Stores the response to replay for a reserved key.
+    pub async fn complete_idempotency_key(
+        &self,
+        key: &str,
+        response: &IdempotentResponse,
+    ) -> Result<(), Error> {
+        let query = sqlx::query(
+            r#"
+            UPDATE idempotency_keys
+            SET status = $2, content_type = $3, body = $4
+            WHERE key = $1
+            "#,
+        )
+        .bind(key)
+        .bind(response.status as i16)
+        .bind(response.content_type.as_deref())
+        .bind(&response.body);
 
-        (1..=n)
+        self.connection().await?.execute(query).await?;
+        Ok(())
+    }
+
+    /// Releases a reserved key whose response wasn't stored.
+    pub async fn release_idempotency_key(&self, key: &str) -> Result<(), Error> {
+        let query = sqlx::query(
+            r#"
+            DELETE FROM idempotency_keys
+            WHERE key = $1 AND status IS NULL
+            "#,
+        )
+        .bind(key);
+
+        self.connection().await?.execute(query).await?;
+        Ok(())
+    }
+
+    /// Deletes the keys first used before `expired_before`, returns how many
+    /// were deleted.
+    pub async fn purge_idempotency_keys(
+        &self,
+        expired_before: DateTime<Utc>,
+    ) -> Result<u64, Error> {
+        let query = sqlx::query(
+            r#"
+            DELETE FROM idempotency_keys
+            WHERE created_at < $1
+            "#,
+        )
+        .bind(expired_before);
+
+        let result = self.connection().await?.execute(query).await?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn insert_new_recovery(
+        &self,
+        existing_commitment: &Hash,
+        new_commitment: &Hash,
+    ) -> Result<(), Error> {
+        let query = sqlx::query(
+            r#"
+            INSERT INTO recoveries (existing_commitment, new_commitment)
+            VALUES ($1, $2)
+            "#,
+        )
+        .bind(existing_commitment)
+        .bind(new_commitment);
+        self.connection().await?.execute(query).await?;
+        Ok(())
+    }
+
+    pub async fn get_latest_deletion(&self) -> Result<LatestDeletionEntry, Error> {
+        let query =
+            sqlx::query("SELECT deletion_timestamp FROM latest_deletion_root WHERE Lock = 'X';");
+
+        let row = self.connection().await?.fetch_optional(query).await?;
+
+        if let Some(row) = row {
+            Ok(LatestDeletionEntry {
+                timestamp: row.get(0),
+            })
+        } else {
+            Ok(LatestDeletionEntry {
+                timestamp: Utc::now(),
+            })
+        }
+    }
+
+    pub async fn update_latest_insertion_timestamp(
+        &self,
+        insertion_timestamp: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        let query = sqlx::query(
+            r#"
+            INSERT INTO latest_insertion_timestamp (Lock, insertion_timestamp)
+            VALUES ('X', $1)
+            ON CONFLICT (Lock)
+            DO UPDATE SET insertion_timestamp = EXCLUDED.insertion_timestamp;
+            "#,
+        )
+        .bind(insertion_timestamp);
+
+        self.connection().await?.execute(query).await?;
+        Ok(())
+    }
+
+    pub async fn update_latest_deletion(
+        &self,
+        deletion_timestamp: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        let query = sqlx::query(
+            r#"
+            INSERT INTO latest_deletion_root (Lock, deletion_timestamp)
+            VALUES ('X', $1)
+            ON CONFLICT (Lock)
+            DO UPDATE SET deletion_timestamp = EXCLUDED.deletion_timestamp;
+            "#,
+        )
+        .bind(deletion_timestamp);
+
+        self.connection().await?.execute(query).await?;
+        Ok(())
+    }
+
+    // TODO: consider using a larger value than i64 for leaf index, ruint should
+    // have postgres compatibility for u256
+    pub async fn get_recoveries(&self) -> Result<Vec<RecoveryEntry>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT *
+            FROM recoveries
+            "#,
+        );
+
+        let result = self.connection().await?.fetch_all(query).await?;
+
+        Ok(result
+            .into_iter()
+            .map(|row| RecoveryEntry {
+                existing_commitment: row.get::<Hash, _>(0),
+                new_commitment:      row.get::<Hash, _>(1),
+            })
+            .collect::<Vec<RecoveryEntry>>())
+    }
+
+    pub async fn insert_new_deletion(
+        &self,
+        leaf_index: usize,
+        identity: &Hash,
+    ) -> Result<(), Error> {
+        let query = sqlx::query(
+            r#"
+            INSERT INTO deletions (leaf_index, commitment)
+            VALUES ($1, $2)
+            "#,
+        )
+        .bind(leaf_index_to_db(leaf_index)?)
+        .bind(identity);
+
+        self.connection().await?.execute(query).await?;
+        Ok(())
+    }
+
+    // TODO: consider using a larger value than i64 for leaf index, ruint should
+    // have postgres compatibility for u256
+    pub async fn get_deletions(&self) -> Result<Vec<DeletionEntry>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT *
+            FROM deletions
+            "#,
+        );
+
+        let result = self.connection().await?.fetch_all(query).await?;
+
+        Ok(result
+            .into_iter()
+            .map(|row| DeletionEntry {
+                leaf_index: leaf_index_from_db(row.get::<i64, _>(0)),
+                commitment: row.get::<Hash, _>(1),
+            })
+            .collect::<Vec<DeletionEntry>>())
+    }
+
+    /// Remove a list of entries from the deletions table
+    pub async fn remove_deletions(&self, commitments: Vec<Hash>) -> Result<(), Error> {
+        let placeholders: String = commitments
+            .iter()
+            .enumerate()
+            .map(|(i, _)| format!("${}", i + 1))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        let query = format!(
+            "DELETE FROM deletions WHERE commitment IN ({})",
+            placeholders
+        );
+
+        let mut query = sqlx::query(&query);
+
+        for commitment in &commitments {
+            query = query.bind(commitment);
+        }
+
+        query.execute(&mut self.connection().await?).await?;
+
+        Ok(())
+    }
+
+    pub async fn insert_transaction(
+        &self,
+        transaction_id: &str,
+        pre_root: &Hash,
+        post_root: &Hash,
+        details: &BatchDetails,
+    ) -> Result<(), Error> {
+        let query = sqlx::query(
+            r#"
+            INSERT INTO transactions (
+                transaction_id,
+                pre_root,
+                post_root,
+                created_at,
+                kind,
+                first_leaf_index,
+                last_leaf_index,
+                identity_count,
+                prover_latency_ms,
+                calldata_size
+            )
+            VALUES ($1, $2, $3, CURRENT_TIMESTAMP, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (transaction_id) DO NOTHING
+            "#,
+        )
+        .bind(transaction_id)
+        .bind(pre_root)
+        .bind(post_root)
+        .bind(&details.kind)
+        .bind(*details.leaf_range.start() as i64)
+        .bind(*details.leaf_range.end() as i64)
+        .bind(details.identity_count as i64)
+        .bind(details.prover_latency.as_millis() as i64)
+        .bind(details.calldata_size as i64);
+
+        self.connection().await?.execute(query).await?;
+        Ok(())
+    }
+
+    /// Returns the reports of the latest `limit` batches handed to the signer,
+    /// newest first.
+    pub async fn get_batch_reports(&self, limit: i64) -> Result<Vec<BatchReport>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT t.transaction_id, t.pre_root, t.post_root, t.created_at,
+                   t.kind, t.first_leaf_index, t.last_leaf_index, t.identity_count,
+                   t.prover_latency_ms, t.calldata_size,
+                   r.transaction_hash, r.block_number, r.block_timestamp,
+                   c.gas_used
+            FROM transactions t
+            LEFT JOIN root_transactions r ON r.root = t.post_root
+            LEFT JOIN batch_costs c ON c.transaction_hash = r.transaction_hash
+            ORDER BY t.created_at DESC, t.transaction_id DESC
+            LIMIT $1
+            "#,
+        )
+        .bind(limit);
+
+        let rows = self.connection().await?.fetch_all(query).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| BatchReport {
+                transaction_id: row.get::<String, _>(0),
+                pre_root:       row.get::<Hash, _>(1),
+                post_root:      row.get::<Hash, _>(2),
+                submitted_at:   row.get::<_, _>(3),
+                details:        row.get::<Option<String>, _>(4).map(|kind| BatchDetails {
+                    kind,
+                    leaf_range: row.get::<i64, _>(5) as usize..=row.get::<i64, _>(6) as usize,
+                    identity_count: row.get::<i64, _>(7) as usize,
+                    prover_latency: Duration::from_millis(row.get::<i64, _>(8) as u64),
+                    calldata_size: row.get::<i64, _>(9) as usize,
+                }),
+                mined:          Self::read_mined_transaction(&row, 10),
+                gas_used:       row
+                    .get::<Option<i64>, _>(13)
+                    .map(|gas_used| gas_used as u64),
+            })
+            .collect())
+    }
+
+    /// Returns every batch handed to the signer, in the order they were
+    /// submitted.
+    pub async fn get_batch_submissions(&self) -> Result<Vec<BatchSubmissionEntry>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT t.transaction_id, t.pre_root, t.post_root,
+                   r.transaction_hash, r.block_number, r.block_timestamp
+            FROM transactions t
+            LEFT JOIN root_transactions r ON r.root = t.post_root
+            ORDER BY t.created_at ASC, t.transaction_id ASC
+            "#,
+        );
+
+        let rows = self.connection().await?.fetch_all(query).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| BatchSubmissionEntry {
+                transaction_id: row.get::<String, _>(0),
+                pre_root:       row.get::<Hash, _>(1),
+                post_root:      row.get::<Hash, _>(2),
+                mined:          Self::read_mined_transaction(&row, 3),
+            })
+            .collect())
+    }
+
+    /// Records a batch which reverted in simulation. A batch which is
+    /// quarantined again keeps its first calldata, its failures are counted.
+    /// An `isolated` batch, a leaf isolated from a reverting batch, holds
+    /// batch submission until it's retried or discarded.
+    pub async fn insert_quarantined_batch(
+        &self,
+        kind: &str,
+        pre_root: &Hash,
+        post_root: &Hash,
+        reason: &str,
+        calldata: &[u8],
+        isolated: bool,
+    ) -> Result<(), Error> {
+        let query = sqlx::query(
+            r#"
+            INSERT INTO quarantined_batches
+                (pre_root, post_root, kind, reason, calldata, isolated, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, CURRENT_TIMESTAMP)
+            ON CONFLICT (pre_root, post_root) DO UPDATE
+            SET reason = EXCLUDED.reason,
+                attempts = quarantined_batches.attempts + 1,
+                isolated = quarantined_batches.isolated OR EXCLUDED.isolated,
+                last_failed_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(pre_root)
+        .bind(post_root)
+        .bind(kind)
+        .bind(reason)
+        .bind(calldata)
+        .bind(isolated);
+
+        self.connection().await?.execute(query).await?;
+        Ok(())
+    }
+
+    /// Returns the quarantined batches, oldest first.
+    pub async fn get_failed_batches(&self) -> Result<Vec<FailedBatch>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT pre_root, post_root, kind, reason, calldata, attempts, created_at, last_failed_at,
+                   isolated
+            FROM quarantined_batches
+            ORDER BY created_at ASC
+            "#,
+        );
+
+        let rows = self.connection().await?.fetch_all(query).await?;
+
+        Ok(rows.iter().map(Self::failed_batch_from_row).collect())
+    }
+
+    pub async fn get_failed_batch(&self, post_root: &Hash) -> Result<Option<FailedBatch>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT pre_root, post_root, kind, reason, calldata, attempts, created_at, last_failed_at,
+                   isolated
+            FROM quarantined_batches
+            WHERE post_root = $1
+            "#,
+        )
+        .bind(post_root);
+
+        let row = self.connection().await?.fetch_optional(query).await?;
+
+        Ok(row.as_ref().map(Self::failed_batch_from_row))
+    }
+
+    fn failed_batch_from_row(row: &PgRow) -> FailedBatch {
+        FailedBatch {
+            pre_root:       row.get::<Hash, _>(0),
+            post_root:      row.get::<Hash, _>(1),
+            kind:           row.get::<String, _>(2),
+            reason:         row.get::<String, _>(3),
+            calldata:       row.get::<Vec<u8>, _>(4),
+            attempts:       row.get::<i32, _>(5) as u32,
+            created_at:     row.get::<_, _>(6),
+            last_failed_at: row.get::<_, _>(7),
+            isolated:       row.get::<bool, _>(8),
+        }
+    }
+
+    /// Whether any quarantined batch is an isolated leaf, or failed at least
+    /// `max_attempts` times if given.
+    pub async fn has_held_batches(&self, max_attempts: Option<u32>) -> Result<bool, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM quarantined_batches WHERE isolated OR attempts >= $1
+            )
+            "#,
+        )
+        .bind(max_attempts.map(i64::from));
+
+        let row = self.connection().await?.fetch_one(query).await?;
+
+        Ok(row.get::<bool, _>(0))
+    }
+
+    /// Resets the failures of a quarantined batch, and releases it if it's an
+    /// isolated leaf, so that it's submitted again. Returns whether the batch
+    /// was quarantined.
+    pub async fn reset_failed_batch_attempts(&self, post_root: &Hash) -> Result<bool, Error> {
+        let query = sqlx::query(
+            r#"
+            UPDATE quarantined_batches
+            SET attempts = 0,
+                isolated = FALSE
+            WHERE post_root = $1
+            "#,
+        )
+        .bind(post_root);
+
+        let result = self.connection().await?.execute(query).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Forgets a quarantined batch. Returns whether the batch was quarantined.
+    pub async fn delete_failed_batch(&self, post_root: &Hash) -> Result<bool, Error> {
+        let query = sqlx::query(
+            r#"
+            DELETE FROM quarantined_batches
+            WHERE post_root = $1
+            "#,
+        )
+        .bind(post_root);
+
+        let result = self.connection().await?.execute(query).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns the pending identities in insertion order, along with the batch
+    /// they were submitted in if any.
+    ///
+    /// A batch contains all identities up to and including the row holding
+    /// its post root, so each identity belongs to the first submitted batch at
+    /// or after it.
+    pub async fn get_pending_queue(&self, limit: i64) -> Result<Vec<QueuedIdentityEntry>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT i.leaf_index, i.commitment, i.pending_as_of, b.post_root, b.transaction_id,
+                   m.metadata
+            FROM identities i
+            LEFT JOIN identity_metadata m ON m.commitment = i.commitment
+            LEFT JOIN LATERAL (
+                SELECT t.post_root, t.transaction_id
+                FROM transactions t
+                JOIN identities r ON r.root = t.post_root
+                WHERE r.id >= i.id
+                ORDER BY r.id ASC
+                LIMIT 1
+            ) b ON true
+            WHERE i.status = $1
+            ORDER BY i.id ASC
+            LIMIT $2
+            "#,
+        )
+        .bind(<&str>::from(ProcessedStatus::Pending))
+        .bind(limit);
+
+        let rows = self.connection().await?.fetch_all(query).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| QueuedIdentityEntry {
+                leaf_index:     leaf_index_from_db(row.get::<i64, _>(0)),
+                commitment:     row.get::<Hash, _>(1),
+                pending_as_of:  row.get::<_, _>(2),
+                batch_root:     row.get::<Option<Hash>, _>(3),
+                transaction_id: row.get::<Option<String>, _>(4),
+                metadata:       row.get::<Option<String>, _>(5),
+            })
+            .collect())
+    }
+
+    /// Counts the pending identities, split by whether they have been
+    /// submitted on chain in a batch or not.
+    pub async fn count_pending_identities_by_state(&self) -> Result<(usize, usize), Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT
+                COUNT(*) FILTER (WHERE b.max_id IS NULL OR i.id > b.max_id),
+                COUNT(*) FILTER (WHERE i.id <= b.max_id)
+            FROM identities i, (
+                SELECT MAX(r.id) AS max_id
+                FROM identities r
+                JOIN transactions t ON t.post_root = r.root
+                WHERE r.status = $1
+            ) b
+            WHERE i.status = $1
+            "#,
+        )
+        .bind(<&str>::from(ProcessedStatus::Pending));
+
+        let row = self.connection().await?.fetch_one(query).await?;
+
+        Ok((row.get::<i64, _>(0) as usize, row.get::<i64, _>(1) as usize))
+    }
+
+    /// Returns the number of identities ahead of the commitment waiting to be
+    /// mined, `None` if it isn't waiting. Queued identities are behind every
+    /// identity in the tree.
+    pub async fn get_queue_position(&self, commitment: &Hash) -> Result<Option<u64>, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT CASE
+                WHEN own.queue_position IS NOT NULL THEN (
+                    SELECT COUNT(*)
+                    FROM unprocessed_identities u
+                    WHERE u.status = $2 AND u.queue_position < own.queue_position
+                ) + (
+                    SELECT COUNT(*)
+                    FROM identities i
+                    WHERE i.status = $3
+                )
+                ELSE (
+                    SELECT COUNT(*)
+                    FROM identities i
+                    WHERE i.status = $3 AND i.leaf_index < own.leaf_index
+                )
+            END
+            FROM (
+                SELECT u.queue_position, NULL::BIGINT AS leaf_index
+                FROM unprocessed_identities u
+                WHERE u.commitment = $1 AND u.status = $2
+                UNION ALL
+                SELECT NULL, i.leaf_index
+                FROM identities i
+                WHERE i.commitment = $1 AND i.status = $3
+            ) own
+            ORDER BY own.leaf_index IS NULL
+            LIMIT 1
+            "#,
+        )
+        .bind(commitment)
+        .bind(<&str>::from(UnprocessedStatus::New))
+        .bind(<&str>::from(ProcessedStatus::Pending));
+
+        let row = self.connection().await?.fetch_optional(query).await?;
+
+        Ok(row.map(|row| row.get::<i64, _>(0) as u64))
+    }
+
+    /// Returns the insertions mined since `since`, and how long they took
+    /// from their latest submission.
+    pub async fn get_insertion_latency(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<InsertionLatency, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT
+                COUNT(*),
+                EXTRACT(EPOCH FROM AVG(mined.created_at - received.created_at))::FLOAT8,
+                (
+                    SELECT COUNT(*)
+                    FROM batch_costs
+                    WHERE kind = $4 AND block_timestamp >= $1
+                )
+            FROM identity_events mined
+            JOIN LATERAL (
+                SELECT r.created_at
+                FROM identity_events r
+                WHERE r.commitment = mined.commitment AND r.event = $2 AND r.id < mined.id
+                ORDER BY r.id DESC
+                LIMIT 1
+            ) received ON TRUE
+            WHERE mined.event = $3 AND mined.created_at >= $1
+            "#,
+        )
+        .bind(since)
+        .bind(<&str>::from(IdentityEvent::Received))
+        .bind(<&str>::from(IdentityEvent::Mined))
+        .bind(<&str>::from(TreeChangeKind::Insertion));
+
+        let row = self.connection().await?.fetch_one(query).await?;
+
+        Ok(InsertionLatency {
+            mined_identities: row.get::<i64, _>(0) as u64,
+            average_seconds:  row.get::<Option<f64>, _>(1),
+            mined_batches:    row.get::<i64, _>(2) as u64,
+        })
+    }
+
+    pub async fn get_unprocessed_commitments(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<types::UnprocessedCommitment>, Error> {
+        let query = sqlx::query(
+            r#"
+                SELECT u.commitment, u.status, u.created_at, u.processed_at, u.error_message,
+                    u.eligibility, m.metadata, u.queue_position
+                FROM unprocessed_identities u
+                LEFT JOIN identity_metadata m ON m.commitment = u.commitment
+                ORDER BY u.queue_position ASC
+                LIMIT $1
+            "#,
+        )
+        .bind(limit);
+
+        let result = self.connection().await?.fetch_all(query).await?;
+
+        Ok(result
+            .into_iter()
+            .map(|row| types::UnprocessedCommitment {
+                commitment:            row.get::<Hash, _>(0),
+                status:                row
+                    .get::<&str, _>(1)
+                    .parse()
+                    .expect("Failed to parse unprocessed status"),
+                created_at:            row.get::<_, _>(2),
+                processed_at:          row.get::<_, _>(3),
+                error_message:         row.get::<_, _>(4),
+                eligibility_timestamp: row.get::<_, _>(5),
+                metadata:              row.get::<_, _>(6),
+                queue_position:        row.get::<i64, _>(7) as u64,
+            })
+            .collect::<Vec<_>>())
+    }
+
+    /// Counts the unprocessed identities by status, separating out the ones
+    /// not yet eligible for processing.
+    pub async fn count_unprocessed_identities_by_state(
+        &self,
+    ) -> Result<UnprocessedIdentityCounts, Error> {
+        let query = sqlx::query(
+            r#"
+            SELECT
+                COUNT(*) FILTER (WHERE status = $1 AND CURRENT_TIMESTAMP > eligibility),
+                COUNT(*) FILTER (WHERE status = $1 AND CURRENT_TIMESTAMP <= eligibility),
+                COUNT(*) FILTER (WHERE status = $2)
+            FROM unprocessed_identities
+            "#,
+        )
+        .bind(<&str>::from(UnprocessedStatus::New))
+        .bind(<&str>::from(UnprocessedStatus::Failed));
+
+        let row = self.connection().await?.fetch_one(query).await?;
+
+        Ok(UnprocessedIdentityCounts {
+            eligible:  row.get::<i64, _>(0) as usize,
+            held_back: row.get::<i64, _>(1) as usize,
+            failed:    row.get::<i64, _>(2) as usize,
+        })
+    }
+
+    pub async fn get_eligible_unprocessed_commitments(
+        &self,
+        status: UnprocessedStatus,
+    ) -> Result<Vec<types::UnprocessedCommitment>, Error> {
+        let query = sqlx::query(
+            r#"
+                SELECT u.commitment, u.status, u.created_at, u.processed_at, u.error_message,
+                    u.eligibility, m.metadata, u.queue_position
+                FROM unprocessed_identities u
+                LEFT JOIN identity_metadata m ON m.commitment = u.commitment
+                WHERE u.status = $1 AND CURRENT_TIMESTAMP > u.eligibility
+                ORDER BY u.queue_position ASC
+                LIMIT $2
+            "#,
+        )
+        .bind(<&str>::from(status))
+        .bind(MAX_UNPROCESSED_FETCH_COUNT);
+
+        let result = self.connection().await?.fetch_all(query).await?;
+
+        Ok(result
+            .into_iter()
+            .map(|row| types::UnprocessedCommitment {
+                commitment: row.get::<Hash, _>(0),
+                status,
+                created_at: row.get::<_, _>(2),
+                processed_at: row.get::<_, _>(3),
+                error_message: row.get::<_, _>(4),
+                eligibility_timestamp: row.get::<_, _>(5),
+                metadata: row.get::<_, _>(6),
+                queue_position: row.get::<i64, _>(7) as u64,
+            })
+            .collect::<Vec<_>>())
+    }
+
+    pub async fn get_unprocessed_commit_status(
+        &self,
+        commitment: &Hash,
+    ) -> Result<Option<(UnprocessedStatus, String)>, Error> {
+        let query = sqlx::query(
+            r#"
+                SELECT status, error_message FROM unprocessed_identities WHERE commitment = $1
+            "#,
+        )
+        .bind(commitment);
+
+        let result = self.connection().await?.fetch_optional(query).await?;
+
+        if let Some(row) = result {
+            return Ok(Some((
+                row.get::<&str, _>(0).parse().expect("couldn't read status"),
+                row.get::<Option<String>, _>(1).unwrap_or_default(),
+            )));
+        };
+        Ok(None)
+    }
+
+    pub async fn remove_unprocessed_identity(&self, commitment: &Hash) -> Result<(), Error> {
+        let query = sqlx::query(
+            r#"
+                DELETE FROM unprocessed_identities WHERE commitment = $1
+            "#,
+        )
+        .bind(commitment);
+
+        self.connection().await?.execute(query).await?;
+
+        Ok(())
+    }
+
+    /// Marks commitments which have been eligible for insertion for longer
+    /// than `max_age` without being inserted as failed, so that they can be
+    /// submitted again. Returns the expired commitments.
+    pub async fn expire_unprocessed_identities(
+        &self,
+        max_age: chrono::Duration,
+    ) -> Result<Vec<Hash>, Error> {
+        let mut tx = self.begin().await?;
+
+        let query = sqlx::query(
+            r#"
+            SELECT commitment
+            FROM   unprocessed_identities
+            WHERE  status = $1 AND eligibility < $2
+            ORDER  BY queue_position
+            FOR UPDATE SKIP LOCKED
+            "#,
+        )
+        .bind(<&str>::from(UnprocessedStatus::New))
+        .bind(Utc::now() - max_age);
+
+        let expired: Vec<Hash> = tx
+            .fetch_all(query)
+            .await?
+            .into_iter()
+            .map(|row| row.get::<Hash, _>(0))
+            .collect();
+
+        let message = format!(
+            "Expired after waiting for more than {} seconds.",
+            max_age.num_seconds()
+        );
+
+        for commitment in &expired {
+            let query = sqlx::query(
+                r#"
+                UPDATE unprocessed_identities SET error_message = $1, status = $2
+                WHERE commitment = $3
+                "#,
+            )
+            .bind(&message)
+            .bind(<&str>::from(UnprocessedStatus::Failed))
+            .bind(commitment);
+
+            tx.execute(query).await?;
+            Self::append_event(&mut tx, commitment, IdentityEvent::Failed, Some(&message)).await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(expired)
+    }
+
+    pub async fn update_err_unprocessed_commitment(
+        &self,
+        commitment: Hash,
+        message: String,
+    ) -> Result<(), Error> {
+        let mut tx = self.begin().await?;
+
+        let query = sqlx::query(
+            r#"
+                UPDATE unprocessed_identities SET error_message = $1, status = $2
+                WHERE commitment = $3
+            "#,
+        )
+        .bind(&message)
+        .bind(<&str>::from(UnprocessedStatus::Failed))
+        .bind(commitment);
+
+        tx.execute(query).await?;
+        Self::append_event(&mut tx, &commitment, IdentityEvent::Failed, Some(&message)).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn identity_exists(&self, commitment: Hash) -> Result<bool, Error> {
+        let query_unprocessed_identity = sqlx::query(
+            r#"SELECT exists(SELECT 1 FROM unprocessed_identities where commitment = $1)"#,
+        )
+        .bind(commitment);
+
+        let row_unprocessed = self
+            .connection()
+            .await?
+            .fetch_one(query_unprocessed_identity)
+            .await?;
+
+        let query_processed_identity =
+            sqlx::query(r#"SELECT exists(SELECT 1 FROM identities where commitment = $1)"#)
+                .bind(commitment);
+
+        let row_processed = self
+            .connection()
+            .await?
+            .fetch_one(query_processed_identity)
+            .await?;
+
+        // The updates of deleted identities may have been pruned, their
+        // events are kept so that they can't be inserted again.
+        let query_deleted_identity = sqlx::query(
+            r#"SELECT exists(SELECT 1 FROM identity_events where commitment = $1 AND event = $2)"#,
+        )
+        .bind(commitment)
+        .bind(<&str>::from(IdentityEvent::Deleted));
+
+        let row_deleted = self
+            .connection()
+            .await?
+            .fetch_one(query_deleted_identity)
+            .await?;
+
+        let exists = row_unprocessed.get::<bool, _>(0)
+            || row_processed.get::<bool, _>(0)
+            || row_deleted.get::<bool, _>(0);
+
+        Ok(exists)
+    }
+
+    // TODO: add docs
+    pub async fn identity_is_queued_for_deletion(&self, commitment: &Hash) -> Result<bool, Error> {
+        let query_queued_deletion =
+            sqlx::query(r#"SELECT exists(SELECT 1 FROM deletions where commitment = $1)"#)
+                .bind(commitment);
+        let row_unprocessed = self
+            .connection()
+            .await?
+            .fetch_one(query_queued_deletion)
+            .await?;
+        Ok(row_unprocessed.get::<bool, _>(0))
+    }
+}
+
+/// Leaf indices are stored as `BIGINT`, which covers trees up to depth 63.
+fn leaf_index_to_db(leaf_index: usize) -> Result<i64, Error> {
+    i64::try_from(leaf_index).map_err(|_| Error::LeafIndexOutOfRange {
+        leaf_index: leaf_index as u64,
+    })
+}
+
+fn leaf_index_from_db(leaf_index: i64) -> usize {
+    usize::try_from(leaf_index).expect("Leaf index is out of range, database is corrupt")
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("database error: {0}")]
+    InternalError(#[from] sqlx::Error),
+
+    #[error("Tried to mine missing root {root:?}")]
+    MissingRoot { root: Hash },
+
+    #[error("Leaf index {leaf_index} cannot be stored in the database")]
+    LeafIndexOutOfRange { leaf_index: u64 },
+
+    #[error("Expected to roll back {expected} pending identities, found {pending}")]
+    RollBackMismatch { expected: usize, pending: usize },
+
+    #[error("The tree is full, all {capacity} leaves are used")]
+    TreeFull { capacity: usize },
+
+    #[error("The commitment is already queued")]
+    DuplicateCommitment,
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashMap, HashSet};
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use anyhow::Context;
+    use chrono::{Days, TimeZone, Timelike, Utc};
+    use ethers::types::{Address, H256, U256};
+    use postgres_docker_utils::DockerContainerGuard;
+    use ruint::Uint;
+    use semaphore::Field;
+
+    use super::types::{
+        BatchCost, IdempotentResponse, IdentityDeletion, InsertionLatency, MinedLeafEntry,
+        NewInsertion, RootHistoryEntry,
+    };
+    use super::{Database, Error, Options};
+    use crate::ethereum::signing_log::{SigningOutcome, SigningPurpose, SigningRecord};
+    use crate::identity_tree::{
+        Hash, IdentityEvent, MinedTransaction, ProcessedStatus, Status, TreeUpdate,
+        UnprocessedStatus,
+    };
+    use crate::insertion_screening::{ScreeningDecision, ScreeningSubject};
+    use crate::prover::{ProverConfiguration, ProverType};
+    use crate::secret::SecretUrl;
+    use crate::utils::retry::RetryPolicy;
+
+    macro_rules! assert_same_time {
+        ($a:expr, $b:expr, $diff:expr) => {
+            assert!(
+                abs_duration($a - $b) < $diff,
+                "Difference between {} and {} is larger than {:?}",
+                $a,
+                $b,
+                $diff
+            );
+        };
+
+        ($a:expr, $b:expr) => {
+            assert_same_time!($a, $b, chrono::Duration::milliseconds(500));
+        };
+    }
+
+    fn abs_duration(x: chrono::Duration) -> chrono::Duration {
+        chrono::Duration::milliseconds(x.num_milliseconds().abs())
+    }
+
+    /// Leaves of the tree in tests which don't run out of them.
+    const CAPACITY: usize = 1 << 20;
+
+    // TODO: we should probably consolidate all tests that propagate errors to
+    // TODO: either use anyhow or eyre
+    async fn setup_db() -> anyhow::Result<(Database, DockerContainerGuard)> {
+        setup_db_with_max_connections(1).await
+    }
+
+    async fn setup_db_with_max_connections(
+        database_max_connections: u32,
+    ) -> anyhow::Result<(Database, DockerContainerGuard)> {
+        let db_container = postgres_docker_utils::setup().await?;
+        let db_socket_addr = db_container.address();
+        let url = format!("postgres://postgres:postgres@{db_socket_addr}/database");
+
+        let db = Database::new(
+            Options {
+                database: SecretUrl::from_str(&url)?,
+                database_migrate: true,
+                database_max_connections,
+            },
+            RetryPolicy::default(),
+        )
+        .await?;
+
+        Ok((db, db_container))
+    }
+
+    /// Connects to the database of `db_container` again, with nothing cached.
+    async fn reconnect(db_container: &DockerContainerGuard) -> anyhow::Result<Database> {
+        let url = format!(
+            "postgres://postgres:postgres@{}/database",
+            db_container.address()
+        );
+
+        Database::new(
+            Options {
+                database:                 SecretUrl::from_str(&url)?,
+                database_migrate:         false,
+                database_max_connections: 1,
+            },
+            RetryPolicy::default(),
+        )
+        .await
+    }
+
+    fn mock_roots(n: usize) -> Vec<Field> {
+        (1..=n).map(Field::from).collect()
+    }
+
+    fn mock_batch_details(leaf_range: std::ops::RangeInclusive<usize>) -> BatchDetails {
+        BatchDetails {
+            kind: "insertion".to_owned(),
+            identity_count: leaf_range.clone().count(),
+            leaf_range,
+            prover_latency: Duration::from_millis(1500),
+            calldata_size: 1000,
+        }
+    }
+
+    fn mock_zero_roots(n: usize) -> Vec<Field> {
+        const ZERO_ROOT_OFFSET: usize = 10_000_000;
+
+        (1..=n)
             .map(|n| ZERO_ROOT_OFFSET - n)
             .map(Field::from)
             .collect()
     }
 
-    fn mock_identities(n: usize) -> Vec<Field> {
-        (1..=n).map(Field::from).collect()
+    fn mock_identities(n: usize) -> Vec<Field> {
+        (1..=n).map(Field::from).collect()
+    }
+
+    async fn assert_roots_are(
+        db: &Database,
+        roots: impl IntoIterator<Item = &Field>,
+        expected_state: ProcessedStatus,
+    ) -> anyhow::Result<()> {
+        for root in roots {
+            let root = db
+                .get_root_state(root)
+                .await?
+                .context("Fetching root state")?;
+
+            assert_eq!(root.status, expected_state,);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn insert_identity() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+        let dec = "1234500000000000000";
+        let commit_hash: Hash = U256::from_dec_str(dec)
+            .expect("cant convert to u256")
+            .into();
+
+        let eligibility_timestamp = Utc::now();
+
+        let hash = db
+            .insert_new_identity(commit_hash, eligibility_timestamp)
+            .await?;
+
+        assert_eq!(commit_hash, hash);
+
+        let commit = db
+            .get_unprocessed_commit_status(&commit_hash)
+            .await?
+            .expect("expected commitment status");
+        assert_eq!(commit.0, UnprocessedStatus::New);
+
+        let identity_count = db
+            .get_eligible_unprocessed_commitments(UnprocessedStatus::New)
+            .await?
+            .len();
+
+        assert_eq!(identity_count, 1);
+
+        assert!(db.remove_unprocessed_identity(&commit_hash).await.is_ok());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn insert_and_delete_identity() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let zero: Hash = U256::zero().into();
+        let zero_root: Hash = U256::from_dec_str("6789")?.into();
+        let root: Hash = U256::from_dec_str("54321")?.into();
+        let commitment: Hash = U256::from_dec_str("12345")?.into();
+
+        db.insert_pending_identity(0, &commitment, &root).await?;
+        db.insert_pending_identity(0, &zero, &zero_root).await?;
+
+        let leaf_index = db
+            .get_identity_leaf_index(&commitment)
+            .await?
+            .context("Missing identity")?;
+
+        assert_eq!(leaf_index.leaf_index, 0);
+
+        Ok(())
+    }
+
+    fn mock_provers() -> HashSet<ProverConfiguration> {
+        let mut provers = HashSet::new();
+
+        provers.insert(ProverConfiguration {
+            batch_size:  100,
+            url:         "http://localhost:8080".to_string(),
+            timeout_s:   100,
+            prover_type: ProverType::Insertion,
+            key_digest:  None,
+        });
+
+        provers.insert(ProverConfiguration {
+            batch_size:  100,
+            url:         "http://localhost:8080".to_string(),
+            timeout_s:   100,
+            prover_type: ProverType::Deletion,
+            key_digest:  None,
+        });
+
+        provers
+    }
+
+    #[tokio::test]
+    async fn test_insert_prover_configuration() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let mock_prover_configuration_0 = ProverConfiguration {
+            batch_size:  100,
+            url:         "http://localhost:8080".to_string(),
+            timeout_s:   100,
+            prover_type: ProverType::Insertion,
+            key_digest:  None,
+        };
+
+        let mock_prover_configuration_1 = ProverConfiguration {
+            batch_size:  100,
+            url:         "http://localhost:8081".to_string(),
+            timeout_s:   100,
+            prover_type: ProverType::Deletion,
+            key_digest:  None,
+        };
+
+        db.insert_prover_configuration(
+            mock_prover_configuration_0.batch_size,
+            mock_prover_configuration_0.url.clone(),
+            mock_prover_configuration_0.timeout_s,
+            mock_prover_configuration_0.prover_type,
+            mock_prover_configuration_0.key_digest,
+        )
+        .await?;
+
+        db.insert_prover_configuration(
+            mock_prover_configuration_1.batch_size,
+            mock_prover_configuration_1.url.clone(),
+            mock_prover_configuration_1.timeout_s,
+            mock_prover_configuration_1.prover_type,
+            mock_prover_configuration_1.key_digest,
+        )
+        .await?;
+
+        let provers = db.get_provers().await?;
+
+        assert!(provers.contains(&mock_prover_configuration_0));
+        assert!(provers.contains(&mock_prover_configuration_1));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_insert_provers() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+        let mock_provers = mock_provers();
+
+        db.insert_provers(mock_provers.clone()).await?;
+
+        let provers = db.get_provers().await?;
+
+        assert_eq!(provers, mock_provers);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_prover_key_digests() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let checked = ProverConfiguration {
+            batch_size:  10,
+            url:         "http://localhost:8080".to_string(),
+            timeout_s:   100,
+            prover_type: ProverType::Insertion,
+            key_digest:  Some(H256::repeat_byte(0x6b)),
+        };
+        let unchecked = ProverConfiguration {
+            batch_size: 20,
+            key_digest: None,
+            ..checked.clone()
+        };
+
+        db.insert_provers(HashSet::from([checked.clone()])).await?;
+        db.insert_prover_configuration(
+            unchecked.batch_size,
+            &unchecked.url,
+            unchecked.timeout_s,
+            unchecked.prover_type,
+            unchecked.key_digest,
+        )
+        .await?;
+
+        let provers = db.get_provers().await?;
+        assert_eq!(
+            provers.get(&checked).and_then(|prover| prover.key_digest),
+            checked.key_digest
+        );
+        assert_eq!(
+            provers.get(&unchecked).map(|prover| prover.key_digest),
+            Some(None)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove_prover() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+        let mock_provers = mock_provers();
+
+        db.insert_provers(mock_provers.clone()).await?;
+        db.remove_prover(100, ProverType::Insertion).await?;
+        let provers = db.get_provers().await?;
+
+        assert_eq!(provers, HashSet::new());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_insert_new_recovery() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let existing_commitment: Uint<256, 4> = Uint::from(1);
+        let new_commitment: Uint<256, 4> = Uint::from(2);
+
+        db.insert_new_recovery(&existing_commitment, &new_commitment)
+            .await?;
+
+        let recoveries = db.get_recoveries().await?;
+
+        assert_eq!(recoveries.len(), 1);
+        assert_eq!(recoveries[0].existing_commitment, existing_commitment);
+        assert_eq!(recoveries[0].new_commitment, new_commitment);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_keys() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let fingerprint = H256::repeat_byte(1);
+        let an_hour_ago = Utc::now() - chrono::Duration::hours(1);
+
+        assert!(db
+            .reserve_idempotency_key("key", &fingerprint, an_hour_ago)
+            .await?
+            .is_none());
+
+        // Reserved but not completed yet
+        let entry = db
+            .reserve_idempotency_key("key", &H256::repeat_byte(2), an_hour_ago)
+            .await?
+            .context("Missing reservation")?;
+        assert_eq!(entry.fingerprint, fingerprint);
+        assert!(entry.response.is_none());
+
+        let response = IdempotentResponse {
+            status:       202,
+            content_type: Some("application/json".to_string()),
+            body:         b"{}".to_vec(),
+        };
+        db.complete_idempotency_key("key", &response).await?;
+
+        // Completed keys aren't released
+        db.release_idempotency_key("key").await?;
+        let entry = db
+            .reserve_idempotency_key("key", &fingerprint, an_hour_ago)
+            .await?
+            .context("Missing reservation")?;
+        assert_eq!(entry.response, Some(response));
+
+        // Expired keys are taken over
+        assert!(db
+            .reserve_idempotency_key("key", &fingerprint, Utc::now())
+            .await?
+            .is_none());
+
+        db.release_idempotency_key("key").await?;
+        assert!(db
+            .reserve_idempotency_key("key", &fingerprint, an_hour_ago)
+            .await?
+            .is_none());
+
+        assert_eq!(db.purge_idempotency_keys(an_hour_ago).await?, 0);
+        assert_eq!(db.purge_idempotency_keys(Utc::now()).await?, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_insert_insertion_receipt() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let identities = mock_identities(2);
+        let roots = mock_roots(2);
+        let commitment = identities[1];
+        let created_at = Utc::now().with_nanosecond(0).unwrap();
+
+        assert!(db.get_insertion_receipt(&commitment).await?.is_none());
+
+        let sequence_number = db.insert_insertion_receipt(&commitment, created_at).await?;
+
+        let receipt = db
+            .get_insertion_receipt(&commitment)
+            .await?
+            .context("Missing receipt")?;
+
+        assert_eq!(receipt.commitment, commitment);
+        assert_eq!(receipt.leaf_index, None);
+        assert_eq!(receipt.pre_root, None);
+        assert_eq!(receipt.created_at, created_at);
+        assert_eq!(receipt.sequence_number, sequence_number);
+
+        // Resubmissions keep their number, later insertions get higher ones.
+        assert_eq!(
+            db.insert_insertion_receipt(&commitment, Utc::now()).await?,
+            sequence_number
+        );
+        assert!(
+            db.insert_insertion_receipt(&Uint::from(3), Utc::now())
+                .await?
+                > sequence_number
+        );
+
+        // The leaf is applied to the root left by the update before it.
+        db.insert_pending_identity(0, &identities[0], &roots[0])
+            .await?;
+        db.insert_pending_identity(1, &commitment, &roots[1])
+            .await?;
+
+        let receipt = db
+            .get_insertion_receipt(&commitment)
+            .await?
+            .context("Missing receipt")?;
+
+        assert_eq!(receipt.leaf_index, Some(1));
+        assert_eq!(receipt.pre_root, Some(roots[0]));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_insert_new_identities() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let identities = mock_identities(3);
+        let signer = Address::repeat_byte(0x42);
+        let insertion = |commitment: Hash| NewInsertion {
+            commitment,
+            metadata: None,
+            signer: None,
+            created_at: Utc::now(),
+        };
+
+        // A resubmission keeps its sequence number.
+        let resubmitted = db
+            .insert_insertion_receipt(&identities[1], Utc::now())
+            .await?;
+
+        let sequence_numbers = db
+            .insert_new_identities(
+                &[
+                    NewInsertion {
+                        metadata: Some("partner".to_owned()),
+                        signer: Some(signer),
+                        ..insertion(identities[0])
+                    },
+                    insertion(identities[1]),
+                    insertion(identities[2]),
+                ],
+                CAPACITY,
+            )
+            .await?;
+
+        assert_eq!(sequence_numbers.len(), 3);
+        assert_eq!(sequence_numbers[1], resubmitted);
+        assert!(sequence_numbers[0] > resubmitted);
+        assert!(sequence_numbers[2] > sequence_numbers[0]);
+
+        // Queued in the order of their sequence numbers.
+        let queued = db.get_unprocessed_commitments(10).await?;
+        assert_eq!(
+            queued
+                .iter()
+                .map(|entry| entry.commitment)
+                .collect::<Vec<_>>(),
+            [identities[1], identities[0], identities[2]]
+        );
+        assert!(queued
+            .iter()
+            .zip([
+                sequence_numbers[1],
+                sequence_numbers[0],
+                sequence_numbers[2]
+            ])
+            .all(|(entry, sequence_number)| entry.queue_position == sequence_number));
+
+        assert_eq!(
+            db.get_identity_metadata(&identities[0]).await?.as_deref(),
+            Some("partner")
+        );
+        assert_eq!(db.get_insertion_signer(&identities[0]).await?, Some(signer));
+        assert_eq!(db.get_identity_events(&identities[2]).await?.len(), 1);
+
+        // Nothing is queued if any of the insertions conflicts.
+        assert!(db
+            .insert_new_identities(
+                &[insertion(Hash::from(42)), insertion(identities[0])],
+                CAPACITY
+            )
+            .await
+            .is_err());
+        assert!(!db.identity_exists(Hash::from(42)).await?);
+
+        Ok(())
     }
 
-    async fn assert_roots_are(
-        db: &Database,
-        roots: impl IntoIterator<Item = &Field>,
-        expected_state: ProcessedStatus,
-    ) -> anyhow::Result<()> {
-        for root in roots {
-            let root = db
-                .get_root_state(root)
-                .await?
-                .context("Fetching root state")?;
+    #[tokio::test]
+    async fn test_insert_new_identities_past_capacity() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
 
-            assert_eq!(root.status, expected_state,);
+        let identities = mock_identities(4);
+        let insertion = |commitment: Hash| NewInsertion {
+            commitment,
+            metadata: None,
+            signer: None,
+            created_at: Utc::now(),
+        };
+
+        // One leaf is assigned and one identity queued.
+        db.insert_pending_identity(0, &identities[0], &Hash::from(1))
+            .await?;
+        db.insert_new_identities(&[insertion(identities[1])], 4)
+            .await?;
+
+        // Two more don't fit into three leaves, but do into four.
+        assert!(matches!(
+            db.insert_new_identities(&[insertion(identities[2]), insertion(identities[3])], 3)
+                .await,
+            Err(Error::TreeFull { capacity: 3 })
+        ));
+        assert!(!db.identity_exists(identities[2]).await?);
+
+        db.insert_new_identities(&[insertion(identities[2]), insertion(identities[3])], 4)
+            .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_queue_order_with_concurrent_replicas() -> anyhow::Result<()> {
+        let (db, db_container) = setup_db_with_max_connections(4).await?;
+        let url = format!(
+            "postgres://postgres:postgres@{}/database",
+            db_container.address()
+        );
+        let replica = Database::new(
+            Options {
+                database:                 SecretUrl::from_str(&url)?,
+                database_migrate:         false,
+                database_max_connections: 4,
+            },
+            RetryPolicy::default(),
+        )
+        .await?;
+
+        let insertion = |commitment: Hash| NewInsertion {
+            commitment,
+            metadata: None,
+            signer: None,
+            created_at: Utc::now(),
+        };
+        let identities = mock_identities(40);
+        let (first, second) = identities.split_at(20);
+
+        // Both replicas accept insertions while the worker takes them off the
+        // queue.
+        let accept = move |db: Arc<Database>, identities: Vec<Hash>| {
+            tokio::spawn(async move {
+                let mut sequence_numbers = HashMap::new();
+                for chunk in identities.chunks(2) {
+                    let insertions = chunk.iter().copied().map(insertion).collect::<Vec<_>>();
+                    let numbers = db.insert_new_identities(&insertions, CAPACITY).await?;
+                    sequence_numbers.extend(chunk.iter().copied().zip(numbers));
+                }
+                anyhow::Ok(sequence_numbers)
+            })
+        };
+        let db = Arc::new(db);
+        let first = accept(db.clone(), first.to_vec());
+        let second = accept(Arc::new(replica), second.to_vec());
+
+        let mut dequeued = vec![];
+        while dequeued.len() < identities.len() {
+            for entry in db
+                .get_eligible_unprocessed_commitments(UnprocessedStatus::New)
+                .await?
+            {
+                db.remove_unprocessed_identity(&entry.commitment).await?;
+                dequeued.push(entry);
+            }
+            tokio::task::yield_now().await;
         }
 
+        let mut sequence_numbers = first.await??;
+        sequence_numbers.extend(second.await??);
+
+        // Taken off the queue in strict sequence number order, whichever
+        // replica accepted them.
+        assert!(dequeued
+            .windows(2)
+            .all(|pair| pair[0].queue_position < pair[1].queue_position));
+        assert!(dequeued
+            .iter()
+            .all(|entry| entry.queue_position == sequence_numbers[&entry.commitment]));
+
         Ok(())
     }
 
     #[tokio::test]
-    async fn insert_identity() -> anyhow::Result<()> {
+    async fn test_insert_new_deletion() -> anyhow::Result<()> {
         let (db, _db_container) = setup_db().await?;
-        let dec = "1234500000000000000";
-        let commit_hash: Hash = U256::from_dec_str(dec)
-            .expect("cant convert to u256")
-            .into();
+        let existing_commitment: Uint<256, 4> = Uint::from(1);
 
-        let eligibility_timestamp = Utc::now();
+        db.insert_new_deletion(0, &existing_commitment).await?;
 
-        let hash = db
-            .insert_new_identity(commit_hash, eligibility_timestamp)
+        let deletions = db.get_deletions().await?;
+        assert_eq!(deletions.len(), 1);
+        assert_eq!(deletions[0].leaf_index, 0);
+        assert_eq!(deletions[0].commitment, existing_commitment);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_eligible_unprocessed_commitments() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+        let commitment_0: Uint<256, 4> = Uint::from(1);
+        let eligibility_timestamp_0 = Utc::now();
+
+        db.insert_new_identity(commitment_0, eligibility_timestamp_0)
             .await?;
 
-        assert_eq!(commit_hash, hash);
+        let commitment_1: Uint<256, 4> = Uint::from(2);
+        let eligibility_timestamp_1 = Utc::now()
+            .checked_add_days(Days::new(7))
+            .expect("Could not create eligibility timestamp");
 
-        let commit = db
-            .get_unprocessed_commit_status(&commit_hash)
-            .await?
-            .expect("expected commitment status");
-        assert_eq!(commit.0, UnprocessedStatus::New);
+        db.insert_new_identity(commitment_1, eligibility_timestamp_1)
+            .await?;
 
-        let identity_count = db
+        let unprocessed_commitments = db
             .get_eligible_unprocessed_commitments(UnprocessedStatus::New)
-            .await?
-            .len();
+            .await?;
 
-        assert_eq!(identity_count, 1);
+        assert_eq!(unprocessed_commitments.len(), 1);
+        assert_eq!(unprocessed_commitments[0].commitment, commitment_0);
+        assert!(
+            unprocessed_commitments[0].eligibility_timestamp.timestamp()
+                - eligibility_timestamp_0.timestamp()
+                <= 1
+        );
 
-        assert!(db.remove_unprocessed_identity(&commit_hash).await.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_unprocessed_commitments() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        // Insert new identity with a valid eligibility timestamp
+        let commitment_0: Uint<256, 4> = Uint::from(1);
+        let eligibility_timestamp_0 = Utc::now();
+        db.insert_new_identity(commitment_0, eligibility_timestamp_0)
+            .await?;
+
+        // Insert new identity with eligibility timestamp in the future
+        let commitment_1: Uint<256, 4> = Uint::from(2);
+        let eligibility_timestamp_1 = Utc::now()
+            .checked_add_days(Days::new(7))
+            .expect("Could not create eligibility timestamp");
+        db.insert_new_identity(commitment_1, eligibility_timestamp_1)
+            .await?;
+
+        let unprocessed_commitments = db
+            .get_eligible_unprocessed_commitments(UnprocessedStatus::New)
+            .await?;
+
+        // Assert unprocessed commitments against expected values
+        assert_eq!(unprocessed_commitments.len(), 1);
+        assert_eq!(unprocessed_commitments[0].commitment, commitment_0);
+        assert_eq!(
+            unprocessed_commitments[0].eligibility_timestamp.timestamp(),
+            eligibility_timestamp_0.timestamp()
+        );
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn insert_and_delete_identity() -> anyhow::Result<()> {
+    async fn test_identity_metadata() -> anyhow::Result<()> {
         let (db, _db_container) = setup_db().await?;
 
-        let zero: Hash = U256::zero().into();
-        let zero_root: Hash = U256::from_dec_str("6789")?.into();
-        let root: Hash = U256::from_dec_str("54321")?.into();
-        let commitment: Hash = U256::from_dec_str("12345")?.into();
+        let commitment: Hash = Uint::from(1);
+        assert_eq!(db.get_identity_metadata(&commitment).await?, None);
 
-        db.insert_pending_identity(0, &commitment, &root).await?;
-        db.insert_pending_identity(0, &zero, &zero_root).await?;
+        db.insert_new_identity(commitment, Utc::now()).await?;
+        db.insert_identity_metadata(&commitment, "channel=web")
+            .await?;
 
-        let leaf_index = db
-            .get_identity_leaf_index(&commitment)
-            .await?
-            .context("Missing identity")?;
+        assert_eq!(
+            db.get_identity_metadata(&commitment).await?.as_deref(),
+            Some("channel=web")
+        );
 
-        assert_eq!(leaf_index.leaf_index, 0);
+        let unprocessed = db.get_unprocessed_commitments(10).await?;
+        assert_eq!(unprocessed[0].metadata.as_deref(), Some("channel=web"));
 
         Ok(())
     }
 
-    fn mock_provers() -> HashSet<ProverConfiguration> {
-        let mut provers = HashSet::new();
+    #[tokio::test]
+    async fn test_insertion_audit_log() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
 
-        provers.insert(ProverConfiguration {
-            batch_size:  100,
-            url:         "http://localhost:8080".to_string(),
-            timeout_s:   100,
-            prover_type: ProverType::Insertion,
-        });
+        let commitment: Hash = Uint::from(1);
+        let signer = Address::repeat_byte(0x42);
 
-        provers.insert(ProverConfiguration {
-            batch_size:  100,
-            url:         "http://localhost:8080".to_string(),
-            timeout_s:   100,
-            prover_type: ProverType::Deletion,
-        });
+        db.insert_insertion_audit_entry(&commitment, None).await?;
+        assert_eq!(db.get_insertion_signer(&commitment).await?, None);
 
-        provers
+        db.insert_insertion_audit_entry(&commitment, Some(signer))
+            .await?;
+        assert_eq!(db.get_insertion_signer(&commitment).await?, Some(signer));
+
+        Ok(())
     }
 
     #[tokio::test]
-    async fn test_insert_prover_configuration() -> anyhow::Result<()> {
+    async fn test_screening_audit_log() -> anyhow::Result<()> {
         let (db, _db_container) = setup_db().await?;
 
-        let mock_prover_configuration_0 = ProverConfiguration {
-            batch_size:  100,
-            url:         "http://localhost:8080".to_string(),
-            timeout_s:   100,
-            prover_type: ProverType::Insertion,
+        let subject = ScreeningSubject {
+            commitment:   Uint::from(1),
+            signer:       Some(Address::repeat_byte(0x42)),
+            jurisdiction: Some("CH".to_owned()),
         };
 
-        let mock_prover_configuration_1 = ProverConfiguration {
-            batch_size:  100,
-            url:         "http://localhost:8081".to_string(),
-            timeout_s:   100,
-            prover_type: ProverType::Deletion,
-        };
+        db.insert_screening_audit_entry(&subject, ScreeningDecision::Denied)
+            .await?;
+        db.insert_screening_audit_entry(&subject, ScreeningDecision::FailedOpen)
+            .await?;
 
-        db.insert_prover_configuration(
-            mock_prover_configuration_0.batch_size,
-            mock_prover_configuration_0.url.clone(),
-            mock_prover_configuration_0.timeout_s,
-            mock_prover_configuration_0.prover_type,
+        let decisions: Vec<String> = sqlx::query_scalar(
+            "SELECT decision FROM screening_audit_log WHERE commitment = $1 ORDER BY id",
         )
+        .bind(subject.commitment)
+        .fetch_all(&db.pool)
         .await?;
+        assert_eq!(decisions, vec!["denied", "failedOpen"]);
 
-        db.insert_prover_configuration(
-            mock_prover_configuration_1.batch_size,
-            mock_prover_configuration_1.url.clone(),
-            mock_prover_configuration_1.timeout_s,
-            mock_prover_configuration_1.prover_type,
-        )
-        .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_signing_audit_log() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let record = SigningRecord {
+            signer:         Address::repeat_byte(0x42),
+            purpose:        SigningPurpose::InsertionBatch,
+            payload_digest: H256::repeat_byte(0x01),
+            nonce:          None,
+            chain_id:       1,
+            outcome:        SigningOutcome::Submitted,
+            transaction_id: Some("tx".to_owned()),
+            timestamp:      Utc::now().with_nanosecond(0).unwrap(),
+        };
+        let receipt = SigningRecord {
+            purpose: SigningPurpose::InsertionReceipt,
+            outcome: SigningOutcome::Signed,
+            transaction_id: None,
+            timestamp: record.timestamp + chrono::Duration::seconds(1),
+            ..record.clone()
+        };
+
+        db.insert_signing_record(&record).await?;
+        db.insert_signing_record(&receipt).await?;
+        db.set_signing_record_nonce("tx", U256::from(7)).await?;
+        // A nonce which is known isn't replaced.
+        db.set_signing_record_nonce("tx", U256::from(8)).await?;
+
+        let records = db.get_signing_records(None, None).await?;
+        assert_eq!(records, vec![
+            SigningRecord {
+                nonce: Some(U256::from(7)),
+                ..record.clone()
+            },
+            receipt.clone(),
+        ]);
+
+        let records = db
+            .get_signing_records(Some(receipt.timestamp), None)
+            .await?;
+        assert_eq!(records, vec![receipt.clone()]);
+        let records = db
+            .get_signing_records(None, Some(receipt.timestamp))
+            .await?;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].purpose, SigningPurpose::InsertionBatch);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_queue_position_and_insertion_latency() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let identities = mock_identities(4);
+        let roots = mock_roots(2);
+
+        for identity in &identities {
+            db.insert_new_identity(*identity, Utc::now()).await?;
+        }
+        for i in 0..2 {
+            db.insert_pending_identity(i, &identities[i], &roots[i])
+                .await?;
+            db.remove_unprocessed_identity(&identities[i]).await?;
+        }
+
+        assert_eq!(db.get_queue_position(&identities[0]).await?, Some(0));
+        assert_eq!(db.get_queue_position(&identities[1]).await?, Some(1));
+        assert_eq!(db.get_queue_position(&identities[3]).await?, Some(3));
+        assert_eq!(db.get_queue_position(&Hash::from(42)).await?, None);
+
+        let since = Utc::now() - chrono::Duration::hours(1);
+        assert_eq!(
+            db.get_insertion_latency(since).await?,
+            InsertionLatency::default()
+        );
+
+        db.mark_root_as_processed(&roots[0]).await?;
+
+        assert_eq!(db.get_queue_position(&identities[0]).await?, None);
+        assert_eq!(db.get_queue_position(&identities[3]).await?, Some(2));
+
+        let latency = db.get_insertion_latency(since).await?;
+        assert_eq!(latency.mined_identities, 1);
+        assert!(latency
+            .average_seconds
+            .is_some_and(|seconds| seconds >= 0.0));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_prune_superseded_updates() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let identities = mock_identities(2);
+        let roots = mock_roots(3);
+
+        db.insert_pending_identity(0, &identities[0], &roots[0])
+            .await?;
+        db.insert_pending_identity(1, &identities[1], &roots[1])
+            .await?;
+        db.insert_pending_identity(0, &Hash::ZERO, &roots[2])
+            .await?;
 
-        let provers = db.get_provers().await?;
+        // The deletion isn't final yet.
+        db.mark_root_as_processed(&roots[2]).await?;
+        assert_eq!(db.prune_superseded_updates(None, None).await?, 0);
 
-        assert!(provers.contains(&mock_prover_configuration_0));
-        assert!(provers.contains(&mock_prover_configuration_1));
+        db.mark_root_as_mined(&roots[2]).await?;
+
+        let an_hour_ago = Utc::now() - chrono::Duration::hours(1);
+        assert_eq!(
+            db.prune_superseded_updates(Some(an_hour_ago), None).await?,
+            0
+        );
+        assert_eq!(db.prune_superseded_updates(None, Some(1)).await?, 0);
+        assert_eq!(db.prune_superseded_updates(None, Some(0)).await?, 1);
+
+        let updates = db.get_tree_updates_until(3).await?;
+        assert_eq!(updates, vec![
+            TreeUpdate::new(1, identities[1]),
+            TreeUpdate::new(0, Hash::ZERO),
+        ]);
+
+        // Deleted identities can't be inserted again once pruned.
+        assert!(db.get_identity_leaf_index(&identities[0]).await?.is_none());
+        assert!(db.identity_exists(identities[0]).await?);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_insert_provers() -> anyhow::Result<()> {
+    async fn test_get_identity_deletion() -> anyhow::Result<()> {
         let (db, _db_container) = setup_db().await?;
-        let mock_provers = mock_provers();
 
-        db.insert_provers(mock_provers.clone()).await?;
+        let identities = mock_identities(2);
+        let roots = mock_roots(3);
 
-        let provers = db.get_provers().await?;
+        db.insert_pending_identity(0, &identities[0], &roots[0])
+            .await?;
+        db.insert_pending_identity(1, &identities[1], &roots[1])
+            .await?;
+        db.insert_pending_identity(0, &Hash::ZERO, &roots[2])
+            .await?;
+        db.mark_root_as_mined(&roots[1]).await?;
+
+        // Not deleted until the deletion is mined.
+        assert!(db.get_identity_deletion(&identities[0]).await?.is_none());
+
+        let transaction = MinedTransaction {
+            transaction_hash: H256::repeat_byte(1),
+            block_number:     7,
+            block_timestamp:  Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+        };
+        db.insert_root_transaction(&roots[2], &transaction).await?;
+        db.mark_root_as_processed(&roots[2]).await?;
+
+        assert_eq!(
+            db.get_identity_deletion(&identities[0]).await?,
+            Some(IdentityDeletion {
+                root:              Some(roots[2]),
+                mined_transaction: Some(transaction),
+            })
+        );
+        assert!(db.get_identity_deletion(&identities[1]).await?.is_none());
+
+        // Once pruned, only the deletion itself is known.
+        db.mark_root_as_mined(&roots[2]).await?;
+        assert_eq!(db.prune_superseded_updates(None, Some(0)).await?, 1);
+        assert_eq!(
+            db.get_identity_deletion(&identities[0]).await?,
+            Some(IdentityDeletion {
+                root:              None,
+                mined_transaction: None,
+            })
+        );
 
-        assert_eq!(provers, mock_provers);
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_remove_prover() -> anyhow::Result<()> {
+    async fn test_replace_tree() -> anyhow::Result<()> {
         let (db, _db_container) = setup_db().await?;
-        let mock_provers = mock_provers();
 
-        db.insert_provers(mock_provers.clone()).await?;
-        db.remove_prover(100, ProverType::Insertion).await?;
-        let provers = db.get_provers().await?;
+        let identities = mock_identities(4);
+        let roots = mock_roots(4);
 
-        assert_eq!(provers, HashSet::new());
+        db.insert_pending_identity(0, &identities[0], &roots[0])
+            .await?;
+        db.insert_pending_identity(1, &identities[1], &roots[1])
+            .await?;
+        db.insert_insertion_receipt(&identities[2], Utc::now())
+            .await?;
+        db.insert_pending_identity(2, &identities[2], &roots[2])
+            .await?;
+        db.mark_root_as_mined(&roots[0]).await?;
+
+        let leaves = [identities[0], Hash::ZERO, identities[3]];
+        assert_eq!(db.replace_tree(&leaves, &roots[3], "Discarded").await?, 2);
+
+        assert_eq!(db.get_next_leaf_index().await?, 3);
+        assert_eq!(
+            db.get_latest_root_by_status(ProcessedStatus::Mined).await?,
+            Some(roots[3])
+        );
+        assert!(db
+            .get_commitments_by_status(ProcessedStatus::Pending)
+            .await?
+            .is_empty());
+        assert_eq!(db.get_tree_updates_until(100).await?, vec![
+            TreeUpdate::new(0, identities[0]),
+            TreeUpdate::new(1, Hash::ZERO),
+            TreeUpdate::new(2, identities[3]),
+        ]);
+        assert!(db.get_identity_leaf_index(&identities[1]).await?.is_none());
+
+        // Discarded identities are reported as failed, without the leaf they
+        // were assigned.
+        assert_eq!(
+            db.get_unprocessed_commit_status(&identities[2]).await?,
+            Some((UnprocessedStatus::Failed, "Discarded".to_owned()))
+        );
+        assert_eq!(
+            db.get_identity_events(&identities[1])
+                .await?
+                .last()
+                .map(|entry| entry.event),
+            Some(IdentityEvent::Failed)
+        );
+        let receipt = db
+            .get_insertion_receipt(&identities[2])
+            .await?
+            .context("Missing receipt")?;
+        assert_eq!(receipt.leaf_index, None);
+        assert_eq!(receipt.pre_root, None);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_insert_new_recovery() -> anyhow::Result<()> {
+    async fn test_roll_back_insertions() -> anyhow::Result<()> {
         let (db, _db_container) = setup_db().await?;
 
-        let existing_commitment: Uint<256, 4> = Uint::from(1);
-        let new_commitment: Uint<256, 4> = Uint::from(2);
+        let identities = mock_identities(4);
+        let roots = mock_roots(4);
 
-        db.insert_new_recovery(&existing_commitment, &new_commitment)
+        for (leaf_index, (identity, root)) in identities.iter().zip(&roots).enumerate() {
+            db.insert_pending_identity(leaf_index, identity, root)
+                .await?;
+        }
+        db.mark_root_as_processed(&roots[0]).await?;
+
+        let insertion = |leaf_index: usize| TreeUpdate::new(leaf_index, identities[leaf_index]);
+
+        // Every pending identity from the first one onwards must be rolled back.
+        assert!(db
+            .roll_back_insertions(&[insertion(2)], "Rolled back")
+            .await
+            .is_err());
+        assert_eq!(db.get_next_leaf_index().await?, 4);
+
+        db.roll_back_insertions(&[insertion(2), insertion(3)], "Rolled back")
             .await?;
 
-        let recoveries = db.get_recoveries().await?;
+        assert_eq!(db.get_next_leaf_index().await?, 2);
+        assert_eq!(
+            db.get_latest_root_by_status(ProcessedStatus::Pending)
+                .await?,
+            Some(roots[1])
+        );
+        assert_eq!(
+            db.get_unprocessed_commit_status(&identities[3]).await?,
+            Some((UnprocessedStatus::Failed, "Rolled back".to_owned()))
+        );
+        assert_eq!(
+            db.get_identity_events(&identities[2])
+                .await?
+                .last()
+                .map(|entry| entry.event),
+            Some(IdentityEvent::Failed)
+        );
+        // Rolled back identities can't be inserted again.
+        assert!(db.identity_exists(identities[2]).await?);
 
-        assert_eq!(recoveries.len(), 1);
-        assert_eq!(recoveries[0].existing_commitment, existing_commitment);
-        assert_eq!(recoveries[0].new_commitment, new_commitment);
+        // Processed identities are never rolled back.
+        assert!(db
+            .roll_back_insertions(&[insertion(0), insertion(1)], "Rolled back")
+            .await
+            .is_err());
+        assert_eq!(db.get_next_leaf_index().await?, 2);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_insert_new_deletion() -> anyhow::Result<()> {
+    async fn test_discard_failed_insertions() -> anyhow::Result<()> {
         let (db, _db_container) = setup_db().await?;
-        let existing_commitment: Uint<256, 4> = Uint::from(1);
 
-        db.insert_new_deletion(0, &existing_commitment).await?;
+        let identities = mock_identities(4);
+        let roots = mock_roots(4);
 
-        let deletions = db.get_deletions().await?;
-        assert_eq!(deletions.len(), 1);
-        assert_eq!(deletions[0].leaf_index, 0);
-        assert_eq!(deletions[0].commitment, existing_commitment);
+        for (leaf_index, (identity, root)) in identities.iter().zip(&roots).enumerate() {
+            db.insert_pending_identity(leaf_index, identity, root)
+                .await?;
+        }
+        db.mark_root_as_processed(&roots[0]).await?;
+        db.insert_quarantined_batch("insertion", &roots[0], &roots[2], "reverted", &[1], false)
+            .await?;
+
+        let insertions: Vec<_> = (1..4)
+            .map(|leaf_index| TreeUpdate::new(leaf_index, identities[leaf_index]))
+            .collect();
+
+        // Only pending roots end a batch which can be discarded.
+        assert!(db
+            .discard_failed_insertions(&insertions, &roots[0], "Discarded")
+            .await
+            .is_err());
+
+        let dropped = db
+            .discard_failed_insertions(&insertions, &roots[2], "Discarded")
+            .await?;
+
+        assert_eq!(dropped, 2);
+        assert_eq!(db.get_next_leaf_index().await?, 1);
+        assert!(db.get_failed_batch(&roots[2]).await?.is_none());
+        for identity in &identities[1..3] {
+            assert_eq!(
+                db.get_unprocessed_commit_status(identity).await?,
+                Some((UnprocessedStatus::Failed, "Discarded".to_owned()))
+            );
+        }
+
+        // The identity after the batch is queued to be inserted again.
+        let queued = db
+            .get_eligible_unprocessed_commitments(UnprocessedStatus::New)
+            .await?;
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].commitment, identities[3]);
+        assert_eq!(
+            db.get_identity_events(&identities[3])
+                .await?
+                .last()
+                .map(|entry| entry.event),
+            Some(IdentityEvent::Received)
+        );
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_get_eligible_unprocessed_commitments() -> anyhow::Result<()> {
-        let (db, _db_container) = setup_db().await?;
-        let commitment_0: Uint<256, 4> = Uint::from(1);
-        let eligibility_timestamp_0 = Utc::now();
+    async fn test_leaf_index_of() -> anyhow::Result<()> {
+        let (db, db_container) = setup_db().await?;
 
-        db.insert_new_identity(commitment_0, eligibility_timestamp_0)
+        let identities = mock_identities(3);
+        let roots = mock_roots(4);
+
+        for (leaf_index, (identity, root)) in identities.iter().zip(&roots).enumerate() {
+            db.insert_pending_identity(leaf_index, identity, root)
+                .await?;
+        }
+        // The identity keeps its leaf once deleted.
+        db.insert_pending_identity(1, &Hash::ZERO, &roots[3])
             .await?;
 
-        let commitment_1: Uint<256, 4> = Uint::from(2);
-        let eligibility_timestamp_1 = Utc::now()
-            .checked_add_days(Days::new(7))
-            .expect("Could not create eligibility timestamp");
+        assert_eq!(db.leaf_index_of(&identities[1]).await?, Some(1));
+        assert_eq!(db.leaf_index_of(&Hash::ZERO).await?, None);
 
-        db.insert_new_identity(commitment_1, eligibility_timestamp_1)
-            .await?;
+        // Leaves are persisted, not only cached.
+        let reconnected = reconnect(&db_container).await?;
+        for (leaf_index, identity) in identities.iter().enumerate() {
+            assert_eq!(reconnected.leaf_index_of(identity).await?, Some(leaf_index));
+        }
+        assert_eq!(
+            reconnected
+                .get_identity_leaf_index(&identities[2])
+                .await?
+                .map(|item| item.leaf_index),
+            Some(2)
+        );
 
-        let unprocessed_commitments = db
-            .get_eligible_unprocessed_commitments(UnprocessedStatus::New)
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rolled_back_leaf_indices_are_forgotten() -> anyhow::Result<()> {
+        let (db, db_container) = setup_db().await?;
+
+        let identities = mock_identities(3);
+        let roots = mock_roots(3);
+
+        for (leaf_index, (identity, root)) in identities.iter().zip(&roots).enumerate() {
+            db.insert_pending_identity(leaf_index, identity, root)
+                .await?;
+        }
+        assert_eq!(db.leaf_index_of(&identities[2]).await?, Some(2));
+
+        db.roll_back_insertions(&[TreeUpdate::new(2, identities[2])], "Rolled back")
             .await?;
 
-        assert_eq!(unprocessed_commitments.len(), 1);
-        assert_eq!(unprocessed_commitments[0].commitment, commitment_0);
-        assert!(
-            unprocessed_commitments[0].eligibility_timestamp.timestamp()
-                - eligibility_timestamp_0.timestamp()
-                <= 1
+        assert_eq!(db.leaf_index_of(&identities[1]).await?, Some(1));
+        assert_eq!(db.leaf_index_of(&identities[2]).await?, None);
+        assert_eq!(
+            reconnect(&db_container)
+                .await?
+                .leaf_index_of(&identities[2])
+                .await?,
+            None
         );
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_get_unprocessed_commitments() -> anyhow::Result<()> {
+    async fn test_identity_events() -> anyhow::Result<()> {
         let (db, _db_container) = setup_db().await?;
 
-        // Insert new identity with a valid eligibility timestamp
-        let commitment_0: Uint<256, 4> = Uint::from(1);
-        let eligibility_timestamp_0 = Utc::now();
-        db.insert_new_identity(commitment_0, eligibility_timestamp_0)
-            .await?;
+        let identities = mock_identities(2);
+        let roots = mock_roots(3);
+
+        let events = |commitment: Hash| {
+            let db = &db;
+            async move {
+                anyhow::Ok(
+                    db.get_identity_events(&commitment)
+                        .await?
+                        .into_iter()
+                        .map(|entry| entry.event)
+                        .collect::<Vec<_>>(),
+                )
+            }
+        };
 
-        // Insert new identity with eligibility timestamp in the future
-        let commitment_1: Uint<256, 4> = Uint::from(2);
-        let eligibility_timestamp_1 = Utc::now()
-            .checked_add_days(Days::new(7))
-            .expect("Could not create eligibility timestamp");
-        db.insert_new_identity(commitment_1, eligibility_timestamp_1)
+        db.insert_new_identity(identities[0], Utc::now()).await?;
+        db.insert_pending_identity(0, &identities[0], &roots[0])
+            .await?;
+        db.append_identity_events(&identities[..1], IdentityEvent::Batched)
             .await?;
+        db.mark_root_as_processed(&roots[0]).await?;
+        db.mark_root_as_mined(&roots[0]).await?;
 
-        let unprocessed_commitments = db
-            .get_eligible_unprocessed_commitments(UnprocessedStatus::New)
+        assert_eq!(events(identities[0]).await?, vec![
+            IdentityEvent::Received,
+            IdentityEvent::Validated,
+            IdentityEvent::Batched,
+            IdentityEvent::Mined,
+            IdentityEvent::Finalized,
+        ]);
+
+        // Deleting the leaf records the deletion of the commitment it held.
+        db.insert_pending_identity(1, &identities[1], &roots[1])
             .await?;
+        db.insert_pending_identity(0, &Hash::ZERO, &roots[2])
+            .await?;
+        db.mark_root_as_processed(&roots[2]).await?;
 
-        // Assert unprocessed commitments against expected values
-        assert_eq!(unprocessed_commitments.len(), 1);
-        assert_eq!(unprocessed_commitments[0].commitment, commitment_0);
         assert_eq!(
-            unprocessed_commitments[0].eligibility_timestamp.timestamp(),
-            eligibility_timestamp_0.timestamp()
+            events(identities[0]).await?.last(),
+            Some(&IdentityEvent::Deleted)
         );
+        assert_eq!(events(identities[1]).await?, vec![
+            IdentityEvent::Validated,
+            IdentityEvent::Mined,
+        ]);
+        assert!(events(Hash::ZERO).await?.is_empty());
+
+        db.insert_new_identity(Hash::from(42), Utc::now()).await?;
+        db.update_err_unprocessed_commitment(Hash::from(42), "rejected".into())
+            .await?;
+
+        let failed = db.get_identity_events(&Hash::from(42)).await?;
+        assert_eq!(failed[1].event, IdentityEvent::Failed);
+        assert_eq!(failed[1].message.as_deref(), Some("rejected"));
 
         Ok(())
     }
@@ -1298,31 +4537,206 @@ mod test {
             .checked_add_days(Days::new(7))
             .expect("Could not create eligibility timestamp");
 
-        // Insert new identity with an eligibility timestamp in the future
-        let commit_hash: Hash = Hash::from(1);
-        db.insert_new_identity(commit_hash, eligibility_timestamp)
+        // Insert new identity with an eligibility timestamp in the future
+        let commit_hash: Hash = Hash::from(1);
+        db.insert_new_identity(commit_hash, eligibility_timestamp)
+            .await?;
+
+        let eligible_commitments = db
+            .get_eligible_unprocessed_commitments(UnprocessedStatus::New)
+            .await?;
+        assert_eq!(eligible_commitments.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expire_unprocessed_identities() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let stale = Hash::from(1);
+        let fresh = Hash::from(2);
+        db.insert_new_identity(stale, Utc::now() - chrono::Duration::hours(2))
+            .await?;
+        db.insert_new_identity(fresh, Utc::now()).await?;
+
+        let expired = db
+            .expire_unprocessed_identities(chrono::Duration::hours(1))
+            .await?;
+        assert_eq!(expired, vec![stale]);
+
+        let (status, message) = db.get_unprocessed_commit_status(&stale).await?.unwrap();
+        assert_eq!(status, UnprocessedStatus::Failed);
+        assert!(message.starts_with("Expired"));
+        assert_eq!(
+            db.get_identity_events(&stale)
+                .await?
+                .last()
+                .map(|entry| entry.event),
+            Some(IdentityEvent::Failed)
+        );
+
+        let (status, _) = db.get_unprocessed_commit_status(&fresh).await?.unwrap();
+        assert_eq!(status, UnprocessedStatus::New);
+
+        // Expired commitments are no longer taken off the queue, and are only
+        // expired once.
+        let eligible = db
+            .get_eligible_unprocessed_commitments(UnprocessedStatus::New)
+            .await?;
+        assert_eq!(eligible.len(), 1);
+        assert_eq!(eligible[0].commitment, fresh);
+        assert!(db
+            .expire_unprocessed_identities(chrono::Duration::hours(1))
+            .await?
+            .is_empty());
+
+        // They can be submitted again.
+        db.remove_unprocessed_identity(&stale).await?;
+        db.insert_new_identity(stale, Utc::now()).await?;
+        let (status, _) = db.get_unprocessed_commit_status(&stale).await?.unwrap();
+        assert_eq!(status, UnprocessedStatus::New);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_queue_order_under_concurrent_insertions() -> anyhow::Result<()> {
+        const COUNT: u64 = 200;
+
+        let (db, _db_container) = setup_db_with_max_connections(8).await?;
+        let db = Arc::new(db);
+        let eligibility = Utc::now() - chrono::Duration::seconds(1);
+
+        let insertions = (1..=COUNT)
+            .map(|commitment| {
+                let db = db.clone();
+                tokio::spawn(async move {
+                    db.insert_new_identity(Hash::from(commitment), eligibility)
+                        .await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Takes identities off the queue like the insertion task does, while
+        // they're still being queued.
+        let mut positions = vec![];
+        while positions.len() < COUNT as usize {
+            for identity in db
+                .get_eligible_unprocessed_commitments(UnprocessedStatus::New)
+                .await?
+            {
+                db.remove_unprocessed_identity(&identity.commitment).await?;
+                positions.push(identity.queue_position);
+            }
+        }
+
+        for insertion in insertions {
+            insertion.await??;
+        }
+
+        // No identity became visible after one queued behind it was taken, so
+        // leaves are assigned in queue order.
+        assert!(positions.windows(2).all(|pair| pair[1] == pair[0] + 1));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_update_insertion_timestamp() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let insertion_timestamp = Utc::now();
+
+        db.update_latest_insertion_timestamp(insertion_timestamp)
+            .await?;
+
+        let latest_insertion_timestamp = db.get_latest_insertion_timestamp().await?.unwrap();
+
+        assert!(latest_insertion_timestamp.timestamp() - insertion_timestamp.timestamp() <= 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_update_last_processed_block() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        assert_eq!(db.get_last_processed_block().await?, None);
+
+        db.update_last_processed_block(100).await?;
+        db.update_last_processed_block(200).await?;
+
+        assert_eq!(db.get_last_processed_block().await?, Some(200));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn identity_events_after() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+        let identities = mock_identities(3);
+
+        assert_eq!(db.get_latest_identity_event_id().await?, 0);
+        assert_eq!(db.get_last_published_identity_event().await?, None);
+
+        db.append_identity_events(&identities, IdentityEvent::Batched)
+            .await?;
+        db.append_identity_events(&identities[..1], IdentityEvent::Submitted)
             .await?;
 
-        let eligible_commitments = db
-            .get_eligible_unprocessed_commitments(UnprocessedStatus::New)
+        let latest = db.get_latest_identity_event_id().await?;
+        let events = db.get_identity_events_after(0, 10).await?;
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[3].id, latest);
+        assert_eq!(events[3].commitment, identities[0]);
+        assert_eq!(events[3].event.event, IdentityEvent::Submitted);
+
+        let after = db.get_identity_events_after(events[0].id, 2).await?;
+        assert_eq!(after, events[1..3]);
+        assert!(db.get_identity_events_after(latest, 10).await?.is_empty());
+
+        db.update_last_published_identity_event(events[1].id)
             .await?;
-        assert_eq!(eligible_commitments.len(), 1);
+        db.update_last_published_identity_event(latest).await?;
+        assert_eq!(db.get_last_published_identity_event().await?, Some(latest));
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_update_insertion_timestamp() -> anyhow::Result<()> {
+    async fn event_outbox() -> anyhow::Result<()> {
         let (db, _db_container) = setup_db().await?;
+        let identities = mock_identities(3);
 
-        let insertion_timestamp = Utc::now();
-
-        db.update_latest_insertion_timestamp(insertion_timestamp)
+        db.append_identity_events(&identities, IdentityEvent::Received)
+            .await?;
+        db.append_identity_events(&identities, IdentityEvent::Batched)
+            .await?;
+        db.append_identity_events(&identities[..1], IdentityEvent::Mined)
             .await?;
 
-        let latest_insertion_timestamp = db.get_latest_insertion_timestamp().await?.unwrap();
+        // Only the events of the lifecycle are published.
+        let outbox = db.get_outbox_events(10).await?;
+        let events: Vec<_> = outbox.iter().map(|event| event.event.event).collect();
+        assert_eq!(events, vec![
+            IdentityEvent::Received,
+            IdentityEvent::Received,
+            IdentityEvent::Received,
+            IdentityEvent::Mined
+        ]);
+        assert_eq!(outbox[3].commitment, identities[0]);
+        assert_eq!(db.get_outbox_events(2).await?, outbox[..2]);
 
-        assert!(latest_insertion_timestamp.timestamp() - insertion_timestamp.timestamp() <= 1);
+        assert_eq!(
+            db.delete_outbox_events(&[outbox[0].id, outbox[2].id])
+                .await?,
+            2
+        );
+        assert_eq!(db.get_outbox_events(10).await?, vec![
+            outbox[1].clone(),
+            outbox[3].clone()
+        ]);
 
         Ok(())
     }
@@ -1380,6 +4794,30 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn leaf_index_beyond_u32() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let identities = mock_identities(1);
+        let roots = mock_roots(1);
+
+        let leaf_index = (1usize << 33) + 5;
+
+        db.insert_pending_identity(leaf_index, &identities[0], &roots[0])
+            .await?;
+
+        let next_leaf_index = db.get_next_leaf_index().await?;
+        assert_eq!(next_leaf_index, leaf_index + 1);
+
+        let item = db
+            .get_identity_leaf_index(&identities[0])
+            .await?
+            .context("Fetching leaf index")?;
+        assert_eq!(item.leaf_index, leaf_index);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn mark_all_as_pending_marks_all() -> anyhow::Result<()> {
         let (db, _db_container) = setup_db().await?;
@@ -1636,6 +5074,346 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn batch_costs() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let identities = mock_identities(3);
+        let roots = mock_roots(3);
+
+        for i in 0..3 {
+            db.insert_pending_identity(i, &identities[i], &roots[i])
+                .await?;
+        }
+
+        // The first batch was applied to the initial root
+        assert_eq!(
+            db.count_updates_between_roots(&Hash::ZERO, &roots[1])
+                .await?,
+            2
+        );
+        assert_eq!(
+            db.count_updates_between_roots(&roots[1], &roots[2]).await?,
+            1
+        );
+
+        let timestamp = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let mut cost = BatchCost {
+            transaction_hash:    H256::repeat_byte(0x42),
+            kind:                "insertion".to_owned(),
+            identity_count:      2,
+            gas_used:            500_000,
+            effective_gas_price: U256::from(30_000_000_000_u64),
+            block_timestamp:     timestamp,
+        };
+
+        assert!(db.insert_batch_cost(&cost).await?);
+
+        cost.identity_count = 3;
+        assert!(!db.insert_batch_cost(&cost).await?);
+
+        assert_eq!(db.get_batch_costs(None, None).await?, vec![cost.clone()]);
+        assert_eq!(
+            db.get_batch_costs(
+                Some(timestamp),
+                Some(timestamp + chrono::Duration::seconds(1))
+            )
+            .await?,
+            vec![cost]
+        );
+        assert!(db
+            .get_batch_costs(Some(timestamp + chrono::Duration::seconds(1)), None)
+            .await?
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tree_updates_after() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let identities = mock_identities(3);
+        let roots = mock_roots(3);
+
+        for i in 0..3 {
+            db.insert_pending_identity(i, &identities[i], &roots[i])
+                .await?;
+        }
+
+        let first_id = db.get_root_id(&roots[0]).await?.context("Missing root")?;
+
+        let entries = db.get_tree_updates_after(first_id).await?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].update.leaf_index, 1);
+        assert_eq!(entries[0].update.element, identities[1]);
+        assert_eq!(entries[1].root, roots[2]);
+
+        let last_id = entries[1].id;
+        assert!(db.get_tree_updates_after(last_id).await?.is_empty());
+        assert_eq!(db.get_tree_updates_after(0).await?.len(), 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tree_updates_until() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let identities = mock_identities(3);
+        let roots = mock_roots(3);
+
+        for i in 0..3 {
+            db.insert_pending_identity(i, &identities[i], &roots[i])
+                .await?;
+        }
+
+        let second_id = db.get_root_id(&roots[1]).await?.context("Missing root")?;
+
+        let updates = db.get_tree_updates_until(second_id).await?;
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].element, identities[0]);
+        assert_eq!(updates[1].leaf_index, 1);
+        assert_eq!(updates[1].element, identities[1]);
+
+        assert!(db.get_tree_updates_until(0).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn root_transaction() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let identities = mock_identities(1);
+        let roots = mock_roots(1);
+
+        db.insert_pending_identity(0, &identities[0], &roots[0])
+            .await?;
+        db.mark_root_as_processed(&roots[0]).await?;
+
+        assert!(db.get_root_transaction(&roots[0]).await?.is_none());
+
+        let transaction = MinedTransaction {
+            transaction_hash: H256::repeat_byte(0x42),
+            block_number:     1234,
+            block_timestamp:  Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+        };
+        db.insert_root_transaction(&roots[0], &transaction).await?;
+
+        assert_eq!(
+            db.get_root_transaction(&roots[0]).await?,
+            Some(transaction.clone())
+        );
+
+        let root = db
+            .get_root_state(&roots[0])
+            .await?
+            .context("Fetching root state")?;
+        assert_eq!(root.mined_transaction, Some(transaction));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn root_history() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let identities = mock_identities(5);
+        let roots = mock_roots(4);
+
+        for i in 0..4 {
+            db.insert_pending_identity(i, &identities[i], &roots[i])
+                .await?;
+        }
+        // A root reached again is only listed as of the first time.
+        db.insert_pending_identity(4, &identities[4], &roots[1])
+            .await?;
+        db.mark_root_as_mined(&roots[1]).await?;
+
+        for (root, block_number) in [(&roots[0], 10), (&roots[1], 20)] {
+            let transaction = MinedTransaction {
+                transaction_hash: H256::repeat_byte(block_number as u8),
+                block_number,
+                block_timestamp: Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+            };
+            db.insert_root_transaction(root, &transaction).await?;
+        }
+
+        let history_roots = |entries: &[RootHistoryEntry]| -> Vec<Hash> {
+            entries.iter().map(|entry| entry.root.root).collect()
+        };
+
+        let first_page = db.get_root_history(0, None, None, None, 2).await?;
+        assert_eq!(history_roots(&first_page), &roots[..2]);
+        assert_eq!(first_page[1].root.status, ProcessedStatus::Mined);
+        assert_eq!(
+            first_page[1]
+                .root
+                .mined_transaction
+                .as_ref()
+                .map(|transaction| transaction.block_number),
+            Some(20)
+        );
+
+        let second_page = db
+            .get_root_history(first_page[1].id, None, None, None, 2)
+            .await?;
+        assert_eq!(history_roots(&second_page), &roots[2..]);
+        assert!(db
+            .get_root_history(second_page[1].id, None, None, None, 2)
+            .await?
+            .is_empty());
+
+        let pending = db
+            .get_root_history(0, Some(ProcessedStatus::Pending), None, None, 10)
+            .await?;
+        assert_eq!(history_roots(&pending), &roots[2..]);
+
+        let from_block = db.get_root_history(0, None, Some(15), None, 10).await?;
+        assert_eq!(history_roots(&from_block), &roots[1..2]);
+        let to_block = db.get_root_history(0, None, None, Some(15), 10).await?;
+        assert_eq!(history_roots(&to_block), &roots[..1]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mined_leaves() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let identities = mock_identities(4);
+        let roots = mock_roots(5);
+
+        for i in 0..4 {
+            db.insert_pending_identity(i, &identities[i], &roots[i])
+                .await?;
+        }
+        // The second leaf is deleted in a later batch.
+        db.insert_pending_identity(1, &Hash::ZERO, &roots[4])
+            .await?;
+
+        assert_eq!(db.set_mined_block(None, &roots[1], 10).await?, 2);
+        assert_eq!(db.set_mined_block(Some(&roots[1]), &roots[3], 20).await?, 2);
+        assert_eq!(db.set_mined_block(Some(&roots[3]), &roots[4], 30).await?, 1);
+        db.mark_root_as_mined(&roots[4]).await?;
+
+        assert_eq!(db.get_mined_block(&identities[0]).await?, Some(10));
+        assert_eq!(db.get_mined_block(&identities[2]).await?, Some(20));
+
+        let listed = |leaves: &[MinedLeafEntry]| -> Vec<(usize, Option<u64>)> {
+            leaves
+                .iter()
+                .map(|leaf| (leaf.leaf_index, leaf.mined_block))
+                .collect()
+        };
+
+        let leaves = db.get_mined_leaves(None, None, 10).await?;
+        assert_eq!(listed(&leaves), [
+            (0, Some(10)),
+            (2, Some(20)),
+            (3, Some(20))
+        ]);
+        assert_eq!(leaves[1].commitment, identities[2]);
+
+        let before = db.get_mined_leaves(None, Some(20), 10).await?;
+        assert_eq!(listed(&before), [(0, Some(10))]);
+
+        let first_page = db.get_mined_leaves(None, None, 2).await?;
+        assert_eq!(listed(&first_page), [(0, Some(10)), (2, Some(20))]);
+        let second_page = db.get_mined_leaves(Some(2), None, 2).await?;
+        assert_eq!(listed(&second_page), [(3, Some(20))]);
+
+        // Updates which are no longer on chain lose their block.
+        db.mark_root_as_processed(&roots[1]).await?;
+        assert_eq!(db.get_mined_block(&identities[0]).await?, Some(10));
+        assert_eq!(db.get_mined_block(&identities[2]).await?, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batch_submissions() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let roots = mock_roots(2);
+
+        db.insert_transaction("tx-0", &Hash::ZERO, &roots[0], &mock_batch_details(0..=0))
+            .await?;
+        db.insert_transaction("tx-1", &roots[0], &roots[1], &mock_batch_details(1..=1))
+            .await?;
+
+        let transaction = MinedTransaction {
+            transaction_hash: H256::repeat_byte(0x42),
+            block_number:     1234,
+            block_timestamp:  Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+        };
+        db.insert_root_transaction(&roots[0], &transaction).await?;
+
+        let batches = db.get_batch_submissions().await?;
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].transaction_id, "tx-0");
+        assert_eq!(batches[0].post_root, roots[0]);
+        assert_eq!(batches[0].mined, Some(transaction));
+        assert_eq!(batches[1].pre_root, roots[0]);
+        assert_eq!(batches[1].mined, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batch_reports() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let identities = mock_identities(3);
+        let roots = mock_roots(3);
+
+        for i in 0..3 {
+            db.insert_pending_identity(i, &identities[i], &roots[i])
+                .await?;
+        }
+
+        db.insert_transaction("tx-0", &Hash::ZERO, &roots[1], &mock_batch_details(0..=1))
+            .await?;
+        db.insert_transaction("tx-1", &roots[1], &roots[2], &mock_batch_details(2..=2))
+            .await?;
+
+        let transaction = MinedTransaction {
+            transaction_hash: H256::repeat_byte(0x42),
+            block_number:     1234,
+            block_timestamp:  Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+        };
+        db.insert_root_transaction(&roots[1], &transaction).await?;
+        db.insert_batch_cost(&BatchCost {
+            transaction_hash:    transaction.transaction_hash,
+            kind:                "insertion".to_owned(),
+            identity_count:      2,
+            gas_used:            500_000,
+            effective_gas_price: U256::from(30_000_000_000_u64),
+            block_timestamp:     transaction.block_timestamp,
+        })
+        .await?;
+
+        let reports = db.get_batch_reports(10).await?;
+        assert_eq!(reports.len(), 2);
+
+        // Newest first
+        assert_eq!(reports[0].transaction_id, "tx-1");
+        assert_eq!(reports[0].details, Some(mock_batch_details(2..=2)));
+        assert_eq!(reports[0].mined, None);
+        assert_eq!(reports[0].gas_used, None);
+
+        assert_eq!(reports[1].pre_root, Hash::ZERO);
+        assert_eq!(reports[1].post_root, roots[1]);
+        assert_eq!(reports[1].details, Some(mock_batch_details(0..=1)));
+        assert_eq!(reports[1].mined, Some(transaction));
+        assert_eq!(reports[1].gas_used, Some(500_000));
+
+        assert_eq!(db.get_batch_reports(1).await?.len(), 1);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn get_commitments_by_status() -> anyhow::Result<()> {
         let (db, _db_container) = setup_db().await?;
@@ -1751,6 +5529,61 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_pending_queue_batch_assignment() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let identities = mock_identities(5);
+        let roots = mock_roots(5);
+
+        for i in 0..5 {
+            db.insert_pending_identity(i, &identities[i], &roots[i])
+                .await
+                .context("Inserting identity")?;
+        }
+
+        // First batch covers identities 0 and 1, second batch covers 2
+        db.insert_transaction("tx-0", &Hash::ZERO, &roots[1], &mock_batch_details(0..=1))
+            .await?;
+        db.insert_transaction("tx-1", &roots[1], &roots[2], &mock_batch_details(2..=2))
+            .await?;
+
+        let queue = db.get_pending_queue(100).await?;
+        assert_eq!(queue.len(), 5);
+
+        let transaction_ids: Vec<_> = queue
+            .iter()
+            .map(|entry| entry.transaction_id.as_deref())
+            .collect();
+        assert_eq!(transaction_ids, vec![
+            Some("tx-0"),
+            Some("tx-0"),
+            Some("tx-1"),
+            None,
+            None
+        ]);
+        assert_eq!(queue[0].batch_root, Some(roots[1]));
+        assert_eq!(queue[2].batch_root, Some(roots[2]));
+        assert_eq!(queue[3].batch_root, None);
+
+        let (pending, batched) = db.count_pending_identities_by_state().await?;
+        assert_eq!(pending, 2);
+        assert_eq!(batched, 3);
+
+        // Mined identities are no longer part of the queue
+        db.mark_root_as_processed(&roots[1]).await?;
+
+        let queue = db.get_pending_queue(100).await?;
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue[0].leaf_index, 2);
+
+        let (pending, batched) = db.count_pending_identities_by_state().await?;
+        assert_eq!(pending, 2);
+        assert_eq!(batched, 1);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_root_invalidation() -> anyhow::Result<()> {
         let (db, _db_container) = setup_db().await?;
@@ -2069,4 +5902,67 @@ mod test {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn failed_batches() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let roots = mock_roots(2);
+
+        assert!(!db.has_held_batches(Some(2)).await?);
+
+        db.insert_quarantined_batch("insertion", &roots[0], &roots[1], "first", &[1, 2], false)
+            .await?;
+        assert!(!db.has_held_batches(Some(2)).await?);
+
+        db.insert_quarantined_batch("insertion", &roots[0], &roots[1], "second", &[3, 4], false)
+            .await?;
+        assert!(db.has_held_batches(Some(2)).await?);
+
+        let batch = db
+            .get_failed_batch(&roots[1])
+            .await?
+            .context("Missing failed batch")?;
+        assert_eq!(batch.pre_root, roots[0]);
+        assert_eq!(batch.reason, "second");
+        assert_eq!(batch.calldata, vec![1, 2]);
+        assert_eq!(batch.attempts, 2);
+        assert_eq!(db.get_failed_batches().await?, vec![batch]);
+
+        assert!(db.reset_failed_batch_attempts(&roots[1]).await?);
+        assert!(!db.has_held_batches(Some(1)).await?);
+
+        assert!(db.delete_failed_batch(&roots[1]).await?);
+        assert!(!db.delete_failed_batch(&roots[1]).await?);
+        assert!(!db.reset_failed_batch_attempts(&roots[1]).await?);
+        assert!(db.get_failed_batch(&roots[1]).await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn isolated_leaves_hold_submission() -> anyhow::Result<()> {
+        let (db, _db_container) = setup_db().await?;
+
+        let roots = mock_roots(2);
+
+        db.insert_quarantined_batch("insertion", &roots[0], &roots[1], "leaf", &[1], true)
+            .await?;
+        assert!(db.has_held_batches(None).await?);
+
+        // Failing again as part of a longer batch doesn't release it.
+        db.insert_quarantined_batch("insertion", &roots[0], &roots[1], "leaf", &[1], false)
+            .await?;
+        let batch = db
+            .get_failed_batch(&roots[1])
+            .await?
+            .context("Missing failed batch")?;
+        assert!(batch.isolated);
+        assert!(db.has_held_batches(Some(10)).await?);
+
+        assert!(db.reset_failed_batch_attempts(&roots[1]).await?);
+        assert!(!db.has_held_batches(None).await?);
+
+        Ok(())
+    }
 }
@@ -1,6 +1,12 @@
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
 use chrono::{DateTime, Utc};
+use ethers::types::{Address, H256, U256};
 
-use crate::identity_tree::{Hash, Status, UnprocessedStatus};
+use crate::identity_tree::{
+    Hash, IdentityEvent, MinedTransaction, RootItem, Status, TreeUpdate, UnprocessedStatus,
+};
 
 pub struct UnprocessedCommitment {
     pub commitment:            Hash,
@@ -9,6 +15,19 @@ pub struct UnprocessedCommitment {
     pub processed_at:          Option<DateTime<Utc>>,
     pub error_message:         Option<String>,
     pub eligibility_timestamp: DateTime<Utc>,
+    pub metadata:              Option<String>,
+    /// Leaf indices are assigned in the order of queue positions
+    pub queue_position:        u64,
+}
+
+/// An accepted insertion, to be queued along with its metadata, audit entry
+/// and receipt.
+#[derive(Clone, Debug)]
+pub struct NewInsertion {
+    pub commitment: Hash,
+    pub metadata:   Option<String>,
+    pub signer:     Option<Address>,
+    pub created_at: DateTime<Utc>,
 }
 
 pub struct RecoveryEntry {
@@ -26,6 +45,16 @@ pub struct DeletionEntry {
     pub commitment: Hash,
 }
 
+/// The mined deletion of an identity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentityDeletion {
+    /// The root after the deletion, unknown once the updates of the identity
+    /// were pruned
+    pub root:              Option<Hash>,
+    /// The transaction which published the root, if recorded
+    pub mined_transaction: Option<MinedTransaction>,
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct CommitmentHistoryEntry {
     pub commitment: Hash,
@@ -35,3 +64,170 @@ pub struct CommitmentHistoryEntry {
     pub held_back:  bool,
     pub status:     Status,
 }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentityEventEntry {
+    pub event:      IdentityEvent,
+    pub message:    Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An identity event along with its position in the log of all of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoggedIdentityEvent {
+    pub id:         u64,
+    pub commitment: Hash,
+    pub event:      IdentityEventEntry,
+}
+
+pub struct InsertionReceiptEntry {
+    pub commitment:      Hash,
+    /// Unset until the insertion leaves the queue
+    pub leaf_index:      Option<usize>,
+    /// Unset until the insertion leaves the queue, or if its leaf was applied
+    /// to the initial root
+    pub pre_root:        Option<Hash>,
+    pub created_at:      DateTime<Utc>,
+    pub sequence_number: u64,
+}
+
+/// The first request made with an idempotency key.
+pub struct IdempotencyKeyEntry {
+    pub fingerprint: H256,
+    /// Unset while the request is being processed
+    pub response:    Option<IdempotentResponse>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdempotentResponse {
+    pub status:       u16,
+    pub content_type: Option<String>,
+    pub body:         Vec<u8>,
+}
+
+/// A leaf of the mined tree.
+pub struct MinedLeafEntry {
+    pub leaf_index:  usize,
+    pub commitment:  Hash,
+    /// Unset if the transaction which mined the leaf wasn't recorded
+    pub mined_block: Option<u64>,
+}
+
+pub struct QueuedIdentityEntry {
+    pub leaf_index:     usize,
+    pub commitment:     Hash,
+    pub pending_as_of:  DateTime<Utc>,
+    // Only set once the identity has been submitted on chain
+    pub batch_root:     Option<Hash>,
+    pub transaction_id: Option<String>,
+    pub metadata:       Option<String>,
+}
+
+/// The gas spent on a mined batch transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchCost {
+    pub transaction_hash:    H256,
+    /// `insertion` or `deletion`
+    pub kind:                String,
+    pub identity_count:      u64,
+    pub gas_used:            u64,
+    /// Price per gas paid in wei
+    pub effective_gas_price: U256,
+    pub block_timestamp:     DateTime<Utc>,
+}
+
+impl BatchCost {
+    /// Cost of the transaction in wei.
+    #[must_use]
+    pub fn cost(&self) -> U256 {
+        self.effective_gas_price * self.gas_used
+    }
+}
+
+/// A batch which reverted in simulation and was therefore never broadcast.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedBatch {
+    pub pre_root:       Hash,
+    pub post_root:      Hash,
+    /// `insertion` or `deletion`
+    pub kind:           String,
+    /// Revert reason of the latest failure
+    pub reason:         String,
+    pub calldata:       Vec<u8>,
+    /// Failures since the batch was first quarantined or last retried
+    pub attempts:       u32,
+    pub created_at:     DateTime<Utc>,
+    pub last_failed_at: DateTime<Utc>,
+    /// Whether the batch is a leaf isolated from a reverting batch, which
+    /// holds batch submission until it's retried or discarded
+    pub isolated:       bool,
+}
+
+/// A row of the tree, in the order it was written.
+pub struct TreeUpdateEntry {
+    pub id:     usize,
+    pub update: TreeUpdate,
+    /// Root of the tree after the update
+    pub root:   Hash,
+}
+
+/// A root the tree went through, identified by the first row leading to it.
+pub struct RootHistoryEntry {
+    pub id:   usize,
+    pub root: RootItem,
+}
+
+/// A batch handed to the signer, with the transaction which mined its post
+/// root if it was mined.
+pub struct BatchSubmissionEntry {
+    pub transaction_id: String,
+    pub pre_root:       Hash,
+    pub post_root:      Hash,
+    pub mined:          Option<MinedTransaction>,
+}
+
+/// What went into a batch handed to the signer, recorded with its
+/// transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchDetails {
+    /// `insertion` or `deletion`
+    pub kind:           String,
+    /// Lowest and highest leaf the batch updates
+    pub leaf_range:     RangeInclusive<usize>,
+    pub identity_count: usize,
+    /// How long the prover took to prove the batch
+    pub prover_latency: Duration,
+    /// Size of the calldata of the transaction in bytes
+    pub calldata_size:  usize,
+}
+
+/// A batch handed to the signer and what became of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchReport {
+    pub transaction_id: String,
+    pub pre_root:       Hash,
+    pub post_root:      Hash,
+    /// Missing for batches submitted before details were recorded
+    pub details:        Option<BatchDetails>,
+    pub submitted_at:   DateTime<Utc>,
+    pub mined:          Option<MinedTransaction>,
+    /// Known once the cost of the mined transaction was recorded
+    pub gas_used:       Option<u64>,
+}
+
+pub struct UnprocessedIdentityCounts {
+    pub eligible:  usize,
+    pub held_back: usize,
+    pub failed:    usize,
+}
+
+/// The insertions mined since some point in time, from which the wait of
+/// queued identities is estimated.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct InsertionLatency {
+    pub mined_identities: u64,
+    pub mined_batches:    u64,
+    /// Average time from being received to being mined, `None` if nothing
+    /// was mined
+    pub average_seconds:  Option<f64>,
+}
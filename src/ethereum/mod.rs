@@ -1,37 +1,74 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Result as AnyhowResult;
 use clap::Parser;
 use ethers::types::transaction::eip2718::TypedTransaction;
-use ethers::types::Address;
+use ethers::types::{Address, H256};
 pub use read::{EventError, ReadProvider};
-use tracing::instrument;
-use url::Url;
+use tracing::{instrument, warn};
 pub use write::TxError;
 
+use self::read::circuit_breaker::CircuitBreakerConfig;
+use self::signing_log::{SigningLog, SigningPurpose};
 use self::write::{TransactionId, WriteProvider};
+use crate::secret::SecretUrl;
 use crate::serde_utils::JsonStrWrapper;
+use crate::utils::retry::RetryPolicy;
 
 pub mod read;
+pub mod signing_log;
 pub mod write;
 
+mod write_fork;
 mod write_oz;
 
-// TODO: Log and metrics for signer / nonces.
 #[derive(Clone, Debug, PartialEq, Parser)]
 #[group(skip)]
 pub struct Options {
-    /// Ethereum API Provider
+    /// Ethereum API Provider. A `ws://` or `wss://` url subscribes to new
+    /// blocks instead of polling while waiting for transactions to be mined.
+    /// Can be an `env:`, `file:` or `vault:` reference, as the url usually
+    /// holds an API key.
     #[clap(long, env, default_value = "http://localhost:8545")]
-    pub ethereum_provider: Url,
+    pub ethereum_provider: SecretUrl,
 
-    /// Provider urls for the secondary chains
+    /// Provider urls for the secondary chains, each of which can be an `env:`,
+    /// `file:` or `vault:` reference
     #[clap(long, env, default_value = "[]")]
-    pub secondary_providers: JsonStrWrapper<Vec<Url>>,
+    pub secondary_providers: JsonStrWrapper<Vec<SecretUrl>>,
 
     #[clap(flatten)]
     pub write_options: write_oz::Options,
+
+    /// Sends transactions from the impersonated `--oz-address` through the
+    /// Ethereum provider instead of OpenZeppelin Defender. Only works against
+    /// a local fork, e.g. `anvil --fork-url`, to rehearse migrations against
+    /// the deployed contracts. Secondary chains are ignored.
+    #[clap(long, env)]
+    pub fork_mode: bool,
+
+    /// The number of consecutive failed requests to a provider after which
+    /// requests to it fail immediately, until a probe after
+    /// `--provider-breaker-open-seconds` succeeds. Set to 0 to disable.
+    #[clap(long, env, default_value = "5")]
+    pub provider_breaker_failures: u32,
+
+    /// The number of seconds requests to a failing provider fail immediately
+    /// before it's probed again
+    #[clap(long, env, default_value = "30")]
+    pub provider_breaker_open_seconds: u64,
+}
+
+impl Options {
+    #[must_use]
+    pub const fn circuit_breaker(&self) -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            failure_threshold: self.provider_breaker_failures,
+            open_duration:     Duration::from_secs(self.provider_breaker_open_seconds),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -40,33 +77,77 @@ pub struct Ethereum {
     // Mapping of chain id to provider
     secondary_read_providers: HashMap<u64, Arc<ReadProvider>>,
     write_provider:           Arc<dyn WriteProvider>,
+    signing_log:              Arc<SigningLog>,
+    fork_mode:                bool,
 }
 
 impl Ethereum {
     #[instrument(name = "Ethereum::new", level = "debug", skip_all)]
-    pub async fn new(options: Options) -> AnyhowResult<Self> {
-        let read_provider = ReadProvider::new(options.ethereum_provider).await?;
+    pub async fn new(
+        options: Options,
+        retry: RetryPolicy,
+        signing_log: Arc<SigningLog>,
+    ) -> AnyhowResult<Self> {
+        let circuit_breaker = options.circuit_breaker();
+        let read_provider = ReadProvider::new(
+            options.ethereum_provider.expose_url().clone(),
+            circuit_breaker,
+            retry,
+        )
+        .await?;
 
         let mut secondary_read_providers = HashMap::new();
 
-        for secondary_url in &options.secondary_providers.0 {
-            let secondary_read_provider = ReadProvider::new(secondary_url.clone()).await?;
+        // Bridged roots can't be rehearsed on a fork of a single chain.
+        if options.fork_mode && !options.secondary_providers.0.is_empty() {
+            warn!("Ignoring secondary providers in fork mode");
+        }
+
+        let secondary_urls: &[SecretUrl] = if options.fork_mode {
+            &[]
+        } else {
+            &options.secondary_providers.0
+        };
+
+        for secondary_url in secondary_urls {
+            let secondary_read_provider =
+                ReadProvider::new(secondary_url.expose_url().clone(), circuit_breaker, retry)
+                    .await?;
             secondary_read_providers.insert(
                 secondary_read_provider.chain_id.as_u64(),
                 Arc::new(secondary_read_provider),
             );
         }
 
-        let write_provider: Arc<dyn WriteProvider> =
-            Arc::new(write_oz::Provider::new(read_provider.clone(), &options.write_options).await?);
+        let write_provider: Arc<dyn WriteProvider> = if options.fork_mode {
+            Arc::new(
+                write_fork::Provider::new(
+                    read_provider.clone(),
+                    options.write_options.oz_address,
+                    options.write_options.oz_mine_timeout,
+                )
+                .await?,
+            )
+        } else {
+            Arc::new(write_oz::Provider::new(read_provider.clone(), &options.write_options).await?)
+        };
 
         Ok(Self {
             read_provider: Arc::new(read_provider),
             secondary_read_providers,
             write_provider,
+            signing_log,
+            fork_mode: options.fork_mode,
         })
     }
 
+    /// Whether transactions are sent to a local fork, see
+    /// [`Options::fork_mode`].
+    #[must_use]
+    pub const fn fork_mode(&self) -> bool {
+        self.fork_mode
+    }
+
     #[must_use]
     pub const fn provider(&self) -> &Arc<ReadProvider> {
         &self.read_provider
@@ -82,19 +163,57 @@ impl Ethereum {
         self.write_provider.address()
     }
 
+    #[must_use]
+    pub fn signing_log(&self) -> &SigningLog {
+        &self.signing_log
+    }
+
+    /// Sends the transaction through the signer, recording it in the
+    /// [`SigningLog`] whether or not it's submitted.
     pub async fn send_transaction(
         &self,
         tx: TypedTransaction,
         only_once: bool,
+        purpose: SigningPurpose,
     ) -> Result<TransactionId, TxError> {
-        self.write_provider.send_transaction(tx, only_once).await
+        let result = self
+            .write_provider
+            .send_transaction(tx.clone(), only_once)
+            .await;
+
+        self.signing_log
+            .record_transaction(
+                self.address(),
+                self.read_provider.chain_id.as_u64(),
+                purpose,
+                &tx,
+                result.as_ref().ok(),
+            )
+            .await;
+
+        result
     }
 
     pub async fn fetch_pending_transactions(&self) -> Result<Vec<TransactionId>, TxError> {
         self.write_provider.fetch_pending_transactions().await
     }
 
+    /// Waits for the transaction to be mined. The nonce the relayer assigned
+    /// to it is recorded in the [`SigningLog`] then, whether or not the
+    /// transaction succeeded.
     pub async fn mine_transaction(&self, tx: TransactionId) -> Result<bool, TxError> {
-        self.write_provider.mine_transaction(tx).await
+        let mined = self.write_provider.mine_transaction(tx.clone()).await?;
+
+        match self.write_provider.nonce(&tx).await {
+            Ok(Some(nonce)) => self.signing_log.record_nonce(&tx, nonce).await,
+            Ok(None) => {}
+            Err(error) => warn!(?error, %tx, "Failed to fetch the nonce of a mined transaction"),
+        }
+
+        Ok(mined)
+    }
+
+    pub async fn transaction_hash(&self, tx: &TransactionId) -> Result<Option<H256>, TxError> {
+        self.write_provider.transaction_hash(tx).await
     }
 }
@@ -0,0 +1,242 @@
+//! Short lived cache of the chain reads on the hot path. Entries expire after
+//! a TTL. Over websockets, new blocks refresh the cached block number and
+//! expire the other entries right away, since they may have changed with the
+//! block.
+
+use std::future::Future;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use ::prometheus::{register_int_counter_vec, IntCounterVec};
+use anyhow::Result as AnyhowResult;
+use ethers::types::U256;
+use futures::StreamExt;
+use once_cell::sync::Lazy;
+use tokio::time::sleep;
+use tracing::warn;
+
+use super::ReadProvider;
+
+static READS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "chain_read_cache_reads",
+        "Number of cached chain reads, by read and whether the cache had it.",
+        &["read", "outcome"]
+    )
+    .unwrap()
+});
+
+/// Delay before subscribing to new blocks again after the subscription
+/// failed.
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+struct Entry<T> {
+    name:  &'static str,
+    value: Mutex<Option<(T, Instant)>>,
+}
+
+impl<T: Copy> Entry<T> {
+    const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            value: Mutex::new(None),
+        }
+    }
+
+    fn get(&self, ttl: Duration) -> Option<T> {
+        let value = self.value.lock().expect("no lock poisoning");
+        value
+            .filter(|(_, fetched_at)| fetched_at.elapsed() < ttl)
+            .map(|(value, _)| value)
+    }
+
+    fn set(&self, value: T) {
+        *self.value.lock().expect("no lock poisoning") = Some((value, Instant::now()));
+    }
+
+    fn clear(&self) {
+        *self.value.lock().expect("no lock poisoning") = None;
+    }
+
+    /// Returns the cached value, or fetches and caches it. Concurrent misses
+    /// each fetch the value.
+    async fn get_or_fetch(
+        &self,
+        ttl: Duration,
+        fetch: impl Future<Output = AnyhowResult<T>>,
+    ) -> AnyhowResult<T> {
+        if let Some(value) = self.get(ttl) {
+            READS.with_label_values(&[self.name, "hit"]).inc();
+            return Ok(value);
+        }
+
+        READS.with_label_values(&[self.name, "miss"]).inc();
+        let value = fetch.await?;
+        self.set(value);
+
+        Ok(value)
+    }
+}
+
+#[derive(Debug)]
+struct Entries {
+    ttl:          Duration,
+    block_number: Entry<u64>,
+    gas_price:    Entry<U256>,
+    latest_root:  Entry<U256>,
+}
+
+impl Entries {
+    fn new_block(&self, block_number: u64) {
+        self.block_number.set(block_number);
+        self.gas_price.clear();
+        self.latest_root.clear();
+    }
+}
+
+#[derive(Debug)]
+pub struct ChainReadCache {
+    entries: Arc<Entries>,
+}
+
+impl ChainReadCache {
+    /// Caches reads for `ttl`, a zero TTL disables the cache. Follows new
+    /// blocks of the provider if it's connected over websockets.
+    #[must_use]
+    pub fn new(ttl: Duration, provider: &ReadProvider) -> Self {
+        let entries = Arc::new(Entries {
+            ttl,
+            block_number: Entry::new("block_number"),
+            gas_price: Entry::new("gas_price"),
+            latest_root: Entry::new("latest_root"),
+        });
+
+        if !ttl.is_zero() && provider.subscriptions.is_some() {
+            tokio::spawn(follow_blocks(Arc::downgrade(&entries), provider.clone()));
+        }
+
+        Self { entries }
+    }
+
+    pub async fn block_number(
+        &self,
+        fetch: impl Future<Output = AnyhowResult<u64>>,
+    ) -> AnyhowResult<u64> {
+        self.entries
+            .block_number
+            .get_or_fetch(self.entries.ttl, fetch)
+            .await
+    }
+
+    pub async fn gas_price(
+        &self,
+        fetch: impl Future<Output = AnyhowResult<U256>>,
+    ) -> AnyhowResult<U256> {
+        self.entries
+            .gas_price
+            .get_or_fetch(self.entries.ttl, fetch)
+            .await
+    }
+
+    pub async fn latest_root(
+        &self,
+        fetch: impl Future<Output = AnyhowResult<U256>>,
+    ) -> AnyhowResult<U256> {
+        self.entries
+            .latest_root
+            .get_or_fetch(self.entries.ttl, fetch)
+            .await
+    }
+}
+
+/// Refreshes the entries on every new block, until the cache is dropped.
+async fn follow_blocks(entries: Weak<Entries>, provider: ReadProvider) {
+    let Some(subscriptions) = &provider.subscriptions else {
+        return;
+    };
+
+    while entries.strong_count() > 0 {
+        let mut blocks = match subscriptions.subscribe_blocks().await {
+            Ok(blocks) => blocks,
+            Err(error) => {
+                warn!(
+                    ?error,
+                    "Failed to subscribe to new blocks for the read cache"
+                );
+                sleep(RESUBSCRIBE_DELAY).await;
+                continue;
+            }
+        };
+
+        while let Some(block) = blocks.next().await {
+            let Some(entries) = entries.upgrade() else {
+                return;
+            };
+
+            // Pending blocks don't change what's cached.
+            if let Some(number) = block.number {
+                entries.new_block(number.as_u64());
+            }
+        }
+
+        warn!("Subscription to new blocks for the read cache ended");
+        sleep(RESUBSCRIBE_DELAY).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn caches_reads_until_they_expire() -> AnyhowResult<()> {
+        let entry = Entry::new("test");
+        let ttl = Duration::from_millis(50);
+
+        assert_eq!(entry.get_or_fetch(ttl, async { Ok(1) }).await?, 1);
+        assert_eq!(entry.get_or_fetch(ttl, async { Ok(2) }).await?, 1);
+
+        sleep(ttl).await;
+        assert_eq!(entry.get_or_fetch(ttl, async { Ok(3) }).await?, 3);
+
+        // Failed fetches aren't cached.
+        entry.clear();
+        assert!(entry
+            .get_or_fetch(ttl, async { Err(anyhow::anyhow!("failed")) })
+            .await
+            .is_err());
+        assert_eq!(entry.get(ttl), None);
+
+        // A zero TTL disables the cache.
+        assert_eq!(
+            entry.get_or_fetch(Duration::ZERO, async { Ok(4) }).await?,
+            4
+        );
+        assert_eq!(
+            entry.get_or_fetch(Duration::ZERO, async { Ok(5) }).await?,
+            5
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn new_blocks_expire_entries() {
+        let entries = Entries {
+            ttl:          Duration::from_secs(60),
+            block_number: Entry::new("block_number"),
+            gas_price:    Entry::new("gas_price"),
+            latest_root:  Entry::new("latest_root"),
+        };
+        entries.block_number.set(1);
+        entries.gas_price.set(U256::from(10));
+        entries.latest_root.set(U256::from(42));
+
+        entries.new_block(2);
+
+        assert_eq!(entries.block_number.get(entries.ttl), Some(2));
+        assert_eq!(entries.gas_price.get(entries.ttl), None);
+        assert_eq!(entries.latest_root.get(entries.ttl), None);
+    }
+}
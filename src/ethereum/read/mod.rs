@@ -1,42 +1,64 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use anyhow::{anyhow, Result as AnyhowResult};
 use chrono::{Duration as ChronoDuration, Utc};
 use ethers::abi::Error as AbiError;
-use ethers::providers::{Http, Middleware, Provider};
+use ethers::providers::{Middleware, Provider, ProviderError, Ws};
 use ethers::types::{BlockId, BlockNumber, Chain, U256};
-use futures::{try_join, FutureExt};
+use futures::{try_join, FutureExt, StreamExt};
 use thiserror::Error;
-use tracing::{error, info};
+use tokio::time::{sleep, timeout};
+use tracing::{error, info, warn};
 use url::Url;
 
+use self::circuit_breaker::{Breaker, BreakerStatus, CircuitBreaker, CircuitBreakerConfig};
+use self::retry::Retry;
 use self::rpc_logger::RpcLogger;
+use self::transport::Transport;
+use crate::utils::retry::RetryPolicy;
 
+pub mod cache;
+pub mod circuit_breaker;
+pub mod retry;
 pub mod rpc_logger;
+pub mod transport;
 
-type InnerProvider = Provider<RpcLogger<Http>>;
+type InnerProvider = Provider<RpcLogger<Retry<CircuitBreaker<Transport>>>>;
 
 #[derive(Clone, Debug)]
 pub struct ReadProvider {
-    inner:        InnerProvider,
-    pub chain_id: U256,
-    pub legacy:   bool,
+    inner:         InnerProvider,
+    breaker:       Arc<Breaker>,
+    /// Subscriptions to new blocks, only available over websockets.
+    subscriptions: Option<Provider<Ws>>,
+    pub chain_id:  U256,
+    pub legacy:    bool,
 }
 
 impl ReadProvider {
-    pub async fn new(url: Url) -> AnyhowResult<Self> {
+    pub async fn new(
+        url: Url,
+        breaker_config: CircuitBreakerConfig,
+        retry: RetryPolicy,
+    ) -> AnyhowResult<Self> {
         // Connect to the Ethereum provider
         // TODO: Allow multiple providers with failover / broadcast.
         // TODO: Requests don't seem to process in parallel. Check if this is
         // a limitation client side or server side.
         // TODO: Does the WebSocket impl handle dropped connections by
-        // reconnecting? What is the timeout on stalled connections? What is
-        // the retry policy?
-        let (provider, chain_id, eip1559) = {
+        // reconnecting? What is the timeout on stalled connections?
+        let (provider, breaker, subscriptions, chain_id, eip1559) = {
             info!(
                 provider = %url,
                 "Connecting to provider"
             );
-            let transport = Http::new(url);
-            let logger = RpcLogger::new(transport);
+            let endpoint = circuit_breaker::endpoint(&url);
+            let transport = Transport::connect(url).await?;
+            let subscriptions = transport.websocket().cloned().map(Provider::new);
+            let breaker = CircuitBreaker::new(transport, endpoint, breaker_config);
+            let breaker_state = breaker.breaker().clone();
+            let logger = RpcLogger::new(Retry::new(breaker, retry));
             let provider = Provider::new(logger);
 
             // Fetch state of the chain.
@@ -77,15 +99,45 @@ impl ReadProvider {
                 // Log an error, but proceed anyway since this doesn't technically block us.
                 error!(%now, %block_time, %block_age, "Block time is more than 30 minutes from now.");
             }
-            (provider, chain_id, eip1559)
+            (provider, breaker_state, subscriptions, chain_id, eip1559)
         };
 
         Ok(Self {
             inner: provider,
+            breaker,
+            subscriptions,
             chain_id,
             legacy: !eip1559,
         })
     }
+
+    /// The state of the circuit breaker in front of the provider.
+    #[must_use]
+    pub fn breaker_status(&self) -> BreakerStatus {
+        self.breaker.status()
+    }
+
+    /// Waits for the next block to be mined, for at most `poll_interval`.
+    ///
+    /// Over websockets this returns as soon as the provider announces a new
+    /// head, otherwise it just sleeps for the poll interval.
+    pub async fn wait_for_new_block(&self, poll_interval: Duration) {
+        let Some(subscriptions) = &self.subscriptions else {
+            sleep(poll_interval).await;
+            return;
+        };
+
+        let new_block = async {
+            let mut blocks = subscriptions.subscribe_blocks().await?;
+            blocks.next().await;
+            Ok::<_, ProviderError>(())
+        };
+
+        if let Ok(Err(error)) = timeout(poll_interval, new_block).await {
+            warn!(?error, "Failed to subscribe to new blocks, polling instead");
+            sleep(poll_interval).await;
+        }
+    }
 }
 
 impl Middleware for ReadProvider {
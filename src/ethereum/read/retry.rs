@@ -0,0 +1,73 @@
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+use ethers::providers::{JsonRpcClient, ProviderError, RpcError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::utils::retry::{RetryPolicy, Retryable};
+
+/// Methods which must not be retried, as a lost response doesn't mean the
+/// transaction wasn't broadcast.
+const NON_IDEMPOTENT_METHODS: [&str; 2] = ["eth_sendTransaction", "eth_sendRawTransaction"];
+
+/// Error codes of providers rejecting requests over their rate limit.
+const RATE_LIMIT_CODES: [i64; 2] = [-32005, 429];
+
+impl Retryable for ProviderError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::JsonRpcClientError(error) => {
+                if let Some(response) = error.as_error_response() {
+                    RATE_LIMIT_CODES.contains(&response.code)
+                } else {
+                    // Responses which don't parse won't parse the next time,
+                    // anything else failed to reach the provider.
+                    error.as_serde_error().is_none()
+                }
+            }
+            Self::HTTPError(error) => error.is_retryable(),
+            // Including requests rejected by an open circuit breaker.
+            _ => false,
+        }
+    }
+}
+
+/// Retries requests failing with transient errors, except those sending
+/// transactions.
+#[derive(Clone, Debug)]
+pub struct Retry<Inner> {
+    inner:  Inner,
+    policy: RetryPolicy,
+}
+
+impl<Inner> Retry<Inner> {
+    pub const fn new(inner: Inner, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+}
+
+#[async_trait]
+impl<Inner> JsonRpcClient for Retry<Inner>
+where
+    Inner: JsonRpcClient<Error = ProviderError> + 'static,
+{
+    type Error = ProviderError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        if NON_IDEMPOTENT_METHODS.contains(&method) {
+            return self.inner.request(method, params).await;
+        }
+
+        // Parameters are serialized once to be sent with every attempt.
+        let params = serde_json::to_value(params)?;
+
+        self.policy
+            .retry("chain", || self.inner.request(method, &params))
+            .await
+    }
+}
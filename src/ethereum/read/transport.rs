@@ -0,0 +1,48 @@
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+use ethers::providers::{Http, JsonRpcClient, ProviderError, Ws};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use url::Url;
+
+/// The transport to the provider, selected from the scheme of its url.
+#[derive(Debug, Clone)]
+pub enum Transport {
+    Http(Http),
+    Ws(Ws),
+}
+
+impl Transport {
+    pub async fn connect(url: Url) -> Result<Self, ProviderError> {
+        match url.scheme() {
+            "ws" | "wss" => Ok(Self::Ws(Ws::connect(url).await?)),
+            _ => Ok(Self::Http(Http::new(url))),
+        }
+    }
+
+    /// The websocket connection, if the provider supports subscriptions.
+    #[must_use]
+    pub const fn websocket(&self) -> Option<&Ws> {
+        match self {
+            Self::Http(_) => None,
+            Self::Ws(ws) => Some(ws),
+        }
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for Transport {
+    type Error = ProviderError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        match self {
+            Self::Http(http) => Ok(http.request(method, params).await?),
+            Self::Ws(ws) => Ok(ws.request(method, params).await?),
+        }
+    }
+}
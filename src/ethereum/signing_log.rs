@@ -0,0 +1,300 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Address, H256, U256};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter, register_int_counter_vec, IntCounter, IntCounterVec};
+use serde::Serialize;
+use thiserror::Error;
+use tracing::{error, info};
+
+use super::write::TransactionId;
+use crate::database::{Database, Error as DatabaseError};
+
+static SIGNING_OPERATIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "signer_operations",
+        "Number of transactions handed to the signer, by purpose and outcome.",
+        &["purpose", "outcome"]
+    )
+    .unwrap()
+});
+
+static UNRECORDED_OPERATIONS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "signer_operations_unrecorded",
+        "Number of signing operations which couldn't be stored in the database."
+    )
+    .unwrap()
+});
+
+/// Signing purpose or outcome stored in the database which this version
+/// doesn't know.
+#[derive(Debug, Error)]
+#[error("unknown signing operation attribute: {0}")]
+pub struct UnknownAttribute(String);
+
+/// Why a transaction was signed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SigningPurpose {
+    InsertionBatch,
+    DeletionBatch,
+    /// A copy of a batch submitted to the shadow identity manager
+    ShadowBatch,
+    InsertionReceipt,
+    NonInclusionAttestation,
+}
+
+impl SigningPurpose {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::InsertionBatch => "insertionBatch",
+            Self::DeletionBatch => "deletionBatch",
+            Self::ShadowBatch => "shadowBatch",
+            Self::InsertionReceipt => "insertionReceipt",
+            Self::NonInclusionAttestation => "nonInclusionAttestation",
+        }
+    }
+}
+
+impl FromStr for SigningPurpose {
+    type Err = UnknownAttribute;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "insertionBatch" => Ok(Self::InsertionBatch),
+            "deletionBatch" => Ok(Self::DeletionBatch),
+            "shadowBatch" => Ok(Self::ShadowBatch),
+            "insertionReceipt" => Ok(Self::InsertionReceipt),
+            "nonInclusionAttestation" => Ok(Self::NonInclusionAttestation),
+            _ => Err(UnknownAttribute(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SigningOutcome {
+    /// A transaction handed to the relayer
+    Submitted,
+    /// A digest signed with the receipt signing key
+    Signed,
+    Failed,
+}
+
+impl SigningOutcome {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Submitted => "submitted",
+            Self::Signed => "signed",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+impl FromStr for SigningOutcome {
+    type Err = UnknownAttribute;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "submitted" => Ok(Self::Submitted),
+            "signed" => Ok(Self::Signed),
+            "failed" => Ok(Self::Failed),
+            _ => Err(UnknownAttribute(s.to_owned())),
+        }
+    }
+}
+
+/// A transaction handed to the signer, or a digest signed on behalf of the
+/// sequencer.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SigningRecord {
+    pub signer:         Address,
+    pub purpose:        SigningPurpose,
+    /// Hash of the unsigned transaction as handed to the signer, or the
+    /// signed digest
+    pub payload_digest: H256,
+    /// `None` until the relayer assigned the nonce, and for digests
+    pub nonce:          Option<U256>,
+    pub chain_id:       u64,
+    pub outcome:        SigningOutcome,
+    pub transaction_id: Option<String>,
+    pub timestamp:      DateTime<Utc>,
+}
+
+/// Attributes every transaction and digest signed on behalf of the
+/// sequencer, through structured logs, metrics and the `signing_audit_log`
+/// table.
+#[derive(Default)]
+pub struct SigningLog {
+    /// `None` where nothing is persisted, e.g. in tests.
+    database: Option<Arc<Database>>,
+}
+
+impl fmt::Debug for SigningLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigningLog")
+            .field("persisted", &self.database.is_some())
+            .finish()
+    }
+}
+
+impl SigningLog {
+    #[must_use]
+    pub const fn new(database: Arc<Database>) -> Self {
+        Self {
+            database: Some(database),
+        }
+    }
+
+    /// Records a transaction handed to `signer`, along with the result of
+    /// submitting it.
+    pub async fn record_transaction(
+        &self,
+        signer: Address,
+        chain_id: u64,
+        purpose: SigningPurpose,
+        tx: &TypedTransaction,
+        result: Option<&TransactionId>,
+    ) {
+        self.record(SigningRecord {
+            signer,
+            purpose,
+            payload_digest: tx.sighash(),
+            nonce: tx.nonce().copied(),
+            chain_id: tx.chain_id().map_or(chain_id, |chain_id| chain_id.as_u64()),
+            outcome: if result.is_some() {
+                SigningOutcome::Submitted
+            } else {
+                SigningOutcome::Failed
+            },
+            transaction_id: result.map(ToString::to_string),
+            timestamp: Utc::now(),
+        })
+        .await;
+    }
+
+    /// Records a digest signed by `signer`.
+    pub async fn record_digest(
+        &self,
+        signer: Address,
+        chain_id: u64,
+        purpose: SigningPurpose,
+        digest: H256,
+        signed: bool,
+    ) {
+        self.record(SigningRecord {
+            signer,
+            purpose,
+            payload_digest: digest,
+            nonce: None,
+            chain_id,
+            outcome: if signed {
+                SigningOutcome::Signed
+            } else {
+                SigningOutcome::Failed
+            },
+            transaction_id: None,
+            timestamp: Utc::now(),
+        })
+        .await;
+    }
+
+    /// Records the nonce the relayer assigned to a submitted transaction.
+    pub async fn record_nonce(&self, transaction_id: &TransactionId, nonce: U256) {
+        info!(
+            target: "signer_audit",
+            transaction_id = %transaction_id,
+            %nonce,
+            "Signing operation nonce assigned"
+        );
+
+        let Some(database) = &self.database else {
+            return;
+        };
+
+        if let Err(error) = database
+            .set_signing_record_nonce(transaction_id.as_ref(), nonce)
+            .await
+        {
+            error!(?error, %transaction_id, "Failed to record the nonce of a signing operation");
+        }
+    }
+
+    async fn record(&self, record: SigningRecord) {
+        info!(
+            target: "signer_audit",
+            signer = ?record.signer,
+            purpose = record.purpose.as_str(),
+            payload_digest = ?record.payload_digest,
+            nonce = ?record.nonce,
+            chain_id = record.chain_id,
+            outcome = record.outcome.as_str(),
+            transaction_id = ?record.transaction_id,
+            "Signing operation"
+        );
+        SIGNING_OPERATIONS
+            .with_label_values(&[record.purpose.as_str(), record.outcome.as_str()])
+            .inc();
+
+        let Some(database) = &self.database else {
+            return;
+        };
+
+        // The operation already happened, failing it now would only hide it
+        // from the caller. It's still in the logs.
+        if let Err(error) = database.insert_signing_record(&record).await {
+            error!(?error, "Failed to store a signing operation");
+            UNRECORDED_OPERATIONS.inc();
+        }
+    }
+
+    /// The recorded operations in `[from, to)`, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database can't be queried.
+    pub async fn records(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SigningRecord>, DatabaseError> {
+        match &self.database {
+            Some(database) => database.get_signing_records(from, to).await,
+            None => Ok(vec![]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attributes_are_read_back() {
+        for purpose in [
+            SigningPurpose::InsertionBatch,
+            SigningPurpose::DeletionBatch,
+            SigningPurpose::ShadowBatch,
+            SigningPurpose::InsertionReceipt,
+            SigningPurpose::NonInclusionAttestation,
+        ] {
+            assert_eq!(purpose.as_str().parse::<SigningPurpose>().unwrap(), purpose);
+        }
+        for outcome in [
+            SigningOutcome::Submitted,
+            SigningOutcome::Signed,
+            SigningOutcome::Failed,
+        ] {
+            assert_eq!(outcome.as_str().parse::<SigningOutcome>().unwrap(), outcome);
+        }
+        assert!("unknown".parse::<SigningPurpose>().is_err());
+    }
+}
@@ -4,7 +4,7 @@ use std::fmt;
 use async_trait::async_trait;
 use ethers::providers::ProviderError;
 use ethers::types::transaction::eip2718::TypedTransaction;
-use ethers::types::{Address, TransactionReceipt, H256};
+use ethers::types::{Address, TransactionReceipt, H256, U256};
 use thiserror::Error;
 
 #[derive(Clone, Debug)]
@@ -65,5 +65,13 @@ pub trait WriteProvider: Sync + Send + fmt::Debug {
 
     async fn mine_transaction(&self, tx: TransactionId) -> Result<bool, TxError>;
 
+    /// The hash of a sent transaction, `None` while it isn't broadcast yet.
+    async fn transaction_hash(&self, tx: &TransactionId) -> Result<Option<H256>, TxError>;
+
+    /// The nonce of a sent transaction, `None` while it isn't assigned yet.
+    async fn nonce(&self, _tx: &TransactionId) -> Result<Option<U256>, TxError> {
+        Ok(None)
+    }
+
     fn address(&self) -> Address;
 }
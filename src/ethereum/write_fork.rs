@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result as AnyhowResult};
+use async_trait::async_trait;
+use ethers::providers::Middleware;
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Address, H256, U256, U64};
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+use super::write::{TransactionId, WriteProvider};
+use super::{ReadProvider, TxError};
+
+/// Upper bound on how long to wait between checks of a pending transaction.
+const MINE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Sends transactions through a local fork of a live chain, e.g. `anvil
+/// --fork-url`, from an impersonated account instead of a relayer. This lets
+/// batches be submitted to the deployed contracts without their keys.
+#[derive(Debug)]
+pub struct Provider {
+    read_provider: ReadProvider,
+    address:       Address,
+    mine_timeout:  Duration,
+}
+
+impl Provider {
+    pub async fn new(
+        read_provider: ReadProvider,
+        address: Address,
+        mine_timeout: Duration,
+    ) -> AnyhowResult<Self> {
+        // Only development nodes allow impersonation, so this also guards
+        // against running in fork mode against a live chain.
+        read_provider
+            .request::<_, ()>("anvil_impersonateAccount", [address])
+            .await
+            .context("Fork mode requires a provider which supports impersonating accounts")?;
+
+        warn!(
+            ?address,
+            "Running in fork mode, transactions are sent from the impersonated address. Never use \
+             this against a live chain."
+        );
+
+        Ok(Self {
+            read_provider,
+            address,
+            mine_timeout,
+        })
+    }
+}
+
+#[async_trait]
+impl WriteProvider for Provider {
+    async fn send_transaction(
+        &self,
+        mut tx: TypedTransaction,
+        _only_once: bool,
+    ) -> Result<TransactionId, TxError> {
+        tx.set_from(self.address);
+
+        let pending = self
+            .read_provider
+            .send_transaction(tx, None)
+            .await
+            .map_err(|err| TxError::Send(err.into()))?;
+
+        let tx_hash = pending.tx_hash();
+        info!(?tx_hash, "Sent transaction to the fork");
+
+        Ok(TransactionId(format!("{tx_hash:?}")))
+    }
+
+    async fn fetch_pending_transactions(&self) -> Result<Vec<TransactionId>, TxError> {
+        // Transactions are mined as soon as they're sent to a fork, nothing
+        // is left pending across restarts.
+        Ok(vec![])
+    }
+
+    async fn mine_transaction(&self, tx: TransactionId) -> Result<bool, TxError> {
+        let tx_hash: H256 = tx.0.parse().map_err(|err| TxError::Parse(Box::new(err)))?;
+
+        let receipt = timeout(self.mine_timeout, async {
+            loop {
+                let receipt = self
+                    .read_provider
+                    .get_transaction_receipt(tx_hash)
+                    .await
+                    .map_err(|err| TxError::Fetch(err.into()))?;
+
+                if let Some(receipt) = receipt {
+                    return Ok::<_, TxError>(receipt);
+                }
+
+                self.read_provider
+                    .wait_for_new_block(MINE_POLL_INTERVAL)
+                    .await;
+            }
+        })
+        .await
+        .map_err(|_| TxError::ConfirmationTimeout)??;
+
+        if receipt.status == Some(U64::from(1u64)) {
+            Ok(true)
+        } else {
+            warn!(?receipt, "Transaction failed");
+
+            Ok(false)
+        }
+    }
+
+    async fn transaction_hash(&self, tx: &TransactionId) -> Result<Option<H256>, TxError> {
+        // Transactions are identified by their hash on a fork.
+        let tx_hash = tx.0.parse().map_err(|err| TxError::Parse(Box::new(err)))?;
+
+        Ok(Some(tx_hash))
+    }
+
+    async fn nonce(&self, tx: &TransactionId) -> Result<Option<U256>, TxError> {
+        let tx_hash: H256 = tx.0.parse().map_err(|err| TxError::Parse(Box::new(err)))?;
+
+        let transaction = self
+            .read_provider
+            .get_transaction(tx_hash)
+            .await
+            .map_err(|err| TxError::Fetch(err.into()))?;
+
+        Ok(transaction.map(|transaction| transaction.nonce))
+    }
+
+    fn address(&self) -> Address {
+        self.address
+    }
+}
@@ -7,12 +7,13 @@ use async_trait::async_trait;
 use clap::Parser;
 use ethers::providers::Middleware;
 use ethers::types::transaction::eip2718::TypedTransaction;
-use ethers::types::{Address, H160, U64};
+use ethers::types::{Address, H160, H256, U256, U64};
 use tracing::{info, warn};
 
 use self::openzeppelin::OzRelay;
 use super::write::{TransactionId, WriteProvider};
 use super::{ReadProvider, TxError};
+use crate::secret::Secret;
 
 mod error;
 mod openzeppelin;
@@ -21,7 +22,6 @@ fn duration_from_str(value: &str) -> Result<Duration, ParseIntError> {
     Ok(Duration::from_secs(u64::from_str(value)?))
 }
 
-// TODO: Log and metrics for signer / nonces.
 #[derive(Clone, Debug, Eq, PartialEq, Parser)]
 #[group(skip)]
 pub struct Options {
@@ -30,11 +30,11 @@ pub struct Options {
 
     /// OpenZeppelin Defender API Key
     #[clap(long, env)]
-    pub oz_api_key: String,
+    pub oz_api_key: Secret,
 
     /// OpenZeppelin Defender API Secret
     #[clap(long, env)]
-    pub oz_api_secret: String,
+    pub oz_api_secret: Secret,
 
     /// OpenZeppelin Defender API Secret
     #[clap(long, env)]
@@ -64,7 +64,7 @@ pub struct Provider {
 
 impl Provider {
     pub async fn new(read_provider: ReadProvider, options: &Options) -> AnyhowResult<Self> {
-        let relay = OzRelay::new(options).await?;
+        let relay = OzRelay::new(read_provider.clone(), options).await?;
 
         Ok(Self {
             read_provider,
@@ -130,6 +130,14 @@ impl WriteProvider for Provider {
         }
     }
 
+    async fn transaction_hash(&self, tx: &TransactionId) -> Result<Option<H256>, TxError> {
+        self.inner.transaction_hash(tx).await
+    }
+
+    async fn nonce(&self, tx: &TransactionId) -> Result<Option<U256>, TxError> {
+        self.inner.nonce(tx).await
+    }
+
     fn address(&self) -> Address {
         self.address
     }
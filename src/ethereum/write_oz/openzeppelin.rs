@@ -2,7 +2,9 @@ use std::fmt::Debug;
 use std::time::Duration;
 
 use anyhow::Result as AnyhowResult;
+use ethers::providers::Middleware;
 use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{TransactionReceipt, H256, U256, U64};
 use once_cell::sync::Lazy;
 use oz_api::data::transactions::{RelayerTransactionBase, SendBaseTransactionRequest, Status};
 use oz_api::OzApi;
@@ -13,7 +15,10 @@ use tracing::{error, info, info_span, Instrument};
 use super::error::Error;
 use super::Options;
 use crate::ethereum::write::TransactionId;
-use crate::ethereum::TxError;
+use crate::ethereum::{ReadProvider, TxError};
+
+/// Upper bound on how long to wait between checks of a pending transaction.
+const MINE_POLL_INTERVAL: Duration = Duration::from_secs(5);
 
 static TX_COUNT: Lazy<IntCounterVec> = Lazy::new(|| {
     register_int_counter_vec!("eth_tx_count", "The transaction count by bytes4.", &[
@@ -22,9 +27,23 @@ static TX_COUNT: Lazy<IntCounterVec> = Lazy::new(|| {
     .unwrap()
 });
 
+/// Whether a transaction was mined, given its receipt if any.
+///
+/// # Errors
+///
+/// Returns [`TxError::Failed`] if the transaction was mined but reverted.
+fn is_mined(receipt: Option<TransactionReceipt>) -> Result<bool, TxError> {
+    match receipt {
+        Some(receipt) if receipt.status == Some(U64::zero()) => Err(TxError::Failed(Some(receipt))),
+        Some(_) => Ok(true),
+        None => Ok(false),
+    }
+}
+
 #[derive(Debug)]
 pub struct OzRelay {
     oz_api:               OzApi,
+    read_provider:        ReadProvider,
     transaction_validity: chrono::Duration,
     send_timeout:         Duration,
     mine_timeout:         Duration,
@@ -32,7 +51,7 @@ pub struct OzRelay {
 }
 
 impl OzRelay {
-    pub async fn new(options: &Options) -> AnyhowResult<Self> {
+    pub async fn new(read_provider: ReadProvider, options: &Options) -> AnyhowResult<Self> {
         let oz_api = if options.oz_api_key.is_empty() && options.oz_api_secret.is_empty() {
             tracing::warn!(
                 "OpenZeppelin Defender API Key and Secret are empty. Connection will operate \
@@ -43,14 +62,15 @@ impl OzRelay {
         } else {
             OzApi::new(
                 &options.oz_api_url,
-                &options.oz_api_key,
-                &options.oz_api_secret,
+                options.oz_api_key.expose(),
+                options.oz_api_secret.expose(),
             )
             .await?
         };
 
         Ok(Self {
             oz_api,
+            read_provider,
             transaction_validity: chrono::Duration::from_std(options.oz_transaction_validity)?,
             send_timeout: options.oz_send_timeout,
             mine_timeout: options.oz_mine_timeout,
@@ -86,10 +106,39 @@ impl OzRelay {
             // provided
             match status {
                 Status::Failed => return Err(TxError::Failed(None)),
-                Status::Mined | Status::Confirmed => return Ok(transaction),
+                Status::Mined | Status::Confirmed => {
+                    // Reverted transactions are mined too.
+                    if let Some(hash) = transaction.hash {
+                        let receipt = self
+                            .read_provider
+                            .get_transaction_receipt(hash)
+                            .await
+                            .map_err(|error| TxError::Fetch(error.into()))?;
+
+                        is_mined(receipt)?;
+                    }
+
+                    return Ok(transaction);
+                }
                 _ => {
-                    info!("waiting 5 s to mine");
-                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    // OpenZeppelin only notices the transaction was mined some
+                    // time after the fact, so check the chain directly.
+                    if let Some(hash) = transaction.hash {
+                        let receipt = self
+                            .read_provider
+                            .get_transaction_receipt(hash)
+                            .await
+                            .map_err(|error| TxError::Fetch(error.into()))?;
+
+                        if is_mined(receipt)? {
+                            return Ok(transaction);
+                        }
+                    }
+
+                    info!("waiting for a new block to mine");
+                    self.read_provider
+                        .wait_for_new_block(MINE_POLL_INTERVAL)
+                        .await;
                 }
             }
         }
@@ -200,6 +249,29 @@ impl OzRelay {
         self.mine_transaction_id(tx_id.0.as_str()).await
     }
 
+    /// The hash OpenZeppelin broadcast the transaction with, `None` until it's
+    /// broadcast. It changes if the transaction is resubmitted with a higher
+    /// gas price.
+    pub async fn transaction_hash(&self, tx_id: &TransactionId) -> Result<Option<H256>, TxError> {
+        let transaction = self
+            .query(tx_id.as_ref())
+            .await
+            .map_err(|err| TxError::Fetch(Box::new(err)))?;
+
+        Ok(transaction.hash)
+    }
+
+    /// The nonce OpenZeppelin assigned to the transaction, `None` until it's
+    /// assigned.
+    pub async fn nonce(&self, tx_id: &TransactionId) -> Result<Option<U256>, TxError> {
+        let transaction = self
+            .query(tx_id.as_ref())
+            .await
+            .map_err(|err| TxError::Fetch(Box::new(err)))?;
+
+        Ok(transaction.nonce.map(U256::from))
+    }
+
     pub async fn fetch_pending_transactions(&self) -> Result<Vec<TransactionId>, TxError> {
         let recent_pending_txs = self
             .list_recent_transactions()
@@ -214,3 +286,25 @@ impl OzRelay {
         Ok(pending_txs)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt(status: u64) -> TransactionReceipt {
+        TransactionReceipt {
+            status: Some(U64::from(status)),
+            ..TransactionReceipt::default()
+        }
+    }
+
+    #[test]
+    fn reverted_transactions_are_failed() {
+        assert!(!is_mined(None).unwrap());
+        assert!(is_mined(Some(receipt(1))).unwrap());
+        assert!(matches!(
+            is_mined(Some(receipt(0))),
+            Err(TxError::Failed(Some(receipt))) if receipt.status == Some(U64::zero())
+        ));
+    }
+}
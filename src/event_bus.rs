@@ -0,0 +1,337 @@
+//! Publishing of the lifecycle of identities to infrastructure consumers,
+//! such as indexers or analytics pipelines.
+//!
+//! The events of the lifecycle (`pending`, `submitted`, `mined` and `failed`)
+//! are added to the `event_outbox` table along with the identity event they
+//! stem from, and published from there. They are removed once the sink
+//! acknowledged them, so every event is published at least once, and
+//! consumers tell redelivered events apart by their `id`.
+
+use std::sync::Arc;
+
+use anyhow::{bail, Result as AnyhowResult};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
+
+use crate::database::types::LoggedIdentityEvent;
+use crate::identity_tree::{Hash, IdentityEvent};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SinkKind {
+    /// Lifecycle events are discarded
+    #[default]
+    None,
+    /// A Kafka topic, requires the `kafka` feature
+    Kafka,
+    /// A Redis stream, requires the `redis` feature
+    Redis,
+    /// An AWS SNS topic, requires the `sns` feature
+    Sns,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Where the lifecycle events of identities are published to
+    #[clap(long, env, value_enum, default_value = "none")]
+    pub event_bus: SinkKind,
+
+    /// Brokers of the Kafka sink (`broker-1:9092,broker-2:9092`) or URL of
+    /// the Redis sink (`redis://localhost:6379`). The SNS sink is configured
+    /// through the usual `AWS_*` variables.
+    #[clap(long, env)]
+    pub event_bus_url: Option<String>,
+
+    /// The Kafka topic, Redis stream or SNS topic ARN events are published to
+    #[clap(long, env, default_value = "identity-lifecycle")]
+    pub event_bus_topic: String,
+
+    /// The number of milliseconds between polls of the outbox
+    #[clap(long, env, default_value = "1000")]
+    pub event_bus_poll_ms: u64,
+
+    /// The most events taken from the outbox at a time
+    #[clap(long, env, default_value = "500")]
+    pub event_bus_batch_size: i64,
+}
+
+/// An event of the lifecycle of an identity as published.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LifecycleEvent {
+    /// Increases with every event, the same event is always published with
+    /// the same id
+    pub id:                  u64,
+    pub identity_commitment: Hash,
+    pub event:               &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message:             Option<String>,
+    pub timestamp:           DateTime<Utc>,
+}
+
+impl From<LoggedIdentityEvent> for LifecycleEvent {
+    fn from(logged: LoggedIdentityEvent) -> Self {
+        let event = match logged.event.event {
+            IdentityEvent::Received => "pending",
+            event => event.into(),
+        };
+
+        Self {
+            id: logged.id,
+            identity_commitment: logged.commitment,
+            event,
+            message: logged.event.message,
+            timestamp: logged.event.created_at,
+        }
+    }
+}
+
+impl LifecycleEvent {
+    /// Events of the same identity share a key, so that sinks which partition
+    /// by key keep them in order.
+    #[must_use]
+    pub fn key(&self) -> String {
+        format!("{:#x}", self.identity_commitment)
+    }
+}
+
+#[async_trait]
+pub trait EventSink: Send + Sync {
+    /// Publishes an event, returning once the sink acknowledged it.
+    async fn publish(&self, event: &LifecycleEvent) -> AnyhowResult<()>;
+}
+
+/// Connects to the sink of the options, `None` if events are discarded.
+///
+/// # Errors
+///
+/// Will return `Err` if the sink isn't compiled in, misconfigured or can't be
+/// connected to.
+pub async fn connect(options: &Options) -> AnyhowResult<Option<Arc<dyn EventSink>>> {
+    let topic = options.event_bus_topic.clone();
+    let url = options.event_bus_url.as_deref();
+
+    let sink: Arc<dyn EventSink> = match options.event_bus {
+        SinkKind::None => return Ok(None),
+        #[cfg(feature = "kafka")]
+        SinkKind::Kafka => {
+            let Some(brokers) = url else {
+                bail!("--event-bus kafka requires --event-bus-url");
+            };
+            Arc::new(kafka::KafkaSink::new(brokers, topic)?)
+        }
+        #[cfg(feature = "redis")]
+        SinkKind::Redis => {
+            let Some(url) = url else {
+                bail!("--event-bus redis requires --event-bus-url");
+            };
+            Arc::new(redis::RedisSink::connect(url, topic).await?)
+        }
+        #[cfg(feature = "sns")]
+        SinkKind::Sns => Arc::new(sns::SnsSink::connect(topic).await),
+        #[allow(unreachable_patterns)]
+        kind => {
+            let _ = (topic, url);
+            bail!("--event-bus {kind:?} isn't supported by this build")
+        }
+    };
+
+    Ok(Some(sink))
+}
+
+#[cfg(feature = "kafka")]
+mod kafka {
+    use std::time::Duration;
+
+    use rdkafka::config::ClientConfig;
+    use rdkafka::producer::{FutureProducer, FutureRecord};
+
+    use super::*;
+
+    pub struct KafkaSink {
+        producer: FutureProducer,
+        topic:    String,
+    }
+
+    impl KafkaSink {
+        pub fn new(brokers: &str, topic: String) -> AnyhowResult<Self> {
+            let producer = ClientConfig::new()
+                .set("bootstrap.servers", brokers)
+                .set("enable.idempotence", "true")
+                .create()?;
+
+            Ok(Self { producer, topic })
+        }
+    }
+
+    #[async_trait]
+    impl EventSink for KafkaSink {
+        async fn publish(&self, event: &LifecycleEvent) -> AnyhowResult<()> {
+            let payload = serde_json::to_vec(event)?;
+            let key = event.key();
+            let record = FutureRecord::to(&self.topic).key(&key).payload(&payload);
+
+            self.producer
+                .send(record, Duration::from_secs(30))
+                .await
+                .map_err(|(error, _)| error)?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+mod redis {
+    use ::redis::aio::ConnectionManager;
+    use ::redis::Client;
+
+    use super::*;
+
+    /// Appends events to a stream with `XADD`, as entries with the fields of
+    /// the event.
+    pub struct RedisSink {
+        connection: ConnectionManager,
+        stream:     String,
+    }
+
+    impl RedisSink {
+        pub async fn connect(url: &str, stream: String) -> AnyhowResult<Self> {
+            let connection = Client::open(url)?.get_connection_manager().await?;
+            Ok(Self { connection, stream })
+        }
+    }
+
+    #[async_trait]
+    impl EventSink for RedisSink {
+        async fn publish(&self, event: &LifecycleEvent) -> AnyhowResult<()> {
+            let mut connection = self.connection.clone();
+            let mut command = ::redis::cmd("XADD");
+            command
+                .arg(&self.stream)
+                .arg("*")
+                .arg("id")
+                .arg(event.id)
+                .arg("identityCommitment")
+                .arg(event.key())
+                .arg("event")
+                .arg(event.event)
+                .arg("timestamp")
+                .arg(event.timestamp.to_rfc3339());
+            if let Some(message) = &event.message {
+                command.arg("message").arg(message);
+            }
+
+            command.query_async::<_, String>(&mut connection).await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "sns")]
+mod sns {
+    use aws_sdk_sns::Client;
+
+    use super::*;
+
+    pub struct SnsSink {
+        client:    Client,
+        topic_arn: String,
+    }
+
+    impl SnsSink {
+        pub async fn connect(topic_arn: String) -> Self {
+            let config = aws_config::load_from_env().await;
+            Self {
+                client: Client::new(&config),
+                topic_arn,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl EventSink for SnsSink {
+        async fn publish(&self, event: &LifecycleEvent) -> AnyhowResult<()> {
+            let mut request = self
+                .client
+                .publish()
+                .topic_arn(&self.topic_arn)
+                .message(serde_json::to_string(event)?);
+
+            // FIFO topics require a group, within which order is kept, and
+            // deduplicate redeliveries by the id.
+            if self.topic_arn.ends_with(".fifo") {
+                request = request
+                    .message_group_id(event.key())
+                    .message_deduplication_id(event.id.to_string());
+            }
+
+            request.send().await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::types::IdentityEventEntry;
+
+    fn logged(event: IdentityEvent, message: Option<&str>) -> LoggedIdentityEvent {
+        LoggedIdentityEvent {
+            id:         7,
+            commitment: Hash::from(42),
+            event:      IdentityEventEntry {
+                event,
+                message: message.map(ToOwned::to_owned),
+                created_at: DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")
+                    .unwrap()
+                    .into(),
+            },
+        }
+    }
+
+    #[test]
+    fn received_identities_are_pending() {
+        assert_eq!(
+            LifecycleEvent::from(logged(IdentityEvent::Received, None)).event,
+            "pending"
+        );
+        assert_eq!(
+            LifecycleEvent::from(logged(IdentityEvent::Mined, None)).event,
+            "mined"
+        );
+    }
+
+    #[test]
+    fn serializes_events() {
+        let event = LifecycleEvent::from(logged(IdentityEvent::Failed, Some("reverted")));
+
+        assert_eq!(event.key(), format!("{:#x}", Hash::from(42)));
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({
+                "id": 7,
+                "identityCommitment": Hash::from(42),
+                "event": "failed",
+                "message": "reverted",
+                "timestamp": "2024-01-01T00:00:00Z",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn discards_events_without_a_sink() -> AnyhowResult<()> {
+        let options = Options {
+            event_bus:            SinkKind::None,
+            event_bus_url:        None,
+            event_bus_topic:      "identity-lifecycle".to_owned(),
+            event_bus_poll_ms:    1000,
+            event_bus_batch_size: 500,
+        };
+        assert!(connect(&options).await?.is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,239 @@
+//! Faults injected at points of the batching pipeline to test that the
+//! sequencer recovers from them, compiled in with the `fault-injection`
+//! feature only.
+//!
+//! Faults are read from `SEQUENCER_FAULTS` whenever an app is built, as a
+//! comma separated list of `<point>=<action>[@<hit>]`, e.g.
+//! `before_db_write=fail@3,after_tx_send=crash`. The action is taken the
+//! `hit`-th time, by default the first time, the point is reached:
+//!
+//! - `crash` aborts the process on the spot, like a power loss would,
+//! - `fail` makes the operation at the point fail once, the task performing it
+//!   is restarted as after any other failure.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result as AnyhowResult};
+use tracing::warn;
+
+const FAULTS_ENV: &str = "SEQUENCER_FAULTS";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FaultPoint {
+    /// The latest tree was updated with new identities, which aren't in the
+    /// database yet.
+    AfterTreeUpdate,
+    /// A pending identity is about to be written to the database.
+    BeforeDbWrite,
+    /// A batch transaction was sent, and not yet recorded.
+    AfterTxSend,
+}
+
+impl FromStr for FaultPoint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "after_tree_update" => Ok(Self::AfterTreeUpdate),
+            "before_db_write" => Ok(Self::BeforeDbWrite),
+            "after_tx_send" => Ok(Self::AfterTxSend),
+            _ => Err(anyhow!("unknown fault point {s}")),
+        }
+    }
+}
+
+impl fmt::Display for FaultPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::AfterTreeUpdate => "after_tree_update",
+            Self::BeforeDbWrite => "before_db_write",
+            Self::AfterTxSend => "after_tx_send",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Crash,
+    Fail,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(not(feature = "fault-injection"), allow(dead_code))]
+struct Fault {
+    point:  FaultPoint,
+    action: Action,
+    /// How often the point has to be reached for the fault to be injected.
+    hit:    usize,
+}
+
+fn parse_faults(spec: &str) -> AnyhowResult<Vec<Fault>> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|fault| !fault.is_empty())
+        .map(|fault| {
+            let (point, action) = fault
+                .split_once('=')
+                .ok_or_else(|| anyhow!("fault {fault} isn't of the form <point>=<action>"))?;
+            let (action, hit) = match action.split_once('@') {
+                Some((action, hit)) => (action, hit.parse()?),
+                None => (action, 1),
+            };
+            let action = match action {
+                "crash" => Action::Crash,
+                "fail" => Action::Fail,
+                _ => return Err(anyhow!("unknown fault action {action}")),
+            };
+            if hit == 0 {
+                return Err(anyhow!("faults are injected from the first hit on"));
+            }
+
+            Ok(Fault {
+                point: point.parse()?,
+                action,
+                hit,
+            })
+        })
+        .collect()
+}
+
+#[cfg(feature = "fault-injection")]
+mod injector {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use once_cell::sync::Lazy;
+    use tracing::error;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Injector {
+        faults: Vec<Fault>,
+        hits:   HashMap<FaultPoint, usize>,
+    }
+
+    static INJECTOR: Lazy<Mutex<Injector>> = Lazy::new(Mutex::default);
+
+    pub fn configure(faults: Vec<Fault>) {
+        if !faults.is_empty() {
+            warn!(?faults, "Fault injection is armed.");
+        }
+        *INJECTOR.lock().expect("no lock poisoning") = Injector {
+            faults,
+            hits: HashMap::new(),
+        };
+    }
+
+    pub fn inject(point: FaultPoint) -> AnyhowResult<()> {
+        let mut injector = INJECTOR.lock().expect("no lock poisoning");
+        let hits = injector.hits.entry(point).or_default();
+        *hits += 1;
+        let hit = *hits;
+
+        let Some(fault) = injector
+            .faults
+            .iter()
+            .find(|fault| fault.point == point && fault.hit == hit)
+        else {
+            return Ok(());
+        };
+
+        match fault.action {
+            Action::Crash => {
+                error!(%point, hit, "Injected crash.");
+                std::process::abort();
+            }
+            Action::Fail => {
+                error!(%point, hit, "Injected failure.");
+                Err(anyhow!("injected failure {point}@{hit}"))
+            }
+        }
+    }
+}
+
+/// Arms the faults set in `SEQUENCER_FAULTS`, replacing the ones armed
+/// before. Without the `fault-injection` feature they are only checked and
+/// ignored.
+///
+/// # Errors
+///
+/// Will return `Err` if the faults are malformed.
+pub fn arm_from_env() -> AnyhowResult<()> {
+    let spec = std::env::var(FAULTS_ENV).unwrap_or_default();
+    let faults = parse_faults(&spec)?;
+
+    #[cfg(feature = "fault-injection")]
+    injector::configure(faults);
+
+    #[cfg(not(feature = "fault-injection"))]
+    if !faults.is_empty() {
+        warn!("{FAULTS_ENV} is set, but fault injection isn't compiled in, ignoring it.");
+    }
+
+    Ok(())
+}
+
+/// Passes `point`, injecting the fault armed for it if any.
+///
+/// # Errors
+///
+/// Will return `Err` if a failure is injected.
+#[cfg(feature = "fault-injection")]
+pub fn inject(point: FaultPoint) -> AnyhowResult<()> {
+    injector::inject(point)
+}
+
+#[cfg(not(feature = "fault-injection"))]
+#[allow(clippy::unnecessary_wraps)]
+pub fn inject(_point: FaultPoint) -> AnyhowResult<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_faults() -> AnyhowResult<()> {
+        assert_eq!(parse_faults("")?, vec![]);
+        assert_eq!(
+            parse_faults("before_db_write=fail@3, after_tx_send=crash")?,
+            vec![
+                Fault {
+                    point:  FaultPoint::BeforeDbWrite,
+                    action: Action::Fail,
+                    hit:    3,
+                },
+                Fault {
+                    point:  FaultPoint::AfterTxSend,
+                    action: Action::Crash,
+                    hit:    1,
+                },
+            ]
+        );
+
+        assert!(parse_faults("after_tx_send").is_err());
+        assert!(parse_faults("after_lunch=crash").is_err());
+        assert!(parse_faults("after_tx_send=explode").is_err());
+        assert!(parse_faults("after_tx_send=fail@0").is_err());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "fault-injection")]
+    #[test]
+    fn fails_on_the_armed_hit() -> AnyhowResult<()> {
+        injector::configure(parse_faults("after_tree_update=fail@2")?);
+
+        assert!(inject(FaultPoint::AfterTreeUpdate).is_ok());
+        assert!(inject(FaultPoint::BeforeDbWrite).is_ok());
+        assert!(inject(FaultPoint::AfterTreeUpdate).is_err());
+        assert!(inject(FaultPoint::AfterTreeUpdate).is_ok());
+
+        injector::configure(vec![]);
+
+        Ok(())
+    }
+}
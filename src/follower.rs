@@ -0,0 +1,331 @@
+//! Warm standby following the tree of a leader sequencer over its `/sync`
+//! routes. The follower starts from a snapshot of the leader's mined tree,
+//! applies every later update checking the root it leads to, and moves its
+//! processed and mined trees along with the leader's, so that an identical
+//! tree state is ready when failing over.
+
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, ensure, Context, Result as AnyhowResult};
+use cli_batteries::await_shutdown;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_gauge, IntGauge};
+use tokio::select;
+use tokio::time::sleep;
+use tracing::{info, warn};
+use url::Url;
+
+use crate::identity_tree::{
+    Hash, TreeSnapshot, TreeState, TreeUpdate, TreeVersionReadOps, TreeWithNextVersion,
+};
+use crate::secret::Secret;
+use crate::server::data::{SyncUpdatesResponse, SYNC_ROOT_HEADER, SYNC_UPDATE_ID_HEADER};
+use crate::server::API_KEY_HEADER;
+use crate::{metrics, Options};
+
+static LAST_UPDATE_ID: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "sync_follower_update_id",
+        "Last row of the leader's tree applied by the follower."
+    )
+    .unwrap()
+});
+
+/// Longest a request for updates waits on the leader for some to be written.
+const WAIT_SECONDS: u64 = 20;
+
+/// Delay before asking the leader again after it couldn't be reached.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+struct Leader {
+    client:  reqwest::Client,
+    url:     Url,
+    api_key: Option<Secret>,
+}
+
+impl Leader {
+    fn get(&self, path: &str) -> AnyhowResult<reqwest::RequestBuilder> {
+        let mut request = self.client.get(self.url.join(path)?);
+        if let Some(api_key) = &self.api_key {
+            request = request.header(API_KEY_HEADER, api_key.expose());
+        }
+
+        Ok(request)
+    }
+
+    /// Downloads a snapshot of the mined tree to `path`, returns it along with
+    /// the last row it includes.
+    async fn snapshot(&self, path: &Path) -> AnyhowResult<(TreeSnapshot, usize)> {
+        let response = self
+            .get("sync/snapshot")?
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned)
+                .with_context(|| format!("the leader didn't send the {name} header"))
+        };
+        let root = Hash::from_str(&header(SYNC_ROOT_HEADER)?)
+            .map_err(|error| anyhow!("invalid snapshot root: {error}"))?;
+        let update_id = header(SYNC_UPDATE_ID_HEADER)?.parse()?;
+
+        std::fs::write(path, response.bytes().await?)?;
+        let snapshot = TreeSnapshot::open(path)?;
+        ensure!(
+            snapshot.root() == root,
+            "snapshot has root {:#x}, the leader announced {root:#x}",
+            snapshot.root()
+        );
+
+        Ok((snapshot, update_id))
+    }
+
+    async fn updates(&self, after: usize) -> AnyhowResult<SyncUpdatesResponse> {
+        let path = format!("sync/updates?after={after}&waitSeconds={WAIT_SECONDS}");
+
+        Ok(self
+            .get(&path)?
+            // Requests stalled beyond the wait are retried.
+            .timeout(Duration::from_secs(2 * WAIT_SECONDS))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+}
+
+/// Applies the updates after `last_id` to the latest tree and moves the
+/// processed and mined trees up to the leader's. Returns the last row applied.
+fn apply(
+    tree_state: &TreeState,
+    mut last_id: usize,
+    response: SyncUpdatesResponse,
+) -> AnyhowResult<usize> {
+    let latest = tree_state.get_latest_tree();
+
+    for update in &response.updates {
+        ensure!(
+            update.id > last_id,
+            "the leader sent row {} after row {last_id}",
+            update.id
+        );
+
+        let root = latest.apply_updates(&[TreeUpdate {
+            leaf_index: update.leaf_index,
+            element:    update.element,
+        }]);
+        ensure!(
+            root == update.root,
+            "tree diverged from the leader at row {}: root {root:#x}, expected {:#x}",
+            update.id,
+            update.root
+        );
+
+        last_id = update.id;
+    }
+
+    // The batching tree only exists to be pulled from, nothing is batched here.
+    if !response.updates.is_empty() {
+        tree_state
+            .get_batching_tree()
+            .apply_updates_up_to(latest.get_root());
+    }
+
+    if let Some(root) = response.processed_root {
+        advance(&tree_state.get_processed_tree(), root);
+    }
+
+    if let Some(root) = response.mined_root {
+        advance(&tree_state.get_mined_tree(), root);
+    }
+
+    Ok(last_id)
+}
+
+fn advance(tree: &(impl TreeVersionReadOps + TreeWithNextVersion), root: Hash) {
+    if tree.get_root() != root {
+        tree.apply_updates_up_to(root);
+    }
+}
+
+/// Writes the mined tree to the tree snapshot file, so that a sequencer taking
+/// over serves proofs from it right away.
+fn write_snapshot(tree_state: &TreeState, path: &str, initial_leaf_value: Hash, depth: usize) {
+    let mined_tree = tree_state.get_mined_tree();
+    let leaves: Vec<_> = (0..mined_tree.next_leaf())
+        .map(|leaf_index| mined_tree.get_leaf(leaf_index))
+        .collect();
+    let leaf_count = leaves.len();
+
+    match TreeSnapshot::write(path, depth, initial_leaf_value, leaves, None) {
+        Ok(()) => info!(path, leaf_count, "Wrote tree snapshot"),
+        Err(error) => warn!(path, ?error, "Failed to write tree snapshot"),
+    }
+}
+
+/// Follows the tree of the sequencer serving the API at `leader` until
+/// shutdown.
+///
+/// # Errors
+///
+/// Will return `Err` if the snapshot can't be fetched or the tree diverges
+/// from the leader's.
+pub async fn run(options: Options, leader: Url, api_key: Option<Secret>) -> AnyhowResult<()> {
+    let app = options.app;
+    let initial_leaf_value = app.contracts.initial_leaf_value;
+    let metrics_sinks = metrics::spawn_sinks(&options.metrics);
+
+    let leader = Leader {
+        client: reqwest::Client::new(),
+        url: leader,
+        api_key,
+    };
+
+    let dir = tempfile::tempdir()?;
+    let (snapshot, mut last_id) = leader
+        .snapshot(&dir.path().join("snapshot"))
+        .await
+        .context("Failed to fetch the snapshot of the leader")?;
+    info!(
+        root = %snapshot.root(),
+        leaf_count = snapshot.leaf_count(),
+        last_id,
+        "Fetched the snapshot of the leader"
+    );
+
+    let depth = snapshot.depth();
+    let tree_state = TreeState::from_snapshot(
+        &snapshot,
+        initial_leaf_value,
+        app.dense_tree_prefix_depth,
+        app.tree_gc_threshold,
+        &app.dense_tree_mmap_file,
+    )?;
+    drop(snapshot);
+
+    loop {
+        let response = select! {
+            response = leader.updates(last_id) => response,
+            () = await_shutdown() => break,
+        };
+
+        let response = match response {
+            Ok(response) => response,
+            Err(error) => {
+                warn!(?error, "Failed to fetch updates from the leader");
+                select! {
+                    () = sleep(RETRY_DELAY) => continue,
+                    () = await_shutdown() => break,
+                }
+            }
+        };
+
+        let update_count = response.updates.len();
+        let state = tree_state.clone();
+        last_id = tokio::task::spawn_blocking(move || apply(&state, last_id, response)).await??;
+        LAST_UPDATE_ID.set(last_id as i64);
+
+        if update_count > 0 {
+            info!(
+                update_count,
+                last_id,
+                root = %tree_state.get_latest_tree().get_root(),
+                "Followed the leader"
+            );
+        }
+    }
+
+    if let Some(path) = &app.tree_snapshot_file {
+        write_snapshot(&tree_state, path, initial_leaf_value, depth);
+    }
+
+    for sink in metrics_sinks {
+        sink.await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use semaphore::poseidon_tree::LazyPoseidonTree;
+
+    use super::*;
+    use crate::server::data::SyncUpdate;
+
+    const DEPTH: usize = 4;
+
+    #[test]
+    fn follows_the_leader() -> AnyhowResult<()> {
+        let dir = tempfile::tempdir()?;
+        let snapshot_path = dir.path().join("snapshot");
+        let mmap_path = dir.path().join("mmap");
+
+        let mut leader = LazyPoseidonTree::new(DEPTH, Hash::ZERO);
+        for i in 0..2 {
+            leader = leader.update(i, &Hash::from(i + 1));
+        }
+        TreeSnapshot::write(
+            &snapshot_path,
+            DEPTH,
+            Hash::ZERO,
+            vec![Hash::from(1), Hash::from(2)],
+            None,
+        )?;
+        let snapshot = TreeSnapshot::open(&snapshot_path)?;
+        let mined_root = leader.root();
+
+        let tree_state =
+            TreeState::from_snapshot(&snapshot, Hash::ZERO, 2, 100, mmap_path.to_str().unwrap())?;
+
+        let mut updates = vec![];
+        let mut roots = vec![];
+        for i in 2..5 {
+            leader = leader.update(i, &Hash::from(i + 1));
+            roots.push(leader.root());
+            updates.push(SyncUpdate {
+                id:         i + 1,
+                leaf_index: i,
+                element:    Hash::from(i + 1),
+                root:       leader.root(),
+            });
+        }
+
+        let last_id = apply(&tree_state, 2, SyncUpdatesResponse {
+            updates,
+            processed_root: Some(roots[1]),
+            mined_root: Some(mined_root),
+        })?;
+
+        assert_eq!(last_id, 5);
+        assert_eq!(tree_state.get_latest_tree().get_root(), roots[2]);
+        assert_eq!(tree_state.get_batching_tree().get_root(), roots[2]);
+        assert_eq!(tree_state.get_processed_tree().get_root(), roots[1]);
+        assert_eq!(tree_state.get_mined_tree().get_root(), mined_root);
+
+        let diverged = apply(&tree_state, last_id, SyncUpdatesResponse {
+            updates:        vec![SyncUpdate {
+                id:         6,
+                leaf_index: 5,
+                element:    Hash::from(6),
+                root:       Hash::from(42),
+            }],
+            processed_root: None,
+            mined_root:     None,
+        });
+        assert!(diverged
+            .unwrap_err()
+            .to_string()
+            .contains("diverged from the leader at row 6"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+//! Idempotency keys of insertions. Clients retrying an insertion after a
+//! network error can't tell whether the first attempt was processed, and a
+//! retry repeats the challenge, screening and capacity checks even when the
+//! commitment is deduplicated. Requests carrying an `Idempotency-Key` header
+//! are answered with the response to the first request with the same key for
+//! as long as it's kept, without being processed again.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::HeaderMap;
+use chrono::Utc;
+use clap::Parser;
+use ethers::types::H256;
+use ethers::utils::keccak256;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use serde::Serialize;
+use tracing::{error, info};
+
+use crate::database::types::{IdempotencyKeyEntry, IdempotentResponse};
+use crate::database::{Database, Error as DatabaseError};
+use crate::server::error::Error as ServerError;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Set on responses which are replayed rather than produced by the request.
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+pub const MAX_KEY_LENGTH: usize = 255;
+
+static REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "idempotent_requests",
+        "Requests with an idempotency key, by outcome.",
+        &["outcome"]
+    )
+    .unwrap()
+});
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// How long the response to a request with an `Idempotency-Key` header is
+    /// replayed to requests with the same key (seconds).
+    #[clap(long, env, default_value = "86400")]
+    pub idempotency_key_ttl_seconds: u64,
+}
+
+/// Reads the idempotency key of a request, if it has one.
+///
+/// # Errors
+///
+/// Will return `Err` if the key is empty, longer than [`MAX_KEY_LENGTH`] or
+/// not printable ASCII.
+pub fn key(headers: &HeaderMap) -> Result<Option<String>, ServerError> {
+    let Some(value) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(None);
+    };
+
+    let key = value
+        .to_str()
+        .map_err(|_| ServerError::InvalidIdempotencyKey)?;
+    let is_valid = !key.is_empty()
+        && key.len() <= MAX_KEY_LENGTH
+        && key.bytes().all(|byte| byte.is_ascii_graphic());
+
+    if !is_valid {
+        return Err(ServerError::InvalidIdempotencyKey);
+    }
+
+    Ok(Some(key.to_owned()))
+}
+
+/// Identifies the request a key was first used with. Requests are hashed as
+/// they're deserialized, so that retries differing only in formatting match.
+#[must_use]
+pub fn fingerprint<T: Serialize>(request: &T) -> H256 {
+    let json = serde_json::to_vec(request).expect("requests serialize to JSON");
+    keccak256(json).into()
+}
+
+pub struct IdempotencyKeys {
+    database: Arc<Database>,
+    ttl:      chrono::Duration,
+}
+
+/// The outcome of reserving a key.
+pub enum Reservation {
+    /// The key wasn't used before, the request is to be processed.
+    New(KeyGuard),
+    /// The response to the first request with the key.
+    Replay(IdempotentResponse),
+}
+
+/// A key reserved for a request being processed. Unless its response is
+/// stored, the key is released when the guard is dropped, so that a request
+/// failing or cancelled half way can be retried with the same key.
+pub struct KeyGuard {
+    database: Arc<Database>,
+    key:      Option<String>,
+}
+
+impl IdempotencyKeys {
+    #[must_use]
+    pub fn new(options: &Options, database: Arc<Database>) -> Self {
+        let ttl = Duration::from_secs(options.idempotency_key_ttl_seconds);
+        Self {
+            database,
+            ttl: chrono::Duration::from_std(ttl).expect("TTL is in range"),
+        }
+    }
+
+    /// Reserves `key` for a request with the given fingerprint, or returns the
+    /// response to the first request with it. Keys expire `ttl` after they
+    /// were first used.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the key was used with a different request, or if
+    /// the first request with the key is still being processed.
+    pub async fn reserve(
+        &self,
+        key: String,
+        fingerprint: H256,
+    ) -> Result<Reservation, ServerError> {
+        let expired_before = Utc::now() - self.ttl;
+
+        let purged = self.database.purge_idempotency_keys(expired_before).await?;
+        if purged > 0 {
+            info!(purged, "Purged expired idempotency keys.");
+        }
+
+        let entry = self
+            .database
+            .reserve_idempotency_key(&key, &fingerprint, expired_before)
+            .await?;
+
+        match entry {
+            None => {
+                REQUESTS.with_label_values(&["new"]).inc();
+                Ok(Reservation::New(KeyGuard {
+                    database: self.database.clone(),
+                    key:      Some(key),
+                }))
+            }
+            Some(IdempotencyKeyEntry {
+                fingerprint: first, ..
+            }) if first != fingerprint => {
+                REQUESTS.with_label_values(&["mismatch"]).inc();
+                Err(ServerError::IdempotencyKeyReused)
+            }
+            Some(IdempotencyKeyEntry { response: None, .. }) => {
+                REQUESTS.with_label_values(&["in_progress"]).inc();
+                Err(ServerError::IdempotencyKeyInProgress)
+            }
+            Some(IdempotencyKeyEntry {
+                response: Some(response),
+                ..
+            }) => {
+                REQUESTS.with_label_values(&["replayed"]).inc();
+                Ok(Reservation::Replay(response))
+            }
+        }
+    }
+}
+
+impl KeyGuard {
+    /// Stores the response to be replayed for the key.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the response can't be stored, in which case the
+    /// key is released.
+    pub async fn complete(mut self, response: &IdempotentResponse) -> Result<(), DatabaseError> {
+        let key = self.key.take().expect("keys are completed once");
+
+        let result = self.database.complete_idempotency_key(&key, response).await;
+        if result.is_err() {
+            self.key = Some(key);
+        }
+
+        result
+    }
+}
+
+impl Drop for KeyGuard {
+    fn drop(&mut self) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+
+        let database = self.database.clone();
+        tokio::spawn(async move {
+            if let Err(error) = database.release_idempotency_key(&key).await {
+                error!(?error, "Failed to release idempotency key.");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, HeaderValue};
+
+    use super::{fingerprint, key, IDEMPOTENCY_KEY_HEADER, MAX_KEY_LENGTH};
+    use crate::server::error::Error as ServerError;
+
+    fn headers(key: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(IDEMPOTENCY_KEY_HEADER, HeaderValue::from_str(key).unwrap());
+        headers
+    }
+
+    #[test]
+    fn reads_keys_from_headers() {
+        assert!(matches!(key(&HeaderMap::new()), Ok(None)));
+        assert_eq!(
+            key(&headers("4f1b2c3d-retry")).unwrap().as_deref(),
+            Some("4f1b2c3d-retry")
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_keys() {
+        for invalid in ["", "with space", &"k".repeat(MAX_KEY_LENGTH + 1)] {
+            assert!(matches!(
+                key(&headers(invalid)),
+                Err(ServerError::InvalidIdempotencyKey)
+            ));
+        }
+    }
+
+    #[test]
+    fn fingerprints_ignore_formatting() {
+        let request: serde_json::Value = serde_json::from_str(r#"{ "a": 1 }"#).unwrap();
+        let reformatted: serde_json::Value = serde_json::from_str(r#"{"a":1}"#).unwrap();
+        let other: serde_json::Value = serde_json::from_str(r#"{"a":2}"#).unwrap();
+
+        assert_eq!(fingerprint(&request), fingerprint(&reformatted));
+        assert_ne!(fingerprint(&request), fingerprint(&other));
+    }
+}
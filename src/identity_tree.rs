@@ -1,20 +1,41 @@
 use std::cmp::min;
 use std::sync::{Arc, Mutex, MutexGuard};
 
+use anyhow::{ensure, Result as AnyhowResult};
 use chrono::Utc;
+use ethers_core::types::H256;
 use semaphore::lazy_merkle_tree::{Derived, LazyMerkleTree};
-use semaphore::merkle_tree::Hasher;
+use semaphore::merkle_tree::{self, Branch, Hasher};
 use semaphore::poseidon_tree::{PoseidonHash, Proof};
 use semaphore::{lazy_merkle_tree, Field};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
 
+use self::lock_metrics::Access;
+use self::published::{wait_for_readers, Published};
+use self::registry::VersionRegistry;
+
+#[cfg(feature = "ark-hasher")]
+mod ark_hasher;
+#[cfg(feature = "ark-hasher")]
+mod fast_poseidon;
+mod lock_metrics;
+mod published;
+mod registry;
+mod snapshot;
 mod status;
 
 pub type PoseidonTree<Version> = LazyMerkleTree<PoseidonHash, Version>;
 pub type Hash = <PoseidonHash as Hasher>::Hash;
 
-pub use self::status::{ProcessedStatus, Status, UnknownStatus, UnprocessedStatus};
+#[cfg(feature = "ark-hasher")]
+pub use self::ark_hasher::ArkPoseidonHash;
+#[cfg(feature = "ark-hasher")]
+pub use self::fast_poseidon::FastPoseidonHash;
+pub use self::snapshot::TreeSnapshot;
+pub use self::status::{
+    IdentityEvent, ProcessedStatus, RemovedStatus, Status, UnknownStatus, UnprocessedStatus,
+};
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
 pub struct TreeUpdate {
@@ -32,6 +53,85 @@ impl TreeUpdate {
     }
 }
 
+/// The hash function of the tree. Versions are generic over it, defaulting to
+/// [`AppHasher`]. Hashers other than those matching the contract are only
+/// useful to compare backends, e.g. in benchmarks.
+pub trait TreeHasher: Hasher<Hash = Hash> + Clone + Send + Sync + 'static {}
+
+impl TreeHasher for PoseidonHash {}
+
+/// The hasher of the trees of the app, the Poseidon hash of semaphore-rs or,
+/// with the `fast-poseidon` feature, [`FastPoseidonHash`]. Both hash like the
+/// contract.
+#[cfg(not(feature = "fast-poseidon"))]
+pub type AppHasher = PoseidonHash;
+#[cfg(feature = "fast-poseidon")]
+pub type AppHasher = FastPoseidonHash;
+
+/// Whether `proof` proves `commitment` to be a leaf of the tree with the given
+/// root, hashing like the tree does. Exported proofs can be verified with it
+/// without access to the tree.
+#[must_use]
+pub fn verify_proof(commitment: Hash, proof: &Proof, root: Hash) -> bool {
+    proof.root(commitment) == root
+}
+
+/// Proves the contiguous leaves from `start_index` onwards at once. The
+/// internal nodes shared by their paths are computed from the leaves, only the
+/// siblings of the range at each level are included in `nodes`, by level and
+/// then index.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RangeProof {
+    pub root:        Hash,
+    pub start_index: usize,
+    pub leaves:      Vec<Hash>,
+    pub nodes:       Vec<TreeNode>,
+}
+
+/// Whether `proof` proves its leaves to be leaves of the tree of the given
+/// depth and root, hashing like the tree does.
+#[must_use]
+pub fn verify_range_proof(proof: &RangeProof, depth: usize, root: Hash) -> bool {
+    if proof.leaves.is_empty() {
+        return false;
+    }
+
+    let mut index = proof.start_index;
+    let mut hashes = proof.leaves.clone();
+    let mut nodes = proof.nodes.iter().peekable();
+
+    for level in 0..depth {
+        let mut sibling = |index: usize| {
+            nodes
+                .next_if(|node| node.level == level && node.index == index)
+                .map(|node| node.hash)
+        };
+
+        if index % 2 == 1 {
+            let Some(left) = sibling(index - 1) else {
+                return false;
+            };
+            hashes.insert(0, left);
+            index -= 1;
+        }
+        if hashes.len() % 2 == 1 {
+            let Some(right) = sibling(index + hashes.len()) else {
+                return false;
+            };
+            hashes.push(right);
+        }
+
+        hashes = hashes
+            .chunks(2)
+            .map(|pair| PoseidonHash::hash_node(&pair[0], &pair[1]))
+            .collect();
+        index /= 2;
+    }
+
+    nodes.next().is_none() && hashes == [root] && proof.root == root
+}
+
 #[derive(Debug)]
 pub struct TreeItem {
     pub status:     ProcessedStatus,
@@ -45,15 +145,47 @@ pub struct RootItem {
     pub status:              ProcessedStatus,
     pub pending_valid_as_of: chrono::DateTime<Utc>,
     pub mined_valid_as_of:   Option<chrono::DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mined_transaction:   Option<MinedTransaction>,
+}
+
+/// The transaction which published a root on chain.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MinedTransaction {
+    pub transaction_hash: H256,
+    pub block_number:     u64,
+    pub block_timestamp:  chrono::DateTime<Utc>,
+}
+
+/// The proof of a tree of the app as a proof of the Poseidon tree, which it
+/// is whichever [`AppHasher`] computed it.
+fn poseidon_proof(proof: merkle_tree::Proof<AppHasher>) -> Proof {
+    merkle_tree::Proof(
+        proof
+            .0
+            .into_iter()
+            .map(|branch| match branch {
+                Branch::Left(hash) => Branch::Left(hash),
+                Branch::Right(hash) => Branch::Right(hash),
+            })
+            .collect(),
+    )
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct InclusionProof {
-    pub status:  Status,
-    pub root:    Option<Field>,
-    pub proof:   Option<Proof>,
-    pub message: Option<String>,
+    pub status:            Status,
+    pub root:              Option<Field>,
+    pub proof:             Option<Proof>,
+    pub message:           Option<String>,
+    /// The transaction which published the root, for mined proofs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mined_transaction: Option<MinedTransaction>,
+    /// The block in which the leaf was mined, for mined proofs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mined_block:       Option<u64>,
 }
 
 /// Additional data held by the canonical tree version. It includes data
@@ -65,14 +197,25 @@ pub struct CanonicalTreeMetadata {
 
 /// Additional data held by any derived tree version. Includes the list of
 /// updates performed since previous version.
-pub struct DerivedTreeMetadata {
-    diff: Vec<AppliedTreeUpdate>,
+pub struct DerivedTreeMetadata<H: TreeHasher = AppHasher> {
+    diff:         Vec<AppliedTreeUpdate<H>>,
+    /// The proof of the most recently inserted leaf, computed while inserting
+    /// it. Proofs are usually requested for it right after the insertion, so
+    /// it's served from here until the tree is next updated.
+    recent_proof: Option<RecentProof<H>>,
 }
 
 #[derive(Clone)]
-pub struct AppliedTreeUpdate {
+struct RecentProof<H: TreeHasher> {
+    leaf_index: usize,
+    root:       Hash,
+    proof:      merkle_tree::Proof<H>,
+}
+
+#[derive(Clone)]
+pub struct AppliedTreeUpdate<H: TreeHasher = AppHasher> {
     pub update: TreeUpdate,
-    pub result: PoseidonTree<Derived>,
+    pub result: LazyMerkleTree<H, Derived>,
 }
 
 /// Trait used to associate a version marker with its metadata type.
@@ -80,33 +223,116 @@ pub trait AllowedTreeVersionMarker
 where
     Self: lazy_merkle_tree::VersionMarker,
 {
-    type Metadata;
+    type Metadata<H: TreeHasher>;
 }
 
 impl AllowedTreeVersionMarker for lazy_merkle_tree::Canonical {
-    type Metadata = CanonicalTreeMetadata;
+    type Metadata<H: TreeHasher> = CanonicalTreeMetadata;
 }
 
 impl AllowedTreeVersionMarker for lazy_merkle_tree::Derived {
-    type Metadata = DerivedTreeMetadata;
+    type Metadata<H: TreeHasher> = DerivedTreeMetadata<H>;
 }
 
 /// Underlying data structure for a tree version. It holds the tree itself, the
 /// next leaf (only used in the latest tree), a pointer to the next version (if
-/// exists) and the metadata specified by the version marker.
-struct TreeVersionData<V: AllowedTreeVersionMarker> {
-    tree:      PoseidonTree<V>,
+/// exists) and the metadata specified by the version marker, all hashed with
+/// `H`.
+struct TreeVersionData<V: AllowedTreeVersionMarker, H: TreeHasher> {
+    tree:      LazyMerkleTree<H, V>,
     next_leaf: usize,
-    next:      Option<TreeVersion<AnyDerived>>,
-    metadata:  V::Metadata,
+    next:      Option<TreeVersion<AnyDerived, H>>,
+    metadata:  V::Metadata<H>,
+    /// What readers of the version see, published once a modification is
+    /// complete.
+    view:      Arc<Published<ReadView<H>>>,
+}
+
+/// The state of a version as served to readers. Proofs are read from it
+/// without locking the version, so that they aren't blocked while updates are
+/// applied to it, e.g. while hundreds of them are promoted to the mined tree.
+struct ReadView<H: TreeHasher> {
+    tree:         LazyMerkleTree<H, Derived>,
+    next_leaf:    usize,
+    recent_proof: Option<RecentProof<H>>,
+}
+
+impl<H: TreeHasher> ReadView<H> {
+    fn new(tree: LazyMerkleTree<H, Derived>, next_leaf: usize) -> Self {
+        Self {
+            tree,
+            next_leaf,
+            recent_proof: None,
+        }
+    }
+
+    fn get_proof(&self, leaf: usize) -> (Hash, merkle_tree::Proof<H>) {
+        if let Some(recent) = self
+            .recent_proof
+            .as_ref()
+            .filter(|recent| recent.leaf_index == leaf)
+        {
+            return (recent.root, recent.proof.clone());
+        }
+
+        (self.tree.root(), self.tree.proof(leaf))
+    }
+
+    /// The siblings of the range are those of the paths of its first and last
+    /// leaves which are outside of it. Once the paths join, the sibling is
+    /// the same on both and it's only included once.
+    fn get_range_proof(&self, start: usize, end: usize) -> RangeProof {
+        let first = self.tree.proof(start);
+        let last = self.tree.proof(end - 1);
+
+        let nodes = first
+            .0
+            .iter()
+            .zip(&last.0)
+            .enumerate()
+            .flat_map(|(level, branches)| {
+                let left = match branches.0 {
+                    Branch::Right(hash) => Some(TreeNode {
+                        level,
+                        index: (start >> level) - 1,
+                        hash: *hash,
+                    }),
+                    Branch::Left(_) => None,
+                };
+                let right = match branches.1 {
+                    Branch::Left(hash) => Some(TreeNode {
+                        level,
+                        index: ((end - 1) >> level) + 1,
+                        hash: *hash,
+                    }),
+                    Branch::Right(_) => None,
+                };
+                left.into_iter().chain(right)
+            })
+            .collect();
+
+        RangeProof {
+            root: self.tree.root(),
+            start_index: start,
+            leaves: (start..end).map(|leaf| self.tree.get_leaf(leaf)).collect(),
+            nodes,
+        }
+    }
 }
 
 /// Basic operations that should be available for all tree versions.
-trait BasicTreeOps {
+trait BasicTreeOps<H: TreeHasher> {
     /// Updates the tree with the given element at the given leaf index.
     fn update(&mut self, leaf_index: usize, element: Hash);
 
-    fn apply_diffs(&mut self, diffs: Vec<AppliedTreeUpdate>);
+    fn apply_diffs(&mut self, diffs: Vec<AppliedTreeUpdate<H>>);
+
+    /// Returns the root and proof of the given leaf if they were cached when
+    /// it was inserted and the tree hasn't changed since.
+    fn cached_proof(&self, leaf_index: usize) -> Option<(Hash, merkle_tree::Proof<H>)>;
+
+    /// Publishes the current state of the version to its readers.
+    fn publish(&mut self);
 
     /// Notifies the tree that it was changed and can perform garbage
     /// collection. This is version-specific and it is up to the implementer to
@@ -114,35 +340,35 @@ trait BasicTreeOps {
     fn garbage_collect(&mut self);
 }
 
-impl<V> TreeVersionData<V>
+impl<V, H> TreeVersionData<V, H>
 where
     V: lazy_merkle_tree::VersionMarker + AllowedTreeVersionMarker,
-    Self: BasicTreeOps,
+    H: TreeHasher,
+    Self: BasicTreeOps<H>,
 {
     /// Gets the current tree root.
     fn get_root(&self) -> Hash {
         self.tree.root()
     }
 
-    /// Gets the leaf value at a given index.
-    fn get_leaf(&self, leaf: usize) -> Hash {
-        self.tree.get_leaf(leaf)
-    }
-
     /// Gets the proof of the given leaf index element
-    fn get_proof(&self, leaf: usize) -> (Hash, Proof) {
+    fn get_proof(&self, leaf: usize) -> (Hash, merkle_tree::Proof<H>) {
+        if let Some(cached) = self.cached_proof(leaf) {
+            return cached;
+        }
+
         let proof = self.tree.proof(leaf);
         (self.tree.root(), proof)
     }
 
     /// Returns _up to_ `maximum_update_count` contiguous deletion or insertion
     /// updates that are to be applied to the tree.
-    fn peek_next_updates(&self, maximum_update_count: usize) -> Vec<AppliedTreeUpdate> {
+    fn peek_next_updates(&self, maximum_update_count: usize) -> Vec<AppliedTreeUpdate<H>> {
         let Some(next) = self.next.as_ref() else {
             return Vec::new();
         };
 
-        let next = next.get_data();
+        let next = next.read_data();
 
         let first_is_zero = match next.metadata.diff.first() {
             Some(first) => first.update.element == Hash::ZERO,
@@ -150,7 +376,7 @@ where
         };
 
         // Gets the next contiguous of insertion or deletion updates from the diff
-        let should_take = |elem: &&AppliedTreeUpdate| {
+        let should_take = |elem: &&AppliedTreeUpdate<H>| {
             if first_is_zero {
                 // If first is zero, we should take only consecutive zeros
                 elem.update.element == Hash::ZERO
@@ -169,6 +395,20 @@ where
             .collect()
     }
 
+    /// Takes over the tree of `replacement`, keeping the links to the other
+    /// versions and the view readers hold, and publishes it. Returns once the
+    /// readers of the replaced tree are done with it.
+    fn take_contents(&mut self, replacement: &mut Self) {
+        let previous = self.view.load();
+
+        std::mem::swap(&mut self.tree, &mut replacement.tree);
+        std::mem::swap(&mut self.metadata, &mut replacement.metadata);
+        self.next_leaf = replacement.next_leaf;
+        self.publish();
+
+        wait_for_readers(previous);
+    }
+
     fn apply_updates_up_to(&mut self, root: Hash) -> usize {
         let Some(next) = self.next.clone() else {
             return 0;
@@ -177,7 +417,7 @@ where
         let num_updates;
         {
             // Acquire the exclusive write lock on the next version.
-            let mut next = next.get_data();
+            let mut next = next.write_data();
 
             let index_of_root = next
                 .metadata
@@ -195,6 +435,7 @@ where
             num_updates = applied_updates.len();
 
             self.apply_diffs(applied_updates);
+            self.publish();
         }
 
         self.garbage_collect();
@@ -203,7 +444,7 @@ where
     }
 }
 
-impl BasicTreeOps for TreeVersionData<lazy_merkle_tree::Canonical> {
+impl<H: TreeHasher> BasicTreeOps<H> for TreeVersionData<lazy_merkle_tree::Canonical, H> {
     fn update(&mut self, leaf_index: usize, element: Hash) {
         take_mut::take(&mut self.tree, |tree| {
             tree.update_with_mutation(leaf_index, &element)
@@ -214,13 +455,43 @@ impl BasicTreeOps for TreeVersionData<lazy_merkle_tree::Canonical> {
         self.metadata.count_since_last_flatten += 1;
     }
 
-    fn apply_diffs(&mut self, diffs: Vec<AppliedTreeUpdate>) {
+    /// The tree is updated in place, which readers must not observe. They're
+    /// moved to the tree the diffs resulted in, which has the same contents,
+    /// before the first update is applied.
+    fn apply_diffs(&mut self, diffs: Vec<AppliedTreeUpdate<H>>) {
+        let Some(last) = diffs.last() else {
+            return;
+        };
+
+        let next_leaf = diffs
+            .iter()
+            .rev()
+            .find(|applied_update| applied_update.update.element != Hash::ZERO)
+            .map_or(self.next_leaf, |applied_update| {
+                applied_update.update.leaf_index + 1
+            });
+        let previous = self
+            .view
+            .store(ReadView::new(last.result.clone(), next_leaf));
+        wait_for_readers(previous);
+
         for applied_update in &diffs {
             let update = &applied_update.update;
             self.update(update.leaf_index, update.element);
         }
     }
 
+    fn cached_proof(&self, _leaf_index: usize) -> Option<(Hash, merkle_tree::Proof<H>)> {
+        None
+    }
+
+    /// The view reads from the storage of the tree, and is therefore replaced
+    /// before the tree is next updated in place.
+    fn publish(&mut self) {
+        self.view
+            .store(ReadView::new(self.tree.derived(), self.next_leaf));
+    }
+
     /// Garbage collection for the canonical tree version. It rewrites all
     /// future versions of the tree to use the more optimized storage of this
     /// tree. This is done periodically, to really make the additional
@@ -228,39 +499,42 @@ impl BasicTreeOps for TreeVersionData<lazy_merkle_tree::Canonical> {
     ///
     /// Warning: this will attempt to acquire lock for all transitive successors
     /// of this tree, and therefore no version locks acquired through
-    /// `TreeVersion#get_data()` may be held at the time of calling this.
+    /// `TreeVersion#read_data()` or `TreeVersion#write_data()` may be held at
+    /// the time of calling this.
     fn garbage_collect(&mut self) {
         if self.metadata.count_since_last_flatten >= self.metadata.flatten_threshold {
             info!("Flattening threshold reached, rebuilding tree versions");
             self.metadata.count_since_last_flatten = 0;
             let next = &self.next;
             if let Some(next) = next {
-                next.get_data().rebuild_on(self.tree.derived());
+                next.write_data().rebuild_on(self.tree.derived());
             }
             info!("Tree versions rebuilt");
         }
     }
 }
 
-impl TreeVersionData<lazy_merkle_tree::Derived> {
-    fn rebuild_on(&mut self, mut tree: PoseidonTree<lazy_merkle_tree::Derived>) {
+impl<H: TreeHasher> TreeVersionData<lazy_merkle_tree::Derived, H> {
+    fn rebuild_on(&mut self, mut tree: LazyMerkleTree<H, lazy_merkle_tree::Derived>) {
         for update in &mut self.metadata.diff {
             tree = tree.update(update.update.leaf_index, &update.update.element);
             update.result = tree.clone();
         }
         self.tree = tree;
+        self.publish();
         let next = &self.next;
         if let Some(next) = next {
-            next.get_data().rebuild_on(self.tree.clone());
+            next.write_data().rebuild_on(self.tree.clone());
         }
     }
 }
 
-impl BasicTreeOps for TreeVersionData<lazy_merkle_tree::Derived> {
+impl<H: TreeHasher> BasicTreeOps<H> for TreeVersionData<lazy_merkle_tree::Derived, H> {
     fn update(&mut self, leaf_index: usize, element: Hash) {
         let updated_tree = self.tree.update(leaf_index, &element);
 
         self.tree = updated_tree.clone();
+        self.metadata.recent_proof = None;
 
         if element != Hash::ZERO {
             self.next_leaf = leaf_index + 1;
@@ -274,13 +548,14 @@ impl BasicTreeOps for TreeVersionData<lazy_merkle_tree::Derived> {
         });
     }
 
-    fn apply_diffs(&mut self, mut diffs: Vec<AppliedTreeUpdate>) {
+    fn apply_diffs(&mut self, mut diffs: Vec<AppliedTreeUpdate<H>>) {
         let last = diffs.last().cloned();
 
         self.metadata.diff.append(&mut diffs);
 
         if let Some(last) = last {
             self.tree = last.result.clone();
+            self.metadata.recent_proof = None;
 
             if last.update.element != Hash::ZERO {
                 self.next_leaf = last.update.leaf_index + 1;
@@ -288,6 +563,22 @@ impl BasicTreeOps for TreeVersionData<lazy_merkle_tree::Derived> {
         }
     }
 
+    fn cached_proof(&self, leaf_index: usize) -> Option<(Hash, merkle_tree::Proof<H>)> {
+        self.metadata
+            .recent_proof
+            .as_ref()
+            .filter(|recent| recent.leaf_index == leaf_index)
+            .map(|recent| (recent.root, recent.proof.clone()))
+    }
+
+    fn publish(&mut self) {
+        self.view.store(ReadView {
+            tree:         self.tree.clone(),
+            next_leaf:    self.next_leaf,
+            recent_proof: self.metadata.recent_proof.clone(),
+        });
+    }
+
     fn garbage_collect(&mut self) {}
 }
 
@@ -343,85 +634,175 @@ impl Version for AnyDerived {
     type TreeVersion = lazy_merkle_tree::Derived;
 }
 
+/// Names of the versions in the order they are built, as held by
+/// [`TreeState`]. Only used to label metrics.
+const VERSION_NAMES: [&str; 3] = ["mined", "processed", "batching"];
+
 /// The most important public-facing type of this library. Exposes a type-safe
 /// API for working with versioned trees. It uses interior mutability and
 /// cloning it only gives a new handle on the underlying shared memory.
-pub struct TreeVersion<V: Version>(Arc<Mutex<TreeVersionData<V::TreeVersion>>>);
+pub struct TreeVersion<V: Version, H: TreeHasher = AppHasher> {
+    data:     Arc<Mutex<TreeVersionData<V::TreeVersion, H>>>,
+    view:     Arc<Published<ReadView<H>>>,
+    name:     &'static str,
+    /// The derived versions built in the same chain
+    versions: Arc<VersionRegistry<H>>,
+}
 
-impl<V: Version> Clone for TreeVersion<V> {
+impl<V: Version, H: TreeHasher> Clone for TreeVersion<V, H> {
     fn clone(&self) -> Self {
-        Self(self.0.clone())
+        Self {
+            data:     self.data.clone(),
+            view:     self.view.clone(),
+            name:     self.name,
+            versions: self.versions.clone(),
+        }
     }
 }
 
-impl<V: Version<TreeVersion = lazy_merkle_tree::Derived>> TreeVersion<V> {
+impl<V: Version<TreeVersion = lazy_merkle_tree::Derived>, H: TreeHasher> TreeVersion<V, H> {
     /// Only used internally to upcast a compatible tree version to
     /// `AnyDerived`.
-    fn as_derived(&self) -> TreeVersion<AnyDerived> {
-        TreeVersion(self.0.clone())
+    fn as_derived(&self) -> TreeVersion<AnyDerived, H> {
+        TreeVersion {
+            data:     self.data.clone(),
+            view:     self.view.clone(),
+            name:     self.name,
+            versions: self.versions.clone(),
+        }
     }
 }
 
 /// The public-facing API for reading from a tree version. It is implemented for
 /// all versions. This being a trait allows us to hide some of the
-/// implementation details.
+/// implementation details. Reads never wait for the version to be modified,
+/// they see it as of the last completed modification.
 pub trait TreeVersionReadOps {
+    /// The hash function of the tree.
+    type Hasher: TreeHasher;
+
     /// Returns the current tree root.
     fn get_root(&self) -> Hash;
     /// Returns the next free leaf.
     fn next_leaf(&self) -> usize;
     /// Returns the given leaf value, the root of the tree and the proof
-    fn get_leaf_and_proof(&self, leaf: usize) -> (Hash, Hash, Proof);
+    fn get_leaf_and_proof(&self, leaf: usize) -> (Hash, Hash, merkle_tree::Proof<Self::Hasher>);
     /// Returns the merkle proof and element at the given leaf.
-    fn get_proof(&self, leaf: usize) -> (Hash, Proof);
+    fn get_proof(&self, leaf: usize) -> (Hash, merkle_tree::Proof<Self::Hasher>);
     /// Gets the leaf value at a given index.
     fn get_leaf(&self, leaf: usize) -> Hash;
+    /// Returns a proof of the leaves from `start` up to `end`, exclusive,
+    /// which must be a non-empty range of leaves of the tree.
+    fn get_range_proof(&self, start: usize, end: usize) -> RangeProof;
 }
 
-impl<V: Version> TreeVersionReadOps for TreeVersion<V>
+impl<V: Version, H: TreeHasher> TreeVersionReadOps for TreeVersion<V, H>
 where
-    TreeVersionData<V::TreeVersion>: BasicTreeOps,
+    TreeVersionData<V::TreeVersion, H>: BasicTreeOps<H>,
 {
+    type Hasher = H;
+
     fn get_root(&self) -> Hash {
-        self.get_data().get_root()
+        self.view.load().tree.root()
     }
 
     fn next_leaf(&self) -> usize {
-        self.get_data().next_leaf
+        self.view.load().next_leaf
     }
 
-    fn get_leaf_and_proof(&self, leaf: usize) -> (Hash, Hash, Proof) {
-        let tree = self.get_data();
+    fn get_leaf_and_proof(&self, leaf: usize) -> (Hash, Hash, merkle_tree::Proof<H>) {
+        let view = self.view.load();
 
-        let (root, proof) = tree.get_proof(leaf);
-        let leaf = tree.get_leaf(leaf);
+        let (root, proof) = view.get_proof(leaf);
+        let leaf = view.tree.get_leaf(leaf);
 
         (leaf, root, proof)
     }
 
-    fn get_proof(&self, leaf: usize) -> (Hash, Proof) {
-        let tree = self.get_data();
-        tree.get_proof(leaf)
+    fn get_proof(&self, leaf: usize) -> (Hash, merkle_tree::Proof<H>) {
+        self.view.load().get_proof(leaf)
     }
 
     fn get_leaf(&self, leaf: usize) -> Hash {
-        let tree = self.get_data();
-        tree.get_leaf(leaf)
+        self.view.load().tree.get_leaf(leaf)
+    }
+
+    fn get_range_proof(&self, start: usize, end: usize) -> RangeProof {
+        self.view.load().get_range_proof(start, end)
     }
 }
 
-impl<V: Version> TreeVersion<V> {
-    fn get_data(&self) -> MutexGuard<TreeVersionData<V::TreeVersion>> {
-        self.0.lock().expect("no lock poisoning")
+impl<V: Version, H: TreeHasher> TreeVersion<V, H> {
+    fn new(
+        data: TreeVersionData<V::TreeVersion, H>,
+        name: &'static str,
+        versions: Arc<VersionRegistry<H>>,
+    ) -> Self {
+        Self {
+            view: data.view.clone(),
+            data: Arc::new(Mutex::new(data)),
+            name,
+            versions,
+        }
+    }
+
+    /// Locks the version to read from it.
+    fn read_data(&self) -> MutexGuard<TreeVersionData<V::TreeVersion, H>> {
+        lock_metrics::lock(&self.data, self.name, Access::Read)
+    }
+
+    /// Locks the version to modify it.
+    fn write_data(&self) -> MutexGuard<TreeVersionData<V::TreeVersion, H>> {
+        lock_metrics::lock(&self.data, self.name, Access::Write)
     }
 }
 
-impl TreeVersion<Latest> {
+impl<H: TreeHasher> TreeVersion<Latest, H> {
     /// Appends many identities to the tree, returns a list with the root, proof
     /// of inclusion and leaf index
     #[must_use]
-    pub fn append_many(&self, identities: &[Hash]) -> Vec<(Hash, Proof, usize)> {
-        let mut data = self.get_data();
+    pub fn append_many(&self, identities: &[Hash]) -> Vec<(Hash, merkle_tree::Proof<H>, usize)> {
+        let mut data = self.write_data();
+        let output = Self::append_to(&mut data, identities);
+        data.publish();
+        output
+    }
+
+    /// Appends many identities like [`Self::append_many`], but only if they
+    /// lead the tree from `pre_root` to `post_root`, e.g. when applying a
+    /// batch submitted by someone else. The tree is left untouched otherwise.
+    #[must_use]
+    pub fn append_many_between(
+        &self,
+        pre_root: Hash,
+        post_root: Hash,
+        identities: &[Hash],
+    ) -> Option<Vec<(Hash, merkle_tree::Proof<H>, usize)>> {
+        let mut data = self.write_data();
+        if data.get_root() != pre_root {
+            return None;
+        }
+
+        let next_leaf = data.next_leaf;
+        let tree = identities
+            .iter()
+            .enumerate()
+            .fold(data.tree.clone(), |tree, (idx, identity)| {
+                tree.update(next_leaf + idx, identity)
+            });
+        if tree.root() != post_root {
+            return None;
+        }
+
+        let output = Self::append_to(&mut data, identities);
+        data.publish();
+        Some(output)
+    }
+
+    fn append_to(
+        data: &mut TreeVersionData<lazy_merkle_tree::Derived, H>,
+        identities: &[Hash],
+    ) -> Vec<(Hash, merkle_tree::Proof<H>, usize)> {
         let next_leaf = data.next_leaf;
 
         let mut output = Vec::with_capacity(identities.len());
@@ -435,14 +816,22 @@ impl TreeVersion<Latest> {
             output.push((root, proof, leaf_index));
         }
 
+        if let Some((root, proof, leaf_index)) = output.last() {
+            data.metadata.recent_proof = Some(RecentProof {
+                leaf_index: *leaf_index,
+                root:       *root,
+                proof:      proof.clone(),
+            });
+        }
+
         output
     }
 
     /// Deletes many identities from the tree, returns a list with the root
     /// and proof of inclusion
     #[must_use]
-    pub fn delete_many(&self, leaf_indices: &[usize]) -> Vec<(Hash, Proof)> {
-        let mut data = self.get_data();
+    pub fn delete_many(&self, leaf_indices: &[usize]) -> Vec<(Hash, merkle_tree::Proof<H>)> {
+        let mut data = self.write_data();
 
         let mut output = Vec::with_capacity(leaf_indices.len());
 
@@ -452,17 +841,50 @@ impl TreeVersion<Latest> {
 
             output.push((root, proof));
         }
+        data.publish();
 
         output
     }
+
+    /// Returns the insertions at the end of the version which weren't taken
+    /// into a batch yet, oldest first. Only these can be rolled back.
+    #[must_use]
+    pub fn unbatched_insertions(&self) -> Vec<TreeUpdate> {
+        let data = self.read_data();
+
+        let mut insertions: Vec<_> = data
+            .metadata
+            .diff
+            .iter()
+            .rev()
+            .take_while(|applied_update| applied_update.update.element != Hash::ZERO)
+            .map(|applied_update| applied_update.update.clone())
+            .collect();
+        insertions.reverse();
+        insertions
+    }
+
+    /// Applies updates written to the database by another instance, returns
+    /// the resulting root.
+    pub fn apply_updates(&self, updates: &[TreeUpdate]) -> Hash {
+        let mut data = self.write_data();
+
+        for update in updates {
+            data.update(update.leaf_index, update.element);
+        }
+        data.publish();
+
+        data.get_root()
+    }
 }
 
-impl<T> TreeVersion<T>
+impl<T, H> TreeVersion<T, H>
 where
     T: Version,
+    H: TreeHasher,
 {
     pub fn commitments_by_indices(&self, indices: impl IntoIterator<Item = usize>) -> Vec<Hash> {
-        let tree = self.get_data();
+        let tree = self.read_data();
 
         let mut commitments = vec![];
 
@@ -476,40 +898,243 @@ where
 
 /// Public API for working with versions that have a successor. Such versions
 /// only allow peeking and applying updates from the successor.
-pub trait TreeWithNextVersion {
-    fn peek_next_updates(&self, maximum_update_count: usize) -> Vec<AppliedTreeUpdate>;
+pub trait TreeWithNextVersion: TreeVersionReadOps {
+    fn peek_next_updates(
+        &self,
+        maximum_update_count: usize,
+    ) -> Vec<AppliedTreeUpdate<Self::Hasher>>;
     fn apply_updates_up_to(&self, root: Hash) -> usize;
 }
 
-impl<V> TreeWithNextVersion for TreeVersion<V>
+impl<V, H> TreeWithNextVersion for TreeVersion<V, H>
 where
     V: HasNextVersion,
-    TreeVersionData<<V as Version>::TreeVersion>: BasicTreeOps,
+    H: TreeHasher,
+    TreeVersionData<<V as Version>::TreeVersion, H>: BasicTreeOps<H>,
 {
-    fn peek_next_updates(&self, maximum_update_count: usize) -> Vec<AppliedTreeUpdate> {
-        self.get_data().peek_next_updates(maximum_update_count)
+    fn peek_next_updates(&self, maximum_update_count: usize) -> Vec<AppliedTreeUpdate<H>> {
+        self.read_data().peek_next_updates(maximum_update_count)
     }
 
     fn apply_updates_up_to(&self, root: Hash) -> usize {
-        self.get_data().apply_updates_up_to(root)
+        self.write_data().apply_updates_up_to(root)
+    }
+}
+
+impl<H: TreeHasher> TreeVersion<Intermediate, H> {
+    /// Returns the nodes of this version as of now, level by level starting
+    /// at `from_level`. Later updates to the version are not reflected.
+    #[must_use]
+    pub fn nodes(&self, from_level: usize) -> TreeNodes<H> {
+        let data = self.read_data();
+
+        TreeNodes {
+            tree:       data.tree.clone(),
+            leaf_count: data.next_leaf,
+            level:      from_level,
+            index:      0,
+        }
+    }
+}
+
+/// A node of the tree, level 0 being the leaves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TreeNode {
+    pub level: usize,
+    pub index: usize,
+    pub hash:  Hash,
+}
+
+/// Iterates the nodes of a tree from the leaves up to the root, see
+/// [`TreeVersion::nodes`]. Only nodes with inserted leaves below them are
+/// yielded, the nodes to their right are roots of empty subtrees.
+///
+/// Nodes are read from the proofs of the tree rather than rehashed.
+pub struct TreeNodes<H: TreeHasher = AppHasher> {
+    tree:       LazyMerkleTree<H, Derived>,
+    leaf_count: usize,
+    level:      usize,
+    index:      usize,
+}
+
+impl<H: TreeHasher> TreeNodes<H> {
+    /// The number of nodes yielded for a level.
+    fn width(&self, level: usize) -> usize {
+        if level == self.tree.depth() {
+            1
+        } else {
+            (self.leaf_count + (1 << level) - 1) >> level
+        }
+    }
+
+    fn node(&self, level: usize, index: usize) -> Hash {
+        if level == 0 {
+            return self.tree.get_leaf(index);
+        }
+
+        if level == self.tree.depth() {
+            return self.tree.root();
+        }
+
+        // The node is the sibling on the path of the leaves below its
+        // sibling.
+        let proof = self.tree.proof((index ^ 1) << level);
+        match proof.0[level] {
+            Branch::Left(hash) | Branch::Right(hash) => hash,
+        }
     }
 }
 
+impl<H: TreeHasher> Iterator for TreeNodes<H> {
+    type Item = TreeNode;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.level <= self.tree.depth() {
+            if self.index < self.width(self.level) {
+                let node = TreeNode {
+                    level: self.level,
+                    index: self.index,
+                    hash:  self.node(self.level, self.index),
+                };
+                self.index += 1;
+
+                return Some(node);
+            }
+
+            self.level += 1;
+            self.index = 0;
+        }
+
+        None
+    }
+}
+
+/// How a version of the chain is referenced, as found by
+/// [`TreeState::collect_orphaned_versions`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionReferences {
+    pub name:            &'static str,
+    /// Handles to the version besides the link from its predecessor,
+    /// including those of the tree state
+    pub handles:         usize,
+    /// Updates applied to the version which its predecessor didn't take yet
+    pub pending_updates: usize,
+}
+
+/// The outcome of a garbage collection pass over the versions of a chain.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionGcReport {
+    /// The versions reachable from the mined version, in order
+    pub versions:          Vec<VersionReferences>,
+    /// Versions cut from the chain but still held, which were detached from
+    /// their successors and emptied of their updates
+    pub orphaned:          usize,
+    /// Updates dropped from the orphaned versions
+    pub reclaimed_updates: usize,
+    /// Versions dropped since the previous pass
+    pub released:          usize,
+}
+
 #[derive(Clone)]
-pub struct TreeState {
-    mined:     TreeVersion<Canonical>,
-    processed: TreeVersion<Intermediate>,
-    batching:  TreeVersion<Intermediate>,
-    latest:    TreeVersion<Latest>,
+pub struct TreeState<H: TreeHasher = AppHasher> {
+    mined:     TreeVersion<Canonical, H>,
+    processed: TreeVersion<Intermediate, H>,
+    batching:  TreeVersion<Intermediate, H>,
+    latest:    TreeVersion<Latest, H>,
 }
 
 impl TreeState {
+    /// Builds a tree state whose versions all hold the snapshotted tree.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the built tree doesn't have the root of the
+    /// snapshot, i.e. the initial leaf value differs.
+    pub fn from_snapshot(
+        snapshot: &TreeSnapshot,
+        initial_leaf_value: Hash,
+        dense_prefix_depth: usize,
+        gc_threshold: usize,
+        mmap_file_path: &str,
+    ) -> AnyhowResult<Self> {
+        let (mined, processed_builder) = CanonicalTreeBuilder::new(
+            snapshot.depth(),
+            dense_prefix_depth,
+            gc_threshold,
+            initial_leaf_value,
+            &snapshot.leaves(),
+            mmap_file_path,
+        )
+        .seal();
+        let (processed, batching_builder) = processed_builder.seal_and_continue();
+        let (batching, latest_builder) = batching_builder.seal_and_continue();
+        let latest = latest_builder.seal();
+
+        ensure!(
+            mined.get_root() == snapshot.root(),
+            "tree built from the snapshot has root {:#x}, expected {:#x}, check \
+             --initial-leaf-value",
+            mined.get_root(),
+            snapshot.root()
+        );
+
+        Ok(Self::new(mined, processed, batching, latest))
+    }
+
+    /// Returns the leaf of the item and its proof against the tree version
+    /// matching its status. With `report_batched`, pending items which are
+    /// already part of a batch are proven against the batching tree and
+    /// reported as batched. Otherwise they're proven against the latest tree,
+    /// like other pending items.
+    #[must_use]
+    pub fn get_proof_for(&self, item: &TreeItem, report_batched: bool) -> (Field, InclusionProof) {
+        let (status, (leaf, root, proof)) = match item.status {
+            ProcessedStatus::Pending | ProcessedStatus::Batched if !report_batched => (
+                ProcessedStatus::Pending,
+                self.latest.get_leaf_and_proof(item.leaf_index),
+            ),
+            ProcessedStatus::Pending | ProcessedStatus::Batched => {
+                let latest = self.latest.get_leaf_and_proof(item.leaf_index);
+
+                if self.batching.get_leaf(item.leaf_index) == latest.0 {
+                    (
+                        ProcessedStatus::Batched,
+                        self.batching.get_leaf_and_proof(item.leaf_index),
+                    )
+                } else {
+                    (ProcessedStatus::Pending, latest)
+                }
+            }
+            ProcessedStatus::Processed => (
+                item.status,
+                self.processed.get_leaf_and_proof(item.leaf_index),
+            ),
+            ProcessedStatus::Mined => (item.status, self.mined.get_leaf_and_proof(item.leaf_index)),
+        };
+
+        let proof = InclusionProof {
+            status:            status.into(),
+            root:              Some(root),
+            proof:             Some(poseidon_proof(proof)),
+            message:           None,
+            mined_transaction: None,
+            mined_block:       None,
+        };
+
+        (leaf, proof)
+    }
+}
+
+impl<H: TreeHasher> TreeState<H> {
     #[must_use]
     pub const fn new(
-        mined: TreeVersion<Canonical>,
-        processed: TreeVersion<Intermediate>,
-        batching: TreeVersion<Intermediate>,
-        latest: TreeVersion<Latest>,
+        mined: TreeVersion<Canonical, H>,
+        processed: TreeVersion<Intermediate, H>,
+        batching: TreeVersion<Intermediate, H>,
+        latest: TreeVersion<Latest, H>,
     ) -> Self {
         Self {
             mined,
@@ -519,50 +1144,198 @@ impl TreeState {
         }
     }
 
+    /// Replaces the trees of every version with those of `replacement`, for
+    /// everyone sharing this state. All versions are locked until each was
+    /// replaced, so that no update is applied to a mix of old and new trees.
+    /// Readers see each version replaced at once.
+    pub fn replace_with(&self, replacement: &Self) {
+        // Locked in the order updates are promoted in.
+        let mut mined = self.mined.write_data();
+        let mut processed = self.processed.write_data();
+        let mut batching = self.batching.write_data();
+        let mut latest = self.latest.write_data();
+
+        mined.take_contents(&mut replacement.mined.write_data());
+        processed.take_contents(&mut replacement.processed.write_data());
+        batching.take_contents(&mut replacement.batching.write_data());
+        latest.take_contents(&mut replacement.latest.write_data());
+    }
+
+    /// Rolls back the `count` most recent insertions to the latest version,
+    /// restoring its next leaf. Returns the rolled back insertions, oldest
+    /// first, or `None` without touching the tree if fewer than `count`
+    /// insertions weren't taken into a batch yet.
+    pub fn roll_back_latest(&self, count: usize) -> Option<Vec<TreeUpdate>> {
+        // Locked in the order updates are promoted in, the batching tree is
+        // what remains once every update of the latest version is rolled back.
+        let batching = self.batching.read_data();
+        let mut latest = self.latest.write_data();
+
+        let diff = &latest.metadata.diff;
+        let kept = diff.len().checked_sub(count)?;
+        if diff[kept..]
+            .iter()
+            .any(|applied_update| applied_update.update.element == Hash::ZERO)
+        {
+            return None;
+        }
+
+        let rolled_back: Vec<_> = latest
+            .metadata
+            .diff
+            .drain(kept..)
+            .map(|applied_update| applied_update.update)
+            .collect();
+        let Some(first) = rolled_back.first() else {
+            return Some(rolled_back);
+        };
+
+        let tree = latest
+            .metadata
+            .diff
+            .last()
+            .map_or_else(|| batching.tree.clone(), |last| last.result.clone());
+        latest.tree = tree;
+        latest.next_leaf = first.leaf_index;
+        latest.metadata.recent_proof = None;
+        latest.publish();
+
+        Some(rolled_back)
+    }
+
+    /// Finds the derived versions which can't be reached from the mined
+    /// version anymore, but are kept alive by stray handles. Each is detached
+    /// from its successor, so that versions only it held are dropped, and its
+    /// updates, each of which holds a tree, are dropped. Also reports how the
+    /// reachable versions are referenced.
+    ///
+    /// Versions are locked one at a time, so no version lock may be held while
+    /// calling this.
+    pub fn collect_orphaned_versions(&self) -> VersionGcReport {
+        let mut versions = vec![VersionReferences {
+            name:            self.mined.name,
+            handles:         Arc::strong_count(&self.mined.data),
+            pending_updates: 0,
+        }];
+        let mut reachable = Vec::new();
+
+        let mut next = self.mined.read_data().next.clone();
+        while let Some(version) = next {
+            let data = version.read_data();
+            // Neither the link from the predecessor nor `version` are handles.
+            versions.push(VersionReferences {
+                name:            version.name,
+                handles:         Arc::strong_count(&version.data) - 2,
+                pending_updates: data.metadata.diff.len(),
+            });
+            reachable.push(Arc::as_ptr(&version.data));
+            next = data.next.clone();
+        }
+
+        let (orphans, released) = self.mined.versions.take_orphans(&reachable);
+        let mut reclaimed_updates = 0;
+        for orphan in &orphans {
+            let successor = {
+                let mut data = lock_metrics::lock(orphan, "orphaned", Access::Write);
+                reclaimed_updates += data.metadata.diff.len();
+                data.metadata.diff = Vec::new();
+                data.metadata.recent_proof = None;
+                data.next.take()
+            };
+            // Dropped without holding the lock of the orphan.
+            drop(successor);
+        }
+
+        if orphans.is_empty() {
+            info!(released, "No orphaned tree versions found");
+        } else {
+            warn!(
+                orphaned = orphans.len(),
+                reclaimed_updates, released, "Collected orphaned tree versions"
+            );
+        }
+
+        VersionGcReport {
+            versions,
+            orphaned: orphans.len(),
+            reclaimed_updates,
+            released,
+        }
+    }
+
     #[must_use]
-    pub fn get_latest_tree(&self) -> TreeVersion<Latest> {
+    pub fn get_latest_tree(&self) -> TreeVersion<Latest, H> {
         self.latest.clone()
     }
 
     #[must_use]
-    pub fn get_mined_tree(&self) -> TreeVersion<Canonical> {
+    pub fn get_mined_tree(&self) -> TreeVersion<Canonical, H> {
         self.mined.clone()
     }
 
     #[must_use]
-    pub fn get_processed_tree(&self) -> TreeVersion<Intermediate> {
+    pub fn get_processed_tree(&self) -> TreeVersion<Intermediate, H> {
         self.processed.clone()
     }
 
     #[must_use]
-    pub fn get_batching_tree(&self) -> TreeVersion<Intermediate> {
+    pub fn get_batching_tree(&self) -> TreeVersion<Intermediate, H> {
         self.batching.clone()
     }
+}
 
+/// A helper for building the first tree version. Exposes a type-safe API over
+/// building a sequence of tree versions efficiently.
+pub struct CanonicalTreeBuilder<H: TreeHasher = AppHasher>(
+    TreeVersionData<lazy_merkle_tree::Canonical, H>,
+);
+impl CanonicalTreeBuilder {
+    /// Creates a new builder hashing with Poseidon, see
+    /// [`Self::new_with_hasher`].
     #[must_use]
-    pub fn get_proof_for(&self, item: &TreeItem) -> (Field, InclusionProof) {
-        let (leaf, root, proof) = match item.status {
-            ProcessedStatus::Pending => self.latest.get_leaf_and_proof(item.leaf_index),
-            ProcessedStatus::Processed => self.processed.get_leaf_and_proof(item.leaf_index),
-            ProcessedStatus::Mined => self.mined.get_leaf_and_proof(item.leaf_index),
-        };
-
-        let proof = InclusionProof {
-            status:  item.status.into(),
-            root:    Some(root),
-            proof:   Some(proof),
-            message: None,
-        };
+    pub fn new(
+        tree_depth: usize,
+        dense_prefix_depth: usize,
+        flattening_threshold: usize,
+        initial_leaf: Field,
+        initial_leaves: &[Field],
+        mmap_file_path: &str,
+    ) -> Self {
+        Self::new_with_hasher(
+            tree_depth,
+            dense_prefix_depth,
+            flattening_threshold,
+            initial_leaf,
+            initial_leaves,
+            mmap_file_path,
+        )
+    }
 
-        (leaf, proof)
+    /// Restores a builder hashing with Poseidon, see
+    /// [`Self::restore_with_hasher`].
+    pub fn restore(
+        tree_depth: usize,
+        dense_prefix_depth: usize,
+        initial_leaf: &Field,
+        last_index: usize,
+        leftover_items: &[ruint::Uint<256, 4>],
+        flattening_threshold: usize,
+        mmap_file_path: &str,
+    ) -> Option<Self> {
+        Self::restore_with_hasher(
+            tree_depth,
+            dense_prefix_depth,
+            initial_leaf,
+            last_index,
+            leftover_items,
+            flattening_threshold,
+            mmap_file_path,
+        )
     }
 }
 
-/// A helper for building the first tree version. Exposes a type-safe API over
-/// building a sequence of tree versions efficiently.
-pub struct CanonicalTreeBuilder(TreeVersionData<lazy_merkle_tree::Canonical>);
-impl CanonicalTreeBuilder {
-    /// Creates a new builder with the given parameters.
+impl<H: TreeHasher> CanonicalTreeBuilder<H> {
+    /// Creates a new builder with the given parameters, hashing with `H`.
     /// * `tree_depth`: The depth of the tree.
     /// * `dense_prefix_depth`: The depth of the dense prefix – i.e. the prefix
     ///   of the tree that will be stored in a vector rather than in a
@@ -575,7 +1348,7 @@ impl CanonicalTreeBuilder {
     ///   nodes in the tree.
     /// * `initial_leaf`: The initial value of the tree leaves.
     #[must_use]
-    pub fn new(
+    pub fn new_with_hasher(
         tree_depth: usize,
         dense_prefix_depth: usize,
         flattening_threshold: usize,
@@ -588,7 +1361,7 @@ impl CanonicalTreeBuilder {
             initial_leaves.split_at(initial_leaves_in_dense_count);
 
         let tree =
-            PoseidonTree::<lazy_merkle_tree::Canonical>::new_mmapped_with_dense_prefix_with_init_values(
+            LazyMerkleTree::<H, lazy_merkle_tree::Canonical>::new_mmapped_with_dense_prefix_with_init_values(
                 tree_depth,
                 dense_prefix_depth,
                 &initial_leaf,
@@ -599,11 +1372,16 @@ impl CanonicalTreeBuilder {
             flatten_threshold:        flattening_threshold,
             count_since_last_flatten: 0,
         };
+        let view = Arc::new(Published::new(ReadView::new(
+            tree.derived(),
+            initial_leaves_in_dense_count,
+        )));
         let mut builder = Self(TreeVersionData {
             tree,
             next_leaf: initial_leaves_in_dense_count,
             metadata,
             next: None,
+            view,
         });
         for (index, leaf) in leftover_initial_leaves.iter().enumerate() {
             builder.update(&TreeUpdate {
@@ -614,7 +1392,9 @@ impl CanonicalTreeBuilder {
         builder
     }
 
-    pub fn restore(
+    /// Restores the tree of a builder hashing with `H` from the file it was
+    /// memory-mapped to, see [`Self::new_with_hasher`].
+    pub fn restore_with_hasher(
         tree_depth: usize,
         dense_prefix_depth: usize,
         initial_leaf: &Field,
@@ -623,8 +1403,8 @@ impl CanonicalTreeBuilder {
         flattening_threshold: usize,
         mmap_file_path: &str,
     ) -> Option<Self> {
-        let tree: LazyMerkleTree<PoseidonHash, lazy_merkle_tree::Canonical> =
-            match PoseidonTree::<lazy_merkle_tree::Canonical>::attempt_dense_mmap_restore(
+        let tree: LazyMerkleTree<H, lazy_merkle_tree::Canonical> =
+            match LazyMerkleTree::<H, lazy_merkle_tree::Canonical>::attempt_dense_mmap_restore(
                 tree_depth,
                 dense_prefix_depth,
                 initial_leaf,
@@ -641,11 +1421,16 @@ impl CanonicalTreeBuilder {
             flatten_threshold:        flattening_threshold,
             count_since_last_flatten: 0,
         };
+        let view = Arc::new(Published::new(ReadView::new(
+            tree.derived(),
+            last_index + 1,
+        )));
         let mut builder = Self(TreeVersionData {
             tree,
             next_leaf: last_index + 1,
             metadata,
             next: None,
+            view,
         });
 
         for (index, leaf) in leftover_items.iter().enumerate() {
@@ -665,38 +1450,47 @@ impl CanonicalTreeBuilder {
 
     /// Seals this version and returns a builder for the next version.
     #[must_use]
-    pub fn seal(self) -> (TreeVersion<Canonical>, DerivedTreeBuilder<Canonical>) {
+    pub fn seal(mut self) -> (TreeVersion<Canonical, H>, DerivedTreeBuilder<Canonical, H>) {
+        self.0.publish();
         let next_tree = self.0.tree.derived();
         let next_leaf = self.0.next_leaf;
-        let sealed = TreeVersion(Arc::new(Mutex::new(self.0)));
-        let next = DerivedTreeBuilder::<Canonical>::new(next_tree, next_leaf, sealed.clone());
+        let sealed = TreeVersion::new(self.0, VERSION_NAMES[0], Arc::new(VersionRegistry::new()));
+        let next = DerivedTreeBuilder::<Canonical, H>::new(next_tree, next_leaf, sealed.clone(), 1);
         (sealed, next)
     }
 }
 
 /// A helper for building successive tree versions. Exposes a type-safe API over
 /// building a sequence of tree versions efficiently.
-pub struct DerivedTreeBuilder<P: Version> {
-    prev:    TreeVersion<P>,
-    current: TreeVersionData<lazy_merkle_tree::Derived>,
+pub struct DerivedTreeBuilder<P: Version, H: TreeHasher = AppHasher> {
+    prev:     TreeVersion<P, H>,
+    current:  TreeVersionData<lazy_merkle_tree::Derived, H>,
+    /// Index of the version being built in the sequence
+    position: usize,
 }
 
-impl<P: Version> DerivedTreeBuilder<P> {
+impl<P: Version, H: TreeHasher> DerivedTreeBuilder<P, H> {
     #[must_use]
-    const fn new<Prev: Version>(
-        tree: PoseidonTree<lazy_merkle_tree::Derived>,
+    fn new<Prev: Version>(
+        tree: LazyMerkleTree<H, lazy_merkle_tree::Derived>,
         next_leaf: usize,
-        prev: TreeVersion<Prev>,
-    ) -> DerivedTreeBuilder<Prev> {
-        let metadata = DerivedTreeMetadata { diff: vec![] };
+        prev: TreeVersion<Prev, H>,
+        position: usize,
+    ) -> DerivedTreeBuilder<Prev, H> {
+        let metadata = DerivedTreeMetadata {
+            diff:         vec![],
+            recent_proof: None,
+        };
         DerivedTreeBuilder {
             prev,
             current: TreeVersionData {
+                view: Arc::new(Published::new(ReadView::new(tree.clone(), next_leaf))),
                 tree,
                 next_leaf,
                 metadata,
                 next: None,
             },
+            position,
         }
     }
 
@@ -708,29 +1502,49 @@ impl<P: Version> DerivedTreeBuilder<P> {
     /// Seals this version and returns a builder for the next version.
     #[must_use]
     pub fn seal_and_continue(
-        self,
-    ) -> (TreeVersion<Intermediate>, DerivedTreeBuilder<Intermediate>) {
+        mut self,
+    ) -> (
+        TreeVersion<Intermediate, H>,
+        DerivedTreeBuilder<Intermediate, H>,
+    ) {
+        self.current.publish();
         let next_tree = self.current.tree.clone();
         let next_leaf = self.current.next_leaf;
-        let sealed = TreeVersion(Arc::new(Mutex::new(self.current)));
-        let next = Self::new(next_tree, next_leaf, sealed.clone());
-        self.prev.get_data().next = Some(sealed.as_derived());
+        let name = VERSION_NAMES
+            .get(self.position)
+            .copied()
+            .unwrap_or("intermediate");
+        let sealed = TreeVersion::new(self.current, name, self.prev.versions.clone());
+        sealed.versions.register(&sealed.data);
+        let next = Self::new(next_tree, next_leaf, sealed.clone(), self.position + 1);
+        self.prev.write_data().next = Some(sealed.as_derived());
         (sealed, next)
     }
 
     /// Seals this version and finishes the building process.
     #[must_use]
-    pub fn seal(self) -> TreeVersion<Latest> {
-        let sealed = TreeVersion(Arc::new(Mutex::new(self.current)));
-        self.prev.get_data().next = Some(sealed.as_derived());
+    pub fn seal(mut self) -> TreeVersion<Latest, H> {
+        self.current.publish();
+        let sealed = TreeVersion::new(self.current, "latest", self.prev.versions.clone());
+        sealed.versions.register(&sealed.data);
+        self.prev.write_data().next = Some(sealed.as_derived());
         sealed
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::{Duration, Instant};
+
+    use semaphore::merkle_tree::Hasher;
+    use semaphore::poseidon_tree::{LazyPoseidonTree, PoseidonHash};
 
-    use super::{CanonicalTreeBuilder, Hash, TreeWithNextVersion};
+    use super::{
+        verify_proof, verify_range_proof, CanonicalTreeBuilder, Hash, ProcessedStatus, Status,
+        TreeHasher, TreeItem, TreeNode, TreeSnapshot, TreeState, TreeUpdate, TreeVersionReadOps,
+        TreeWithNextVersion,
+    };
 
     #[test]
     fn test_peek_next_updates() {
@@ -779,4 +1593,582 @@ mod tests {
 
         assert_eq!(next_updates.len(), 3);
     }
+
+    #[test]
+    fn test_leaf_index_beyond_u32() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let leaf_index = (1usize << 33) + 5;
+        let element = Hash::from(42);
+
+        let mut builder = CanonicalTreeBuilder::new(
+            40,
+            10,
+            0,
+            Hash::ZERO,
+            &[Hash::from(1), Hash::from(2)],
+            temp_dir.path().join("testfile").to_str().unwrap(),
+        );
+        builder.update(&TreeUpdate {
+            leaf_index,
+            element,
+        });
+        let (canonical_tree, _) = builder.seal();
+
+        assert_eq!(canonical_tree.next_leaf(), leaf_index + 1);
+
+        let (leaf, root, proof) = canonical_tree.get_leaf_and_proof(leaf_index);
+        assert_eq!(leaf, element);
+        assert_eq!(proof.0.len(), 40);
+        assert_eq!(proof.leaf_index(), leaf_index);
+        assert_eq!(proof.root(leaf), root);
+    }
+
+    #[test]
+    fn test_batched_identities_are_proven_against_batching_tree() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let (mined, processed_builder) = CanonicalTreeBuilder::new(
+            10,
+            10,
+            0,
+            Hash::ZERO,
+            &[Hash::from(1)],
+            temp_dir.path().join("testfile").to_str().unwrap(),
+        )
+        .seal();
+        let (processed, batching_builder) = processed_builder.seal_and_continue();
+        let (batching, mut latest_builder) = batching_builder.seal_and_continue();
+        latest_builder.update(&TreeUpdate {
+            leaf_index: 1,
+            element:    Hash::from(2),
+        });
+        latest_builder.update(&TreeUpdate {
+            leaf_index: 2,
+            element:    Hash::from(3),
+        });
+        let latest = latest_builder.seal();
+        let tree_state = TreeState::new(mined, processed, batching.clone(), latest.clone());
+
+        // The first pending identity is taken into a batch.
+        let batch_root = batching
+            .peek_next_updates(1)
+            .last()
+            .map(|update| update.result.root())
+            .unwrap();
+        batching.apply_updates_up_to(batch_root);
+
+        let pending = |leaf_index| TreeItem {
+            status: ProcessedStatus::Pending,
+            leaf_index,
+        };
+
+        let (leaf, proof) = tree_state.get_proof_for(&pending(1), true);
+        assert_eq!(leaf, Hash::from(2));
+        assert_eq!(proof.status, Status::Processed(ProcessedStatus::Batched));
+        assert_eq!(proof.root, Some(batching.get_root()));
+
+        let (leaf, proof) = tree_state.get_proof_for(&pending(1), false);
+        assert_eq!(leaf, Hash::from(2));
+        assert_eq!(proof.status, Status::Processed(ProcessedStatus::Pending));
+        assert_eq!(proof.root, Some(latest.get_root()));
+
+        let (leaf, proof) = tree_state.get_proof_for(&pending(2), true);
+        assert_eq!(leaf, Hash::from(3));
+        assert_eq!(proof.status, Status::Processed(ProcessedStatus::Pending));
+        assert_eq!(proof.root, Some(latest.get_root()));
+
+        let (_, proof) = tree_state.get_proof_for(
+            &TreeItem {
+                status:     ProcessedStatus::Mined,
+                leaf_index: 0,
+            },
+            true,
+        );
+        assert_eq!(proof.status, Status::Processed(ProcessedStatus::Mined));
+    }
+
+    #[test]
+    fn test_nodes_match_hashed_tree() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let leaves = [Hash::from(1), Hash::from(2), Hash::from(3)];
+        let (_, processed_builder) = CanonicalTreeBuilder::new(
+            3,
+            2,
+            0,
+            Hash::ZERO,
+            &leaves,
+            temp_dir.path().join("testfile").to_str().unwrap(),
+        )
+        .seal();
+        let (processed, _) = processed_builder.seal_and_continue();
+
+        let hash = |left, right| PoseidonHash::hash_node(&left, &right);
+        let empty = hash(Hash::ZERO, Hash::ZERO);
+        let left = hash(leaves[0], leaves[1]);
+        let right = hash(leaves[2], Hash::ZERO);
+        let subtree = hash(left, right);
+        let root = hash(subtree, hash(empty, empty));
+
+        let node = |level, index, hash| TreeNode { level, index, hash };
+        let expected = vec![
+            node(0, 0, leaves[0]),
+            node(0, 1, leaves[1]),
+            node(0, 2, leaves[2]),
+            node(1, 0, left),
+            node(1, 1, right),
+            node(2, 0, subtree),
+            node(3, 0, root),
+        ];
+
+        assert_eq!(processed.get_root(), root);
+        assert_eq!(processed.nodes(0).collect::<Vec<_>>(), expected);
+        assert_eq!(processed.nodes(2).collect::<Vec<_>>(), expected[5..]);
+    }
+
+    #[test]
+    fn test_recent_proof_is_invalidated_by_updates() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let (_, latest_builder) = CanonicalTreeBuilder::new(
+            10,
+            10,
+            0,
+            Hash::ZERO,
+            &[],
+            temp_dir.path().join("testfile").to_str().unwrap(),
+        )
+        .seal();
+        let latest = latest_builder.seal();
+
+        let inserted = latest.append_many(&[Hash::from(1), Hash::from(2)]);
+        let (root, proof, leaf_index) = inserted.last().cloned().unwrap();
+        assert_eq!(latest.get_proof(leaf_index), (root, proof));
+
+        // Deleting another leaf changes the path of the recent one.
+        let _ = latest.delete_many(&[0]);
+        let (leaf, root, proof) = latest.get_leaf_and_proof(leaf_index);
+        assert_eq!(root, latest.get_root());
+        assert_eq!(proof.root(leaf), root);
+        assert_ne!(root, inserted[1].0);
+    }
+
+    #[test]
+    fn test_append_many_between_requires_both_roots() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let (_, latest_builder) = CanonicalTreeBuilder::new(
+            10,
+            10,
+            0,
+            Hash::ZERO,
+            &[],
+            temp_dir.path().join("testfile").to_str().unwrap(),
+        )
+        .seal();
+        let latest = latest_builder.seal();
+
+        let identities = [Hash::from(1), Hash::from(2)];
+        let pre_root = latest.get_root();
+        let post_root = LazyPoseidonTree::new(10, Hash::ZERO)
+            .update(0, &identities[0])
+            .update(1, &identities[1])
+            .root();
+
+        // The identities don't lead to the post root.
+        let swapped = [identities[1], identities[0]];
+        assert_eq!(
+            latest.append_many_between(pre_root, post_root, &swapped),
+            None
+        );
+        assert_eq!(latest.get_root(), pre_root);
+
+        let appended = latest
+            .append_many_between(pre_root, post_root, &identities)
+            .unwrap();
+        assert_eq!(appended.len(), 2);
+        assert_eq!(latest.get_root(), post_root);
+
+        // The tree moved on from the pre root.
+        assert_eq!(
+            latest.append_many_between(pre_root, post_root, &identities),
+            None
+        );
+    }
+
+    #[test]
+    fn test_verify_proof() {
+        let tree = LazyPoseidonTree::new(4, Hash::ZERO)
+            .update(0, &Hash::from(1))
+            .update(1, &Hash::from(2));
+        let proof = tree.proof(1);
+
+        assert!(verify_proof(Hash::from(2), &proof, tree.root()));
+        assert!(!verify_proof(Hash::from(1), &proof, tree.root()));
+        assert!(!verify_proof(Hash::from(2), &proof, Hash::from(3)));
+    }
+
+    #[test]
+    fn test_range_proof() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let leaves: Vec<Hash> = (1..=11).map(Hash::from).collect();
+
+        let (tree, _) = CanonicalTreeBuilder::new(
+            5,
+            2,
+            0,
+            Hash::ZERO,
+            &leaves,
+            temp_dir.path().join("testfile").to_str().unwrap(),
+        )
+        .seal();
+        let root = tree.get_root();
+
+        for start in 0..leaves.len() {
+            for end in start + 1..=leaves.len() {
+                let proof = tree.get_range_proof(start, end);
+                assert_eq!(proof.leaves, leaves[start..end]);
+                assert!(verify_range_proof(&proof, 5, root), "[{start}, {end})");
+            }
+        }
+
+        // Nodes shared by the paths of the leaves are left out, 5 nodes rather
+        // than the 40 of independent proofs.
+        let proof = tree.get_range_proof(1, 9);
+        assert_eq!(
+            proof
+                .nodes
+                .iter()
+                .map(|node| (node.level, node.index))
+                .collect::<Vec<_>>(),
+            [(0, 0), (0, 9), (1, 5), (2, 3), (4, 1)]
+        );
+        assert_eq!(proof.nodes[0].hash, leaves[0]);
+        assert_eq!(proof.nodes[1].hash, leaves[9]);
+
+        let mut tampered = proof.clone();
+        tampered.leaves[3] = Hash::from(42);
+        assert!(!verify_range_proof(&tampered, 5, root));
+
+        let mut tampered = proof.clone();
+        tampered.nodes.pop();
+        assert!(!verify_range_proof(&tampered, 5, root));
+
+        assert!(!verify_range_proof(&proof, 6, root));
+    }
+
+    #[test]
+    fn test_reads_dont_wait_for_modifications() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let (mined, _) = CanonicalTreeBuilder::new(
+            10,
+            10,
+            0,
+            Hash::ZERO,
+            &[Hash::from(1)],
+            temp_dir.path().join("testfile").to_str().unwrap(),
+        )
+        .seal();
+        let root = mined.get_root();
+
+        // Hold the version as if it were being modified.
+        let _guard = mined.write_data();
+
+        let reader = {
+            let mined = mined.clone();
+            std::thread::spawn(move || mined.get_leaf_and_proof(0))
+        };
+        let started = Instant::now();
+        while !reader.is_finished() {
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "read waited for the version lock"
+            );
+            std::thread::yield_now();
+        }
+
+        let (leaf, proof_root, proof) = reader.join().unwrap();
+        assert_eq!(leaf, Hash::from(1));
+        assert_eq!(proof_root, root);
+        assert_eq!(proof.root(leaf), root);
+    }
+
+    #[test]
+    fn test_read_latency_during_promotion() {
+        const UPDATES: usize = 256;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let (mined, processed_builder) = CanonicalTreeBuilder::new(
+            10,
+            10,
+            1 << 20,
+            Hash::ZERO,
+            &[],
+            temp_dir.path().join("testfile").to_str().unwrap(),
+        )
+        .seal();
+        let processed = processed_builder.seal();
+
+        let identities: Vec<Hash> = (1..=UPDATES as u64).map(Hash::from).collect();
+        let appended = processed.append_many(&identities);
+        let pre_root = mined.get_root();
+        let post_root = appended.last().unwrap().0;
+
+        let reading = AtomicBool::new(false);
+        let promoted = AtomicBool::new(false);
+        let (promotion, reads) = std::thread::scope(|scope| {
+            let promotion = scope.spawn(|| {
+                while !reading.load(Ordering::SeqCst) {
+                    std::thread::yield_now();
+                }
+
+                let started = Instant::now();
+                assert_eq!(mined.apply_updates_up_to(post_root), UPDATES);
+                promoted.store(true, Ordering::SeqCst);
+                started.elapsed()
+            });
+
+            let mut reads = vec![];
+            while !promoted.load(Ordering::SeqCst) {
+                let started = Instant::now();
+                let (leaf, root, proof) = mined.get_leaf_and_proof(UPDATES / 2);
+                reads.push(started.elapsed());
+                reading.store(true, Ordering::SeqCst);
+
+                // Promotion is published at once, never half way through.
+                assert!(root == pre_root || root == post_root);
+                assert_eq!(proof.root(leaf), root);
+            }
+
+            (promotion.join().unwrap(), reads)
+        });
+
+        // Reads would otherwise wait for most of the promotion.
+        let slowest_read = reads.iter().max().copied().unwrap_or_default();
+        assert!(
+            slowest_read < promotion / 2,
+            "slowest of {} reads took {slowest_read:?} during a promotion taking {promotion:?}",
+            reads.len()
+        );
+        assert_eq!(mined.get_root(), post_root);
+        assert_eq!(mined.next_leaf(), UPDATES);
+    }
+
+    #[test]
+    fn test_roll_back_latest() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let (mined, processed_builder) = CanonicalTreeBuilder::new(
+            10,
+            10,
+            0,
+            Hash::ZERO,
+            &[Hash::from(1), Hash::from(2)],
+            temp_dir.path().join("testfile").to_str().unwrap(),
+        )
+        .seal();
+        let (processed, batching_builder) = processed_builder.seal_and_continue();
+        let (batching, latest_builder) = batching_builder.seal_and_continue();
+        let latest = latest_builder.seal();
+        let tree_state = TreeState::new(mined, processed, batching.clone(), latest.clone());
+
+        let appended = latest.append_many(&[Hash::from(3)]);
+        batching.apply_updates_up_to(appended[0].0);
+        let _ = latest.append_many(&[Hash::from(4), Hash::from(5), Hash::from(6)]);
+
+        assert_eq!(latest.unbatched_insertions(), [
+            TreeUpdate::new(3, Hash::from(4)),
+            TreeUpdate::new(4, Hash::from(5)),
+            TreeUpdate::new(5, Hash::from(6)),
+        ]);
+
+        // Batched insertions can't be rolled back.
+        assert!(tree_state.roll_back_latest(4).is_none());
+        assert_eq!(latest.next_leaf(), 6);
+
+        let rolled_back = tree_state.roll_back_latest(2).unwrap();
+        assert_eq!(rolled_back, [
+            TreeUpdate::new(4, Hash::from(5)),
+            TreeUpdate::new(5, Hash::from(6)),
+        ]);
+        assert_eq!(latest.next_leaf(), 4);
+        assert_eq!(latest.get_leaf(4), Hash::ZERO);
+
+        // Rolling back every unbatched insertion leaves the batching tree.
+        assert_eq!(tree_state.roll_back_latest(1).unwrap().len(), 1);
+        assert_eq!(latest.get_root(), batching.get_root());
+        assert_eq!(latest.next_leaf(), 3);
+
+        // Deletions aren't rolled back.
+        let _ = latest.delete_many(&[0]);
+        assert!(tree_state.roll_back_latest(1).is_none());
+        assert_eq!(latest.get_leaf(0), Hash::ZERO);
+
+        // The freed leaves are appended to again and batched as usual.
+        let appended = latest.append_many(&[Hash::from(7)]);
+        assert_eq!(appended[0].2, 3);
+        batching.apply_updates_up_to(appended[0].0);
+        assert_eq!(batching.get_leaf(3), Hash::from(7));
+        assert!(latest.unbatched_insertions().is_empty());
+    }
+
+    #[test]
+    fn test_replace_with_snapshot() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let snapshot_path = temp_dir.path().join("snapshot");
+        let leaves = vec![Hash::from(7), Hash::ZERO, Hash::from(9)];
+        TreeSnapshot::write(&snapshot_path, 10, Hash::ZERO, leaves, None).unwrap();
+        let snapshot = TreeSnapshot::open(&snapshot_path).unwrap();
+
+        let (mined, processed_builder) = CanonicalTreeBuilder::new(
+            10,
+            10,
+            0,
+            Hash::ZERO,
+            &[Hash::from(1)],
+            temp_dir.path().join("testfile").to_str().unwrap(),
+        )
+        .seal();
+        let (processed, batching_builder) = processed_builder.seal_and_continue();
+        let (batching, latest_builder) = batching_builder.seal_and_continue();
+        let latest = latest_builder.seal();
+        let tree_state = TreeState::new(mined, processed, batching, latest);
+        // An update pending in the latest tree is discarded by the replacement.
+        tree_state.get_latest_tree().append_many(&[Hash::from(2)]);
+
+        let replacement = TreeState::from_snapshot(
+            &snapshot,
+            Hash::ZERO,
+            10,
+            0,
+            temp_dir.path().join("restored").to_str().unwrap(),
+        )
+        .unwrap();
+        // Handles taken before the replacement see it as well.
+        let mined = tree_state.get_mined_tree();
+        tree_state.replace_with(&replacement);
+
+        assert_eq!(mined.get_root(), snapshot.root());
+        assert_eq!(tree_state.get_latest_tree().get_root(), snapshot.root());
+        assert_eq!(tree_state.get_latest_tree().next_leaf(), 3);
+        assert_eq!(tree_state.get_processed_tree().get_leaf(2), Hash::from(9));
+
+        // Updates continue from the replaced tree, and are promoted as usual.
+        let appended = tree_state.get_latest_tree().append_many(&[Hash::from(10)]);
+        assert_eq!(appended[0].2, 3);
+        let batching = tree_state.get_batching_tree();
+        batching.apply_updates_up_to(appended[0].0);
+        assert_eq!(batching.get_leaf(3), Hash::from(10));
+        assert_eq!(tree_state.get_mined_tree().get_root(), snapshot.root());
+
+        // A different initial leaf value doesn't reproduce the snapshot.
+        assert!(TreeState::from_snapshot(
+            &snapshot,
+            Hash::from(1),
+            10,
+            0,
+            temp_dir.path().join("other").to_str().unwrap(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_collect_orphaned_versions() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (mined, processed_builder) = CanonicalTreeBuilder::new(
+            10,
+            10,
+            0,
+            Hash::ZERO,
+            &[],
+            temp_dir.path().join("testfile").to_str().unwrap(),
+        )
+        .seal();
+        let (processed, batching_builder) = processed_builder.seal_and_continue();
+        let (batching, latest_builder) = batching_builder.seal_and_continue();
+        let latest = latest_builder.seal();
+        let tree_state = TreeState::new(mined, processed, batching, latest);
+        tree_state
+            .get_latest_tree()
+            .append_many(&[Hash::from(1), Hash::from(2)]);
+
+        let report = tree_state.collect_orphaned_versions();
+        assert_eq!(report.orphaned, 0);
+        assert_eq!(
+            report
+                .versions
+                .iter()
+                .map(|version| (version.name, version.pending_updates))
+                .collect::<Vec<_>>(),
+            vec![
+                ("mined", 0),
+                ("processed", 0),
+                ("batching", 0),
+                ("latest", 2)
+            ]
+        );
+        assert!(report.versions.iter().all(|version| version.handles == 1));
+
+        // Abandon the batching and latest versions, which are still held by
+        // the tree state.
+        tree_state.processed.write_data().next = None;
+        let report = tree_state.collect_orphaned_versions();
+        assert_eq!(report.versions.len(), 2);
+        assert_eq!(report.orphaned, 2);
+        assert_eq!(report.reclaimed_updates, 2);
+        assert_eq!(report.released, 0);
+        assert!(tree_state.batching.read_data().next.is_none());
+        assert!(tree_state.latest.read_data().metadata.diff.is_empty());
+
+        // Orphans are only collected once.
+        assert_eq!(tree_state.collect_orphaned_versions().orphaned, 0);
+    }
+
+    /// Hashes nodes by adding up their children, so that the root of a tree
+    /// is the sum of its leaves.
+    #[derive(Clone)]
+    struct SumHash;
+
+    impl Hasher for SumHash {
+        type Hash = Hash;
+
+        fn hash_node(left: &Hash, right: &Hash) -> Hash {
+            *left + *right
+        }
+    }
+
+    impl TreeHasher for SumHash {}
+
+    #[test]
+    fn test_versions_hash_with_their_hasher() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let (mined, processed_builder) = CanonicalTreeBuilder::<SumHash>::new_with_hasher(
+            10,
+            10,
+            0,
+            Hash::ZERO,
+            &[Hash::from(1)],
+            temp_dir.path().join("testfile").to_str().unwrap(),
+        )
+        .seal();
+        let latest = processed_builder.seal();
+        assert_eq!(mined.get_root(), Hash::from(1));
+
+        let appended = latest.append_many(&[Hash::from(2), Hash::from(3)]);
+        let (root, proof, leaf_index) = appended.last().unwrap();
+        assert_eq!(*root, Hash::from(6));
+        assert_eq!(*leaf_index, 2);
+        assert_eq!(proof.root(Hash::from(3)), *root);
+
+        mined.apply_updates_up_to(*root);
+        assert_eq!(mined.get_root(), Hash::from(6));
+        assert_eq!(
+            mined.get_leaf_and_proof(1).2.root(Hash::from(2)),
+            Hash::from(6)
+        );
+    }
 }
@@ -0,0 +1,100 @@
+//! A Poseidon [`TreeHasher`] built on `ark-crypto-primitives`, for comparing
+//! it with the one of semaphore-rs. Its round constants are generated with
+//! the arkworks procedure, so its hashes differ from those of the contract
+//! and trees built with it can't be used to insert identities on chain.
+
+use ark_bn254::Fr;
+use ark_crypto_primitives::sponge::poseidon::{
+    find_poseidon_ark_and_mds, PoseidonConfig, PoseidonSponge,
+};
+use ark_crypto_primitives::sponge::{CryptographicSponge, FieldBasedCryptographicSponge};
+use ark_ff::PrimeField;
+use once_cell::sync::Lazy;
+use semaphore::merkle_tree::Hasher;
+
+use super::{Hash, TreeHasher};
+
+/// The parameters of the width 3 instance over BN254, as used for Merkle
+/// trees.
+const FULL_ROUNDS: usize = 8;
+const PARTIAL_ROUNDS: usize = 57;
+const ALPHA: u64 = 5;
+const RATE: usize = 2;
+const CAPACITY: usize = 1;
+
+static CONFIG: Lazy<PoseidonConfig<Fr>> = Lazy::new(|| {
+    let (ark, mds) = find_poseidon_ark_and_mds::<Fr>(
+        u64::from(Fr::MODULUS_BIT_SIZE),
+        RATE,
+        FULL_ROUNDS as u64,
+        PARTIAL_ROUNDS as u64,
+        0,
+    );
+    PoseidonConfig::new(FULL_ROUNDS, PARTIAL_ROUNDS, ALPHA, mds, ark, RATE, CAPACITY)
+});
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArkPoseidonHash;
+
+impl Hasher for ArkPoseidonHash {
+    type Hash = Hash;
+
+    fn hash_node(left: &Self::Hash, right: &Self::Hash) -> Self::Hash {
+        let mut sponge = PoseidonSponge::new(&CONFIG);
+        sponge.absorb(&to_field(left));
+        sponge.absorb(&to_field(right));
+        let output: Vec<Fr> = sponge.squeeze_native_field_elements(1);
+        from_field(output[0])
+    }
+}
+
+impl TreeHasher for ArkPoseidonHash {}
+
+fn to_field(value: &Hash) -> Fr {
+    Fr::from_le_bytes_mod_order(&value.to_le_bytes::<32>())
+}
+
+fn from_field(value: Fr) -> Hash {
+    Hash::from_limbs(value.into_bigint().0)
+}
+
+#[cfg(test)]
+mod tests {
+    use semaphore::merkle_tree::Hasher;
+    use semaphore::poseidon_tree::PoseidonHash;
+
+    use super::{from_field, to_field, ArkPoseidonHash};
+    use crate::identity_tree::{CanonicalTreeBuilder, Hash, TreeVersionReadOps};
+
+    #[test]
+    fn field_conversion_roundtrips() {
+        let value = Hash::from(0x1234_5678_u64) << 200;
+        assert_eq!(from_field(to_field(&value)), value);
+    }
+
+    #[test]
+    fn builds_trees_with_its_own_hashes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let (_, processed_builder) = CanonicalTreeBuilder::<ArkPoseidonHash>::new_with_hasher(
+            10,
+            10,
+            0,
+            Hash::ZERO,
+            &[],
+            temp_dir.path().join("testfile").to_str().unwrap(),
+        )
+        .seal();
+        let latest = processed_builder.seal();
+
+        let identities = [Hash::from(1), Hash::from(2), Hash::from(3)];
+        let (root, proof, _) = latest.append_many(&identities).pop().unwrap();
+
+        assert_eq!(latest.get_root(), root);
+        assert_eq!(proof.root(identities[2]), root);
+        assert_ne!(
+            ArkPoseidonHash::hash_node(&identities[0], &identities[1]),
+            PoseidonHash::hash_node(&identities[0], &identities[1])
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! The Poseidon hash of the contract, computed with precomputed parameters
+//! instead of through semaphore-rs. The round constants and the MDS matrix of
+//! circomlib are converted into arkworks field elements once, which keep them
+//! in Montgomery form, and every hash runs on a fixed size state without
+//! allocating. Its hashes are those of [`PoseidonHash`], so trees built with
+//! it have the same roots.
+//!
+//! [`PoseidonHash`]: semaphore::poseidon_tree::PoseidonHash
+
+use ark_bn254::Fr;
+use ark_ff::{BigInt, Field, PrimeField, Zero};
+use light_poseidon::parameters::bn254_x5::get_poseidon_parameters;
+use once_cell::sync::Lazy;
+use semaphore::merkle_tree::Hasher;
+
+use super::{Hash, TreeHasher};
+
+/// The state holds a zero capacity element and the two children.
+const WIDTH: usize = 3;
+const FULL_ROUNDS: usize = 8;
+const PARTIAL_ROUNDS: usize = 57;
+const ROUNDS: usize = FULL_ROUNDS + PARTIAL_ROUNDS;
+
+struct Parameters {
+    round_constants: [[Fr; WIDTH]; ROUNDS],
+    mds:             [[Fr; WIDTH]; WIDTH],
+}
+
+static PARAMETERS: Lazy<Parameters> = Lazy::new(|| {
+    let parameters = get_poseidon_parameters::<Fr>(WIDTH as u8).expect("width 3 is a circom width");
+    assert_eq!(parameters.full_rounds, FULL_ROUNDS);
+    assert_eq!(parameters.partial_rounds, PARTIAL_ROUNDS);
+    assert_eq!(parameters.alpha, 5);
+
+    let mut round_constants = [[Fr::zero(); WIDTH]; ROUNDS];
+    for (round, constants) in round_constants.iter_mut().enumerate() {
+        constants.copy_from_slice(&parameters.ark[round * WIDTH..(round + 1) * WIDTH]);
+    }
+
+    let mut mds = [[Fr::zero(); WIDTH]; WIDTH];
+    for (row, entries) in mds.iter_mut().zip(&parameters.mds) {
+        row.copy_from_slice(entries);
+    }
+
+    Parameters {
+        round_constants,
+        mds,
+    }
+});
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FastPoseidonHash;
+
+impl Hasher for FastPoseidonHash {
+    type Hash = Hash;
+
+    fn hash_node(left: &Self::Hash, right: &Self::Hash) -> Self::Hash {
+        from_field(permute([Fr::zero(), to_field(left), to_field(right)])[0])
+    }
+}
+
+impl TreeHasher for FastPoseidonHash {}
+
+fn permute(mut state: [Fr; WIDTH]) -> [Fr; WIDTH] {
+    let parameters = &*PARAMETERS;
+    let half_full_rounds = FULL_ROUNDS / 2;
+
+    for (round, constants) in parameters.round_constants.iter().enumerate() {
+        for (element, constant) in state.iter_mut().zip(constants) {
+            *element += constant;
+        }
+
+        // Partial rounds only raise the first element.
+        let is_full = round < half_full_rounds || round >= half_full_rounds + PARTIAL_ROUNDS;
+        if is_full {
+            state.iter_mut().for_each(sbox);
+        } else {
+            sbox(&mut state[0]);
+        }
+
+        state = mix(&parameters.mds, &state);
+    }
+
+    state
+}
+
+/// Raises to the fifth power with two squarings and a multiplication.
+fn sbox(element: &mut Fr) {
+    let square = element.square();
+    *element *= square.square();
+}
+
+fn mix(mds: &[[Fr; WIDTH]; WIDTH], state: &[Fr; WIDTH]) -> [Fr; WIDTH] {
+    let mut mixed = [Fr::zero(); WIDTH];
+    for (result, row) in mixed.iter_mut().zip(mds) {
+        for (entry, element) in row.iter().zip(state) {
+            *result += *entry * element;
+        }
+    }
+    mixed
+}
+
+fn to_field(value: &Hash) -> Fr {
+    // Tree nodes are reduced already, anything else is reduced modulo the
+    // field.
+    Fr::from_bigint(BigInt(*value.as_limbs()))
+        .unwrap_or_else(|| Fr::from_le_bytes_mod_order(&value.to_le_bytes::<32>()))
+}
+
+fn from_field(value: Fr) -> Hash {
+    Hash::from_limbs(value.into_bigint().0)
+}
+
+#[cfg(test)]
+mod tests {
+    use semaphore::merkle_tree::Hasher;
+    use semaphore::poseidon_tree::PoseidonHash;
+
+    use super::FastPoseidonHash;
+    use crate::identity_tree::Hash;
+
+    #[test]
+    fn hashes_like_semaphore() {
+        let mut left = Hash::from(0x1234_5678_u64);
+        let mut right = Hash::ZERO;
+
+        for _ in 0..64 {
+            assert_eq!(
+                FastPoseidonHash::hash_node(&left, &right),
+                PoseidonHash::hash_node(&left, &right)
+            );
+            (left, right) = (PoseidonHash::hash_node(&left, &right), left);
+        }
+    }
+}
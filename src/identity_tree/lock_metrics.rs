@@ -0,0 +1,83 @@
+//! Time spent waiting for the locks of tree versions. Without the
+//! `sequencer` feature there is no metrics registry, so nothing is recorded.
+
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "sequencer")]
+use once_cell::sync::Lazy;
+#[cfg(feature = "sequencer")]
+use prometheus::{exponential_buckets, register_histogram_vec, HistogramVec};
+
+#[cfg(feature = "sequencer")]
+static LOCK_WAIT: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "tree_lock_wait_seconds",
+        "Time spent waiting for the lock of a tree version, by version and access.",
+        &["version", "access"],
+        exponential_buckets(0.000_001, 4.0, 12).unwrap()
+    )
+    .unwrap()
+});
+
+/// Whether the lock is taken to read from or to modify the version. Both are
+/// exclusive, the distinction shows which side contends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl Access {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+        }
+    }
+}
+
+/// Locks the data of a version, recording how long it took.
+pub fn lock<'a, T>(
+    mutex: &'a Mutex<T>,
+    version: &'static str,
+    access: Access,
+) -> MutexGuard<'a, T> {
+    let start = Instant::now();
+    let guard = mutex.lock().expect("no lock poisoning");
+    observe(version, access, start.elapsed());
+    guard
+}
+
+#[cfg(feature = "sequencer")]
+fn observe(version: &'static str, access: Access, wait: Duration) {
+    LOCK_WAIT
+        .with_label_values(&[version, access.as_str()])
+        .observe(wait.as_secs_f64());
+}
+
+#[cfg(not(feature = "sequencer"))]
+fn observe(_version: &'static str, _access: Access, _wait: Duration) {}
+
+#[cfg(all(test, feature = "sequencer"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_wait_by_version_and_access() {
+        let count = |access: Access| {
+            LOCK_WAIT
+                .with_label_values(&["test", access.as_str()])
+                .get_sample_count()
+        };
+        let (reads, writes) = (count(Access::Read), count(Access::Write));
+
+        let mutex = Mutex::new(());
+        drop(lock(&mutex, "test", Access::Read));
+        drop(lock(&mutex, "test", Access::Read));
+        drop(lock(&mutex, "test", Access::Write));
+
+        assert_eq!(count(Access::Read), reads + 2);
+        assert_eq!(count(Access::Write), writes + 1);
+    }
+}
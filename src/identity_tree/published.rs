@@ -0,0 +1,127 @@
+//! Values replaced as a whole and read without waiting for whoever produces
+//! the next one, in the manner of `arc-swap`. Readers take the current value
+//! under a lock held for no longer than that, so the work preparing a new
+//! value never blocks them.
+
+use std::ops::Deref;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+
+/// A published value along with the number of its readers, so that a writer
+/// can sleep until they're done with it.
+struct Slot<T> {
+    value:    T,
+    readers:  Mutex<usize>,
+    released: Condvar,
+}
+
+impl<T> Slot<T> {
+    fn new(value: T) -> Arc<Self> {
+        Arc::new(Self {
+            value,
+            readers: Mutex::new(0),
+            released: Condvar::new(),
+        })
+    }
+}
+
+pub struct Published<T>(RwLock<Arc<Slot<T>>>);
+
+impl<T> Published<T> {
+    pub fn new(value: T) -> Self {
+        Self(RwLock::new(Slot::new(value)))
+    }
+
+    /// The value last published.
+    pub fn load(&self) -> Loaded<T> {
+        Loaded::new(&self.0.read().expect("no lock poisoning"))
+    }
+
+    /// Publishes a new value, returns the one it replaces.
+    pub fn store(&self, value: T) -> Loaded<T> {
+        let mut current = self.0.write().expect("no lock poisoning");
+        let previous = Loaded::new(&current);
+        *current = Slot::new(value);
+        previous
+    }
+}
+
+/// A value loaded from a [`Published`], whose holder counts as one of its
+/// readers until it's dropped.
+pub struct Loaded<T>(Arc<Slot<T>>);
+
+impl<T> Loaded<T> {
+    fn new(slot: &Arc<Slot<T>>) -> Self {
+        *slot.readers.lock().expect("no lock poisoning") += 1;
+        Self(slot.clone())
+    }
+}
+
+impl<T> Deref for Loaded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0.value
+    }
+}
+
+impl<T> Drop for Loaded<T> {
+    fn drop(&mut self) {
+        let mut readers = self.0.readers.lock().expect("no lock poisoning");
+        *readers -= 1;
+        if *readers == 0 {
+            self.0.released.notify_all();
+        }
+    }
+}
+
+/// Waits until the readers which loaded a value before it was replaced are
+/// done with it, not counting the caller holding `previous`. Must not be called
+/// while holding another value loaded from the same [`Published`].
+pub fn wait_for_readers<T>(previous: Loaded<T>) {
+    let slot = previous.0.clone();
+    drop(previous);
+
+    let mut readers = slot.readers.lock().expect("no lock poisoning");
+    while *readers > 0 {
+        readers = slot.released.wait(readers).expect("no lock poisoning");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn readers_keep_the_value_they_loaded() {
+        let published = Published::new(1);
+
+        let loaded = published.load();
+        let previous = published.store(2);
+
+        assert_eq!(*loaded, 1);
+        assert_eq!(*previous, 1);
+        assert_eq!(*published.load(), 2);
+    }
+
+    #[test]
+    fn waits_for_readers_of_the_previous_value() {
+        let published = Arc::new(Published::new(1));
+
+        let loaded = published.load();
+        let previous = published.store(2);
+        let slot = previous.0.clone();
+
+        let reader = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            drop(loaded);
+        });
+
+        wait_for_readers(previous);
+        assert_eq!(*slot.readers.lock().unwrap(), 0);
+
+        reader.join().unwrap();
+    }
+}
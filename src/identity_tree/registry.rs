@@ -0,0 +1,59 @@
+//! Bookkeeping of the derived versions of a chain. Versions hold their
+//! successor by a strong pointer, so a version cut from the chain, e.g. one
+//! left behind on an abandoned path, is kept alive by any handle to it and
+//! keeps its own successors alive in turn. The registry holds a weak pointer
+//! to every version built, so that those can be found and released.
+
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+use semaphore::lazy_merkle_tree::Derived;
+
+use super::{TreeHasher, TreeVersionData};
+
+type DerivedData<H> = Mutex<TreeVersionData<Derived, H>>;
+
+pub struct VersionRegistry<H: TreeHasher> {
+    versions: Mutex<Vec<Weak<DerivedData<H>>>>,
+}
+
+impl<H: TreeHasher> VersionRegistry<H> {
+    pub const fn new() -> Self {
+        Self {
+            versions: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn register(&self, version: &Arc<DerivedData<H>>) {
+        self.lock().push(Arc::downgrade(version));
+    }
+
+    /// Returns the registered versions which are alive but not `reachable`,
+    /// and how many were dropped since they were registered. Both are
+    /// forgotten, only the reachable versions stay registered.
+    pub fn take_orphans(
+        &self,
+        reachable: &[*const DerivedData<H>],
+    ) -> (Vec<Arc<DerivedData<H>>>, usize) {
+        let mut versions = self.lock();
+        let registered = versions.len();
+
+        let mut orphans = Vec::new();
+        versions.retain(|version| {
+            let Some(version) = version.upgrade() else {
+                return false;
+            };
+            if reachable.contains(&Arc::as_ptr(&version)) {
+                return true;
+            }
+            orphans.push(version);
+            false
+        });
+
+        let released = registered - versions.len() - orphans.len();
+        (orphans, released)
+    }
+
+    fn lock(&self) -> MutexGuard<Vec<Weak<DerivedData<H>>>> {
+        self.versions.lock().expect("no lock poisoning")
+    }
+}
@@ -0,0 +1,413 @@
+//! Fixed-layout binary snapshot of a tree, which is memory-mapped so that
+//! proofs can be served from it before the tree is rebuilt on startup.
+//!
+//! All integers and field elements are little-endian. The file consists of
+//!
+//! * the magic bytes `b"PTSNAP01"`,
+//! * the depth and the number of leaves as `u64`,
+//! * the root of an empty subtree for every level from the leaves up to the
+//!   root, `depth + 1` field elements,
+//! * the nodes of every level from the leaves up to the root. Level `l` holds
+//!   the first `ceil(leaf_count / 2^l)` nodes, the nodes to the right of those
+//!   are roots of empty subtrees.
+//!
+//! Snapshots can be compressed with zstd as a whole. Compressed snapshots are
+//! recognized by the zstd frame magic and decompressed into memory on load,
+//! rather than memory-mapped.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Seek, Write};
+use std::ops::Deref;
+use std::path::Path;
+
+use anyhow::{bail, ensure, Result as AnyhowResult};
+use memmap2::Mmap;
+use semaphore::merkle_tree::Hasher;
+use semaphore::poseidon_tree::{Branch, Proof};
+
+use super::{AppHasher, Hash, InclusionProof, ProcessedStatus};
+
+const MAGIC: &[u8; 8] = b"PTSNAP01";
+const HEADER_SIZE: usize = 24;
+const NODE_SIZE: usize = 32;
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Debug)]
+enum Contents {
+    Mapped(Mmap),
+    Decompressed(Vec<u8>),
+}
+
+impl Deref for Contents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Mapped(mmap) => mmap,
+            Self::Decompressed(bytes) => bytes,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TreeSnapshot {
+    contents:     Contents,
+    depth:        usize,
+    leaf_count:   usize,
+    /// Leaf index of every commitment in the tree, built on load.
+    leaf_indices: HashMap<Hash, usize>,
+}
+
+impl TreeSnapshot {
+    /// Writes a snapshot of the tree with the given leaves, compressed with
+    /// zstd at `zstd_level` if set. The file is replaced atomically.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file can't be written.
+    pub fn write(
+        path: impl AsRef<Path>,
+        depth: usize,
+        initial_leaf_value: Hash,
+        leaves: Vec<Hash>,
+        zstd_level: Option<i32>,
+    ) -> AnyhowResult<()> {
+        ensure!(
+            leaves.len() <= 1 << depth,
+            "{} leaves don't fit a tree of depth {depth}",
+            leaves.len()
+        );
+
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("tmp");
+        let file = File::create(&tmp_path)?;
+
+        let file = if let Some(level) = zstd_level {
+            let mut encoder = zstd::Encoder::new(file, level)?;
+            Self::write_contents(&mut encoder, depth, initial_leaf_value, leaves)?;
+            encoder.finish()?
+        } else {
+            let mut writer = BufWriter::new(file);
+            Self::write_contents(&mut writer, depth, initial_leaf_value, leaves)?;
+            writer.into_inner()?
+        };
+
+        file.sync_all()?;
+        fs::rename(tmp_path, path)?;
+
+        Ok(())
+    }
+
+    fn write_contents(
+        writer: &mut impl Write,
+        depth: usize,
+        initial_leaf_value: Hash,
+        leaves: Vec<Hash>,
+    ) -> AnyhowResult<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&(depth as u64).to_le_bytes())?;
+        writer.write_all(&(leaves.len() as u64).to_le_bytes())?;
+
+        let empty = empty_roots(depth, initial_leaf_value);
+        for node in &empty {
+            writer.write_all(&node.to_le_bytes::<NODE_SIZE>())?;
+        }
+
+        let mut level = leaves;
+        for empty_root in &empty {
+            for node in &level {
+                writer.write_all(&node.to_le_bytes::<NODE_SIZE>())?;
+            }
+
+            level = level
+                .chunks(2)
+                .map(|pair| AppHasher::hash_node(&pair[0], pair.get(1).unwrap_or(empty_root)))
+                .collect();
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Maps a snapshot into memory. The leaves are read to index them, other
+    /// nodes are only read from disk once accessed, unless the snapshot is
+    /// compressed.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file can't be read or isn't a valid snapshot.
+    pub fn open(path: impl AsRef<Path>) -> AnyhowResult<Self> {
+        let mut file = File::open(path)?;
+
+        let mut magic = [0; ZSTD_MAGIC.len()];
+        let is_compressed = file.read_exact(&mut magic).is_ok() && magic == ZSTD_MAGIC;
+
+        let contents = if is_compressed {
+            file.rewind()?;
+            Contents::Decompressed(zstd::decode_all(file)?)
+        } else {
+            // Safety: the snapshot is only ever replaced by renaming a new file
+            // over it, so the mapped file isn't modified.
+            Contents::Mapped(unsafe { Mmap::map(&file)? })
+        };
+
+        if contents.len() < HEADER_SIZE || &contents[..MAGIC.len()] != MAGIC {
+            bail!("not a tree snapshot");
+        }
+
+        let read_u64 = |offset: usize| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&contents[offset..offset + 8]);
+            u64::from_le_bytes(bytes)
+        };
+        let depth = usize::try_from(read_u64(8))?;
+        let leaf_count = usize::try_from(read_u64(16))?;
+
+        ensure!(
+            depth < usize::BITS as usize && leaf_count <= 1 << depth,
+            "invalid tree snapshot header"
+        );
+
+        let mut snapshot = Self {
+            contents,
+            depth,
+            leaf_count,
+            leaf_indices: HashMap::new(),
+        };
+
+        let expected_size = snapshot.level_offset(depth + 1);
+        ensure!(
+            snapshot.contents.len() == expected_size,
+            "tree snapshot is {} bytes, expected {expected_size}",
+            snapshot.contents.len()
+        );
+
+        // Deleted leaves hold the initial leaf value. Leaves are never reused,
+        // so the first leaf of a commitment is the one it was inserted at.
+        let deleted = snapshot.empty_root(0);
+        let mut leaf_indices = HashMap::with_capacity(leaf_count);
+        for leaf_index in 0..leaf_count {
+            let leaf = snapshot.node(0, leaf_index);
+            if leaf != deleted {
+                leaf_indices.entry(leaf).or_insert(leaf_index);
+            }
+        }
+        snapshot.leaf_indices = leaf_indices;
+
+        Ok(snapshot)
+    }
+
+    #[must_use]
+    pub const fn depth(&self) -> usize {
+        self.depth
+    }
+
+    #[must_use]
+    pub const fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    #[must_use]
+    pub fn root(&self) -> Hash {
+        self.node(self.depth, 0)
+    }
+
+    /// The leaves up to the last inserted one, deleted leaves holding the
+    /// initial leaf value.
+    #[must_use]
+    pub fn leaves(&self) -> Vec<Hash> {
+        (0..self.leaf_count)
+            .map(|leaf_index| self.node(0, leaf_index))
+            .collect()
+    }
+
+    /// The leaf index of a commitment, `None` if it isn't in the tree or was
+    /// deleted.
+    #[must_use]
+    pub fn leaf_index(&self, commitment: &Hash) -> Option<usize> {
+        self.leaf_indices.get(commitment).copied()
+    }
+
+    #[must_use]
+    pub fn proof(&self, leaf_index: usize) -> Proof {
+        let branches = (0..self.depth)
+            .map(|level| {
+                let index = leaf_index >> level;
+                let sibling = self.node(level, index ^ 1);
+
+                if index & 1 == 0 {
+                    Branch::Left(sibling)
+                } else {
+                    Branch::Right(sibling)
+                }
+            })
+            .collect();
+
+        Proof(branches)
+    }
+
+    /// Proof of inclusion of the commitment in the snapshotted tree, if it's
+    /// part of it.
+    #[must_use]
+    pub fn inclusion_proof(&self, commitment: &Hash) -> Option<InclusionProof> {
+        let leaf_index = self.leaf_index(commitment)?;
+
+        Some(InclusionProof {
+            status:            ProcessedStatus::Mined.into(),
+            root:              Some(self.root()),
+            proof:             Some(self.proof(leaf_index)),
+            message:           None,
+            mined_transaction: None,
+            mined_block:       None,
+        })
+    }
+
+    fn level_len(&self, level: usize) -> usize {
+        (self.leaf_count + (1 << level) - 1) >> level
+    }
+
+    fn level_offset(&self, level: usize) -> usize {
+        let empty_roots = (self.depth + 1) * NODE_SIZE;
+        let nodes: usize = (0..level).map(|level| self.level_len(level)).sum();
+
+        HEADER_SIZE + empty_roots + nodes * NODE_SIZE
+    }
+
+    fn node(&self, level: usize, index: usize) -> Hash {
+        if index >= self.level_len(level) {
+            return self.empty_root(level);
+        }
+
+        self.read_node(self.level_offset(level) + index * NODE_SIZE)
+    }
+
+    fn empty_root(&self, level: usize) -> Hash {
+        self.read_node(HEADER_SIZE + level * NODE_SIZE)
+    }
+
+    fn read_node(&self, offset: usize) -> Hash {
+        let mut bytes = [0; NODE_SIZE];
+        bytes.copy_from_slice(&self.contents[offset..offset + NODE_SIZE]);
+        Hash::from_le_bytes(bytes)
+    }
+}
+
+fn empty_roots(depth: usize, initial_leaf_value: Hash) -> Vec<Hash> {
+    let mut roots = Vec::with_capacity(depth + 1);
+    roots.push(initial_leaf_value);
+    for level in 0..depth {
+        roots.push(AppHasher::hash_node(&roots[level], &roots[level]));
+    }
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use semaphore::poseidon_tree::LazyPoseidonTree;
+
+    use super::*;
+
+    #[test]
+    fn proofs_match_the_tree() -> AnyhowResult<()> {
+        let depth = 4;
+        let leaves: Vec<Hash> = (1..=5_u64).map(Hash::from).collect();
+
+        let tree = leaves.iter().enumerate().fold(
+            LazyPoseidonTree::new(depth, Hash::ZERO),
+            |tree, (i, leaf)| tree.update(i, leaf),
+        );
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("snapshot");
+        TreeSnapshot::write(&path, depth, Hash::ZERO, leaves.clone(), None)?;
+        let snapshot = TreeSnapshot::open(&path)?;
+
+        assert_eq!(snapshot.leaf_count(), leaves.len());
+        assert_eq!(snapshot.leaves(), leaves);
+        assert_eq!(snapshot.root(), tree.root());
+
+        for (i, leaf) in leaves.iter().enumerate() {
+            assert_eq!(snapshot.leaf_index(leaf), Some(i));
+            assert_eq!(snapshot.proof(i), tree.proof(i));
+        }
+        assert_eq!(snapshot.leaf_index(&Hash::from(42)), None);
+        assert!(snapshot.inclusion_proof(&Hash::ZERO).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn deleted_leaves_have_no_index() -> AnyhowResult<()> {
+        let initial_leaf_value = Hash::from(7);
+        let leaves = vec![
+            Hash::from(1),
+            initial_leaf_value,
+            Hash::from(3),
+            initial_leaf_value,
+        ];
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("snapshot");
+        TreeSnapshot::write(&path, 3, initial_leaf_value, leaves, None)?;
+        let snapshot = TreeSnapshot::open(&path)?;
+
+        assert_eq!(snapshot.leaf_index(&Hash::from(3)), Some(2));
+        assert_eq!(snapshot.leaf_index(&initial_leaf_value), None);
+        assert!(snapshot.inclusion_proof(&initial_leaf_value).is_none());
+        assert!(snapshot.inclusion_proof(&Hash::from(3)).is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn empty_tree() -> AnyhowResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("snapshot");
+        TreeSnapshot::write(&path, 3, Hash::ZERO, vec![], None)?;
+        let snapshot = TreeSnapshot::open(&path)?;
+
+        assert_eq!(snapshot.root(), LazyPoseidonTree::new(3, Hash::ZERO).root());
+
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_snapshots_are_decompressed_on_load() -> AnyhowResult<()> {
+        let depth = 10;
+        let leaves: Vec<Hash> = (1..=100_u64).map(Hash::from).collect();
+
+        let dir = tempfile::tempdir()?;
+        let plain_path = dir.path().join("plain");
+        let compressed_path = dir.path().join("compressed");
+        TreeSnapshot::write(&plain_path, depth, Hash::ZERO, leaves.clone(), None)?;
+        TreeSnapshot::write(&compressed_path, depth, Hash::ZERO, leaves.clone(), Some(3))?;
+
+        assert!(fs::metadata(&compressed_path)?.len() < fs::metadata(&plain_path)?.len());
+
+        let plain = TreeSnapshot::open(&plain_path)?;
+        let compressed = TreeSnapshot::open(&compressed_path)?;
+
+        assert_eq!(compressed.leaf_count(), leaves.len());
+        assert_eq!(compressed.root(), plain.root());
+        assert_eq!(compressed.leaf_index(&leaves[42]), Some(42));
+        assert_eq!(compressed.proof(42), plain.proof(42));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_truncated_files() -> AnyhowResult<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("snapshot");
+        TreeSnapshot::write(&path, 3, Hash::ZERO, vec![Hash::from(1)], None)?;
+
+        let bytes = fs::read(&path)?;
+        fs::write(&path, &bytes[..bytes.len() - 1])?;
+
+        assert!(TreeSnapshot::open(&path).is_err());
+
+        Ok(())
+    }
+}
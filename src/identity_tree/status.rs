@@ -13,6 +13,13 @@ pub enum ProcessedStatus {
     /// mined.
     Pending,
 
+    /// Root is part of a batch which is being proven or submitted, but is not
+    /// yet mined.
+    ///
+    /// NOTE: This status is never persisted, pending identities are reported
+    /// as batched once they're included in the batching tree.
+    Batched,
+
     /// Root is mined on mainnet but is still waiting for confirmation on
     /// relayed chains
     ///
@@ -41,26 +48,121 @@ pub enum UnprocessedStatus {
     New,
 }
 
-/// A status type visible on the API level - contains both the processed and
-/// unprocessed statuses
+/// Status of identity commitments which were removed from the tree
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum RemovedStatus {
+    /// The deletion of the identity was mined, its leaf holds the initial
+    /// leaf value
+    Deleted,
+}
+
+/// A status type visible on the API level - contains the processed,
+/// unprocessed and removed statuses
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase")]
 #[serde(untagged)]
 pub enum Status {
     Unprocessed(UnprocessedStatus),
     Processed(ProcessedStatus),
+    Removed(RemovedStatus),
 }
 
 #[derive(Debug, Error)]
 #[error("unknown status")]
 pub struct UnknownStatus;
 
+/// A step in the lifecycle of an identity. The events of an identity are
+/// persisted in order and its [`Status`] is derived from them.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum IdentityEvent {
+    /// Accepted by `/insertIdentity` and queued.
+    Received,
+    /// Passed the checks of the insertion task and appended to the tree.
+    Validated,
+    /// Included in a batch sent to the prover.
+    Batched,
+    /// The batch transaction was submitted.
+    Submitted,
+    /// The batch was mined on mainnet.
+    Mined,
+    /// The root was bridged to all relayed chains.
+    Finalized,
+    /// Rejected or expired while queued, see the message of the event.
+    Failed,
+    /// The deletion of the identity was mined.
+    Deleted,
+}
+
+impl IdentityEvent {
+    /// The status of an identity whose latest event this is.
+    #[must_use]
+    pub const fn status(self) -> Option<Status> {
+        match self {
+            Self::Received => Some(Status::Unprocessed(UnprocessedStatus::New)),
+            Self::Failed => Some(Status::Unprocessed(UnprocessedStatus::Failed)),
+            Self::Validated | Self::Batched | Self::Submitted => {
+                Some(Status::Processed(ProcessedStatus::Pending))
+            }
+            Self::Mined => Some(Status::Processed(ProcessedStatus::Processed)),
+            Self::Finalized => Some(Status::Processed(ProcessedStatus::Mined)),
+            Self::Deleted => Some(Status::Removed(RemovedStatus::Deleted)),
+        }
+    }
+}
+
+impl Status {
+    /// Derives the status from the events of an identity, oldest first.
+    pub fn from_events<I>(events: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = IdentityEvent>,
+        I::IntoIter: DoubleEndedIterator,
+    {
+        events.into_iter().rev().find_map(IdentityEvent::status)
+    }
+}
+
+impl FromStr for IdentityEvent {
+    type Err = UnknownStatus;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "received" => Ok(Self::Received),
+            "validated" => Ok(Self::Validated),
+            "batched" => Ok(Self::Batched),
+            "submitted" => Ok(Self::Submitted),
+            "mined" => Ok(Self::Mined),
+            "finalized" => Ok(Self::Finalized),
+            "failed" => Ok(Self::Failed),
+            "deleted" => Ok(Self::Deleted),
+            _ => Err(UnknownStatus),
+        }
+    }
+}
+
+impl From<IdentityEvent> for &str {
+    fn from(event: IdentityEvent) -> Self {
+        match event {
+            IdentityEvent::Received => "received",
+            IdentityEvent::Validated => "validated",
+            IdentityEvent::Batched => "batched",
+            IdentityEvent::Submitted => "submitted",
+            IdentityEvent::Mined => "mined",
+            IdentityEvent::Finalized => "finalized",
+            IdentityEvent::Failed => "failed",
+            IdentityEvent::Deleted => "deleted",
+        }
+    }
+}
+
 impl FromStr for ProcessedStatus {
     type Err = UnknownStatus;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "pending" => Ok(Self::Pending),
+            "batched" => Ok(Self::Batched),
             "mined" => Ok(Self::Mined),
             "processed" => Ok(Self::Processed),
             _ => Err(UnknownStatus),
@@ -72,6 +174,7 @@ impl From<ProcessedStatus> for &str {
     fn from(scope: ProcessedStatus) -> Self {
         match scope {
             ProcessedStatus::Pending => "pending",
+            ProcessedStatus::Batched => "batched",
             ProcessedStatus::Mined => "mined",
             ProcessedStatus::Processed => "processed",
         }
@@ -86,6 +189,8 @@ impl FromStr for Status {
             Ok(Self::Unprocessed(s))
         } else if let Ok(s) = ProcessedStatus::from_str(s) {
             Ok(Self::Processed(s))
+        } else if let Ok(s) = RemovedStatus::from_str(s) {
+            Ok(Self::Removed(s))
         } else {
             Err(UnknownStatus)
         }
@@ -113,6 +218,25 @@ impl From<UnprocessedStatus> for &str {
     }
 }
 
+impl FromStr for RemovedStatus {
+    type Err = UnknownStatus;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deleted" => Ok(Self::Deleted),
+            _ => Err(UnknownStatus),
+        }
+    }
+}
+
+impl From<RemovedStatus> for &str {
+    fn from(scope: RemovedStatus) -> Self {
+        match scope {
+            RemovedStatus::Deleted => "deleted",
+        }
+    }
+}
+
 impl From<UnprocessedStatus> for Status {
     fn from(status: UnprocessedStatus) -> Self {
         Self::Unprocessed(status)
@@ -125,6 +249,12 @@ impl From<ProcessedStatus> for Status {
     }
 }
 
+impl From<RemovedStatus> for Status {
+    fn from(status: RemovedStatus) -> Self {
+        Self::Removed(status)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use test_case::test_case;
@@ -132,9 +262,11 @@ mod tests {
     use super::*;
 
     #[test_case(Status::Processed(ProcessedStatus::Pending) => "pending")]
+    #[test_case(Status::Processed(ProcessedStatus::Batched) => "batched")]
     #[test_case(Status::Processed(ProcessedStatus::Mined) => "mined")]
     #[test_case(Status::Unprocessed(UnprocessedStatus::New) => "new")]
     #[test_case(Status::Unprocessed(UnprocessedStatus::Failed) => "failed")]
+    #[test_case(Status::Removed(RemovedStatus::Deleted) => "deleted")]
     fn serialize_status(api_status: Status) -> &'static str {
         let s = serde_json::to_string(&api_status).unwrap();
 
@@ -145,13 +277,62 @@ mod tests {
     }
 
     #[test_case("pending" => Status::Processed(ProcessedStatus::Pending))]
+    #[test_case("batched" => Status::Processed(ProcessedStatus::Batched))]
     #[test_case("mined" => Status::Processed(ProcessedStatus::Mined))]
     #[test_case("new" => Status::Unprocessed(UnprocessedStatus::New))]
     #[test_case("failed" => Status::Unprocessed(UnprocessedStatus::Failed))]
+    #[test_case("deleted" => Status::Removed(RemovedStatus::Deleted))]
     fn deserialize_status(s: &str) -> Status {
         // Wrapped because JSON expected `"something"` and not `something`
         let wrapped = format!("\"{s}\"");
 
         serde_json::from_str(&wrapped).unwrap()
     }
+
+    #[test]
+    #[allow(clippy::enum_glob_use)]
+    fn status_from_events() {
+        use IdentityEvent::*;
+
+        let status = |events: &[IdentityEvent]| Status::from_events(events.iter().copied());
+
+        assert_eq!(status(&[]), None);
+        assert_eq!(status(&[Received]), Some(UnprocessedStatus::New.into()));
+        assert_eq!(
+            status(&[Received, Failed]),
+            Some(UnprocessedStatus::Failed.into())
+        );
+        assert_eq!(
+            status(&[Received, Validated, Batched]),
+            Some(ProcessedStatus::Pending.into())
+        );
+        assert_eq!(
+            status(&[Submitted, Mined]),
+            Some(ProcessedStatus::Processed.into())
+        );
+        assert_eq!(
+            status(&[Mined, Finalized]),
+            Some(ProcessedStatus::Mined.into())
+        );
+        assert_eq!(
+            status(&[Mined, Finalized, Deleted]),
+            Some(RemovedStatus::Deleted.into())
+        );
+    }
+
+    #[test]
+    fn event_names_round_trip() {
+        for event in [
+            IdentityEvent::Received,
+            IdentityEvent::Validated,
+            IdentityEvent::Batched,
+            IdentityEvent::Submitted,
+            IdentityEvent::Mined,
+            IdentityEvent::Finalized,
+            IdentityEvent::Failed,
+            IdentityEvent::Deleted,
+        ] {
+            assert_eq!(IdentityEvent::from_str(event.into()).unwrap(), event);
+        }
+    }
 }
@@ -0,0 +1,343 @@
+//! Bulk import of identity commitments from a CSV file.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{bail, ensure, Result as AnyhowResult};
+use chrono::Utc;
+use tracing::info;
+
+use crate::app::{snark_scalar_field, AppBuilder, MAX_METADATA_SIZE};
+use crate::database::types::NewInsertion;
+use crate::database::{Database, Error as DatabaseError};
+use crate::identity_tree::{Hash, TreeVersionReadOps, UnprocessedStatus};
+use crate::server::error::Error as ServerError;
+use crate::Options;
+
+#[derive(Debug, PartialEq, Eq)]
+struct Entry {
+    line:       usize,
+    commitment: Hash,
+    metadata:   Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Outcome {
+    /// Queued for insertion, the leaf index is assigned once batched.
+    Queued,
+    Inserted {
+        leaf_index: usize,
+    },
+    Rejected(String),
+}
+
+#[derive(Default)]
+struct Report {
+    results: Vec<(usize, String, Outcome)>,
+}
+
+impl Report {
+    fn record(&mut self, line: usize, commitment: String, outcome: Outcome) {
+        self.results.push((line, commitment, outcome));
+    }
+
+    fn record_entry(&mut self, entry: &Entry, outcome: Outcome) {
+        self.record(entry.line, format!("{:#x}", entry.commitment), outcome);
+    }
+
+    fn rejected(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|(_, _, outcome)| matches!(outcome, Outcome::Rejected(_)))
+            .count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut results: Vec<_> = self.results.iter().collect();
+        results.sort_by_key(|(line, ..)| *line);
+
+        for (line, commitment, outcome) in results {
+            match outcome {
+                Outcome::Queued => writeln!(f, "line {line}: {commitment} queued")?,
+                Outcome::Inserted { leaf_index } => {
+                    writeln!(f, "line {line}: {commitment} leaf index {leaf_index}")?;
+                }
+                Outcome::Rejected(reason) => {
+                    writeln!(f, "line {line}: {commitment} rejected, {reason}")?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses one commitment per line, in hex or decimal, optionally followed by
+/// its metadata in the second column. A header line is skipped.
+fn parse_csv(contents: &str, report: &mut Report) -> Vec<Entry> {
+    let mut entries = Vec::new();
+
+    for (index, line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (commitment, metadata) = match line.split_once(',') {
+            Some((commitment, metadata)) => (commitment, Some(metadata)),
+            None => (line, None),
+        };
+        let commitment = unquote(commitment);
+        let metadata = metadata
+            .map(unquote)
+            .filter(|metadata| !metadata.is_empty())
+            .map(ToOwned::to_owned);
+
+        match Hash::from_str(commitment) {
+            Ok(commitment) => entries.push(Entry {
+                line: line_number,
+                commitment,
+                metadata,
+            }),
+            Err(_) if line_number == 1 => {}
+            Err(error) => report.record(
+                line_number,
+                commitment.to_owned(),
+                Outcome::Rejected(format!("invalid commitment: {error}")),
+            ),
+        }
+    }
+
+    entries
+}
+
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// Imports the identities listed in a CSV file and prints a report of the
+/// outcome for each of them.
+///
+/// By default the identities are queued like through `/insertIdentity`, so
+/// they're batched by the running sequencer. In offline mode they are
+/// appended to the tree in the database directly, which must only be done
+/// while the sequencer is stopped.
+///
+/// # Errors
+///
+/// Will return `Err` if the file can't be read, the import fails, or any of
+/// the entries were rejected.
+pub async fn run(options: Options, file: &Path, offline: bool) -> AnyhowResult<()> {
+    let contents = std::fs::read_to_string(file)?;
+
+    let mut report = Report::default();
+    let entries = parse_csv(&contents, &mut report);
+    info!(entries = entries.len(), offline, "Importing identities");
+
+    if offline {
+        import_offline(options, entries, &mut report).await?;
+    } else {
+        import_queued(options, entries, &mut report).await?;
+    }
+
+    println!("{report}");
+
+    let rejected = report.rejected();
+    if rejected > 0 {
+        bail!("{rejected} entries were rejected");
+    }
+
+    Ok(())
+}
+
+/// Queues the identities for insertion through the database, like
+/// `/insertIdentity` does, so that they're batched by the running sequencer.
+async fn import_queued(
+    options: Options,
+    entries: Vec<Entry>,
+    report: &mut Report,
+) -> AnyhowResult<()> {
+    let initial_leaf_value = options.app.contracts.initial_leaf_value;
+    let capacity: usize = 1 << options.app.contracts.tree_depth;
+    let database = Database::new(options.app.database, options.app.retry.policy()).await?;
+
+    let mut seen = HashSet::new();
+    let mut accepted = Vec::with_capacity(entries.len());
+    for entry in entries {
+        // Failed commitments, e.g. expired ones, can be submitted again as
+        // long as they didn't make it into the tree.
+        if let Some((UnprocessedStatus::Failed, _)) = database
+            .get_unprocessed_commit_status(&entry.commitment)
+            .await?
+        {
+            if database
+                .get_identity_leaf_index(&entry.commitment)
+                .await?
+                .is_none()
+            {
+                database
+                    .remove_unprocessed_identity(&entry.commitment)
+                    .await?;
+            }
+        }
+
+        match rejection(&database, &entry, initial_leaf_value, &mut seen).await? {
+            Some(error) => report.record_entry(&entry, Outcome::Rejected(error.to_string())),
+            None => accepted.push(entry),
+        }
+    }
+
+    let created_at = Utc::now();
+    let insertions: Vec<NewInsertion> = accepted
+        .iter()
+        .map(|entry| NewInsertion {
+            commitment: entry.commitment,
+            metadata: entry.metadata.clone(),
+            signer: None,
+            created_at,
+        })
+        .collect();
+    // Either all of them fit into the tree or none is queued.
+    let outcome = match database.insert_new_identities(&insertions, capacity).await {
+        Ok(_) => Outcome::Queued,
+        Err(DatabaseError::TreeFull { capacity }) => {
+            Outcome::Rejected(ServerError::TreeFull { capacity }.to_string())
+        }
+        Err(error) => return Err(error.into()),
+    };
+
+    for entry in &accepted {
+        report.record_entry(entry, outcome.clone());
+    }
+
+    Ok(())
+}
+
+/// Why an entry can't be inserted, if it can't.
+async fn rejection(
+    database: &Database,
+    entry: &Entry,
+    initial_leaf_value: Hash,
+    seen: &mut HashSet<Hash>,
+) -> AnyhowResult<Option<ServerError>> {
+    let rejection = if entry
+        .metadata
+        .as_ref()
+        .is_some_and(|metadata| metadata.len() > MAX_METADATA_SIZE)
+    {
+        Some(ServerError::MetadataTooLarge)
+    } else if entry.commitment == initial_leaf_value {
+        Some(ServerError::InvalidCommitment)
+    } else if entry.commitment >= snark_scalar_field() {
+        Some(ServerError::UnreducedCommitment)
+    } else if !seen.insert(entry.commitment) || database.identity_exists(entry.commitment).await? {
+        Some(ServerError::DuplicateCommitment)
+    } else {
+        None
+    };
+
+    Ok(rejection)
+}
+
+async fn import_offline(
+    options: Options,
+    entries: Vec<Entry>,
+    report: &mut Report,
+) -> AnyhowResult<()> {
+    let initial_leaf_value = options.app.contracts.initial_leaf_value;
+    let capacity: usize = 1 << options.app.contracts.tree_depth;
+
+    let database = Database::new(options.app.database.clone(), options.app.retry.policy()).await?;
+    let tree_state = AppBuilder::new(options.app).build_tree().await?;
+    let latest_tree = tree_state.get_latest_tree();
+
+    let next_db_index = database.get_next_leaf_index().await?;
+    let next_leaf = latest_tree.next_leaf();
+    ensure!(
+        next_leaf == next_db_index,
+        "Database and tree are out of sync. Next leaf index in tree is: {next_leaf}, in database: \
+         {next_db_index}"
+    );
+
+    let mut seen = HashSet::new();
+    let mut accepted = Vec::with_capacity(entries.len());
+    for entry in entries {
+        match rejection(&database, &entry, initial_leaf_value, &mut seen).await? {
+            Some(error) => report.record_entry(&entry, Outcome::Rejected(error.to_string())),
+            None => accepted.push(entry),
+        }
+    }
+
+    // Entries past the last leaf of the tree are rejected.
+    let room = capacity.saturating_sub(next_leaf);
+    if accepted.len() > room {
+        for entry in accepted.split_off(room) {
+            report.record_entry(
+                &entry,
+                Outcome::Rejected(ServerError::TreeFull { capacity }.to_string()),
+            );
+        }
+    }
+
+    let commitments: Vec<Hash> = accepted.iter().map(|entry| entry.commitment).collect();
+    let data = latest_tree.append_many(&commitments);
+
+    for ((root, _proof, leaf_index), entry) in data.into_iter().zip(accepted) {
+        database
+            .insert_pending_identity(leaf_index, &entry.commitment, &root)
+            .await?;
+
+        if let Some(metadata) = &entry.metadata {
+            database
+                .insert_identity_metadata(&entry.commitment, metadata)
+                .await?;
+        }
+
+        report.record_entry(&entry, Outcome::Inserted { leaf_index });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commitments_and_metadata() {
+        let contents = "commitment,metadata\n0x1,\"partner a\"\n\n2\nnot a number,x\n3,\n";
+
+        let mut report = Report::default();
+        let entries = parse_csv(contents, &mut report);
+
+        assert_eq!(entries, vec![
+            Entry {
+                line:       2,
+                commitment: Hash::from(1),
+                metadata:   Some("partner a".into()),
+            },
+            Entry {
+                line:       4,
+                commitment: Hash::from(2),
+                metadata:   None,
+            },
+            Entry {
+                line:       6,
+                commitment: Hash::from(3),
+                metadata:   None,
+            },
+        ]);
+
+        assert_eq!(report.rejected(), 1);
+        assert_eq!(report.results[0].0, 5);
+    }
+}
@@ -0,0 +1,294 @@
+//! Challenges clients have to solve before an identity is inserted, to make
+//! scripted signups on public deployments costly.
+
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use clap::{Parser, ValueEnum};
+use ethers::utils::keccak256;
+use serde::Deserialize;
+use tracing::warn;
+use url::Url;
+
+use crate::identity_tree::Hash;
+use crate::redaction::redacted;
+use crate::secret::Secret;
+use crate::server::error::Error as ServerError;
+
+/// Tokens longer than this are rejected without being checked.
+const MAX_TOKEN_LENGTH: usize = 4096;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ChallengeKind {
+    #[default]
+    None,
+    /// Hashcash-style proof of work bound to the commitment
+    ProofOfWork,
+    /// A token issued by an external captcha service
+    Captcha,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// The challenge `/insertIdentity` requests have to carry a solution to.
+    #[clap(long, env, value_enum, default_value = "none")]
+    pub insertion_challenge: ChallengeKind,
+
+    /// Number of leading zero bits required of `keccak256(commitment ||
+    /// token)` for proof of work challenges.
+    #[clap(long, env, default_value = "20")]
+    pub pow_difficulty_bits: u32,
+
+    /// Endpoint verifying captcha tokens, e.g. hCaptcha's or Turnstile's
+    /// `siteverify`. It's sent the `secret` and `response` form fields and has
+    /// to respond with `{"success": bool}`.
+    #[clap(long, env)]
+    pub captcha_verify_url: Option<Url>,
+
+    /// Secret of the deployment at the captcha service.
+    #[clap(long, env, default_value = "")]
+    pub captcha_secret: Secret,
+
+    /// Timeout of captcha verification requests (seconds).
+    #[clap(long, env, default_value = "5")]
+    pub captcha_timeout_seconds: u64,
+}
+
+/// Checks solutions to a challenge.
+#[async_trait]
+pub trait ChallengeVerifier: Send + Sync {
+    /// # Errors
+    ///
+    /// Will return `Err` if the token doesn't solve the challenge for the
+    /// commitment, or it can't be checked.
+    async fn verify(&self, commitment: Hash, token: &str) -> Result<(), ServerError>;
+}
+
+/// The challenge configured for the deployment, if any.
+pub struct InsertionChallenge {
+    verifier: Option<Box<dyn ChallengeVerifier>>,
+}
+
+impl InsertionChallenge {
+    /// # Errors
+    ///
+    /// Will return `Err` if the options of the configured challenge are
+    /// missing or invalid.
+    pub fn new(options: &Options) -> anyhow::Result<Self> {
+        let verifier: Option<Box<dyn ChallengeVerifier>> = match options.insertion_challenge {
+            ChallengeKind::None => None,
+            ChallengeKind::ProofOfWork => {
+                Some(Box::new(ProofOfWork::new(options.pow_difficulty_bits)?))
+            }
+            ChallengeKind::Captcha => Some(Box::new(Captcha::new(options)?)),
+        };
+
+        Ok(Self { verifier })
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if a challenge is configured and the token is missing
+    /// or doesn't solve it.
+    pub async fn verify(&self, commitment: Hash, token: Option<&str>) -> Result<(), ServerError> {
+        let Some(verifier) = &self.verifier else {
+            return Ok(());
+        };
+
+        let Some(token) = token else {
+            return Err(ServerError::MissingChallengeToken);
+        };
+
+        if token.len() > MAX_TOKEN_LENGTH {
+            return Err(ServerError::InvalidChallengeToken);
+        }
+
+        verifier.verify(commitment, token).await
+    }
+}
+
+/// Requires `keccak256(commitment || token)` to start with a number of zero
+/// bits. Solutions are bound to the commitment, so they can't be reused.
+pub struct ProofOfWork {
+    difficulty_bits: u32,
+}
+
+impl ProofOfWork {
+    /// # Errors
+    ///
+    /// Will return `Err` if the difficulty exceeds the size of the digest.
+    pub fn new(difficulty_bits: u32) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            difficulty_bits <= 256,
+            "Proof of work difficulty must be at most 256 bits"
+        );
+
+        Ok(Self { difficulty_bits })
+    }
+
+    #[must_use]
+    pub fn digest(commitment: Hash, token: &str) -> [u8; 32] {
+        let mut preimage = commitment.to_be_bytes::<32>().to_vec();
+        preimage.extend_from_slice(token.as_bytes());
+
+        keccak256(preimage)
+    }
+
+    fn leading_zero_bits(digest: &[u8; 32]) -> u32 {
+        let mut bits = 0;
+
+        for byte in digest {
+            bits += byte.leading_zeros();
+            if *byte != 0 {
+                break;
+            }
+        }
+
+        bits
+    }
+}
+
+#[async_trait]
+impl ChallengeVerifier for ProofOfWork {
+    async fn verify(&self, commitment: Hash, token: &str) -> Result<(), ServerError> {
+        let digest = Self::digest(commitment, token);
+
+        if Self::leading_zero_bits(&digest) < self.difficulty_bits {
+            return Err(ServerError::InvalidChallengeToken);
+        }
+
+        Ok(())
+    }
+}
+
+/// Verifies tokens with a captcha service.
+pub struct Captcha {
+    client: reqwest::Client,
+    url:    Url,
+    secret: Secret,
+}
+
+#[derive(Deserialize)]
+struct CaptchaVerification {
+    success: bool,
+}
+
+impl Captcha {
+    /// # Errors
+    ///
+    /// Will return `Err` if the verification URL isn't set.
+    pub fn new(options: &Options) -> anyhow::Result<Self> {
+        let url = options
+            .captcha_verify_url
+            .clone()
+            .context("--captcha-verify-url is required for captcha challenges")?;
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(options.captcha_timeout_seconds))
+            .build()?;
+
+        Ok(Self {
+            client,
+            url,
+            secret: options.captcha_secret.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl ChallengeVerifier for Captcha {
+    async fn verify(&self, commitment: Hash, token: &str) -> Result<(), ServerError> {
+        let verification = self
+            .client
+            .post(self.url.clone())
+            .form(&[("secret", self.secret.expose()), ("response", token)])
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status);
+
+        let verification = match verification {
+            Ok(response) => response.json::<CaptchaVerification>().await,
+            Err(error) => Err(error),
+        }
+        .map_err(|error| {
+            warn!(commitment = ?redacted(&commitment), ?error, "Failed to verify captcha token.");
+            ServerError::ChallengeVerifierUnavailable
+        })?;
+
+        if !verification.success {
+            return Err(ServerError::InvalidChallengeToken);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solve(commitment: Hash, difficulty_bits: u32) -> String {
+        (0_u64..)
+            .map(|nonce| nonce.to_string())
+            .find(|nonce| {
+                ProofOfWork::leading_zero_bits(&ProofOfWork::digest(commitment, nonce))
+                    >= difficulty_bits
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn counts_leading_zero_bits() {
+        let mut digest = [0xff; 32];
+        assert_eq!(ProofOfWork::leading_zero_bits(&digest), 0);
+
+        digest[0] = 0;
+        digest[1] = 0b0001_0000;
+        assert_eq!(ProofOfWork::leading_zero_bits(&digest), 11);
+
+        assert_eq!(ProofOfWork::leading_zero_bits(&[0; 32]), 256);
+    }
+
+    #[tokio::test]
+    async fn proof_of_work_is_bound_to_the_commitment() {
+        let challenge = InsertionChallenge {
+            verifier: Some(Box::new(ProofOfWork::new(8).unwrap())),
+        };
+        let token = solve(Hash::from(42), 8);
+
+        assert!(challenge.verify(Hash::from(42), Some(&token)).await.is_ok());
+        assert!(matches!(
+            challenge.verify(Hash::from(42), None).await,
+            Err(ServerError::MissingChallengeToken)
+        ));
+
+        // Solving for another commitment by chance is unlikely enough for
+        // this token.
+        let other = (43_u64..)
+            .map(Hash::from)
+            .find(|commitment| {
+                ProofOfWork::leading_zero_bits(&ProofOfWork::digest(*commitment, &token)) < 8
+            })
+            .unwrap();
+        assert!(matches!(
+            challenge.verify(other, Some(&token)).await,
+            Err(ServerError::InvalidChallengeToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn no_challenge_accepts_everything() {
+        let challenge = InsertionChallenge::new(&Options {
+            insertion_challenge:     ChallengeKind::None,
+            pow_difficulty_bits:     20,
+            captcha_verify_url:      None,
+            captcha_secret:          Secret::default(),
+            captcha_timeout_seconds: 5,
+        })
+        .unwrap();
+
+        assert!(challenge.verify(Hash::from(42), None).await.is_ok());
+    }
+}
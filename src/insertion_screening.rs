@@ -0,0 +1,345 @@
+//! Screening of submissions against sanctions lists or jurisdictions before an
+//! identity is inserted, for deployments with compliance requirements.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use axum::http::HeaderMap;
+use clap::{Parser, ValueEnum};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use url::Url;
+
+use crate::identity_tree::Hash;
+use crate::redaction::redacted;
+use crate::secret::Secret;
+use crate::serde_utils::JsonStrWrapper;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ScreenerKind {
+    #[default]
+    None,
+    /// An external screening service
+    Http,
+    /// Blocked addresses and jurisdictions listed in the options
+    StaticList,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// How `/insertIdentity` submissions are screened before the identity is
+    /// inserted.
+    #[clap(long, env, value_enum, default_value = "none")]
+    pub insertion_screening: ScreenerKind,
+
+    /// Endpoint of the screening service. It's sent `{"commitment", "signer",
+    /// "jurisdiction"}` and has to respond with `{"allowed": bool}`.
+    #[clap(long, env)]
+    pub screening_url: Option<Url>,
+
+    /// Bearer token authenticating with the screening service.
+    #[clap(long, env, default_value = "")]
+    pub screening_api_key: Secret,
+
+    /// Timeout of requests to the screening service (seconds).
+    #[clap(long, env, default_value = "5")]
+    pub screening_timeout_seconds: u64,
+
+    /// Signer addresses whose submissions are denied by the static list, e.g.
+    /// `["0x..."]`.
+    #[clap(long, env, default_value = "[]")]
+    pub screening_blocked_addresses: JsonStrWrapper<HashSet<Address>>,
+
+    /// Jurisdictions whose submissions are denied by the static list, as
+    /// reported in `--screening-jurisdiction-header`, e.g. `["KP"]`.
+    #[clap(long, env, default_value = "[]")]
+    pub screening_blocked_jurisdictions: JsonStrWrapper<HashSet<String>>,
+
+    /// Request header carrying the jurisdiction of the client, e.g. the
+    /// `CF-IPCountry` header set by the CDN in front of the sequencer.
+    #[clap(long, env)]
+    pub screening_jurisdiction_header: Option<String>,
+
+    /// Accept submissions which can't be screened, e.g. while the screening
+    /// service is down, rather than rejecting them.
+    #[clap(long, env)]
+    pub screening_fail_open: bool,
+}
+
+/// What's known about the submitter of an insertion.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScreeningSubject {
+    pub commitment:   Hash,
+    /// Set if the insertion was signed
+    pub signer:       Option<Address>,
+    pub jurisdiction: Option<String>,
+}
+
+/// The outcome of screening a submission, as recorded in the audit log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScreeningDecision {
+    Allowed,
+    Denied,
+    /// Screening failed and the submission was accepted
+    FailedOpen,
+    /// Screening failed and the submission was rejected
+    FailedClosed,
+}
+
+impl ScreeningDecision {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Allowed => "allowed",
+            Self::Denied => "denied",
+            Self::FailedOpen => "failedOpen",
+            Self::FailedClosed => "failedClosed",
+        }
+    }
+}
+
+/// Decides whether a submission may be inserted.
+#[async_trait]
+pub trait Screener: Send + Sync {
+    /// Returns whether the subject is allowed.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the subject can't be screened.
+    async fn screen(&self, subject: &ScreeningSubject) -> anyhow::Result<bool>;
+}
+
+/// The screening configured for the deployment, if any.
+pub struct InsertionScreening {
+    screener:            Option<Box<dyn Screener>>,
+    jurisdiction_header: Option<String>,
+    fail_open:           bool,
+}
+
+impl InsertionScreening {
+    /// # Errors
+    ///
+    /// Will return `Err` if the options of the configured screener are
+    /// missing or invalid.
+    pub fn new(options: &Options) -> anyhow::Result<Self> {
+        let screener: Option<Box<dyn Screener>> = match options.insertion_screening {
+            ScreenerKind::None => None,
+            ScreenerKind::Http => Some(Box::new(HttpScreener::new(options)?)),
+            ScreenerKind::StaticList => Some(Box::new(StaticList {
+                addresses:     options.screening_blocked_addresses.0.clone(),
+                jurisdictions: options.screening_blocked_jurisdictions.0.clone(),
+            })),
+        };
+
+        Ok(Self {
+            screener,
+            jurisdiction_header: options.screening_jurisdiction_header.clone(),
+            fail_open: options.screening_fail_open,
+        })
+    }
+
+    /// The jurisdiction of the client, if the deployment is configured to
+    /// read it from the request.
+    #[must_use]
+    pub fn jurisdiction(&self, headers: &HeaderMap) -> Option<String> {
+        let header = self.jurisdiction_header.as_ref()?;
+
+        headers
+            .get(header)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim().to_ascii_uppercase())
+    }
+
+    /// Screens the subject, `None` if no screening is configured.
+    pub async fn screen(&self, subject: &ScreeningSubject) -> Option<ScreeningDecision> {
+        let screener = self.screener.as_ref()?;
+
+        let decision = match screener.screen(subject).await {
+            Ok(true) => ScreeningDecision::Allowed,
+            Ok(false) => ScreeningDecision::Denied,
+            Err(error) => {
+                warn!(
+                    commitment = ?redacted(&subject.commitment),
+                    ?error,
+                    fail_open = self.fail_open,
+                    "Failed to screen submission."
+                );
+
+                if self.fail_open {
+                    ScreeningDecision::FailedOpen
+                } else {
+                    ScreeningDecision::FailedClosed
+                }
+            }
+        };
+
+        Some(decision)
+    }
+}
+
+/// Denies listed signers and jurisdictions.
+pub struct StaticList {
+    addresses:     HashSet<Address>,
+    jurisdictions: HashSet<String>,
+}
+
+#[async_trait]
+impl Screener for StaticList {
+    async fn screen(&self, subject: &ScreeningSubject) -> anyhow::Result<bool> {
+        let blocked_signer = subject
+            .signer
+            .is_some_and(|signer| self.addresses.contains(&signer));
+        let blocked_jurisdiction = subject.jurisdiction.as_ref().is_some_and(|jurisdiction| {
+            self.jurisdictions
+                .iter()
+                .any(|blocked| blocked.eq_ignore_ascii_case(jurisdiction))
+        });
+
+        Ok(!blocked_signer && !blocked_jurisdiction)
+    }
+}
+
+/// Asks an external screening service.
+pub struct HttpScreener {
+    client:  reqwest::Client,
+    url:     Url,
+    api_key: Secret,
+}
+
+#[derive(Deserialize)]
+struct HttpScreeningResponse {
+    allowed: bool,
+}
+
+impl HttpScreener {
+    /// # Errors
+    ///
+    /// Will return `Err` if the screening URL isn't set.
+    pub fn new(options: &Options) -> anyhow::Result<Self> {
+        let url = options
+            .screening_url
+            .clone()
+            .context("--screening-url is required for HTTP screening")?;
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(options.screening_timeout_seconds))
+            .build()?;
+
+        Ok(Self {
+            client,
+            url,
+            api_key: options.screening_api_key.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl Screener for HttpScreener {
+    async fn screen(&self, subject: &ScreeningSubject) -> anyhow::Result<bool> {
+        let mut request = self.client.post(self.url.clone()).json(subject);
+        if !self.api_key.is_empty() {
+            request = request.bearer_auth(self.api_key.expose());
+        }
+
+        let response: HttpScreeningResponse =
+            request.send().await?.error_for_status()?.json().await?;
+
+        Ok(response.allowed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Unavailable;
+
+    #[async_trait]
+    impl Screener for Unavailable {
+        async fn screen(&self, _subject: &ScreeningSubject) -> anyhow::Result<bool> {
+            anyhow::bail!("screening service is down")
+        }
+    }
+
+    fn subject(signer: Option<Address>, jurisdiction: Option<&str>) -> ScreeningSubject {
+        ScreeningSubject {
+            commitment: Hash::from(42),
+            signer,
+            jurisdiction: jurisdiction.map(ToOwned::to_owned),
+        }
+    }
+
+    #[tokio::test]
+    async fn static_list_denies_listed_signers_and_jurisdictions() {
+        let screening = InsertionScreening {
+            screener:            Some(Box::new(StaticList {
+                addresses:     HashSet::from([Address::repeat_byte(1)]),
+                jurisdictions: HashSet::from(["KP".to_owned()]),
+            })),
+            jurisdiction_header: None,
+            fail_open:           false,
+        };
+
+        let signer = Some(Address::repeat_byte(1));
+        assert_eq!(
+            screening.screen(&subject(signer, None)).await,
+            Some(ScreeningDecision::Denied)
+        );
+        assert_eq!(
+            screening.screen(&subject(None, Some("kp"))).await,
+            Some(ScreeningDecision::Denied)
+        );
+
+        let signer = Some(Address::repeat_byte(2));
+        assert_eq!(
+            screening.screen(&subject(signer, Some("CH"))).await,
+            Some(ScreeningDecision::Allowed)
+        );
+        assert_eq!(
+            screening.screen(&subject(None, None)).await,
+            Some(ScreeningDecision::Allowed)
+        );
+    }
+
+    #[tokio::test]
+    async fn failures_follow_the_configured_behavior() {
+        for (fail_open, expected) in [
+            (true, ScreeningDecision::FailedOpen),
+            (false, ScreeningDecision::FailedClosed),
+        ] {
+            let screening = InsertionScreening {
+                screener: Some(Box::new(Unavailable)),
+                jurisdiction_header: None,
+                fail_open,
+            };
+
+            assert_eq!(screening.screen(&subject(None, None)).await, Some(expected));
+        }
+    }
+
+    #[tokio::test]
+    async fn jurisdiction_is_read_from_the_configured_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert("cf-ipcountry", "ch".parse().unwrap());
+
+        let screening = InsertionScreening::new(&Options {
+            insertion_screening:             ScreenerKind::None,
+            screening_url:                   None,
+            screening_api_key:               Secret::default(),
+            screening_timeout_seconds:       5,
+            screening_blocked_addresses:     JsonStrWrapper(HashSet::new()),
+            screening_blocked_jurisdictions: JsonStrWrapper(HashSet::new()),
+            screening_jurisdiction_header:   Some("CF-IPCountry".to_owned()),
+            screening_fail_open:             false,
+        })
+        .unwrap();
+
+        assert_eq!(screening.jurisdiction(&headers), Some("CH".to_owned()));
+        assert_eq!(screening.screen(&subject(None, None)).await, None);
+    }
+}
@@ -0,0 +1,230 @@
+//! EIP-712 signatures by which clients prove control of an Ethereum address
+//! when inserting an identity.
+
+use std::collections::HashSet;
+
+use clap::Parser;
+use ethers::abi::{encode, Token};
+use ethers::types::transaction::eip712::EIP712Domain;
+use ethers::types::{Address, Bytes, Signature, H256, U256};
+use ethers::utils::keccak256;
+use tracing::warn;
+
+use crate::identity_tree::Hash;
+use crate::redaction::redacted;
+use crate::server::error::Error as ServerError;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Require `/insertIdentity` requests to carry an EIP-712 signature over
+    /// the commitment. The recovered signer is recorded in the audit log.
+    #[clap(long, env)]
+    pub require_insertion_signatures: bool,
+
+    /// Only accept signatures by these addresses. Any signer is accepted when
+    /// left empty.
+    #[clap(long, env, value_delimiter = ',')]
+    pub authorized_insertion_signers: Vec<Address>,
+}
+
+const DOMAIN_NAME: &str = "Signup Sequencer";
+const DOMAIN_VERSION: &str = "1";
+const INSERT_IDENTITY_TYPE: &str = "InsertIdentity(uint256 identityCommitment)";
+
+/// Verifies the signatures of `InsertIdentity(uint256 identityCommitment)`
+/// messages. The domain is bound to the chain and the identity manager
+/// contract, so signatures can't be replayed against another deployment.
+pub struct InsertionAuthenticator {
+    required:           bool,
+    authorized_signers: HashSet<Address>,
+    domain_separator:   [u8; 32],
+}
+
+impl InsertionAuthenticator {
+    #[must_use]
+    pub fn new(options: &Options, chain_id: U256, verifying_contract: Address) -> Self {
+        let domain = EIP712Domain {
+            name:               Some(DOMAIN_NAME.into()),
+            version:            Some(DOMAIN_VERSION.into()),
+            chain_id:           Some(chain_id),
+            verifying_contract: Some(verifying_contract),
+            salt:               None,
+        };
+
+        Self {
+            required:           options.require_insertion_signatures,
+            authorized_signers: options
+                .authorized_insertion_signers
+                .iter()
+                .copied()
+                .collect(),
+            domain_separator:   domain.separator(),
+        }
+    }
+
+    /// Computes the EIP-712 digest signed for the commitment.
+    #[must_use]
+    pub fn digest(&self, commitment: Hash) -> H256 {
+        let struct_hash = keccak256(encode(&[
+            Token::FixedBytes(keccak256(INSERT_IDENTITY_TYPE).to_vec()),
+            Token::Uint(commitment.into()),
+        ]));
+
+        let mut bytes = Vec::with_capacity(66);
+        bytes.extend_from_slice(b"\x19\x01");
+        bytes.extend_from_slice(&self.domain_separator);
+        bytes.extend_from_slice(&struct_hash);
+
+        keccak256(bytes).into()
+    }
+
+    /// Recovers the signer of the commitment. Unsigned insertions are only
+    /// accepted when signatures aren't required.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if a required signature is missing, the signature is
+    /// malformed or it isn't by one of the authorized signers.
+    pub fn authenticate(
+        &self,
+        commitment: Hash,
+        signature: Option<&Bytes>,
+    ) -> Result<Option<Address>, ServerError> {
+        let Some(signature) = signature else {
+            if self.required {
+                return Err(ServerError::MissingSignature);
+            }
+            return Ok(None);
+        };
+
+        let signer = Signature::try_from(signature.as_ref())
+            .and_then(|signature| signature.recover(self.digest(commitment)))
+            .map_err(|error| {
+                warn!(commitment = ?redacted(&commitment), ?error, "Invalid insertion signature.");
+                ServerError::InvalidSignature
+            })?;
+
+        if !self.authorized_signers.is_empty() && !self.authorized_signers.contains(&signer) {
+            warn!(commitment = ?redacted(&commitment), ?signer, "Unauthorized insertion signer.");
+            return Err(ServerError::InvalidSignature);
+        }
+
+        Ok(Some(signer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::signers::{LocalWallet, Signer};
+
+    use super::*;
+
+    const TEST_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+    const UNAUTHORIZED_KEY: &str =
+        "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
+
+    fn authenticator(required: bool) -> InsertionAuthenticator {
+        authorizing(required, vec![])
+    }
+
+    fn authorizing(required: bool, signers: Vec<Address>) -> InsertionAuthenticator {
+        InsertionAuthenticator::new(
+            &Options {
+                require_insertion_signatures: required,
+                authorized_insertion_signers: signers,
+            },
+            U256::from(1),
+            Address::repeat_byte(0x42),
+        )
+    }
+
+    fn sign(authenticator: &InsertionAuthenticator, commitment: Hash) -> (Address, Bytes) {
+        sign_with(TEST_KEY, authenticator, commitment)
+    }
+
+    fn sign_with(
+        key: &str,
+        authenticator: &InsertionAuthenticator,
+        commitment: Hash,
+    ) -> (Address, Bytes) {
+        let wallet: LocalWallet = key.parse().unwrap();
+        let signature = wallet.sign_hash(authenticator.digest(commitment)).unwrap();
+
+        (wallet.address(), signature.to_vec().into())
+    }
+
+    #[test]
+    fn recovers_the_signer() {
+        let authenticator = authenticator(true);
+        let (address, signature) = sign(&authenticator, Hash::from(42));
+
+        assert_eq!(
+            authenticator
+                .authenticate(Hash::from(42), Some(&signature))
+                .unwrap(),
+            Some(address)
+        );
+
+        // A signature over another commitment recovers to another address.
+        assert_ne!(
+            authenticator
+                .authenticate(Hash::from(43), Some(&signature))
+                .unwrap(),
+            Some(address)
+        );
+    }
+
+    #[test]
+    fn requires_signatures_only_when_configured() {
+        assert!(matches!(
+            authenticator(true).authenticate(Hash::from(42), None),
+            Err(ServerError::MissingSignature)
+        ));
+        assert!(matches!(
+            authenticator(false).authenticate(Hash::from(42), None),
+            Ok(None)
+        ));
+        assert!(matches!(
+            authenticator(false).authenticate(Hash::from(42), Some(&Bytes::from(vec![1, 2, 3]))),
+            Err(ServerError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn rejects_unauthorized_signers() {
+        let authorized: LocalWallet = TEST_KEY.parse().unwrap();
+        let authenticator = authorizing(true, vec![authorized.address()]);
+
+        let (address, signature) = sign(&authenticator, Hash::from(42));
+        assert_eq!(
+            authenticator
+                .authenticate(Hash::from(42), Some(&signature))
+                .unwrap(),
+            Some(address)
+        );
+
+        let (_, signature) = sign_with(UNAUTHORIZED_KEY, &authenticator, Hash::from(42));
+        assert!(matches!(
+            authenticator.authenticate(Hash::from(42), Some(&signature)),
+            Err(ServerError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn digest_is_bound_to_the_domain() {
+        let other_chain = InsertionAuthenticator::new(
+            &Options {
+                require_insertion_signatures: true,
+                authorized_insertion_signers: vec![],
+            },
+            U256::from(10),
+            Address::repeat_byte(0x42),
+        );
+
+        assert_ne!(
+            authenticator(true).digest(Hash::from(42)),
+            other_chain.digest(Hash::from(42))
+        );
+    }
+}
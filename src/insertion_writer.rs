@@ -0,0 +1,212 @@
+//! Writes accepted insertions to the database in batches, so that concurrent
+//! submissions share a transaction instead of each paying for its own round
+//! trips. Submitters are only answered once their batch is committed.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Parser;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+use tracing::warn;
+
+use crate::database::types::NewInsertion;
+use crate::database::{Database, Error as DatabaseError};
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Maximum number of accepted insertions written to the database in a
+    /// single transaction.
+    #[clap(long, env, default_value = "100")]
+    pub insertion_write_batch_size: usize,
+
+    /// Longest an accepted insertion waits for others to be written along
+    /// with it (milliseconds).
+    #[clap(long, env, default_value = "5")]
+    pub insertion_write_max_delay_ms: u64,
+
+    /// Write every insertion in its own transaction, as soon as it's
+    /// accepted, instead of batching writes.
+    #[clap(long, env)]
+    pub per_insertion_durability: bool,
+}
+
+type Reply = oneshot::Sender<Result<u64, DatabaseError>>;
+
+pub struct InsertionWriter {
+    database: Arc<Database>,
+    /// Leaves of the tree, insertions which don't fit anymore are refused.
+    capacity: usize,
+    /// `None` if insertions are written individually.
+    sender:   Option<mpsc::Sender<(NewInsertion, Reply)>>,
+}
+
+impl InsertionWriter {
+    /// Starts the task writing batches, unless writes aren't batched.
+    #[must_use]
+    pub fn new(options: &Options, database: Arc<Database>, capacity: usize) -> Self {
+        if options.per_insertion_durability || options.insertion_write_batch_size <= 1 {
+            return Self {
+                database,
+                capacity,
+                sender: None,
+            };
+        }
+
+        let batch_size = options.insertion_write_batch_size;
+        let max_delay = Duration::from_millis(options.insertion_write_max_delay_ms);
+        let (sender, receiver) = mpsc::channel(batch_size);
+        tokio::spawn(write_batches(
+            database.clone(),
+            receiver,
+            batch_size,
+            max_delay,
+            capacity,
+        ));
+
+        Self {
+            database,
+            capacity,
+            sender: Some(sender),
+        }
+    }
+
+    /// Queues the insertion and returns its sequence number, once it's
+    /// committed.
+    pub async fn write(&self, insertion: NewInsertion) -> Result<u64, DatabaseError> {
+        let Some(sender) = &self.sender else {
+            return write_one(&self.database, insertion, self.capacity).await;
+        };
+
+        let (reply, response) = oneshot::channel();
+        sender
+            .send((insertion, reply))
+            .await
+            .expect("insertion writer stopped");
+
+        response.await.expect("insertion writer dropped the reply")
+    }
+}
+
+async fn write_one(
+    database: &Database,
+    insertion: NewInsertion,
+    capacity: usize,
+) -> Result<u64, DatabaseError> {
+    let sequence_numbers = database
+        .insert_new_identities(&[insertion], capacity)
+        .await?;
+    Ok(sequence_numbers[0])
+}
+
+/// Collects insertions until `batch_size` of them are pending or the first
+/// one waited for `max_delay`, and writes them together. Runs until the
+/// writer is dropped.
+async fn write_batches(
+    database: Arc<Database>,
+    mut receiver: mpsc::Receiver<(NewInsertion, Reply)>,
+    batch_size: usize,
+    max_delay: Duration,
+    capacity: usize,
+) {
+    while let Some(first) = receiver.recv().await {
+        let deadline = Instant::now() + max_delay;
+        let mut batch = vec![first];
+
+        while batch.len() < batch_size {
+            match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Ok(Some(pending)) => batch.push(pending),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        let (insertions, replies): (Vec<_>, Vec<_>) = batch.into_iter().unzip();
+
+        match database.insert_new_identities(&insertions, capacity).await {
+            Ok(sequence_numbers) => {
+                for (reply, sequence_number) in replies.into_iter().zip(sequence_numbers) {
+                    // The submitter may have gone away, the insertion stands.
+                    let _ = reply.send(Ok(sequence_number));
+                }
+            }
+            Err(error) => {
+                // A single conflicting insertion fails the whole batch, so each
+                // is retried on its own to give every submitter its own result.
+                // The ones which still fit are queued if the tree filled up.
+                warn!(
+                    ?error,
+                    count = insertions.len(),
+                    "Failed to write batch of insertions"
+                );
+
+                for (insertion, reply) in insertions.into_iter().zip(replies) {
+                    let _ = reply.send(write_one(&database, insertion, capacity).await);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use chrono::Utc;
+
+    use super::*;
+    use crate::database::Options as DatabaseOptions;
+    use crate::identity_tree::Hash;
+    use crate::secret::SecretUrl;
+    use crate::utils::retry::RetryPolicy;
+
+    #[tokio::test]
+    async fn concurrent_duplicates_are_refused() -> anyhow::Result<()> {
+        let db_container = postgres_docker_utils::setup().await?;
+        let url = format!(
+            "postgres://postgres:postgres@{}/database",
+            db_container.address()
+        );
+        let database = Database::new(
+            DatabaseOptions {
+                database:                 SecretUrl::from_str(&url)?,
+                database_migrate:         true,
+                database_max_connections: 1,
+            },
+            RetryPolicy::default(),
+        )
+        .await?;
+
+        let writer = InsertionWriter::new(
+            &Options {
+                insertion_write_batch_size:   100,
+                insertion_write_max_delay_ms: 50,
+                per_insertion_durability:     false,
+            },
+            Arc::new(database),
+            1 << 20,
+        );
+        let insertion = || NewInsertion {
+            commitment: Hash::from(42),
+            metadata:   None,
+            signer:     None,
+            created_at: Utc::now(),
+        };
+
+        // Both are written in the same batch, which fails as a whole.
+        let (first, second) = tokio::join!(writer.write(insertion()), writer.write(insertion()));
+
+        let (written, refused) = if first.is_ok() {
+            (first, second)
+        } else {
+            (second, first)
+        };
+        assert!(written.is_ok(), "{written:?}");
+        assert!(
+            matches!(refused, Err(DatabaseError::DuplicateCommitment)),
+            "{refused:?}"
+        );
+
+        Ok(())
+    }
+}
@@ -6,26 +6,100 @@
     clippy::too_many_arguments
 )]
 
+#[cfg(feature = "sequencer")]
 pub mod app;
+#[cfg(feature = "sequencer")]
+mod check;
+#[cfg(feature = "sequencer")]
+mod config_validation;
+#[cfg(feature = "sequencer")]
 mod contracts;
+#[cfg(feature = "sequencer")]
 mod database;
+#[cfg(feature = "sequencer")]
 mod ethereum;
+#[cfg(feature = "sequencer")]
+mod event_bus;
+#[cfg(feature = "sequencer")]
+mod fault_injection;
+#[cfg(feature = "sequencer")]
+mod follower;
+#[cfg(feature = "sequencer")]
+mod idempotency;
 pub mod identity_tree;
+#[cfg(feature = "sequencer")]
+mod import;
+#[cfg(feature = "sequencer")]
+mod insertion_challenge;
+#[cfg(feature = "sequencer")]
+mod insertion_screening;
+#[cfg(feature = "sequencer")]
+mod insertion_signatures;
+#[cfg(feature = "sequencer")]
+mod insertion_writer;
+#[cfg(feature = "sequencer")]
+mod metrics;
+#[cfg(feature = "sequencer")]
+mod prove_all;
+#[cfg(feature = "sequencer")]
 mod prover;
+#[cfg(feature = "sequencer")]
+mod queue_estimate;
+#[cfg(feature = "sequencer")]
+mod receipts;
+#[cfg(feature = "sequencer")]
+mod redaction;
+#[cfg(feature = "sequencer")]
+mod replay;
+#[cfg(feature = "sequencer")]
+mod restore_tree;
+#[cfg(feature = "sequencer")]
 pub mod secret;
+#[cfg(feature = "sequencer")]
 mod serde_utils;
+#[cfg(feature = "sequencer")]
 pub mod server;
+#[cfg(feature = "sequencer")]
+pub mod services;
+#[cfg(feature = "sequencer")]
 mod task_monitor;
+#[cfg(feature = "sequencer")]
+mod tree_capacity;
+#[cfg(feature = "sequencer")]
 pub mod utils;
+#[cfg(feature = "sequencer")]
+mod verify;
 
+#[cfg(feature = "sequencer")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "sequencer")]
 use std::sync::Arc;
 
-use anyhow::Result as AnyhowResult;
-use clap::Parser;
-use tracing::info;
+#[cfg(feature = "sequencer")]
+use anyhow::{Context, Result as AnyhowResult};
+#[cfg(feature = "sequencer")]
+use clap::{Parser, Subcommand};
+#[cfg(feature = "sequencer")]
+use cli_batteries::await_shutdown;
+#[cfg(feature = "sequencer")]
+use futures::future::try_join_all;
+#[cfg(feature = "sequencer")]
+use tokio::select;
+#[cfg(feature = "sequencer")]
+use tracing::{info, warn};
+#[cfg(feature = "sequencer")]
+use url::Url;
 
-use crate::app::App;
+#[cfg(feature = "sequencer")]
+use crate::app::{App, AppBuilder};
+#[cfg(feature = "sequencer")]
+use crate::identity_tree::{Hash, TreeSnapshot};
+#[cfg(feature = "sequencer")]
+use crate::secret::{Secret, SecretResolver};
+#[cfg(feature = "sequencer")]
+use crate::server::AppState;
 
+#[cfg(feature = "sequencer")]
 #[derive(Clone, Debug, PartialEq, Parser)]
 #[group(skip)]
 pub struct Options {
@@ -34,27 +108,458 @@ pub struct Options {
 
     #[clap(flatten)]
     pub server: server::Options,
+
+    #[clap(flatten)]
+    pub metrics: metrics::Options,
+
+    #[clap(flatten)]
+    pub secrets: secret::Options,
+
+    #[clap(flatten)]
+    pub redaction: redaction::Options,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[cfg(feature = "sequencer")]
+#[derive(Clone, Debug, PartialEq, Eq, Subcommand)]
+pub enum Command {
+    /// Serve the API only. Insertions and deletions are queued in the
+    /// database for a `process` instance, and the tree follows the one it
+    /// writes. Any number of instances can share the database.
+    Serve,
+
+    /// Serve inclusion proofs, roots and the tree info from a tree snapshot
+    /// file alone, e.g. on a host without network egress. The database, the
+    /// chain and the signer aren't used, and the snapshot isn't verified
+    /// against the chain.
+    ServeOffline {
+        /// Path of the tree snapshot file.
+        snapshot: PathBuf,
+    },
+
+    /// Follow the tree of another sequencer over its `/sync` routes as a warm
+    /// standby, until shutdown. On shutdown the mined tree is written to
+    /// `--tree-snapshot-file`, for a sequencer taking over to serve proofs
+    /// from.
+    Follow {
+        /// Base URL of the API of the sequencer to follow.
+        leader: Url,
+
+        /// API key sent to the leader, needs the `read-all` scope. Can be an
+        /// `env:`, `file:` or `vault:` reference.
+        #[clap(long, env = "LEADER_API_KEY")]
+        leader_api_key: Option<Secret>,
+    },
+
+    /// Batch identities and submit them on chain without serving the API.
+    /// Only one instance may run per database.
+    Process,
+
+    /// Validate the configuration and the connections to the database, the
+    /// chain and the provers, print a report and exit.
+    Check,
+
+    /// Import identity commitments from a CSV file and print the leaf index
+    /// assigned to each. Every line holds a commitment, optionally followed by
+    /// its metadata.
+    Import {
+        /// Path of the CSV file.
+        file: PathBuf,
+
+        /// Append the identities to the tree in the database directly instead
+        /// of queueing them for the sequencer. Must only be used while the
+        /// sequencer is stopped.
+        #[clap(long)]
+        offline: bool,
+    },
+
+    /// Write a JSON pack of inclusion proofs for the commitments listed in a
+    /// file, one per line, against a historical root. Doesn't touch the
+    /// running service.
+    ProveAll {
+        /// Path of the file listing the commitments.
+        commitments: PathBuf,
+
+        /// Root to prove against, the tree is rebuilt from the database as of
+        /// that root.
+        #[clap(
+            long,
+            required_unless_present = "snapshot",
+            conflicts_with = "snapshot"
+        )]
+        root: Option<Hash>,
+
+        /// Tree snapshot file to prove against, instead of the database.
+        #[clap(long)]
+        snapshot: Option<PathBuf>,
+
+        /// Path to write the pack to, instead of stdout.
+        #[clap(long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Replay the tree mutations and batch submissions recorded in the
+    /// database in memory, print the root after every step and exit non-zero
+    /// at the first step where it diverges from the recorded one. Doesn't
+    /// touch the running service.
+    Replay {
+        /// Only print the batches mined from this block on.
+        #[clap(long)]
+        from_block: Option<u64>,
+
+        /// Only print the batches mined up to this block. Batches which
+        /// weren't mined yet are printed without it.
+        #[clap(long)]
+        to_block: Option<u64>,
+    },
+
+    /// Replace the tree in the database with a tree snapshot whose root is
+    /// the latest root of the contract, e.g. after the database was
+    /// corrupted. Identities which weren't mined yet are discarded. Must only
+    /// be used while the sequencer is stopped, a running one is restored
+    /// through `/admin/restoreTree`.
+    RestoreTree {
+        /// Path of the tree snapshot file.
+        snapshot: PathBuf,
+    },
+
+    /// Verify the inclusion proofs of a pack written by `prove-all`, or a
+    /// single `/inclusionProof` response, print the outcome of every proof
+    /// and exit non-zero if any doesn't hold. Nothing but the file is read.
+    Verify {
+        /// Path of the proof file.
+        file: PathBuf,
+
+        /// Root to verify against, defaults to the root of the file.
+        #[clap(long)]
+        root: Option<Hash>,
+
+        /// Only verify the proof of this commitment. Required for single
+        /// proofs, which don't name their commitment.
+        #[clap(long)]
+        commitment: Option<Hash>,
+    },
 }
 
 /// ```
 /// assert!(true);
 /// ```
+#[cfg(feature = "sequencer")]
 #[allow(clippy::missing_errors_doc)]
-pub async fn main(options: Options) -> AnyhowResult<()> {
-    // Create App struct
-    let app = Arc::new(App::new(options.app).await?);
-    let app_for_server = app.clone();
+pub async fn main(mut options: Options) -> AnyhowResult<()> {
+    redaction::init(&options.redaction);
+
+    // Offline only the API keys are needed, the other secrets may be stored
+    // remotely.
+    if let Some(Command::ServeOffline { snapshot }) = &options.command {
+        let resolver = SecretResolver::new(&options.secrets).await?;
+        options.server.resolve_api_keys(&resolver).await?;
+        return serve_offline(options.server, snapshot).await;
+    }
+
+    if let Some(Command::Verify {
+        file,
+        root,
+        commitment,
+    }) = &options.command
+    {
+        return verify::run(file, *root, *commitment);
+    }
+
+    // The check command reports the problems along with its other checks.
+    if options.command != Some(Command::Check) {
+        config_validation::validate(&options)?;
+    }
+
+    options.resolve_secrets().await?;
+
+    match options.command.clone() {
+        Some(Command::Check) => return check::run(options).await,
+        Some(Command::Import { file, offline }) => {
+            return import::run(options, &file, offline).await;
+        }
+        Some(Command::ProveAll {
+            commitments,
+            root,
+            snapshot,
+            output,
+        }) => {
+            let source = match (root, &snapshot) {
+                (Some(root), _) => prove_all::Source::Root(root),
+                (None, Some(snapshot)) => prove_all::Source::Snapshot(snapshot),
+                (None, None) => unreachable!("clap requires a root or a snapshot"),
+            };
+            return prove_all::run(options, &commitments, source, output.as_deref()).await;
+        }
+        Some(Command::Replay {
+            from_block,
+            to_block,
+        }) => {
+            let range = replay::BlockRange {
+                from: from_block,
+                to:   to_block,
+            };
+            return replay::run(options, range).await;
+        }
+        Some(Command::RestoreTree { snapshot }) => {
+            return restore_tree::run(options, &snapshot).await;
+        }
+        Some(Command::ServeOffline { .. }) => unreachable!("served before resolving secrets"),
+        Some(Command::Verify { .. }) => unreachable!("verified before resolving secrets"),
+        Some(Command::Follow {
+            leader,
+            leader_api_key,
+        }) => return follower::run(options, leader, leader_api_key).await,
+        Some(Command::Serve) => return serve(options, true).await,
+        Some(Command::Process) => return process(options).await,
+        None => {}
+    }
+
+    serve(options, false).await
+}
+
+#[cfg(feature = "sequencer")]
+impl Options {
+    /// Replaces the `env:`, `file:` and `vault:` references of secret options
+    /// with the secrets they refer to.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` naming the option whose secret can't be resolved.
+    pub async fn resolve_secrets(&mut self) -> AnyhowResult<()> {
+        let resolver = SecretResolver::new(&self.secrets).await?;
+        self.server.resolve_api_keys(&resolver).await?;
+
+        if let Some(Command::Follow {
+            leader_api_key: Some(key),
+            ..
+        }) = &mut self.command
+        {
+            key.resolve(&resolver)
+                .await
+                .context("Failed to resolve --leader-api-key")?;
+        }
+
+        let app = &mut self.app;
+
+        app.database
+            .database
+            .resolve(&resolver)
+            .await
+            .context("Failed to resolve --database")?;
+
+        let ethereum = &mut app.ethereum;
+        ethereum
+            .ethereum_provider
+            .resolve(&resolver)
+            .await
+            .context("Failed to resolve --ethereum-provider")?;
+        for provider in &mut ethereum.secondary_providers.0 {
+            provider
+                .resolve(&resolver)
+                .await
+                .context("Failed to resolve --secondary-providers")?;
+        }
 
-    // Start server (will stop on shutdown signal)
-    server::main(app_for_server, options.server).await?;
+        let write_options = &mut ethereum.write_options;
+        write_options
+            .oz_api_key
+            .resolve(&resolver)
+            .await
+            .context("Failed to resolve --oz-api-key")?;
+        write_options
+            .oz_api_secret
+            .resolve(&resolver)
+            .await
+            .context("Failed to resolve --oz-api-secret")?;
 
-    info!("Stopping the app");
-    app.shutdown().await?;
+        if let Some(key) = &mut app.receipts.receipt_signing_key {
+            key.resolve(&resolver)
+                .await
+                .context("Failed to resolve --receipt-signing-key")?;
+        }
+
+        app.insertion_challenge
+            .captcha_secret
+            .resolve(&resolver)
+            .await
+            .context("Failed to resolve --captcha-secret")?;
+
+        app.insertion_screening
+            .screening_api_key
+            .resolve(&resolver)
+            .await
+            .context("Failed to resolve --screening-api-key")?;
+
+        for group in &mut app.groups.0 {
+            group
+                .database
+                .resolve(&resolver)
+                .await
+                .with_context(|| format!("Failed to resolve the database of group {}", group.id))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The state of the app of a tree before it has started.
+#[cfg(feature = "sequencer")]
+fn initial_state(options: &app::Options) -> AppState {
+    let state = AppState::default();
+    let Some(path) = &options.tree_snapshot_file else {
+        return state;
+    };
+
+    match TreeSnapshot::open(path) {
+        Ok(snapshot) => {
+            info!(
+                path,
+                leaf_count = snapshot.leaf_count(),
+                "Serving inclusion proofs from the tree snapshot until ready"
+            );
+            state.with_snapshot(snapshot)
+        }
+        Err(error) => {
+            warn!(path, ?error, "Failed to open the tree snapshot");
+            state
+        }
+    }
+}
+
+/// Builds the app of a tree and switches its handlers over to it.
+#[cfg(feature = "sequencer")]
+async fn start(
+    options: app::Options,
+    state: Arc<AppState>,
+    group: Option<String>,
+    api_only: bool,
+) -> AnyhowResult<Arc<App>> {
+    let mut builder = AppBuilder::new(options).with_progress(state.progress().clone());
+    if api_only {
+        builder = builder.api_only();
+    }
+
+    let app = Arc::new(builder.build().await?);
+    state.set_ready(app.clone());
+    info!(group = group.as_deref(), "App is ready");
+
+    Ok(app)
+}
+
+/// Serves the API, and also processes batches unless `api_only` is set.
+#[cfg(feature = "sequencer")]
+async fn serve(options: Options, api_only: bool) -> AnyhowResult<()> {
+    let group_options = options.app.group_options()?;
+
+    // Start alternate metrics sinks (will stop on shutdown signal)
+    let metrics_sinks = metrics::spawn_sinks(&options.metrics);
+
+    // Start server right away, requests are rejected until the apps are ready
+    // (will stop on shutdown signal)
+    let mut state = initial_state(&options.app);
+    let mut starts = vec![];
+    for (id, group_options) in group_options {
+        let group_state = Arc::new(initial_state(&group_options));
+        state = state.with_group(id.clone(), group_state.clone());
+        starts.push(start(group_options, group_state, Some(id), api_only));
+    }
+    let state = Arc::new(state);
+    starts.insert(0, start(options.app, state.clone(), None, api_only));
+
+    let queue_options = options.server.insertion_queue.clone();
+    let server = server::main(state, options.server);
+    tokio::pin!(server);
+
+    // Create App structs
+    let apps = select! {
+        apps = try_join_all(starts) => apps?,
+        result = &mut server => {
+            // Shut down before the apps finished starting
+            result?;
+            return Ok(());
+        }
+    };
+
+    // Insertions are added to the tree of the deployment. `serve` instances
+    // share the database with a `process` instance, which doesn't serve the
+    // API, so status transitions are only published by full sequencers.
+    let insertion_queue = server::insertion_queue::run(apps[0].clone(), queue_options, !api_only);
+    select! {
+        result = &mut server => result?,
+        result = insertion_queue => {
+            // Failing to consume insertions stops the sequencer
+            result?;
+            server.await?;
+        }
+    }
+
+    info!("Stopping the apps");
+    for app in apps {
+        app.shutdown().await?;
+    }
+
+    for sink in metrics_sinks {
+        sink.await?;
+    }
+
+    Ok(())
+}
+
+/// Serves the read-only routes from a tree snapshot, without starting an app.
+#[cfg(feature = "sequencer")]
+async fn serve_offline(options: server::Options, path: &Path) -> AnyhowResult<()> {
+    let snapshot = TreeSnapshot::open(path)
+        .with_context(|| format!("failed to open snapshot {}", path.display()))?;
+    let written_at = std::fs::metadata(path)?.modified()?.into();
+
+    warn!(
+        path = %path.display(),
+        root = %snapshot.root(),
+        leaf_count = snapshot.leaf_count(),
+        "Serving offline from a tree snapshot which isn't verified against the chain"
+    );
+
+    let state = Arc::new(AppState::offline(snapshot, written_at));
+    server::main(state, options).await
+}
+
+/// Processes batches without serving the API.
+#[cfg(feature = "sequencer")]
+async fn process(options: Options) -> AnyhowResult<()> {
+    let group_options = options.app.group_options()?;
+    let metrics_sinks = metrics::spawn_sinks(&options.metrics);
+
+    let builds = std::iter::once(options.app)
+        .chain(group_options.into_iter().map(|(_, options)| options))
+        .map(|options| AppBuilder::new(options).build());
+
+    let apps = select! {
+        apps = try_join_all(builds) => apps?,
+        () = await_shutdown() => {
+            // Shut down before the apps finished starting
+            return Ok(());
+        }
+    };
+    info!("App is processing identities");
+
+    await_shutdown().await;
+
+    info!("Stopping the apps");
+    for app in apps {
+        app.shutdown().await?;
+    }
+
+    for sink in metrics_sinks {
+        sink.await?;
+    }
 
     Ok(())
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "sequencer"))]
 pub mod test {
     use tracing::{error, warn};
     use tracing_test::traced_test;
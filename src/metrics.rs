@@ -0,0 +1,278 @@
+//! Alternate sinks for the metrics served on the Prometheus pull endpoint.
+//!
+//! Both sinks read from the default registry, so every metric registered
+//! through the `register_*!` macros is exported without further setup.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::{Context, Result as AnyhowResult};
+use async_trait::async_trait;
+use clap::Parser;
+use cli_batteries::await_shutdown;
+use prometheus::proto::{MetricFamily, MetricType};
+use prometheus::{Encoder, TextEncoder};
+use tokio::net::UdpSocket;
+use tokio::select;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+use url::Url;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// URL of a Prometheus pushgateway to periodically push metrics to.
+    #[clap(long, env)]
+    pub metrics_pushgateway_url: Option<Url>,
+
+    /// Job name metrics are grouped under on the pushgateway.
+    #[clap(long, env, default_value = "signup-sequencer")]
+    pub metrics_pushgateway_job: String,
+
+    /// Address of a StatsD (or Datadog) agent to periodically send metrics
+    /// to.
+    #[clap(long, env)]
+    pub metrics_statsd_addr: Option<SocketAddr>,
+
+    /// Prefix prepended to all metric names sent to StatsD.
+    #[clap(long, env, default_value = "signup_sequencer")]
+    pub metrics_statsd_prefix: String,
+
+    /// Interval at which metrics are pushed to the configured sinks (seconds).
+    #[clap(long, env, default_value = "15")]
+    pub metrics_push_interval: u64,
+}
+
+/// Starts a task for every configured sink. The tasks stop on shutdown.
+#[must_use]
+pub fn spawn_sinks(options: &Options) -> Vec<JoinHandle<()>> {
+    let interval = Duration::from_secs(options.metrics_push_interval);
+    let mut handles = vec![];
+
+    if let Some(url) = &options.metrics_pushgateway_url {
+        info!(%url, "Pushing metrics to pushgateway");
+
+        let sink = Pushgateway::new(url, &options.metrics_pushgateway_job);
+        handles.push(tokio::spawn(run_sink(interval, sink)));
+    }
+
+    if let Some(addr) = options.metrics_statsd_addr {
+        info!(%addr, "Sending metrics to StatsD");
+
+        let sink = StatsD::new(addr, options.metrics_statsd_prefix.clone());
+        handles.push(tokio::spawn(run_sink(interval, sink)));
+    }
+
+    handles
+}
+
+#[async_trait]
+trait Sink: Send {
+    async fn push(&mut self, families: &[MetricFamily]) -> AnyhowResult<()>;
+}
+
+async fn run_sink(interval: Duration, mut sink: impl Sink + 'static) {
+    let mut timer = tokio::time::interval(interval);
+
+    loop {
+        select! {
+            _ = timer.tick() => {}
+            _ = await_shutdown() => break,
+        }
+
+        if let Err(err) = sink.push(&prometheus::gather()).await {
+            warn!(?err, "Failed to push metrics.");
+        }
+    }
+
+    // Flush once more so the final values aren't lost.
+    if let Err(err) = sink.push(&prometheus::gather()).await {
+        error!(?err, "Failed to push metrics on shutdown.");
+    }
+}
+
+struct Pushgateway {
+    client: reqwest::Client,
+    url:    String,
+}
+
+impl Pushgateway {
+    fn new(url: &Url, job: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url:    format!("{}/metrics/job/{job}", url.as_str().trim_end_matches('/')),
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for Pushgateway {
+    async fn push(&mut self, families: &[MetricFamily]) -> AnyhowResult<()> {
+        let encoder = TextEncoder::new();
+        let mut body = vec![];
+        encoder.encode(families, &mut body)?;
+
+        // `PUT` replaces all metrics of the job, so metrics which are no longer
+        // reported don't linger on the gateway.
+        self.client
+            .put(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, encoder.format_type())
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+struct StatsD {
+    addr:     SocketAddr,
+    prefix:   String,
+    socket:   Option<UdpSocket>,
+    counters: HashMap<String, f64>,
+}
+
+impl StatsD {
+    fn new(addr: SocketAddr, prefix: String) -> Self {
+        Self {
+            addr,
+            prefix,
+            socket: None,
+            counters: HashMap::new(),
+        }
+    }
+
+    /// Formats the metric families as DogStatsD lines, with labels as tags.
+    ///
+    /// Prometheus counters are cumulative while StatsD counters are not, so
+    /// counters are sent as the difference to the previously sent value.
+    fn format(&mut self, families: &[MetricFamily]) -> Vec<String> {
+        let mut lines = vec![];
+
+        for family in families {
+            let name = format!("{}.{}", self.prefix, family.get_name());
+
+            for metric in family.get_metric() {
+                let mut tags = String::new();
+                for (i, label) in metric.get_label().iter().enumerate() {
+                    let separator = if i == 0 { "|#" } else { "," };
+                    let _ = write!(
+                        tags,
+                        "{separator}{}:{}",
+                        label.get_name(),
+                        label.get_value()
+                    );
+                }
+
+                match family.get_field_type() {
+                    MetricType::COUNTER => {
+                        let value = metric.get_counter().get_value();
+                        lines.push(self.counter_line(&name, &tags, value));
+                    }
+                    MetricType::GAUGE => {
+                        let value = metric.get_gauge().get_value();
+                        lines.push(format!("{name}:{value}|g{tags}"));
+                    }
+                    MetricType::HISTOGRAM => {
+                        let histogram = metric.get_histogram();
+                        lines.push(self.counter_line(
+                            &format!("{name}.sum"),
+                            &tags,
+                            histogram.get_sample_sum(),
+                        ));
+                        lines.push(self.counter_line(
+                            &format!("{name}.count"),
+                            &tags,
+                            histogram.get_sample_count() as f64,
+                        ));
+                    }
+                    MetricType::SUMMARY | MetricType::UNTYPED => {}
+                }
+            }
+        }
+
+        lines
+    }
+
+    fn counter_line(&mut self, name: &str, tags: &str, value: f64) -> String {
+        let previous = self
+            .counters
+            .insert(format!("{name}{tags}"), value)
+            .unwrap_or_default();
+
+        // A counter can only decrease if it was reset, in which case the whole
+        // value is new.
+        let delta = if value >= previous {
+            value - previous
+        } else {
+            value
+        };
+
+        format!("{name}:{delta}|c{tags}")
+    }
+}
+
+#[async_trait]
+impl Sink for StatsD {
+    async fn push(&mut self, families: &[MetricFamily]) -> AnyhowResult<()> {
+        if self.socket.is_none() {
+            let local_addr = if self.addr.is_ipv4() {
+                "0.0.0.0:0"
+            } else {
+                "[::]:0"
+            };
+            let socket = UdpSocket::bind(local_addr).await?;
+            socket
+                .connect(self.addr)
+                .await
+                .context("Connecting to StatsD agent")?;
+            self.socket = Some(socket);
+        }
+
+        let lines = self.format(families);
+        let socket = self.socket.as_ref().expect("Socket was just connected");
+
+        // One metric per datagram keeps us clear of the agent's packet size
+        // limit.
+        for line in lines {
+            socket.send(line.as_bytes()).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::{IntCounterVec, IntGauge, Opts, Registry};
+
+    use super::*;
+
+    #[test]
+    fn statsd_format() {
+        let registry = Registry::new();
+
+        let counter = IntCounterVec::new(Opts::new("requests", "Requests"), &["route"]).unwrap();
+        let gauge = IntGauge::new("pending", "Pending").unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+        registry.register(Box::new(gauge.clone())).unwrap();
+
+        let mut statsd = StatsD::new("127.0.0.1:8125".parse().unwrap(), "test".to_string());
+
+        counter.with_label_values(&["/insertIdentity"]).inc_by(3);
+        gauge.set(7);
+
+        let lines = statsd.format(&registry.gather());
+        assert!(lines.contains(&"test.pending:7|g".to_string()));
+        assert!(lines.contains(&"test.requests:3|c|#route:/insertIdentity".to_string()));
+
+        // Counters are sent as the increase since the last push
+        counter.with_label_values(&["/insertIdentity"]).inc_by(2);
+
+        let lines = statsd.format(&registry.gather());
+        assert!(lines.contains(&"test.requests:2|c|#route:/insertIdentity".to_string()));
+    }
+}
@@ -0,0 +1,219 @@
+//! Offline generation of inclusion proofs for many commitments against a
+//! historical root.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, ensure, Context, Result as AnyhowResult};
+use semaphore::poseidon_tree::Proof;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::database::Database;
+use crate::identity_tree::{Hash, TreeSnapshot};
+use crate::Options;
+
+/// Tree the proofs are generated against.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Source<'a> {
+    /// The tree as of the root, rebuilt from the database.
+    Root(Hash),
+    /// A snapshot file written by `--tree-snapshot-file`.
+    Snapshot(&'a Path),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProofPack {
+    pub root:    Hash,
+    pub proofs:  Vec<PackedProof>,
+    /// Commitments which aren't part of the tree.
+    pub missing: Vec<Hash>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackedProof {
+    pub identity_commitment: Hash,
+    pub leaf_index:          usize,
+    pub proof:               Proof,
+}
+
+/// Parses one commitment per line, in hex or decimal. Blank lines are
+/// skipped.
+fn parse_commitments(contents: &str) -> AnyhowResult<Vec<Hash>> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            Hash::from_str(line.trim())
+                .map_err(|error| anyhow!("line {}: invalid commitment: {error}", index + 1))
+        })
+        .collect()
+}
+
+fn build_pack(snapshot: &TreeSnapshot, commitments: Vec<Hash>) -> ProofPack {
+    let mut proofs = Vec::with_capacity(commitments.len());
+    let mut missing = Vec::new();
+    for commitment in commitments {
+        match snapshot.leaf_index(&commitment) {
+            Some(leaf_index) => proofs.push(PackedProof {
+                identity_commitment: commitment,
+                leaf_index,
+                proof: snapshot.proof(leaf_index),
+            }),
+            None => missing.push(commitment),
+        }
+    }
+
+    ProofPack {
+        root: snapshot.root(),
+        proofs,
+        missing,
+    }
+}
+
+/// Writes a JSON pack with the inclusion proof of every commitment listed in
+/// a file against the given tree, to `output` or to stdout.
+///
+/// Only reads from the database, so it can run next to the sequencer.
+///
+/// # Errors
+///
+/// Will return `Err` if a file can't be read or written, a commitment can't be
+/// parsed, or the root isn't known.
+pub async fn run(
+    options: Options,
+    commitments: &Path,
+    source: Source<'_>,
+    output: Option<&Path>,
+) -> AnyhowResult<()> {
+    let commitments = parse_commitments(&std::fs::read_to_string(commitments)?)?;
+
+    // Proofs against a root are read from a temporary snapshot of the tree as of
+    // that root.
+    let dir = tempfile::tempdir()?;
+    let snapshot = match source {
+        Source::Snapshot(path) => TreeSnapshot::open(path)
+            .with_context(|| format!("failed to open snapshot {}", path.display()))?,
+        Source::Root(root) => {
+            snapshot_at_root(options, &root, &dir.path().join("snapshot")).await?
+        }
+    };
+
+    let pack = build_pack(&snapshot, commitments);
+    info!(
+        root = %pack.root,
+        proofs = pack.proofs.len(),
+        missing = pack.missing.len(),
+        "Generated proof pack"
+    );
+
+    match output {
+        Some(path) => write_pack(&pack, File::create(path)?)?,
+        None => write_pack(&pack, io::stdout().lock())?,
+    }
+
+    Ok(())
+}
+
+/// Rebuilds the tree as of `root` from the database into a snapshot at
+/// `path`.
+async fn snapshot_at_root(
+    options: Options,
+    root: &Hash,
+    path: &Path,
+) -> AnyhowResult<TreeSnapshot> {
+    let tree_depth = options.app.contracts.tree_depth;
+    let initial_leaf_value = options.app.contracts.initial_leaf_value;
+
+    let database = Database::new(options.app.database, options.app.retry.policy()).await?;
+    let Some(root_id) = database.get_root_id(root).await? else {
+        bail!("root {root} is not in the database");
+    };
+
+    let mut leaves = Vec::new();
+    for update in database.get_tree_updates_until(root_id).await? {
+        if update.leaf_index >= leaves.len() {
+            leaves.resize(update.leaf_index + 1, initial_leaf_value);
+        }
+        leaves[update.leaf_index] = update.element;
+    }
+
+    TreeSnapshot::write(path, tree_depth, initial_leaf_value, leaves, None)?;
+
+    let snapshot = TreeSnapshot::open(path)?;
+    ensure!(
+        snapshot.root() == *root,
+        "tree rebuilt from the database has root {}, expected {root}",
+        snapshot.root()
+    );
+
+    Ok(snapshot)
+}
+
+fn write_pack(pack: &ProofPack, writer: impl Write) -> AnyhowResult<()> {
+    let mut writer = BufWriter::new(writer);
+    serde_json::to_writer(&mut writer, pack)?;
+    writeln!(writer)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use semaphore::poseidon_tree::LazyPoseidonTree;
+
+    use super::*;
+
+    #[test]
+    fn parses_commitments() {
+        let commitments = parse_commitments("0x1\n\n  2 \n").unwrap();
+        assert_eq!(commitments, vec![Hash::from(1), Hash::from(2)]);
+
+        let error = parse_commitments("0x1\nnot a number\n").unwrap_err();
+        assert!(error.to_string().starts_with("line 2: invalid commitment"));
+    }
+
+    #[test]
+    fn packs_proofs_from_snapshot() -> AnyhowResult<()> {
+        let depth = 4;
+        let leaves: Vec<Hash> = (1..=5_u64).map(Hash::from).collect();
+
+        let tree = leaves.iter().enumerate().fold(
+            LazyPoseidonTree::new(depth, Hash::ZERO),
+            |tree, (i, leaf)| tree.update(i, leaf),
+        );
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("snapshot");
+        TreeSnapshot::write(&path, depth, Hash::ZERO, leaves, None)?;
+        let snapshot = TreeSnapshot::open(&path)?;
+
+        let pack = build_pack(&snapshot, vec![
+            Hash::from(4),
+            Hash::from(42),
+            Hash::from(1),
+        ]);
+
+        assert_eq!(pack.root, tree.root());
+        assert_eq!(pack.proofs.len(), 2);
+        assert_eq!(pack.proofs[0].identity_commitment, Hash::from(4));
+        assert_eq!(pack.proofs[0].leaf_index, 3);
+        assert_eq!(pack.proofs[0].proof, tree.proof(3));
+        assert_eq!(pack.proofs[1].leaf_index, 0);
+        assert_eq!(pack.missing, vec![Hash::from(42)]);
+
+        let mut json = Vec::new();
+        write_pack(&pack, &mut json)?;
+        let json: serde_json::Value = serde_json::from_slice(&json)?;
+        assert_eq!(json["proofs"][0]["leafIndex"], 3);
+        assert!(json["proofs"][0]["identityCommitment"].is_string());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+//! Builds the input of the batch insertion circuit while a batch is formed:
+//! the commitments of the batch, padded to the batch size of the prover, with
+//! the Merkle path of every leaf they're inserted at and the roots before and
+//! after.
+
+use ethers::types::U256;
+use semaphore::poseidon_tree::{Branch, Proof};
+use serde::{Deserialize, Serialize};
+
+use crate::identity_tree::AppliedTreeUpdate;
+use crate::prover::compute_insertion_proof_input_hash;
+use crate::prover::identity::{Identity, MerkleProof};
+
+/// An insertion batch as proven and submitted on chain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InsertionBatch {
+    pub start_index: usize,
+    pub pre_root:    U256,
+    pub post_root:   U256,
+    /// The identities of the batch followed by the empty leaves padding it.
+    pub identities:  Vec<Identity>,
+}
+
+impl InsertionBatch {
+    /// Builds the batch of `updates`, consecutive insertions peeked from the
+    /// batching tree whose root is `pre_root`, padded with empty leaves up to
+    /// `batch_size`. The path of every leaf is taken from the version of the
+    /// tree its update results in, so that it already includes the leaves
+    /// before it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `updates` is empty or longer than `batch_size`.
+    #[must_use]
+    pub fn from_updates(pre_root: U256, updates: &[AppliedTreeUpdate], batch_size: usize) -> Self {
+        let last = updates.last().expect("Updates is non empty.");
+        assert!(
+            updates.len() <= batch_size,
+            "Batch of {} identities exceeds the batch size {batch_size}.",
+            updates.len()
+        );
+
+        let mut identities: Vec<Identity> = updates
+            .iter()
+            .map(|update| {
+                let proof = update.result.proof(update.update.leaf_index);
+                Identity::new(update.update.element.into(), merkle_path(&proof))
+            })
+            .collect();
+
+        // The prover only accepts batches of its size, the remaining leaves
+        // are inserted as zeros on top of the last update.
+        let padding_start = last.update.leaf_index + 1;
+        let padding_end = padding_start + batch_size - updates.len();
+        identities.extend((padding_start..padding_end).map(|leaf_index| {
+            let proof = last.result.proof(leaf_index);
+            Identity::new(U256::zero(), merkle_path(&proof))
+        }));
+
+        Self {
+            start_index: updates[0].update.leaf_index,
+            pre_root,
+            post_root: last.result.root().into(),
+            identities,
+        }
+    }
+
+    /// The commitments of the batch, including the padding.
+    #[must_use]
+    pub fn commitments(&self) -> Vec<U256> {
+        self.identities
+            .iter()
+            .map(|identity| identity.commitment)
+            .collect()
+    }
+}
+
+/// The input of the batch insertion circuit, as posted to the prover.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InsertionProofInput {
+    pub input_hash:           U256,
+    pub start_index:          u32,
+    pub pre_root:             U256,
+    pub post_root:            U256,
+    pub identity_commitments: Vec<U256>,
+    pub merkle_proofs:        Vec<MerkleProof>,
+}
+
+impl InsertionProofInput {
+    /// Builds the input proving the insertion of `identities` at
+    /// `start_index`, along with the hash of the public inputs the contract
+    /// verifies the proof against.
+    #[must_use]
+    pub fn new(start_index: u32, pre_root: U256, post_root: U256, identities: &[Identity]) -> Self {
+        let identity_commitments: Vec<U256> = identities
+            .iter()
+            .map(|identity| identity.commitment)
+            .collect();
+        let input_hash = compute_insertion_proof_input_hash(
+            start_index,
+            pre_root,
+            post_root,
+            &identity_commitments,
+        );
+
+        Self {
+            input_hash,
+            start_index,
+            pre_root,
+            post_root,
+            identity_commitments,
+            merkle_proofs: identities
+                .iter()
+                .map(|identity| identity.merkle_proof.clone())
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<&InsertionBatch> for InsertionProofInput {
+    type Error = std::num::TryFromIntError;
+
+    fn try_from(batch: &InsertionBatch) -> Result<Self, Self::Error> {
+        Ok(Self::new(
+            batch.start_index.try_into()?,
+            batch.pre_root,
+            batch.post_root,
+            &batch.identities,
+        ))
+    }
+}
+
+/// The siblings on the path from a leaf to the root, leaf first.
+fn merkle_path(proof: &Proof) -> MerkleProof {
+    proof
+        .0
+        .iter()
+        .map(|branch| match branch {
+            Branch::Left(sibling) | Branch::Right(sibling) => U256::from(*sibling),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use semaphore::poseidon_tree::LazyPoseidonTree;
+
+    use super::*;
+    use crate::identity_tree::{Hash, TreeUpdate};
+
+    /// The input of inserting 1, 2 and 3 into an empty tree of depth 10 with a
+    /// prover of batch size 3.
+    const FIXTURE: &str = include_str!("../../tests/fixtures/insertion_input.json");
+
+    fn updates(commitments: &[u64]) -> (U256, Vec<AppliedTreeUpdate>) {
+        let mut tree = LazyPoseidonTree::new(10, Hash::ZERO).derived();
+        let pre_root = tree.root().into();
+
+        let updates = commitments
+            .iter()
+            .enumerate()
+            .map(|(leaf_index, &commitment)| {
+                let element = Hash::from(commitment);
+                tree = tree.update(leaf_index, &element);
+                AppliedTreeUpdate {
+                    update: TreeUpdate::new(leaf_index, element),
+                    result: tree.clone(),
+                }
+            })
+            .collect();
+
+        (pre_root, updates)
+    }
+
+    #[test]
+    fn matches_the_fixture() {
+        let fixture: InsertionProofInput = serde_json::from_str(FIXTURE).unwrap();
+
+        let (pre_root, updates) = updates(&[1, 2, 3]);
+        let batch = InsertionBatch::from_updates(pre_root, &updates, 3);
+        let input = InsertionProofInput::try_from(&batch).unwrap();
+
+        assert_eq!(input, fixture);
+        assert_eq!(
+            serde_json::to_value(&input).unwrap(),
+            serde_json::from_str::<serde_json::Value>(FIXTURE).unwrap()
+        );
+    }
+
+    #[test]
+    fn pads_to_the_batch_size() {
+        let fixture: InsertionProofInput = serde_json::from_str(FIXTURE).unwrap();
+
+        let (pre_root, updates) = updates(&[1, 2, 3]);
+        let batch = InsertionBatch::from_updates(pre_root, &updates, 5);
+
+        assert_eq!(batch.start_index, 0);
+        assert_eq!(batch.post_root, fixture.post_root);
+        assert_eq!(batch.commitments()[..3], fixture.identity_commitments[..]);
+        assert_eq!(batch.commitments()[3..], [U256::zero(); 2]);
+
+        // Zeros don't change the root, so padding is proven against the tree
+        // with every identity of the batch.
+        let last = &updates[2].result;
+        for (leaf_index, identity) in batch.identities.iter().enumerate().skip(3) {
+            assert_eq!(identity.merkle_proof, merkle_path(&last.proof(leaf_index)));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds the batch size")]
+    fn rejects_batches_larger_than_the_prover() {
+        let (pre_root, updates) = updates(&[1, 2, 3]);
+        let _ = InsertionBatch::from_updates(pre_root, &updates, 2);
+    }
+}
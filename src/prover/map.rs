@@ -3,6 +3,7 @@ use std::collections::BTreeMap;
 use tokio::sync::{RwLock, RwLockReadGuard};
 
 use crate::prover::{Prover, ProverConfiguration, ProverType, Provers};
+use crate::utils::retry::RetryPolicy;
 
 /// The type of a map containing a mapping from a usize to a locked item.
 type SharedProverMap<P> = RwLock<ProverMap<P>>;
@@ -50,6 +51,11 @@ impl<P> ProverMap<P> {
         self.map.len()
     }
 
+    /// The batch sizes of the registered provers, ascending.
+    pub fn batch_sizes(&self) -> Vec<usize> {
+        self.map.keys().copied().collect()
+    }
+
     pub fn max_batch_size(&self) -> usize {
         self.map.iter().next_back().map_or(0, |(size, _)| *size)
     }
@@ -59,6 +65,13 @@ impl<P> ProverMap<P> {
     }
 }
 
+impl<P: Clone> ProverMap<P> {
+    /// Copies of the registered provers, by ascending batch size.
+    pub fn provers(&self) -> Vec<P> {
+        self.map.values().cloned().collect()
+    }
+}
+
 impl ProverMap<Prover> {
     pub fn as_configuration_vec(&self) -> Vec<ProverConfiguration> {
         self.map
@@ -68,6 +81,7 @@ impl ProverMap<Prover> {
                 timeout_s:   v.timeout_s(),
                 batch_size:  *k,
                 prover_type: v.prover_type(),
+                key_digest:  v.key_digest(),
             })
             .collect()
     }
@@ -90,18 +104,23 @@ pub type ReadOnlyInsertionProver<'a> = ReadOnlyProver<'a, Prover>;
 /// Builds an insertion prover map from the provided configuration.
 pub fn initialize_prover_maps(
     db_provers: Provers,
+    retry: RetryPolicy,
 ) -> anyhow::Result<(InsertionProverMap, DeletionProverMap)> {
     let mut insertion_map = BTreeMap::new();
     let mut deletion_map = BTreeMap::new();
 
     for prover in db_provers {
-        match prover.prover_type {
+        let batch_size = prover.batch_size;
+        let prover_type = prover.prover_type;
+        let prover = Prover::from_prover_conf(&prover)?.with_retry(retry);
+
+        match prover_type {
             ProverType::Insertion => {
-                insertion_map.insert(prover.batch_size, Prover::from_prover_conf(&prover)?);
+                insertion_map.insert(batch_size, prover);
             }
 
             ProverType::Deletion => {
-                deletion_map.insert(prover.batch_size, Prover::from_prover_conf(&prover)?);
+                deletion_map.insert(batch_size, prover);
             }
         }
     }
@@ -8,6 +8,7 @@
 //! `batch_insertion::Prover`, `batch_insertion::Identity` and so on).
 
 pub mod identity;
+pub mod insertion_input;
 pub mod map;
 pub mod proof;
 
@@ -18,22 +19,58 @@ use std::mem::size_of;
 use std::time::Duration;
 
 use clap::Parser;
-use ethers::types::U256;
+use ethers::types::{H256, U256};
 use ethers::utils::keccak256;
 pub use map::{InsertionProverMap, ProverMap, ReadOnlyProver};
 use once_cell::sync::Lazy;
 use prometheus::{exponential_buckets, register_histogram, Histogram};
 pub use proof::Proof;
+use reqwest::Response;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use url::Url;
 
 use crate::prover::identity::Identity;
+use crate::prover::insertion_input::InsertionProofInput;
 use crate::serde_utils::JsonStrWrapper;
 use crate::utils::index_packing::pack_indices;
+use crate::utils::retry::{RetryPolicy, Retryable};
 
 /// The endpoint used for proving operations.
 const MTB_PROVE_ENDPOINT: &str = "prove";
 
+/// The endpoint reporting the circuit a prover proves.
+const MTB_INFO_ENDPOINT: &str = "info";
+
+/// A prover which couldn't be reached, or kept failing with transient errors
+/// through all retries.
+#[derive(Debug, Error)]
+#[error("prover at {url} is unavailable")]
+pub struct ProverUnavailable {
+    pub url:    String,
+    #[source]
+    pub source: reqwest::Error,
+}
+
+/// A prover proving another circuit than the one it's configured for, e.g.
+/// because it was redeployed with new proving keys before the sequencer was
+/// told to expect them.
+#[derive(Debug, Error)]
+pub enum ProverKeyMismatch {
+    #[error("prover at {url} proves batches of {actual} identities, expected {expected}")]
+    BatchSize {
+        url:      String,
+        expected: usize,
+        actual:   usize,
+    },
+    #[error("prover at {url} serves proving key {actual:#x}, expected {expected:#x}")]
+    KeyDigest {
+        url:      String,
+        expected: H256,
+        actual:   H256,
+    },
+}
+
 static TOTAL_PROVING_TIME: Lazy<Histogram> = Lazy::new(|| {
     register_histogram!(
         "total_proving_time",
@@ -84,9 +121,52 @@ pub struct ProverConfiguration {
 
     // TODO: add docs
     pub prover_type: ProverType,
+
+    /// The digest of the proving key the prover is expected to serve, as
+    /// reported by its `info` endpoint. Batches aren't submitted while it
+    /// serves another key. Unchecked if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_digest: Option<H256>,
+}
+
+/// What a prover reports about the circuit it proves.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProverInfo {
+    pub batch_size: usize,
+    /// Digest of the proving key the prover loaded
+    pub key_digest: H256,
+}
+
+/// Whether a prover serves the proving key it's expected to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum KeyStatus {
+    Match,
+    Mismatch,
+    /// No key digest is configured for the prover
+    Unchecked,
+    /// The prover didn't report its proving key
+    Unknown,
+}
+
+/// The proving key of a prover compared with its configuration.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProverKeyReport {
+    pub url:                 String,
+    pub prover_type:         ProverType,
+    pub batch_size:          usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected_key_digest: Option<H256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub info:                Option<ProverInfo>,
+    pub status:              KeyStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error:               Option<String>,
 }
 
-#[derive(Debug, Copy, Clone, sqlx::Type, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[derive(Debug, Copy, Clone, sqlx::Type, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 #[sqlx(type_name = "prover_enum", rename_all = "PascalCase")]
 pub enum ProverType {
@@ -117,6 +197,8 @@ pub struct Prover {
     batch_size:  usize,
     timeout_s:   u64,
     prover_type: ProverType,
+    key_digest:  Option<H256>,
+    retry:       RetryPolicy,
 }
 
 impl Prover {
@@ -138,6 +220,8 @@ impl Prover {
             batch_size: options.batch_size,
             timeout_s: options.timeout_s,
             prover_type: options.prover_type,
+            key_digest: options.key_digest,
+            retry: RetryPolicy::default(),
         };
 
         Ok(mtb)
@@ -159,9 +243,18 @@ impl Prover {
             batch_size: prover_conf.batch_size,
             timeout_s: prover_conf.timeout_s,
             prover_type: prover_conf.prover_type,
+            key_digest: prover_conf.key_digest,
+            retry: RetryPolicy::default(),
         })
     }
 
+    /// Retries requests failing with transient errors according to `retry`.
+    #[must_use]
+    pub const fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
     pub fn batch_size(&self) -> usize {
         self.batch_size
     }
@@ -174,6 +267,92 @@ impl Prover {
         self.timeout_s
     }
 
+    pub fn key_digest(&self) -> Option<H256> {
+        self.key_digest
+    }
+
+    /// Asks the prover which circuit it proves.
+    pub async fn info(&self) -> anyhow::Result<ProverInfo> {
+        let url = self.target_url.join(MTB_INFO_ENDPOINT)?;
+
+        let info = self
+            .client
+            .get(url)
+            .timeout(Duration::from_secs(self.timeout_s))
+            .send()
+            .await
+            .and_then(Response::error_for_status)
+            .map_err(|source| self.request_error(source))?
+            .json()
+            .await?;
+
+        Ok(info)
+    }
+
+    /// Checks that the prover proves the configured batch size with the
+    /// configured proving key. Provers without a configured key digest aren't
+    /// asked.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` with a [`ProverKeyMismatch`] if the prover proves
+    /// another circuit, or if it can't be asked.
+    pub async fn verify_key(&self) -> anyhow::Result<()> {
+        let Some(expected) = self.key_digest else {
+            return Ok(());
+        };
+
+        let info = self.info().await?;
+        Ok(self.check_info(expected, &info)?)
+    }
+
+    /// Compares the proving key of the prover with its configuration.
+    pub async fn key_report(&self) -> ProverKeyReport {
+        let (info, error) = match self.info().await {
+            Ok(info) => (Some(info), None),
+            Err(error) => (None, Some(format!("{error:#}"))),
+        };
+
+        let status = match (self.key_digest, &info) {
+            (None, _) => KeyStatus::Unchecked,
+            (Some(_), None) => KeyStatus::Unknown,
+            (Some(expected), Some(info)) => match self.check_info(expected, info) {
+                Ok(()) => KeyStatus::Match,
+                Err(_) => KeyStatus::Mismatch,
+            },
+        };
+
+        ProverKeyReport {
+            url: self.url(),
+            prover_type: self.prover_type,
+            batch_size: self.batch_size,
+            expected_key_digest: self.key_digest,
+            info,
+            status,
+            error,
+        }
+    }
+
+    fn check_info(&self, expected: H256, info: &ProverInfo) -> Result<(), ProverKeyMismatch> {
+        if info.batch_size != self.batch_size {
+            return Err(ProverKeyMismatch::BatchSize {
+                url:      self.url(),
+                expected: self.batch_size,
+                actual:   info.batch_size,
+            });
+        }
+
+        if info.key_digest != expected {
+            return Err(ProverKeyMismatch::KeyDigest {
+                url: self.url(),
+                expected,
+                actual: info.key_digest,
+            });
+        }
+
+        Ok(())
+    }
+
     /// Generates a proof term for the provided identity insertions into the
     /// merkle tree.
     ///
@@ -201,37 +380,10 @@ impl Prover {
 
         let total_proving_time_timer = TOTAL_PROVING_TIME.start_timer();
 
-        let identity_commitments: Vec<U256> = identities.iter().map(|id| id.commitment).collect();
-        let input_hash = compute_insertion_proof_input_hash(
-            start_index,
-            pre_root,
-            post_root,
-            &identity_commitments,
-        );
-        let merkle_proofs = identities
-            .iter()
-            .map(|id| id.merkle_proof.clone())
-            .collect();
-
-        let proof_input = InsertionProofInput {
-            input_hash,
-            start_index,
-            pre_root,
-            post_root,
-            identity_commitments,
-            merkle_proofs,
-        };
-
-        let request = self
-            .client
-            .post(self.target_url.join(MTB_PROVE_ENDPOINT)?)
-            .body("OH MY GOD")
-            .json(&proof_input)
-            .build()?;
+        let proof_input = InsertionProofInput::new(start_index, pre_root, post_root, identities);
 
         let prover_proving_time_timer = PROVER_PROVING_TIME.start_timer();
-        let proof_term = self.client.execute(request).await?;
-        let proof_term = proof_term.error_for_status()?;
+        let proof_term = self.prove(&proof_input).await?;
         prover_proving_time_timer.observe_duration();
 
         let json = proof_term.text().await?;
@@ -278,16 +430,8 @@ impl Prover {
             merkle_proofs,
         };
 
-        let request = self
-            .client
-            .post(self.target_url.join(MTB_PROVE_ENDPOINT)?)
-            .body("OH MY GOD")
-            .json(&proof_input)
-            .build()?;
-
         let prover_proving_time_timer = PROVER_PROVING_TIME.start_timer();
-        let proof_term = self.client.execute(request).await?;
-        let proof_term = proof_term.error_for_status()?;
+        let proof_term = self.prove(&proof_input).await?;
         prover_proving_time_timer.observe_duration();
 
         let json = proof_term.text().await?;
@@ -302,6 +446,41 @@ impl Prover {
         Ok(proof)
     }
 
+    /// Sends `proof_input` to the prover. Proving is deterministic, so
+    /// requests failing with transient errors are sent again.
+    async fn prove(&self, proof_input: &(impl Serialize + Sync)) -> anyhow::Result<Response> {
+        let url = &self.target_url.join(MTB_PROVE_ENDPOINT)?;
+
+        let response = self
+            .retry
+            .retry("prover", move || async move {
+                self.client
+                    .post(url.clone())
+                    .body("OH MY GOD")
+                    .json(proof_input)
+                    .send()
+                    .await?
+                    .error_for_status()
+            })
+            .await
+            .map_err(|source| self.request_error(source))?;
+
+        Ok(response)
+    }
+
+    /// Requests which failed because the prover couldn't be reached are
+    /// reported as [`ProverUnavailable`].
+    fn request_error(&self, source: reqwest::Error) -> anyhow::Error {
+        if source.is_retryable() {
+            anyhow::Error::new(ProverUnavailable {
+                url: self.url(),
+                source,
+            })
+        } else {
+            source.into()
+        }
+    }
+
     pub fn url(&self) -> String {
         self.target_url.to_string()
     }
@@ -398,17 +577,6 @@ impl Display for ProverError {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct InsertionProofInput {
-    input_hash:           U256,
-    start_index:          u32,
-    pre_root:             U256,
-    post_root:            U256,
-    identity_commitments: Vec<U256>,
-    merkle_proofs:        Vec<Vec<U256>>,
-}
-
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct DeletionProofInput {
@@ -434,6 +602,7 @@ mod test {
             timeout_s:   30,
             batch_size:  3,
             prover_type: ProverType::Insertion,
+            key_digest:  None,
         };
         let mtb = Prover::new(&options).unwrap();
         let input_data = get_default_proof_input();
@@ -466,6 +635,7 @@ mod test {
             timeout_s:   30,
             batch_size:  3,
             prover_type: ProverType::Insertion,
+            key_digest:  None,
         };
         let mtb = Prover::new(&options).unwrap();
         let mut input_data = get_default_proof_input();
@@ -494,6 +664,7 @@ mod test {
             timeout_s:   30,
             batch_size:  10,
             prover_type: ProverType::Insertion,
+            key_digest:  None,
         };
         let mtb = Prover::new(&options).unwrap();
         let input_data = get_default_proof_input();
@@ -517,6 +688,45 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn provers_serving_another_key_are_refused() -> anyhow::Result<()> {
+        let mock_service = mock::Service::new("0.0.0.0:3004".into()).await?;
+
+        let prover = |key_digest| {
+            Prover::new(&ProverConfiguration {
+                url: "http://localhost:3004".into(),
+                timeout_s: 30,
+                batch_size: 3,
+                prover_type: ProverType::Insertion,
+                key_digest,
+            })
+        };
+        let unchecked = prover(None)?.verify_key().await;
+        let matching = prover(Some(mock::KEY_DIGEST))?.verify_key().await;
+        let mismatching = prover(Some(H256::repeat_byte(1)))?;
+        let refused = mismatching.verify_key().await;
+        let report = mismatching.key_report().await;
+
+        mock_service.stop();
+
+        assert!(unchecked.is_ok());
+        assert!(matching.is_ok());
+        assert!(matches!(
+            refused.unwrap_err().downcast_ref::<ProverKeyMismatch>(),
+            Some(ProverKeyMismatch::KeyDigest { actual, .. }) if *actual == mock::KEY_DIGEST
+        ));
+        assert_eq!(report.status, KeyStatus::Mismatch);
+        assert_eq!(
+            report.info,
+            Some(ProverInfo {
+                batch_size: 3,
+                key_digest: mock::KEY_DIGEST,
+            })
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn compute_input_hash_should_succeed() {
         let input = get_default_proof_input();
@@ -676,12 +886,15 @@ mod test {
 pub mod mock {
     use std::net::SocketAddr;
 
-    use axum::routing::post;
+    use axum::routing::{get, post};
     use axum::{Json, Router};
     use axum_server::Handle;
 
     use super::*;
 
+    /// The digest of the proving key the mock reports.
+    pub const KEY_DIGEST: H256 = H256::repeat_byte(0x6b);
+
     pub struct Service {
         server: Handle,
     }
@@ -710,7 +923,15 @@ pub mod mock {
                     }
                 }
             };
-            let app = Router::new().route("/prove", post(prove));
+            let info = || async {
+                Json(ProverInfo {
+                    batch_size: 3,
+                    key_digest: KEY_DIGEST,
+                })
+            };
+            let app = Router::new()
+                .route("/prove", post(prove))
+                .route("/info", get(info));
 
             let addr: SocketAddr = url.parse()?;
             let server = Handle::new();
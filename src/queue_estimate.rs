@@ -0,0 +1,195 @@
+//! Estimates when queued identities will be mined, from the insertions mined
+//! recently, so that clients can tell their users how long to wait.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::database::types::InsertionLatency;
+use crate::database::{Database, Error as DatabaseError};
+use crate::identity_tree::Hash;
+use crate::server::data::QueueEstimate;
+
+/// How long the latency of recent insertions is reused before it's queried
+/// again, so that busy instances don't query it for every insertion.
+const LATENCY_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Insertions mined within this many seconds are the basis of the queue
+    /// estimates returned to clients.
+    #[clap(long, env, default_value = "3600")]
+    pub queue_estimate_window_seconds: u64,
+}
+
+pub struct QueueEstimator {
+    database:      Arc<Database>,
+    window:        Duration,
+    batch_timeout: Duration,
+    latency:       Mutex<Option<(Instant, InsertionLatency)>>,
+}
+
+impl QueueEstimator {
+    /// Batches are assumed to be formed every `batch_timeout` until some are
+    /// mined.
+    #[must_use]
+    pub fn new(options: &Options, batch_timeout: Duration, database: Arc<Database>) -> Self {
+        Self {
+            database,
+            window: Duration::from_secs(options.queue_estimate_window_seconds.max(1)),
+            batch_timeout,
+            latency: Mutex::new(None),
+        }
+    }
+
+    /// Estimates when the identity, received at `received_at`, will be mined.
+    /// `None` if it isn't waiting to be mined.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database queries fail.
+    pub async fn estimate(
+        &self,
+        commitment: &Hash,
+        received_at: DateTime<Utc>,
+    ) -> Result<Option<QueueEstimate>, DatabaseError> {
+        let Some(position) = self.database.get_queue_position(commitment).await? else {
+            return Ok(None);
+        };
+
+        let latency = self.latency().await?;
+        let waited = (Utc::now() - received_at).to_std().unwrap_or_default();
+
+        Ok(Some(estimate(
+            position,
+            &latency,
+            self.window,
+            self.batch_timeout,
+            waited,
+        )))
+    }
+
+    async fn latency(&self) -> Result<InsertionLatency, DatabaseError> {
+        let mut cached = self.latency.lock().await;
+        if let Some((queried_at, latency)) = *cached {
+            if queried_at.elapsed() < LATENCY_REFRESH_INTERVAL {
+                return Ok(latency);
+            }
+        }
+
+        let since = Utc::now() - chrono::Duration::from_std(self.window).unwrap_or_default();
+        let latency = self.database.get_insertion_latency(since).await?;
+        *cached = Some((Instant::now(), latency));
+
+        Ok(latency)
+    }
+}
+
+/// Expects an identity to take the average latency of recent insertions, or,
+/// when the queue ahead of it is longer than usual, the time to mine the queue
+/// at the recent rate plus a batch of its own.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+fn estimate(
+    position: u64,
+    latency: &InsertionLatency,
+    window: Duration,
+    batch_timeout: Duration,
+    waited: Duration,
+) -> QueueEstimate {
+    let batch_interval = if latency.mined_batches == 0 {
+        batch_timeout
+    } else {
+        window.div_f64(latency.mined_batches as f64)
+    };
+
+    let estimated_seconds_to_mined = latency
+        .average_seconds
+        .filter(|_| latency.mined_identities > 0)
+        .map(|average| {
+            let rate = latency.mined_identities as f64 / window.as_secs_f64();
+            let queue = position as f64 / rate + batch_interval.as_secs_f64();
+            let remaining = average - waited.as_secs_f64();
+
+            remaining.max(queue).round() as u64
+        });
+
+    QueueEstimate {
+        queue_position: position,
+        batch_interval_seconds: batch_interval.as_secs(),
+        estimated_seconds_to_mined,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(3600);
+    const BATCH_TIMEOUT: Duration = Duration::from_secs(180);
+
+    fn latency(
+        mined_identities: u64,
+        mined_batches: u64,
+        average_seconds: f64,
+    ) -> InsertionLatency {
+        InsertionLatency {
+            mined_identities,
+            mined_batches,
+            average_seconds: Some(average_seconds),
+        }
+    }
+
+    #[test]
+    fn nothing_is_estimated_before_insertions_are_mined() {
+        let estimate = estimate(
+            5,
+            &InsertionLatency::default(),
+            WINDOW,
+            BATCH_TIMEOUT,
+            Duration::ZERO,
+        );
+
+        assert_eq!(estimate, QueueEstimate {
+            queue_position:             5,
+            batch_interval_seconds:     180,
+            estimated_seconds_to_mined: None,
+        });
+    }
+
+    #[test]
+    fn short_queues_take_the_average_latency() {
+        // 12 batches of 300 identities an hour, mined within 10 minutes.
+        let latency = latency(3600, 12, 600.0);
+
+        let fresh = estimate(10, &latency, WINDOW, BATCH_TIMEOUT, Duration::ZERO);
+        assert_eq!(fresh.batch_interval_seconds, 300);
+        assert_eq!(fresh.estimated_seconds_to_mined, Some(600));
+
+        let waited = estimate(
+            10,
+            &latency,
+            WINDOW,
+            BATCH_TIMEOUT,
+            Duration::from_secs(200),
+        );
+        assert_eq!(waited.estimated_seconds_to_mined, Some(400));
+    }
+
+    #[test]
+    fn long_queues_take_the_time_to_mine_them() {
+        // One identity a second, so the queue ahead takes an hour to mine.
+        let latency = latency(3600, 12, 600.0);
+
+        let estimate = estimate(3600, &latency, WINDOW, BATCH_TIMEOUT, Duration::ZERO);
+        assert_eq!(estimate.estimated_seconds_to_mined, Some(3600 + 300));
+    }
+}
@@ -0,0 +1,364 @@
+use std::mem::size_of;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{Context, Result as AnyhowResult};
+use chrono::{DateTime, Timelike, Utc};
+use clap::Parser;
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::{Address, Bytes, H256, U256};
+use ethers::utils::keccak256;
+use serde::{Deserialize, Serialize};
+
+use crate::ethereum::signing_log::{SigningLog, SigningPurpose};
+use crate::identity_tree::Hash;
+use crate::secret::Secret;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Private key used to sign identity insertion receipts. If not set,
+    /// receipts are returned unsigned.
+    #[clap(long, env)]
+    pub receipt_signing_key: Option<Secret>,
+}
+
+/// Statement by the sequencer that it accepted an identity commitment.
+///
+/// The leaf index, and the root the leaf is applied to, are only known once
+/// the identity leaves the queue, so receipts issued at insertion time leave
+/// them empty. Refetching the receipt afterwards returns it with both.
+///
+/// The sequence number orders accepted insertions. It's assigned when the
+/// receipt is stored and isn't covered by the signature.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InsertionReceipt {
+    pub identity_commitment: Hash,
+    pub leaf_index:          Option<u64>,
+    pub pre_root:            Option<Hash>,
+    pub timestamp:           DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence_number:     Option<u64>,
+    pub signer:              Option<Address>,
+    pub signature:           Option<Bytes>,
+}
+
+impl InsertionReceipt {
+    #[must_use]
+    pub fn new(
+        identity_commitment: Hash,
+        leaf_index: Option<u64>,
+        pre_root: Option<Hash>,
+        timestamp: DateTime<Utc>,
+    ) -> Self {
+        // The digest only covers whole seconds, so make sure the receipt
+        // doesn't carry more precision than what's actually signed.
+        let timestamp = timestamp.with_nanosecond(0).unwrap_or(timestamp);
+
+        Self {
+            identity_commitment,
+            leaf_index,
+            pre_root,
+            timestamp,
+            sequence_number: None,
+            signer: None,
+            signature: None,
+        }
+    }
+
+    /// Computes the digest signed by the sequencer.
+    ///
+    /// The digest is the `keccak256` hash of the receipt fields arranged as
+    /// follows:
+    ///
+    /// ```md
+    /// IdComm || LeafIndex || PreRoot || Timestamp
+    ///   256  ||    256    ||   256   ||    64     bits
+    /// ```
+    ///
+    /// where `LeafIndex` and `PreRoot` are all ones if no leaf has been
+    /// assigned yet and `Timestamp` is in seconds since the unix epoch. All
+    /// values are encoded in _big-endian_ byte ordering.
+    #[must_use]
+    pub fn digest(&self) -> H256 {
+        let leaf_index = self.leaf_index.map_or(U256::MAX, U256::from);
+        let pre_root = self.pre_root.map_or(U256::MAX, U256::from);
+
+        let mut bytes: Vec<u8> = vec![];
+        for value in [self.identity_commitment.into(), leaf_index, pre_root] {
+            let mut value_bytes: [u8; size_of::<U256>()] = Default::default();
+            value.to_big_endian(value_bytes.as_mut_slice());
+            bytes.extend(value_bytes.iter());
+        }
+        bytes.extend_from_slice(&(self.timestamp.timestamp() as u64).to_be_bytes());
+
+        keccak256(bytes).into()
+    }
+}
+
+/// Statement by the sequencer that an identity commitment is not part of the
+/// tree with the given root.
+///
+/// Identities are appended in insertion order rather than sorted by
+/// commitment, so there are no adjacent leaves that could prove absence from
+/// the tree itself. The sequencer's signature is all the verifier gets.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NonInclusionAttestation {
+    pub identity_commitment: Hash,
+    pub root:                Hash,
+    pub timestamp:           DateTime<Utc>,
+    pub signer:              Option<Address>,
+    pub signature:           Option<Bytes>,
+}
+
+impl NonInclusionAttestation {
+    #[must_use]
+    pub fn new(identity_commitment: Hash, root: Hash, timestamp: DateTime<Utc>) -> Self {
+        let timestamp = timestamp.with_nanosecond(0).unwrap_or(timestamp);
+
+        Self {
+            identity_commitment,
+            root,
+            timestamp,
+            signer: None,
+            signature: None,
+        }
+    }
+
+    /// Computes the digest signed by the sequencer.
+    ///
+    /// The digest is the `keccak256` hash of the attestation fields arranged
+    /// as follows:
+    ///
+    /// ```md
+    /// IdComm || Root || Timestamp
+    ///   256  || 256  ||    64     bits
+    /// ```
+    ///
+    /// The different length keeps these digests distinct from those of
+    /// [`InsertionReceipt`]s.
+    #[must_use]
+    pub fn digest(&self) -> H256 {
+        let values: [U256; 2] = [self.identity_commitment.into(), self.root.into()];
+
+        let mut bytes: Vec<u8> = vec![];
+        for value in values {
+            let mut value_bytes: [u8; size_of::<U256>()] = Default::default();
+            value.to_big_endian(value_bytes.as_mut_slice());
+            bytes.extend(value_bytes.iter());
+        }
+        bytes.extend_from_slice(&(self.timestamp.timestamp() as u64).to_be_bytes());
+
+        keccak256(bytes).into()
+    }
+}
+
+pub struct ReceiptSigner {
+    wallet:      Option<LocalWallet>,
+    chain_id:    u64,
+    signing_log: Arc<SigningLog>,
+}
+
+impl ReceiptSigner {
+    /// # Errors
+    ///
+    /// Will return `Err` if the signing key is not a valid private key.
+    pub fn new(
+        options: Options,
+        chain_id: u64,
+        signing_log: Arc<SigningLog>,
+    ) -> AnyhowResult<Self> {
+        let wallet = options
+            .receipt_signing_key
+            .map(|key| LocalWallet::from_str(key.expose()))
+            .transpose()
+            .context("Invalid --receipt-signing-key")?;
+
+        Ok(Self {
+            wallet,
+            chain_id,
+            signing_log,
+        })
+    }
+
+    /// Whether a signing key is configured.
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        self.wallet.is_some()
+    }
+
+    /// Signs the receipt digest, leaving the receipt unsigned if no signing
+    /// key is configured.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the wallet fails to sign the digest.
+    pub async fn sign(&self, mut receipt: InsertionReceipt) -> AnyhowResult<InsertionReceipt> {
+        if let Some((signer, signature)) = self
+            .sign_digest(SigningPurpose::InsertionReceipt, receipt.digest())
+            .await?
+        {
+            receipt.signer = Some(signer);
+            receipt.signature = Some(signature);
+        }
+
+        Ok(receipt)
+    }
+
+    /// Signs the attestation digest, leaving the attestation unsigned if no
+    /// signing key is configured.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the wallet fails to sign the digest.
+    pub async fn sign_non_inclusion(
+        &self,
+        mut attestation: NonInclusionAttestation,
+    ) -> AnyhowResult<NonInclusionAttestation> {
+        if let Some((signer, signature)) = self
+            .sign_digest(
+                SigningPurpose::NonInclusionAttestation,
+                attestation.digest(),
+            )
+            .await?
+        {
+            attestation.signer = Some(signer);
+            attestation.signature = Some(signature);
+        }
+
+        Ok(attestation)
+    }
+
+    /// Signs the digest, recording the operation in the [`SigningLog`]
+    /// whether or not it succeeds. Nothing is recorded without a signing key.
+    async fn sign_digest(
+        &self,
+        purpose: SigningPurpose,
+        digest: H256,
+    ) -> AnyhowResult<Option<(Address, Bytes)>> {
+        let Some(wallet) = &self.wallet else {
+            return Ok(None);
+        };
+
+        let signature = wallet.sign_hash(digest);
+        self.signing_log
+            .record_digest(
+                wallet.address(),
+                self.chain_id,
+                purpose,
+                digest,
+                signature.is_ok(),
+            )
+            .await;
+
+        Ok(Some((wallet.address(), signature?.to_vec().into())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::Signature;
+
+    use super::*;
+
+    const TEST_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+    fn test_receipt() -> InsertionReceipt {
+        InsertionReceipt::new(
+            Hash::from(42),
+            Some(7),
+            Some(Hash::from(1337)),
+            DateTime::from_str("2023-09-01T12:00:00.123Z").unwrap(),
+        )
+    }
+
+    #[test]
+    fn timestamp_is_truncated_to_seconds() {
+        let receipt = test_receipt();
+
+        assert_eq!(receipt.timestamp.timestamp_subsec_nanos(), 0);
+    }
+
+    #[test]
+    fn digest_depends_on_leaf_index() {
+        let receipt = test_receipt();
+        let mut unassigned = receipt.clone();
+        unassigned.leaf_index = None;
+
+        assert_ne!(receipt.digest(), unassigned.digest());
+    }
+
+    #[test]
+    fn digest_depends_on_pre_root() {
+        let receipt = test_receipt();
+        let mut unassigned = receipt.clone();
+        unassigned.pre_root = None;
+
+        assert_ne!(receipt.digest(), unassigned.digest());
+    }
+
+    #[tokio::test]
+    async fn unsigned_without_key() {
+        let signer = ReceiptSigner::new(
+            Options {
+                receipt_signing_key: None,
+            },
+            1,
+            Arc::default(),
+        )
+        .unwrap();
+
+        let receipt = signer.sign(test_receipt()).await.unwrap();
+
+        assert!(!signer.is_enabled());
+        assert!(receipt.signer.is_none());
+        assert!(receipt.signature.is_none());
+    }
+
+    #[tokio::test]
+    async fn signature_recovers_to_signer() {
+        let signer = ReceiptSigner::new(
+            Options {
+                receipt_signing_key: Some(TEST_KEY.parse().unwrap()),
+            },
+            1,
+            Arc::default(),
+        )
+        .unwrap();
+
+        let receipt = signer.sign(test_receipt()).await.unwrap();
+
+        let signature = Signature::try_from(receipt.signature.as_ref().unwrap().as_ref()).unwrap();
+        let recovered = signature.recover(receipt.digest()).unwrap();
+
+        assert_eq!(Some(recovered), receipt.signer);
+    }
+
+    #[tokio::test]
+    async fn non_inclusion_signature_recovers_to_signer() {
+        let signer = ReceiptSigner::new(
+            Options {
+                receipt_signing_key: Some(TEST_KEY.parse().unwrap()),
+            },
+            1,
+            Arc::default(),
+        )
+        .unwrap();
+
+        let attestation = NonInclusionAttestation::new(
+            Hash::from(42),
+            Hash::from(1337),
+            DateTime::from_str("2023-09-01T12:00:00.123Z").unwrap(),
+        );
+        let attestation = signer.sign_non_inclusion(attestation).await.unwrap();
+
+        let signature =
+            Signature::try_from(attestation.signature.as_ref().unwrap().as_ref()).unwrap();
+        let recovered = signature.recover(attestation.digest()).unwrap();
+
+        assert_eq!(Some(recovered), attestation.signer);
+        assert_ne!(attestation.digest(), test_receipt().digest());
+    }
+}
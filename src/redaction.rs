@@ -0,0 +1,293 @@
+//! Redaction of identity commitments in logs and audit logs, for deployments
+//! which must not persist them in the clear.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use clap::{Parser, ValueEnum};
+use ethers::utils::{hex, keccak256};
+use serde_json::Value;
+
+use crate::identity_tree::Hash;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Redaction {
+    /// Commitments are written as they are
+    #[default]
+    None,
+    /// Only the first and last four hex digits are kept, e.g. `0x1234…cdef`
+    Truncate,
+    /// Commitments are replaced by a prefix of their keccak256 hash, so that
+    /// the entries of a commitment can still be correlated
+    Hash,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// How identity commitments are written to logs, including the request
+    /// and response bodies of the API, and to the screening audit log.
+    #[clap(long, env, value_enum, default_value = "none")]
+    pub log_redaction: Redaction,
+}
+
+static REDACTION: OnceLock<Redaction> = OnceLock::new();
+
+/// Keys of JSON bodies whose values are commitments, or lists of them.
+const COMMITMENT_KEYS: [&str; 5] = [
+    "identityCommitment",
+    "identityCommitments",
+    "previousIdentityCommitment",
+    "newIdentityCommitment",
+    "commitment",
+];
+
+/// Hex digits from which a hex number in free text is taken for a commitment.
+/// Shorter numbers, e.g. leaf indices and status codes, are kept.
+const MIN_REDACTED_HEX_DIGITS: usize = 32;
+
+/// Sets the redaction of the process. Later calls have no effect.
+pub fn init(options: &Options) {
+    _ = REDACTION.set(options.log_redaction);
+}
+
+/// The redaction of the process, [`Redaction::None`] until [`init`] is called.
+#[must_use]
+pub fn current() -> Redaction {
+    REDACTION.get().copied().unwrap_or_default()
+}
+
+/// Formats a commitment for logs, e.g. `commitment = %redacted(&commitment)`.
+#[must_use]
+pub fn redacted(commitment: &Hash) -> Redacted<'_> {
+    Redacted(commitment)
+}
+
+/// A commitment displayed with the redaction of the process.
+pub struct Redacted<'a>(&'a Hash);
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&current().commitment(self.0))
+    }
+}
+
+impl fmt::Debug for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match current() {
+            Redaction::None => fmt::Debug::fmt(self.0, f),
+            _ => fmt::Display::fmt(self, f),
+        }
+    }
+}
+
+impl Redaction {
+    #[must_use]
+    pub fn commitment(self, commitment: &Hash) -> String {
+        match self {
+            Self::None => format!("{commitment:#x}"),
+            Self::Truncate => {
+                let digits = format!("{commitment:064x}");
+                format!("0x{}…{}", &digits[..4], &digits[60..])
+            }
+            Self::Hash => digest(&commitment.to_be_bytes::<32>()),
+        }
+    }
+
+    /// Redacts a commitment as written by a client, which may not parse.
+    fn value(self, value: &str) -> String {
+        if let Ok(commitment) = Hash::from_str(value) {
+            return self.commitment(&commitment);
+        }
+
+        match self {
+            Self::None => value.to_owned(),
+            Self::Truncate => value.chars().take(6).chain(['…']).collect(),
+            Self::Hash => digest(value.as_bytes()),
+        }
+    }
+
+    /// Redacts the commitments of a request or response body. Commitments
+    /// are looked up by key in JSON bodies, other bodies are redacted like
+    /// [`Self::text`].
+    #[must_use]
+    pub fn body(self, body: &str) -> Cow<'_, str> {
+        if self == Self::None {
+            return Cow::Borrowed(body);
+        }
+
+        match serde_json::from_str::<Value>(body) {
+            Ok(mut json) => {
+                self.json(&mut json, false);
+                Cow::Owned(json.to_string())
+            }
+            Err(_) => self.text(body),
+        }
+    }
+
+    fn json(self, json: &mut Value, is_commitment: bool) {
+        match json {
+            Value::String(value) if is_commitment => *value = self.value(value),
+            Value::Array(values) => {
+                for value in values {
+                    self.json(value, is_commitment);
+                }
+            }
+            Value::Object(fields) => {
+                for (key, value) in fields {
+                    self.json(value, COMMITMENT_KEYS.contains(&key.as_str()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Redacts the long hex numbers of free text, e.g. error messages, which
+    /// are taken for commitments. Roots and hashes are redacted along with
+    /// them.
+    #[must_use]
+    pub fn text(self, text: &str) -> Cow<'_, str> {
+        if self == Self::None {
+            return Cow::Borrowed(text);
+        }
+
+        let mut redacted = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("0x") {
+            let digits = rest[start + 2..]
+                .find(|c: char| !c.is_ascii_hexdigit())
+                .unwrap_or(rest.len() - start - 2);
+            let end = start + 2 + digits;
+
+            redacted.push_str(&rest[..start]);
+            if digits >= MIN_REDACTED_HEX_DIGITS {
+                redacted.push_str(&self.value(&rest[start..end]));
+            } else {
+                redacted.push_str(&rest[start..end]);
+            }
+            rest = &rest[end..];
+        }
+        redacted.push_str(rest);
+
+        Cow::Owned(redacted)
+    }
+
+    /// Redacts the commitment segments of a request path, e.g.
+    /// `/receipts/{commitment}`.
+    #[must_use]
+    pub fn path(self, path: &str) -> Cow<'_, str> {
+        if self == Self::None || !path.contains("/receipts/") {
+            return Cow::Borrowed(path);
+        }
+
+        let mut previous = "";
+        let segments: Vec<String> = path
+            .split('/')
+            .map(|segment| {
+                let redacted = if previous == "receipts" {
+                    self.value(segment)
+                } else {
+                    segment.to_owned()
+                };
+                previous = segment;
+                redacted
+            })
+            .collect();
+
+        Cow::Owned(segments.join("/"))
+    }
+
+    /// The bytes stored in audit logs in place of the commitment, `None` if
+    /// it's stored as is.
+    #[must_use]
+    pub fn audit_bytes(self, commitment: &Hash) -> Option<Vec<u8>> {
+        let bytes = commitment.to_be_bytes::<32>();
+        match self {
+            Self::None => None,
+            Self::Truncate => Some([&bytes[..2], &bytes[30..]].concat()),
+            Self::Hash => Some(keccak256(bytes).to_vec()),
+        }
+    }
+}
+
+/// The first 8 bytes of the keccak256 hash, enough to correlate log entries.
+fn digest(bytes: &[u8]) -> String {
+    format!("keccak:{}", hex::encode(&keccak256(bytes)[..8]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commitment() -> Hash {
+        "0x1234000000000000000000000000000000000000000000000000000000abcdef"
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn commitments_are_truncated_or_hashed() {
+        let commitment = commitment();
+
+        assert_eq!(
+            Redaction::None.commitment(&commitment),
+            format!("{commitment:#x}")
+        );
+        assert_eq!(Redaction::Truncate.commitment(&commitment), "0x1234…cdef");
+
+        let hashed = Redaction::Hash.commitment(&commitment);
+        assert!(hashed.starts_with("keccak:"));
+        assert_eq!(hashed.len(), "keccak:".len() + 16);
+        assert_eq!(Redaction::Hash.commitment(&commitment), hashed);
+    }
+
+    #[test]
+    fn commitments_are_redacted_from_json_bodies() {
+        let body = format!(
+            r#"{{"identityCommitment":"{:#x}","metadata":"kept","batch":[{{"commitment":"not hex"}}]}}"#,
+            commitment()
+        );
+
+        let redacted = Redaction::Truncate.body(&body);
+        let json: Value = serde_json::from_str(&redacted).unwrap();
+        assert_eq!(json["identityCommitment"], "0x1234…cdef");
+        assert_eq!(json["metadata"], "kept");
+        assert_eq!(json["batch"][0]["commitment"], "not he…");
+
+        assert_eq!(Redaction::None.body(&body), body);
+    }
+
+    #[test]
+    fn long_hex_numbers_are_redacted_from_text() {
+        let text = format!("leaf 0x2a ({:#066x}) reverted", commitment());
+
+        assert_eq!(
+            Redaction::Truncate.text(&text),
+            "leaf 0x2a (0x1234…cdef) reverted"
+        );
+        assert_eq!(
+            Redaction::Truncate.path(&format!("/v1/receipts/{:#x}", commitment())),
+            "/v1/receipts/0x1234…cdef"
+        );
+        assert_eq!(Redaction::Hash.path("/treeInfo"), "/treeInfo");
+    }
+
+    #[test]
+    fn audit_logs_store_digests() {
+        let commitment = commitment();
+
+        assert_eq!(Redaction::None.audit_bytes(&commitment), None);
+        assert_eq!(
+            Redaction::Truncate.audit_bytes(&commitment),
+            Some(vec![0x12, 0x34, 0xcd, 0xef])
+        );
+        assert_eq!(
+            Redaction::Hash
+                .audit_bytes(&commitment)
+                .map(|bytes| bytes.len()),
+            Some(32)
+        );
+    }
+}
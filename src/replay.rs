@@ -0,0 +1,356 @@
+//! Deterministic replay of the tree mutations and batch submissions recorded
+//! in the database, to find where the state diverged.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use anyhow::{bail, Result as AnyhowResult};
+use semaphore::poseidon_tree::LazyPoseidonTree;
+use tracing::info;
+
+use crate::database::types::{BatchSubmissionEntry, TreeUpdateEntry};
+use crate::database::Database;
+use crate::identity_tree::Hash;
+use crate::Options;
+
+/// Blocks whose batches are printed, both ends inclusive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BlockRange {
+    pub from: Option<u64>,
+    pub to:   Option<u64>,
+}
+
+impl BlockRange {
+    /// Batches which weren't mined yet are only part of open ended ranges.
+    fn contains(self, block: Option<u64>) -> bool {
+        match block {
+            Some(block) => {
+                self.from.map_or(true, |from| block >= from)
+                    && self.to.map_or(true, |to| block <= to)
+            }
+            None => self.to.is_none(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Step {
+    Update {
+        id:            usize,
+        leaf_index:    usize,
+        element:       Hash,
+        root:          Hash,
+        recorded_root: Hash,
+    },
+    Batch {
+        transaction_id: String,
+        pre_root:       Hash,
+        post_root:      Hash,
+        /// Root of the replayed tree before the updates of the batch
+        replayed_root:  Hash,
+        block:          Option<u64>,
+    },
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Update {
+                id,
+                leaf_index,
+                element,
+                root,
+                recorded_root,
+            } => {
+                write!(
+                    f,
+                    "row {id}: leaf {leaf_index} = {element:#x}, root {root:#x}"
+                )?;
+                if root != recorded_root {
+                    write!(f, ", DIVERGED from recorded root {recorded_root:#x}")?;
+                }
+                Ok(())
+            }
+            Self::Batch {
+                transaction_id,
+                pre_root,
+                post_root,
+                replayed_root,
+                block,
+            } => {
+                write!(f, "batch {transaction_id}: {pre_root:#x} -> {post_root:#x}")?;
+                match block {
+                    Some(block) => write!(f, ", mined in block {block}")?,
+                    None => write!(f, ", not mined")?,
+                }
+                if pre_root != replayed_root {
+                    write!(f, ", DIVERGED from replayed pre root {replayed_root:#x}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Replay {
+    steps:      Vec<Step>,
+    updates:    usize,
+    root:       Option<Hash>,
+    /// The first step at which the replayed state differs from the recorded
+    /// one.
+    divergence: Option<String>,
+}
+
+/// Applies the updates in the order they were written to an empty tree, and
+/// places every batch after the update which produced its post root. Stops at
+/// the first divergence, whose steps are kept even outside of the range.
+fn replay(
+    tree_depth: usize,
+    initial_leaf_value: Hash,
+    updates: Vec<TreeUpdateEntry>,
+    batches: Vec<BatchSubmissionEntry>,
+    range: BlockRange,
+) -> Replay {
+    let mut batches_by_post_root: HashMap<Hash, Vec<BatchSubmissionEntry>> = HashMap::new();
+    for batch in batches {
+        batches_by_post_root
+            .entry(batch.post_root)
+            .or_default()
+            .push(batch);
+    }
+
+    let mut tree = LazyPoseidonTree::new(tree_depth, initial_leaf_value);
+    let mut replay = Replay::default();
+    let mut batch_pre_root = tree.root();
+    let mut pending = vec![];
+
+    for entry in updates {
+        tree = tree.update(entry.update.leaf_index, &entry.update.element);
+        let root = tree.root();
+        replay.updates += 1;
+        replay.root = Some(root);
+
+        pending.push(Step::Update {
+            id: entry.id,
+            leaf_index: entry.update.leaf_index,
+            element: entry.update.element,
+            root,
+            recorded_root: entry.root,
+        });
+
+        if root != entry.root {
+            replay.steps.append(&mut pending);
+            replay.divergence = Some(format!("row {}", entry.id));
+            return replay;
+        }
+
+        let Some(batches) = batches_by_post_root.remove(&root) else {
+            continue;
+        };
+
+        // Resubmissions of a batch share its post root, the one which was
+        // mined determines the block.
+        let block = batches
+            .iter()
+            .find_map(|batch| batch.mined.as_ref().map(|mined| mined.block_number));
+        let diverged = batches
+            .iter()
+            .find(|batch| batch.pre_root != batch_pre_root)
+            .map(|batch| batch.transaction_id.clone());
+
+        if range.contains(block) || diverged.is_some() {
+            replay.steps.append(&mut pending);
+            replay
+                .steps
+                .extend(batches.into_iter().map(|batch| Step::Batch {
+                    transaction_id: batch.transaction_id,
+                    pre_root: batch.pre_root,
+                    post_root: batch.post_root,
+                    replayed_root: batch_pre_root,
+                    block,
+                }));
+        } else {
+            pending.clear();
+        }
+
+        if let Some(transaction_id) = diverged {
+            replay.divergence = Some(format!("batch {transaction_id}"));
+            return replay;
+        }
+
+        batch_pre_root = root;
+    }
+
+    // Updates which weren't submitted yet.
+    if range.contains(None) {
+        replay.steps.append(&mut pending);
+    }
+
+    replay
+}
+
+/// Replays the tree mutations and batch submissions recorded in the database
+/// in memory, printing the root after every step whose batch was mined within
+/// `range`.
+///
+/// Only reads from the database, so it can run next to the sequencer.
+///
+/// # Errors
+///
+/// Will return `Err` if the database can't be read, or the replayed state
+/// diverges from the recorded one.
+pub async fn run(options: Options, range: BlockRange) -> AnyhowResult<()> {
+    let tree_depth = options.app.contracts.tree_depth;
+    let initial_leaf_value = options.app.contracts.initial_leaf_value;
+
+    let database = Database::new(options.app.database, options.app.retry.policy()).await?;
+    let updates = database.get_tree_updates_after(0).await?;
+    let batches = database.get_batch_submissions().await?;
+    info!(
+        updates = updates.len(),
+        batches = batches.len(),
+        ?range,
+        "Replaying"
+    );
+
+    let replay = replay(tree_depth, initial_leaf_value, updates, batches, range);
+    for step in &replay.steps {
+        println!("{step}");
+    }
+
+    if let Some(divergence) = replay.divergence {
+        bail!("replay diverged at {divergence}");
+    }
+
+    match replay.root {
+        Some(root) => println!("replayed {} updates, root {root:#x}", replay.updates),
+        None => println!("nothing to replay"),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use ethers::types::H256;
+
+    use super::*;
+    use crate::identity_tree::{MinedTransaction, TreeUpdate};
+
+    const DEPTH: usize = 4;
+
+    fn updates(count: usize) -> Vec<TreeUpdateEntry> {
+        let mut tree = LazyPoseidonTree::new(DEPTH, Hash::ZERO);
+
+        (0..count)
+            .map(|i| {
+                let element = Hash::from(i + 1);
+                tree = tree.update(i, &element);
+
+                TreeUpdateEntry {
+                    id:     i + 1,
+                    update: TreeUpdate {
+                        leaf_index: i,
+                        element,
+                    },
+                    root:   tree.root(),
+                }
+            })
+            .collect()
+    }
+
+    fn batch(
+        id: &str,
+        pre_root: Hash,
+        post_root: Hash,
+        block: Option<u64>,
+    ) -> BatchSubmissionEntry {
+        BatchSubmissionEntry {
+            transaction_id: id.to_owned(),
+            pre_root,
+            post_root,
+            mined: block.map(|block_number| MinedTransaction {
+                transaction_hash: H256::zero(),
+                block_number,
+                block_timestamp: Utc::now(),
+            }),
+        }
+    }
+
+    fn rows(replay: &Replay) -> Vec<String> {
+        replay
+            .steps
+            .iter()
+            .map(|step| match step {
+                Step::Update { id, .. } => format!("row {id}"),
+                Step::Batch { transaction_id, .. } => format!("batch {transaction_id}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn replays_batches_within_range() {
+        let updates = updates(5);
+        let empty_root = LazyPoseidonTree::new(DEPTH, Hash::ZERO).root();
+        let batches = vec![
+            batch("tx-0", empty_root, updates[1].root, Some(10)),
+            batch("tx-1", updates[1].root, updates[3].root, Some(20)),
+        ];
+        let root = updates[4].root;
+
+        let replay = replay(DEPTH, Hash::ZERO, updates, batches, BlockRange {
+            from: Some(15),
+            to:   None,
+        });
+
+        assert_eq!(replay.divergence, None);
+        assert_eq!(replay.updates, 5);
+        assert_eq!(replay.root, Some(root));
+        assert_eq!(rows(&replay), vec!["row 3", "row 4", "batch tx-1", "row 5"]);
+    }
+
+    #[test]
+    fn closed_ranges_skip_unmined_updates() {
+        let updates = updates(3);
+        let empty_root = LazyPoseidonTree::new(DEPTH, Hash::ZERO).root();
+        let batches = vec![batch("tx-0", empty_root, updates[1].root, Some(10))];
+
+        let replay = replay(DEPTH, Hash::ZERO, updates, batches, BlockRange {
+            from: None,
+            to:   Some(10),
+        });
+
+        assert_eq!(rows(&replay), vec!["row 1", "row 2", "batch tx-0"]);
+    }
+
+    #[test]
+    fn stops_at_the_first_divergence() {
+        let mut updates = updates(4);
+        updates[2].update.element = Hash::from(42);
+
+        let replay = replay(DEPTH, Hash::ZERO, updates, vec![], BlockRange {
+            from: None,
+            to:   Some(0),
+        });
+
+        assert_eq!(replay.divergence.as_deref(), Some("row 3"));
+        assert_eq!(rows(&replay), vec!["row 1", "row 2", "row 3"]);
+        assert!(replay.steps[2].to_string().contains("DIVERGED"));
+    }
+
+    #[test]
+    fn detects_batches_built_on_another_root() {
+        let updates = updates(2);
+        let batches = vec![batch("tx-0", Hash::from(7), updates[1].root, Some(10))];
+
+        let replay = replay(DEPTH, Hash::ZERO, updates, batches, BlockRange {
+            from: Some(20),
+            to:   None,
+        });
+
+        assert_eq!(replay.divergence.as_deref(), Some("batch tx-0"));
+        assert_eq!(rows(&replay), vec!["row 1", "row 2", "batch tx-0"]);
+    }
+}
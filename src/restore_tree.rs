@@ -0,0 +1,80 @@
+//! Replacement of the tree in the database with a snapshot, to recover from a
+//! corrupted database while the sequencer is stopped. A running sequencer
+//! restores its tree through `/admin/restoreTree` instead.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{ensure, Result as AnyhowResult};
+use tracing::info;
+
+use crate::app::RESTORED_MESSAGE;
+use crate::contracts::flavor::ContractAdapter;
+use crate::database::Database;
+use crate::ethereum::ReadProvider;
+use crate::identity_tree::{Hash, TreeSnapshot, TreeState};
+use crate::Options;
+
+/// Replaces the identities in the database with the leaves of the snapshot,
+/// once it's verified to reproduce the latest root of the contract. The tree
+/// is rebuilt from the database on the next start.
+///
+/// # Errors
+///
+/// Will return `Err` if the snapshot can't be read or verified, or the
+/// database can't be written.
+pub async fn run(options: Options, snapshot_path: &Path) -> AnyhowResult<()> {
+    let contracts = &options.app.contracts;
+    let snapshot = TreeSnapshot::open(snapshot_path)?;
+    ensure!(
+        snapshot.depth() == contracts.tree_depth,
+        "snapshot of depth {}, the tree has depth {}",
+        snapshot.depth(),
+        contracts.tree_depth
+    );
+
+    // Rebuilding the tree checks that the leaves reproduce the root.
+    let temp_dir = tempfile::tempdir()?;
+    let mmap_file_path = temp_dir.path().join("restored");
+    TreeState::from_snapshot(
+        &snapshot,
+        contracts.initial_leaf_value,
+        options.app.dense_tree_prefix_depth,
+        options.app.tree_gc_threshold,
+        &mmap_file_path.to_string_lossy(),
+    )?;
+
+    let provider = ReadProvider::new(
+        options.app.ethereum.ethereum_provider.expose_url().clone(),
+        options.app.ethereum.circuit_breaker(),
+        options.app.retry.policy(),
+    )
+    .await?;
+    let contract = ContractAdapter::new(
+        contracts.contract_flavor,
+        contracts.identity_manager_address,
+        contracts.semaphore_group_id,
+        Arc::new(provider),
+    );
+    let contract_root: Hash = contract.latest_root().await?.into();
+    ensure!(
+        snapshot.root() == contract_root,
+        "the snapshot root {:#x} doesn't match the contract root {contract_root:#x}",
+        snapshot.root()
+    );
+
+    let database = Database::new(options.app.database, options.app.retry.policy()).await?;
+    let leaves = snapshot.leaves();
+    let discarded = database
+        .replace_tree(&leaves, &contract_root, RESTORED_MESSAGE)
+        .await?;
+
+    info!(
+        root = ?contract_root,
+        leaf_count = leaves.len(),
+        discarded,
+        "Restored the tree from a snapshot"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,278 @@
+//! The connections of the API server. Besides HTTP/1.1, clients may speak
+//! HTTP/2 without TLS (prior knowledge), multiplexing their requests over a
+//! single connection instead of opening one per request in flight.
+
+use std::future::poll_fn;
+use std::io;
+use std::net::TcpListener;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use anyhow::Result as AnyhowResult;
+use clap::Parser;
+use hyper::server::accept::{self, Accept};
+use hyper::server::conn::{AddrIncoming, AddrStream};
+use hyper::server::Builder;
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram, register_int_counter, register_int_gauge, Histogram, IntCounter, IntGauge,
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+const DEFAULT_HTTP2_MAX_CONCURRENT_STREAMS: u32 = 200;
+const DEFAULT_HTTP2_KEEP_ALIVE_INTERVAL_SECONDS: u64 = 20;
+const DEFAULT_HTTP2_KEEP_ALIVE_TIMEOUT_SECONDS: u64 = 20;
+const DEFAULT_TCP_KEEP_ALIVE_SECONDS: u64 = 60;
+
+static OPEN_CONNECTIONS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!("api_open_connections", "Connections currently open.").unwrap()
+});
+
+static ACCEPTED_CONNECTIONS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!("api_accepted_connections", "Connections accepted.").unwrap()
+});
+
+static DELAYED_CONNECTIONS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "api_delayed_connections",
+        "Connections which waited to be accepted as `--max-connections` were open."
+    )
+    .unwrap()
+});
+
+static CONNECTION_DURATION: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "api_connection_duration_seconds",
+        "How long connections were open in seconds.",
+        vec![0.1, 1.0, 10.0, 60.0, 300.0, 900.0, 3600.0]
+    )
+    .unwrap()
+});
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Maximum number of requests in flight on a single HTTP/2 connection
+    #[clap(long, env, default_value_t = DEFAULT_HTTP2_MAX_CONCURRENT_STREAMS)]
+    pub http2_max_concurrent_streams: u32,
+
+    /// How often idle HTTP/2 connections are pinged to keep them alive
+    /// (seconds), 0 to never ping them
+    #[clap(long, env, default_value_t = DEFAULT_HTTP2_KEEP_ALIVE_INTERVAL_SECONDS)]
+    pub http2_keep_alive_interval_seconds: u64,
+
+    /// How long to wait for the answer to a ping before closing the HTTP/2
+    /// connection (seconds)
+    #[clap(long, env, default_value_t = DEFAULT_HTTP2_KEEP_ALIVE_TIMEOUT_SECONDS)]
+    pub http2_keep_alive_timeout_seconds: u64,
+
+    /// How often TCP keep-alive probes are sent on idle connections
+    /// (seconds), 0 to disable them
+    #[clap(long, env, default_value_t = DEFAULT_TCP_KEEP_ALIVE_SECONDS)]
+    pub tcp_keep_alive_seconds: u64,
+
+    /// Maximum number of open connections, further connections are only
+    /// accepted once others were closed. 0 for no limit.
+    #[clap(long, env, default_value = "0")]
+    pub max_connections: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            http2_max_concurrent_streams:      DEFAULT_HTTP2_MAX_CONCURRENT_STREAMS,
+            http2_keep_alive_interval_seconds: DEFAULT_HTTP2_KEEP_ALIVE_INTERVAL_SECONDS,
+            http2_keep_alive_timeout_seconds:  DEFAULT_HTTP2_KEEP_ALIVE_TIMEOUT_SECONDS,
+            tcp_keep_alive_seconds:            DEFAULT_TCP_KEEP_ALIVE_SECONDS,
+            max_connections:                   0,
+        }
+    }
+}
+
+impl Options {
+    /// Builds a server accepting connections from `listener` as configured.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if `listener` can't be used with the runtime.
+    pub fn server(
+        &self,
+        listener: TcpListener,
+    ) -> AnyhowResult<Builder<impl Accept<Conn = Connection, Error = io::Error>>> {
+        listener.set_nonblocking(true)?;
+        let mut incoming =
+            AddrIncoming::from_listener(tokio::net::TcpListener::from_std(listener)?)?;
+        incoming.set_keepalive(nonzero_seconds(self.tcp_keep_alive_seconds));
+
+        let limit =
+            (self.max_connections > 0).then(|| Arc::new(Semaphore::new(self.max_connections)));
+        let connections = async_stream::stream! {
+            loop {
+                let permit = match &limit {
+                    Some(limit) => {
+                        if limit.available_permits() == 0 {
+                            DELAYED_CONNECTIONS.inc();
+                        }
+                        let permit = limit.clone().acquire_owned().await;
+                        Some(permit.expect("the semaphore is never closed"))
+                    }
+                    None => None,
+                };
+
+                match poll_fn(|cx| Pin::new(&mut incoming).poll_accept(cx)).await {
+                    Some(accepted) => yield accepted.map(|stream| Connection::new(stream, permit)),
+                    None => break,
+                }
+            }
+        };
+
+        Ok(hyper::Server::builder(accept::from_stream(connections))
+            .http2_max_concurrent_streams(self.http2_max_concurrent_streams)
+            .http2_keep_alive_interval(nonzero_seconds(self.http2_keep_alive_interval_seconds))
+            .http2_keep_alive_timeout(Duration::from_secs(self.http2_keep_alive_timeout_seconds)))
+    }
+}
+
+fn nonzero_seconds(seconds: u64) -> Option<Duration> {
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+/// An accepted connection, counted as open until it's dropped. It holds one
+/// of the `--max-connections` while open.
+pub struct Connection {
+    stream:    AddrStream,
+    opened_at: Instant,
+    _permit:   Option<OwnedSemaphorePermit>,
+}
+
+impl Connection {
+    fn new(stream: AddrStream, permit: Option<OwnedSemaphorePermit>) -> Self {
+        ACCEPTED_CONNECTIONS.inc();
+        OPEN_CONNECTIONS.inc();
+
+        Self {
+            stream,
+            opened_at: Instant::now(),
+            _permit: permit,
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        OPEN_CONNECTIONS.dec();
+        CONNECTION_DURATION.observe(self.opened_at.elapsed().as_secs_f64());
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use hyper::{Body, Request, Response};
+
+    use super::Options;
+
+    #[tokio::test]
+    async fn serves_http2_without_tls() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let service = hyper::service::make_service_fn(|_| async {
+            Ok::<_, hyper::Error>(hyper::service::service_fn(
+                |request: Request<Body>| async move {
+                    Ok::<_, hyper::Error>(Response::new(Body::from(format!(
+                        "{:?}",
+                        request.version()
+                    ))))
+                },
+            ))
+        });
+        let server = Options::default().server(listener).unwrap().serve(service);
+        tokio::spawn(server);
+
+        let client = reqwest::Client::builder()
+            .http2_prior_knowledge()
+            .build()
+            .unwrap();
+        let response = client.get(format!("http://{addr}/")).send().await.unwrap();
+
+        assert_eq!(response.version(), reqwest::Version::HTTP_2);
+        assert_eq!(response.text().await.unwrap(), "HTTP/2.0");
+    }
+
+    #[tokio::test]
+    async fn limits_open_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let service = hyper::service::make_service_fn(|_| async {
+            Ok::<_, hyper::Error>(hyper::service::service_fn(|_: Request<Body>| async {
+                Ok::<_, hyper::Error>(Response::new(Body::empty()))
+            }))
+        });
+        let options = Options {
+            max_connections: 1,
+            ..Options::default()
+        };
+        tokio::spawn(options.server(listener).unwrap().serve(service));
+
+        // The first client keeps its connection open, the second one's is
+        // only accepted once it's closed.
+        let first = reqwest::Client::new();
+        first.get(format!("http://{addr}/")).send().await.unwrap();
+
+        let second = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_millis(500))
+            .build()
+            .unwrap();
+        assert!(second.get(format!("http://{addr}/")).send().await.is_err());
+
+        drop(first);
+        let second = reqwest::Client::new();
+        assert!(second.get(format!("http://{addr}/")).send().await.is_ok());
+    }
+}
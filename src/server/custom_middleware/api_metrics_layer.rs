@@ -7,6 +7,9 @@ use prometheus::{
     IntCounterVec,
 };
 
+use crate::server::group;
+use crate::server::version::ApiVersion;
+
 static REQUESTS: Lazy<Counter> =
     Lazy::new(|| register_counter!(opts!("api_requests", "Number of requests received.")).unwrap());
 
@@ -19,6 +22,24 @@ static STATUS: Lazy<IntCounterVec> = Lazy::new(|| {
     .unwrap()
 });
 
+static VERSION_STATUS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "api_version_response_status",
+        "The API responses by version and status code, `unversioned` for unprefixed routes.",
+        &["version", "status_code"]
+    )
+    .unwrap()
+});
+
+static PROTOCOL: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "api_requests_by_protocol",
+        "The API requests by HTTP version, e.g. `HTTP/2.0`.",
+        &["protocol"]
+    )
+    .unwrap()
+});
+
 static LATENCY: Lazy<Histogram> = Lazy::new(|| {
     register_histogram!("api_latency_seconds", "The API latency in seconds.").unwrap()
 });
@@ -26,12 +47,21 @@ static LATENCY: Lazy<Histogram> = Lazy::new(|| {
 pub async fn middleware<B>(request: Request<B>, next: Next<B>) -> Result<Response, StatusCode> {
     let _timer = LATENCY.start_timer(); // Observes on drop
     REQUESTS.inc();
+    PROTOCOL
+        .with_label_values(&[&format!("{:?}", request.version())])
+        .inc();
+
+    let version = ApiVersion::from_path(group::ungrouped(request.uri().path()))
+        .map_or("unversioned", ApiVersion::label);
 
     let response = next.run(request).await;
 
     STATUS
         .with_label_values(&[response.status().as_str()])
         .inc();
+    VERSION_STATUS
+        .with_label_values(&[version, response.status().as_str()])
+        .inc();
 
     Ok(response)
 }
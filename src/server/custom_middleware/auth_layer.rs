@@ -0,0 +1,294 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use axum::extract::{MatchedPath, State};
+use axum::http::{HeaderMap, Method, Request};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::server::error::Error;
+use crate::server::group;
+use crate::server::version::ApiVersion;
+
+/// Header carrying the API key of a request.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// What an API key may access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scope {
+    /// Proofs and the status of individual commitments
+    ReadProofs,
+    /// Everything readable, including listings of queued commitments
+    ReadAll,
+    /// Inserting, deleting and recovering identities
+    Write,
+    /// Everything, including changing the batch sizes
+    Admin,
+}
+
+impl Scope {
+    /// Whether holding `self` grants access to endpoints requiring `required`.
+    #[must_use]
+    pub fn grants(self, required: Self) -> bool {
+        match self {
+            Self::Admin => true,
+            Self::ReadAll => matches!(required, Self::ReadAll | Self::ReadProofs),
+            Self::ReadProofs | Self::Write => self == required,
+        }
+    }
+
+    /// The scope required to access the route, `None` for public routes.
+    #[must_use]
+    pub fn required_for(endpoint: &str) -> Option<Self> {
+        match endpoint {
+            // Calls are authorized against the routes of their methods.
+            "/health" | "/version" | "/rpc" => None,
+            "/verifySemaphoreProof"
+            | "/inclusionProof"
+            | "/nonInclusionProof"
+            | "/rangeProof"
+            | "/treeInfo"
+            | "/checkRoot"
+            | "/identityHistory"
+            | "/identityStatus"
+            | "/receipts/:commitment" => Some(Self::ReadProofs),
+            "/listBatchSizes" | "/admin/queue" | "/treeNodes" | "/roots" | "/leaves"
+            | "/status" | "/sync/snapshot" | "/sync/updates" => Some(Self::ReadAll),
+            "/insertIdentity" | "/insertIdentities" | "/deleteIdentity" | "/recoverIdentity" => {
+                Some(Self::Write)
+            }
+            // Routes added without a scope are only accessible to admins.
+            _ => Some(Self::Admin),
+        }
+    }
+}
+
+/// Whether the route rewrites the tree or the queue. These are refused unless
+/// an admin key is configured, even when every other endpoint is public.
+fn is_destructive(method: &Method, endpoint: &str) -> bool {
+    match endpoint {
+        "/admin/restoreTree"
+        | "/admin/rollBackInsertions"
+        | "/admin/gcTreeVersions"
+        | "/admin/failedBatches/:post_root/retry" => true,
+        // Failed batches can be read, discarding them is destructive.
+        "/admin/failedBatches/:post_root" => *method == Method::DELETE,
+        _ => false,
+    }
+}
+
+/// The configured API keys and their scopes. Without any keys every endpoint
+/// is public, but the destructive admin ones, which are refused unless a key
+/// with the admin scope is configured.
+#[derive(Clone, Debug, Default)]
+pub struct ApiKeys {
+    keys: HashMap<String, HashSet<Scope>>,
+}
+
+impl ApiKeys {
+    #[must_use]
+    pub fn new(keys: HashMap<String, HashSet<Scope>>) -> Self {
+        Self { keys }
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if the endpoint is a destructive one and no admin key
+    /// is configured, or if keys are configured and the request doesn't carry
+    /// one, carries an unknown one, or one without a scope granting access to
+    /// the endpoint.
+    pub fn authorize(
+        &self,
+        headers: &HeaderMap,
+        method: &Method,
+        endpoint: &str,
+    ) -> Result<(), Error> {
+        let Some(required) = Scope::required_for(endpoint) else {
+            return Ok(());
+        };
+
+        if is_destructive(method, endpoint) && !self.has_admin_key() {
+            return Err(Error::AdminApiDisabled);
+        }
+
+        if self.keys.is_empty() {
+            return Ok(());
+        }
+
+        let key = headers
+            .get(API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .ok_or(Error::MissingApiKey)?;
+
+        let scopes = self.keys.get(key).ok_or(Error::InvalidApiKey)?;
+
+        if !scopes.iter().any(|scope| scope.grants(required)) {
+            return Err(Error::InsufficientScope);
+        }
+
+        Ok(())
+    }
+
+    fn has_admin_key(&self) -> bool {
+        self.keys
+            .values()
+            .any(|scopes| scopes.contains(&Scope::Admin))
+    }
+}
+
+/// Rejects requests whose API key doesn't grant access to the route.
+pub async fn middleware<B>(
+    State(api_keys): State<Arc<ApiKeys>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let endpoint = request.extensions().get::<MatchedPath>().map_or_else(
+        || request.uri().path().to_owned(),
+        |path| path.as_str().to_owned(),
+    );
+
+    let route = ApiVersion::unversioned(group::ungrouped(&endpoint));
+    if let Err(error) = api_keys.authorize(request.headers(), request.method(), route) {
+        warn!(endpoint, %error, "Rejected request");
+        return error.into_response();
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn api_keys() -> ApiKeys {
+        ApiKeys::new(HashMap::from([
+            ("proofs".to_owned(), HashSet::from([Scope::ReadProofs])),
+            (
+                "writer".to_owned(),
+                HashSet::from([Scope::ReadAll, Scope::Write]),
+            ),
+            ("admin".to_owned(), HashSet::from([Scope::Admin])),
+        ]))
+    }
+
+    fn headers(key: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(API_KEY_HEADER, HeaderValue::from_str(key).unwrap());
+        headers
+    }
+
+    #[test]
+    fn enforces_scopes() {
+        let api_keys = api_keys();
+
+        assert!(api_keys
+            .authorize(&headers("proofs"), &Method::POST, "/inclusionProof")
+            .is_ok());
+        assert!(matches!(
+            api_keys.authorize(&headers("proofs"), &Method::GET, "/admin/queue"),
+            Err(Error::InsufficientScope)
+        ));
+        assert!(matches!(
+            api_keys.authorize(&headers("proofs"), &Method::POST, "/insertIdentity"),
+            Err(Error::InsufficientScope)
+        ));
+
+        assert!(api_keys
+            .authorize(&headers("writer"), &Method::GET, "/admin/queue")
+            .is_ok());
+        assert!(api_keys
+            .authorize(&headers("writer"), &Method::POST, "/insertIdentity")
+            .is_ok());
+        assert!(matches!(
+            api_keys.authorize(&headers("writer"), &Method::POST, "/addBatchSize"),
+            Err(Error::InsufficientScope)
+        ));
+
+        assert!(api_keys
+            .authorize(&headers("admin"), &Method::POST, "/addBatchSize")
+            .is_ok());
+    }
+
+    #[test]
+    fn requires_known_key() {
+        let api_keys = api_keys();
+
+        assert!(api_keys
+            .authorize(&HeaderMap::new(), &Method::GET, "/health")
+            .is_ok());
+        assert!(api_keys
+            .authorize(&HeaderMap::new(), &Method::GET, "/version")
+            .is_ok());
+        assert!(matches!(
+            api_keys.authorize(&HeaderMap::new(), &Method::POST, "/inclusionProof"),
+            Err(Error::MissingApiKey)
+        ));
+        assert!(matches!(
+            api_keys.authorize(&headers("unknown"), &Method::POST, "/inclusionProof"),
+            Err(Error::InvalidApiKey)
+        ));
+    }
+
+    #[test]
+    fn without_keys_everything_but_destructive_endpoints_is_public() {
+        let api_keys = ApiKeys::default();
+
+        for (method, endpoint) in [
+            (Method::GET, "/admin/queue"),
+            (Method::GET, "/admin/costs"),
+            (Method::POST, "/insertIdentity"),
+            (Method::POST, "/addBatchSize"),
+            (Method::POST, "/removeBatchSize"),
+            (Method::GET, "/admin/failedBatches/:post_root"),
+        ] {
+            assert!(api_keys
+                .authorize(&HeaderMap::new(), &method, endpoint)
+                .is_ok());
+        }
+
+        for (method, endpoint) in [
+            (Method::POST, "/admin/restoreTree"),
+            (Method::POST, "/admin/rollBackInsertions"),
+            (Method::POST, "/admin/gcTreeVersions"),
+            (Method::DELETE, "/admin/failedBatches/:post_root"),
+            (Method::POST, "/admin/failedBatches/:post_root/retry"),
+        ] {
+            assert!(matches!(
+                api_keys.authorize(&HeaderMap::new(), &method, endpoint),
+                Err(Error::AdminApiDisabled)
+            ));
+        }
+    }
+
+    #[test]
+    fn destructive_endpoints_require_an_admin_key() {
+        let api_keys = ApiKeys::new(HashMap::from([(
+            "writer".to_owned(),
+            HashSet::from([Scope::ReadAll, Scope::Write]),
+        )]));
+
+        assert!(matches!(
+            api_keys.authorize(
+                &headers("writer"),
+                &Method::POST,
+                "/admin/rollBackInsertions"
+            ),
+            Err(Error::AdminApiDisabled)
+        ));
+        assert!(api_keys
+            .authorize(
+                &headers("admin"),
+                &Method::POST,
+                "/admin/rollBackInsertions"
+            )
+            .is_err());
+        assert!(api_keys
+            .authorize(&headers("writer"), &Method::POST, "/insertIdentity")
+            .is_ok());
+    }
+}
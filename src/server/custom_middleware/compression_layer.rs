@@ -0,0 +1,288 @@
+use std::io::Write;
+
+use axum::body::HttpBody;
+use axum::http::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, ETAG, VARY};
+use axum::http::{HeaderMap, HeaderValue, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use hyper::Body;
+use tracing::error;
+
+use crate::server::{etag, negotiation};
+
+/// Responses smaller than this aren't worth the overhead of compressing.
+const MIN_COMPRESSED_SIZE: usize = 1024;
+
+/// Responses from this size on, e.g. of `/leaves` and `/roots`, are compressed
+/// on the blocking pool, so that they don't hold up the runtime.
+const MIN_BLOCKING_SIZE: usize = 64 * 1024;
+
+/// The content codings responses are compressed with, in order of preference.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Coding {
+    Brotli,
+    Gzip,
+}
+
+impl Coding {
+    /// The preferred coding the client accepts, if any.
+    fn negotiate(headers: &HeaderMap) -> Option<Self> {
+        [Self::Brotli, Self::Gzip]
+            .into_iter()
+            .find(|coding| accepts_encoding(headers, coding.content_coding()))
+    }
+
+    const fn content_coding(self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Gzip => "gzip",
+        }
+    }
+
+    fn compress(self, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Brotli => brotli(body),
+            Self::Gzip => gzip(body),
+        }
+    }
+}
+
+/// Compresses successful responses with Brotli or gzip when the client
+/// accepts either.
+pub async fn middleware<B>(request: Request<B>, next: Next<B>) -> Response {
+    let coding = Coding::negotiate(request.headers());
+
+    let mut response = next.run(request).await;
+
+    if response.status() != StatusCode::OK || response.headers().contains_key(CONTENT_ENCODING) {
+        return response;
+    }
+
+    // Caches must not serve the compressed body to clients without the coding.
+    response
+        .headers_mut()
+        .append(VARY, HeaderValue::from_static("accept-encoding"));
+
+    // Streamed bodies, whose size isn't known upfront, would have to be
+    // buffered in full.
+    let Some(coding) = coding else {
+        return response;
+    };
+    if response.body().size_hint().exact().is_none() {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(error) => {
+            error!(?error, "Error reading response body");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if body.len() < MIN_COMPRESSED_SIZE {
+        return Response::from_parts(parts, axum::body::boxed(Body::from(body)));
+    }
+
+    let compressed = if body.len() < MIN_BLOCKING_SIZE {
+        coding.compress(&body)
+    } else {
+        let body = body.clone();
+        tokio::task::spawn_blocking(move || coding.compress(&body))
+            .await
+            .unwrap_or_else(|error| Err(std::io::Error::new(std::io::ErrorKind::Other, error)))
+    };
+    let compressed = match compressed {
+        Ok(compressed) => compressed,
+        Err(error) => {
+            error!(?error, "Error compressing response body");
+            return Response::from_parts(parts, axum::body::boxed(Body::from(body)));
+        }
+    };
+
+    parts.headers.insert(
+        CONTENT_ENCODING,
+        HeaderValue::from_static(coding.content_coding()),
+    );
+    parts.headers.remove(CONTENT_LENGTH);
+    // The compressed bytes differ from the ones the strong tag was computed
+    // for, they're only semantically equivalent.
+    if let Some(tag) = parts.headers.get(ETAG) {
+        let tag = etag::weaken(tag);
+        parts.headers.insert(ETAG, tag);
+    }
+
+    Response::from_parts(parts, axum::body::boxed(Body::from(compressed)))
+}
+
+/// Whether `Accept-Encoding` lists the content coding, or any coding, with a
+/// non-zero quality.
+pub fn accepts_encoding(headers: &HeaderMap, content_coding: &str) -> bool {
+    negotiation::accepted(headers, ACCEPT_ENCODING)
+        .any(|coding| coding.eq_ignore_ascii_case(content_coding) || coding == "*")
+}
+
+/// Encodes the chunks of a streamed response, which this layer leaves
+/// uncompressed, with zstd if the client accepts it. Every chunk is flushed so
+/// that the client can decode it as soon as it arrives.
+pub enum StreamEncoder {
+    Identity,
+    Zstd(zstd::Encoder<'static, Vec<u8>>),
+}
+
+impl StreamEncoder {
+    #[must_use]
+    pub fn negotiate(headers: &HeaderMap, zstd_level: i32) -> Self {
+        if !accepts_encoding(headers, "zstd") {
+            return Self::Identity;
+        }
+
+        match zstd::Encoder::new(Vec::new(), zstd_level) {
+            Ok(encoder) => Self::Zstd(encoder),
+            Err(error) => {
+                error!(?error, zstd_level, "Error creating zstd encoder");
+                Self::Identity
+            }
+        }
+    }
+
+    /// The `Content-Encoding` of the encoded chunks, if any.
+    #[must_use]
+    pub const fn content_encoding(&self) -> Option<&'static str> {
+        match self {
+            Self::Identity => None,
+            Self::Zstd(_) => Some("zstd"),
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if the chunk can't be compressed.
+    pub fn encode(&mut self, chunk: String) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Identity => Ok(chunk.into_bytes()),
+            Self::Zstd(encoder) => {
+                encoder.write_all(chunk.as_bytes())?;
+                encoder.flush()?;
+                Ok(std::mem::take(encoder.get_mut()))
+            }
+        }
+    }
+
+    /// Returns the end of the encoded stream.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the stream can't be completed.
+    pub fn finish(self) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Identity => Ok(vec![]),
+            Self::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+fn gzip(body: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::with_capacity(body.len() / 2), Compression::fast());
+    encoder.write_all(body)?;
+    encoder.finish()
+}
+
+fn brotli(body: &[u8]) -> std::io::Result<Vec<u8>> {
+    // Quality 4 compresses about as fast as gzip does at its fastest, and
+    // smaller.
+    let mut encoder =
+        brotli::CompressorWriter::new(Vec::with_capacity(body.len() / 2), 4096, 4, 22);
+    encoder.write_all(body)?;
+    encoder.flush()?;
+    Ok(encoder.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    fn headers(accept_encoding: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            ACCEPT_ENCODING,
+            HeaderValue::from_str(accept_encoding).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn negotiates_brotli_or_gzip() {
+        let coding = |accept_encoding| Coding::negotiate(&headers(accept_encoding));
+
+        assert_eq!(coding("gzip"), Some(Coding::Gzip));
+        assert_eq!(coding("br;q=1.0, gzip;q=0.8"), Some(Coding::Brotli));
+        assert_eq!(coding("br;q=0, gzip"), Some(Coding::Gzip));
+        assert_eq!(coding("*"), Some(Coding::Brotli));
+        assert_eq!(coding("gzip;q=0"), None);
+        assert_eq!(coding("deflate"), None);
+        assert_eq!(Coding::negotiate(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn streams_zstd_when_accepted() {
+        let encoder = StreamEncoder::negotiate(&headers("gzip"), 3);
+        assert_eq!(encoder.content_encoding(), None);
+
+        let mut encoder = StreamEncoder::negotiate(&headers("gzip, zstd"), 3);
+        assert_eq!(encoder.content_encoding(), Some("zstd"));
+
+        let chunk = "{\"level\":0,\"index\":0}\n".repeat(100);
+        let mut stream = encoder.encode(chunk.clone()).unwrap();
+
+        // Flushed chunks decode on their own.
+        let mut decoder = zstd::Decoder::new(stream.as_slice()).unwrap();
+        let mut decoded = vec![0; chunk.len()];
+        decoder.read_exact(&mut decoded).unwrap();
+        assert_eq!(decoded, chunk.as_bytes());
+
+        stream.extend(encoder.encode(chunk.clone()).unwrap());
+        stream.extend(encoder.finish().unwrap());
+        assert!(stream.len() < chunk.len());
+        assert_eq!(
+            zstd::decode_all(stream.as_slice()).unwrap(),
+            chunk.repeat(2).as_bytes()
+        );
+    }
+
+    #[test]
+    fn gzip_round_trips() {
+        let body = b"{\"proof\":[]}".repeat(200);
+
+        let compressed = gzip(&body).unwrap();
+        assert!(compressed.len() < body.len());
+
+        let mut decompressed = vec![];
+        GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, body);
+    }
+
+    #[test]
+    fn brotli_round_trips() {
+        let body = b"{\"proof\":[]}".repeat(200);
+
+        let compressed = brotli(&body).unwrap();
+        assert!(compressed.len() < body.len());
+
+        let mut decompressed = vec![];
+        brotli::Decompressor::new(compressed.as_slice(), 4096)
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, body);
+    }
+}
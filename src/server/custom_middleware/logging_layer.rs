@@ -1,5 +1,6 @@
 #![allow(clippy::cast_possible_truncation)]
 
+use axum::http::header::CONTENT_TYPE;
 use axum::http::{Request, StatusCode};
 use axum::middleware::Next;
 use axum::response::Response;
@@ -8,21 +9,31 @@ use hyper::body::HttpBody;
 use hyper::{Body, Method};
 use tracing::{error, info, info_span, warn, Instrument};
 
+use crate::redaction;
+
 // 1 MiB
 const MAX_REQUEST_BODY_SIZE: u64 = 1024 * 1024;
 
-pub async fn middleware<B>(request: Request<B>, next: Next<Body>) -> Result<Response, StatusCode>
-where
-    B: HttpBody,
-    <B as HttpBody>::Error: std::error::Error,
-{
+pub async fn middleware(request: Request<Body>, next: Next<Body>) -> Result<Response, StatusCode> {
     let (parts, body) = request.into_parts();
 
-    let uri_path = parts.uri.path().to_string();
+    // Only the logged path and query are redacted, routing uses the request.
+    let redaction = redaction::current();
+    let uri_path = redaction.path(parts.uri.path()).into_owned();
     let request_method = parts.method.clone();
-    let request_query = parts.uri.query().map(ToString::to_string);
-
-    if let Method::GET = request_method {
+    let request_query = parts
+        .uri
+        .query()
+        .map(|query| redaction.text(query).into_owned());
+
+    // Streamed bodies are passed on as they arrive, without being logged.
+    let is_streamed = parts
+        .headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-ndjson"));
+
+    if request_method == Method::GET || is_streamed {
         let span = info_span!("request", ?uri_path, ?request_method, ?request_query);
 
         async {
@@ -35,7 +46,7 @@ where
                 "Processing request"
             );
 
-            let body = Body::empty();
+            let body = if is_streamed { body } else { Body::empty() };
             let request = Request::from_parts(parts, body);
 
             let response = next.run(request).await;
@@ -57,7 +68,15 @@ where
     } else {
         let body = body_to_string(body).await?;
 
-        let span = info_span!("request", ?uri_path, ?request_method, ?request_query, ?body);
+        let logged_body = redaction.body(&body).into_owned();
+
+        let span = info_span!(
+            "request",
+            ?uri_path,
+            ?request_method,
+            ?request_query,
+            body = ?logged_body
+        );
 
         async {
             cli_batteries::trace_from_headers(&parts.headers);
@@ -66,7 +85,7 @@ where
                 ?uri_path,
                 ?request_method,
                 ?request_query,
-                ?body,
+                body = ?logged_body,
                 "Processing request"
             );
 
@@ -104,6 +123,7 @@ async fn handle_response(
 
     let response = if response_status.is_client_error() || response_status.is_server_error() {
         let response_body = body_to_string(body).await?;
+        let logged_body = redaction::current().body(&response_body);
 
         if response_status.is_client_error() {
             warn!(
@@ -111,7 +131,7 @@ async fn handle_response(
                 ?request_method,
                 ?request_query,
                 ?response_status,
-                ?response_body,
+                response_body = ?logged_body,
                 "Error processing request"
             );
         } else {
@@ -120,7 +140,7 @@ async fn handle_response(
                 ?request_method,
                 ?request_query,
                 ?response_status,
-                ?response_body,
+                response_body = ?logged_body,
                 "Error processing request"
             );
         }
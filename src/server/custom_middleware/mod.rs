@@ -1,4 +1,6 @@
 pub mod api_metrics_layer;
+pub mod auth_layer;
+pub mod compression_layer;
 pub mod logging_layer;
 pub mod remove_auth_layer;
 pub mod timeout_layer;
@@ -1,17 +1,110 @@
-use std::time::Duration;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use axum::extract::State;
+use axum::extract::{MatchedPath, State};
 use axum::http::{Request, StatusCode};
 use axum::middleware::Next;
-use axum::response::Response;
+use axum::response::{IntoResponse, Response};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use tracing::warn;
 
+use crate::server::group;
+use crate::server::version::ApiVersion;
+
+static TIMEOUTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "api_request_timeouts",
+        "Number of requests aborted for exceeding the endpoint timeout.",
+        &["endpoint"]
+    )
+    .unwrap()
+});
+
+/// The time requests may take before they are aborted, by route.
+#[derive(Clone, Debug)]
+pub struct EndpointTimeouts {
+    default:   Duration,
+    endpoints: HashMap<String, Duration>,
+}
+
+impl EndpointTimeouts {
+    #[must_use]
+    pub fn new(default: Duration) -> Self {
+        Self {
+            default,
+            endpoints: HashMap::new(),
+        }
+    }
+
+    /// Overrides the timeout of the routes, keyed by their path as registered
+    /// with the router, e.g. `/receipts/:commitment`.
+    #[must_use]
+    pub fn with_endpoints(
+        mut self,
+        endpoints: impl IntoIterator<Item = (String, Duration)>,
+    ) -> Self {
+        self.endpoints.extend(endpoints);
+        self
+    }
+
+    #[must_use]
+    pub fn get(&self, endpoint: &str) -> Duration {
+        self.endpoints
+            .get(endpoint)
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// Aborts the handler once the timeout of its endpoint elapsed, responding
+/// with `504 Gateway Timeout`.
 pub async fn middleware<B>(
-    State(timeout_duration): State<Duration>,
+    State(timeouts): State<Arc<EndpointTimeouts>>,
     request: Request<B>,
     next: Next<B>,
-) -> Result<Response, StatusCode> {
-    match tokio::time::timeout(timeout_duration, next.run(request)).await {
-        Ok(response) => Ok(response),
-        Err(_elapsed) => Err(StatusCode::REQUEST_TIMEOUT),
+) -> Response {
+    let endpoint = request.extensions().get::<MatchedPath>().map_or_else(
+        || request.uri().path().to_owned(),
+        |path| path.as_str().to_owned(),
+    );
+    let method = request.method().clone();
+    let timeout = timeouts.get(ApiVersion::unversioned(group::ungrouped(&endpoint)));
+
+    let start = Instant::now();
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_elapsed) => {
+            TIMEOUTS.with_label_values(&[&endpoint]).inc();
+
+            let elapsed = start.elapsed();
+            warn!(%method, endpoint, ?timeout, ?elapsed, "Request timed out");
+
+            (
+                StatusCode::GATEWAY_TIMEOUT,
+                format!(
+                    "{method} {endpoint} did not complete within its timeout of {}s, aborted \
+                     after {}ms",
+                    timeout.as_secs_f64(),
+                    elapsed.as_millis()
+                ),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoint_timeouts_fall_back_to_the_default() {
+        let timeouts = EndpointTimeouts::new(Duration::from_secs(300))
+            .with_endpoints([("/inclusionProof".to_owned(), Duration::from_secs(5))]);
+
+        assert_eq!(timeouts.get("/inclusionProof"), Duration::from_secs(5));
+        assert_eq!(timeouts.get("/insertIdentity"), Duration::from_secs(300));
     }
 }
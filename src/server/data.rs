@@ -1,30 +1,233 @@
-use hyper::StatusCode;
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use ethers::types::{Address, Bytes, H256, U256};
+use hyper::header::{HeaderValue, ACCEPT};
+use hyper::{HeaderMap, StatusCode};
+use semaphore::poseidon_tree::Branch;
 use semaphore::protocol::Proof;
 use semaphore::Field;
 use serde::{Deserialize, Serialize};
 
+use crate::database::types::{
+    BatchCost, BatchReport, FailedBatch, MinedLeafEntry, RootHistoryEntry,
+};
+use crate::ethereum::read::circuit_breaker::BreakerStatus;
+use crate::ethereum::signing_log::{SigningOutcome, SigningRecord};
 use crate::identity_tree::{
-    Hash, InclusionProof, ProcessedStatus, RootItem, Status, UnprocessedStatus,
+    Hash, IdentityEvent, InclusionProof, MinedTransaction, ProcessedStatus, RangeProof,
+    RemovedStatus, RootItem, Status, TreeSnapshot, UnprocessedStatus, VersionGcReport,
 };
-use crate::prover::{ProverConfiguration, ProverType};
+use crate::prover::{ProverConfiguration, ProverKeyReport, ProverType};
+use crate::receipts::{InsertionReceipt, NonInclusionAttestation};
+use crate::server::version::ApiVersion;
+use crate::server::{etag, negotiation};
+use crate::task_monitor::tasks::foreign_roots::ForeignRoot;
+use crate::task_monitor::tasks::monitor_balance::FundsStatus;
+use crate::task_monitor::tasks::monitor_contract::ContractSuspension;
+use crate::task_monitor::tasks::monitor_provers::ProverOutage;
 
 #[derive(Serialize)]
 #[serde(transparent)]
 pub struct InclusionProofResponse(pub InclusionProof);
 
+#[derive(Serialize)]
+#[serde(transparent)]
+pub struct RangeProofResponse(pub RangeProof);
+
+/// An inclusion proof in the `MerkleProof` shape expected by
+/// `@semaphore-protocol/proof`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SemaphoreJsInclusionProofResponse {
+    pub status:       Status,
+    pub root:         Option<Field>,
+    pub leaf:         Hash,
+    /// Sibling nodes from the leaf up to the root
+    pub siblings:     Option<Vec<Hash>>,
+    /// `0` where the path goes left, `1` where it goes right
+    pub path_indices: Option<Vec<u8>>,
+    pub message:      Option<String>,
+}
+
 #[derive(Serialize)]
 #[serde(transparent)]
 pub struct ListBatchSizesResponse(pub Vec<ProverConfiguration>);
 
+#[derive(Serialize)]
+#[serde(transparent)]
+pub struct ProverKeysResponse(pub Vec<ProverKeyReport>);
+
 #[derive(Serialize)]
 #[serde(transparent)]
 pub struct VerifySemaphoreProofResponse(pub RootItem);
 
+#[derive(Serialize)]
+#[serde(transparent)]
+pub struct CheckRootResponse(pub RootItem);
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InsertionReceiptResponse {
+    #[serde(flatten)]
+    pub receipt:        InsertionReceipt,
+    /// Absent once the identity was mined
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_estimate: Option<QueueEstimate>,
+}
+
+/// How far an identity is from being mined, estimated from the insertions
+/// mined recently.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueEstimate {
+    /// Identities ahead of this one waiting to be mined
+    pub queue_position:             u64,
+    /// Average time between recently mined insertion batches, the batch
+    /// timeout if none were mined
+    pub batch_interval_seconds:     u64,
+    /// Absent until insertions were mined recently
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_seconds_to_mined: Option<u64>,
+}
+
+/// The outcome of a line of a `/insertIdentities` request, streamed back as
+/// soon as the line is processed.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkInsertionResult {
+    /// Line of the request, counting from 1
+    pub line:                usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity_commitment: Option<Hash>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receipt:             Option<InsertionReceipt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error:               Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(transparent)]
+pub struct NonInclusionProofResponse(pub NonInclusionAttestation);
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct TreeInfoResponse {
+    pub tree_depth:        usize,
+    pub next_leaf_index:   u64,
+    /// Root of the tree including all pending identities
+    pub latest_root:       Hash,
+    /// Root of the tree including all batched identities, i.e. the root the
+    /// batch being proven or submitted results in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batching_root:     Option<Hash>,
+    /// Root of the tree as mined on chain
+    pub mined_root:        Hash,
+    /// The transaction which published the mined root
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mined_transaction: Option<MinedTransaction>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthResponse {
+    pub status:        HealthStatus,
+    /// Percentage of the tree rebuilt, while syncing on startup
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sync_progress: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signer:        Option<SignerHealth>,
+    /// State of the circuit breaker in front of the Ethereum provider
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider:      Option<BreakerStatus>,
+    /// The ongoing outage of a prover, batches aren't formed until it ends
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prover:        Option<ProverOutage>,
+    /// The root on chain which wasn't submitted by this sequencer and
+    /// couldn't be applied, batches aren't submitted until it's reconciled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub foreign_root:  Option<ForeignRoot>,
+    /// The action of the contract owner which keeps the contract from
+    /// accepting batches, they aren't submitted until it's reverted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contract:      Option<ContractSuspension>,
+}
+
+/// The build of the sequencer and the tree it serves.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionResponse {
+    pub environment:              Option<String>,
+    pub version:                  String,
+    pub commit_sha:               String,
+    pub build_date:               String,
+    pub identity_manager_address: Address,
+    pub chain_id:                 u64,
+    pub tree_depth:               usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HealthStatus {
+    Ok,
+    /// Operational, but needs attention
+    Degraded,
+    /// Batches are not being submitted
+    Unavailable,
+    /// The tree is being rebuilt on startup, no requests are served yet
+    Syncing,
+    /// Proofs are served from a snapshot which isn't verified against the
+    /// chain
+    Offline,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignerHealth {
+    pub address:            Address,
+    /// Balance in wei, as of the last block checked
+    pub balance:            Option<U256>,
+    pub funds:              FundsStatus,
+    pub submissions_paused: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(deny_unknown_fields)]
 pub struct IdentityHistoryResponse {
-    pub history: Vec<IdentityHistoryEntry>,
+    pub history:  Vec<IdentityHistoryEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentityStatusResponse {
+    /// Derived from the latest event, absent if the identity only has
+    /// deletion events.
+    pub status:          Option<Status>,
+    /// Order in which the insertion was accepted, absent for identities
+    /// inserted without a receipt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence_number: Option<u64>,
+    /// Absent unless the identity is waiting to be mined.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue_estimate:  Option<QueueEstimate>,
+    /// Opaque metadata attached on insertion, never submitted on chain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata:        Option<String>,
+    /// All events of the identity, oldest first.
+    pub events:          Vec<IdentityStatusEvent>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentityStatusEvent {
+    pub event:     IdentityEvent,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message:   Option<String>,
+    pub timestamp: DateTime<Utc>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -61,11 +264,391 @@ pub enum IdentityHistoryEntryStatus {
     Bridged,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct QueueResponse {
+    pub counts:  QueueCounts,
+    pub entries: Vec<QueueEntry>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct QueueCounts {
+    /// Unprocessed identities eligible for processing
+    pub buffered: usize,
+    /// Unprocessed identities not yet eligible for processing
+    pub queued:   usize,
+    /// Unprocessed identities which failed to be processed
+    pub failed:   usize,
+    /// Identities in the tree but not yet submitted on chain
+    pub pending:  usize,
+    /// Identities submitted on chain but not yet mined
+    pub batched:  usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct QueueEntry {
+    pub identity_commitment: Hash,
+    pub leaf_index:          Option<u64>,
+    pub status:              IdentityHistoryEntryStatus,
+    pub age_seconds:         i64,
+    /// The post root of the batch the identity was submitted in
+    pub batch_root:          Option<Hash>,
+    /// The hash of the transaction of the batch, once it's broadcast
+    pub transaction_hash:    Option<H256>,
+    pub error_message:       Option<String>,
+    pub metadata:            Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct QueueQuery {
+    /// Maximum number of entries to return for each queue stage.
+    #[serde(default)]
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RootsQuery {
+    /// `next` of the previous page, roots are listed from the first one
+    /// without it.
+    #[serde(default)]
+    pub after:      usize,
+    /// Only roots of this status.
+    pub status:     Option<ProcessedStatus>,
+    /// Only roots mined in this block or later.
+    pub from_block: Option<u64>,
+    /// Only roots mined in this block or earlier.
+    pub to_block:   Option<u64>,
+    /// Maximum number of roots to return.
+    pub limit:      Option<i64>,
+}
+
+/// Roots of the tree in the order it reached them.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RootsResponse {
+    pub roots: Vec<RootHistoryItem>,
+    /// `after` of the next page, absent once the last root was listed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next:  Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RootHistoryItem {
+    /// Row of the tree which first led to the root
+    pub id:   usize,
+    #[serde(flatten)]
+    pub root: RootItem,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LeavesQuery {
+    /// `next` of the previous page, leaves are listed from the first one
+    /// without it.
+    pub after:              Option<usize>,
+    /// Only leaves mined before this block.
+    pub mined_before_block: Option<u64>,
+    /// Maximum number of leaves to return.
+    pub limit:              Option<i64>,
+}
+
+/// Leaves of the mined tree in order.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeavesResponse {
+    pub leaves: Vec<LeafItem>,
+    /// `after` of the next page, absent once the last leaf was listed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next:   Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeafItem {
+    pub leaf_index:  usize,
+    pub commitment:  Hash,
+    /// The block in which the leaf was mined, unset if it wasn't recorded
+    pub mined_block: Option<u64>,
+}
+
+/// Root of the mined tree a `/sync/snapshot` response holds.
+pub const SYNC_ROOT_HEADER: &str = "x-sync-root";
+
+/// Last row of the tree included in a `/sync/snapshot` response, updates are
+/// followed from there.
+pub const SYNC_UPDATE_ID_HEADER: &str = "x-sync-update-id";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct SyncUpdatesQuery {
+    /// Row of the tree to return the updates after
+    pub after:        usize,
+    /// How long to wait for updates if there are none yet
+    #[serde(default)]
+    pub wait_seconds: u64,
+}
+
+/// Updates of the tree in the order they were written, along with the roots
+/// the processed and mined trees have reached.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncUpdatesResponse {
+    pub updates:        Vec<SyncUpdate>,
+    pub processed_root: Option<Hash>,
+    pub mined_root:     Option<Hash>,
+}
+
+/// A tree snapshot file of the mined tree, served to followers.
+pub struct SyncSnapshot {
+    pub root:      Hash,
+    /// Last row of the tree included in the snapshot
+    pub update_id: usize,
+    pub bytes:     Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncUpdate {
+    pub id:         usize,
+    pub leaf_index: usize,
+    pub element:    Hash,
+    /// Root of the tree after the update
+    pub root:       Hash,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct TreeNodesQuery {
+    /// Level to start streaming at, 0 being the leaves
+    #[serde(default)]
+    pub from_level: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct RangeProofQuery {
+    /// First proven leaf, inclusive
+    pub from: usize,
+    /// Last proven leaf, exclusive
+    pub to:   usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct CostsQuery {
+    /// Start of the reported period, inclusive
+    #[serde(default)]
+    pub from: Option<DateTime<Utc>>,
+    /// End of the reported period, exclusive
+    #[serde(default)]
+    pub to:   Option<DateTime<Utc>>,
+}
+
+/// Gas spent on the batches mined in a period. Amounts are in wei.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CostsResponse {
+    pub from:    Option<DateTime<Utc>>,
+    pub to:      Option<DateTime<Utc>>,
+    pub totals:  CostTotals,
+    pub batches: Vec<BatchCostEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct SignerActivityQuery {
+    /// Start of the reported period, inclusive
+    #[serde(default)]
+    pub from: Option<DateTime<Utc>>,
+    /// End of the reported period, exclusive
+    #[serde(default)]
+    pub to:   Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignerActivityResponse {
+    pub signer:     Address,
+    pub from:       Option<DateTime<Utc>>,
+    pub to:         Option<DateTime<Utc>>,
+    pub totals:     SigningTotals,
+    /// Totals by purpose, e.g. `insertionBatch`
+    pub purposes:   BTreeMap<&'static str, SigningTotals>,
+    pub operations: Vec<SigningRecord>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SigningTotals {
+    pub submitted: usize,
+    pub signed:    usize,
+    pub failed:    usize,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CostTotals {
+    pub batch_count:        usize,
+    pub identity_count:     u64,
+    pub gas_used:           u64,
+    pub cost:               U256,
+    /// Cost amortized over the identities of all batches
+    pub cost_per_identity:  Option<U256>,
+    /// Cost amortized over the identities of insertion batches only
+    pub cost_per_insertion: Option<U256>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchCostEntry {
+    pub transaction_hash:    H256,
+    pub kind:                String,
+    pub identity_count:      u64,
+    pub gas_used:            u64,
+    pub effective_gas_price: U256,
+    pub cost:                U256,
+    pub cost_per_identity:   Option<U256>,
+    pub block_timestamp:     DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct BatchesQuery {
+    /// Maximum number of batches to return, newest first.
+    #[serde(default)]
+    pub limit: Option<i64>,
+}
+
+/// Reports of the latest batches handed to the signer.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchesResponse {
+    pub batches: Vec<BatchReportEntry>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchReportEntry {
+    pub transaction_id:       String,
+    pub pre_root:             Hash,
+    pub post_root:            Hash,
+    /// Missing for batches submitted before reports were recorded
+    #[serde(flatten)]
+    pub details:              Option<BatchDetailsEntry>,
+    pub submitted_at:         DateTime<Utc>,
+    pub mined_transaction:    Option<MinedTransaction>,
+    pub gas_used:             Option<u64>,
+    /// Seconds from submission until the block including the transaction
+    pub confirmation_seconds: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchDetailsEntry {
+    pub kind:              String,
+    pub first_leaf_index:  usize,
+    pub last_leaf_index:   usize,
+    pub identity_count:    usize,
+    pub prover_latency_ms: u64,
+    pub calldata_size:     usize,
+}
+
+/// Batches which reverted in simulation and were never broadcast.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedBatchesResponse {
+    pub batches: Vec<FailedBatchEntry>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedBatchEntry {
+    pub pre_root:       Hash,
+    pub post_root:      Hash,
+    pub kind:           String,
+    pub reason:         String,
+    pub attempts:       u32,
+    /// Whether batch submission is held until the batch is retried or
+    /// discarded
+    pub held:           bool,
+    /// Whether the batch is a single leaf isolated from a reverting batch
+    pub isolated:       bool,
+    pub created_at:     DateTime<Utc>,
+    pub last_failed_at: DateTime<Utc>,
+    /// Only included when inspecting a single batch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calldata:       Option<Bytes>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct RestoreTreeRequest {
+    /// Path of the snapshot on the host of the sequencer
+    pub snapshot_path: String,
+}
+
+/// The outcome of replacing the tree with a snapshot.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreTreeResponse {
+    pub previous_root:        Hash,
+    pub root:                 Hash,
+    pub leaf_count:           u64,
+    /// Identities which weren't mined yet and were dropped with the tree
+    pub discarded_identities: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct RollBackInsertionsRequest {
+    /// How many of the latest insertions to roll back
+    pub count: usize,
+}
+
+/// The outcome of rolling back the latest insertions.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RollBackInsertionsResponse {
+    pub previous_root: Hash,
+    pub root:          Hash,
+    /// The leaf the next insertion is assigned
+    pub next_leaf:     usize,
+    /// The rolled back commitments, by leaf index
+    pub rolled_back:   Vec<Hash>,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(deny_unknown_fields)]
 pub struct InsertCommitmentRequest {
     pub identity_commitment: Hash,
+    /// Opaque metadata stored alongside the identity, never submitted on chain
+    #[serde(default)]
+    pub metadata:            Option<String>,
+    /// EIP-712 signature over the commitment, proving control of the
+    /// signing address
+    #[serde(default)]
+    pub signature:           Option<Bytes>,
+    /// Solution to the challenge the deployment requires before insertions
+    #[serde(default)]
+    pub challenge_token:     Option<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -80,6 +663,9 @@ pub struct AddBatchSizeRequest {
     pub timeout_seconds: u64,
     // TODO: add docs
     pub prover_type:     ProverType,
+    /// The digest of the proving key the prover is expected to serve.
+    #[serde(default)]
+    pub key_digest:      Option<H256>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -106,6 +692,20 @@ pub struct IdentityHistoryRequest {
     pub identity_commitment: Hash,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct IdentityStatusRequest {
+    pub identity_commitment: Hash,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct CheckRootRequest {
+    pub root: Hash,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(deny_unknown_fields)]
@@ -125,6 +725,61 @@ pub struct VerifySemaphoreProofQuery {
     pub max_root_age_seconds: Option<i64>,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProofFormat {
+    #[default]
+    Default,
+    /// The `MerkleProof` shape of `@semaphore-protocol/proof`
+    SemaphoreJs,
+}
+
+/// The media type inclusion proofs are encoded in, negotiated through the
+/// `Accept` header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProofEncoding {
+    #[default]
+    Json,
+    /// Smaller and faster to decode for high-volume verifiers
+    Cbor,
+    /// Like CBOR, for verifiers with MessagePack tooling at hand
+    MessagePack,
+}
+
+impl ProofEncoding {
+    /// Picks CBOR or MessagePack, in that order, if the client accepts it,
+    /// JSON otherwise.
+    #[must_use]
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let accepts = |encoding: Self| {
+            negotiation::accepted(headers, ACCEPT)
+                .any(|media_type| media_type.eq_ignore_ascii_case(encoding.content_type()))
+        };
+
+        [Self::Cbor, Self::MessagePack]
+            .into_iter()
+            .find(|encoding| accepts(*encoding))
+            .unwrap_or(Self::Json)
+    }
+
+    #[must_use]
+    pub const fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Cbor => "application/cbor",
+            Self::MessagePack => "application/msgpack",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct InclusionProofQuery {
+    #[serde(default)]
+    pub format: ProofFormat,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(deny_unknown_fields)]
@@ -156,6 +811,29 @@ impl InclusionProofResponse {
     }
 }
 
+impl InclusionProofResponse {
+    /// Whether the proof was requested for an identity which was deleted.
+    #[must_use]
+    pub fn is_deleted(&self) -> bool {
+        self.0.status == Status::Removed(RemovedStatus::Deleted)
+    }
+
+    /// Reports batched identities as pending to `/v1` clients, and omits the
+    /// mined transaction and block.
+    #[must_use]
+    pub fn for_version(mut self, version: ApiVersion) -> Self {
+        if version == ApiVersion::V1 {
+            if self.0.status == Status::Processed(ProcessedStatus::Batched) {
+                self.0.status = Status::Processed(ProcessedStatus::Pending);
+            }
+            self.0.mined_transaction = None;
+            self.0.mined_block = None;
+        }
+
+        self
+    }
+}
+
 impl From<InclusionProof> for InclusionProofResponse {
     fn from(value: InclusionProof) -> Self {
         Self(value)
@@ -167,10 +845,68 @@ impl ToResponseCode for InclusionProofResponse {
         match self.0.status {
             Status::Unprocessed(UnprocessedStatus::Failed) => StatusCode::BAD_REQUEST,
             Status::Unprocessed(UnprocessedStatus::New)
-            | Status::Processed(ProcessedStatus::Pending) => StatusCode::ACCEPTED,
+            | Status::Processed(ProcessedStatus::Pending | ProcessedStatus::Batched) => {
+                StatusCode::ACCEPTED
+            }
             Status::Processed(ProcessedStatus::Mined | ProcessedStatus::Processed) => {
                 StatusCode::OK
             }
+            // Unlike commitments which were never inserted, which are not found.
+            Status::Removed(RemovedStatus::Deleted) => StatusCode::GONE,
+        }
+    }
+}
+
+impl InclusionProofResponse {
+    /// Entity tag identifying this proof in the given format. Proofs are only
+    /// tagged once they are served against a root.
+    #[must_use]
+    pub fn etag(
+        &self,
+        commitment: &Hash,
+        format: ProofFormat,
+        encoding: ProofEncoding,
+    ) -> Option<HeaderValue> {
+        let root = self.0.root?;
+        let status = serde_json::to_vec(&self.0.status).unwrap_or_default();
+        let format = serde_json::to_vec(&format).unwrap_or_default();
+
+        Some(etag::compute(&[
+            &etag::hash_bytes(commitment),
+            &etag::hash_bytes(&root),
+            &status,
+            &format,
+            encoding.content_type().as_bytes(),
+        ]))
+    }
+
+    /// Converts the proof of `leaf` into the shape expected by
+    /// `@semaphore-protocol/proof`.
+    #[must_use]
+    pub fn to_semaphore_js(&self, leaf: Hash) -> SemaphoreJsInclusionProofResponse {
+        let (siblings, path_indices) = self
+            .0
+            .proof
+            .as_ref()
+            .map(|proof| {
+                proof
+                    .0
+                    .iter()
+                    .map(|branch| match branch {
+                        Branch::Left(sibling) => (*sibling, 0),
+                        Branch::Right(sibling) => (*sibling, 1),
+                    })
+                    .unzip()
+            })
+            .unzip();
+
+        SemaphoreJsInclusionProofResponse {
+            status: self.0.status,
+            root: self.0.root,
+            leaf,
+            siblings,
+            path_indices,
+            message: self.0.message.clone(),
         }
     }
 }
@@ -187,6 +923,12 @@ impl ToResponseCode for ListBatchSizesResponse {
     }
 }
 
+impl ToResponseCode for ProverKeysResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
 impl VerifySemaphoreProofResponse {
     #[must_use]
     pub fn hide_processed_status(mut self) -> Self {
@@ -200,12 +942,455 @@ impl VerifySemaphoreProofResponse {
     }
 }
 
+impl VerifySemaphoreProofResponse {
+    /// Omits the mined transaction from `/v1` responses.
+    #[must_use]
+    pub fn for_version(mut self, version: ApiVersion) -> Self {
+        if version == ApiVersion::V1 {
+            self.0.mined_transaction = None;
+        }
+
+        self
+    }
+}
+
 impl ToResponseCode for VerifySemaphoreProofResponse {
     fn to_response_code(&self) -> StatusCode {
         StatusCode::OK
     }
 }
 
+impl CheckRootResponse {
+    #[must_use]
+    pub fn hide_processed_status(mut self) -> Self {
+        self.0.status = if self.0.status == ProcessedStatus::Processed {
+            ProcessedStatus::Pending
+        } else {
+            self.0.status
+        };
+
+        self
+    }
+}
+
+impl CheckRootResponse {
+    /// Omits the mined transaction from `/v1` responses.
+    #[must_use]
+    pub fn for_version(mut self, version: ApiVersion) -> Self {
+        if version == ApiVersion::V1 {
+            self.0.mined_transaction = None;
+        }
+
+        self
+    }
+}
+
+impl CheckRootResponse {
+    /// The root of a snapshot is the only one known offline, as mined when the
+    /// snapshot was written.
+    #[must_use]
+    pub fn from_snapshot(
+        snapshot: &TreeSnapshot,
+        written_at: DateTime<Utc>,
+        root: &Hash,
+    ) -> Option<Self> {
+        (snapshot.root() == *root).then(|| {
+            Self(RootItem {
+                root:                *root,
+                status:              ProcessedStatus::Mined,
+                pending_valid_as_of: written_at,
+                mined_valid_as_of:   Some(written_at),
+                mined_transaction:   None,
+            })
+        })
+    }
+}
+
+impl ToResponseCode for CheckRootResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+impl From<InsertionReceipt> for InsertionReceiptResponse {
+    fn from(receipt: InsertionReceipt) -> Self {
+        Self {
+            receipt,
+            queue_estimate: None,
+        }
+    }
+}
+
+impl ToResponseCode for InsertionReceiptResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+impl From<NonInclusionAttestation> for NonInclusionProofResponse {
+    fn from(value: NonInclusionAttestation) -> Self {
+        Self(value)
+    }
+}
+
+impl ToResponseCode for NonInclusionProofResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+impl VersionResponse {
+    /// Describes this build, serving the given tree. The commit and the build
+    /// date are set by the build script, `unknown` when built without it.
+    #[must_use]
+    pub fn new(
+        environment: Option<String>,
+        identity_manager_address: Address,
+        chain_id: u64,
+        tree_depth: usize,
+    ) -> Self {
+        Self {
+            environment,
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            commit_sha: option_env!("COMMIT_SHA").unwrap_or("unknown").to_owned(),
+            build_date: option_env!("BUILD_DATE").unwrap_or("unknown").to_owned(),
+            identity_manager_address,
+            chain_id,
+            tree_depth,
+        }
+    }
+}
+
+impl ToResponseCode for VersionResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+impl ToResponseCode for HealthResponse {
+    fn to_response_code(&self) -> StatusCode {
+        match self.status {
+            HealthStatus::Ok | HealthStatus::Degraded | HealthStatus::Offline => StatusCode::OK,
+            HealthStatus::Unavailable | HealthStatus::Syncing => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+impl HealthResponse {
+    #[must_use]
+    pub const fn syncing(sync_progress: f64) -> Self {
+        Self {
+            status:        HealthStatus::Syncing,
+            sync_progress: Some(sync_progress),
+            signer:        None,
+            provider:      None,
+            prover:        None,
+            foreign_root:  None,
+            contract:      None,
+        }
+    }
+
+    #[must_use]
+    pub const fn offline() -> Self {
+        Self {
+            status:        HealthStatus::Offline,
+            sync_progress: None,
+            signer:        None,
+            provider:      None,
+            prover:        None,
+            foreign_root:  None,
+            contract:      None,
+        }
+    }
+}
+
+impl TreeInfoResponse {
+    #[must_use]
+    pub fn etag(&self) -> HeaderValue {
+        etag::compute(&[
+            &etag::hash_bytes(&self.latest_root),
+            &self
+                .batching_root
+                .as_ref()
+                .map(etag::hash_bytes)
+                .unwrap_or_default(),
+            &etag::hash_bytes(&self.mined_root),
+        ])
+    }
+}
+
+impl TreeInfoResponse {
+    /// The snapshot is taken of the mined tree, nothing is pending.
+    #[must_use]
+    pub fn from_snapshot(snapshot: &TreeSnapshot) -> Self {
+        let root = snapshot.root();
+
+        Self {
+            tree_depth:        snapshot.depth(),
+            next_leaf_index:   snapshot.leaf_count() as u64,
+            latest_root:       root,
+            batching_root:     None,
+            mined_root:        root,
+            mined_transaction: None,
+        }
+    }
+
+    /// Omits the batching root and the mined transaction from `/v1` responses.
+    #[must_use]
+    pub fn for_version(mut self, version: ApiVersion) -> Self {
+        if version == ApiVersion::V1 {
+            self.batching_root = None;
+            self.mined_transaction = None;
+        }
+
+        self
+    }
+}
+
+impl ToResponseCode for TreeInfoResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+impl SignerActivityResponse {
+    #[must_use]
+    pub fn new(
+        signer: Address,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        operations: Vec<SigningRecord>,
+    ) -> Self {
+        let mut totals = SigningTotals::default();
+        let mut purposes = BTreeMap::new();
+
+        for operation in &operations {
+            let purpose: &mut SigningTotals =
+                purposes.entry(operation.purpose.as_str()).or_default();
+
+            match operation.outcome {
+                SigningOutcome::Submitted => {
+                    totals.submitted += 1;
+                    purpose.submitted += 1;
+                }
+                SigningOutcome::Signed => {
+                    totals.signed += 1;
+                    purpose.signed += 1;
+                }
+                SigningOutcome::Failed => {
+                    totals.failed += 1;
+                    purpose.failed += 1;
+                }
+            }
+        }
+
+        Self {
+            signer,
+            from,
+            to,
+            totals,
+            purposes,
+            operations,
+        }
+    }
+}
+
+impl ToResponseCode for SignerActivityResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+impl CostsResponse {
+    #[must_use]
+    pub fn new(
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        batches: Vec<BatchCost>,
+    ) -> Self {
+        let mut totals = CostTotals::default();
+        let mut insertion_count = 0;
+        let mut insertion_cost = U256::zero();
+
+        for batch in &batches {
+            totals.batch_count += 1;
+            totals.identity_count += batch.identity_count;
+            totals.gas_used += batch.gas_used;
+            totals.cost += batch.cost();
+
+            if batch.kind == "insertion" {
+                insertion_count += batch.identity_count;
+                insertion_cost += batch.cost();
+            }
+        }
+
+        totals.cost_per_identity = amortize(totals.cost, totals.identity_count);
+        totals.cost_per_insertion = amortize(insertion_cost, insertion_count);
+
+        Self {
+            from,
+            to,
+            totals,
+            batches: batches.into_iter().map(BatchCostEntry::from).collect(),
+        }
+    }
+}
+
+impl FailedBatchEntry {
+    #[must_use]
+    pub fn new(batch: FailedBatch, max_attempts: Option<u32>, with_calldata: bool) -> Self {
+        Self {
+            pre_root:       batch.pre_root,
+            post_root:      batch.post_root,
+            kind:           batch.kind,
+            reason:         batch.reason,
+            attempts:       batch.attempts,
+            held:           batch.isolated
+                || max_attempts.is_some_and(|max_attempts| batch.attempts >= max_attempts),
+            isolated:       batch.isolated,
+            created_at:     batch.created_at,
+            last_failed_at: batch.last_failed_at,
+            calldata:       with_calldata.then(|| Bytes::from(batch.calldata)),
+        }
+    }
+}
+
+impl ToResponseCode for RestoreTreeResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+impl ToResponseCode for RangeProofResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+impl ToResponseCode for RollBackInsertionsResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+impl ToResponseCode for VersionGcReport {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+impl ToResponseCode for RootsResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+impl ToResponseCode for LeavesResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+impl ToResponseCode for FailedBatchesResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+impl ToResponseCode for FailedBatchEntry {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+impl From<BatchCost> for BatchCostEntry {
+    fn from(batch: BatchCost) -> Self {
+        let cost = batch.cost();
+
+        Self {
+            transaction_hash: batch.transaction_hash,
+            kind: batch.kind,
+            identity_count: batch.identity_count,
+            gas_used: batch.gas_used,
+            effective_gas_price: batch.effective_gas_price,
+            cost,
+            cost_per_identity: amortize(cost, batch.identity_count),
+            block_timestamp: batch.block_timestamp,
+        }
+    }
+}
+
+impl From<RootHistoryEntry> for RootHistoryItem {
+    fn from(entry: RootHistoryEntry) -> Self {
+        Self {
+            id:   entry.id,
+            root: entry.root,
+        }
+    }
+}
+
+impl From<MinedLeafEntry> for LeafItem {
+    fn from(entry: MinedLeafEntry) -> Self {
+        Self {
+            leaf_index:  entry.leaf_index,
+            commitment:  entry.commitment,
+            mined_block: entry.mined_block,
+        }
+    }
+}
+
+impl From<BatchReport> for BatchReportEntry {
+    fn from(report: BatchReport) -> Self {
+        // Block timestamps are in seconds, so the transaction may appear to be
+        // confirmed before it was submitted.
+        let confirmation_seconds = report.mined.as_ref().map(|mined| {
+            (mined.block_timestamp - report.submitted_at)
+                .num_seconds()
+                .max(0)
+        });
+
+        Self {
+            transaction_id: report.transaction_id,
+            pre_root: report.pre_root,
+            post_root: report.post_root,
+            details: report.details.map(|details| BatchDetailsEntry {
+                kind:              details.kind,
+                first_leaf_index:  *details.leaf_range.start(),
+                last_leaf_index:   *details.leaf_range.end(),
+                identity_count:    details.identity_count,
+                prover_latency_ms: details.prover_latency.as_millis() as u64,
+                calldata_size:     details.calldata_size,
+            }),
+            submitted_at: report.submitted_at,
+            mined_transaction: report.mined,
+            gas_used: report.gas_used,
+            confirmation_seconds,
+        }
+    }
+}
+
+impl ToResponseCode for BatchesResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+fn amortize(cost: U256, identity_count: u64) -> Option<U256> {
+    (identity_count > 0).then(|| cost / identity_count)
+}
+
+impl ToResponseCode for CostsResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+impl ToResponseCode for QueueResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
 impl IdentityHistoryEntryKind {
     #[must_use]
     pub fn is_insertion(&self) -> bool {
@@ -230,8 +1415,125 @@ impl ToResponseCode for () {
 
 #[cfg(test)]
 mod tests {
+    use semaphore::poseidon_tree::LazyPoseidonTree;
+
     use super::*;
 
+    #[test]
+    fn semaphore_js_inclusion_proof() {
+        let leaf = Hash::from(42);
+        let tree = LazyPoseidonTree::new(3, Hash::ZERO).update(5, &leaf);
+        let proof = tree.proof(5);
+
+        let response = InclusionProofResponse(InclusionProof {
+            status:            Status::Processed(ProcessedStatus::Mined),
+            root:              Some(tree.root()),
+            proof:             Some(proof.clone()),
+            message:           None,
+            mined_transaction: None,
+            mined_block:       None,
+        })
+        .to_semaphore_js(leaf);
+
+        // Leaf 5 is 0b101, path indices go from the leaf up
+        assert_eq!(response.path_indices, Some(vec![1, 0, 1]));
+        assert_eq!(
+            response.siblings,
+            Some(
+                proof
+                    .0
+                    .iter()
+                    .map(|(Branch::Left(sibling) | Branch::Right(sibling))| *sibling)
+                    .collect()
+            )
+        );
+        assert_eq!(response.root, Some(proof.root(leaf)));
+    }
+
+    #[test]
+    fn offline_responses_from_snapshot() -> anyhow::Result<()> {
+        let leaves: Vec<Hash> = (1..=3_u64).map(Hash::from).collect();
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("snapshot");
+        TreeSnapshot::write(&path, 4, Hash::ZERO, leaves, None)?;
+        let snapshot = TreeSnapshot::open(&path)?;
+        let written_at = Utc::now();
+
+        let info = TreeInfoResponse::from_snapshot(&snapshot);
+        assert_eq!(info.tree_depth, 4);
+        assert_eq!(info.next_leaf_index, 3);
+        assert_eq!(info.latest_root, snapshot.root());
+        assert_eq!(info.mined_root, snapshot.root());
+
+        let root = CheckRootResponse::from_snapshot(&snapshot, written_at, &snapshot.root())
+            .expect("the snapshot root is known");
+        assert_eq!(root.0.status, ProcessedStatus::Mined);
+        assert_eq!(root.0.mined_valid_as_of, Some(written_at));
+        assert!(CheckRootResponse::from_snapshot(&snapshot, written_at, &Hash::from(1)).is_none());
+
+        assert_eq!(HealthResponse::offline().to_response_code(), StatusCode::OK);
+
+        Ok(())
+    }
+
+    #[test]
+    fn costs_are_amortized_over_identities() {
+        let batch = |kind: &str, identity_count, gas_used| BatchCost {
+            transaction_hash: H256::from_low_u64_be(gas_used),
+            kind: kind.to_owned(),
+            identity_count,
+            gas_used,
+            effective_gas_price: U256::from(10),
+            block_timestamp: Utc::now(),
+        };
+
+        let response = CostsResponse::new(None, None, vec![
+            batch("insertion", 4, 1000),
+            batch("deletion", 2, 600),
+            batch("insertion", 0, 100),
+        ]);
+
+        assert_eq!(response.totals, CostTotals {
+            batch_count:        3,
+            identity_count:     6,
+            gas_used:           1700,
+            cost:               U256::from(17_000),
+            cost_per_identity:  Some(U256::from(2833)),
+            cost_per_insertion: Some(U256::from(2750)),
+        });
+        assert_eq!(
+            response.batches[0].cost_per_identity,
+            Some(U256::from(2500))
+        );
+        assert_eq!(response.batches[2].cost_per_identity, None);
+    }
+
+    #[test]
+    fn proof_encoding_negotiation() {
+        let encoding = |accept: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(ACCEPT, HeaderValue::from_str(accept).unwrap());
+            ProofEncoding::from_headers(&headers)
+        };
+
+        assert_eq!(encoding("application/cbor"), ProofEncoding::Cbor);
+        assert_eq!(
+            encoding("application/json;q=0.5, application/cbor"),
+            ProofEncoding::Cbor
+        );
+        assert_eq!(encoding("application/cbor;q=0"), ProofEncoding::Json);
+        assert_eq!(encoding("application/msgpack"), ProofEncoding::MessagePack);
+        assert_eq!(
+            encoding("application/cbor;q=0, application/msgpack"),
+            ProofEncoding::MessagePack
+        );
+        assert_eq!(encoding("*/*"), ProofEncoding::Json);
+        assert_eq!(
+            ProofEncoding::from_headers(&HeaderMap::new()),
+            ProofEncoding::Json
+        );
+    }
+
     #[test]
     fn identity_history_entry_status_ordering() {
         let expected = vec![
@@ -249,4 +1551,109 @@ mod tests {
 
         assert_eq!(expected, statuses);
     }
+
+    #[test]
+    fn version_describes_the_build() {
+        let version =
+            VersionResponse::new(Some("staging".to_owned()), Address::repeat_byte(1), 5, 30);
+
+        let json = serde_json::to_value(&version).unwrap();
+        assert_eq!(json["environment"], "staging");
+        assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(json["chainId"], 5);
+        assert_eq!(json["treeDepth"], 30);
+        assert!(json["commitSha"].is_string());
+        assert!(json["buildDate"].is_string());
+    }
+
+    #[test]
+    fn v1_responses_are_frozen() {
+        let tree_info = || TreeInfoResponse {
+            tree_depth:        20,
+            next_leaf_index:   2,
+            latest_root:       Hash::from(3),
+            batching_root:     Some(Hash::from(2)),
+            mined_root:        Hash::from(1),
+            mined_transaction: Some(MinedTransaction {
+                transaction_hash: H256::repeat_byte(1),
+                block_number:     1,
+                block_timestamp:  Utc::now(),
+            }),
+        };
+
+        let v1 = serde_json::to_value(tree_info().for_version(ApiVersion::V1)).unwrap();
+        let mut fields: Vec<&String> = v1.as_object().unwrap().keys().collect();
+        fields.sort();
+        assert_eq!(fields, [
+            "latestRoot",
+            "minedRoot",
+            "nextLeafIndex",
+            "treeDepth"
+        ]);
+
+        let v2 = tree_info().for_version(ApiVersion::V2);
+        assert_eq!(v2.batching_root, Some(Hash::from(2)));
+        assert!(v2.mined_transaction.is_some());
+
+        let proof = |status| {
+            InclusionProofResponse(InclusionProof {
+                status:            Status::Processed(status),
+                root:              None,
+                proof:             None,
+                message:           None,
+                mined_transaction: Some(MinedTransaction {
+                    transaction_hash: H256::repeat_byte(1),
+                    block_number:     1,
+                    block_timestamp:  Utc::now(),
+                }),
+                mined_block:       Some(1),
+            })
+        };
+        assert_eq!(
+            proof(ProcessedStatus::Batched)
+                .for_version(ApiVersion::V1)
+                .0
+                .status,
+            Status::Processed(ProcessedStatus::Pending)
+        );
+        assert_eq!(
+            proof(ProcessedStatus::Batched)
+                .for_version(ApiVersion::V2)
+                .0
+                .status,
+            Status::Processed(ProcessedStatus::Batched)
+        );
+
+        let v1 = serde_json::to_value(proof(ProcessedStatus::Mined).for_version(ApiVersion::V1))
+            .unwrap();
+        assert!(v1.get("minedTransaction").is_none());
+        assert!(v1.get("minedBlock").is_none());
+        let v2 = serde_json::to_value(proof(ProcessedStatus::Mined).for_version(ApiVersion::V2))
+            .unwrap();
+        assert_eq!(v2["minedTransaction"]["blockNumber"], 1);
+        assert_eq!(v2["minedBlock"], 1);
+    }
+
+    #[test]
+    fn deleted_identities_are_gone() {
+        let proof = InclusionProofResponse(InclusionProof {
+            status:            RemovedStatus::Deleted.into(),
+            root:              Some(Hash::from(2)),
+            proof:             None,
+            message:           None,
+            mined_transaction: Some(MinedTransaction {
+                transaction_hash: H256::repeat_byte(1),
+                block_number:     7,
+                block_timestamp:  Utc::now(),
+            }),
+            mined_block:       None,
+        });
+
+        assert!(proof.is_deleted());
+        assert_eq!(proof.to_response_code(), StatusCode::GONE);
+
+        let json = serde_json::to_value(&proof).unwrap();
+        assert_eq!(json["status"], "deleted");
+        assert_eq!(json["minedTransaction"]["blockNumber"], 7);
+    }
 }
@@ -4,6 +4,7 @@ use hyper::{Body, StatusCode};
 use thiserror::Error;
 
 use crate::database;
+use crate::identity_tree::Hash;
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -29,6 +30,8 @@ pub enum Error {
     UnreducedCommitment,
     #[error("provided identity commitment is already included")]
     DuplicateCommitment,
+    #[error("provided identity commitment is included in the tree")]
+    IdentityCommitmentIncluded,
     #[error("Root mismatch between tree and contract.")]
     RootMismatch,
     #[error("Root provided in semaphore proof is too old.")]
@@ -63,6 +66,60 @@ pub enum Error {
     NoProversOnIdInsert,
     #[error("Identity Manager had no provers on point of identity deletion.")]
     NoProversOnIdDeletion,
+    #[error("Identity metadata exceeds the maximum size")]
+    MetadataTooLarge,
+    #[error("The sequencer is still starting up")]
+    NotReady,
+    #[error("The identity manager contract does not support deletions")]
+    DeletionsNotSupported,
+    #[error("No signing key is configured to sign non-inclusion attestations")]
+    AttestationsNotSupported,
+    #[error("The insertion must be signed")]
+    MissingSignature,
+    #[error("invalid insertion signature")]
+    InvalidSignature,
+    #[error("The insertion must carry a challenge token")]
+    MissingChallengeToken,
+    #[error("invalid challenge token")]
+    InvalidChallengeToken,
+    #[error("The challenge token could not be verified")]
+    ChallengeVerifierUnavailable,
+    #[error("The submission was denied by screening")]
+    SubmissionDenied,
+    #[error("The submission could not be screened")]
+    ScreeningUnavailable,
+    #[error("The request must carry an API key")]
+    MissingApiKey,
+    #[error("invalid API key")]
+    InvalidApiKey,
+    #[error("The API key is not allowed to access this endpoint")]
+    InsufficientScope,
+    #[error("This endpoint is disabled until an admin API key is configured")]
+    AdminApiDisabled,
+    #[error("No failed batch with this post root")]
+    FailedBatchNotFound,
+    #[error("Too many proof requests, try again later")]
+    TooManyProofRequests,
+    #[error("Range proofs cover at most {0} leaves")]
+    RangeTooLarge(usize),
+    #[error("invalid tree snapshot: {0}")]
+    InvalidSnapshot(String),
+    #[error("The snapshot root {snapshot:#x} doesn't match the contract root {contract:#x}")]
+    SnapshotRootMismatch { snapshot: Hash, contract: Hash },
+    #[error("The tree is maintained by another instance")]
+    TreeNotOwned,
+    #[error("Only {unbatched} unbatched insertions can be rolled back, not {requested}")]
+    TooFewUnbatchedInsertions { requested: usize, unbatched: usize },
+    #[error("The tree is full, all {capacity} leaves are used")]
+    TreeFull { capacity: usize },
+    #[error("The tree is nearly full, {used} of {capacity} leaves are used")]
+    TreeCapacityThresholdReached { used: usize, capacity: usize },
+    #[error("Idempotency-Key must be 1 to 255 printable ASCII characters")]
+    InvalidIdempotencyKey,
+    #[error("The Idempotency-Key was used with a different request")]
+    IdempotencyKeyReused,
+    #[error("A request with the same Idempotency-Key is still being processed")]
+    IdempotencyKeyInProgress,
     #[error(transparent)]
     Other(#[from] EyreError),
 }
@@ -96,15 +153,43 @@ impl Error {
     fn to_status_code(&self) -> StatusCode {
         match self {
             Self::InvalidMethod => StatusCode::METHOD_NOT_ALLOWED,
-            Self::InvalidPath => StatusCode::NOT_FOUND,
+            Self::InvalidPath | Self::FailedBatchNotFound => StatusCode::NOT_FOUND,
             Self::InvalidContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
             Self::IndexOutOfBounds
             | Self::IdentityCommitmentNotFound
             | Self::InvalidCommitment
+            | Self::InvalidSnapshot(_)
+            | Self::RangeTooLarge(_)
+            | Self::InvalidIdempotencyKey
             | Self::InvalidSerialization(_) => StatusCode::BAD_REQUEST,
             Self::IdentityAlreadyDeleted
+            | Self::SnapshotRootMismatch { .. }
+            | Self::TreeNotOwned
+            | Self::TooFewUnbatchedInsertions { .. }
+            | Self::TreeFull { .. }
+            | Self::TreeCapacityThresholdReached { .. }
+            | Self::IdempotencyKeyInProgress
             | Self::IdentityQueuedForDeletion
+            | Self::IdentityCommitmentIncluded
             | Self::DuplicateCommitment => StatusCode::CONFLICT,
+            Self::NotReady | Self::ChallengeVerifierUnavailable | Self::ScreeningUnavailable => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            Self::DeletionsNotSupported | Self::AttestationsNotSupported => {
+                StatusCode::NOT_IMPLEMENTED
+            }
+            Self::MissingSignature
+            | Self::InvalidSignature
+            | Self::MissingApiKey
+            | Self::InvalidApiKey => StatusCode::UNAUTHORIZED,
+            Self::MissingChallengeToken
+            | Self::InvalidChallengeToken
+            | Self::InsufficientScope
+            | Self::AdminApiDisabled
+            | Self::SubmissionDenied => StatusCode::FORBIDDEN,
+            Self::MetadataTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::TooManyProofRequests => StatusCode::TOO_MANY_REQUESTS,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
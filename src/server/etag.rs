@@ -0,0 +1,140 @@
+//! Support for conditional requests based on the root a response is served
+//! against.
+
+use axum::http::header::{ETAG, IF_NONE_MATCH};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use ethers::utils::keccak256;
+
+use crate::identity_tree::Hash;
+
+/// Computes a strong entity tag from the values identifying a response.
+///
+/// Responses served against the same root are identical, so callers pass the
+/// serving root(s) along with whatever else distinguishes the resource.
+#[must_use]
+pub fn compute(parts: &[&[u8]]) -> HeaderValue {
+    let digest = keccak256(parts.concat());
+
+    HeaderValue::from_str(&format!("\"{}\"", hex::encode(&digest[..16])))
+        .expect("Hex encoded ETag is a valid header value")
+}
+
+/// Returns the bytes of a hash for use in [`compute`].
+#[must_use]
+pub fn hash_bytes(hash: &Hash) -> [u8; 32] {
+    hash.to_be_bytes::<32>()
+}
+
+/// Turns a strong entity tag into a weak one, e.g. for a compressed
+/// representation of the response it was computed for.
+#[must_use]
+pub fn weaken(etag: &HeaderValue) -> HeaderValue {
+    let Ok(tag) = etag.to_str() else {
+        return etag.clone();
+    };
+
+    if tag.starts_with("W/") {
+        return etag.clone();
+    }
+
+    HeaderValue::from_str(&format!("W/{tag}")).unwrap_or_else(|_| etag.clone())
+}
+
+/// Checks whether the `If-None-Match` header of a request matches the given
+/// entity tag.
+#[must_use]
+pub fn matches(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let Some(if_none_match) = headers.get(IF_NONE_MATCH) else {
+        return false;
+    };
+
+    let Ok(if_none_match) = if_none_match.to_str() else {
+        return false;
+    };
+
+    let etag = etag.to_str().unwrap_or_default();
+
+    if_none_match.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
+
+/// Responds with `304 Not Modified` if the request already holds the current
+/// version of the resource, and attaches the entity tag to the response
+/// otherwise.
+pub fn respond(
+    headers: &HeaderMap,
+    etag: Option<HeaderValue>,
+    response: impl FnOnce() -> Response,
+) -> Response {
+    let Some(etag) = etag else {
+        return response();
+    };
+
+    if matches(headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response();
+    }
+
+    let mut response = response();
+    response.headers_mut().insert(ETAG, etag);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn etag_is_deterministic() {
+        let root = Hash::from(42);
+
+        assert_eq!(
+            compute(&[&hash_bytes(&root), b"mined"]),
+            compute(&[&hash_bytes(&root), b"mined"])
+        );
+        assert_ne!(
+            compute(&[&hash_bytes(&root), b"mined"]),
+            compute(&[&hash_bytes(&root), b"pending"])
+        );
+    }
+
+    #[test]
+    fn if_none_match() {
+        let etag = compute(&[b"test"]);
+
+        let mut headers = HeaderMap::new();
+        assert!(!matches(&headers, &etag));
+
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_static("\"other\""));
+        assert!(!matches(&headers, &etag));
+
+        headers.insert(
+            IF_NONE_MATCH,
+            HeaderValue::from_str(&format!("\"other\", W/{}", etag.to_str().unwrap())).unwrap(),
+        );
+        assert!(matches(&headers, &etag));
+
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_static("*"));
+        assert!(matches(&headers, &etag));
+    }
+
+    #[test]
+    fn not_modified_response() {
+        let etag = compute(&[b"test"]);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_NONE_MATCH, etag.clone());
+
+        let response = respond(&headers, Some(etag.clone()), || {
+            StatusCode::OK.into_response()
+        });
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let response = respond(&HeaderMap::new(), Some(etag.clone()), || {
+            StatusCode::OK.into_response()
+        });
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(ETAG), Some(&etag));
+    }
+}
@@ -0,0 +1,39 @@
+//! Trees hosted in addition to the default one are served under
+//! `/groups/<id>`, with the same routes as the default tree.
+
+const PREFIX: &str = "/groups";
+
+/// The prefix of the routes of a group.
+#[must_use]
+pub fn prefix(id: &str) -> String {
+    format!("{PREFIX}/{id}")
+}
+
+/// The route of a path without its group prefix, e.g. `/v1/inclusionProof`
+/// for `/groups/app/v1/inclusionProof`. Routes are configured and authorized
+/// the same for every group.
+#[must_use]
+pub fn ungrouped(path: &str) -> &str {
+    split(path).map_or(path, |(_, route)| route)
+}
+
+fn split(path: &str) -> Option<(&str, &str)> {
+    let rest = path.strip_prefix(PREFIX)?.strip_prefix('/')?;
+    rest.find('/').map(|index| rest.split_at(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_are_ungrouped() {
+        assert_eq!(
+            ungrouped("/groups/app/v1/inclusionProof"),
+            "/v1/inclusionProof"
+        );
+        assert_eq!(ungrouped("/groups/app/treeInfo"), "/treeInfo");
+        assert_eq!(ungrouped("/inclusionProof"), "/inclusionProof");
+        assert_eq!(ungrouped("/groupsapp/treeInfo"), "/groupsapp/treeInfo");
+    }
+}
@@ -0,0 +1,435 @@
+//! Accepts insertions from a message queue, for backends which would rather
+//! publish commitments than call `/insertIdentity`. Messages are the bodies
+//! of `/insertIdentity` requests and go through the same checks. Status
+//! transitions of identities are published back to another topic.
+
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result as AnyhowResult};
+use async_trait::async_trait;
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use cli_batteries::await_shutdown;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use serde::Serialize;
+use tokio::{select, time};
+use tracing::{error, info, warn};
+
+use super::data::InsertCommitmentRequest;
+use super::{accept_insertion, validation};
+use crate::app::App;
+use crate::database::types::LoggedIdentityEvent;
+use crate::identity_tree::Hash;
+
+/// How many identity events are published at a time.
+const STATUS_BATCH: i64 = 1000;
+
+static INSERTION_MESSAGES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "insertion_queue_messages",
+        "Insertion messages consumed from the message queue, by outcome",
+        &["outcome"]
+    )
+    .unwrap()
+});
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Brokers of a message queue insertions are also accepted from, e.g.
+    /// `nats://localhost:4222` or `kafka://broker-1:9092,broker-2:9092`.
+    /// Requires a build with the `nats` or `kafka` feature.
+    #[clap(long, env)]
+    pub insertion_queue: Option<Broker>,
+
+    /// Topic (Kafka) or subject (NATS) insertions are consumed from
+    #[clap(long, env, default_value = "insertions")]
+    pub insertion_queue_topic: String,
+
+    /// Consumer group (Kafka) or queue group (NATS) shared by the sequencers
+    /// consuming insertions, each message is handled by one of them
+    #[clap(long, env, default_value = "signup-sequencer")]
+    pub insertion_queue_group: String,
+
+    /// Topic (Kafka) or subject (NATS) the events of identities are published
+    /// to
+    #[clap(long, env, default_value = "insertion-status")]
+    pub insertion_status_topic: String,
+
+    /// How often new identity events are looked for (milliseconds)
+    #[clap(long, env, default_value = "1000")]
+    pub insertion_status_poll_ms: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Broker {
+    Nats(String),
+    /// Comma separated `host:port` of the bootstrap servers
+    Kafka(String),
+}
+
+impl FromStr for Broker {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("nats://") {
+            Ok(Self::Nats(s.to_owned()))
+        } else if let Some(servers) = s.strip_prefix("kafka://") {
+            Ok(Self::Kafka(servers.to_owned()))
+        } else {
+            Err(anyhow!("{s} is neither a nats:// nor a kafka:// URL"))
+        }
+    }
+}
+
+/// A status transition of an identity, or the rejection of a message.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StatusMessage {
+    /// Missing if a rejected message didn't name one
+    identity_commitment: Option<Hash>,
+    /// An identity event, e.g. `received` or `mined`, or `rejected`
+    event:               &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message:             Option<String>,
+    timestamp:           DateTime<Utc>,
+}
+
+impl StatusMessage {
+    fn rejected(identity_commitment: Option<Hash>, reason: String) -> Self {
+        Self {
+            identity_commitment,
+            event: "rejected",
+            message: Some(reason),
+            timestamp: Utc::now(),
+        }
+    }
+
+    /// Messages of the same identity share a key, so that Kafka keeps them in
+    /// order.
+    fn key(&self) -> String {
+        self.identity_commitment
+            .map(|commitment| format!("{commitment:#x}"))
+            .unwrap_or_default()
+    }
+}
+
+impl From<LoggedIdentityEvent> for StatusMessage {
+    fn from(logged: LoggedIdentityEvent) -> Self {
+        Self {
+            identity_commitment: Some(logged.commitment),
+            event:               logged.event.event.into(),
+            message:             logged.event.message,
+            timestamp:           logged.event.created_at,
+        }
+    }
+}
+
+#[async_trait]
+trait MessageQueue: Send + Sync {
+    /// Waits for the next insertion message, `None` once the subscription
+    /// ended.
+    async fn receive(&self) -> AnyhowResult<Option<Vec<u8>>>;
+
+    /// Marks the messages received so far as handled, so that they aren't
+    /// delivered again.
+    async fn commit(&self) -> AnyhowResult<()>;
+
+    async fn publish(&self, key: &str, payload: Vec<u8>) -> AnyhowResult<()>;
+}
+
+async fn connect(options: &Options, broker: &Broker) -> AnyhowResult<Arc<dyn MessageQueue>> {
+    match broker {
+        #[cfg(feature = "nats")]
+        Broker::Nats(url) => Ok(Arc::new(nats::Nats::connect(url, options).await?)),
+        #[cfg(feature = "kafka")]
+        Broker::Kafka(servers) => Ok(Arc::new(kafka::Kafka::connect(servers, options)?)),
+        #[allow(unreachable_patterns)]
+        _ => {
+            let _ = options;
+            bail!("--insertion-queue {broker:?} isn't supported by this build")
+        }
+    }
+}
+
+/// Consumes insertions until shutdown. Status transitions are published as
+/// well if `publish_status` is set, which only one of the sequencers sharing
+/// a database should do.
+///
+/// # Errors
+///
+/// Will return `Err` if the queue can't be connected to, or fails.
+pub async fn run(app: Arc<App>, options: Options, publish_status: bool) -> AnyhowResult<()> {
+    let Some(broker) = &options.insertion_queue else {
+        return Ok(());
+    };
+
+    let queue = connect(&options, broker).await?;
+    info!(
+        ?broker,
+        topic = %options.insertion_queue_topic,
+        "Consuming insertions"
+    );
+
+    let consume = consume(&app, queue.as_ref(), &options.insertion_status_topic);
+    let statuses = async {
+        if publish_status {
+            publish_events(&app, queue.as_ref(), options.insertion_status_poll_ms).await
+        } else {
+            std::future::pending().await
+        }
+    };
+
+    select! {
+        result = consume => result,
+        result = statuses => result,
+        () = await_shutdown() => Ok(()),
+    }
+}
+
+async fn consume(app: &App, queue: &dyn MessageQueue, status_topic: &str) -> AnyhowResult<()> {
+    while let Some(payload) = queue.receive().await? {
+        if let Some(rejection) = handle_message(app, &payload).await {
+            INSERTION_MESSAGES.with_label_values(&["rejected"]).inc();
+            warn!(
+                reason = rejection.message.as_deref(),
+                status_topic, "Rejected an insertion message"
+            );
+            publish(queue, &rejection).await?;
+        } else {
+            INSERTION_MESSAGES.with_label_values(&["accepted"]).inc();
+        }
+
+        queue.commit().await?;
+    }
+
+    bail!("Insertion queue subscription ended")
+}
+
+/// Inserts the identity of a message like `/insertIdentity` does, returns
+/// the rejection to publish if it wasn't accepted. Accepted identities are
+/// published along with their other events.
+async fn handle_message(app: &App, payload: &[u8]) -> Option<StatusMessage> {
+    let request = match validation::validate::<InsertCommitmentRequest>(payload) {
+        Ok(request) => request,
+        Err(error) => return Some(StatusMessage::rejected(None, error.to_string())),
+    };
+
+    let commitment = request.identity_commitment;
+    match accept_insertion(app, &HeaderMap::new(), request).await {
+        Ok(_) => None,
+        Err(error) => Some(StatusMessage::rejected(Some(commitment), error.to_string())),
+    }
+}
+
+/// Publishes the events of all identities as they are logged, resuming after
+/// the last one published.
+async fn publish_events(app: &App, queue: &dyn MessageQueue, poll_ms: u64) -> AnyhowResult<()> {
+    let database = app.database();
+
+    // Identities inserted before the queue was set up aren't of interest.
+    let mut last_published = match database.get_last_published_identity_event().await? {
+        Some(event_id) => event_id,
+        None => database.get_latest_identity_event_id().await?,
+    };
+
+    let mut timer = time::interval(Duration::from_millis(poll_ms));
+    loop {
+        timer.tick().await;
+
+        let events = database
+            .get_identity_events_after(last_published, STATUS_BATCH)
+            .await?;
+        let Some(last) = events.last().map(|event| event.id) else {
+            continue;
+        };
+
+        for event in events {
+            publish(queue, &StatusMessage::from(event)).await?;
+        }
+
+        // Events are published again if the sequencer stops before this.
+        database.update_last_published_identity_event(last).await?;
+        last_published = last;
+    }
+}
+
+async fn publish(queue: &dyn MessageQueue, status: &StatusMessage) -> AnyhowResult<()> {
+    let payload = serde_json::to_vec(status)?;
+    if let Err(error) = queue.publish(&status.key(), payload).await {
+        error!(?error, "Failed to publish an identity status");
+        return Err(error);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "nats")]
+mod nats {
+    use async_nats::{Client, Subscriber};
+    use futures::StreamExt;
+    use tokio::sync::Mutex;
+
+    use super::*;
+
+    /// Core NATS, without persistence: messages published while no sequencer
+    /// is subscribed are lost.
+    pub struct Nats {
+        client:       Client,
+        subscriber:   Mutex<Subscriber>,
+        status_topic: String,
+    }
+
+    impl Nats {
+        pub async fn connect(url: &str, options: &Options) -> AnyhowResult<Self> {
+            let client = async_nats::connect(url).await?;
+            let subscriber = client
+                .queue_subscribe(
+                    options.insertion_queue_topic.clone(),
+                    options.insertion_queue_group.clone(),
+                )
+                .await?;
+
+            Ok(Self {
+                client,
+                subscriber: Mutex::new(subscriber),
+                status_topic: options.insertion_status_topic.clone(),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl MessageQueue for Nats {
+        async fn receive(&self) -> AnyhowResult<Option<Vec<u8>>> {
+            let message = self.subscriber.lock().await.next().await;
+            Ok(message.map(|message| message.payload.to_vec()))
+        }
+
+        async fn commit(&self) -> AnyhowResult<()> {
+            Ok(())
+        }
+
+        async fn publish(&self, _key: &str, payload: Vec<u8>) -> AnyhowResult<()> {
+            self.client
+                .publish(self.status_topic.clone(), payload.into())
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "kafka")]
+mod kafka {
+    use rdkafka::config::ClientConfig;
+    use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+    use rdkafka::producer::{FutureProducer, FutureRecord};
+    use rdkafka::Message;
+
+    use super::*;
+
+    /// Offsets are committed once a message was handled, so messages are
+    /// delivered at least once.
+    pub struct Kafka {
+        consumer:     StreamConsumer,
+        producer:     FutureProducer,
+        status_topic: String,
+    }
+
+    impl Kafka {
+        pub fn connect(servers: &str, options: &Options) -> AnyhowResult<Self> {
+            let consumer: StreamConsumer = ClientConfig::new()
+                .set("bootstrap.servers", servers)
+                .set("group.id", &options.insertion_queue_group)
+                .set("enable.auto.commit", "false")
+                .create()?;
+            consumer.subscribe(&[&options.insertion_queue_topic])?;
+
+            let producer: FutureProducer = ClientConfig::new()
+                .set("bootstrap.servers", servers)
+                .create()?;
+
+            Ok(Self {
+                consumer,
+                producer,
+                status_topic: options.insertion_status_topic.clone(),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl MessageQueue for Kafka {
+        async fn receive(&self) -> AnyhowResult<Option<Vec<u8>>> {
+            let message = self.consumer.recv().await?;
+            Ok(Some(message.payload().unwrap_or_default().to_vec()))
+        }
+
+        async fn commit(&self) -> AnyhowResult<()> {
+            self.consumer.commit_consumer_state(CommitMode::Async)?;
+            Ok(())
+        }
+
+        async fn publish(&self, key: &str, payload: Vec<u8>) -> AnyhowResult<()> {
+            let record = FutureRecord::to(&self.status_topic)
+                .key(key)
+                .payload(&payload);
+            self.producer
+                .send(record, Duration::from_secs(30))
+                .await
+                .map_err(|(error, _)| error)?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::types::IdentityEventEntry;
+    use crate::identity_tree::IdentityEvent;
+
+    #[test]
+    fn parses_brokers() {
+        assert_eq!(
+            "nats://localhost:4222".parse::<Broker>().unwrap(),
+            Broker::Nats("nats://localhost:4222".to_owned())
+        );
+        assert_eq!(
+            "kafka://broker-1:9092,broker-2:9092"
+                .parse::<Broker>()
+                .unwrap(),
+            Broker::Kafka("broker-1:9092,broker-2:9092".to_owned())
+        );
+        assert!("amqp://localhost".parse::<Broker>().is_err());
+    }
+
+    #[test]
+    fn status_messages() {
+        let timestamp = Utc::now();
+        let status = StatusMessage::from(LoggedIdentityEvent {
+            id:         7,
+            commitment: Hash::from(42),
+            event:      IdentityEventEntry {
+                event:      IdentityEvent::Mined,
+                message:    None,
+                created_at: timestamp,
+            },
+        });
+
+        assert_eq!(status.key(), format!("{:#x}", Hash::from(42)));
+        assert_eq!(
+            serde_json::to_value(&status).unwrap(),
+            serde_json::json!({
+                "identityCommitment": Hash::from(42),
+                "event": "mined",
+                "timestamp": timestamp,
+            })
+        );
+
+        let rejection = StatusMessage::rejected(None, "invalid JSON".to_owned());
+        assert_eq!(rejection.key(), "");
+        assert_eq!(rejection.message.as_deref(), Some("invalid JSON"));
+    }
+}
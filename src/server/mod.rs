@@ -1,30 +1,75 @@
+pub mod connections;
 pub mod error;
+mod etag;
+pub mod group;
+pub mod insertion_queue;
+mod ndjson;
+mod negotiation;
+mod rpc;
+mod state;
+mod status_page;
+mod validation;
+pub mod version;
 
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
 use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::{bail, ensure, Result as AnyhowResult};
-use axum::extract::{Query, State};
+use anyhow::{bail, ensure, Context, Result as AnyhowResult};
+use axum::body::{boxed, Body, Full, HttpBody, StreamBody};
+use axum::extract::{Extension, Path, Query, State};
+use axum::http::header::{CONTENT_ENCODING, CONTENT_TYPE, VARY};
+use axum::http::{HeaderMap, HeaderValue};
+use axum::response::{Html, IntoResponse, Response};
 use axum::routing::{get, post};
 use axum::{middleware, Json, Router};
+use chrono::Utc;
 use clap::Parser;
 use cli_batteries::await_shutdown;
 use error::Error;
 use hyper::StatusCode;
-use tracing::info;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::{error, info};
 use url::{Host, Url};
+use validation::ValidatedJson;
+use version::ApiVersion;
 
+pub use self::custom_middleware::auth_layer::API_KEY_HEADER;
+use self::custom_middleware::auth_layer::{ApiKeys, Scope};
+use self::custom_middleware::compression_layer::StreamEncoder;
+use self::custom_middleware::timeout_layer::EndpointTimeouts;
+use self::ndjson::{Line, Lines};
+pub use self::state::AppState;
+use self::state::ReadyApp;
+use self::status_page::{StatusPage, RECENT_BATCHES};
 use crate::app::App;
+use crate::database::types::IdempotentResponse;
+use crate::idempotency::{self, Reservation, IDEMPOTENT_REPLAYED_HEADER};
+use crate::identity_tree::{Hash, VersionGcReport};
+use crate::receipts::InsertionReceipt;
+use crate::secret::{Secret, SecretResolver};
+use crate::serde_utils::JsonStrWrapper;
 
 mod custom_middleware;
 pub mod data;
 
 use self::data::{
-    AddBatchSizeRequest, DeletionRequest, IdentityHistoryRequest, IdentityHistoryResponse,
-    InclusionProofRequest, InclusionProofResponse, InsertCommitmentRequest, ListBatchSizesResponse,
-    RecoveryRequest, RemoveBatchSizeRequest, ToResponseCode, VerifySemaphoreProofQuery,
-    VerifySemaphoreProofRequest, VerifySemaphoreProofResponse,
+    AddBatchSizeRequest, BatchesQuery, BatchesResponse, BulkInsertionResult, CheckRootRequest,
+    CheckRootResponse, CostsQuery, CostsResponse, DeletionRequest, FailedBatchEntry,
+    FailedBatchesResponse, HealthResponse, IdentityHistoryRequest, IdentityHistoryResponse,
+    IdentityStatusRequest, IdentityStatusResponse, InclusionProofQuery, InclusionProofRequest,
+    InclusionProofResponse, InsertCommitmentRequest, InsertionReceiptResponse, LeavesQuery,
+    LeavesResponse, ListBatchSizesResponse, NonInclusionProofResponse, ProofEncoding, ProofFormat,
+    ProverKeysResponse, QueueQuery, QueueResponse, RangeProofQuery, RangeProofResponse,
+    RecoveryRequest, RemoveBatchSizeRequest, RestoreTreeRequest, RestoreTreeResponse,
+    RollBackInsertionsRequest, RollBackInsertionsResponse, RootsQuery, RootsResponse,
+    SignerActivityQuery, SignerActivityResponse, SyncUpdatesQuery, SyncUpdatesResponse,
+    ToResponseCode, TreeInfoResponse, TreeNodesQuery, VerifySemaphoreProofQuery,
+    VerifySemaphoreProofRequest, VerifySemaphoreProofResponse, VersionResponse, SYNC_ROOT_HEADER,
+    SYNC_UPDATE_ID_HEADER,
 };
 
 #[derive(Clone, Debug, PartialEq, Eq, Parser)]
@@ -38,35 +83,513 @@ pub struct Options {
     /// Request handling timeout (seconds)
     #[clap(long, env, default_value = "300")]
     pub serve_timeout: u64,
+
+    /// Timeouts (seconds) overriding `serve_timeout` for individual endpoints,
+    /// keyed by route, e.g. `{"/inclusionProof": 5}`
+    #[clap(long, env, default_value = "{}")]
+    pub endpoint_timeouts: JsonStrWrapper<HashMap<String, u64>>,
+
+    /// API keys and their scopes (`read-proofs`, `read-all`, `write` or
+    /// `admin`), e.g. `{"<key>": ["read-proofs"]}`. Keys are sent in the
+    /// `X-Api-Key` header. Without keys every endpoint is public, but the ones
+    /// rewriting the tree or the queue, which are only served once an `admin`
+    /// key is configured. Can be an `env:`, `file:` or `vault:` reference to
+    /// the JSON.
+    #[clap(long, env, default_value = "{}")]
+    pub api_keys: Secret,
+
+    /// Serve a JSON-RPC 2.0 endpoint at `/rpc`, with the methods
+    /// `sequencer_insertIdentity`, `sequencer_inclusionProof` and
+    /// `sequencer_status`.
+    #[clap(long, env)]
+    pub json_rpc: bool,
+
+    #[clap(flatten)]
+    pub connections: connections::Options,
+
+    #[clap(flatten)]
+    pub insertion_queue: insertion_queue::Options,
+}
+
+impl Options {
+    /// Resolves `--api-keys` if it's a reference, and checks that the keys
+    /// parse.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the reference can't be resolved or the keys are
+    /// invalid.
+    pub async fn resolve_api_keys(&mut self, resolver: &SecretResolver) -> AnyhowResult<()> {
+        self.api_keys
+            .resolve(resolver)
+            .await
+            .context("Failed to resolve --api-keys")?;
+        self.parse_api_keys()?;
+
+        Ok(())
+    }
+
+    fn parse_api_keys(&self) -> AnyhowResult<HashMap<String, HashSet<Scope>>> {
+        serde_json::from_str(self.api_keys.expose()).context("Failed to parse --api-keys")
+    }
 }
 
 async fn inclusion_proof(
-    State(app): State<Arc<App>>,
-    Json(inclusion_proof_request): Json<InclusionProofRequest>,
-) -> Result<(StatusCode, Json<InclusionProofResponse>), Error> {
-    let result = app
-        .inclusion_proof(&inclusion_proof_request.identity_commitment)
+    State(state): State<Arc<AppState>>,
+    Extension(version): Extension<ApiVersion>,
+    Query(inclusion_proof_query): Query<InclusionProofQuery>,
+    headers: HeaderMap,
+    ValidatedJson(inclusion_proof_request): ValidatedJson<InclusionProofRequest>,
+) -> Result<Response, Error> {
+    let commitment = inclusion_proof_request.identity_commitment;
+    let format = inclusion_proof_query.format;
+
+    let result = match state.app() {
+        Some(app) => {
+            app.inclusion_proof(&commitment, version != ApiVersion::V1)
+                .await?
+        }
+        // Mined identities are served from the snapshot while the tree is
+        // still being rebuilt, or for good when offline.
+        None => {
+            let missing = if state.offline_snapshot().is_some() {
+                Error::IdentityCommitmentNotFound
+            } else {
+                Error::NotReady
+            };
+
+            state
+                .snapshot()
+                .and_then(|snapshot| snapshot.inclusion_proof(&commitment))
+                .map(InclusionProofResponse)
+                .ok_or(missing)?
+        }
+    };
+
+    // The deleted status isn't part of `/v1`, whose clients were told the
+    // commitment is invalid.
+    if version == ApiVersion::V1 && result.is_deleted() {
+        return Err(Error::InvalidCommitment);
+    }
+
+    let result = result.hide_processed_status().for_version(version);
+    let encoding = ProofEncoding::from_headers(&headers);
+    let etag = result.etag(&commitment, format, encoding);
+
+    Ok(etag::respond(&headers, etag, || {
+        let status_code = result.to_response_code();
+
+        match format {
+            ProofFormat::Default => encode(status_code, encoding, &result),
+            ProofFormat::SemaphoreJs => {
+                encode(status_code, encoding, &result.to_semaphore_js(commitment))
+            }
+        }
+    }))
+}
+
+fn encode(status_code: StatusCode, encoding: ProofEncoding, value: &impl Serialize) -> Response {
+    match encoding {
+        ProofEncoding::Json => (status_code, Json(value)).into_response(),
+        ProofEncoding::Cbor => {
+            let mut body = vec![];
+            match ciborium::into_writer(value, &mut body) {
+                Ok(()) => {
+                    (status_code, [(CONTENT_TYPE, encoding.content_type())], body).into_response()
+                }
+                Err(error) => {
+                    error!(?error, "Failed to encode response as CBOR");
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+            }
+        }
+        // Structs are encoded as maps, keyed like the JSON fields.
+        ProofEncoding::MessagePack => match rmp_serde::to_vec_named(value) {
+            Ok(body) => {
+                (status_code, [(CONTENT_TYPE, encoding.content_type())], body).into_response()
+            }
+            Err(error) => {
+                error!(?error, "Failed to encode response as MessagePack");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+    }
+}
+
+async fn non_inclusion_proof(
+    ReadyApp(app): ReadyApp,
+    ValidatedJson(non_inclusion_proof_request): ValidatedJson<InclusionProofRequest>,
+) -> Result<(StatusCode, Json<NonInclusionProofResponse>), Error> {
+    let attestation = app
+        .non_inclusion_proof(&non_inclusion_proof_request.identity_commitment)
         .await?;
 
-    let result = result.hide_processed_status();
+    let result = NonInclusionProofResponse::from(attestation);
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn health(State(state): State<Arc<AppState>>) -> (StatusCode, Json<HealthResponse>) {
+    let result = match (state.app(), state.offline_snapshot()) {
+        (Some(app), _) => app.health(),
+        (None, Some(_)) => HealthResponse::offline(),
+        (None, None) => HealthResponse::syncing(state.progress().percentage()),
+    };
+
+    (result.to_response_code(), Json(result))
+}
+
+async fn tree_info(
+    State(state): State<Arc<AppState>>,
+    Extension(version): Extension<ApiVersion>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let result = match (state.app(), state.offline_snapshot()) {
+        (Some(app), _) => app.tree_info().await?,
+        (None, Some((snapshot, _))) => TreeInfoResponse::from_snapshot(snapshot),
+        (None, None) => return Err(Error::NotReady),
+    };
+    let etag = result.etag();
+    let result = result.for_version(version);
+
+    Ok(etag::respond(&headers, Some(etag), || {
+        (result.to_response_code(), Json(result)).into_response()
+    }))
+}
+
+async fn range_proof(
+    ReadyApp(app): ReadyApp,
+    Query(query): Query<RangeProofQuery>,
+) -> Result<(StatusCode, Json<RangeProofResponse>), Error> {
+    let result = RangeProofResponse(app.range_proof(query.from, query.to).await?);
 
     Ok((result.to_response_code(), Json(result)))
 }
 
+/// Number of nodes sent to `/treeNodes` clients at once.
+const TREE_NODES_CHUNK_SIZE: usize = 1024;
+
+async fn tree_nodes(
+    ReadyApp(app): ReadyApp,
+    headers: HeaderMap,
+    Query(query): Query<TreeNodesQuery>,
+) -> Response {
+    let nodes = app.tree_nodes(query.from_level);
+    let mut encoder = StreamEncoder::negotiate(&headers, app.export_zstd_level());
+    let content_encoding = encoder.content_encoding();
+    let (sender, mut receiver) = mpsc::channel::<Vec<u8>>(4);
+
+    // Nodes are read from proofs of the tree, which is kept off the runtime.
+    tokio::task::spawn_blocking(move || {
+        let mut chunk = String::new();
+
+        for (count, node) in nodes.enumerate() {
+            chunk.push_str(&serde_json::to_string(&node).expect("nodes serialize to JSON"));
+            chunk.push('\n');
+
+            if (count + 1) % TREE_NODES_CHUNK_SIZE == 0 {
+                // The client disconnected if sending fails.
+                let Ok(bytes) = encoder.encode(std::mem::take(&mut chunk)) else {
+                    return;
+                };
+                if sender.blocking_send(bytes).is_err() {
+                    return;
+                }
+            }
+        }
+
+        if !chunk.is_empty() {
+            let Ok(bytes) = encoder.encode(chunk) else {
+                return;
+            };
+            _ = sender.blocking_send(bytes);
+        }
+
+        match encoder.finish() {
+            Ok(bytes) if !bytes.is_empty() => _ = sender.blocking_send(bytes),
+            Ok(_) => {}
+            Err(error) => error!(?error, "Error finishing tree nodes stream"),
+        }
+    });
+
+    let body = StreamBody::new(async_stream::stream! {
+        while let Some(chunk) = receiver.recv().await {
+            yield Ok::<_, Infallible>(chunk);
+        }
+    });
+
+    let mut response = ([(CONTENT_TYPE, "application/x-ndjson")], body).into_response();
+    if let Some(content_encoding) = content_encoding {
+        let headers = response.headers_mut();
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static(content_encoding));
+        headers.insert(VARY, HeaderValue::from_static("accept-encoding"));
+    }
+
+    response
+}
+
+/// Longest a `/sync/updates` request waits for updates to be written.
+const MAX_SYNC_WAIT: Duration = Duration::from_secs(30);
+
+async fn sync_snapshot(ReadyApp(app): ReadyApp) -> Result<Response, Error> {
+    let snapshot = app.sync_snapshot().await?;
+
+    Ok((
+        [
+            (CONTENT_TYPE.as_str(), "application/octet-stream".to_owned()),
+            (SYNC_ROOT_HEADER, format!("{:#x}", snapshot.root)),
+            (SYNC_UPDATE_ID_HEADER, snapshot.update_id.to_string()),
+        ],
+        snapshot.bytes,
+    )
+        .into_response())
+}
+
+async fn sync_updates(
+    ReadyApp(app): ReadyApp,
+    Query(query): Query<SyncUpdatesQuery>,
+) -> Result<Json<SyncUpdatesResponse>, Error> {
+    let wait = Duration::from_secs(query.wait_seconds).min(MAX_SYNC_WAIT);
+
+    Ok(Json(app.sync_updates(query.after, wait).await?))
+}
+
+async fn check_root(
+    State(state): State<Arc<AppState>>,
+    Extension(version): Extension<ApiVersion>,
+    ValidatedJson(req): ValidatedJson<CheckRootRequest>,
+) -> Result<(StatusCode, Json<CheckRootResponse>), Error> {
+    let result = match (state.app(), state.offline_snapshot()) {
+        (Some(app), _) => app.check_root(&req.root).await?,
+        (None, Some((snapshot, written_at))) => {
+            CheckRootResponse::from_snapshot(snapshot, written_at, &req.root)
+                .ok_or(Error::InvalidRoot)?
+        }
+        (None, None) => return Err(Error::NotReady),
+    };
+
+    let result = result.hide_processed_status().for_version(version);
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+/// Insertions with an `Idempotency-Key` header are processed once per key, the
+/// response is stored and replayed to retries. Server errors aren't stored, so
+/// that those can be retried with the same key. Payloads failing validation
+/// are rejected before the key is used.
 async fn insert_identity(
-    State(app): State<Arc<App>>,
-    Json(insert_identity_request): Json<InsertCommitmentRequest>,
-) -> Result<(), Error> {
-    app.insert_identity(insert_identity_request.identity_commitment)
+    ReadyApp(app): ReadyApp,
+    headers: HeaderMap,
+    ValidatedJson(insert_identity_request): ValidatedJson<InsertCommitmentRequest>,
+) -> Result<Response, Error> {
+    let Some(key) = idempotency::key(&headers)? else {
+        return Ok(respond_to_insertion(&app, &headers, insert_identity_request).await);
+    };
+
+    let fingerprint = idempotency::fingerprint(&insert_identity_request);
+    let guard = match app.reserve_idempotency_key(key, fingerprint).await? {
+        Reservation::New(guard) => guard,
+        Reservation::Replay(response) => return Ok(replay_response(response)),
+    };
+
+    let response = respond_to_insertion(&app, &headers, insert_identity_request).await;
+    if response.status().is_server_error() {
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = hyper::body::to_bytes(body)
+        .await
+        .map_err(|error| Error::Other(error.into()))?;
+
+    let stored = IdempotentResponse {
+        status:       parts.status.as_u16(),
+        content_type: parts
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned),
+        body:         body.to_vec(),
+    };
+    if let Err(error) = guard.complete(&stored).await {
+        error!(?error, "Failed to store idempotent response");
+    }
+
+    Ok(Response::from_parts(parts, boxed(Full::from(body))))
+}
+
+/// Replays a stored response, marked as such.
+fn replay_response(stored: IdempotentResponse) -> Response {
+    let status = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let mut response = (status, stored.body).into_response();
+
+    let headers = response.headers_mut();
+    match stored.content_type.map(HeaderValue::try_from) {
+        Some(Ok(content_type)) => {
+            headers.insert(CONTENT_TYPE, content_type);
+        }
+        _ => {
+            headers.remove(CONTENT_TYPE);
+        }
+    }
+    headers.insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+
+    response
+}
+
+async fn respond_to_insertion(
+    app: &App,
+    headers: &HeaderMap,
+    request: InsertCommitmentRequest,
+) -> Response {
+    let receipt = match accept_insertion(app, headers, request).await {
+        Ok(receipt) => receipt,
+        Err(error) => return error.into_response(),
+    };
+    let queue_estimate = app
+        .queue_estimate(&receipt.identity_commitment, receipt.timestamp)
+        .await;
+
+    let result = InsertionReceiptResponse {
+        receipt,
+        queue_estimate,
+    };
+
+    (result.to_response_code(), Json(result)).into_response()
+}
+
+/// Runs the checks of the deployment on a submission and inserts it.
+async fn accept_insertion(
+    app: &App,
+    headers: &HeaderMap,
+    request: InsertCommitmentRequest,
+) -> Result<InsertionReceipt, Error> {
+    // Checked before anything is recorded, so unsolved insertions are cheap to
+    // reject.
+    app.verify_insertion_challenge(
+        request.identity_commitment,
+        request.challenge_token.as_deref(),
+    )
+    .await?;
+
+    let signer =
+        app.authenticate_insertion(request.identity_commitment, request.signature.as_ref())?;
+
+    app.screen_insertion(request.identity_commitment, signer, headers)
         .await?;
 
-    Ok(())
+    app.insert_identity(request.identity_commitment, request.metadata, signer)
+        .await
+}
+
+/// Inserts identities from a newline delimited JSON body of
+/// [`InsertCommitmentRequest`]s. Lines are processed as they arrive and their
+/// results streamed back, so that memory use doesn't grow with the import.
+async fn insert_identities(
+    ReadyApp(app): ReadyApp,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, Error> {
+    let is_ndjson = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-ndjson"));
+
+    if !is_ndjson {
+        return Err(Error::InvalidContentType);
+    }
+
+    let results = async_stream::stream! {
+        let mut lines = Lines::new(validation::MAX_PAYLOAD_SIZE);
+        let mut body = body;
+
+        loop {
+            let (chunk, end) = match body.data().await {
+                Some(Ok(chunk)) => (lines.push(&chunk), false),
+                Some(Err(error)) => {
+                    error!(?error, "Error reading bulk insertion body");
+                    break;
+                }
+                None => (lines.finish().into_iter().collect(), true),
+            };
+
+            for line in chunk {
+                let result = insert_line(&app, &headers, line).await;
+                let mut json = serde_json::to_string(&result).expect("results serialize to JSON");
+                json.push('\n');
+
+                yield Ok::<_, Infallible>(json);
+            }
+
+            if end {
+                break;
+            }
+        }
+    };
+
+    Ok((
+        [(CONTENT_TYPE, "application/x-ndjson")],
+        StreamBody::new(results),
+    )
+        .into_response())
+}
+
+async fn insert_line(app: &App, headers: &HeaderMap, line: Line) -> BulkInsertionResult {
+    let mut result = BulkInsertionResult {
+        line:                0,
+        identity_commitment: None,
+        receipt:             None,
+        error:               None,
+    };
+
+    let bytes = match line {
+        Line::Complete { number, bytes } => {
+            result.line = number;
+            bytes
+        }
+        Line::TooLong { number } => {
+            result.line = number;
+            result.error = Some(format!(
+                "line exceeds {} bytes",
+                validation::MAX_PAYLOAD_SIZE
+            ));
+            return result;
+        }
+    };
+
+    let request = match validation::validate::<InsertCommitmentRequest>(&bytes) {
+        Ok(request) => request,
+        Err(error) => {
+            result.error = Some(error.to_string());
+            return result;
+        }
+    };
+
+    result.identity_commitment = Some(request.identity_commitment);
+    match accept_insertion(app, headers, request).await {
+        Ok(receipt) => result.receipt = Some(receipt),
+        Err(error) => result.error = Some(error.to_string()),
+    }
+
+    result
+}
+
+async fn insertion_receipt(
+    ReadyApp(app): ReadyApp,
+    Path(commitment): Path<Hash>,
+) -> Result<(StatusCode, Json<InsertionReceiptResponse>), Error> {
+    let receipt = app.insertion_receipt(&commitment).await?;
+
+    let result = InsertionReceiptResponse::from(receipt);
+
+    Ok((result.to_response_code(), Json(result)))
 }
 
 async fn verify_semaphore_proof(
-    State(app): State<Arc<App>>,
+    ReadyApp(app): ReadyApp,
+    Extension(version): Extension<ApiVersion>,
     Query(verify_semaphore_proof_query): Query<VerifySemaphoreProofQuery>,
-    Json(verify_semaphore_proof_request): Json<VerifySemaphoreProofRequest>,
+    ValidatedJson(verify_semaphore_proof_request): ValidatedJson<VerifySemaphoreProofRequest>,
 ) -> Result<(StatusCode, Json<VerifySemaphoreProofResponse>), Error> {
     let result = app
         .verify_semaphore_proof(
@@ -75,20 +598,21 @@ async fn verify_semaphore_proof(
         )
         .await?;
 
-    let result = result.hide_processed_status();
+    let result = result.hide_processed_status().for_version(version);
 
     Ok((result.to_response_code(), Json(result)))
 }
 
 async fn add_batch_size(
-    State(app): State<Arc<App>>,
-    Json(req): Json<AddBatchSizeRequest>,
+    ReadyApp(app): ReadyApp,
+    ValidatedJson(req): ValidatedJson<AddBatchSizeRequest>,
 ) -> Result<(), Error> {
     app.add_batch_size(
         req.url,
         req.batch_size,
         req.timeout_seconds,
         req.prover_type,
+        req.key_digest,
     )
     .await?;
 
@@ -96,16 +620,16 @@ async fn add_batch_size(
 }
 
 async fn delete_identity(
-    State(app): State<Arc<App>>,
-    Json(req): Json<DeletionRequest>,
+    ReadyApp(app): ReadyApp,
+    ValidatedJson(req): ValidatedJson<DeletionRequest>,
 ) -> Result<(), Error> {
     app.delete_identity(&req.identity_commitment).await?;
     Ok(())
 }
 
 async fn recover_identity(
-    State(app): State<Arc<App>>,
-    Json(req): Json<RecoveryRequest>,
+    ReadyApp(app): ReadyApp,
+    ValidatedJson(req): ValidatedJson<RecoveryRequest>,
 ) -> Result<(), Error> {
     app.recover_identity(
         &req.previous_identity_commitment,
@@ -117,17 +641,28 @@ async fn recover_identity(
 }
 
 async fn identity_history(
-    State(app): State<Arc<App>>,
-    Json(req): Json<IdentityHistoryRequest>,
+    ReadyApp(app): ReadyApp,
+    ValidatedJson(req): ValidatedJson<IdentityHistoryRequest>,
 ) -> Result<Json<IdentityHistoryResponse>, Error> {
     let history = app.identity_history(&req.identity_commitment).await?;
 
-    Ok(Json(IdentityHistoryResponse { history }))
+    let metadata = app.identity_metadata(&req.identity_commitment).await?;
+
+    Ok(Json(IdentityHistoryResponse { history, metadata }))
+}
+
+async fn identity_status(
+    ReadyApp(app): ReadyApp,
+    ValidatedJson(req): ValidatedJson<IdentityStatusRequest>,
+) -> Result<Json<IdentityStatusResponse>, Error> {
+    let result = app.identity_status(&req.identity_commitment).await?;
+
+    Ok(Json(result))
 }
 
 async fn remove_batch_size(
-    State(app): State<Arc<App>>,
-    Json(req): Json<RemoveBatchSizeRequest>,
+    ReadyApp(app): ReadyApp,
+    ValidatedJson(req): ValidatedJson<RemoveBatchSizeRequest>,
 ) -> Result<(), Error> {
     app.remove_batch_size(req.batch_size, req.prover_type)
         .await?;
@@ -135,20 +670,171 @@ async fn remove_batch_size(
     Ok(())
 }
 
+async fn version_info(ReadyApp(app): ReadyApp) -> (StatusCode, Json<VersionResponse>) {
+    let result = app.version();
+
+    (result.to_response_code(), Json(result))
+}
+
 async fn list_batch_sizes(
-    State(app): State<Arc<App>>,
+    ReadyApp(app): ReadyApp,
 ) -> Result<(StatusCode, Json<ListBatchSizesResponse>), Error> {
     let result = app.list_batch_sizes().await?;
 
     Ok((result.to_response_code(), Json(result)))
 }
 
+async fn prover_keys(
+    ReadyApp(app): ReadyApp,
+) -> Result<(StatusCode, Json<ProverKeysResponse>), Error> {
+    let result = app.prover_keys().await;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn costs(
+    ReadyApp(app): ReadyApp,
+    Query(query): Query<CostsQuery>,
+) -> Result<(StatusCode, Json<CostsResponse>), Error> {
+    let result = app.costs(query.from, query.to).await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn batches(
+    ReadyApp(app): ReadyApp,
+    Query(query): Query<BatchesQuery>,
+) -> Result<(StatusCode, Json<BatchesResponse>), Error> {
+    let result = app.batches(query.limit).await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn roots(
+    ReadyApp(app): ReadyApp,
+    Query(query): Query<RootsQuery>,
+) -> Result<(StatusCode, Json<RootsResponse>), Error> {
+    let result = app.roots(query).await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn leaves(
+    ReadyApp(app): ReadyApp,
+    Query(query): Query<LeavesQuery>,
+) -> Result<(StatusCode, Json<LeavesResponse>), Error> {
+    let result = app.leaves(query).await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn status_page(ReadyApp(app): ReadyApp) -> Result<Html<String>, Error> {
+    let generated_at = Utc::now();
+
+    let mut batches = app
+        .costs(Some(generated_at - chrono::Duration::days(1)), None)
+        .await?
+        .batches;
+    batches.reverse();
+    batches.truncate(RECENT_BATCHES);
+
+    let page = StatusPage {
+        generated_at,
+        tree: app.tree_info().await?,
+        queue: app.queue(Some(0)).await?.counts,
+        health: app.health(),
+        batches,
+        sync_lag: app.sync_lag().await?,
+    };
+
+    Ok(Html(page.render()))
+}
+
+async fn signer_activity(
+    ReadyApp(app): ReadyApp,
+    Query(query): Query<SignerActivityQuery>,
+) -> Result<(StatusCode, Json<SignerActivityResponse>), Error> {
+    let result = app.signer_activity(query.from, query.to).await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn queue(
+    ReadyApp(app): ReadyApp,
+    Query(query): Query<QueueQuery>,
+) -> Result<(StatusCode, Json<QueueResponse>), Error> {
+    let result = app.queue(query.limit).await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn failed_batches(
+    ReadyApp(app): ReadyApp,
+) -> Result<(StatusCode, Json<FailedBatchesResponse>), Error> {
+    let result = app.failed_batches().await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn failed_batch(
+    ReadyApp(app): ReadyApp,
+    Path(post_root): Path<Hash>,
+) -> Result<(StatusCode, Json<FailedBatchEntry>), Error> {
+    let result = app.failed_batch(&post_root).await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn retry_failed_batch(
+    ReadyApp(app): ReadyApp,
+    Path(post_root): Path<Hash>,
+) -> Result<(), Error> {
+    app.retry_failed_batch(&post_root).await?;
+
+    Ok(())
+}
+
+async fn discard_failed_batch(
+    ReadyApp(app): ReadyApp,
+    Path(post_root): Path<Hash>,
+) -> Result<(), Error> {
+    app.discard_failed_batch(&post_root).await?;
+
+    Ok(())
+}
+
+async fn restore_tree(
+    ReadyApp(app): ReadyApp,
+    ValidatedJson(req): ValidatedJson<RestoreTreeRequest>,
+) -> Result<(StatusCode, Json<RestoreTreeResponse>), Error> {
+    let result = app.restore_tree(req.snapshot_path.as_ref()).await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn roll_back_insertions(
+    ReadyApp(app): ReadyApp,
+    ValidatedJson(req): ValidatedJson<RollBackInsertionsRequest>,
+) -> Result<(StatusCode, Json<RollBackInsertionsResponse>), Error> {
+    let result = app.roll_back_insertions(req.count).await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn gc_tree_versions(
+    ReadyApp(app): ReadyApp,
+) -> Result<(StatusCode, Json<VersionGcReport>), Error> {
+    let result = app.gc_tree_versions().await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
 /// # Errors
 ///
 /// Will return `Err` if `options.server` URI is not http, incorrectly includes
 /// a path beyond `/`, or cannot be cast into an IP address. Also returns an
 /// `Err` if the server cannot bind to the given address.
-pub async fn main(app: Arc<App>, options: Options) -> AnyhowResult<()> {
+pub async fn main(state: Arc<AppState>, options: Options) -> AnyhowResult<()> {
     ensure!(
         options.server.scheme() == "http",
         "Only http:// is supported in {}",
@@ -172,8 +858,24 @@ pub async fn main(app: Arc<App>, options: Options) -> AnyhowResult<()> {
     info!("Will listen on {}", addr);
     let listener = TcpListener::bind(addr)?;
 
-    let serve_timeout = Duration::from_secs(options.serve_timeout);
-    bind_from_listener(app, serve_timeout, listener).await?;
+    let timeouts = EndpointTimeouts::new(Duration::from_secs(options.serve_timeout))
+        .with_endpoints(
+            options
+                .endpoint_timeouts
+                .0
+                .into_iter()
+                .map(|(endpoint, seconds)| (endpoint, Duration::from_secs(seconds))),
+        );
+    let api_keys = ApiKeys::new(options.parse_api_keys()?);
+    serve_from_listener(
+        state,
+        timeouts,
+        api_keys,
+        options.json_rpc,
+        &options.connections,
+        listener,
+    )
+    .await?;
 
     Ok(())
 }
@@ -187,23 +889,139 @@ pub async fn bind_from_listener(
     serve_timeout: Duration,
     listener: TcpListener,
 ) -> AnyhowResult<()> {
-    let router = Router::new()
+    serve_from_listener(
+        Arc::new(AppState::ready(app)),
+        EndpointTimeouts::new(serve_timeout),
+        ApiKeys::default(),
+        false,
+        &connections::Options::default(),
+        listener,
+    )
+    .await
+}
+
+/// The routes of the API, served to handlers as the given version.
+fn routes(version: ApiVersion) -> Router<Arc<AppState>> {
+    Router::new()
         .route("/verifySemaphoreProof", post(verify_semaphore_proof))
         .route("/inclusionProof", post(inclusion_proof))
+        .route("/nonInclusionProof", post(non_inclusion_proof))
+        .route("/treeInfo", get(tree_info))
+        .route("/treeNodes", get(tree_nodes))
+        .route("/rangeProof", get(range_proof))
+        .route("/roots", get(roots))
+        .route("/leaves", get(leaves))
+        .route("/checkRoot", post(check_root))
         .route("/insertIdentity", post(insert_identity))
+        .route("/insertIdentities", post(insert_identities))
         .route("/deleteIdentity", post(delete_identity))
         .route("/recoverIdentity", post(recover_identity))
         .route("/identityHistory", post(identity_history))
+        .route("/identityStatus", post(identity_status))
+        .route("/receipts/:commitment", get(insertion_receipt))
+        // Followers replicating the tree
+        .route("/sync/snapshot", get(sync_snapshot))
+        .route("/sync/updates", get(sync_updates))
         // Operate on batch sizes
         .route("/addBatchSize", post(add_batch_size))
         .route("/removeBatchSize", post(remove_batch_size))
         .route("/listBatchSizes", get(list_batch_sizes))
+        .route("/admin/proverKeys", get(prover_keys))
+        // Operational introspection
+        .route("/health", get(health))
+        .route("/version", get(version_info))
+        .route("/status", get(status_page))
+        .route("/admin/queue", get(queue))
+        .route("/admin/costs", get(costs))
+        .route("/admin/batches", get(batches))
+        .route("/admin/signer", get(signer_activity))
+        .route("/admin/failedBatches", get(failed_batches))
+        .route(
+            "/admin/failedBatches/:post_root",
+            get(failed_batch).delete(discard_failed_batch),
+        )
+        .route(
+            "/admin/failedBatches/:post_root/retry",
+            post(retry_failed_batch),
+        )
+        .route("/admin/restoreTree", post(restore_tree))
+        .route("/admin/rollBackInsertions", post(roll_back_insertions))
+        .route("/admin/gcTreeVersions", post(gc_tree_versions))
+        .layer(Extension(version))
+}
+
+/// The read-only routes served offline from a snapshot.
+fn offline_routes(version: ApiVersion) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/inclusionProof", post(inclusion_proof))
+        .route("/treeInfo", get(tree_info))
+        .route("/checkRoot", post(check_root))
+        .route("/health", get(health))
+        .layer(Extension(version))
+}
+
+/// The routes of every version of the API, unprefixed routes serving
+/// [`ApiVersion::UNPREFIXED`].
+fn versioned_routes(routes: fn(ApiVersion) -> Router<Arc<AppState>>) -> Router<Arc<AppState>> {
+    ApiVersion::ALL
+        .into_iter()
+        .fold(routes(ApiVersion::UNPREFIXED), |router, version| {
+            router.nest(version.prefix(), routes(version))
+        })
+}
+
+async fn serve_from_listener(
+    state: Arc<AppState>,
+    timeouts: EndpointTimeouts,
+    api_keys: ApiKeys,
+    json_rpc: bool,
+    connections: &connections::Options,
+    listener: TcpListener,
+) -> AnyhowResult<()> {
+    // Offline there's no app to handle the other routes.
+    let offline = state.offline_snapshot().is_some();
+    let api_routes = if offline { offline_routes } else { routes };
+
+    // The JSON-RPC endpoint isn't versioned, calls are answered like the
+    // latest version of the REST routes.
+    let serve_rpc = json_rpc && !offline;
+    let all_routes = || {
+        let router = versioned_routes(api_routes);
+        if serve_rpc {
+            router.route("/rpc", post(rpc::handler))
+        } else {
+            router
+        }
+    };
+
+    let api_keys = Arc::new(api_keys);
+    let router = state
+        .groups()
+        .iter()
+        .fold(all_routes(), |router, (id, group_state)| {
+            router.nest(
+                &group::prefix(id),
+                all_routes().with_state(group_state.clone()),
+            )
+        })
+        // Applied to matched routes only, so that timeouts are looked up and
+        // counted by route rather than by raw path.
+        .route_layer(middleware::from_fn_with_state(
+            Arc::new(timeouts),
+            custom_middleware::timeout_layer::middleware,
+        ))
+        // Checked before the handler's timeout starts.
+        .route_layer(middleware::from_fn_with_state(
+            api_keys.clone(),
+            custom_middleware::auth_layer::middleware,
+        ))
+        // Calls to `/rpc` are authorized by method.
+        .layer(Extension(api_keys))
         .layer(middleware::from_fn(
-            custom_middleware::api_metrics_layer::middleware,
+            custom_middleware::compression_layer::middleware,
         ))
-        .layer(middleware::from_fn_with_state(
-            serve_timeout,
-            custom_middleware::timeout_layer::middleware,
+        .layer(middleware::from_fn(
+            custom_middleware::api_metrics_layer::middleware,
         ))
         .layer(middleware::from_fn(
             custom_middleware::logging_layer::middleware,
@@ -211,9 +1029,10 @@ pub async fn bind_from_listener(
         .layer(middleware::from_fn(
             custom_middleware::remove_auth_layer::middleware,
         ))
-        .with_state(app.clone());
+        .with_state(state);
 
-    let server = axum::Server::from_tcp(listener)?
+    let server = connections
+        .server(listener)?
         .serve(router.into_make_service())
         .with_graceful_shutdown(await_shutdown());
 
@@ -0,0 +1,130 @@
+//! Incremental splitting of newline delimited JSON request bodies, so that
+//! bulk requests are processed as they arrive rather than buffered in full.
+
+/// A line of the body, numbered from 1.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Line {
+    Complete {
+        number: usize,
+        bytes:  Vec<u8>,
+    },
+    /// The line was longer than the limit and has been discarded.
+    TooLong {
+        number: usize,
+    },
+}
+
+/// Splits chunks of a body into lines, holding at most one partial line of up
+/// to `max_length` bytes. Blank lines are skipped, but still counted.
+#[derive(Debug)]
+pub struct Lines {
+    max_length: usize,
+    buffer:     Vec<u8>,
+    discarding: bool,
+    number:     usize,
+}
+
+impl Lines {
+    #[must_use]
+    pub const fn new(max_length: usize) -> Self {
+        Self {
+            max_length,
+            buffer: Vec::new(),
+            discarding: false,
+            number: 0,
+        }
+    }
+
+    /// Returns the lines completed by `chunk`.
+    pub fn push(&mut self, mut chunk: &[u8]) -> Vec<Line> {
+        let mut lines = vec![];
+
+        while let Some(end) = chunk.iter().position(|byte| *byte == b'\n') {
+            self.extend(&chunk[..end]);
+            lines.extend(self.take());
+            chunk = &chunk[end + 1..];
+        }
+
+        self.extend(chunk);
+        lines
+    }
+
+    /// Returns the last line, if the body doesn't end with a newline.
+    pub fn finish(&mut self) -> Option<Line> {
+        self.take()
+    }
+
+    fn extend(&mut self, bytes: &[u8]) {
+        if self.discarding {
+            return;
+        }
+
+        if self.buffer.len() + bytes.len() > self.max_length {
+            self.discarding = true;
+            self.buffer = Vec::new();
+        } else {
+            self.buffer.extend_from_slice(bytes);
+        }
+    }
+
+    fn take(&mut self) -> Option<Line> {
+        self.number += 1;
+        let number = self.number;
+
+        if std::mem::take(&mut self.discarding) {
+            return Some(Line::TooLong { number });
+        }
+
+        let bytes = std::mem::take(&mut self.buffer);
+        if bytes.iter().all(u8::is_ascii_whitespace) {
+            return None;
+        }
+
+        Some(Line::Complete { number, bytes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn complete(number: usize, line: &str) -> Line {
+        Line::Complete {
+            number,
+            bytes: line.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn lines_span_chunks() {
+        let mut lines = Lines::new(16);
+
+        assert_eq!(lines.push(b"{\"a\""), vec![]);
+        assert_eq!(lines.push(b":1}\n{\"b\":2}\n{\"c\""), vec![
+            complete(1, "{\"a\":1}"),
+            complete(2, "{\"b\":2}"),
+        ]);
+        assert_eq!(lines.push(b":3}"), vec![]);
+        assert_eq!(lines.finish(), Some(complete(3, "{\"c\":3}")));
+        assert_eq!(lines.finish(), None);
+    }
+
+    #[test]
+    fn blank_lines_are_skipped_but_counted() {
+        let mut lines = Lines::new(16);
+
+        assert_eq!(lines.push(b"\n \r\n{}\r\n"), vec![complete(3, "{}\r")]);
+        assert_eq!(lines.finish(), None);
+    }
+
+    #[test]
+    fn long_lines_are_discarded() {
+        let mut lines = Lines::new(4);
+
+        assert_eq!(lines.push(b"{\"a\""), vec![]);
+        assert_eq!(lines.push(b":1}\n{}"), vec![Line::TooLong { number: 1 }]);
+        assert_eq!(lines.push(b"123456"), vec![]);
+        assert_eq!(lines.push(b"\n"), vec![Line::TooLong { number: 2 }]);
+        assert_eq!(lines.finish(), None);
+    }
+}
@@ -0,0 +1,48 @@
+//! Parsing of the `Accept` family of request headers.
+
+use axum::http::header::HeaderName;
+use axum::http::HeaderMap;
+
+/// The values listed in every `header` of the request, without their
+/// parameters, leaving out those the client refuses with a quality of zero.
+pub fn accepted(headers: &HeaderMap, header: HeaderName) -> impl Iterator<Item = &str> {
+    headers
+        .get_all(header)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|item| {
+            let mut params = item.split(';').map(str::trim);
+            let value = params.next().unwrap_or_default();
+            let refused = params.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|quality| quality.parse::<f32>().ok())
+                    .is_some_and(|quality| quality <= 0.0)
+            });
+
+            (!refused).then_some(value)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::header::ACCEPT_ENCODING;
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn refused_values_are_left_out() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            ACCEPT_ENCODING,
+            HeaderValue::from_static("br;q=1.0, gzip;q=0, zstd"),
+        );
+        headers.append(ACCEPT_ENCODING, HeaderValue::from_static("deflate;q=0.0"));
+
+        assert_eq!(accepted(&headers, ACCEPT_ENCODING).collect::<Vec<_>>(), [
+            "br", "zstd"
+        ]);
+    }
+}
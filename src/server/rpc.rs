@@ -0,0 +1,321 @@
+//! A JSON-RPC 2.0 surface over the REST routes, for wallet tooling which only
+//! speaks JSON-RPC. Methods are dispatched to the handlers of their routes, so
+//! that both surfaces behave the same, and authorized against the scope of
+//! those routes. Params are the request bodies of the routes, by name.
+
+use std::sync::Arc;
+
+use axum::extract::{Extension, Query, State};
+use axum::http::header::{ACCEPT, IF_NONE_MATCH};
+use axum::http::{HeaderMap, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use bytes::Bytes;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Value};
+
+use super::custom_middleware::auth_layer::ApiKeys;
+use super::data::{InclusionProofQuery, ProofFormat};
+use super::error::Error;
+use super::state::{AppState, ReadyApp};
+use super::validation::{ValidatedJson, ValidationError, MAX_PAYLOAD_SIZE};
+use super::version::ApiVersion;
+use super::{health, inclusion_proof, insert_identity};
+
+/// Maximum number of calls in a batch.
+pub const MAX_BATCH_SIZE: usize = 100;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Errors of the handlers, with the HTTP status of the route as `data`.
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    method:  String,
+    #[serde(default)]
+    params:  Option<Value>,
+    /// Unset for notifications, which aren't answered. A `null` id is set.
+    #[serde(default, deserialize_with = "present")]
+    id:      Option<Value>,
+}
+
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Serialize)]
+struct RpcResponse {
+    jsonrpc: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result:  Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error:   Option<RpcError>,
+    id:      Value,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcError {
+    code:    i64,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data:    Option<Value>,
+}
+
+impl RpcResponse {
+    fn new(id: Value, result: Result<Value, RpcError>) -> Self {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+
+        Self {
+            jsonrpc: "2.0",
+            result,
+            error,
+            id,
+        }
+    }
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+/// Answers a call or a batch of calls. Calls are answered with `200 OK`
+/// whatever their outcome, batches of notifications with `204 No Content`.
+pub async fn handler(
+    State(state): State<Arc<AppState>>,
+    Extension(api_keys): Extension<Arc<ApiKeys>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if body.len() > MAX_PAYLOAD_SIZE {
+        let error = RpcError::new(
+            INVALID_REQUEST,
+            format!("payload exceeds {MAX_PAYLOAD_SIZE} bytes"),
+        );
+        return Json(RpcResponse::new(Value::Null, Err(error))).into_response();
+    }
+
+    let payload = match serde_json::from_slice::<Value>(&body) {
+        Ok(payload) => payload,
+        Err(error) => {
+            let error = RpcError::new(PARSE_ERROR, error.to_string());
+            return Json(RpcResponse::new(Value::Null, Err(error))).into_response();
+        }
+    };
+
+    let calls = match payload {
+        Value::Array(calls) => calls,
+        payload => {
+            return match call(&state, &api_keys, &headers, payload).await {
+                Some(response) => Json(response).into_response(),
+                None => StatusCode::NO_CONTENT.into_response(),
+            };
+        }
+    };
+
+    if calls.is_empty() || calls.len() > MAX_BATCH_SIZE {
+        let error = RpcError::new(
+            INVALID_REQUEST,
+            format!("batches must have 1 to {MAX_BATCH_SIZE} calls"),
+        );
+        return Json(RpcResponse::new(Value::Null, Err(error))).into_response();
+    }
+
+    let mut responses = Vec::with_capacity(calls.len());
+    for payload in calls {
+        responses.extend(call(&state, &api_keys, &headers, payload).await);
+    }
+
+    if responses.is_empty() {
+        return StatusCode::NO_CONTENT.into_response();
+    }
+
+    Json(responses).into_response()
+}
+
+/// Handles a single call, returns `None` for notifications.
+async fn call(
+    state: &Arc<AppState>,
+    api_keys: &ApiKeys,
+    headers: &HeaderMap,
+    payload: Value,
+) -> Option<RpcResponse> {
+    let request = match serde_json::from_value::<RpcRequest>(payload) {
+        Ok(request) if request.jsonrpc == "2.0" => request,
+        Ok(request) => {
+            let error = RpcError::new(INVALID_REQUEST, "`jsonrpc` must be \"2.0\"");
+            return request.id.map(|id| RpcResponse::new(id, Err(error)));
+        }
+        Err(error) => {
+            let error = RpcError::new(INVALID_REQUEST, error.to_string());
+            return Some(RpcResponse::new(Value::Null, Err(error)));
+        }
+    };
+
+    let result = dispatch(state, api_keys, headers, &request.method, request.params).await;
+
+    request.id.map(|id| RpcResponse::new(id, result))
+}
+
+async fn dispatch(
+    state: &Arc<AppState>,
+    api_keys: &ApiKeys,
+    headers: &HeaderMap,
+    method: &str,
+    params: Option<Value>,
+) -> Result<Value, RpcError> {
+    let route = match method {
+        "sequencer_insertIdentity" => "/insertIdentity",
+        "sequencer_inclusionProof" => "/inclusionProof",
+        "sequencer_status" => "/health",
+        _ => {
+            return Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("unknown method `{method}`"),
+            ))
+        }
+    };
+
+    if let Err(error) = api_keys.authorize(headers, &Method::POST, route) {
+        return into_result(error.into_response()).await;
+    }
+
+    match method {
+        "sequencer_insertIdentity" => {
+            let request = params_as(params)?;
+            let Some(app) = state.app().cloned() else {
+                return into_result(Error::NotReady.into_response()).await;
+            };
+
+            let response = insert_identity(ReadyApp(app), headers.clone(), ValidatedJson(request))
+                .await
+                .into_response();
+            into_result(response).await
+        }
+        "sequencer_inclusionProof" => {
+            let request = params_as(params)?;
+            let query = InclusionProofQuery {
+                format: ProofFormat::default(),
+            };
+
+            // Proofs are always returned as JSON, and never as `304 Not
+            // Modified`.
+            let mut headers = headers.clone();
+            headers.remove(ACCEPT);
+            headers.remove(IF_NONE_MATCH);
+
+            let response = inclusion_proof(
+                State(state.clone()),
+                Extension(ApiVersion::LATEST),
+                Query(query),
+                headers,
+                ValidatedJson(request),
+            )
+            .await
+            .into_response();
+            into_result(response).await
+        }
+        _ => {
+            // The status is reported whether or not the sequencer is healthy.
+            let (_, Json(health)) = health(State(state.clone())).await;
+            Ok(serde_json::to_value(health).expect("responses serialize to JSON"))
+        }
+    }
+}
+
+/// Deserializes the params of a call like the body of its route.
+fn params_as<T: DeserializeOwned>(params: Option<Value>) -> Result<T, RpcError> {
+    let params = params.unwrap_or_else(|| json!({}));
+
+    serde_json::from_value(params)
+        .map_err(|error| RpcError::new(INVALID_PARAMS, ValidationError::from(error).to_string()))
+}
+
+/// Turns the response of a route into the outcome of a call, the body of
+/// successful responses being the result.
+async fn into_result(response: Response) -> Result<Value, RpcError> {
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .map_err(|error| RpcError::new(SERVER_ERROR, error.to_string()))?;
+
+    if status.is_success() {
+        if body.is_empty() {
+            return Ok(Value::Null);
+        }
+
+        return serde_json::from_slice(&body)
+            .map_err(|error| RpcError::new(SERVER_ERROR, error.to_string()));
+    }
+
+    // Validation errors are JSON, the others plain text.
+    let (message, details) = match serde_json::from_slice::<Value>(&body) {
+        Ok(details) => (status.to_string(), Some(details)),
+        Err(_) => (String::from_utf8_lossy(&body).into_owned(), None),
+    };
+
+    Err(RpcError {
+        code: SERVER_ERROR,
+        message,
+        data: Some(json!({
+            "status": status.as_u16(),
+            "details": details,
+        })),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::response::IntoResponse;
+    use serde_json::{json, Value};
+
+    use super::{into_result, RpcRequest, RpcResponse, SERVER_ERROR};
+    use crate::server::error::Error;
+
+    #[test]
+    fn tells_notifications_from_null_ids() {
+        let notification: RpcRequest =
+            serde_json::from_value(json!({"jsonrpc": "2.0", "method": "sequencer_status"}))
+                .unwrap();
+        let null_id: RpcRequest = serde_json::from_value(
+            json!({"jsonrpc": "2.0", "method": "sequencer_status", "id": null}),
+        )
+        .unwrap();
+
+        assert_eq!(notification.id, None);
+        assert_eq!(null_id.id, Some(Value::Null));
+    }
+
+    #[test]
+    fn responses_carry_either_a_result_or_an_error() {
+        let response = RpcResponse::new(json!(1), Ok(Value::Null));
+        assert_eq!(
+            serde_json::to_value(response).unwrap(),
+            json!({"jsonrpc": "2.0", "result": null, "id": 1})
+        );
+    }
+
+    #[tokio::test]
+    async fn errors_of_routes_are_server_errors() {
+        let error = into_result(Error::DuplicateCommitment.into_response())
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.code, SERVER_ERROR);
+        assert_eq!(error.message, Error::DuplicateCommitment.to_string());
+        assert_eq!(error.data, Some(json!({"status": 409, "details": null})));
+    }
+}
@@ -0,0 +1,109 @@
+//! State shared by the handlers, which allows serving requests while the app
+//! is still starting up.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock};
+
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use chrono::{DateTime, Utc};
+
+use super::error::Error;
+use crate::app::{App, StartupProgress};
+use crate::identity_tree::TreeSnapshot;
+
+#[derive(Default)]
+pub struct AppState {
+    app:      OnceLock<Arc<App>>,
+    progress: Arc<StartupProgress>,
+    snapshot: Option<TreeSnapshot>,
+    /// When the snapshot was written, if it's served offline and the app never
+    /// starts
+    offline:  Option<DateTime<Utc>>,
+    /// The states of the trees served under `/groups/<id>`, by id
+    groups:   BTreeMap<String, Arc<AppState>>,
+}
+
+impl AppState {
+    /// State of an app which has already started.
+    #[must_use]
+    pub fn ready(app: Arc<App>) -> Self {
+        let state = Self::default();
+        state.set_ready(app);
+        state
+    }
+
+    /// Serves inclusion proofs of mined identities from the snapshot until
+    /// the app has started.
+    #[must_use]
+    pub fn with_snapshot(mut self, snapshot: TreeSnapshot) -> Self {
+        self.snapshot = Some(snapshot);
+        self
+    }
+
+    /// Serves the read-only routes from the snapshot only, without ever
+    /// starting the app. The snapshot isn't verified against the chain.
+    #[must_use]
+    pub fn offline(snapshot: TreeSnapshot, written_at: DateTime<Utc>) -> Self {
+        Self {
+            snapshot: Some(snapshot),
+            offline: Some(written_at),
+            ..Self::default()
+        }
+    }
+
+    /// Serves the app of another tree under `/groups/<id>`.
+    #[must_use]
+    pub fn with_group(mut self, id: String, state: Arc<Self>) -> Self {
+        self.groups.insert(id, state);
+        self
+    }
+
+    #[must_use]
+    pub const fn groups(&self) -> &BTreeMap<String, Arc<Self>> {
+        &self.groups
+    }
+
+    #[must_use]
+    pub fn progress(&self) -> &Arc<StartupProgress> {
+        &self.progress
+    }
+
+    /// Switches all handlers over to the started app.
+    pub fn set_ready(&self, app: Arc<App>) {
+        assert!(self.app.set(app).is_ok(), "App was already started");
+    }
+
+    #[must_use]
+    pub fn app(&self) -> Option<&Arc<App>> {
+        self.app.get()
+    }
+
+    #[must_use]
+    pub const fn snapshot(&self) -> Option<&TreeSnapshot> {
+        self.snapshot.as_ref()
+    }
+
+    /// The snapshot served offline and when it was written.
+    #[must_use]
+    pub fn offline_snapshot(&self) -> Option<(&TreeSnapshot, DateTime<Utc>)> {
+        self.snapshot.as_ref().zip(self.offline)
+    }
+}
+
+/// Extracts the app, rejecting requests with `503 Service Unavailable` until
+/// it has started.
+pub struct ReadyApp(pub Arc<App>);
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for ReadyApp {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        _parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        state.app().cloned().map(Self).ok_or(Error::NotReady)
+    }
+}
@@ -0,0 +1,229 @@
+//! A self-contained HTML page summarizing the state of the sequencer, for
+//! operators without access to dashboards.
+
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+use ethers::types::U256;
+use ethers::utils::format_ether;
+use serde::Serialize;
+
+use super::data::{BatchCostEntry, HealthResponse, QueueCounts, TreeInfoResponse};
+use crate::task_monitor::tasks::monitor_contract::OwnerAction;
+
+/// The number of most recent batches listed.
+pub const RECENT_BATCHES: usize = 20;
+
+/// The data rendered on the page, as served by the JSON endpoints.
+#[derive(Debug)]
+pub struct StatusPage {
+    pub generated_at: DateTime<Utc>,
+    pub tree:         TreeInfoResponse,
+    pub queue:        QueueCounts,
+    pub health:       HealthResponse,
+    /// Most recent first
+    pub batches:      Vec<BatchCostEntry>,
+    /// Blocks between the chain head and the last block whose events were
+    /// processed, `None` if unknown
+    pub sync_lag:     Option<u64>,
+}
+
+/// A value as it's serialized in JSON responses, e.g. the name of a status.
+fn label(value: &impl Serialize) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(ToOwned::to_owned))
+        .unwrap_or_default()
+}
+
+fn ether(wei: U256) -> String {
+    format!("{} ETH", format_ether(wei))
+}
+
+fn row(html: &mut String, name: &str, value: impl std::fmt::Display) {
+    _ = write!(html, "<tr><th>{name}</th><td>{value}</td></tr>");
+}
+
+impl StatusPage {
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut html = String::from(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta http-equiv=\"refresh\" \
+             content=\"30\"><title>Sequencer \
+             status</title><style>body{font-family:sans-serif;margin:2em}table{border-collapse:\
+             collapse;margin-bottom:2em}th,td{border:1px solid #ccc;padding:.3em \
+             .6em;text-align:left;font-family:monospace}</style></head><body>",
+        );
+
+        _ = write!(
+            html,
+            "<h1>Sequencer status: {}</h1><p>Generated at {}</p>",
+            label(&self.health.status),
+            self.generated_at.to_rfc3339()
+        );
+
+        html.push_str("<h2>Tree</h2><table>");
+        row(&mut html, "Depth", self.tree.tree_depth);
+        row(&mut html, "Next leaf index", self.tree.next_leaf_index);
+        row(&mut html, "Latest root", label(&self.tree.latest_root));
+        if let Some(batching_root) = self.tree.batching_root {
+            row(&mut html, "Batching root", label(&batching_root));
+        }
+        row(&mut html, "Mined root", label(&self.tree.mined_root));
+        if let Some(transaction) = &self.tree.mined_transaction {
+            row(
+                &mut html,
+                "Mined in",
+                format!(
+                    "{:?} (block {}, {})",
+                    transaction.transaction_hash,
+                    transaction.block_number,
+                    transaction.block_timestamp.to_rfc3339()
+                ),
+            );
+        }
+        match self.sync_lag {
+            Some(lag) => row(&mut html, "Sync lag", format!("{lag} blocks")),
+            None => row(&mut html, "Sync lag", "unknown"),
+        }
+        html.push_str("</table>");
+
+        html.push_str("<h2>Queue</h2><table>");
+        row(&mut html, "Buffered", self.queue.buffered);
+        row(&mut html, "Queued", self.queue.queued);
+        row(&mut html, "Failed", self.queue.failed);
+        row(&mut html, "Pending", self.queue.pending);
+        row(&mut html, "Batched", self.queue.batched);
+        html.push_str("</table>");
+
+        if let Some(signer) = &self.health.signer {
+            html.push_str("<h2>Signer</h2><table>");
+            row(&mut html, "Address", format!("{:?}", signer.address));
+            row(
+                &mut html,
+                "Balance",
+                signer.balance.map_or_else(|| "unknown".to_owned(), ether),
+            );
+            row(&mut html, "Funds", label(&signer.funds));
+            row(&mut html, "Submissions paused", signer.submissions_paused);
+            html.push_str("</table>");
+        }
+
+        // Read-only instances have no signer, but still depend on the chain
+        // and the provers.
+        html.push_str("<h2>Dependencies</h2><table>");
+        match &self.health.provider {
+            Some(provider) => row(&mut html, "Provider", label(provider)),
+            None => row(&mut html, "Provider", "unknown"),
+        }
+        match &self.health.prover {
+            Some(outage) => row(&mut html, "Prover", format!("down since {}", outage.since)),
+            None => row(&mut html, "Prover", "up"),
+        }
+        match &self.health.foreign_root {
+            Some(foreign_root) => row(
+                &mut html,
+                "Chain root",
+                format!("diverged at root {:#x}", foreign_root.root),
+            ),
+            None => row(&mut html, "Chain root", "in sync"),
+        }
+        match &self.health.contract {
+            Some(suspension) => {
+                let action = match &suspension.action {
+                    OwnerAction::Paused => "paused".to_owned(),
+                    OwnerAction::OperatorChanged { operator } => {
+                        format!("operator changed to {operator:?}")
+                    }
+                };
+                row(
+                    &mut html,
+                    "Contract",
+                    format!("{action} since {}", suspension.since),
+                );
+            }
+            None => row(&mut html, "Contract", "accepting batches"),
+        }
+        html.push_str("</table>");
+
+        html.push_str("<h2>Recent batches</h2>");
+        if self.batches.is_empty() {
+            html.push_str("<p>No batches mined recently.</p>");
+        } else {
+            html.push_str(
+                "<table><tr><th>Mined at</th><th>Kind</th><th>Identities</th><th>Gas \
+                 used</th><th>Cost</th><th>Transaction</th></tr>",
+            );
+            for batch in &self.batches {
+                _ = write!(
+                    html,
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:?}</td></tr>",
+                    batch.block_timestamp.to_rfc3339(),
+                    batch.kind,
+                    batch.identity_count,
+                    batch.gas_used,
+                    ether(batch.cost),
+                    batch.transaction_hash
+                );
+            }
+            html.push_str("</table>");
+        }
+
+        html.push_str("</body></html>");
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity_tree::Hash;
+    use crate::server::data::HealthStatus;
+
+    #[test]
+    fn renders_tree_and_queue() {
+        let page = StatusPage {
+            generated_at: Utc::now(),
+            tree:         TreeInfoResponse {
+                tree_depth:        20,
+                next_leaf_index:   3,
+                latest_root:       Hash::from(3),
+                batching_root:     Some(Hash::from(2)),
+                mined_root:        Hash::from(1),
+                mined_transaction: None,
+            },
+            queue:        QueueCounts {
+                buffered: 4,
+                queued:   0,
+                failed:   1,
+                pending:  2,
+                batched:  1,
+            },
+            health:       HealthResponse {
+                status:        HealthStatus::Ok,
+                sync_progress: None,
+                signer:        None,
+                provider:      None,
+                prover:        None,
+                foreign_root:  None,
+                contract:      None,
+            },
+            batches:      vec![],
+            sync_lag:     Some(2),
+        };
+
+        let html = page.render();
+
+        assert!(html.contains("<h1>Sequencer status: ok</h1>"));
+        assert!(html.contains(&format!(
+            "<tr><th>Mined root</th><td>{}</td></tr>",
+            label(&Hash::from(1))
+        )));
+        assert!(html.contains("<tr><th>Buffered</th><td>4</td></tr>"));
+        assert!(html.contains("<tr><th>Sync lag</th><td>2 blocks</td></tr>"));
+        assert!(html.contains("No batches mined recently."));
+        assert!(!html.contains("<h2>Signer</h2>"));
+        assert!(html.contains("<tr><th>Prover</th><td>up</td></tr>"));
+        assert!(html.contains("<tr><th>Contract</th><td>accepting batches</td></tr>"));
+    }
+}
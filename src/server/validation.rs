@@ -0,0 +1,260 @@
+//! Strict deserialization of JSON request payloads.
+//!
+//! Request types deny unknown fields, and [`ValidatedJson`] reports anything
+//! that doesn't deserialize into them as `422 Unprocessable Entity` with
+//! details on the offending field, rather than an opaque error.
+
+use std::fmt;
+
+use axum::body::HttpBody;
+use axum::extract::FromRequest;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{async_trait, BoxError, Json};
+use bytes::Bytes;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Request payloads are small, anything larger than this is rejected before
+/// parsing.
+pub const MAX_PAYLOAD_SIZE: usize = 64 * 1024;
+
+/// Maximum nesting of JSON objects and arrays. None of the request types nest
+/// deeper than a couple of levels.
+const MAX_NESTING_DEPTH: usize = 8;
+
+/// A JSON extractor which rejects invalid payloads with field level details.
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for ValidatedJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = ValidationError;
+
+    async fn from_request(request: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let is_json = request
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/json"));
+
+        if !is_json {
+            return Err(ValidationError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "expected request with `Content-Type: application/json`",
+            ));
+        }
+
+        let body = Bytes::from_request(request, state)
+            .await
+            .map_err(|rejection| ValidationError::new(rejection.status(), rejection.body_text()))?;
+
+        validate(&body).map(Self)
+    }
+}
+
+/// Deserializes a payload, enforcing the size and nesting limits.
+///
+/// # Errors
+///
+/// Will return `Err` if the payload exceeds the limits or doesn't deserialize
+/// into `T`.
+pub fn validate<T: DeserializeOwned>(body: &[u8]) -> Result<T, ValidationError> {
+    if body.len() > MAX_PAYLOAD_SIZE {
+        return Err(ValidationError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("payload exceeds {MAX_PAYLOAD_SIZE} bytes"),
+        ));
+    }
+
+    if nesting_depth(body) > MAX_NESTING_DEPTH {
+        return Err(ValidationError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("payload is nested deeper than {MAX_NESTING_DEPTH} levels"),
+        ));
+    }
+
+    serde_json::from_slice(body).map_err(ValidationError::from)
+}
+
+/// Computes the maximum nesting depth of objects and arrays without parsing
+/// the payload, so that deeply nested input can't exhaust the stack.
+fn nesting_depth(body: &[u8]) -> usize {
+    let mut depth = 0_usize;
+    let mut max_depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for byte in body {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+                max_depth = max_depth.max(depth);
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    max_depth
+}
+
+#[derive(Debug)]
+pub struct ValidationError {
+    status: StatusCode,
+    body:   ValidationErrorBody,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ValidationErrorBody {
+    message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors:  Vec<FieldError>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FieldError {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field:   Option<String>,
+    message: String,
+    line:    usize,
+    column:  usize,
+}
+
+impl ValidationError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: ValidationErrorBody {
+                message: message.into(),
+                errors:  vec![],
+            },
+        }
+    }
+}
+
+impl From<serde_json::Error> for ValidationError {
+    fn from(error: serde_json::Error) -> Self {
+        // `serde_json` only reports positions, but the errors for missing,
+        // unknown and duplicate fields quote the field name.
+        let message = error.to_string();
+        let field = ["missing field `", "unknown field `", "duplicate field `"]
+            .iter()
+            .find_map(|prefix| message.strip_prefix(prefix))
+            .and_then(|rest| rest.split('`').next())
+            .map(ToString::to_string);
+
+        let message = message
+            .rsplit_once(" at line ")
+            .map_or(message.as_str(), |(message, _)| message)
+            .to_string();
+
+        Self {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            body:   ValidationErrorBody {
+                message: "invalid request payload".to_string(),
+                errors:  vec![FieldError {
+                    field,
+                    message,
+                    line: error.line(),
+                    column: error.column(),
+                }],
+            },
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.body.message)?;
+
+        for error in &self.body.errors {
+            match &error.field {
+                Some(field) => write!(f, ", `{field}`: {}", error.message)?,
+                None => write!(f, ", {}", error.message)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl IntoResponse for ValidationError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    #[serde(deny_unknown_fields)]
+    struct TestRequest {
+        #[allow(dead_code)]
+        identity_commitment: String,
+    }
+
+    #[test]
+    fn accepts_valid_payload() {
+        validate::<TestRequest>(br#"{"identityCommitment": "0x1"}"#).unwrap();
+    }
+
+    #[test]
+    fn reports_field_errors() {
+        let error =
+            validate::<TestRequest>(br#"{"identityCommitment": "0x1", "foo": 1}"#).unwrap_err();
+
+        assert_eq!(error.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error.body.errors[0].field.as_deref(), Some("foo"));
+        assert!(error
+            .to_string()
+            .starts_with("invalid request payload, `foo`: unknown field `foo`"));
+
+        let error = validate::<TestRequest>(b"{}").unwrap_err();
+
+        assert_eq!(
+            error.body.errors[0].field.as_deref(),
+            Some("identityCommitment")
+        );
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        let body = format!("{}{}", "[".repeat(100), "]".repeat(100));
+
+        let error = validate::<TestRequest>(body.as_bytes()).unwrap_err();
+
+        assert_eq!(error.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(error.body.errors.is_empty());
+    }
+
+    #[test]
+    fn ignores_brackets_in_strings() {
+        assert_eq!(nesting_depth(br#"{"a": "[[[{{{\"]]]"}"#), 1);
+    }
+}
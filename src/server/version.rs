@@ -0,0 +1,84 @@
+/// A version of the API, served under its own route prefix.
+///
+/// Responses under `/v1` are frozen: fields and statuses added since are only
+/// served under `/v2`. Unprefixed routes serve `/v1`, so that clients which
+/// predate versioning are unaffected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ApiVersion {
+    V1,
+    V2,
+}
+
+impl ApiVersion {
+    pub const ALL: [Self; 2] = [Self::V1, Self::V2];
+    pub const LATEST: Self = Self::V2;
+    /// The version served by unprefixed routes.
+    pub const UNPREFIXED: Self = Self::V1;
+
+    #[must_use]
+    pub const fn prefix(self) -> &'static str {
+        match self {
+            Self::V1 => "/v1",
+            Self::V2 => "/v2",
+        }
+    }
+
+    /// The label of the version in metrics.
+    #[must_use]
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::V1 => "v1",
+            Self::V2 => "v2",
+        }
+    }
+
+    /// The version a request path is explicitly prefixed with.
+    #[must_use]
+    pub fn from_path(path: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|version| Self::strip(*version, path).is_some())
+    }
+
+    /// The route of a path without its version prefix, e.g. `/inclusionProof`
+    /// for `/v1/inclusionProof`. Routes are configured and authorized by their
+    /// unversioned path.
+    #[must_use]
+    pub fn unversioned(path: &str) -> &str {
+        Self::ALL
+            .into_iter()
+            .find_map(|version| Self::strip(version, path))
+            .unwrap_or(path)
+    }
+
+    fn strip(self, path: &str) -> Option<&str> {
+        path.strip_prefix(self.prefix())
+            .filter(|route| route.starts_with('/'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_are_read_from_the_prefix() {
+        assert_eq!(
+            ApiVersion::from_path("/v1/inclusionProof"),
+            Some(ApiVersion::V1)
+        );
+        assert_eq!(ApiVersion::from_path("/v2/treeInfo"), Some(ApiVersion::V2));
+        assert_eq!(ApiVersion::from_path("/inclusionProof"), None);
+        assert_eq!(ApiVersion::from_path("/v1x/inclusionProof"), None);
+    }
+
+    #[test]
+    fn routes_are_unversioned() {
+        assert_eq!(
+            ApiVersion::unversioned("/v1/receipts/:commitment"),
+            "/receipts/:commitment"
+        );
+        assert_eq!(ApiVersion::unversioned("/v2/health"), "/health");
+        assert_eq!(ApiVersion::unversioned("/health"), "/health");
+    }
+}
@@ -0,0 +1,108 @@
+//! The services the sequencer is composed of.
+//!
+//! [`App`](crate::app::App) ties the tree, the chain and batch submission
+//! together. These traits expose each of them on their own, so that another
+//! binary can embed e.g. only the tree to serve proofs. See
+//! [`AppBuilder`](crate::app::AppBuilder) for how they are built.
+
+use anyhow::Result as AnyhowResult;
+use async_trait::async_trait;
+
+use crate::contracts::IdentityManager;
+use crate::identity_tree::{Hash, InclusionProof, TreeItem, TreeState, TreeVersionReadOps};
+use crate::task_monitor::TaskMonitor;
+
+/// The in-memory merkle trees and the proofs they provide.
+pub trait TreeService: Send + Sync {
+    /// Root of the tree including all pending identities.
+    fn latest_root(&self) -> Hash;
+
+    /// Root of the tree including all batched identities.
+    fn batching_root(&self) -> Hash;
+
+    /// Root of the tree as processed on chain.
+    fn processed_root(&self) -> Hash;
+
+    /// Leaf index the next inserted identity is assigned.
+    fn next_leaf_index(&self) -> usize;
+
+    /// Proof of inclusion against the tree version matching the status of the
+    /// item.
+    fn inclusion_proof(&self, item: &TreeItem) -> InclusionProof;
+}
+
+impl TreeService for TreeState {
+    fn latest_root(&self) -> Hash {
+        self.get_latest_tree().get_root()
+    }
+
+    fn batching_root(&self) -> Hash {
+        self.get_batching_tree().get_root()
+    }
+
+    fn processed_root(&self) -> Hash {
+        self.get_processed_tree().get_root()
+    }
+
+    fn next_leaf_index(&self) -> usize {
+        self.get_latest_tree().next_leaf()
+    }
+
+    fn inclusion_proof(&self, item: &TreeItem) -> InclusionProof {
+        self.get_proof_for(item, true).1
+    }
+}
+
+/// The identity manager contract the trees are mirrored to.
+#[async_trait]
+pub trait ChainService: Send + Sync {
+    fn tree_depth(&self) -> usize;
+
+    fn initial_leaf_value(&self) -> Hash;
+
+    /// The latest root of the contract.
+    async fn latest_root(&self) -> AnyhowResult<Hash>;
+
+    /// Whether the root was mined on the contract.
+    async fn is_root_mined(&self, root: Hash) -> AnyhowResult<bool>;
+}
+
+#[async_trait]
+impl ChainService for IdentityManager {
+    fn tree_depth(&self) -> usize {
+        IdentityManager::tree_depth(self)
+    }
+
+    fn initial_leaf_value(&self) -> Hash {
+        IdentityManager::initial_leaf_value(self)
+    }
+
+    async fn latest_root(&self) -> AnyhowResult<Hash> {
+        Ok(IdentityManager::latest_root(self).await?.into())
+    }
+
+    async fn is_root_mined(&self, root: Hash) -> AnyhowResult<bool> {
+        IdentityManager::is_root_mined(self, root.into()).await
+    }
+}
+
+/// The background tasks which batch identities and submit them on chain.
+#[async_trait]
+pub trait BatchService: Send + Sync {
+    /// Starts submitting batches. Does nothing if already started.
+    async fn start(&self);
+
+    /// Stops submitting batches, waiting for the tasks to finish.
+    async fn shutdown(&self) -> AnyhowResult<()>;
+}
+
+#[async_trait]
+impl BatchService for TaskMonitor {
+    async fn start(&self) {
+        TaskMonitor::start(self).await;
+    }
+
+    async fn shutdown(&self) -> AnyhowResult<()> {
+        TaskMonitor::shutdown(self).await
+    }
+}
@@ -3,27 +3,45 @@ use std::time::Duration;
 
 use anyhow::Result as AnyhowResult;
 use clap::Parser;
+use ethers::types::U256;
 use once_cell::sync::Lazy;
 use prometheus::{linear_buckets, register_gauge, register_histogram, Gauge, Histogram};
-use tokio::sync::{broadcast, mpsc, Notify, RwLock};
+use tokio::sync::{broadcast, mpsc, Mutex, Notify, RwLock};
 use tokio::task::JoinHandle;
 use tracing::{info, instrument, warn};
 
+use self::batch_sizing::BatchSizing;
 use self::tasks::delete_identities::DeleteIdentities;
-use self::tasks::finalize_identities::FinalizeRoots;
+use self::tasks::finalize_identities::{self, FinalizeRoots};
+use self::tasks::foreign_roots::TreeDivergence;
 use self::tasks::insert_identities::InsertIdentities;
+use self::tasks::monitor_balance::{check_thresholds, MonitorBalance, SignerFunds};
+use self::tasks::monitor_contract::{ContractState, MonitorContract};
+use self::tasks::monitor_provers::{MonitorProvers, ProverAvailability};
 use self::tasks::monitor_txs::MonitorTxs;
 use self::tasks::process_identities::ProcessIdentities;
+use self::tasks::prune_tree_history::PruneTreeHistory;
+use self::tasks::publish_events::PublishEvents;
+use self::tasks::sync_tree::SyncTree;
 use crate::contracts::SharedIdentityManager;
 use crate::database::Database;
+use crate::event_bus::{self, EventSink};
 use crate::identity_tree::TreeState;
+use crate::utils::cpu_pool::CpuPool;
 
+pub mod batch_sizing;
 pub mod tasks;
 
 const PROCESS_IDENTITIES_BACKOFF: Duration = Duration::from_secs(5);
 const FINALIZE_IDENTITIES_BACKOFF: Duration = Duration::from_secs(5);
 const INSERT_IDENTITIES_BACKOFF: Duration = Duration::from_secs(5);
 const DELETE_IDENTITIES_BACKOFF: Duration = Duration::from_secs(5);
+const MONITOR_BALANCE_BACKOFF: Duration = Duration::from_secs(5);
+const MONITOR_PROVERS_BACKOFF: Duration = Duration::from_secs(5);
+const MONITOR_CONTRACT_BACKOFF: Duration = Duration::from_secs(5);
+const SYNC_TREE_BACKOFF: Duration = Duration::from_secs(5);
+const PRUNE_TREE_HISTORY_BACKOFF: Duration = Duration::from_secs(5);
+const PUBLISH_EVENTS_BACKOFF: Duration = Duration::from_secs(5);
 
 struct RunningInstance {
     handles:         Vec<JoinHandle<()>>,
@@ -111,9 +129,86 @@ pub struct Options {
     #[clap(long, env, default_value = "30")]
     pub time_between_scans_seconds: u64,
 
+    /// The number of seconds a commitment may wait in the queue after becoming
+    /// eligible for insertion before it's marked as failed, which allows it to
+    /// be submitted again. Set to 0 to disable.
+    #[clap(long, env, default_value = "0")]
+    pub max_pending_age_seconds: u64,
+
     /// The number of txs in the channel that we'll be monitoring
     #[clap(long, env, default_value = "100")]
     pub monitored_txs_capacity: usize,
+
+    /// Signer balance (in wei) below which a warning is logged
+    #[clap(long, env, default_value = "100000000000000000", value_parser = U256::from_dec_str)]
+    pub signer_balance_soft_threshold: U256,
+
+    /// Signer balance (in wei) below which batch submission is paused
+    #[clap(long, env, default_value = "10000000000000000", value_parser = U256::from_dec_str)]
+    pub signer_balance_hard_threshold: U256,
+
+    /// The number of seconds between polls for new blocks to check the signer
+    /// balance at
+    #[clap(long, env, default_value = "5")]
+    pub signer_balance_poll_seconds: u64,
+
+    /// The number of seconds between polls of the database for tree updates
+    /// written by the `process` instance, when only serving the API
+    #[clap(long, env, default_value = "1")]
+    pub tree_sync_interval_seconds: u64,
+
+    /// The number of times a batch may revert in simulation before batch
+    /// submission is held until it's retried or discarded through
+    /// `/admin/failedBatches`. Set to 0 to retry indefinitely.
+    #[clap(long, env, default_value = "5")]
+    pub max_batch_attempts: u32,
+
+    /// Choose the size of each batch from the queue depth and the gas price
+    /// instead of waiting for the largest prover to fill up. While gas is
+    /// below `--batch-gas-price-threshold` a batch is submitted as soon as the
+    /// queue fills any prover.
+    #[clap(long, env)]
+    pub dynamic_batch_sizing: bool,
+
+    /// The smallest prover batch size batches are submitted with
+    #[clap(long, env, default_value = "1")]
+    pub min_batch_size: usize,
+
+    /// The largest prover batch size batches are submitted with. Set to 0 to
+    /// use the largest prover.
+    #[clap(long, env, default_value = "0")]
+    pub max_batch_size: usize,
+
+    /// Gas price (in wei) from which dynamic batch sizing waits for batches
+    /// of the largest size
+    #[clap(long, env, default_value = "30000000000", value_parser = U256::from_dec_str)]
+    pub batch_gas_price_threshold: U256,
+
+    /// The longest delay between probes of an unavailable prover, batches
+    /// aren't formed until it responds again
+    #[clap(long, env, default_value = "60")]
+    pub prover_probe_max_backoff_seconds: u64,
+
+    /// The number of seconds between polls of whether the owner paused the
+    /// contract or replaced the signer as its identity operator
+    #[clap(long, env, default_value = "30")]
+    pub contract_state_poll_seconds: u64,
+
+    /// Tree updates superseded by an update of the same leaf which was
+    /// finalized more than this many days ago are deleted, i.e. the insertions
+    /// of deleted identities. Set to 0 to keep them by age.
+    #[clap(long, env, default_value = "0")]
+    pub tree_history_retention_days: u64,
+
+    /// The number of latest tree updates whose superseded updates are kept
+    /// regardless of their age. Set to 0 to keep them by number. Updates are
+    /// only pruned if either retention is set, and kept while either holds.
+    #[clap(long, env, default_value = "0")]
+    pub tree_history_retention_updates: u64,
+
+    /// The number of seconds between prunings of the tree history
+    #[clap(long, env, default_value = "3600")]
+    pub tree_history_prune_interval_seconds: u64,
 }
 
 /// A worker that commits identities to the blockchain.
@@ -131,7 +226,12 @@ pub struct TaskMonitor {
     database:                  Arc<Database>,
     identity_manager:          SharedIdentityManager,
     tree_state:                TreeState,
+    tree_pool:                 Arc<CpuPool>,
     batch_insert_timeout_secs: u64,
+    /// Held while updating the latest tree and recording the updates in the
+    /// database, so that they're recorded in the order they were applied.
+    update_lock:               Arc<Mutex<()>>,
+    divergence:                Arc<TreeDivergence>,
 
     // Finalization params
     scanning_window_size:           u64,
@@ -143,15 +243,47 @@ pub struct TaskMonitor {
     // TODO: docs
     min_batch_deletion_size:        usize,
     monitored_txs_capacity:         usize,
+    max_pending_age:                Option<Duration>,
+
+    // Signer balance params
+    signer_funds:                  Arc<SignerFunds>,
+    signer_balance_soft_threshold: U256,
+    signer_balance_hard_threshold: U256,
+    signer_balance_poll_interval:  Duration,
+
+    tree_sync_interval: Duration,
+
+    prover_availability:      Arc<ProverAvailability>,
+    prover_probe_max_backoff: Duration,
+
+    contract_state:               Arc<ContractState>,
+    contract_state_poll_interval: Duration,
+
+    max_batch_attempts: u32,
+    batch_sizing:       BatchSizing,
+
+    tree_history_retention:      Option<Duration>,
+    tree_history_retain_updates: Option<u64>,
+    tree_history_prune_interval: Duration,
+
+    event_sink:              Option<Arc<dyn EventSink>>,
+    event_bus_poll_interval: Duration,
+    event_bus_batch_size:    i64,
 }
 
 impl TaskMonitor {
+    /// # Errors
+    ///
+    /// Will return `Err` if the signer balance thresholds are inconsistent.
     pub fn new(
         database: Arc<Database>,
         contracts: SharedIdentityManager,
         tree_state: TreeState,
+        tree_pool: Arc<CpuPool>,
         options: &Options,
-    ) -> Self {
+        event_bus: &event_bus::Options,
+        event_sink: Option<Arc<dyn EventSink>>,
+    ) -> AnyhowResult<Self> {
         let Options {
             batch_timeout_seconds,
             scanning_window_size,
@@ -159,16 +291,36 @@ impl TaskMonitor {
             time_between_scans_seconds,
             max_epoch_duration_seconds,
             monitored_txs_capacity,
+            max_pending_age_seconds,
             batch_deletion_timeout_seconds,
             min_batch_deletion_size,
+            signer_balance_soft_threshold,
+            signer_balance_hard_threshold,
+            signer_balance_poll_seconds,
+            tree_sync_interval_seconds,
+            max_batch_attempts,
+            dynamic_batch_sizing,
+            min_batch_size,
+            max_batch_size,
+            batch_gas_price_threshold,
+            prover_probe_max_backoff_seconds,
+            contract_state_poll_seconds,
+            tree_history_retention_days,
+            tree_history_retention_updates,
+            tree_history_prune_interval_seconds,
         } = *options;
 
-        Self {
+        check_thresholds(signer_balance_soft_threshold, signer_balance_hard_threshold)?;
+
+        Ok(Self {
             instance: RwLock::new(None),
             database,
             identity_manager: contracts,
             tree_state,
+            tree_pool,
             batch_insert_timeout_secs: batch_timeout_seconds,
+            update_lock: Arc::new(Mutex::new(())),
+            divergence: Arc::new(TreeDivergence::default()),
             scanning_window_size,
             scanning_chain_head_offset,
             time_between_scans: Duration::from_secs(time_between_scans_seconds),
@@ -176,7 +328,90 @@ impl TaskMonitor {
             min_batch_deletion_size,
             max_epoch_duration: Duration::from_secs(max_epoch_duration_seconds),
             monitored_txs_capacity,
-        }
+            max_pending_age: (max_pending_age_seconds > 0)
+                .then(|| Duration::from_secs(max_pending_age_seconds)),
+            signer_funds: Arc::new(SignerFunds::default()),
+            signer_balance_soft_threshold,
+            signer_balance_hard_threshold,
+            signer_balance_poll_interval: Duration::from_secs(signer_balance_poll_seconds),
+            tree_sync_interval: Duration::from_secs(tree_sync_interval_seconds),
+            prover_availability: Arc::new(ProverAvailability::default()),
+            prover_probe_max_backoff: Duration::from_secs(prover_probe_max_backoff_seconds),
+            contract_state: Arc::new(ContractState::default()),
+            contract_state_poll_interval: Duration::from_secs(contract_state_poll_seconds),
+            max_batch_attempts,
+            batch_sizing: BatchSizing::new(
+                dynamic_batch_sizing,
+                min_batch_size,
+                (max_batch_size > 0).then_some(max_batch_size),
+                batch_gas_price_threshold,
+            ),
+            tree_history_retention: (tree_history_retention_days > 0)
+                .then(|| Duration::from_secs(tree_history_retention_days * 24 * 60 * 60)),
+            tree_history_retain_updates: (tree_history_retention_updates > 0)
+                .then_some(tree_history_retention_updates),
+            tree_history_prune_interval: Duration::from_secs(tree_history_prune_interval_seconds),
+            event_sink,
+            event_bus_poll_interval: Duration::from_millis(event_bus.event_bus_poll_ms),
+            event_bus_batch_size: event_bus.event_bus_batch_size,
+        })
+    }
+
+    /// The number of failures after which a quarantined batch holds batch
+    /// submission, `None` if batches are retried indefinitely.
+    #[must_use]
+    pub fn max_batch_attempts(&self) -> Option<u32> {
+        (self.max_batch_attempts > 0).then_some(self.max_batch_attempts)
+    }
+
+    /// The last observed balance of the signer.
+    #[must_use]
+    pub fn signer_funds(&self) -> &SignerFunds {
+        &self.signer_funds
+    }
+
+    /// Whether the tree on chain diverged from ours.
+    #[must_use]
+    pub fn divergence(&self) -> &TreeDivergence {
+        &self.divergence
+    }
+
+    /// Whether the provers are reachable.
+    #[must_use]
+    pub fn prover_availability(&self) -> &ProverAvailability {
+        &self.prover_availability
+    }
+
+    /// Whether the contract accepts batches from the signer.
+    #[must_use]
+    pub fn contract_state(&self) -> &ContractState {
+        &self.contract_state
+    }
+
+    /// Processes the chain events missed while the sequencer was down. Must
+    /// be called before [`Self::start`].
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the events can't be fetched or processed.
+    pub async fn backfill_missed_blocks(&self) -> AnyhowResult<()> {
+        finalize_identities::backfill_missed_blocks(
+            &self.database,
+            &self.identity_manager,
+            &self.tree_state,
+            &self.tree_pool,
+            &self.update_lock,
+            &self.divergence,
+            self.scanning_window_size,
+            self.scanning_chain_head_offset,
+            self.max_epoch_duration,
+        )
+        .await
+    }
+
+    /// Whether batches are being submitted, or the tree synced.
+    pub async fn is_running(&self) -> bool {
+        self.instance.read().await.is_some()
     }
 
     #[instrument(level = "debug", skip_all)]
@@ -204,8 +439,10 @@ impl TaskMonitor {
         let finalize_identities = FinalizeRoots::new(
             self.database.clone(),
             self.identity_manager.clone(),
-            self.tree_state.get_processed_tree(),
-            self.tree_state.get_mined_tree(),
+            self.tree_state.clone(),
+            self.tree_pool.clone(),
+            self.update_lock.clone(),
+            self.divergence.clone(),
             self.scanning_window_size,
             self.scanning_chain_head_offset,
             self.time_between_scans,
@@ -228,6 +465,12 @@ impl TaskMonitor {
             self.batch_insert_timeout_secs,
             monitored_txs_sender,
             wake_up_notify.clone(),
+            self.signer_funds.clone(),
+            self.prover_availability.clone(),
+            self.divergence.clone(),
+            self.contract_state.clone(),
+            self.max_batch_attempts(),
+            self.batch_sizing,
         );
 
         let process_identities_handle = crate::utils::spawn_monitored_with_backoff(
@@ -248,11 +491,63 @@ impl TaskMonitor {
 
         handles.push(monitor_txs_handle);
 
+        // Monitor signer balance task
+        let monitor_balance = MonitorBalance::new(
+            self.identity_manager.clone(),
+            self.signer_funds.clone(),
+            self.signer_balance_soft_threshold,
+            self.signer_balance_hard_threshold,
+            self.signer_balance_poll_interval,
+        );
+
+        let monitor_balance_handle = crate::utils::spawn_monitored_with_backoff(
+            move || monitor_balance.clone().run(),
+            shutdown_sender.clone(),
+            MONITOR_BALANCE_BACKOFF,
+        );
+
+        handles.push(monitor_balance_handle);
+
+        // Monitor provers task
+        let monitor_provers = MonitorProvers::new(
+            self.prover_availability.clone(),
+            wake_up_notify.clone(),
+            self.prover_probe_max_backoff,
+        );
+
+        let monitor_provers_handle = crate::utils::spawn_monitored_with_backoff(
+            move || monitor_provers.clone().run(),
+            shutdown_sender.clone(),
+            MONITOR_PROVERS_BACKOFF,
+        );
+
+        handles.push(monitor_provers_handle);
+
+        // Monitor contract state task
+        let monitor_contract = MonitorContract::new(
+            self.identity_manager.clone(),
+            self.contract_state.clone(),
+            wake_up_notify.clone(),
+            self.contract_state_poll_interval,
+        );
+
+        let monitor_contract_handle = crate::utils::spawn_monitored_with_backoff(
+            move || monitor_contract.clone().run(),
+            shutdown_sender.clone(),
+            MONITOR_CONTRACT_BACKOFF,
+        );
+
+        handles.push(monitor_contract_handle);
+
         // Insert identities task
         let insert_identities = InsertIdentities::new(
             self.database.clone(),
             self.tree_state.get_latest_tree(),
+            self.tree_pool.clone(),
+            self.update_lock.clone(),
             wake_up_notify.clone(),
+            self.max_pending_age,
+            1 << self.identity_manager.tree_depth(),
         );
 
         let insert_identities_handle = crate::utils::spawn_monitored_with_backoff(
@@ -267,6 +562,8 @@ impl TaskMonitor {
         let delete_identities = DeleteIdentities::new(
             self.database.clone(),
             self.tree_state.get_latest_tree(),
+            self.tree_pool.clone(),
+            self.update_lock.clone(),
             self.batch_deletion_timeout_seconds,
             self.min_batch_deletion_size,
             wake_up_notify,
@@ -280,12 +577,76 @@ impl TaskMonitor {
 
         handles.push(delete_identities_handle);
 
+        // Prune tree history task
+        if self.tree_history_retention.is_some() || self.tree_history_retain_updates.is_some() {
+            let prune_tree_history = PruneTreeHistory::new(
+                self.database.clone(),
+                self.tree_history_retention,
+                self.tree_history_retain_updates,
+                self.tree_history_prune_interval,
+            );
+
+            let prune_tree_history_handle = crate::utils::spawn_monitored_with_backoff(
+                move || prune_tree_history.clone().run(),
+                shutdown_sender.clone(),
+                PRUNE_TREE_HISTORY_BACKOFF,
+            );
+
+            handles.push(prune_tree_history_handle);
+        }
+
+        // Publish lifecycle events task
+        let publish_events = PublishEvents::new(
+            self.database.clone(),
+            self.event_sink.clone(),
+            self.event_bus_poll_interval,
+            self.event_bus_batch_size,
+        );
+
+        let publish_events_handle = crate::utils::spawn_monitored_with_backoff(
+            move || publish_events.clone().run(),
+            shutdown_sender.clone(),
+            PUBLISH_EVENTS_BACKOFF,
+        );
+
+        handles.push(publish_events_handle);
+
         *instance = Some(RunningInstance {
             handles,
             shutdown_sender,
         });
     }
 
+    /// Follows the tree written to the database by a separate instance, in
+    /// place of the tasks started by [`Self::start`].
+    #[instrument(level = "debug", skip_all)]
+    pub async fn start_tree_sync(&self) {
+        let mut instance = self.instance.write().await;
+        if instance.is_some() {
+            warn!("Identity committer already running");
+        }
+
+        let (shutdown_sender, _) = broadcast::channel(1);
+
+        let sync_tree = SyncTree::new(
+            self.database.clone(),
+            self.tree_state.clone(),
+            self.tree_pool.clone(),
+            self.tree_sync_interval,
+        );
+
+        let sync_tree_handle = crate::utils::spawn_monitored_with_backoff(
+            move || sync_tree.clone().run(),
+            shutdown_sender.clone(),
+            SYNC_TREE_BACKOFF,
+        );
+
+        *instance = Some(RunningInstance {
+            handles: vec![sync_tree_handle],
+            shutdown_sender,
+        });
+    }
+
     async fn log_pending_identities_count(database: &Database) -> AnyhowResult<()> {
         let identities = database.count_pending_identities().await?;
         PENDING_IDENTITIES.set(f64::from(identities));
@@ -0,0 +1,170 @@
+use ethers::types::U256;
+
+/// Chooses the size of the next batch among the batch sizes of the provers.
+///
+/// Without dynamic sizing batches wait for the largest prover to fill up (or
+/// for the batch timeout). With it, batches are submitted as soon as the queue
+/// fills any prover while gas is cheap, and only wait for the largest prover
+/// when gas is expensive and amortizing it over more identities pays off.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchSizing {
+    enabled:             bool,
+    min_batch_size:      usize,
+    max_batch_size:      Option<usize>,
+    gas_price_threshold: U256,
+}
+
+impl BatchSizing {
+    #[must_use]
+    pub const fn new(
+        enabled: bool,
+        min_batch_size: usize,
+        max_batch_size: Option<usize>,
+        gas_price_threshold: U256,
+    ) -> Self {
+        Self {
+            enabled,
+            min_batch_size,
+            max_batch_size,
+            gas_price_threshold,
+        }
+    }
+
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The batch sizes within the configured bounds, ascending. If no prover
+    /// is within the bounds all of them are used, rather than stalling.
+    fn allowed_sizes(&self, sizes: &[usize]) -> Vec<usize> {
+        let allowed: Vec<usize> = sizes
+            .iter()
+            .copied()
+            .filter(|size| {
+                *size >= self.min_batch_size
+                    && !matches!(self.max_batch_size, Some(max) if *size > max)
+            })
+            .collect();
+
+        if allowed.is_empty() {
+            sizes.to_vec()
+        } else {
+            allowed
+        }
+    }
+
+    /// The most updates a single batch may contain.
+    #[must_use]
+    pub fn max_batch_size(&self, sizes: &[usize]) -> usize {
+        self.allowed_sizes(sizes).last().copied().unwrap_or(0)
+    }
+
+    /// The batch size of the prover a batch of `update_count` updates is
+    /// proven with.
+    #[must_use]
+    pub fn prover_size(&self, sizes: &[usize], update_count: usize) -> usize {
+        self.allowed_sizes(sizes)
+            .into_iter()
+            .find(|size| *size >= update_count)
+            .unwrap_or(update_count)
+    }
+
+    /// The number of queued updates to wait for before submitting a batch.
+    /// `gas_price` is `None` if it couldn't be fetched, in which case batches
+    /// wait for the largest prover.
+    #[must_use]
+    pub fn target_batch_size(
+        &self,
+        sizes: &[usize],
+        queue_depth: usize,
+        gas_price: Option<U256>,
+    ) -> usize {
+        let allowed = self.allowed_sizes(sizes);
+        let largest = allowed.last().copied().unwrap_or(0);
+
+        if !self.enabled {
+            return largest;
+        }
+
+        match gas_price {
+            Some(gas_price) if gas_price < self.gas_price_threshold => allowed
+                .iter()
+                .rev()
+                .find(|size| **size <= queue_depth)
+                .or_else(|| allowed.first())
+                .copied()
+                .unwrap_or(0),
+            _ => largest,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZES: &[usize] = &[10, 100, 1000];
+    const THRESHOLD: u64 = 50;
+
+    fn dynamic(min_batch_size: usize, max_batch_size: Option<usize>) -> BatchSizing {
+        BatchSizing::new(true, min_batch_size, max_batch_size, U256::from(THRESHOLD))
+    }
+
+    #[test]
+    fn waits_for_largest_batch_when_disabled() {
+        let sizing = BatchSizing::new(false, 1, None, U256::from(THRESHOLD));
+
+        assert_eq!(
+            sizing.target_batch_size(SIZES, 150, Some(U256::one())),
+            1000
+        );
+    }
+
+    #[test]
+    fn fills_the_largest_prover_the_queue_can_while_gas_is_cheap() {
+        let sizing = dynamic(1, None);
+        let cheap = Some(U256::from(THRESHOLD - 1));
+
+        assert_eq!(sizing.target_batch_size(SIZES, 5, cheap), 10);
+        assert_eq!(sizing.target_batch_size(SIZES, 150, cheap), 100);
+        assert_eq!(sizing.target_batch_size(SIZES, 5000, cheap), 1000);
+    }
+
+    #[test]
+    fn waits_for_largest_batch_while_gas_is_expensive() {
+        let sizing = dynamic(1, None);
+
+        assert_eq!(
+            sizing.target_batch_size(SIZES, 150, Some(U256::from(THRESHOLD))),
+            1000
+        );
+        assert_eq!(sizing.target_batch_size(SIZES, 150, None), 1000);
+    }
+
+    #[test]
+    fn respects_bounds() {
+        let sizing = dynamic(100, Some(100));
+        let cheap = Some(U256::one());
+
+        assert_eq!(sizing.target_batch_size(SIZES, 5, cheap), 100);
+        assert_eq!(sizing.max_batch_size(SIZES), 100);
+        assert_eq!(sizing.prover_size(SIZES, 5), 100);
+
+        // Bounds excluding every prover are ignored.
+        let sizing = dynamic(2000, None);
+        assert_eq!(sizing.max_batch_size(SIZES), 1000);
+        assert_eq!(sizing.prover_size(SIZES, 5), 10);
+    }
+
+    #[test]
+    fn timed_out_batches_are_padded_to_the_smallest_prover_they_fit() {
+        let sizing = BatchSizing::new(false, 1, None, U256::from(THRESHOLD));
+        assert_eq!(sizing.prover_size(SIZES, 3), 10);
+        assert_eq!(sizing.prover_size(SIZES, 150), 1000);
+
+        // Even below the minimum batch size.
+        let sizing = BatchSizing::new(false, 100, None, U256::from(THRESHOLD));
+        assert_eq!(sizing.prover_size(SIZES, 3), 100);
+    }
+}
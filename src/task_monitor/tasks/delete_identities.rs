@@ -3,16 +3,19 @@ use std::sync::Arc;
 
 use anyhow::Result as AnyhowResult;
 use chrono::Utc;
-use tokio::sync::Notify;
+use tokio::sync::{Mutex, Notify};
 use tracing::info;
 
 use crate::database::types::DeletionEntry;
 use crate::database::Database;
 use crate::identity_tree::{Hash, Latest, TreeVersion};
+use crate::utils::cpu_pool::CpuPool;
 
 pub struct DeleteIdentities {
     database:                Arc<Database>,
     latest_tree:             TreeVersion<Latest>,
+    tree_pool:               Arc<CpuPool>,
+    update_lock:             Arc<Mutex<()>>,
     deletion_time_interval:  i64,
     min_deletion_batch_size: usize,
     wake_up_notify:          Arc<Notify>,
@@ -22,6 +25,8 @@ impl DeleteIdentities {
     pub fn new(
         database: Arc<Database>,
         latest_tree: TreeVersion<Latest>,
+        tree_pool: Arc<CpuPool>,
+        update_lock: Arc<Mutex<()>>,
         deletion_time_interval: i64,
         min_deletion_batch_size: usize,
         wake_up_notify: Arc<Notify>,
@@ -29,6 +34,8 @@ impl DeleteIdentities {
         Arc::new(Self {
             database,
             latest_tree,
+            tree_pool,
+            update_lock,
             deletion_time_interval,
             min_deletion_batch_size,
             wake_up_notify,
@@ -39,6 +46,8 @@ impl DeleteIdentities {
         delete_identities(
             &self.database,
             &self.latest_tree,
+            &self.tree_pool,
+            &self.update_lock,
             self.deletion_time_interval,
             self.min_deletion_batch_size,
             self.wake_up_notify.clone(),
@@ -50,6 +59,8 @@ impl DeleteIdentities {
 async fn delete_identities(
     database: &Database,
     latest_tree: &TreeVersion<Latest>,
+    tree_pool: &CpuPool,
+    update_lock: &Mutex<()>,
     deletion_time_interval: i64,
     min_deletion_batch_size: usize,
     wake_up_notify: Arc<Notify>,
@@ -80,9 +91,13 @@ async fn delete_identities(
                 .map(|d| (d.leaf_index, d.commitment))
                 .unzip();
 
+            let update_guard = update_lock.lock().await;
+
             // Delete the commitments at the target leaf indices in the latest tree,
             // generating the proof for each update
-            let data = latest_tree.delete_many(&leaf_indices);
+            let tree = latest_tree.clone();
+            let indices = leaf_indices.clone();
+            let data = tree_pool.run(move || tree.delete_many(&indices)).await;
 
             assert_eq!(
                 data.len(),
@@ -98,6 +113,8 @@ async fn delete_identities(
                     .await?;
             }
 
+            drop(update_guard);
+
             // Remove the previous commitments from the deletions table
             database.remove_deletions(previous_commitments).await?;
             wake_up_notify.notify_one();
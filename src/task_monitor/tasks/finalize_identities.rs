@@ -3,25 +3,66 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{Context, Result as AnyhowResult};
-use chrono::Utc;
+use chrono::{TimeZone, Utc};
 use ethers::abi::RawLog;
 use ethers::contract::EthEvent;
 use ethers::providers::Middleware;
 use ethers::types::{Address, Log, Topic, ValueOrArray, U256};
-use tracing::{info, instrument};
-
-use crate::contracts::abi::{BridgedWorldId, RootAddedFilter, TreeChangeKind, TreeChangedFilter};
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_counter_vec, register_gauge_vec, register_int_counter_vec, CounterVec, GaugeVec,
+    IntCounterVec,
+};
+use tokio::sync::Mutex;
+use tracing::{info, instrument, warn};
+
+use crate::contracts::abi::{BridgedWorldId, RootAddedFilter, TreeChangeKind};
+use crate::contracts::flavor::{ContractAdapter, TreeChange};
 use crate::contracts::scanner::BlockScanner;
 use crate::contracts::{IdentityManager, SharedIdentityManager};
+use crate::database::types::BatchCost;
 use crate::database::Database;
-use crate::identity_tree::{Canonical, Intermediate, TreeVersion, TreeWithNextVersion};
+use crate::identity_tree::{
+    Intermediate, MinedTransaction, TreeState, TreeVersion, TreeWithNextVersion,
+};
+use crate::task_monitor::tasks::foreign_roots::{self, TreeDivergence};
 use crate::task_monitor::TaskMonitor;
+use crate::utils::cpu_pool::CpuPool;
+
+static BATCH_GAS_USED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "batch_gas_used",
+        "Gas used by mined batch transactions.",
+        &["kind"]
+    )
+    .unwrap()
+});
+
+static BATCH_COST: Lazy<CounterVec> = Lazy::new(|| {
+    register_counter_vec!(
+        "batch_cost_wei",
+        "Cost of mined batch transactions in wei.",
+        &["kind"]
+    )
+    .unwrap()
+});
+
+static BATCH_COST_PER_IDENTITY: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "batch_cost_per_identity_wei",
+        "Cost per identity of the last mined batch in wei.",
+        &["kind"]
+    )
+    .unwrap()
+});
 
 pub struct FinalizeRoots {
     database:         Arc<Database>,
     identity_manager: SharedIdentityManager,
-    processed_tree:   TreeVersion<Intermediate>,
-    finalized_tree:   TreeVersion<Canonical>,
+    tree_state:       TreeState,
+    tree_pool:        Arc<CpuPool>,
+    update_lock:      Arc<Mutex<()>>,
+    divergence:       Arc<TreeDivergence>,
 
     scanning_window_size:       u64,
     scanning_chain_head_offset: u64,
@@ -33,8 +74,10 @@ impl FinalizeRoots {
     pub fn new(
         database: Arc<Database>,
         identity_manager: SharedIdentityManager,
-        processed_tree: TreeVersion<Intermediate>,
-        finalized_tree: TreeVersion<Canonical>,
+        tree_state: TreeState,
+        tree_pool: Arc<CpuPool>,
+        update_lock: Arc<Mutex<()>>,
+        divergence: Arc<TreeDivergence>,
         scanning_window_size: u64,
         scanning_chain_head_offset: u64,
         time_between_scans: Duration,
@@ -43,8 +86,10 @@ impl FinalizeRoots {
         Arc::new(Self {
             database,
             identity_manager,
-            processed_tree,
-            finalized_tree,
+            tree_state,
+            tree_pool,
+            update_lock,
+            divergence,
             scanning_window_size,
             scanning_chain_head_offset,
             time_between_scans,
@@ -56,8 +101,10 @@ impl FinalizeRoots {
         finalize_roots_loop(
             &self.database,
             &self.identity_manager,
-            &self.processed_tree,
-            &self.finalized_tree,
+            &self.tree_state,
+            &self.tree_pool,
+            &self.update_lock,
+            &self.divergence,
             self.scanning_window_size,
             self.scanning_chain_head_offset,
             self.time_between_scans,
@@ -70,61 +117,164 @@ impl FinalizeRoots {
 async fn finalize_roots_loop(
     database: &Database,
     identity_manager: &IdentityManager,
-    processed_tree: &TreeVersion<Intermediate>,
-    finalized_tree: &TreeVersion<Canonical>,
+    tree_state: &TreeState,
+    tree_pool: &CpuPool,
+    update_lock: &Mutex<()>,
+    divergence: &TreeDivergence,
     scanning_window_size: u64,
     scanning_chain_head_offset: u64,
     time_between_scans: Duration,
     max_epoch_duration: Duration,
 ) -> AnyhowResult<()> {
-    let mainnet_abi = identity_manager.abi();
+    let mainnet_abi = identity_manager.contract();
     let secondary_abis = identity_manager.secondary_abis();
 
-    let mut mainnet_scanner =
-        BlockScanner::new_latest(mainnet_abi.client().clone(), scanning_window_size)
-            .await?
-            .with_offset(scanning_chain_head_offset);
+    // Resume where the previous run left off, blocks missed while the
+    // sequencer was down are backfilled on startup.
+    let mainnet_scanner = match database.get_last_processed_block().await? {
+        Some(last_block) => BlockScanner::new_from(
+            mainnet_abi.client().clone(),
+            last_block + 1,
+            scanning_window_size,
+        ),
+        None => {
+            BlockScanner::new_latest(mainnet_abi.client().clone(), scanning_window_size).await?
+        }
+    };
+    let mut mainnet_scanner = mainnet_scanner.with_offset(scanning_chain_head_offset);
 
     let mut secondary_scanners =
         init_secondary_scanners(secondary_abis, scanning_window_size).await?;
 
     let mainnet_address = mainnet_abi.address();
+    let mainnet_topic = mainnet_abi.tree_change_topic();
 
     loop {
-        let mainnet_logs = fetch_mainnet_logs(&mut mainnet_scanner, mainnet_address).await?;
+        let mainnet_logs =
+            fetch_mainnet_logs(&mut mainnet_scanner, mainnet_address, mainnet_topic.clone())
+                .await?;
 
         finalize_mainnet_roots(
             database,
             identity_manager,
-            processed_tree,
+            tree_state,
+            update_lock,
+            divergence,
             &mainnet_logs,
             max_epoch_duration,
         )
         .await?;
 
-        let mut roots = extract_roots_from_mainnet_logs(mainnet_logs);
+        let mut roots = extract_roots_from_mainnet_logs(mainnet_abi, mainnet_logs);
         roots.extend(fetch_secondary_logs(&mut secondary_scanners).await?);
 
-        finalize_secondary_roots(database, identity_manager, finalized_tree, roots).await?;
+        finalize_secondary_roots(database, identity_manager, tree_state, tree_pool, roots).await?;
+
+        database
+            .update_last_processed_block(mainnet_scanner.current_block().saturating_sub(1))
+            .await?;
 
         tokio::time::sleep(time_between_scans).await;
     }
 }
 
+/// Processes the events emitted while the sequencer was down, from the block
+/// after the last processed one up to the chain head, one scanning window at a
+/// time.
+#[instrument(level = "info", skip_all)]
+pub async fn backfill_missed_blocks(
+    database: &Database,
+    identity_manager: &IdentityManager,
+    tree_state: &TreeState,
+    tree_pool: &CpuPool,
+    update_lock: &Mutex<()>,
+    divergence: &TreeDivergence,
+    scanning_window_size: u64,
+    scanning_chain_head_offset: u64,
+    max_epoch_duration: Duration,
+) -> AnyhowResult<()> {
+    let mainnet_abi = identity_manager.contract();
+    let mainnet_address = mainnet_abi.address();
+    let mainnet_topic = mainnet_abi.tree_change_topic();
+
+    let Some(last_block) = database.get_last_processed_block().await? else {
+        // Nothing was processed yet, so there is no gap to detect.
+        return Ok(());
+    };
+
+    let head = mainnet_abi
+        .client()
+        .get_block_number()
+        .await?
+        .as_u64()
+        .saturating_sub(scanning_chain_head_offset);
+
+    if head <= last_block + 1 {
+        return Ok(());
+    }
+
+    let gap = head - last_block;
+    warn!(
+        last_block,
+        head, gap, "Detected a gap in processed blocks, backfilling"
+    );
+
+    let mut scanner = BlockScanner::new_from(
+        mainnet_abi.client().clone(),
+        last_block + 1,
+        scanning_window_size,
+    )
+    .with_offset(scanning_chain_head_offset);
+
+    while scanner.current_block() < head {
+        let mainnet_logs =
+            fetch_mainnet_logs(&mut scanner, mainnet_address, mainnet_topic.clone()).await?;
+
+        finalize_mainnet_roots(
+            database,
+            identity_manager,
+            tree_state,
+            update_lock,
+            divergence,
+            &mainnet_logs,
+            max_epoch_duration,
+        )
+        .await?;
+
+        // Roots bridged during the gap are checked on the secondary chains
+        // directly, their events were missed as well.
+        let roots = extract_roots_from_mainnet_logs(mainnet_abi, mainnet_logs);
+        finalize_secondary_roots(database, identity_manager, tree_state, tree_pool, roots).await?;
+
+        let processed_block = scanner.current_block().saturating_sub(1);
+        database
+            .update_last_processed_block(processed_block)
+            .await?;
+
+        let done = processed_block - last_block;
+        info!(
+            processed_block,
+            head,
+            progress = %format!("{:.1}%", done as f64 / gap as f64 * 100.0),
+            "Backfilled blocks"
+        );
+    }
+
+    info!(gap, "Backfill complete");
+
+    Ok(())
+}
+
 async fn fetch_mainnet_logs<M>(
     mainnet_scanner: &mut BlockScanner<M>,
     mainnet_address: Address,
+    mainnet_topic: Topic,
 ) -> anyhow::Result<Vec<Log>>
 where
     M: Middleware,
     <M as Middleware>::Error: 'static,
 {
-    let mainnet_topics = [
-        Some(Topic::from(TreeChangedFilter::signature())),
-        None,
-        None,
-        None,
-    ];
+    let mainnet_topics = [Some(mainnet_topic), None, None, None];
 
     let mainnet_address = Some(ValueOrArray::Value(mainnet_address));
 
@@ -168,30 +318,77 @@ where
 async fn finalize_mainnet_roots(
     database: &Database,
     identity_manager: &IdentityManager,
-    processed_tree: &TreeVersion<Intermediate>,
+    tree_state: &TreeState,
+    update_lock: &Mutex<()>,
+    divergence: &TreeDivergence,
     logs: &[Log],
     max_epoch_duration: Duration,
 ) -> Result<(), anyhow::Error> {
+    let contract = identity_manager.contract();
+    let processed_tree = tree_state.get_processed_tree();
+
     for log in logs {
-        let Some(event) = raw_log_to_tree_changed(log) else {
+        let Some(event) = contract.decode_tree_change(log) else {
             continue;
         };
 
         let pre_root = event.pre_root;
         let post_root = event.post_root;
-        let kind = TreeChangeKind::from(event.kind);
+        let kind = event.kind;
 
         info!(?pre_root, ?post_root, ?kind, "Mining batch");
 
-        // Double check
-        if !identity_manager.is_root_mined(post_root).await? {
+        // Double check. Without a root history only the latest root could be
+        // confirmed, so the event is trusted instead.
+        if contract.flavor().has_root_history()
+            && !identity_manager.is_root_mined(post_root).await?
+        {
             continue;
         }
 
+        // Roots submitted by someone else are applied from the calldata of
+        // their transaction, if possible. Otherwise the task fails until the
+        // database is reconciled with the chain, rather than diverging.
+        if database.get_root_state(&post_root.into()).await?.is_none() {
+            foreign_roots::apply_foreign_root(
+                database,
+                identity_manager,
+                tree_state,
+                update_lock,
+                divergence,
+                &event,
+                log,
+            )
+            .await?;
+        }
+
         database.mark_root_as_processed(&post_root.into()).await?;
 
+        // Leaves are listed by the block they were mined in.
+        if let Some(block_number) = log.block_number {
+            let batch_pre_root: Option<Hash> = pre_root.map(Into::into);
+            database
+                .set_mined_block(
+                    batch_pre_root.as_ref(),
+                    &post_root.into(),
+                    block_number.as_u64(),
+                )
+                .await?;
+        }
+
+        // Only informational, so failing to fetch it doesn't hold up the tree.
+        if let Err(err) = record_batch_transaction(database, contract, &event, log).await {
+            warn!(
+                ?err,
+                ?post_root,
+                "Failed to record the transaction of the root"
+            );
+        }
+
         info!(?pre_root, ?post_root, ?kind, "Batch mined");
 
+        identity_manager.compare_shadow_root(post_root).await;
+
         if kind == TreeChangeKind::Deletion {
             // NOTE: We must do this before updating the tree
             //       because we fetch commitments from the processed tree
@@ -199,7 +396,7 @@ async fn finalize_mainnet_roots(
             update_eligible_recoveries(
                 database,
                 identity_manager,
-                processed_tree,
+                &processed_tree,
                 log,
                 max_epoch_duration,
             )
@@ -216,11 +413,105 @@ async fn finalize_mainnet_roots(
     Ok(())
 }
 
+/// Records the transaction which published the root and the gas it cost.
+async fn record_batch_transaction(
+    database: &Database,
+    contract: &ContractAdapter,
+    event: &TreeChange,
+    log: &Log,
+) -> AnyhowResult<()> {
+    let transaction_hash = log.transaction_hash.context("Missing tx hash")?;
+    let block_number = log.block_number.context("Missing block number")?;
+
+    let client = contract.client();
+    let block = client
+        .get_block(block_number)
+        .await?
+        .context("Missing block")?;
+    let block_timestamp = i64::try_from(block.timestamp.as_u64())
+        .ok()
+        .and_then(|timestamp| Utc.timestamp_opt(timestamp, 0).single())
+        .context("Invalid block timestamp")?;
+
+    database
+        .insert_root_transaction(&event.post_root.into(), &MinedTransaction {
+            transaction_hash,
+            block_number: block_number.as_u64(),
+            block_timestamp,
+        })
+        .await?;
+
+    let receipt = client
+        .get_transaction_receipt(transaction_hash)
+        .await?
+        .context("Missing receipt")?;
+    let gas_used = receipt.gas_used.context("Missing gas used")?;
+    let effective_gas_price = receipt
+        .effective_gas_price
+        .context("Missing effective gas price")?;
+
+    let identity_count = match event.pre_root {
+        Some(pre_root) => {
+            database
+                .count_updates_between_roots(&pre_root.into(), &event.post_root.into())
+                .await?
+        }
+        // Every event changes a single identity, and each root was recorded
+        // with the transaction above.
+        None => {
+            database
+                .count_roots_of_transaction(&transaction_hash)
+                .await?
+        }
+    };
+
+    let kind: &str = event.kind.into();
+    let cost = BatchCost {
+        transaction_hash,
+        kind: kind.to_owned(),
+        identity_count,
+        gas_used: gas_used.as_u64(),
+        effective_gas_price,
+        block_timestamp,
+    };
+
+    let is_new = database.insert_batch_cost(&cost).await?;
+    if is_new {
+        BATCH_GAS_USED
+            .with_label_values(&[kind])
+            .inc_by(cost.gas_used);
+        BATCH_COST
+            .with_label_values(&[kind])
+            .inc_by(cost.cost().to_string().parse().unwrap_or_default());
+    }
+
+    if identity_count > 0 {
+        BATCH_COST_PER_IDENTITY.with_label_values(&[kind]).set(
+            (cost.cost() / identity_count)
+                .to_string()
+                .parse()
+                .unwrap_or(f64::NAN),
+        );
+    }
+
+    info!(
+        ?transaction_hash,
+        kind,
+        identity_count,
+        gas_used = cost.gas_used,
+        ?effective_gas_price,
+        "Recorded batch cost"
+    );
+
+    Ok(())
+}
+
 #[instrument(level = "info", skip_all)]
 async fn finalize_secondary_roots(
     database: &Database,
     identity_manager: &IdentityManager,
-    finalized_tree: &TreeVersion<Canonical>,
+    tree_state: &TreeState,
+    tree_pool: &CpuPool,
     roots: Vec<U256>,
 ) -> Result<(), anyhow::Error> {
     for root in roots {
@@ -232,7 +523,10 @@ async fn finalize_secondary_roots(
         }
 
         database.mark_root_as_mined(&root.into()).await?;
-        finalized_tree.apply_updates_up_to(root.into());
+        let tree = tree_state.get_mined_tree();
+        tree_pool
+            .run(move || tree.apply_updates_up_to(root.into()))
+            .await;
 
         info!(?root, "Root finalized");
     }
@@ -262,10 +556,13 @@ where
     Ok(secondary_scanners)
 }
 
-fn extract_roots_from_mainnet_logs(mainnet_logs: Vec<Log>) -> Vec<U256> {
+fn extract_roots_from_mainnet_logs(
+    contract: &ContractAdapter,
+    mainnet_logs: Vec<Log>,
+) -> Vec<U256> {
     let mut roots = vec![];
     for log in mainnet_logs {
-        let Some(event) = raw_log_to_tree_changed(&log) else {
+        let Some(event) = contract.decode_tree_change(&log) else {
             continue;
         };
 
@@ -276,12 +573,6 @@ fn extract_roots_from_mainnet_logs(mainnet_logs: Vec<Log>) -> Vec<U256> {
     roots
 }
 
-fn raw_log_to_tree_changed(log: &Log) -> Option<TreeChangedFilter> {
-    let raw_log = RawLog::from((log.topics.clone(), log.data.to_vec()));
-
-    TreeChangedFilter::decode_log(&raw_log).ok()
-}
-
 fn extract_roots_from_secondary_logs(logs: &[Log]) -> Vec<U256> {
     let mut roots = vec![];
 
@@ -0,0 +1,276 @@
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Context, Result as AnyhowResult};
+use chrono::{DateTime, Utc};
+use ethers::types::{Log, H256, U256};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter, register_int_gauge, IntCounter, IntGauge};
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::contracts::abi::TreeChangeKind;
+use crate::contracts::flavor::TreeChange;
+use crate::contracts::IdentityManager;
+use crate::database::Database;
+use crate::identity_tree::{Hash, TreeState, TreeVersionReadOps, TreeWithNextVersion};
+
+static TREE_DIVERGED: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "tree_diverged",
+        "Whether the root on chain was changed by someone else in a way which couldn't be \
+         applied, batch submission is halted while it is."
+    )
+    .unwrap()
+});
+
+static FOREIGN_BATCHES_APPLIED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "foreign_batches_applied",
+        "Batches mined on chain which weren't submitted by this sequencer, and were applied to \
+         its tree."
+    )
+    .unwrap()
+});
+
+/// A root on chain which this sequencer didn't submit, and couldn't apply.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForeignRoot {
+    pub root:             Hash,
+    pub transaction_hash: Option<H256>,
+    pub block_number:     Option<u64>,
+    pub since:            DateTime<Utc>,
+    pub reason:           String,
+}
+
+/// Whether the tree on chain diverged from ours, shared between the task
+/// finalizing roots, which detects roots it can't account for, and the batch
+/// processor, which can't build on a root the contract no longer has.
+#[derive(Debug, Default)]
+pub struct TreeDivergence {
+    foreign_root: RwLock<Option<ForeignRoot>>,
+}
+
+impl TreeDivergence {
+    /// The root which halted batch submission, if any.
+    #[must_use]
+    pub fn foreign_root(&self) -> Option<ForeignRoot> {
+        self.foreign_root.read().expect("no lock poisoning").clone()
+    }
+
+    #[must_use]
+    pub fn is_halted(&self) -> bool {
+        self.foreign_root
+            .read()
+            .expect("no lock poisoning")
+            .is_some()
+    }
+
+    fn halt(&self, foreign_root: ForeignRoot) {
+        let mut current = self.foreign_root.write().expect("no lock poisoning");
+        if current.as_ref().map(|current| current.root) != Some(foreign_root.root) {
+            error!(
+                root = ?foreign_root.root,
+                transaction_hash = ?foreign_root.transaction_hash,
+                reason = foreign_root.reason,
+                "Root on chain wasn't submitted by this sequencer and can't be applied, halting \
+                 batch submission."
+            );
+            TREE_DIVERGED.set(1);
+            *current = Some(foreign_root);
+        }
+    }
+
+    fn resume(&self) {
+        let mut current = self.foreign_root.write().expect("no lock poisoning");
+        if current.take().is_some() {
+            info!("Foreign root was applied, resuming batch submission.");
+            TREE_DIVERGED.set(0);
+        }
+    }
+}
+
+/// Applies the batch which produced a root unknown to the database, e.g. one
+/// submitted by another sequencer or the owner of the contract. Only insertion
+/// batches building on the processed tree, with nothing appended on top of it
+/// yet, can be applied. Otherwise batch submission is halted until an operator
+/// reconciles the database with the chain.
+///
+/// `update_lock` must be held by everything updating the latest tree, so that
+/// nothing is recorded in between the identities of the batch.
+///
+/// # Errors
+///
+/// Will return `Err` if the batch can't be applied.
+pub async fn apply_foreign_root(
+    database: &Database,
+    identity_manager: &IdentityManager,
+    tree_state: &TreeState,
+    update_lock: &Mutex<()>,
+    divergence: &TreeDivergence,
+    event: &TreeChange,
+    log: &Log,
+) -> AnyhowResult<()> {
+    let root: Hash = event.post_root.into();
+    warn!(?root, kind = ?event.kind, "Root wasn't submitted by this sequencer.");
+
+    match apply_foreign_batch(
+        database,
+        identity_manager,
+        tree_state,
+        update_lock,
+        event,
+        log,
+    )
+    .await
+    {
+        Ok(count) => {
+            FOREIGN_BATCHES_APPLIED.inc();
+            info!(?root, count, "Applied foreign batch to the tree.");
+            divergence.resume();
+            Ok(())
+        }
+        Err(error) => {
+            divergence.halt(ForeignRoot {
+                root,
+                transaction_hash: log.transaction_hash,
+                block_number: log.block_number.map(|block| block.as_u64()),
+                since: Utc::now(),
+                reason: format!("{error:#}"),
+            });
+            Err(error.context(format!("Failed to apply foreign root {root:#x}")))
+        }
+    }
+}
+
+/// Appends the identities of the batch to the tree and records them as
+/// pending, returning their number.
+async fn apply_foreign_batch(
+    database: &Database,
+    identity_manager: &IdentityManager,
+    tree_state: &TreeState,
+    update_lock: &Mutex<()>,
+    event: &TreeChange,
+    log: &Log,
+) -> AnyhowResult<usize> {
+    ensure!(
+        event.kind == TreeChangeKind::Insertion,
+        "only insertion batches can be applied"
+    );
+
+    let tx_hash = log.transaction_hash.context("Missing tx hash")?;
+    let call = identity_manager
+        .fetch_insertion_from_tx(tx_hash)
+        .await
+        .context("Failed to fetch the commitments of the batch")?;
+    ensure!(
+        call.post_root == event.post_root,
+        "the transaction didn't produce the root of the event"
+    );
+
+    let processed_tree = tree_state.get_processed_tree();
+    let processed_root = processed_tree.get_root();
+    let pre_root: Hash = call.pre_root.into();
+    ensure!(
+        pre_root == processed_root,
+        "the batch builds on {pre_root:#x} rather than the processed root {processed_root:#x}"
+    );
+
+    let start_index = call.start_index as usize;
+    let next_leaf = processed_tree.next_leaf();
+    ensure!(
+        start_index == next_leaf,
+        "the batch starts at leaf {start_index} rather than the next leaf {next_leaf}"
+    );
+
+    let commitments = batch_commitments(&call.identity_commitments);
+    ensure!(
+        !commitments.contains(&Hash::ZERO),
+        "the batch inserts zero commitments"
+    );
+    for commitment in &commitments {
+        if let Some(item) = database.get_identity_leaf_index(commitment).await? {
+            bail!(
+                "commitment {commitment:#x} is already at leaf {}",
+                item.leaf_index
+            );
+        }
+    }
+
+    let _update_guard = update_lock.lock().await;
+
+    let latest_tree = tree_state.get_latest_tree();
+    let Some(appended) =
+        latest_tree.append_many_between(processed_root, event.post_root.into(), &commitments)
+    else {
+        bail!(
+            "identities were appended on top of the processed root, or the commitments don't lead \
+             to the root of the event"
+        );
+    };
+
+    // Before anything is batched on top of the foreign identities.
+    tree_state
+        .get_batching_tree()
+        .apply_updates_up_to(event.post_root.into());
+
+    for ((root, _proof, leaf_index), commitment) in appended.iter().zip(&commitments) {
+        database
+            .insert_pending_identity(*leaf_index, commitment, root)
+            .await?;
+        database.remove_unprocessed_identity(commitment).await?;
+    }
+
+    Ok(commitments.len())
+}
+
+/// The commitments of a batch without the zeros it was padded with to the
+/// batch size of the prover.
+fn batch_commitments(identity_commitments: &[U256]) -> Vec<Hash> {
+    let len = identity_commitments
+        .iter()
+        .rposition(|commitment| !commitment.is_zero())
+        .map_or(0, |last| last + 1);
+
+    identity_commitments[..len]
+        .iter()
+        .map(|&commitment| commitment.into())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn padding_is_stripped_from_batches() {
+        let commitments = [1, 0, 2, 0, 0].map(U256::from);
+
+        assert_eq!(batch_commitments(&commitments), vec![
+            Hash::from(1),
+            Hash::ZERO,
+            Hash::from(2)
+        ]);
+        assert!(batch_commitments(&[U256::zero(); 4]).is_empty());
+    }
+
+    #[test]
+    fn halts_until_resumed() {
+        let divergence = TreeDivergence::default();
+        assert!(!divergence.is_halted());
+
+        let foreign_root = ForeignRoot {
+            root:             Hash::from(1),
+            transaction_hash: None,
+            block_number:     Some(2),
+            since:            Utc::now(),
+            reason:           "only insertion batches can be applied".to_owned(),
+        };
+        divergence.halt(foreign_root.clone());
+        assert_eq!(divergence.foreign_root(), Some(foreign_root));
+
+        divergence.resume();
+        assert!(!divergence.is_halted());
+    }
+}
@@ -3,44 +3,86 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result as AnyhowResult;
-use tokio::sync::Notify;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter, IntCounter};
+use tokio::sync::{Mutex, Notify};
 use tokio::time::sleep;
-use tracing::instrument;
+use tracing::{instrument, warn};
 
 use crate::database::types::UnprocessedCommitment;
 use crate::database::Database;
+use crate::fault_injection::{self, FaultPoint};
 use crate::identity_tree::{Hash, Latest, TreeVersion, TreeVersionReadOps, UnprocessedStatus};
+use crate::utils::cpu_pool::CpuPool;
+
+static EXPIRED_COMMITMENTS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "expired_commitments",
+        "Commitments which waited too long in the queue and were marked as failed."
+    )
+    .unwrap()
+});
 
 pub struct InsertIdentities {
-    database:       Arc<Database>,
-    latest_tree:    TreeVersion<Latest>,
-    wake_up_notify: Arc<Notify>,
+    database:        Arc<Database>,
+    latest_tree:     TreeVersion<Latest>,
+    tree_pool:       Arc<CpuPool>,
+    update_lock:     Arc<Mutex<()>>,
+    wake_up_notify:  Arc<Notify>,
+    max_pending_age: Option<Duration>,
+    /// Leaves of the tree, identities past the last one are failed.
+    capacity:        usize,
 }
 
 impl InsertIdentities {
     pub fn new(
         database: Arc<Database>,
         latest_tree: TreeVersion<Latest>,
+        tree_pool: Arc<CpuPool>,
+        update_lock: Arc<Mutex<()>>,
         wake_up_notify: Arc<Notify>,
+        max_pending_age: Option<Duration>,
+        capacity: usize,
     ) -> Arc<Self> {
         Arc::new(Self {
             database,
             latest_tree,
+            tree_pool,
+            update_lock,
             wake_up_notify,
+            max_pending_age,
+            capacity,
         })
     }
 
     pub async fn run(self: Arc<Self>) -> anyhow::Result<()> {
-        insert_identities_loop(&self.database, &self.latest_tree, &self.wake_up_notify).await
+        insert_identities_loop(
+            &self.database,
+            &self.latest_tree,
+            &self.tree_pool,
+            &self.update_lock,
+            &self.wake_up_notify,
+            self.max_pending_age,
+            self.capacity,
+        )
+        .await
     }
 }
 
 async fn insert_identities_loop(
     database: &Database,
     latest_tree: &TreeVersion<Latest>,
+    tree_pool: &CpuPool,
+    update_lock: &Mutex<()>,
     wake_up_notify: &Notify,
+    max_pending_age: Option<Duration>,
+    capacity: usize,
 ) -> AnyhowResult<()> {
     loop {
+        if let Some(max_pending_age) = max_pending_age {
+            expire_identities(database, max_pending_age).await?;
+        }
+
         // get commits from database
         let unprocessed = database
             .get_eligible_unprocessed_commitments(UnprocessedStatus::New)
@@ -50,17 +92,41 @@ async fn insert_identities_loop(
             continue;
         }
 
-        insert_identities(database, latest_tree, unprocessed).await?;
+        {
+            // Batches applied from the chain aren't appended in between.
+            let _update_guard = update_lock.lock().await;
+            insert_identities(database, latest_tree, tree_pool, unprocessed, capacity).await?;
+        }
         // Notify the identity processing task, that there are new identities
         wake_up_notify.notify_one();
     }
 }
 
+async fn expire_identities(database: &Database, max_pending_age: Duration) -> AnyhowResult<()> {
+    let expired = database
+        .expire_unprocessed_identities(chrono::Duration::from_std(max_pending_age)?)
+        .await?;
+
+    if !expired.is_empty() {
+        let expired = expired.len();
+        EXPIRED_COMMITMENTS.inc_by(expired as u64);
+        warn!(
+            expired,
+            ?max_pending_age,
+            "Expired commitments which waited too long in the queue"
+        );
+    }
+
+    Ok(())
+}
+
 #[instrument(level = "info", skip_all)]
 async fn insert_identities(
     database: &Database,
     latest_tree: &TreeVersion<Latest>,
+    tree_pool: &CpuPool,
     identities: Vec<UnprocessedCommitment>,
+    capacity: usize,
 ) -> AnyhowResult<()> {
     // Dedup
     let mut commitments_set = HashSet::new();
@@ -108,12 +174,33 @@ async fn insert_identities(
          {next_db_index}"
     );
 
+    // Identities queued past the last leaf, e.g. by an older sequencer which
+    // didn't check the capacity when queueing, can never be inserted.
+    let room = capacity.saturating_sub(next_leaf);
+    if identities.len() > room {
+        let overflow = identities.split_off(room);
+        warn!(
+            count = overflow.len(),
+            capacity, "The tree is full, failing the remaining queued identities"
+        );
+        for identity in overflow {
+            database
+                .update_err_unprocessed_commitment(
+                    identity.commitment,
+                    format!("The tree is full, all {capacity} leaves are used."),
+                )
+                .await?;
+        }
+    }
+
     let identities: Vec<Hash> = identities
         .into_iter()
         .map(|insert| insert.commitment)
         .collect();
 
-    let data = latest_tree.append_many(&identities);
+    let tree = latest_tree.clone();
+    let commitments = identities.clone();
+    let data = tree_pool.run(move || tree.append_many(&commitments)).await;
 
     assert_eq!(
         data.len(),
@@ -121,9 +208,13 @@ async fn insert_identities(
         "Length mismatch when appending identities to tree"
     );
 
+    fault_injection::inject(FaultPoint::AfterTreeUpdate)?;
+
     let items = data.into_iter().zip(identities);
 
     for ((root, _proof, leaf_index), identity) in items {
+        fault_injection::inject(FaultPoint::BeforeDbWrite)?;
+
         database
             .insert_pending_identity(leaf_index, &identity, &root)
             .await?;
@@ -1,5 +1,12 @@
 pub mod delete_identities;
 pub mod finalize_identities;
+pub mod foreign_roots;
 pub mod insert_identities;
+pub mod monitor_balance;
+pub mod monitor_contract;
+pub mod monitor_provers;
 pub mod monitor_txs;
 pub mod process_identities;
+pub mod prune_tree_history;
+pub mod publish_events;
+pub mod sync_tree;
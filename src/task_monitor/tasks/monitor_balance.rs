@@ -0,0 +1,239 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::{ensure, Result as AnyhowResult};
+use ethers::types::{U256, U64};
+use ethers::utils::format_ether;
+use once_cell::sync::Lazy;
+use prometheus::{register_gauge, Gauge};
+use serde::Serialize;
+use tokio::time;
+use tracing::{error, info, warn};
+
+use crate::contracts::{IdentityManager, SharedIdentityManager};
+
+static SIGNER_BALANCE: Lazy<Gauge> = Lazy::new(|| {
+    register_gauge!(
+        "signer_balance_eth",
+        "Balance of the transaction signer in ETH"
+    )
+    .unwrap()
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FundsStatus {
+    /// The balance hasn't been fetched yet
+    Unknown,
+    Sufficient,
+    /// Below the soft threshold, batches are still submitted
+    Low,
+    /// Below the hard threshold, batch submission is paused
+    Depleted,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SignerFundsState {
+    status:       FundsStatus,
+    balance:      Option<U256>,
+    block_number: Option<U64>,
+}
+
+/// The last observed balance of the signer, shared between the balance monitor
+/// and everything that needs to know whether submissions are paused.
+#[derive(Debug)]
+pub struct SignerFunds {
+    state: RwLock<SignerFundsState>,
+}
+
+impl Default for SignerFunds {
+    fn default() -> Self {
+        Self {
+            state: RwLock::new(SignerFundsState {
+                status:       FundsStatus::Unknown,
+                balance:      None,
+                block_number: None,
+            }),
+        }
+    }
+}
+
+impl SignerFunds {
+    #[must_use]
+    pub fn status(&self) -> FundsStatus {
+        self.state.read().expect("no lock poisoning").status
+    }
+
+    #[must_use]
+    pub fn balance(&self) -> Option<U256> {
+        self.state.read().expect("no lock poisoning").balance
+    }
+
+    /// Whether batch submission is paused because the signer ran out of
+    /// funds.
+    #[must_use]
+    pub fn submissions_paused(&self) -> bool {
+        self.status() == FundsStatus::Depleted
+    }
+
+    fn update(&self, block_number: U64, balance: U256, status: FundsStatus) {
+        let mut state = self.state.write().expect("no lock poisoning");
+        state.block_number = Some(block_number);
+        state.balance = Some(balance);
+        state.status = status;
+    }
+
+    fn last_block_number(&self) -> Option<U64> {
+        self.state.read().expect("no lock poisoning").block_number
+    }
+}
+
+pub struct MonitorBalance {
+    identity_manager: SharedIdentityManager,
+    signer_funds:     Arc<SignerFunds>,
+    soft_threshold:   U256,
+    hard_threshold:   U256,
+    poll_interval:    Duration,
+}
+
+impl MonitorBalance {
+    pub fn new(
+        identity_manager: SharedIdentityManager,
+        signer_funds: Arc<SignerFunds>,
+        soft_threshold: U256,
+        hard_threshold: U256,
+        poll_interval: Duration,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            identity_manager,
+            signer_funds,
+            soft_threshold,
+            hard_threshold,
+            poll_interval,
+        })
+    }
+
+    pub async fn run(self: Arc<Self>) -> anyhow::Result<()> {
+        monitor_balance_loop(
+            &self.identity_manager,
+            &self.signer_funds,
+            self.soft_threshold,
+            self.hard_threshold,
+            self.poll_interval,
+        )
+        .await
+    }
+}
+
+async fn monitor_balance_loop(
+    identity_manager: &IdentityManager,
+    signer_funds: &SignerFunds,
+    soft_threshold: U256,
+    hard_threshold: U256,
+    poll_interval: Duration,
+) -> AnyhowResult<()> {
+    let mut timer = time::interval(poll_interval);
+
+    loop {
+        timer.tick().await;
+
+        let (block_number, balance) = identity_manager.signer_balance().await?;
+
+        // Only act once per block, the balance can't change in between.
+        if signer_funds.last_block_number() == Some(block_number) {
+            continue;
+        }
+
+        let status = funds_status(balance, soft_threshold, hard_threshold);
+        let previous_status = signer_funds.status();
+
+        signer_funds.update(block_number, balance, status);
+        SIGNER_BALANCE.set(format_ether(balance).parse().unwrap_or(f64::NAN));
+
+        let balance = format_ether(balance);
+        match status {
+            FundsStatus::Depleted => error!(
+                %balance,
+                %block_number,
+                "Signer balance is below the hard threshold, pausing batch submission."
+            ),
+            FundsStatus::Low => warn!(
+                %balance,
+                %block_number,
+                "Signer balance is below the soft threshold."
+            ),
+            FundsStatus::Sufficient if previous_status == FundsStatus::Depleted => info!(
+                %balance,
+                %block_number,
+                "Signer balance recovered, resuming batch submission."
+            ),
+            FundsStatus::Sufficient | FundsStatus::Unknown => {}
+        }
+    }
+}
+
+/// Checks that submission isn't paused before the low balance warning.
+///
+/// # Errors
+///
+/// Will return `Err` if the hard threshold exceeds the soft threshold.
+pub fn check_thresholds(soft_threshold: U256, hard_threshold: U256) -> AnyhowResult<()> {
+    ensure!(
+        hard_threshold <= soft_threshold,
+        "--signer-balance-hard-threshold {hard_threshold} exceeds --signer-balance-soft-threshold \
+         {soft_threshold}"
+    );
+
+    Ok(())
+}
+
+fn funds_status(balance: U256, soft_threshold: U256, hard_threshold: U256) -> FundsStatus {
+    if balance < hard_threshold {
+        FundsStatus::Depleted
+    } else if balance < soft_threshold {
+        FundsStatus::Low
+    } else {
+        FundsStatus::Sufficient
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thresholds() {
+        let soft = U256::from(100);
+        let hard = U256::from(10);
+
+        assert_eq!(
+            funds_status(U256::from(5), soft, hard),
+            FundsStatus::Depleted
+        );
+        assert_eq!(funds_status(U256::from(10), soft, hard), FundsStatus::Low);
+        assert_eq!(funds_status(U256::from(99), soft, hard), FundsStatus::Low);
+        assert_eq!(
+            funds_status(U256::from(100), soft, hard),
+            FundsStatus::Sufficient
+        );
+    }
+
+    #[test]
+    fn hard_threshold_must_not_exceed_soft_threshold() {
+        assert!(check_thresholds(U256::from(100), U256::from(10)).is_ok());
+        assert!(check_thresholds(U256::from(10), U256::from(10)).is_ok());
+        assert!(check_thresholds(U256::from(10), U256::from(100)).is_err());
+    }
+
+    #[test]
+    fn paused_when_depleted() {
+        let signer_funds = SignerFunds::default();
+        assert!(!signer_funds.submissions_paused());
+
+        signer_funds.update(U64::from(1), U256::from(5), FundsStatus::Depleted);
+        assert!(signer_funds.submissions_paused());
+
+        signer_funds.update(U64::from(2), U256::from(500), FundsStatus::Sufficient);
+        assert!(!signer_funds.submissions_paused());
+    }
+}
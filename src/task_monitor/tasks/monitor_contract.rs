@@ -0,0 +1,181 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::Result as AnyhowResult;
+use chrono::{DateTime, Utc};
+use ethers::types::Address;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_gauge, IntGauge};
+use serde::Serialize;
+use tokio::sync::Notify;
+use tokio::time;
+use tracing::{error, info};
+
+use crate::contracts::SharedIdentityManager;
+
+static CONTRACT_SUSPENDED: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "contract_suspended",
+        "Whether the owner of the identity manager contract paused it or replaced the signer as \
+         its identity operator, batch submission is suspended while it is."
+    )
+    .unwrap()
+});
+
+/// An action of the contract owner which keeps the contract from accepting
+/// our batches.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "reason")]
+pub enum OwnerAction {
+    Paused,
+    /// Another account was made the identity operator
+    OperatorChanged {
+        operator: Address,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractSuspension {
+    #[serde(flatten)]
+    pub action: OwnerAction,
+    pub since:  DateTime<Utc>,
+}
+
+/// Whether the contract accepts batches from the signer, shared between the
+/// monitor polling the contract and the batch processor, which doesn't submit
+/// batches bound to revert while it doesn't.
+#[derive(Debug, Default)]
+pub struct ContractState {
+    suspension: RwLock<Option<ContractSuspension>>,
+}
+
+impl ContractState {
+    /// The owner action batch submission is suspended for, if any.
+    #[must_use]
+    pub fn suspension(&self) -> Option<ContractSuspension> {
+        self.suspension.read().expect("no lock poisoning").clone()
+    }
+
+    #[must_use]
+    pub fn is_suspended(&self) -> bool {
+        self.suspension.read().expect("no lock poisoning").is_some()
+    }
+
+    /// Records the owner action found by the last poll, returns whether
+    /// submission was resumed.
+    fn update(&self, action: Option<OwnerAction>) -> bool {
+        let mut suspension = self.suspension.write().expect("no lock poisoning");
+        match action {
+            Some(action) => {
+                if suspension.as_ref().map(|current| &current.action) != Some(&action) {
+                    error!(
+                        ?action,
+                        "Contract doesn't accept batches, suspending batch submission."
+                    );
+                    *suspension = Some(ContractSuspension {
+                        action,
+                        since: Utc::now(),
+                    });
+                }
+                CONTRACT_SUSPENDED.set(1);
+                false
+            }
+            None => {
+                CONTRACT_SUSPENDED.set(0);
+                suspension.take().is_some()
+            }
+        }
+    }
+}
+
+pub struct MonitorContract {
+    identity_manager: SharedIdentityManager,
+    state:            Arc<ContractState>,
+    wake_up_notify:   Arc<Notify>,
+    poll_interval:    Duration,
+}
+
+impl MonitorContract {
+    pub fn new(
+        identity_manager: SharedIdentityManager,
+        state: Arc<ContractState>,
+        wake_up_notify: Arc<Notify>,
+        poll_interval: Duration,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            identity_manager,
+            state,
+            wake_up_notify,
+            poll_interval,
+        })
+    }
+
+    pub async fn run(self: Arc<Self>) -> AnyhowResult<()> {
+        let mut timer = time::interval(self.poll_interval);
+
+        loop {
+            timer.tick().await;
+
+            let paused = self.identity_manager.is_paused().await?;
+            let operator = self.identity_manager.operator().await?;
+            let action = owner_action(paused, operator, self.identity_manager.signer_address());
+
+            if self.state.update(action) {
+                info!("Contract accepts batches again, resuming batch submission.");
+                self.wake_up_notify.notify_one();
+            }
+        }
+    }
+}
+
+/// The owner action keeping the contract from accepting batches of `signer`.
+fn owner_action(paused: Option<bool>, operator: Address, signer: Address) -> Option<OwnerAction> {
+    if paused == Some(true) {
+        Some(OwnerAction::Paused)
+    } else if operator != signer {
+        Some(OwnerAction::OperatorChanged { operator })
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn owner_actions() {
+        let signer = Address::from_low_u64_be(1);
+        let other = Address::from_low_u64_be(2);
+
+        assert_eq!(owner_action(None, signer, signer), None);
+        assert_eq!(owner_action(Some(false), signer, signer), None);
+        assert_eq!(
+            owner_action(Some(true), signer, signer),
+            Some(OwnerAction::Paused)
+        );
+        assert_eq!(
+            owner_action(Some(false), other, signer),
+            Some(OwnerAction::OperatorChanged { operator: other })
+        );
+    }
+
+    #[test]
+    fn suspends_until_resumed() {
+        let state = ContractState::default();
+        assert!(!state.is_suspended());
+
+        assert!(!state.update(Some(OwnerAction::Paused)));
+        let suspension = state.suspension().expect("contract is paused");
+        assert_eq!(suspension.action, OwnerAction::Paused);
+
+        // Later polls belong to the same suspension.
+        assert!(!state.update(Some(OwnerAction::Paused)));
+        assert_eq!(state.suspension(), Some(suspension));
+
+        assert!(state.update(None));
+        assert!(!state.is_suspended());
+        assert!(!state.update(None));
+    }
+}
@@ -0,0 +1,179 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::Result as AnyhowResult;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_gauge, IntGauge};
+use serde::Serialize;
+use tokio::sync::Notify;
+use tokio::time;
+use tracing::{error, info, warn};
+
+static PROVER_AVAILABLE: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "prover_available",
+        "Whether the provers are reachable, batches are only formed while they are."
+    )
+    .unwrap()
+});
+
+/// The delay before the first probe of an unavailable prover, doubling with
+/// every failed probe.
+const INITIAL_PROBE_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProverOutage {
+    pub url:   String,
+    pub since: DateTime<Utc>,
+    pub error: String,
+}
+
+/// Whether the provers are reachable, shared between the batch processor,
+/// which stops forming batches while they aren't, and the monitor probing
+/// them until they recover.
+#[derive(Debug, Default)]
+pub struct ProverAvailability {
+    outage:        RwLock<Option<ProverOutage>>,
+    outage_notify: Notify,
+}
+
+impl ProverAvailability {
+    /// The ongoing outage, if a prover couldn't be reached.
+    #[must_use]
+    pub fn outage(&self) -> Option<ProverOutage> {
+        self.outage.read().expect("no lock poisoning").clone()
+    }
+
+    #[must_use]
+    pub fn is_down(&self) -> bool {
+        self.outage.read().expect("no lock poisoning").is_some()
+    }
+
+    /// Records that the prover at `url` couldn't be reached. Batches aren't
+    /// formed until a probe of it succeeds.
+    pub fn mark_down(&self, url: String, error: &anyhow::Error) {
+        let mut outage = self.outage.write().expect("no lock poisoning");
+        if outage.is_none() {
+            error!(
+                url,
+                ?error,
+                "Prover is unavailable, pausing batch formation."
+            );
+            *outage = Some(ProverOutage {
+                url,
+                since: Utc::now(),
+                error: format!("{error:#}"),
+            });
+            PROVER_AVAILABLE.set(0);
+            self.outage_notify.notify_one();
+        }
+    }
+
+    fn mark_up(&self) {
+        *self.outage.write().expect("no lock poisoning") = None;
+        PROVER_AVAILABLE.set(1);
+    }
+
+    async fn wait_for_outage(&self) -> ProverOutage {
+        loop {
+            if let Some(outage) = self.outage() {
+                return outage;
+            }
+
+            self.outage_notify.notified().await;
+        }
+    }
+}
+
+pub struct MonitorProvers {
+    availability:      Arc<ProverAvailability>,
+    wake_up_notify:    Arc<Notify>,
+    max_probe_backoff: Duration,
+    client:            reqwest::Client,
+}
+
+impl MonitorProvers {
+    pub fn new(
+        availability: Arc<ProverAvailability>,
+        wake_up_notify: Arc<Notify>,
+        max_probe_backoff: Duration,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            availability,
+            wake_up_notify,
+            max_probe_backoff,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    pub async fn run(self: Arc<Self>) -> AnyhowResult<()> {
+        PROVER_AVAILABLE.set(i64::from(!self.availability.is_down()));
+
+        loop {
+            let outage = self.availability.wait_for_outage().await;
+
+            let mut probe = 1;
+            loop {
+                time::sleep(probe_backoff(probe, self.max_probe_backoff)).await;
+
+                // Any HTTP response means the prover is reachable, it only
+                // accepts proving requests.
+                match self.client.get(&outage.url).send().await {
+                    Ok(_) => break,
+                    Err(error) => warn!(url = outage.url, probe, ?error, "Prover is still down."),
+                }
+                probe += 1;
+            }
+
+            self.availability.mark_up();
+            info!(
+                url = outage.url,
+                since = %outage.since,
+                "Prover recovered, resuming batch formation."
+            );
+            self.wake_up_notify.notify_one();
+        }
+    }
+}
+
+/// The delay before the given probe, starting at 1.
+fn probe_backoff(probe: u32, max_backoff: Duration) -> Duration {
+    INITIAL_PROBE_BACKOFF
+        .saturating_mul(2_u32.saturating_pow(probe.saturating_sub(1)))
+        .min(max_backoff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probes_back_off_exponentially() {
+        let max = Duration::from_secs(60);
+
+        assert_eq!(probe_backoff(1, max), Duration::from_secs(1));
+        assert_eq!(probe_backoff(2, max), Duration::from_secs(2));
+        assert_eq!(probe_backoff(4, max), Duration::from_secs(8));
+        assert_eq!(probe_backoff(7, max), max);
+        assert_eq!(probe_backoff(100, max), max);
+    }
+
+    #[tokio::test]
+    async fn outages_are_reported_until_recovery() {
+        let availability = ProverAvailability::default();
+        assert!(!availability.is_down());
+
+        availability.mark_down("http://prover".to_owned(), &anyhow::anyhow!("refused"));
+        let outage = availability.wait_for_outage().await;
+        assert_eq!(outage.url, "http://prover");
+
+        // Later failures belong to the same outage.
+        availability.mark_down("http://other".to_owned(), &anyhow::anyhow!("refused"));
+        assert_eq!(availability.outage(), Some(outage));
+
+        availability.mark_up();
+        assert!(!availability.is_down());
+    }
+}
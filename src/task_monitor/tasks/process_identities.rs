@@ -1,9 +1,12 @@
-use std::sync::Arc;
-use std::time::Duration;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result as AnyhowResult};
 use chrono::{DateTime, Utc};
 use ethers::types::U256;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter_vec, IntCounterVec};
 use ruint::Uint;
 use semaphore::merkle_tree::Proof;
 use semaphore::poseidon_tree::Branch;
@@ -11,16 +14,34 @@ use tokio::sync::{mpsc, Notify};
 use tokio::{select, time};
 use tracing::{debug, error, info, instrument, warn};
 
-use crate::contracts::{IdentityManager, SharedIdentityManager};
+use crate::contracts::{IdentityManager, SharedIdentityManager, SimulatedRevert};
+use crate::database::types::BatchDetails;
 use crate::database::Database;
 use crate::ethereum::write::TransactionId;
 use crate::identity_tree::{
-    AppliedTreeUpdate, Hash, Intermediate, TreeVersion, TreeVersionReadOps, TreeWithNextVersion,
+    AppliedTreeUpdate, Hash, IdentityEvent, Intermediate, TreeVersion, TreeVersionReadOps,
+    TreeWithNextVersion,
 };
 use crate::prover::identity::Identity;
-use crate::prover::{Prover, ReadOnlyProver};
+use crate::prover::insertion_input::InsertionBatch;
+use crate::prover::{Prover, ProverUnavailable, ReadOnlyProver};
+use crate::redaction::redacted;
+use crate::task_monitor::batch_sizing::BatchSizing;
+use crate::task_monitor::tasks::foreign_roots::TreeDivergence;
+use crate::task_monitor::tasks::monitor_balance::SignerFunds;
+use crate::task_monitor::tasks::monitor_contract::ContractState;
+use crate::task_monitor::tasks::monitor_provers::ProverAvailability;
 use crate::task_monitor::TaskMonitor;
-use crate::utils::index_packing::pack_indices;
+use crate::utils::index_packing::{pack_indices, padding_index};
+
+static BATCH_SIMULATION_REVERTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "batch_simulation_reverts",
+        "Batches which reverted in simulation and were not broadcast",
+        &["kind"]
+    )
+    .unwrap()
+});
 
 /// The number of seconds either side of the timer tick to treat as enough to
 /// trigger a forced batch insertion.
@@ -33,6 +54,12 @@ pub struct ProcessIdentities {
     batch_insert_timeout_secs: u64,
     monitored_txs_sender:      mpsc::Sender<TransactionId>,
     wake_up_notify:            Arc<Notify>,
+    signer_funds:              Arc<SignerFunds>,
+    prover_availability:       Arc<ProverAvailability>,
+    divergence:                Arc<TreeDivergence>,
+    contract_state:            Arc<ContractState>,
+    max_batch_attempts:        Option<u32>,
+    batch_sizing:              BatchSizing,
 }
 
 impl ProcessIdentities {
@@ -43,6 +70,12 @@ impl ProcessIdentities {
         batch_insert_timeout_secs: u64,
         monitored_txs_sender: mpsc::Sender<TransactionId>,
         wake_up_notify: Arc<Notify>,
+        signer_funds: Arc<SignerFunds>,
+        prover_availability: Arc<ProverAvailability>,
+        divergence: Arc<TreeDivergence>,
+        contract_state: Arc<ContractState>,
+        max_batch_attempts: Option<u32>,
+        batch_sizing: BatchSizing,
     ) -> Arc<Self> {
         Arc::new(Self {
             database,
@@ -51,6 +84,12 @@ impl ProcessIdentities {
             batch_insert_timeout_secs,
             monitored_txs_sender,
             wake_up_notify,
+            signer_funds,
+            prover_availability,
+            divergence,
+            contract_state,
+            max_batch_attempts,
+            batch_sizing,
         })
     }
 
@@ -61,7 +100,13 @@ impl ProcessIdentities {
             &self.batching_tree,
             &self.monitored_txs_sender,
             &self.wake_up_notify,
+            &self.signer_funds,
+            &self.prover_availability,
+            &self.divergence,
+            &self.contract_state,
             self.batch_insert_timeout_secs,
+            self.max_batch_attempts,
+            &self.batch_sizing,
         )
         .await
     }
@@ -73,7 +118,13 @@ async fn process_identities(
     batching_tree: &TreeVersion<Intermediate>,
     monitored_txs_sender: &mpsc::Sender<TransactionId>,
     wake_up_notify: &Notify,
+    signer_funds: &SignerFunds,
+    prover_availability: &ProverAvailability,
+    divergence: &TreeDivergence,
+    contract_state: &ContractState,
     timeout_secs: u64,
+    max_batch_attempts: Option<u32>,
+    batch_sizing: &BatchSizing,
 ) -> AnyhowResult<()> {
     info!("Awaiting for a clean slate");
     identity_manager.await_clean_slate().await?;
@@ -115,21 +166,53 @@ async fn process_identities(
                     continue;
                 }
 
-                let batch_size = if next_update[0].update.element == Hash::ZERO {
-                    identity_manager.max_deletion_batch_size().await
-                }else{
-                    identity_manager.max_insertion_batch_size().await
-                };
+                if signer_funds.submissions_paused() {
+                    warn!("Signer funds are depleted, skipping batch submission.");
+                    continue;
+                }
+
+                if divergence.is_halted() {
+                    warn!("The tree diverged from the chain, skipping batch submission.");
+                    continue;
+                }
+
+                // The contract monitor wakes us up once it accepts batches
+                // again.
+                if contract_state.is_suspended() {
+                    debug!("Contract doesn't accept batches, skipping batch submission.");
+                    continue;
+                }
+
+                // The prover monitor wakes us up once it's back.
+                if prover_availability.is_down() {
+                    debug!("Prover is unavailable, skipping batch formation.");
+                    continue;
+                }
+
+                if is_held(database, max_batch_attempts).await? {
+                    warn!(
+                        "A batch failed too often, skipping batch submission until it's retried \
+                         or discarded."
+                    );
+                    continue;
+                }
+
+                let sizes = batch_sizes(identity_manager, &next_update[0]).await;
+                let batch_size = batch_sizing.max_batch_size(&sizes);
 
                 let updates = batching_tree.peek_next_updates(batch_size);
 
-                commit_identities(
+                let committed = commit_identities(
                     database,
                     identity_manager,
                     batching_tree,
                     monitored_txs_sender,
                     &updates,
-                ).await?;
+                    batch_sizing.prover_size(&sizes, updates.len()),
+                ).await;
+                if is_prover_outage(committed, prover_availability)? {
+                    continue;
+                }
 
                 last_batch_time = Utc::now();
                 database.update_latest_insertion_timestamp(last_batch_time).await?;
@@ -159,14 +242,33 @@ async fn process_identities(
                     continue;
                 }
 
-                let batch_size = if next_update[0].update.element == Hash::ZERO {
-                    identity_manager.max_deletion_batch_size().await
-                }else{
-                    identity_manager.max_insertion_batch_size().await
-                };
+                // The timer retries once funds are available again, or the
+                // failed batch was dealt with.
+                if signer_funds.submissions_paused()
+                    || divergence.is_halted()
+                    || contract_state.is_suspended()
+                    || prover_availability.is_down()
+                    || is_held(database, max_batch_attempts).await?
+                {
+                    continue;
+                }
+
+                let sizes = batch_sizes(identity_manager, &next_update[0]).await;
 
                 // We have _at most_ one complete batch here.
-                let updates = batching_tree.peek_next_updates(batch_size);
+                let mut updates =
+                    batching_tree.peek_next_updates(batch_sizing.max_batch_size(&sizes));
+
+                let gas_price = if batch_sizing.is_enabled() {
+                    identity_manager
+                        .gas_price()
+                        .await
+                        .map_err(|error| warn!(?error, "Failed to fetch the gas price."))
+                        .ok()
+                } else {
+                    None
+                };
+                let batch_size = batch_sizing.target_batch_size(&sizes, updates.len(), gas_price);
 
                 // If there are not enough identities to insert at this
                 // stage we can wait. The timer will ensure that the API
@@ -183,13 +285,19 @@ async fn process_identities(
                     continue;
                 }
 
-                commit_identities(
+                updates.truncate(batch_size);
+
+                let committed = commit_identities(
                     database,
                     identity_manager,
                     batching_tree,
                     monitored_txs_sender,
                     &updates,
-                ).await?;
+                    batch_sizing.prover_size(&sizes, updates.len()),
+                ).await;
+                if is_prover_outage(committed, prover_availability)? {
+                    continue;
+                }
 
                 // We've inserted the identities, so we want to ensure that
                 // we don't trigger again until either we get a full batch
@@ -205,12 +313,52 @@ async fn process_identities(
     }
 }
 
+/// Whether a quarantined batch failed `max_batch_attempts` times, or a leaf
+/// was isolated from a reverting batch, in which case nothing is submitted
+/// until an operator intervenes.
+async fn is_held(database: &Database, max_batch_attempts: Option<u32>) -> AnyhowResult<bool> {
+    Ok(database.has_held_batches(max_batch_attempts).await?)
+}
+
+/// Whether the batch failed because its prover couldn't be reached, in which
+/// case batch formation is paused until it recovers. Other errors are
+/// returned.
+fn is_prover_outage(
+    committed: AnyhowResult<()>,
+    prover_availability: &ProverAvailability,
+) -> AnyhowResult<bool> {
+    let Err(error) = committed else {
+        return Ok(false);
+    };
+
+    let Some(url) = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<ProverUnavailable>())
+        .map(|outage| outage.url.clone())
+    else {
+        return Err(error);
+    };
+
+    prover_availability.mark_down(url, &error);
+    Ok(true)
+}
+
+/// The batch sizes of the provers for the kind of the next update.
+async fn batch_sizes(identity_manager: &IdentityManager, next: &AppliedTreeUpdate) -> Vec<usize> {
+    if next.update.element == Hash::ZERO {
+        identity_manager.deletion_batch_sizes().await
+    } else {
+        identity_manager.insertion_batch_sizes().await
+    }
+}
+
 async fn commit_identities(
     database: &Database,
     identity_manager: &IdentityManager,
     batching_tree: &TreeVersion<Intermediate>,
     monitored_txs_sender: &mpsc::Sender<TransactionId>,
     updates: &[AppliedTreeUpdate],
+    prover_size: usize,
 ) -> AnyhowResult<()> {
     // If the update is an insertion
     let tx_id = if updates
@@ -221,7 +369,7 @@ async fn commit_identities(
         != Hash::ZERO
     {
         let prover = identity_manager
-            .get_suitable_insertion_prover(updates.len())
+            .get_suitable_insertion_prover(prover_size)
             .await?;
 
         info!(
@@ -233,7 +381,7 @@ async fn commit_identities(
         insert_identities(database, identity_manager, batching_tree, updates, prover).await?
     } else {
         let prover = identity_manager
-            .get_suitable_deletion_prover(updates.len())
+            .get_suitable_deletion_prover(prover_size)
             .await?;
 
         info!(
@@ -297,98 +445,132 @@ pub async fn insert_identities(
     }
 
     // Grab the initial conditions before the updates are applied to the tree.
-
-    let start_index = updates[0].update.leaf_index;
     let pre_root: U256 = batching_tree.get_root().into();
-    let mut commitments: Vec<U256> = updates
-        .iter()
-        .map(|update| update.update.element.into())
-        .collect();
 
-    let latest_tree_from_updates = updates
-        .last()
-        .expect("Updates is non empty.")
-        .result
-        .clone();
+    let error = match submit_insertion_batch(database, identity_manager, &prover, updates, pre_root)
+        .await
+    {
+        Ok((transaction_id, details)) => {
+            return Ok(Some(
+                record_insertion_batch(
+                    database,
+                    batching_tree,
+                    updates,
+                    pre_root,
+                    transaction_id,
+                    &details,
+                )
+                .await,
+            ))
+        }
+        Err(error) => error,
+    };
 
-    // Next get merkle proofs for each update - note the proofs are acquired from
-    // intermediate versions of the tree
-    let mut merkle_proofs: Vec<_> = updates
-        .iter()
-        .map(|update_with_tree| {
-            update_with_tree
-                .result
-                .proof(update_with_tree.update.leaf_index)
-        })
-        .collect();
+    // A reverting batch is usually down to one of its leaves. Only that leaf is
+    // quarantined and the leaves before it are submitted on their own. The
+    // leaves after it can't be inserted before it, so submission is held on the
+    // isolated leaf instead of bisecting the same batch again every time.
+    let isolated = match error.downcast_ref::<SimulatedRevert>() {
+        Some(revert) => {
+            find_reverting_leaf(identity_manager, &prover, updates, pre_root, revert).await
+        }
+        None => None,
+    };
 
-    // Grab some variables for sizes to make querying easier.
-    let commitment_count = updates.len();
+    let Some((culprit, revert)) = isolated else {
+        let post_root: U256 = updates
+            .last()
+            .expect("Updates is non empty.")
+            .result
+            .root()
+            .into();
+        quarantine_if_reverted(database, "insertion", pre_root, post_root, &error).await;
+        error!(?error, "Failed to insert identity to contract.");
+        return Err(error);
+    };
 
-    // If these aren't equal then something has gone terribly wrong and is a
-    // programmer bug, so we abort.
-    assert_eq!(
-        commitment_count,
-        merkle_proofs.len(),
-        "Number of identities does not match the number of merkle proofs."
+    let update = &updates[culprit].update;
+    let leaf_pre_root = if culprit == 0 {
+        pre_root
+    } else {
+        updates[culprit - 1].result.root().into()
+    };
+    let leaf_post_root = updates[culprit].result.root().into();
+    error!(
+        leaf_index = update.leaf_index,
+        commitment = ?redacted(&update.element),
+        reason = %revert.reason,
+        "Isolated the leaf making the insertion batch revert."
     );
+    quarantine(
+        database,
+        "insertion",
+        leaf_pre_root,
+        leaf_post_root,
+        &SimulatedRevert {
+            reason:   format!(
+                "leaf {} ({:#x}) reverted: {}",
+                update.leaf_index, update.element, revert.reason
+            ),
+            calldata: revert.calldata,
+        },
+        true,
+    )
+    .await;
 
-    let batch_size = prover.batch_size();
-
-    // The verifier and prover can only work with a given batch size, so we need to
-    // ensure that our batches match that size. We do this by padding with
-    // subsequent zero identities and their associated merkle proofs if the batch is
-    // too small.
-    if commitment_count != batch_size {
-        let start_index = updates
-            .last()
-            .expect("Already confirmed to exist.")
-            .update
-            .leaf_index
-            + 1;
-        let padding = batch_size - commitment_count;
-        commitments.append(&mut vec![U256::zero(); padding]);
+    if culprit == 0 {
+        return Ok(None);
+    }
 
-        for i in start_index..(start_index + padding) {
-            let proof = latest_tree_from_updates.proof(i);
-            merkle_proofs.push(proof);
+    let updates = &updates[..culprit];
+    match submit_insertion_batch(database, identity_manager, &prover, updates, pre_root).await {
+        Ok((transaction_id, details)) => Ok(Some(
+            record_insertion_batch(
+                database,
+                batching_tree,
+                updates,
+                pre_root,
+                transaction_id,
+                &details,
+            )
+            .await,
+        )),
+        Err(error) => {
+            let post_root = leaf_pre_root;
+            quarantine_if_reverted(database, "insertion", pre_root, post_root, &error).await;
+            error!(?error, "Failed to insert identity to contract.");
+            Err(error)
         }
     }
+}
 
-    assert_eq!(
-        commitments.len(),
-        batch_size,
-        "Mismatch between commitments and batch size."
-    );
-    assert_eq!(
-        merkle_proofs.len(),
-        batch_size,
-        "Mismatch between merkle proofs and batch size."
-    );
-
-    // With the updates applied we can grab the value of the tree's new root and
-    // build our identities for sending to the identity manager.
-    let post_root: U256 = latest_tree_from_updates.root().into();
-    let identity_commitments: Vec<Identity> = commitments
-        .iter()
-        .zip(merkle_proofs)
-        .map(|(id, prf)| {
-            let commitment: U256 = id.into();
-            let proof: Vec<U256> = prf
-                .0
-                .iter()
-                .map(|branch| match branch {
-                    Branch::Left(v) | Branch::Right(v) => U256::from(*v),
-                })
-                .collect();
-            Identity::new(commitment, proof)
-        })
-        .collect();
+/// Proves, simulates and submits an insertion batch of `updates`, which must
+/// be consecutive leaves.
+async fn submit_insertion_batch(
+    database: &Database,
+    identity_manager: &IdentityManager,
+    prover: &Prover,
+    updates: &[AppliedTreeUpdate],
+    pre_root: U256,
+) -> AnyhowResult<(TransactionId, BatchDetails)> {
+    let InsertionBatch {
+        start_index,
+        post_root,
+        identities: identity_commitments,
+        ..
+    } = InsertionBatch::from_updates(pre_root, updates, prover.batch_size());
 
     identity_manager.validate_merkle_proofs(&identity_commitments)?;
 
-    // We prepare the proof before reserving a slot in the pending identities
-    let proof = IdentityManager::prepare_insertion_proof(
+    let batch_commitments: Vec<Hash> = updates.iter().map(|update| update.update.element).collect();
+    database
+        .append_identity_events(&batch_commitments, IdentityEvent::Batched)
+        .await?;
+
+    // We prepare the proof before reserving a slot in the pending identities.
+    let proving_started = Instant::now();
+    let proof = insertion_proof(
+        identity_manager,
         prover,
         start_index,
         pre_root,
@@ -404,9 +586,11 @@ pub async fn insert_identities(
         "Submitting insertion batch"
     );
 
+    let prover_latency = proving_started.elapsed();
+
     // With all the data prepared we can submit the identities to the on-chain
     // identity manager and wait for that transaction to be mined.
-    let transaction_id = identity_manager
+    let sent = identity_manager
         .register_identities(
             start_index,
             pre_root,
@@ -414,11 +598,35 @@ pub async fn insert_identities(
             identity_commitments,
             proof,
         )
-        .await
-        .map_err(|e| {
-            error!(?e, "Failed to insert identity to contract.");
-            e
-        })?;
+        .await?;
+
+    let details = BatchDetails {
+        kind: "insertion".to_owned(),
+        leaf_range: updates[0].update.leaf_index..=updates[updates.len() - 1].update.leaf_index,
+        identity_count: updates.len(),
+        prover_latency,
+        calldata_size: sent.calldata_size,
+    };
+
+    Ok((sent.transaction_id, details))
+}
+
+/// Records a submitted insertion batch and moves the batching tree past it.
+async fn record_insertion_batch(
+    database: &Database,
+    batching_tree: &TreeVersion<Intermediate>,
+    updates: &[AppliedTreeUpdate],
+    pre_root: U256,
+    transaction_id: TransactionId,
+    details: &BatchDetails,
+) -> TransactionId {
+    let start_index = updates[0].update.leaf_index;
+    let post_root: U256 = updates
+        .last()
+        .expect("Updates is non empty.")
+        .result
+        .root()
+        .into();
 
     info!(
         start_index,
@@ -428,6 +636,36 @@ pub async fn insert_identities(
         "Insertion batch submitted"
     );
 
+    // The transaction has already been sent, so failing to record it must not
+    // prevent the batching tree from being updated.
+    if let Err(err) = database
+        .insert_transaction(
+            transaction_id.as_ref(),
+            &pre_root.into(),
+            &post_root.into(),
+            details,
+        )
+        .await
+    {
+        error!(
+            ?err,
+            ?transaction_id,
+            "Failed to record submitted transaction."
+        );
+    }
+
+    let batch_commitments: Vec<Hash> = updates.iter().map(|update| update.update.element).collect();
+    if let Err(err) = database
+        .append_identity_events(&batch_commitments, IdentityEvent::Submitted)
+        .await
+    {
+        error!(
+            ?err,
+            ?transaction_id,
+            "Failed to record submitted identities."
+        );
+    }
+
     // Update the batching tree only after submitting the identities to the chain
     batching_tree.apply_updates_up_to(post_root.into());
 
@@ -435,7 +673,133 @@ pub async fn insert_identities(
 
     TaskMonitor::log_batch_size(updates.len());
 
-    Ok(Some(transaction_id))
+    transaction_id
+}
+
+/// Contracts which recompute the tree on chain don't verify a proof of the
+/// insertion.
+async fn insertion_proof(
+    identity_manager: &IdentityManager,
+    prover: &Prover,
+    start_index: usize,
+    pre_root: U256,
+    identity_commitments: &[Identity],
+    post_root: U256,
+) -> AnyhowResult<crate::prover::Proof> {
+    if !identity_manager.flavor().requires_batch_proofs() {
+        return Ok(crate::prover::Proof::from([U256::zero(); 8]));
+    }
+
+    IdentityManager::prepare_insertion_proof(
+        prover,
+        start_index,
+        pre_root,
+        identity_commitments,
+        post_root,
+    )
+    .await
+}
+
+/// Finds the leaf making an insertion batch revert, along with the revert it
+/// causes. A leaf named by the revert reason is taken at its word. Otherwise
+/// the batch is bisected, simulating prefixes of it to find the shortest one
+/// which reverts. Returns `None` if a prefix couldn't be simulated.
+async fn find_reverting_leaf(
+    identity_manager: &IdentityManager,
+    prover: &Prover,
+    updates: &[AppliedTreeUpdate],
+    pre_root: U256,
+    revert: &SimulatedRevert,
+) -> Option<(usize, SimulatedRevert)> {
+    let commitments: Vec<Hash> = updates.iter().map(|update| update.update.element).collect();
+    if let Some(culprit) = leaf_named_by(&revert.reason, &commitments) {
+        return Some((culprit, revert.clone()));
+    }
+
+    info!(
+        batch_size = updates.len(),
+        "Bisecting the insertion batch to find the leaf making it revert."
+    );
+
+    let start_index = updates[0].update.leaf_index;
+    let shortest_revert = Mutex::new(revert.clone());
+    let culprit = bisect(updates.len(), |len| {
+        let prefix = &updates[..len];
+        let shortest_revert = &shortest_revert;
+        async move {
+            let InsertionBatch {
+                post_root,
+                identities,
+                ..
+            } = InsertionBatch::from_updates(pre_root, prefix, prover.batch_size());
+            let simulation = async {
+                let proof = insertion_proof(
+                    identity_manager,
+                    prover,
+                    start_index,
+                    pre_root,
+                    &identities,
+                    post_root,
+                )
+                .await?;
+                identity_manager
+                    .simulate_insertion(start_index, pre_root, post_root, &identities, proof)
+                    .await
+            };
+
+            match simulation.await {
+                Ok(()) => Some(false),
+                Err(error) => match error.downcast::<SimulatedRevert>() {
+                    Ok(revert) => {
+                        *shortest_revert.lock().expect("no lock poisoning") = revert;
+                        Some(true)
+                    }
+                    Err(error) => {
+                        warn!(?error, len, "Failed to simulate a prefix of the batch.");
+                        None
+                    }
+                },
+            }
+        }
+    })
+    .await?;
+
+    let revert = shortest_revert.into_inner().expect("no lock poisoning");
+
+    Some((culprit, revert))
+}
+
+/// The position of the commitment the revert reason mentions.
+fn leaf_named_by(reason: &str, commitments: &[Hash]) -> Option<usize> {
+    let reason = reason.to_lowercase();
+
+    commitments.iter().position(|commitment| {
+        reason.contains(&format!("{commitment:#x}")) || reason.contains(&commitment.to_string())
+    })
+}
+
+/// Bisects a batch of `len` leaves which reverts, where `reverts(n)` tells
+/// whether its first `n` leaves revert, or `None` if that can't be told.
+/// Returns the index of the last leaf of the shortest prefix which reverts.
+async fn bisect<F, Fut>(len: usize, mut reverts: F) -> Option<usize>
+where
+    F: FnMut(usize) -> Fut,
+    Fut: Future<Output = Option<bool>>,
+{
+    // The first `passing` leaves pass and the first `reverting` revert.
+    let mut passing = 0;
+    let mut reverting = len;
+
+    while reverting - passing > 1 {
+        let mid = passing + (reverting - passing) / 2;
+        if reverts(mid).await? {
+            reverting = mid;
+        } else {
+            passing = mid;
+        }
+    }
+
+    Some(reverting - 1)
 }
 
 pub async fn delete_identities(
@@ -459,8 +823,9 @@ pub async fn delete_identities(
 
     let mut deletion_indices = updates
         .iter()
-        .map(|f| f.update.leaf_index as u32)
-        .collect::<Vec<u32>>();
+        .map(|f| u32::try_from(f.update.leaf_index))
+        .collect::<Result<Vec<u32>, _>>()
+        .context("Deletion index does not fit into u32")?;
 
     let commitments =
         batching_tree.commitments_by_indices(deletion_indices.iter().map(|x| *x as usize));
@@ -500,7 +865,7 @@ pub async fn delete_identities(
     // ensure that our batches match that size. We do this by padding deletion
     // indices with tree.depth() ^ 2. The deletion prover will skip the proof for
     // any deletion with an index greater than the max tree depth
-    let pad_index = 2_u32.pow(latest_tree_from_updates.depth() as u32);
+    let pad_index = padding_index(latest_tree_from_updates.depth())?;
 
     if commitment_count != batch_size {
         let padding = batch_size - commitment_count;
@@ -545,6 +910,7 @@ pub async fn delete_identities(
     identity_manager.validate_merkle_proofs(&identity_commitments)?;
 
     // We prepare the proof before reserving a slot in the pending identities
+    let proving_started = Instant::now();
     let proof = IdentityManager::prepare_deletion_proof(
         prover,
         pre_root,
@@ -553,6 +919,7 @@ pub async fn delete_identities(
         post_root,
     )
     .await?;
+    let prover_latency = proving_started.elapsed();
 
     let packed_deletion_indices = pack_indices(&deletion_indices);
 
@@ -560,14 +927,20 @@ pub async fn delete_identities(
 
     // With all the data prepared we can submit the identities to the on-chain
     // identity manager and wait for that transaction to be mined.
-    let transaction_id = identity_manager
+    let sent = identity_manager
         .delete_identities(proof, packed_deletion_indices, pre_root, post_root)
-        .await
-        .map_err(|e| {
+        .await;
+
+    let sent = match sent {
+        Ok(sent) => sent,
+        Err(e) => {
+            quarantine_if_reverted(database, "deletion", pre_root, post_root, &e).await;
             error!(?e, "Failed to insert identity to contract.");
-            e
-        })?;
+            return Err(e);
+        }
+    };
 
+    let transaction_id = sent.transaction_id;
     info!(
         ?pre_root,
         ?post_root,
@@ -575,6 +948,34 @@ pub async fn delete_identities(
         "Deletion batch submitted"
     );
 
+    let deleted_leaves = updates.iter().map(|update| update.update.leaf_index);
+    let details = BatchDetails {
+        kind: "deletion".to_owned(),
+        leaf_range: deleted_leaves.clone().min().unwrap_or_default()
+            ..=deleted_leaves.max().unwrap_or_default(),
+        identity_count: updates.len(),
+        prover_latency,
+        calldata_size: sent.calldata_size,
+    };
+
+    // The transaction has already been sent, so failing to record it must not
+    // prevent the batching tree from being updated.
+    if let Err(err) = database
+        .insert_transaction(
+            transaction_id.as_ref(),
+            &pre_root.into(),
+            &post_root.into(),
+            &details,
+        )
+        .await
+    {
+        error!(
+            ?err,
+            ?transaction_id,
+            "Failed to record submitted transaction."
+        );
+    }
+
     // Update the batching tree only after submitting the identities to the chain
     batching_tree.apply_updates_up_to(post_root.into());
 
@@ -584,3 +985,116 @@ pub async fn delete_identities(
 
     Ok(Some(transaction_id))
 }
+
+/// Records a batch which reverted in simulation, so that it can be inspected.
+/// The batch was not broadcast, it's retried once the task restarts.
+async fn quarantine_if_reverted(
+    database: &Database,
+    kind: &str,
+    pre_root: U256,
+    post_root: U256,
+    error: &anyhow::Error,
+) {
+    if let Some(revert) = error.downcast_ref::<SimulatedRevert>() {
+        quarantine(database, kind, pre_root, post_root, revert, false).await;
+    }
+}
+
+async fn quarantine(
+    database: &Database,
+    kind: &str,
+    pre_root: U256,
+    post_root: U256,
+    revert: &SimulatedRevert,
+    isolated: bool,
+) {
+    BATCH_SIMULATION_REVERTS.with_label_values(&[kind]).inc();
+    error!(
+        kind,
+        ?pre_root,
+        ?post_root,
+        reason = %revert.reason,
+        "Batch reverted in simulation, not broadcasting it."
+    );
+
+    if let Err(err) = database
+        .insert_quarantined_batch(
+            kind,
+            &pre_root.into(),
+            &post_root.into(),
+            &revert.reason,
+            &revert.calldata,
+            isolated,
+        )
+        .await
+    {
+        error!(?err, "Failed to quarantine batch.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn bisection_finds_the_reverting_leaf() {
+        for len in 1..=9 {
+            for culprit in 0..len {
+                let mut simulated = HashSet::new();
+                let found = bisect(len, |prefix| {
+                    assert!(simulated.insert(prefix), "prefix {prefix} simulated twice");
+                    async move { Some(prefix > culprit) }
+                })
+                .await;
+
+                assert_eq!(found, Some(culprit));
+                assert!(simulated.len() <= 4, "{} simulations", simulated.len());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn bisection_gives_up_when_a_prefix_cant_be_simulated() {
+        assert_eq!(bisect(8, |_| async { None }).await, None);
+    }
+
+    #[tokio::test]
+    async fn unreachable_provers_pause_batch_formation() {
+        let availability = ProverAvailability::default();
+        assert!(!is_prover_outage(Ok(()), &availability).unwrap());
+
+        let other = is_prover_outage(Err(anyhow::anyhow!("reverted")), &availability);
+        assert!(other.is_err());
+        assert!(!availability.is_down());
+
+        // Nothing listens on the discard port.
+        let source = reqwest::get("http://127.0.0.1:9").await.unwrap_err();
+        let unreachable = anyhow::Error::new(ProverUnavailable {
+            url: "http://127.0.0.1:9".to_owned(),
+            source,
+        })
+        .context("Failed to prove the batch");
+        assert!(is_prover_outage(Err(unreachable), &availability).unwrap());
+        assert_eq!(availability.outage().unwrap().url, "http://127.0.0.1:9");
+    }
+
+    #[test]
+    fn revert_reasons_naming_a_leaf() {
+        let commitments = [Hash::from(0xabc), Hash::from(12345), Hash::from(7)];
+
+        assert_eq!(
+            leaf_named_by("UnreducedElement(0, 0xABC)", &commitments),
+            Some(0)
+        );
+        assert_eq!(
+            leaf_named_by("duplicate commitment 12345", &commitments),
+            Some(1)
+        );
+        assert_eq!(
+            leaf_named_by("ProofValidationFailure()", &commitments),
+            None
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result as AnyhowResult;
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter, IntCounter};
+use tokio::time;
+use tracing::info;
+
+use crate::database::Database;
+
+static PRUNED_TREE_UPDATES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "pruned_tree_updates",
+        "Tree updates deleted from the database after being superseded by a finalized update of \
+         the same leaf."
+    )
+    .unwrap()
+});
+
+/// Deletes the tree updates which no longer contribute to the tree once they
+/// fall out of the retention, i.e. the insertions of identities whose deletion
+/// was finalized. Updates which can still be rolled back are never pruned, as
+/// only updates superseded by a finalized one are.
+pub struct PruneTreeHistory {
+    database:       Arc<Database>,
+    retention:      Option<Duration>,
+    retain_updates: Option<u64>,
+    interval:       Duration,
+}
+
+impl PruneTreeHistory {
+    pub fn new(
+        database: Arc<Database>,
+        retention: Option<Duration>,
+        retain_updates: Option<u64>,
+        interval: Duration,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            database,
+            retention,
+            retain_updates,
+            interval,
+        })
+    }
+
+    pub async fn run(self: Arc<Self>) -> AnyhowResult<()> {
+        loop {
+            let finalized_before = self
+                .retention
+                .map(|retention| Utc::now() - chrono::Duration::from_std(retention))
+                .transpose()?;
+
+            let pruned = self
+                .database
+                .prune_superseded_updates(finalized_before, self.retain_updates)
+                .await?;
+
+            if pruned > 0 {
+                PRUNED_TREE_UPDATES.inc_by(pruned);
+                info!(pruned, "Pruned superseded tree updates.");
+            }
+
+            time::sleep(self.interval).await;
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result as AnyhowResult;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter, IntCounter};
+use tokio::time;
+use tracing::{debug, warn};
+
+use crate::database::Database;
+use crate::event_bus::{EventSink, LifecycleEvent};
+
+static PUBLISHED_LIFECYCLE_EVENTS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "published_lifecycle_events",
+        "Lifecycle events of identities published to the event bus."
+    )
+    .unwrap()
+});
+
+/// Publishes the lifecycle events waiting in the outbox, oldest first, and
+/// removes them once the sink acknowledged them. Without a sink the outbox is
+/// only emptied.
+pub struct PublishEvents {
+    database:   Arc<Database>,
+    sink:       Option<Arc<dyn EventSink>>,
+    interval:   Duration,
+    batch_size: i64,
+}
+
+impl PublishEvents {
+    pub fn new(
+        database: Arc<Database>,
+        sink: Option<Arc<dyn EventSink>>,
+        interval: Duration,
+        batch_size: i64,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            database,
+            sink,
+            interval,
+            batch_size,
+        })
+    }
+
+    pub async fn run(self: Arc<Self>) -> AnyhowResult<()> {
+        loop {
+            let events = self.database.get_outbox_events(self.batch_size).await?;
+            if events.is_empty() {
+                time::sleep(self.interval).await;
+                continue;
+            }
+
+            let mut published = Vec::with_capacity(events.len());
+            for event in events {
+                let id = event.id;
+                if let Some(sink) = &self.sink {
+                    if let Err(error) = sink.publish(&LifecycleEvent::from(event)).await {
+                        warn!(?error, id, "Failed to publish a lifecycle event.");
+                        // Keep the events published so far from being published again.
+                        self.database.delete_outbox_events(&published).await?;
+                        return Err(error);
+                    }
+                    PUBLISHED_LIFECYCLE_EVENTS.inc();
+                }
+                published.push(id);
+            }
+
+            let removed = self.database.delete_outbox_events(&published).await?;
+            debug!(removed, "Removed lifecycle events from the outbox.");
+
+            // A backlog is drained without waiting.
+            if published.len() < self.batch_size as usize {
+                time::sleep(self.interval).await;
+            }
+        }
+    }
+}
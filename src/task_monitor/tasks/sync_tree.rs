@@ -0,0 +1,144 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{ensure, Context, Result as AnyhowResult};
+use tokio::time::sleep;
+use tracing::{info, instrument};
+
+use crate::database::Database;
+use crate::identity_tree::{
+    Hash, ProcessedStatus, TreeState, TreeVersionReadOps, TreeWithNextVersion,
+};
+use crate::utils::cpu_pool::CpuPool;
+
+/// Follows the tree written to the database by a separate `process` instance,
+/// so that an instance which only serves the API answers from an up to date
+/// tree without batching anything itself.
+pub struct SyncTree {
+    database:   Arc<Database>,
+    tree_state: TreeState,
+    tree_pool:  Arc<CpuPool>,
+    interval:   Duration,
+}
+
+impl SyncTree {
+    pub fn new(
+        database: Arc<Database>,
+        tree_state: TreeState,
+        tree_pool: Arc<CpuPool>,
+        interval: Duration,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            database,
+            tree_state,
+            tree_pool,
+            interval,
+        })
+    }
+
+    pub async fn run(self: Arc<Self>) -> anyhow::Result<()> {
+        sync_tree_loop(
+            &self.database,
+            &self.tree_state,
+            &self.tree_pool,
+            self.interval,
+        )
+        .await
+    }
+}
+
+async fn sync_tree_loop(
+    database: &Database,
+    tree_state: &TreeState,
+    tree_pool: &CpuPool,
+    interval: Duration,
+) -> AnyhowResult<()> {
+    let latest_root = tree_state.get_latest_tree().get_root();
+
+    // The tree is built from the whole table on startup, so its root is that
+    // of a row unless nothing was inserted yet.
+    let mut last_id = if tree_state.get_latest_tree().next_leaf() == 0 {
+        0
+    } else {
+        database
+            .get_root_id(&latest_root)
+            .await?
+            .context("The latest root is no longer in the database, restart to rebuild the tree")?
+    };
+
+    loop {
+        last_id = sync_latest_tree(database, tree_state, tree_pool, last_id).await?;
+
+        sync_finalized_trees(database, tree_state).await?;
+
+        sleep(interval).await;
+    }
+}
+
+/// Appends the rows written after `last_id` to the latest tree, returns the
+/// last row applied.
+#[instrument(level = "debug", skip(database, tree_state, tree_pool))]
+async fn sync_latest_tree(
+    database: &Database,
+    tree_state: &TreeState,
+    tree_pool: &CpuPool,
+    last_id: usize,
+) -> AnyhowResult<usize> {
+    let entries = database.get_tree_updates_after(last_id).await?;
+    let Some(last_entry) = entries.last() else {
+        return Ok(last_id);
+    };
+
+    let next_id = last_entry.id;
+    let expected_root = last_entry.root;
+    let updates: Vec<_> = entries.into_iter().map(|entry| entry.update).collect();
+    let update_count = updates.len();
+
+    let latest_tree = tree_state.get_latest_tree();
+    let root = tree_pool
+        .run(move || latest_tree.apply_updates(&updates))
+        .await;
+
+    // The latest tree now holds updates the database doesn't, nothing can be
+    // served from it. Restarts fail as well, since its root isn't in the
+    // database, until the instance is restarted to rebuild the tree.
+    ensure!(
+        root == expected_root,
+        "Synced tree diverged from the database at row {next_id}: computed root {root:?}, \
+         expected {expected_root:?}"
+    );
+
+    // The batching tree only exists to be pulled from, nothing is batched here.
+    tree_state.get_batching_tree().apply_updates_up_to(root);
+
+    info!(update_count, ?root, "Synced tree updates");
+
+    Ok(next_id)
+}
+
+/// Moves the processed and mined trees up to the latest roots marked as such.
+async fn sync_finalized_trees(database: &Database, tree_state: &TreeState) -> AnyhowResult<()> {
+    let mined_root = database
+        .get_latest_root_by_status(ProcessedStatus::Mined)
+        .await?;
+    let processed_root = database
+        .get_latest_root_by_status(ProcessedStatus::Processed)
+        .await?
+        .or(mined_root);
+
+    if let Some(root) = processed_root {
+        advance(&tree_state.get_processed_tree(), root);
+    }
+
+    if let Some(root) = mined_root {
+        advance(&tree_state.get_mined_tree(), root);
+    }
+
+    Ok(())
+}
+
+fn advance(tree: &(impl TreeVersionReadOps + TreeWithNextVersion), root: Hash) {
+    if tree.get_root() != root {
+        tree.apply_updates_up_to(root);
+    }
+}
@@ -0,0 +1,191 @@
+//! Keeps insertions from overflowing the tree. A tree of depth `d` has room
+//! for `2^d` leaves, and batches can't be formed past the last one, so
+//! insertions are rejected before they're queued once the leaves are used up.
+//! A lower threshold warns operators ahead of time and can reserve the
+//! remaining leaves for priority signers.
+
+use clap::Parser;
+use ethers::types::Address;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_gauge, IntGauge};
+use tracing::warn;
+
+use crate::server::error::Error as ServerError;
+
+static USED_LEAVES: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "tree_used_leaves",
+        "Leaves of the tree which are assigned or queued."
+    )
+    .unwrap()
+});
+
+static CAPACITY_THRESHOLD_REACHED: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "tree_capacity_threshold_reached",
+        "Set while the used leaves are past `--tree-capacity-threshold`."
+    )
+    .unwrap()
+});
+
+#[derive(Clone, Debug, PartialEq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Fraction of the leaves of the tree past which operators are warned
+    /// through the `tree_capacity_threshold_reached` gauge.
+    #[clap(long, env, default_value = "0.9")]
+    pub tree_capacity_threshold: f64,
+
+    /// Reject insertions past `--tree-capacity-threshold` unless they're
+    /// signed by one of `--priority-insertion-signers`.
+    #[clap(long, env)]
+    pub block_insertions_past_capacity_threshold: bool,
+
+    /// Signers whose insertions are accepted until the tree is full.
+    #[clap(long, env, value_delimiter = ',')]
+    pub priority_insertion_signers: Vec<Address>,
+}
+
+/// Checks the leaves used against the capacity of the tree.
+pub struct TreeCapacity {
+    capacity:         usize,
+    threshold:        usize,
+    block_past:       bool,
+    priority_signers: Vec<Address>,
+}
+
+impl TreeCapacity {
+    #[must_use]
+    pub fn new(options: &Options, tree_depth: usize) -> Self {
+        let capacity = 1_usize << tree_depth;
+        let fraction = options.tree_capacity_threshold.clamp(0.0, 1.0);
+
+        #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_sign_loss)]
+        let threshold = (capacity as f64 * fraction).ceil() as usize;
+
+        Self {
+            capacity,
+            threshold: threshold.min(capacity),
+            block_past: options.block_insertions_past_capacity_threshold,
+            priority_signers: options.priority_insertion_signers.clone(),
+        }
+    }
+
+    /// The number of leaves of the tree.
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Checks whether another insertion by `signer` can be accepted with
+    /// `used` leaves assigned or queued already.
+    ///
+    /// Concurrent insertions are checked against the same count, so the
+    /// threshold may be overshot by the insertions in flight. The last leaves
+    /// can be reserved by setting the threshold below them. The capacity
+    /// itself is enforced again when insertions are queued, see
+    /// `Database::insert_new_identities`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the tree is full, or if the threshold is reached,
+    /// blocking is enabled and `signer` isn't a priority signer.
+    pub fn check(&self, used: usize, signer: Option<Address>) -> Result<(), ServerError> {
+        USED_LEAVES.set(used.try_into().unwrap_or(i64::MAX));
+
+        let threshold_reached = used >= self.threshold;
+        CAPACITY_THRESHOLD_REACHED.set(threshold_reached.into());
+
+        if used >= self.capacity {
+            warn!(
+                used,
+                capacity = self.capacity,
+                "Rejecting insertion, the tree is full."
+            );
+            return Err(ServerError::TreeFull {
+                capacity: self.capacity,
+            });
+        }
+
+        if !threshold_reached {
+            return Ok(());
+        }
+
+        let is_priority = signer.is_some_and(|signer| self.priority_signers.contains(&signer));
+        if self.block_past && !is_priority {
+            warn!(
+                used,
+                threshold = self.threshold,
+                capacity = self.capacity,
+                "Rejecting insertion past the tree capacity threshold."
+            );
+            return Err(ServerError::TreeCapacityThresholdReached {
+                used,
+                capacity: self.capacity,
+            });
+        }
+
+        warn!(
+            used,
+            threshold = self.threshold,
+            capacity = self.capacity,
+            "The tree is past its capacity threshold."
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::Address;
+
+    use super::{Options, TreeCapacity};
+    use crate::server::error::Error as ServerError;
+
+    fn options(threshold: f64, block: bool, priority: Vec<Address>) -> Options {
+        Options {
+            tree_capacity_threshold:                  threshold,
+            block_insertions_past_capacity_threshold: block,
+            priority_insertion_signers:               priority,
+        }
+    }
+
+    #[test]
+    fn rejects_insertions_into_a_full_tree() {
+        let priority = Address::repeat_byte(1);
+        let capacity = TreeCapacity::new(&options(1.0, false, vec![priority]), 4);
+
+        assert!(capacity.check(15, None).is_ok());
+        assert!(matches!(
+            capacity.check(16, Some(priority)),
+            Err(ServerError::TreeFull { capacity: 16 })
+        ));
+    }
+
+    #[test]
+    fn only_warns_past_the_threshold_by_default() {
+        let capacity = TreeCapacity::new(&options(0.5, false, vec![]), 4);
+
+        assert_eq!(capacity.threshold, 8);
+        assert!(capacity.check(12, None).is_ok());
+    }
+
+    #[test]
+    fn reserves_leaves_past_the_threshold_for_priority_signers() {
+        let priority = Address::repeat_byte(1);
+        let other = Address::repeat_byte(2);
+        let capacity = TreeCapacity::new(&options(0.5, true, vec![priority]), 4);
+
+        assert!(capacity.check(7, None).is_ok());
+        assert!(matches!(
+            capacity.check(8, None),
+            Err(ServerError::TreeCapacityThresholdReached {
+                used:     8,
+                capacity: 16,
+            })
+        ));
+        assert!(capacity.check(8, Some(other)).is_err());
+        assert!(capacity.check(15, Some(priority)).is_ok());
+    }
+}
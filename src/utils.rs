@@ -8,7 +8,10 @@ use tokio::sync::broadcast;
 use tokio::task::JoinHandle;
 use tracing::{error, info};
 
+pub mod cpu_pool;
 pub mod index_packing;
+pub mod proof_limiter;
+pub mod retry;
 pub mod tree_updates;
 
 pub trait Any<A> {
@@ -0,0 +1,127 @@
+//! Dedicated thread pools for CPU heavy work, which would otherwise stall the
+//! tasks and request handlers sharing the tokio runtime.
+
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+
+use anyhow::Result as AnyhowResult;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_gauge_vec, IntGaugeVec};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use tokio::sync::{oneshot, Semaphore};
+
+static QUEUED_JOBS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "cpu_pool_queued_jobs",
+        "Number of jobs queued or running on a CPU pool.",
+        &["pool"]
+    )
+    .unwrap()
+});
+
+pub struct CpuPool {
+    name:  &'static str,
+    pool:  ThreadPool,
+    /// Bounds the number of queued jobs, callers wait for a slot once full.
+    queue: Arc<Semaphore>,
+}
+
+impl CpuPool {
+    /// Creates a pool with `threads` threads, or one per core if zero.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the threads can't be spawned.
+    pub fn new(name: &'static str, threads: usize, queue_size: usize) -> AnyhowResult<Self> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(move |index| format!("{name}-{index}"))
+            .build()?;
+
+        Ok(Self {
+            name,
+            pool,
+            queue: Arc::new(Semaphore::new(queue_size)),
+        })
+    }
+
+    /// Runs the closure on the pool and waits for its result. Panics are
+    /// propagated to the caller.
+    pub async fn run<F, R>(&self, job: F) -> R
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let permit = self
+            .queue
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the queue semaphore is never closed");
+
+        let queued = QUEUED_JOBS.with_label_values(&[self.name]);
+        queued.inc();
+
+        let (sender, receiver) = oneshot::channel();
+        self.pool.spawn(move || {
+            let result = catch_unwind(AssertUnwindSafe(job));
+            queued.dec();
+            drop(permit);
+            // The caller may have stopped waiting.
+            _ = sender.send(result);
+        });
+
+        match receiver.await.expect("jobs always send their result") {
+            Ok(result) => result,
+            Err(panic) => resume_unwind(panic),
+        }
+    }
+}
+
+/// The pools CPU heavy work is moved onto.
+pub struct CpuPools {
+    /// Hashing when applying batches to the tree.
+    pub tree:  Arc<CpuPool>,
+    /// Proof verification and merkle proof generation.
+    pub proof: Arc<CpuPool>,
+}
+
+impl CpuPools {
+    /// # Errors
+    ///
+    /// Will return `Err` if the threads can't be spawned.
+    pub fn new(tree_threads: usize, proof_threads: usize, queue_size: usize) -> AnyhowResult<Self> {
+        Ok(Self {
+            tree:  Arc::new(CpuPool::new("tree", tree_threads, queue_size)?),
+            proof: Arc::new(CpuPool::new("proof", proof_threads, queue_size)?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn runs_jobs_off_the_runtime() -> AnyhowResult<()> {
+        let pool = CpuPool::new("test", 2, 1)?;
+
+        let thread_name = pool
+            .run(|| std::thread::current().name().map(ToOwned::to_owned))
+            .await;
+        assert!(thread_name.is_some_and(|name| name.starts_with("test-")));
+
+        let results = futures::future::join_all((0..4).map(|i| pool.run(move || i * 2))).await;
+        assert_eq!(results, vec![0, 2, 4, 6]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "job panicked")]
+    async fn propagates_panics() {
+        let pool = CpuPool::new("test", 1, 1).unwrap();
+
+        pool.run(|| panic!("job panicked")).await;
+    }
+}
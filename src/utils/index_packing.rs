@@ -1,3 +1,14 @@
+/// Returns the index used to pad deletion batches, `2^tree_depth`.
+///
+/// The contract packs deletion indices as `u32`, so deletions are only
+/// supported for trees where the padding index still fits.
+pub fn padding_index(tree_depth: usize) -> anyhow::Result<u32> {
+    u32::try_from(tree_depth)
+        .ok()
+        .and_then(|depth| 2u32.checked_pow(depth))
+        .ok_or_else(|| anyhow::anyhow!("Tree depth {tree_depth} is too large for deletions"))
+}
+
 #[must_use]
 pub fn pack_indices(indices: &[u32]) -> Vec<u8> {
     let mut packed = Vec::with_capacity(indices.len() * 4);
@@ -38,4 +49,12 @@ mod tests {
 
         assert_eq!(unpacked, indices);
     }
+
+    #[test]
+    fn test_padding_index() {
+        assert_eq!(padding_index(30).unwrap(), 1 << 30);
+        assert_eq!(padding_index(31).unwrap(), 1 << 31);
+        assert!(padding_index(32).is_err());
+        assert!(padding_index(40).is_err());
+    }
 }
@@ -0,0 +1,169 @@
+//! Bounds the number of proofs built or verified at once, so that bursts of
+//! proof requests can't starve insertions of the tree and the proof pool.
+//! Requests which would wait behind a full queue are turned away right away.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use once_cell::sync::Lazy;
+use prometheus::{
+    exponential_buckets, register_histogram, register_int_counter, register_int_gauge, Histogram,
+    IntCounter, IntGauge,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+static LIMIT: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "proof_computations_limit",
+        "Maximum number of proofs computed at once, 0 if unlimited."
+    )
+    .unwrap()
+});
+
+static IN_FLIGHT: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "proof_computations_in_flight",
+        "Number of proofs being computed."
+    )
+    .unwrap()
+});
+
+static WAITING: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "proof_computations_waiting",
+        "Number of proof requests waiting for a computation slot."
+    )
+    .unwrap()
+});
+
+static REJECTED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "proof_computations_rejected",
+        "Number of proof requests rejected because the wait queue was full."
+    )
+    .unwrap()
+});
+
+static WAIT: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "proof_computation_wait_seconds",
+        "Time proof requests waited for a computation slot.",
+        exponential_buckets(0.000_1, 4.0, 10).unwrap()
+    )
+    .unwrap()
+});
+
+pub struct ProofLimiter {
+    permits:     Arc<Semaphore>,
+    waiting:     AtomicUsize,
+    max_waiting: usize,
+}
+
+/// A computation slot, released when dropped.
+pub struct ProofPermit {
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Drop for ProofPermit {
+    fn drop(&mut self) {
+        IN_FLIGHT.dec();
+    }
+}
+
+/// Counts a request as waiting for as long as it's alive, including when the
+/// request is cancelled.
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+        WAITING.dec();
+    }
+}
+
+impl ProofLimiter {
+    /// Allows `max_concurrent` computations at once, or any number if zero,
+    /// with up to `max_waiting` requests waiting for a slot.
+    #[must_use]
+    pub fn new(max_concurrent: usize, max_waiting: usize) -> Self {
+        LIMIT.set(max_concurrent as i64);
+
+        let permits = if max_concurrent == 0 {
+            Semaphore::MAX_PERMITS
+        } else {
+            max_concurrent
+        };
+
+        Self {
+            permits: Arc::new(Semaphore::new(permits)),
+            waiting: AtomicUsize::new(0),
+            max_waiting,
+        }
+    }
+
+    /// Waits for a computation slot. Returns `None` without waiting if the
+    /// queue of waiting requests is full.
+    pub async fn acquire(&self) -> Option<ProofPermit> {
+        let permit = match self.permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                if self.waiting.fetch_add(1, Ordering::Relaxed) >= self.max_waiting {
+                    self.waiting.fetch_sub(1, Ordering::Relaxed);
+                    REJECTED.inc();
+                    return None;
+                }
+                WAITING.inc();
+                let _waiting = Waiting(&self.waiting);
+
+                let start = Instant::now();
+                let permit = self
+                    .permits
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("the proof semaphore is never closed");
+                WAIT.observe(start.elapsed().as_secs_f64());
+
+                permit
+            }
+        };
+
+        IN_FLIGHT.inc();
+
+        Some(ProofPermit { _permit: permit })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn rejects_requests_beyond_the_queue() {
+        let limiter = ProofLimiter::new(1, 1);
+
+        let running = limiter.acquire().await.expect("a slot is free");
+
+        let waiting = limiter.acquire();
+        tokio::pin!(waiting);
+        assert!(waiting.as_mut().now_or_never().is_none());
+
+        // The queue is full.
+        assert!(limiter.acquire().await.is_none());
+
+        drop(running);
+        assert!(waiting.await.is_some());
+        assert_eq!(limiter.waiting.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn zero_is_unlimited() {
+        let limiter = ProofLimiter::new(0, 0);
+
+        let permits: Vec<_> = futures::future::join_all((0..100).map(|_| limiter.acquire())).await;
+        assert!(permits.iter().all(Option::is_some));
+    }
+}
@@ -0,0 +1,206 @@
+use std::fmt::Debug;
+use std::future::Future;
+use std::time::Duration;
+
+use clap::Parser;
+use ethers::core::rand::{thread_rng, Rng};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use tracing::warn;
+
+static RETRIES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "client_retries",
+        "Number of retried requests to the chain, the database and the provers.",
+        &["client"]
+    )
+    .unwrap()
+});
+
+/// Retries of requests to the Ethereum provider, the database and the provers.
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// The number of attempts of a request failing with a transient error,
+    /// e.g. a timeout or a dropped connection. Set to 1 to disable retries.
+    #[clap(long, env, default_value = "3")]
+    pub retry_max_attempts: u32,
+
+    /// The delay before the first retry (milliseconds). It doubles with every
+    /// retry, with random jitter.
+    #[clap(long, env, default_value = "100")]
+    pub retry_initial_backoff_ms: u64,
+
+    /// The longest delay between retries (milliseconds).
+    #[clap(long, env, default_value = "5000")]
+    pub retry_max_backoff_ms: u64,
+}
+
+impl Options {
+    #[must_use]
+    pub const fn policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts:    self.retry_max_attempts,
+            initial_backoff: Duration::from_millis(self.retry_initial_backoff_ms),
+            max_backoff:     Duration::from_millis(self.retry_max_backoff_ms),
+        }
+    }
+}
+
+/// Classifies errors worth retrying, i.e. those which may not happen again.
+pub trait Retryable {
+    fn is_retryable(&self) -> bool;
+}
+
+impl Retryable for reqwest::Error {
+    fn is_retryable(&self) -> bool {
+        self.is_timeout()
+            || self.is_connect()
+            || self.status().map_or(false, |status| {
+                status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            })
+    }
+}
+
+/// Exponential backoff with jitter, shared by all clients.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts:    u32,
+    initial_backoff: Duration,
+    max_backoff:     Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts:    3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff:     Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before the given retry, starting at 1. Delays are drawn
+    /// uniformly up to the exponential backoff, so that clients failing
+    /// together don't retry together.
+    #[must_use]
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponential = self
+            .initial_backoff
+            .saturating_mul(2_u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_backoff);
+
+        exponential.mul_f64(thread_rng().gen_range(0.0..=1.0))
+    }
+
+    /// Runs `operation` until it succeeds, fails with an error which isn't
+    /// [`Retryable`], or runs out of attempts. `client` labels the retries in
+    /// logs and metrics.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the last attempt.
+    pub async fn retry<T, E, F, Fut>(&self, client: &str, mut operation: F) -> Result<T, E>
+    where
+        E: Retryable + Debug,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 1;
+
+        loop {
+            match operation().await {
+                Err(error) if error.is_retryable() && attempt < self.max_attempts => {
+                    let backoff = self.backoff(attempt);
+                    warn!(client, attempt, ?backoff, ?error, "Retrying request");
+                    RETRIES.with_label_values(&[client]).inc();
+
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct TestError {
+        retryable: bool,
+    }
+
+    impl Retryable for TestError {
+        fn is_retryable(&self) -> bool {
+            self.retryable
+        }
+    }
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(4),
+        }
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let policy = policy(10);
+
+        for retry in 1..10 {
+            let cap = Duration::from_millis(1 << (retry - 1).min(2));
+            assert!(policy.backoff(retry) <= cap);
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors_up_to_max_attempts() {
+        let attempts = &AtomicU32::new(0);
+
+        let result: Result<(), _> = policy(3)
+            .retry("test", move || async move {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(TestError { retryable: true })
+            })
+            .await;
+
+        assert_eq!(result, Err(TestError { retryable: true }));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn stops_at_permanent_errors_and_successes() {
+        let attempts = &AtomicU32::new(0);
+
+        let result: Result<(), _> = policy(3)
+            .retry("test", move || async move {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(TestError { retryable: false })
+            })
+            .await;
+
+        assert_eq!(result, Err(TestError { retryable: false }));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        attempts.store(0, Ordering::SeqCst);
+        let result = policy(3)
+            .retry("test", move || async move {
+                if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(TestError { retryable: true })
+                } else {
+                    Ok(())
+                }
+            })
+            .await;
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+}
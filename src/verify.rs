@@ -0,0 +1,221 @@
+//! Offline verification of exported inclusion proofs, e.g. packs written by
+//! `prove-all`, without access to the tree.
+
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result as AnyhowResult};
+use semaphore::poseidon_tree::Proof;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::identity_tree::{verify_proof, Hash};
+use crate::prove_all::{PackedProof, ProofPack};
+
+/// The proofs of a file, either a pack or a single `/inclusionProof`
+/// response.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ProofFile {
+    Pack(ProofPack),
+    Single { root: Hash, proof: Proof },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Verdict {
+    commitment: Hash,
+    leaf_index: usize,
+    /// Why the proof doesn't hold, `None` if it does.
+    failure:    Option<String>,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.failure {
+            None => write!(
+                f,
+                "[PASS] {:#x} at leaf {}",
+                self.commitment, self.leaf_index
+            ),
+            Some(failure) => write!(
+                f,
+                "[FAIL] {:#x} at leaf {}: {failure}",
+                self.commitment, self.leaf_index
+            ),
+        }
+    }
+}
+
+fn verify_entry(entry: &PackedProof, root: Hash) -> Verdict {
+    let proven_index = entry.proof.leaf_index();
+
+    let failure = if !verify_proof(entry.identity_commitment, &entry.proof, root) {
+        Some(format!(
+            "proof leads to root {:#x}",
+            entry.proof.root(entry.identity_commitment)
+        ))
+    } else if proven_index != entry.leaf_index {
+        Some(format!("proof is of leaf {proven_index}"))
+    } else {
+        None
+    };
+
+    Verdict {
+        commitment: entry.identity_commitment,
+        leaf_index: entry.leaf_index,
+        failure,
+    }
+}
+
+/// Verifies the proofs of a file against `root`, or the root the file was
+/// written for. Only the proofs of `commitment` are verified if it's given,
+/// single proofs don't name their commitment and require it.
+fn verify_file(
+    file: ProofFile,
+    root: Option<Hash>,
+    commitment: Option<Hash>,
+) -> AnyhowResult<Vec<Verdict>> {
+    let (file_root, entries) = match file {
+        ProofFile::Pack(pack) => (pack.root, pack.proofs),
+        ProofFile::Single { root, proof } => {
+            let Some(commitment) = commitment else {
+                bail!("--commitment is required to verify a single proof");
+            };
+            let entry = PackedProof {
+                identity_commitment: commitment,
+                leaf_index: proof.leaf_index(),
+                proof,
+            };
+            (root, vec![entry])
+        }
+    };
+
+    let root = root.unwrap_or(file_root);
+    let verdicts: Vec<Verdict> = entries
+        .iter()
+        .filter(|entry| commitment.map_or(true, |c| c == entry.identity_commitment))
+        .map(|entry| verify_entry(entry, root))
+        .collect();
+
+    if verdicts.is_empty() {
+        bail!("no proofs to verify");
+    }
+
+    Ok(verdicts)
+}
+
+/// Verifies the proofs of a file, printing a line per proof, and fails if any
+/// doesn't hold.
+///
+/// # Errors
+///
+/// Will return `Err` if the file can't be read or parsed, or a proof doesn't
+/// hold.
+pub fn run(file: &Path, root: Option<Hash>, commitment: Option<Hash>) -> AnyhowResult<()> {
+    let contents = std::fs::read_to_string(file)
+        .with_context(|| format!("failed to read {}", file.display()))?;
+    let proofs: ProofFile = serde_json::from_str(&contents)
+        .with_context(|| format!("{} is neither a proof pack nor a proof", file.display()))?;
+
+    if root.is_none() {
+        warn!(
+            "Verifying against the root of the file, pass --root to verify against a trusted one"
+        );
+    }
+
+    let verdicts = verify_file(proofs, root, commitment)?;
+    for verdict in &verdicts {
+        println!("{verdict}");
+    }
+
+    let failed = verdicts
+        .iter()
+        .filter(|verdict| verdict.failure.is_some())
+        .count();
+    if failed > 0 {
+        bail!("{failed} of {} proof(s) failed", verdicts.len());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use semaphore::poseidon_tree::LazyPoseidonTree;
+    use serde_json::json;
+
+    use super::*;
+
+    fn pack() -> (Hash, serde_json::Value) {
+        let tree = LazyPoseidonTree::new(4, Hash::ZERO)
+            .update(0, &Hash::from(1))
+            .update(1, &Hash::from(2));
+
+        let pack = json!({
+            "root": tree.root(),
+            "proofs": [
+                { "identityCommitment": Hash::from(1), "leafIndex": 0, "proof": tree.proof(0) },
+                { "identityCommitment": Hash::from(2), "leafIndex": 1, "proof": tree.proof(1) },
+            ],
+            "missing": [Hash::from(42)],
+        });
+
+        (tree.root(), pack)
+    }
+
+    #[test]
+    fn verifies_packs() -> AnyhowResult<()> {
+        let (root, pack) = pack();
+
+        let verdicts = verify_file(serde_json::from_value(pack.clone())?, None, None)?;
+        assert_eq!(verdicts.len(), 2);
+        assert!(verdicts.iter().all(|verdict| verdict.failure.is_none()));
+
+        let verdicts = verify_file(serde_json::from_value(pack)?, Some(Hash::from(3)), None)?;
+        assert!(verdicts.iter().all(|verdict| verdict.failure.is_some()));
+        assert!(verdicts[0].to_string().starts_with("[FAIL]"));
+        assert_ne!(root, Hash::from(3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn proofs_must_match_their_leaf() -> AnyhowResult<()> {
+        let (_, mut pack) = pack();
+        pack["proofs"][1]["leafIndex"] = json!(3);
+
+        let verdicts = verify_file(serde_json::from_value(pack)?, None, Some(Hash::from(2)))?;
+        assert_eq!(verdicts, vec![Verdict {
+            commitment: Hash::from(2),
+            leaf_index: 3,
+            failure:    Some("proof is of leaf 1".to_owned()),
+        }]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn verifies_single_proofs() -> AnyhowResult<()> {
+        let (root, pack) = pack();
+        let response = json!({
+            "status": "mined",
+            "root": root,
+            "proof": pack["proofs"][1]["proof"],
+            "message": null,
+        });
+
+        let file = || serde_json::from_value::<ProofFile>(response.clone());
+        assert!(verify_file(file()?, None, None).is_err());
+
+        let verdicts = verify_file(file()?, Some(root), Some(Hash::from(2)))?;
+        assert_eq!(
+            verdicts[0].to_string(),
+            format!("[PASS] {:#x} at leaf 1", Hash::from(2))
+        );
+
+        let verdicts = verify_file(file()?, None, Some(Hash::from(1)))?;
+        assert!(verdicts[0].failure.is_some());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use common::prelude::*;
+use ethers::abi::parse_abi;
+use ethers::contract::Contract;
+
+const SUPPORTED_DEPTH: usize = 20;
+const IDLE_TIME: u64 = 7;
+
+/// A batch which reverts is quarantined after simulating it, without being
+/// broadcast.
+#[tokio::test]
+async fn reverting_batches_are_not_broadcast() -> anyhow::Result<()> {
+    init_tracing_subscriber();
+    info!("Starting batch simulation test");
+
+    let batch_size: usize = 3;
+    #[allow(clippy::cast_possible_truncation)]
+    let tree_depth: u8 = SUPPORTED_DEPTH as u8;
+
+    let mut ref_tree = PoseidonTree::new(SUPPORTED_DEPTH + 1, ruint::Uint::ZERO);
+    let initial_root: U256 = ref_tree.root().into();
+
+    let (mock_chain, db_container, insertion_prover_map, _, micro_oz) =
+        spawn_deps(initial_root, &[batch_size], &[], tree_depth).await?;
+
+    let prover_mock = &insertion_prover_map[&batch_size];
+
+    let db_socket_addr = db_container.address();
+    let db_url = format!("postgres://postgres:postgres@{db_socket_addr}/database");
+
+    let temp_dir = tempfile::tempdir()?;
+
+    let mut options = Options::try_parse_from([
+        "signup-sequencer",
+        "--identity-manager-address",
+        "0x0000000000000000000000000000000000000000", // placeholder, updated below
+        "--database",
+        &db_url,
+        "--database-max-connections",
+        "1",
+        "--tree-depth",
+        &format!("{tree_depth}"),
+        "--prover-urls",
+        &prover_mock.arg_string(),
+        "--batch-timeout-seconds",
+        "10",
+        "--dense-tree-prefix-depth",
+        "10",
+        "--tree-gc-threshold",
+        "1",
+        "--oz-api-key",
+        "",
+        "--oz-api-secret",
+        "",
+        "--oz-api-url",
+        &micro_oz.endpoint(),
+        "--oz-address",
+        &format!("{:?}", micro_oz.address()),
+        "--time-between-scans-seconds",
+        "1",
+        "--dense-tree-mmap-file",
+        temp_dir.path().join("testfile").to_str().unwrap(),
+    ])
+    .context("Failed to create options")?;
+
+    options.server.server = Url::parse("http://127.0.0.1:0/")?;
+
+    options.app.contracts.identity_manager_address = mock_chain.identity_manager.address();
+    options.app.ethereum.ethereum_provider = mock_chain.anvil.endpoint().parse()?;
+
+    let (app, local_addr) = spawn_app(options.clone())
+        .await
+        .expect("Failed to spawn app.");
+
+    // Revoke the sequencer's authorization once it's running, so that every
+    // batch reverts.
+    let abi = parse_abi(&[
+        "function setIdentityOperator(address newIdentityOperator) public virtual returns \
+         (address)",
+        "function latestRoot() public view virtual returns (uint256 root)",
+    ])?;
+    let identity_manager = Contract::new(
+        mock_chain.identity_manager.address(),
+        abi,
+        mock_chain.identity_manager.client(),
+    );
+    identity_manager
+        .method::<_, Address>("setIdentityOperator", Address::random())?
+        .send()
+        .await?
+        .await?;
+
+    let provider = Provider::<Http>::try_from(mock_chain.anvil.endpoint())?;
+    let nonce_before = provider
+        .get_transaction_count(micro_oz.address(), None)
+        .await?;
+    let latest_root_before = identity_manager
+        .method::<_, U256>("latestRoot", ())?
+        .call()
+        .await?;
+
+    let identities: Vec<Field> = generate_test_identities(batch_size)
+        .iter()
+        .map(|identity| Hash::from_str_radix(identity, 16).unwrap())
+        .collect();
+
+    let uri = "http://".to_owned() + &local_addr.to_string();
+    let client = Client::new();
+
+    for leaf_index in 0..batch_size {
+        test_insert_identity(&uri, &client, &mut ref_tree, &identities, leaf_index).await;
+    }
+
+    tokio::time::sleep(Duration::from_secs(IDLE_TIME)).await;
+
+    let req = Request::builder()
+        .method("GET")
+        .uri(uri.clone() + "/admin/failedBatches")
+        .body(Body::empty())?;
+    let response = client.request(req).await?;
+    assert!(response.status().is_success());
+    let bytes = hyper::body::to_bytes(response.into_body()).await?;
+    let failed: serde_json::Value = serde_json::from_slice(&bytes)?;
+
+    let batches = failed["batches"].as_array().context("Missing batches")?;
+    assert_eq!(batches.len(), 1, "Unexpected failed batches: {failed}");
+    assert_eq!(batches[0]["kind"], "insertion");
+    assert_eq!(batches[0]["preRoot"], json!(Hash::from(initial_root)));
+    assert!(batches[0]["attempts"].as_u64() >= Some(1));
+
+    // Without an admin key the batch can be read, but not discarded.
+    let post_root = batches[0]["postRoot"]
+        .as_str()
+        .context("Missing post root")?;
+    let req = Request::builder()
+        .method("DELETE")
+        .uri(format!("{uri}/admin/failedBatches/{post_root}"))
+        .body(Body::empty())?;
+    let response = client.request(req).await?;
+    assert_eq!(response.status(), hyper::StatusCode::FORBIDDEN);
+
+    // Nothing was sent from the signer and the contract didn't move.
+    assert_eq!(
+        provider
+            .get_transaction_count(micro_oz.address(), None)
+            .await?,
+        nonce_before
+    );
+    assert_eq!(
+        identity_manager
+            .method::<_, U256>("latestRoot", ())?
+            .call()
+            .await?,
+        latest_root_before
+    );
+
+    shutdown();
+    app.await.unwrap();
+    reset_shutdown();
+
+    Ok(())
+}
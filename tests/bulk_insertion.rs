@@ -0,0 +1,172 @@
+mod common;
+use common::prelude::*;
+
+#[tokio::test]
+async fn bulk_insertion() -> anyhow::Result<()> {
+    info!("Starting bulk insertion test");
+
+    let tree_depth: u8 = 20;
+
+    let ref_tree = PoseidonTree::new(tree_depth as usize + 1, ruint::Uint::ZERO);
+    let initial_root: U256 = ref_tree.root().into();
+    let batch_size: usize = 3;
+
+    let (mock_chain, db_container, insertion_prover_map, _, micro_oz) =
+        spawn_deps(initial_root, &[batch_size], &[], tree_depth).await?;
+    let prover_mock = &insertion_prover_map[&batch_size];
+
+    let db_socket_addr = db_container.address();
+    let db_url = format!("postgres://postgres:postgres@{db_socket_addr}/database");
+
+    let temp_dir = tempfile::tempdir()?;
+    info!(
+        "temp dir created at: {:?}",
+        temp_dir.path().join("testfile")
+    );
+
+    let mut options = Options::try_parse_from([
+        "signup-sequencer",
+        "--identity-manager-address",
+        "0x0000000000000000000000000000000000000000", // placeholder, updated below
+        "--database",
+        &db_url,
+        "--database-max-connections",
+        "1",
+        "--tree-depth",
+        &format!("{tree_depth}"),
+        "--prover-urls",
+        &prover_mock.arg_string(),
+        "--batch-timeout-seconds",
+        "10",
+        "--dense-tree-prefix-depth",
+        "10",
+        "--tree-gc-threshold",
+        "1",
+        "--oz-api-key",
+        "",
+        "--oz-api-secret",
+        "",
+        "--oz-api-url",
+        &micro_oz.endpoint(),
+        "--oz-address",
+        &format!("{:?}", micro_oz.address()),
+        "--time-between-scans-seconds",
+        "1",
+        "--dense-tree-mmap-file",
+        temp_dir.path().join("testfile").to_str().unwrap(),
+    ])
+    .context("Failed to create options")?;
+
+    options.server.server = Url::parse("http://127.0.0.1:0/")?;
+
+    options.app.contracts.identity_manager_address = mock_chain.identity_manager.address();
+    options.app.ethereum.ethereum_provider = mock_chain.anvil.endpoint().parse()?;
+
+    let (app, local_addr) = spawn_app(options.clone())
+        .await
+        .expect("Failed to spawn app.");
+
+    let uri = "http://".to_owned() + &local_addr.to_string();
+    let client = Client::new();
+
+    let identities = generate_test_identities(2)
+        .iter()
+        .map(|identity| Hash::from_str_radix(identity, 16).unwrap())
+        .collect::<Vec<_>>();
+    let lines = [
+        json!({ "identityCommitment": identities[0] }).to_string(),
+        String::new(),
+        "{ not json".to_owned(),
+        json!({ "identityCommitment": ruint::Uint::<256, 4>::MAX }).to_string(),
+        json!({ "identityCommitment": identities[0] }).to_string(),
+        json!({ "identityCommitment": identities[1] }).to_string(),
+    ];
+
+    // The body is sent in chunks splitting lines.
+    let ndjson = lines.join("\n");
+    let chunks = ndjson
+        .as_bytes()
+        .chunks(7)
+        .map(|chunk| Ok::<_, std::io::Error>(chunk.to_vec()))
+        .collect::<Vec<_>>();
+
+    let req = Request::builder()
+        .method("POST")
+        .uri(uri.to_owned() + "/insertIdentities")
+        .header("Content-Type", "application/x-ndjson")
+        .body(Body::wrap_stream(futures::stream::iter(chunks)))
+        .expect("Failed to create insert identities hyper::Body");
+
+    let response = client
+        .request(req)
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+
+    let bytes = hyper::body::to_bytes(response.into_body())
+        .await
+        .expect("Failed to read body bytes");
+    let results = String::from_utf8_lossy(&bytes)
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<serde_json::Value>, _>>()?;
+
+    assert_eq!(results.len(), 5);
+
+    assert_eq!(results[0]["line"], 1);
+    assert_eq!(
+        results[0]["receipt"]["identityCommitment"],
+        json!(identities[0])
+    );
+
+    assert_eq!(results[1]["line"], 3);
+    assert!(results[1]["error"]
+        .as_str()
+        .unwrap()
+        .starts_with("invalid request payload"));
+
+    assert_eq!(results[2]["line"], 4);
+    assert_eq!(
+        results[2]["error"],
+        "provided identity commitment is not in reduced form"
+    );
+
+    assert_eq!(results[3]["line"], 5);
+    assert_eq!(
+        results[3]["error"],
+        "provided identity commitment is already included"
+    );
+
+    assert_eq!(results[4]["line"], 6);
+    assert_eq!(
+        results[4]["receipt"]["identityCommitment"],
+        json!(identities[1])
+    );
+    assert!(
+        results[4]["receipt"]["sequenceNumber"].as_u64()
+            > results[0]["receipt"]["sequenceNumber"].as_u64()
+    );
+
+    // Anything but NDJSON is rejected upfront.
+    let req = Request::builder()
+        .method("POST")
+        .uri(uri.to_owned() + "/insertIdentities")
+        .header("Content-Type", "application/json")
+        .body(Body::from(lines[0].clone()))
+        .expect("Failed to create insert identities hyper::Body");
+
+    let response = client
+        .request(req)
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), hyper::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    shutdown();
+    app.await?;
+    for (_, prover) in insertion_prover_map.into_iter() {
+        prover.stop();
+    }
+    reset_shutdown();
+
+    Ok(())
+}
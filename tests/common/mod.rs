@@ -512,7 +512,13 @@ pub async fn test_insert_identity(
         panic!("Failed to insert identity");
     }
 
-    assert!(bytes.is_empty());
+    let receipt: serde_json::Value =
+        serde_json::from_slice(&bytes).expect("Failed to parse insertion receipt");
+    assert_eq!(
+        receipt["identityCommitment"],
+        json!(test_leaves[leaf_index]),
+        "Insertion receipt is for a different commitment"
+    );
     ref_tree.set(leaf_index, test_leaves[leaf_index]);
 
     (ref_tree.proof(leaf_index).unwrap(), ref_tree.root())
@@ -0,0 +1,146 @@
+#![cfg(feature = "fault-injection")]
+#![allow(clippy::needless_range_loop)]
+
+mod common;
+
+use common::prelude::*;
+
+const SUPPORTED_DEPTH: usize = 20;
+const IDLE_TIME: u64 = 7;
+const FAULTS_ENV: &str = "SEQUENCER_FAULTS";
+
+/// Interrupts the sequencer at the points faults are injected at and checks
+/// that the identities it was working on are inserted after a restart.
+#[tokio::test]
+async fn fault_injection() -> anyhow::Result<()> {
+    // Initialize logging for the test.
+    init_tracing_subscriber();
+    info!("Starting integration test");
+
+    let batch_size: usize = 3;
+    #[allow(clippy::cast_possible_truncation)]
+    let tree_depth: u8 = SUPPORTED_DEPTH as u8;
+
+    let mut ref_tree = PoseidonTree::new(SUPPORTED_DEPTH + 1, ruint::Uint::ZERO);
+    let initial_root: U256 = ref_tree.root().into();
+
+    let (mock_chain, db_container, insertion_prover_map, _, micro_oz) =
+        spawn_deps(initial_root, &[batch_size], &[], tree_depth).await?;
+
+    let prover_mock = &insertion_prover_map[&batch_size];
+
+    let db_socket_addr = db_container.address();
+    let db_url = format!("postgres://postgres:postgres@{db_socket_addr}/database");
+
+    // temp dir will be deleted on drop call
+    let temp_dir = tempfile::tempdir()?;
+
+    let mut options = Options::try_parse_from([
+        "signup-sequencer",
+        "--identity-manager-address",
+        "0x0000000000000000000000000000000000000000", // placeholder, updated below
+        "--database",
+        &db_url,
+        "--database-max-connections",
+        "1",
+        "--tree-depth",
+        &format!("{tree_depth}"),
+        "--prover-urls",
+        &prover_mock.arg_string(),
+        "--batch-timeout-seconds",
+        "10",
+        "--dense-tree-prefix-depth",
+        "10",
+        "--tree-gc-threshold",
+        "1",
+        "--oz-api-key",
+        "",
+        "--oz-api-secret",
+        "",
+        "--oz-api-url",
+        &micro_oz.endpoint(),
+        "--oz-address",
+        &format!("{:?}", micro_oz.address()),
+        "--time-between-scans-seconds",
+        "1",
+        "--dense-tree-mmap-file",
+        temp_dir.path().join("testfile").to_str().unwrap(),
+    ])
+    .context("Failed to create options")?;
+
+    options.server.server = Url::parse("http://127.0.0.1:0/").expect("Failed to parse URL");
+
+    options.app.contracts.identity_manager_address = mock_chain.identity_manager.address();
+    options.app.ethereum.ethereum_provider = mock_chain
+        .anvil
+        .endpoint()
+        .parse()
+        .expect("Failed to parse Anvil url");
+
+    let test_identities = generate_test_identities(batch_size * 2);
+    let identities_ref: Vec<Field> = test_identities
+        .iter()
+        .map(|i| Hash::from_str_radix(i, 16).unwrap())
+        .collect();
+
+    let client = Client::new();
+
+    // Fail writing the second identity of the batch, so that the tree runs ahead
+    // of the database.
+    std::env::set_var(FAULTS_ENV, "before_db_write=fail@2");
+    let (app, local_addr) = spawn_app(options.clone())
+        .await
+        .expect("Failed to spawn app.");
+    let uri = "http://".to_owned() + &local_addr.to_string();
+
+    for i in 0..batch_size {
+        test_insert_identity(&uri, &client, &mut ref_tree, &identities_ref, i).await;
+    }
+    tokio::time::sleep(Duration::from_secs(IDLE_TIME)).await;
+
+    info!("Restarting the app after the interrupted database write");
+    shutdown();
+    app.await.unwrap();
+    reset_shutdown();
+
+    // Submit the next batch, but fail before the transaction is recorded.
+    std::env::set_var(FAULTS_ENV, "after_tx_send=fail");
+    let (app, local_addr) = spawn_app(options.clone())
+        .await
+        .expect("Failed to spawn app.");
+    let uri = "http://".to_owned() + &local_addr.to_string();
+
+    for i in 0..batch_size {
+        test_inclusion_proof(&uri, &client, i, &ref_tree, &identities_ref[i], false).await;
+    }
+
+    for i in batch_size..batch_size * 2 {
+        test_insert_identity(&uri, &client, &mut ref_tree, &identities_ref, i).await;
+    }
+    tokio::time::sleep(Duration::from_secs(IDLE_TIME * 2)).await;
+
+    info!("Restarting the app after the unrecorded transaction");
+    shutdown();
+    app.await.unwrap();
+    reset_shutdown();
+
+    std::env::remove_var(FAULTS_ENV);
+    let (app, local_addr) = spawn_app(options.clone())
+        .await
+        .expect("Failed to spawn app.");
+    let uri = "http://".to_owned() + &local_addr.to_string();
+
+    // The transaction was mined, so the identities are found on chain.
+    for i in 0..batch_size * 2 {
+        test_inclusion_proof(&uri, &client, i, &ref_tree, &identities_ref[i], false).await;
+    }
+
+    shutdown();
+    app.await.unwrap();
+    for (_, prover) in insertion_prover_map.into_iter() {
+        prover.stop();
+    }
+    reset_shutdown();
+
+    Ok(())
+}
@@ -0,0 +1,195 @@
+"""Generates the golden trees `tests/golden_trees.rs` checks the sequencer
+against, independently of it and of semaphore-rs:
+
+    python3 tests/fixtures/golden/generate.py
+
+The Poseidon parameters are derived with the Grain LFSR of the reference
+script of the Poseidon paper, `generate_parameters_grain.sage 1 0 254 3 8 57`,
+as circomlib's are. The MiMC sponge uses the round constants of
+`tests/fixtures/mimcsponge.json`. Regenerating the files must not change them.
+"""
+
+import hashlib
+import json
+from pathlib import Path
+
+P = 21888242871839275222246405745257275088548364400416034343698204186575808495617
+
+DIR = Path(__file__).parent
+
+
+def grain(n, t, full_rounds, partial_rounds):
+    """The bits of the Grain LFSR seeded with the Poseidon instance."""
+
+    def bits(value, width):
+        return [int(bit) for bit in bin(value)[2:].zfill(width)]
+
+    state = (
+        bits(1, 2)
+        + bits(0, 4)
+        + bits(n, 12)
+        + bits(t, 12)
+        + bits(full_rounds, 10)
+        + bits(partial_rounds, 10)
+        + [1] * 30
+    )
+
+    def step():
+        bit = state[62] ^ state[51] ^ state[38] ^ state[23] ^ state[13] ^ state[0]
+        state.pop(0)
+        state.append(bit)
+        return bit
+
+    for _ in range(160):
+        step()
+
+    while True:
+        # A set bit keeps the next one, an unset one drops it.
+        if step():
+            yield step()
+        else:
+            step()
+
+
+def take(bits, n):
+    value = 0
+    for _ in range(n):
+        value = (value << 1) | next(bits)
+    return value
+
+
+def poseidon_parameters():
+    bits = grain(254, 3, 8, 57)
+
+    constants = []
+    for _ in range((8 + 57) * 3):
+        value = take(bits, 254)
+        while value >= P:
+            value = take(bits, 254)
+        constants.append(value)
+
+    # The Cauchy matrix of the first candidate, which passes the security
+    # checks of the reference script for width 3.
+    xs_ys = [take(bits, 254) % P for _ in range(6)]
+    xs, ys = xs_ys[:3], xs_ys[3:]
+    mds = [[pow(x + y, P - 2, P) for y in ys] for x in xs]
+
+    return constants, mds
+
+
+POSEIDON_CONSTANTS, POSEIDON_MDS = poseidon_parameters()
+
+
+def poseidon(left, right):
+    state = [0, left, right]
+    for round in range(8 + 57):
+        state = [(value + POSEIDON_CONSTANTS[round * 3 + i]) % P for i, value in enumerate(state)]
+        if round < 4 or round >= 4 + 57:
+            state = [pow(value, 5, P) for value in state]
+        else:
+            state[0] = pow(state[0], 5, P)
+        state = [sum(m * value for m, value in zip(row, state)) % P for row in POSEIDON_MDS]
+    return state[0]
+
+
+MIMC_CONSTANTS = [
+    int(constant, 16)
+    for constant in json.loads((DIR.parent / "mimcsponge.json").read_text())["constants"]
+]
+
+
+def mimc(left, right):
+    """The sponge with a zero key and a single output, as circomlib's
+    `MiMCSponge(2, 220, 1)`."""
+    state_left, state_right = 0, 0
+    for value in (left, right):
+        state_left = (state_left + value) % P
+        for round, constant in enumerate(MIMC_CONSTANTS):
+            t = pow(state_left + constant, 5, P)
+            if round < len(MIMC_CONSTANTS) - 1:
+                state_left, state_right = (state_right + t) % P, state_left
+            else:
+                state_right = (state_right + t) % P
+    return state_left
+
+
+def field_element(i):
+    digest = hashlib.sha256(f"leaf{i}".encode()).digest()
+    return int.from_bytes(digest, "big") % P
+
+
+def hex_field(value):
+    return f"0x{value:064x}"
+
+
+def vector(hash_node, name, depth, leaves, proof_indices):
+    # Only the nodes above the leaves are hashed, the others are those of the
+    # empty tree.
+    empty = [0]
+    for _ in range(depth):
+        empty.append(hash_node(empty[-1], empty[-1]))
+
+    levels = [dict(enumerate(leaves))]
+    for height in range(depth):
+        nodes = levels[-1]
+        parents = {index // 2 for index in nodes}
+        levels.append(
+            {
+                parent: hash_node(
+                    nodes.get(2 * parent, empty[height]),
+                    nodes.get(2 * parent + 1, empty[height]),
+                )
+                for parent in parents
+            }
+        )
+
+    proofs = []
+    for leaf_index in proof_indices:
+        proof = []
+        index = leaf_index
+        for height in range(depth):
+            # `Left` if the node on the path is the left child.
+            side = "Left" if index % 2 == 0 else "Right"
+            sibling = levels[height].get(index ^ 1, empty[height])
+            proof.append({side: hex_field(sibling)})
+            index //= 2
+        proofs.append({"leafIndex": leaf_index, "proof": proof})
+
+    return {
+        "name": name,
+        "depth": depth,
+        "leaves": [hex_field(leaf) for leaf in leaves],
+        "root": hex_field(levels[-1].get(0, empty[depth])),
+        "proofs": proofs,
+    }
+
+
+def golden_file(hasher, hash_node):
+    small = list(range(1, 6))
+    full = list(range(1, 17))
+    elements = [field_element(i) for i in range(33)]
+
+    return {
+        "hasher": hasher,
+        "vectors": [
+            vector(hash_node, "empty depth 4", 4, [], [0, 15]),
+            vector(hash_node, "small leaves depth 4", 4, small, [0, 4, 5, 15]),
+            vector(hash_node, "full depth 4", 4, full, [0, 7, 15]),
+            vector(hash_node, "deleted leaf depth 4", 4, [1, 0, 3], [0, 1, 2]),
+            vector(hash_node, "field elements depth 10", 10, elements, [0, 31, 32, 33, 1023]),
+            vector(hash_node, "empty depth 20", 20, [], [0, (1 << 20) - 1]),
+            vector(
+                hash_node, "field elements depth 20", 20, elements[:3], [0, 2, 3, (1 << 20) - 1]
+            ),
+        ],
+    }
+
+
+def main():
+    for hasher, hash_node in (("poseidon", poseidon), ("mimc", mimc)):
+        contents = json.dumps(golden_file(hasher, hash_node), indent=2)
+        (DIR / f"{hasher}.json").write_text(contents)
+
+
+if __name__ == "__main__":
+    main()
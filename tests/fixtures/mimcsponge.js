@@ -0,0 +1,58 @@
+// Generates `mimcsponge.json`, the round constants and hashes of the MiMC
+// sponge of circomlib that `tests/mimc_vectors.rs` checks semaphore-rs against:
+//
+//     npm install circomlibjs@0.1.7
+//     node tests/fixtures/mimcsponge.js > tests/fixtures/mimcsponge.json
+//
+// The inputs are drawn from a fixed seed, regenerating the file must not change
+// it.
+const { buildMimcSponge } = require("circomlibjs");
+
+const MASK = (1n << 64n) - 1n;
+
+// splitmix64
+let state = 0x5eedn;
+function next() {
+    state = (state + 0x9e3779b97f4a7c15n) & MASK;
+    let z = state;
+    z = ((z ^ (z >> 30n)) * 0xbf58476d1ce4e5b9n) & MASK;
+    z = ((z ^ (z >> 27n)) * 0x94d049bb133111ebn) & MASK;
+    return z ^ (z >> 31n);
+}
+
+function hex(value) {
+    return "0x" + value.toString(16).padStart(64, "0");
+}
+
+async function main() {
+    const mimcSponge = await buildMimcSponge();
+    const F = mimcSponge.F;
+    const p = F.p;
+
+    const element = () => {
+        let value = 0n;
+        for (let limb = 0; limb < 4; limb++) {
+            value = (value << 64n) | next();
+        }
+        return value % p;
+    };
+
+    const inputs = [[], [0n], [1n], [1n, 2n], [p - 1n], [p - 1n, p - 1n], [0n, 0n, 0n, 0n]];
+    for (let length = 1; length <= 8; length++) {
+        for (let i = 0; i < 4; i++) {
+            inputs.push(Array.from({ length }, element));
+        }
+    }
+
+    const fixture = {
+        constants: mimcSponge.getConstants().map((constant) => hex(F.toObject(constant))),
+        vectors: inputs.map((values) => ({
+            inputs: values.map(hex),
+            hash: hex(F.toObject(mimcSponge.multiHash(values, 0, 1))),
+        })),
+    };
+
+    console.log(JSON.stringify(fixture, null, 2));
+}
+
+main();
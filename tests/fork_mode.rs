@@ -0,0 +1,121 @@
+mod common;
+
+use common::prelude::*;
+use ethers::abi::parse_abi;
+use ethers::contract::Contract;
+
+const SUPPORTED_DEPTH: usize = 20;
+
+/// In fork mode batches are sent from the impersonated operator through the
+/// Ethereum provider, without a relayer.
+#[tokio::test]
+async fn batches_are_sent_from_the_impersonated_operator() -> anyhow::Result<()> {
+    init_tracing_subscriber();
+    info!("Starting fork mode test");
+
+    let batch_size: usize = 3;
+    #[allow(clippy::cast_possible_truncation)]
+    let tree_depth: u8 = SUPPORTED_DEPTH as u8;
+
+    let mut ref_tree = PoseidonTree::new(SUPPORTED_DEPTH + 1, ruint::Uint::ZERO);
+    let initial_root: U256 = ref_tree.root().into();
+
+    let (mock_chain, db_container, insertion_prover_map, _, micro_oz) =
+        spawn_deps(initial_root, &[batch_size], &[], tree_depth).await?;
+
+    let prover_mock = &insertion_prover_map[&batch_size];
+
+    let db_socket_addr = db_container.address();
+    let db_url = format!("postgres://postgres:postgres@{db_socket_addr}/database");
+
+    let temp_dir = tempfile::tempdir()?;
+
+    let mut options = Options::try_parse_from([
+        "signup-sequencer",
+        "--identity-manager-address",
+        "0x0000000000000000000000000000000000000000", // placeholder, updated below
+        "--database",
+        &db_url,
+        "--database-max-connections",
+        "1",
+        "--tree-depth",
+        &format!("{tree_depth}"),
+        "--prover-urls",
+        &prover_mock.arg_string(),
+        "--batch-timeout-seconds",
+        "3",
+        "--dense-tree-prefix-depth",
+        "10",
+        "--tree-gc-threshold",
+        "1",
+        "--oz-api-key",
+        "",
+        "--oz-api-secret",
+        "",
+        // Nothing listens there, transactions must not go through a relayer.
+        "--oz-api-url",
+        "http://127.0.0.1:1",
+        "--oz-address",
+        &format!("{:?}", micro_oz.address()),
+        "--fork-mode",
+        "--time-between-scans-seconds",
+        "1",
+        "--dense-tree-mmap-file",
+        temp_dir.path().join("testfile").to_str().unwrap(),
+    ])
+    .context("Failed to create options")?;
+
+    options.server.server = Url::parse("http://127.0.0.1:0/")?;
+
+    options.app.contracts.identity_manager_address = mock_chain.identity_manager.address();
+    options.app.ethereum.ethereum_provider = mock_chain.anvil.endpoint().parse()?;
+
+    let provider = Provider::<Http>::try_from(mock_chain.anvil.endpoint())?;
+    let nonce_before = provider
+        .get_transaction_count(micro_oz.address(), None)
+        .await?;
+
+    let (app, local_addr) = spawn_app(options.clone())
+        .await
+        .expect("Failed to spawn app.");
+
+    let identities: Vec<Field> = generate_test_identities(batch_size)
+        .iter()
+        .map(|identity| Hash::from_str_radix(identity, 16).unwrap())
+        .collect();
+
+    let uri = "http://".to_owned() + &local_addr.to_string();
+    let client = Client::new();
+
+    for leaf_index in 0..batch_size {
+        test_insert_identity(&uri, &client, &mut ref_tree, &identities, leaf_index).await;
+    }
+
+    for (leaf_index, identity) in identities.iter().enumerate() {
+        test_inclusion_proof(&uri, &client, leaf_index, &ref_tree, identity, false).await;
+    }
+
+    // The batch was mined on chain, sent from the operator's account.
+    let identity_manager = Contract::new(
+        mock_chain.identity_manager.address(),
+        parse_abi(&["function latestRoot() public view virtual returns (uint256 root)"])?,
+        mock_chain.identity_manager.client(),
+    );
+    let latest_root = identity_manager
+        .method::<_, U256>("latestRoot", ())?
+        .call()
+        .await?;
+    assert_eq!(latest_root, ref_tree.root().into());
+    assert!(
+        provider
+            .get_transaction_count(micro_oz.address(), None)
+            .await?
+            > nonce_before
+    );
+
+    shutdown();
+    app.await.unwrap();
+    reset_shutdown();
+
+    Ok(())
+}
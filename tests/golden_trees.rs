@@ -0,0 +1,237 @@
+//! Checks the tree against recorded vectors of leaf sets and the roots and
+//! proofs the contract and the circuits expect for them, for the Poseidon hash
+//! of the contract and the MiMC hash of semaphore-rs. A failure here means
+//! roots are no longer compatible with the contract, so the vectors in
+//! `tests/fixtures/golden` must never be updated to make it pass. They are
+//! produced by `tests/fixtures/golden/generate.py`, which computes the trees
+//! with its own Poseidon and MiMC, independently of the sequencer and of
+//! semaphore-rs.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use semaphore::merkle_tree::{Branch, Hasher, MerkleTree, Proof};
+use semaphore::mimc_hash;
+use semaphore::mimc_tree::MimcHash;
+use semaphore::poseidon_tree::PoseidonHash;
+use serde::Deserialize;
+use signup_sequencer::identity_tree::{
+    AppHasher, CanonicalTreeBuilder, Hash, TreeHasher, TreeVersionReadOps,
+};
+
+#[derive(Debug, Deserialize)]
+struct GoldenFile {
+    hasher:  String,
+    vectors: Vec<GoldenVector>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoldenVector {
+    name:   String,
+    depth:  usize,
+    leaves: Vec<Hash>,
+    root:   Hash,
+    proofs: Vec<GoldenProof>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoldenProof {
+    leaf_index: usize,
+    proof:      Vec<GoldenBranch>,
+}
+
+/// A branch as served by `/inclusionProof`: `Left` if the node on the path is
+/// the left child, with its sibling.
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+enum GoldenBranch {
+    Left(Hash),
+    Right(Hash),
+}
+
+fn golden_proof<H: Hasher>(
+    proof: &Proof<H>,
+    to_field: impl Fn(&H::Hash) -> Hash,
+) -> Vec<GoldenBranch> {
+    proof
+        .0
+        .iter()
+        .map(|branch| match branch {
+            Branch::Left(sibling) => GoldenBranch::Left(to_field(sibling)),
+            Branch::Right(sibling) => GoldenBranch::Right(to_field(sibling)),
+        })
+        .collect()
+}
+
+/// The MiMC hash of semaphore-rs on field elements, for the tree versions to
+/// hash with.
+#[derive(Clone)]
+struct MimcFieldHash;
+
+impl Hasher for MimcFieldHash {
+    type Hash = Hash;
+
+    fn hash_node(left: &Hash, right: &Hash) -> Hash {
+        mimc_hash::hash(&[*left, *right])
+    }
+}
+
+impl TreeHasher for MimcFieldHash {}
+
+/// A hasher of the reference trees, whose hashes needn't be field elements.
+trait ReferenceHasher: Hasher {
+    fn from_field(hash: Hash) -> Self::Hash;
+
+    fn to_field(hash: &Self::Hash) -> Hash;
+}
+
+impl ReferenceHasher for PoseidonHash {
+    fn from_field(hash: Hash) -> Self::Hash {
+        hash
+    }
+
+    fn to_field(hash: &Self::Hash) -> Hash {
+        *hash
+    }
+}
+
+impl ReferenceHasher for MimcHash {
+    fn from_field(hash: Hash) -> Self::Hash {
+        hash.to_be_bytes()
+    }
+
+    fn to_field(hash: &Self::Hash) -> Hash {
+        Hash::from_be_bytes(*hash)
+    }
+}
+
+fn golden_files() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/golden");
+
+    let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("Failed to read the golden vectors")
+        .map(|entry| entry.expect("Failed to read the golden vectors").path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    files.sort();
+
+    assert!(!files.is_empty(), "No golden vectors in {}", dir.display());
+
+    files
+}
+
+fn load(path: &Path) -> GoldenFile {
+    let contents = fs::read_to_string(path)
+        .unwrap_or_else(|error| panic!("Failed to read {}: {error}", path.display()));
+
+    serde_json::from_str(&contents)
+        .unwrap_or_else(|error| panic!("Failed to parse {}: {error}", path.display()))
+}
+
+/// Checks the vector against the tree versions the sequencer serves proofs
+/// from, with the leaves split between the dense prefix and the sparse part.
+fn check_sequencer_tree<H: TreeHasher>(vector: &GoldenVector) {
+    for dense_prefix_depth in [vector.depth.min(4), vector.depth.min(10)] {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (tree, _) = CanonicalTreeBuilder::<H>::new_with_hasher(
+            vector.depth,
+            dense_prefix_depth,
+            0,
+            Hash::ZERO,
+            &vector.leaves,
+            temp_dir.path().join("golden").to_str().unwrap(),
+        )
+        .seal();
+
+        assert_eq!(
+            tree.get_root(),
+            vector.root,
+            "{}: root with a dense prefix of depth {dense_prefix_depth}",
+            vector.name
+        );
+
+        for expected in &vector.proofs {
+            let (leaf, root, proof) = tree.get_leaf_and_proof(expected.leaf_index);
+            let proof = golden_proof(&proof, |hash| *hash);
+
+            assert_eq!(
+                leaf,
+                vector
+                    .leaves
+                    .get(expected.leaf_index)
+                    .copied()
+                    .unwrap_or(Hash::ZERO),
+                "{}: leaf {}",
+                vector.name,
+                expected.leaf_index
+            );
+            assert_eq!(root, vector.root, "{}: proof root", vector.name);
+            assert_eq!(
+                proof, expected.proof,
+                "{}: proof of leaf {} with a dense prefix of depth {dense_prefix_depth}",
+                vector.name, expected.leaf_index
+            );
+        }
+    }
+}
+
+/// Checks the vector against the reference implementation the tests build
+/// expected proofs with.
+fn check_reference_tree<H: ReferenceHasher>(vector: &GoldenVector) {
+    // The depth of the reference tree counts the leaves as a level.
+    let mut tree = MerkleTree::<H>::new(vector.depth + 1, H::from_field(Hash::ZERO));
+    for (leaf_index, leaf) in vector.leaves.iter().enumerate() {
+        tree.set(leaf_index, H::from_field(*leaf));
+    }
+
+    assert_eq!(
+        H::to_field(&tree.root()),
+        vector.root,
+        "{}: reference root",
+        vector.name
+    );
+
+    for expected in &vector.proofs {
+        let proof = tree.proof(expected.leaf_index).unwrap();
+        let proof = golden_proof(&proof, H::to_field);
+
+        assert_eq!(
+            proof, expected.proof,
+            "{}: reference proof of leaf {}",
+            vector.name, expected.leaf_index
+        );
+    }
+}
+
+#[test]
+fn trees_match_golden_vectors() {
+    let mut hashers = Vec::new();
+
+    for path in golden_files() {
+        let golden = load(&path);
+        assert!(!golden.vectors.is_empty(), "{}: no vectors", path.display());
+
+        for vector in &golden.vectors {
+            match golden.hasher.as_str() {
+                "poseidon" => {
+                    check_sequencer_tree::<AppHasher>(vector);
+                    check_reference_tree::<PoseidonHash>(vector);
+                }
+                "mimc" => {
+                    check_sequencer_tree::<MimcFieldHash>(vector);
+                    check_reference_tree::<MimcHash>(vector);
+                }
+                hasher => panic!("{}: unsupported hasher {hasher}", path.display()),
+            }
+        }
+
+        hashers.push(golden.hasher);
+    }
+
+    hashers.sort();
+    assert_eq!(hashers, ["mimc", "poseidon"], "golden files of each hasher");
+}
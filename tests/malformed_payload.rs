@@ -3,8 +3,8 @@ mod common;
 use common::prelude::*;
 use hyper::StatusCode;
 
-/// Tests that the app rejects payloads which are too large or are not valid
-/// UTF-8 strings
+/// Tests that the app rejects payloads which are too large, are not valid
+/// UTF-8 strings or don't match the request type
 #[tokio::test]
 async fn malformed_payload() -> anyhow::Result<()> {
     init_tracing_subscriber();
@@ -67,7 +67,7 @@ async fn malformed_payload() -> anyhow::Result<()> {
     options.server.server = Url::parse("http://127.0.0.1:0/")?;
 
     options.app.contracts.identity_manager_address = mock_chain.identity_manager.address();
-    options.app.ethereum.ethereum_provider = Url::parse(&mock_chain.anvil.endpoint())?;
+    options.app.ethereum.ethereum_provider = mock_chain.anvil.endpoint().parse()?;
 
     let (app, local_addr) = spawn_app(options.clone())
         .await
@@ -104,6 +104,39 @@ async fn malformed_payload() -> anyhow::Result<()> {
 
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
 
+    // Payloads which don't match the request type are rejected with details
+    let unknown_field_payload = Request::builder()
+        .method("POST")
+        .uri(format!("{uri}/insertIdentity"))
+        .header("Content-Type", "application/json")
+        .body(Body::from(r#"{"identityCommitment": "0x1", "foo": 1}"#))
+        .unwrap();
+
+    let response = client.request(unknown_field_payload).await?;
+
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let bytes = hyper::body::to_bytes(response.into_body()).await?;
+    let body: serde_json::Value = serde_json::from_slice(&bytes)?;
+
+    assert_eq!(body["errors"][0]["field"], "foo");
+
+    // Deeply nested payloads are rejected before parsing
+    let nested_payload = Request::builder()
+        .method("POST")
+        .uri(format!("{uri}/insertIdentity"))
+        .header("Content-Type", "application/json")
+        .body(Body::from(format!(
+            "{}{}",
+            "[".repeat(1000),
+            "]".repeat(1000)
+        )))
+        .unwrap();
+
+    let response = client.request(nested_payload).await?;
+
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
     shutdown();
     app.await?;
     for (_, prover) in insertion_prover_map.into_iter() {
@@ -0,0 +1,123 @@
+//! Checks the MiMC sponge of semaphore-rs against the round constants and
+//! hashes in `tests/fixtures/mimcsponge.json`. Random inputs, which no vector
+//! covers, are checked against a reference sponge computed with arbitrary
+//! precision integers from the round constants of the same file, so that a
+//! regression in the field arithmetic of semaphore-rs shows up whatever the
+//! inputs. The vectors have yet to be regenerated with circomlibjs by
+//! `tests/fixtures/mimcsponge.js`, which must leave them unchanged. Like the
+//! golden trees, they must never be updated to make these pass.
+
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use num_bigint::BigUint;
+use proptest::prelude::*;
+use ruint::aliases::U256;
+use ruint::uint;
+use semaphore::mimc_hash::hash;
+use serde::Deserialize;
+
+const MODULUS: U256 =
+    uint!(21888242871839275222246405745257275088548364400416034343698204186575808495617_U256);
+
+#[derive(Debug, Deserialize)]
+struct Fixture {
+    constants: Vec<U256>,
+    vectors:   Vec<Vector>,
+}
+
+/// A hash of the sponge with a zero key and a single output, as circomlibjs'
+/// `multiHash` computes it.
+#[derive(Debug, Deserialize)]
+struct Vector {
+    inputs: Vec<U256>,
+    hash:   U256,
+}
+
+fn fixture() -> &'static Fixture {
+    static FIXTURE: OnceLock<Fixture> = OnceLock::new();
+
+    FIXTURE.get_or_init(|| {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/mimcsponge.json");
+        let contents = fs::read_to_string(&path)
+            .unwrap_or_else(|error| panic!("Failed to read {}: {error}", path.display()));
+
+        serde_json::from_str(&contents)
+            .unwrap_or_else(|error| panic!("Failed to parse {}: {error}", path.display()))
+    })
+}
+
+fn to_big(value: &U256) -> BigUint {
+    BigUint::from_bytes_be(&value.to_be_bytes::<32>())
+}
+
+fn from_big(value: &BigUint) -> U256 {
+    U256::try_from_be_slice(&value.to_bytes_be()).expect("reduced values fit")
+}
+
+/// The sponge of circomlibjs, absorbing every input into the left half of the
+/// state before a Feistel permutation with the round constants of the fixture.
+fn reference_hash(inputs: &[U256]) -> U256 {
+    let modulus = to_big(&MODULUS);
+    let constants: Vec<BigUint> = fixture().constants.iter().map(to_big).collect();
+    let last_round = constants.len() - 1;
+
+    let mut left = BigUint::default();
+    let mut right = BigUint::default();
+    for input in inputs {
+        left = (left + to_big(input)) % &modulus;
+
+        for (round, constant) in constants.iter().enumerate() {
+            let t = (&left + constant).modpow(&BigUint::from(5_u32), &modulus);
+
+            // The last round doesn't swap the halves.
+            if round < last_round {
+                (left, right) = ((right + t) % &modulus, left);
+            } else {
+                right = (right + t) % &modulus;
+            }
+        }
+    }
+
+    from_big(&left)
+}
+
+fn field_element() -> impl Strategy<Value = U256> {
+    any::<[u64; 4]>().prop_map(|limbs| U256::from_limbs(limbs) % MODULUS)
+}
+
+#[test]
+fn hash_matches_the_vectors() {
+    assert!(!fixture().vectors.is_empty(), "No MiMC vectors");
+
+    for vector in &fixture().vectors {
+        assert_eq!(
+            hash(&vector.inputs),
+            vector.hash,
+            "inputs {:?}",
+            vector.inputs
+        );
+    }
+}
+
+#[test]
+fn reference_matches_the_vectors() {
+    assert_eq!(fixture().constants.len(), 220, "MiMC sponge rounds");
+
+    for vector in &fixture().vectors {
+        assert_eq!(
+            reference_hash(&vector.inputs),
+            vector.hash,
+            "inputs {:?}",
+            vector.inputs
+        );
+    }
+}
+
+proptest! {
+    #[test]
+    fn hash_matches_the_reference(inputs in prop::collection::vec(field_element(), 0..8)) {
+        prop_assert_eq!(hash(&inputs), reference_hash(&inputs));
+    }
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use common::prelude::*;
+
+const SUPPORTED_DEPTH: usize = 20;
+const IDLE_TIME: u64 = 7;
+
+/// Identities which were mined but are still queued, e.g. because they were
+/// resubmitted after a crash, are dropped from the queue on startup instead of
+/// being batched again.
+#[tokio::test]
+async fn startup_reconciliation() -> anyhow::Result<()> {
+    init_tracing_subscriber();
+    info!("Starting startup reconciliation test");
+
+    let batch_size: usize = 3;
+    #[allow(clippy::cast_possible_truncation)]
+    let tree_depth: u8 = SUPPORTED_DEPTH as u8;
+
+    let mut ref_tree = PoseidonTree::new(SUPPORTED_DEPTH + 1, ruint::Uint::ZERO);
+    let initial_root: U256 = ref_tree.root().into();
+
+    let (mock_chain, db_container, insertion_prover_map, _, micro_oz) =
+        spawn_deps(initial_root, &[batch_size], &[], tree_depth).await?;
+
+    let prover_mock = &insertion_prover_map[&batch_size];
+
+    let db_socket_addr = db_container.address();
+    let db_url = format!("postgres://postgres:postgres@{db_socket_addr}/database");
+
+    let temp_dir = tempfile::tempdir()?;
+
+    let mut options = Options::try_parse_from([
+        "signup-sequencer",
+        "--identity-manager-address",
+        "0x0000000000000000000000000000000000000000", // placeholder, updated below
+        "--database",
+        &db_url,
+        "--database-max-connections",
+        "1",
+        "--tree-depth",
+        &format!("{tree_depth}"),
+        "--prover-urls",
+        &prover_mock.arg_string(),
+        "--batch-timeout-seconds",
+        "10",
+        "--dense-tree-prefix-depth",
+        "10",
+        "--tree-gc-threshold",
+        "1",
+        "--oz-api-key",
+        "",
+        "--oz-api-secret",
+        "",
+        "--oz-api-url",
+        &micro_oz.endpoint(),
+        "--oz-address",
+        &format!("{:?}", micro_oz.address()),
+        "--time-between-scans-seconds",
+        "1",
+        "--dense-tree-mmap-file",
+        temp_dir.path().join("testfile").to_str().unwrap(),
+    ])
+    .context("Failed to create options")?;
+
+    options.server.server = Url::parse("http://127.0.0.1:0/")?;
+
+    options.app.contracts.identity_manager_address = mock_chain.identity_manager.address();
+    options.app.ethereum.ethereum_provider = mock_chain.anvil.endpoint().parse()?;
+
+    let (app, local_addr) = spawn_app(options.clone())
+        .await
+        .expect("Failed to spawn app.");
+
+    let identities: Vec<Field> = generate_test_identities(batch_size)
+        .iter()
+        .map(|identity| Hash::from_str_radix(identity, 16).unwrap())
+        .collect();
+
+    let uri = "http://".to_owned() + &local_addr.to_string();
+    let client = Client::new();
+
+    for leaf_index in 0..batch_size {
+        test_insert_identity(&uri, &client, &mut ref_tree, &identities, leaf_index).await;
+    }
+
+    tokio::time::sleep(Duration::from_secs(IDLE_TIME)).await;
+
+    for (leaf_index, identity) in identities.iter().enumerate() {
+        test_inclusion_proof(&uri, &client, leaf_index, &ref_tree, identity, false).await;
+    }
+
+    shutdown();
+    app.await.unwrap();
+    reset_shutdown();
+
+    // Queue the mined identities again, as if they had been resubmitted
+    // while the batch was in flight.
+    let pool = sqlx::PgPool::connect(&db_url).await?;
+    for identity in &identities {
+        sqlx::query(
+            "INSERT INTO unprocessed_identities (commitment, status, created_at, eligibility) \
+             VALUES ($1, 'new', CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)",
+        )
+        .bind(identity)
+        .execute(&pool)
+        .await?;
+    }
+
+    let (app, local_addr) = spawn_app(options.clone())
+        .await
+        .expect("Failed to spawn app.");
+    let uri = "http://".to_owned() + &local_addr.to_string();
+
+    tokio::time::sleep(Duration::from_secs(IDLE_TIME)).await;
+
+    let queued: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM unprocessed_identities")
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(queued, 0, "Mined identities are still queued");
+
+    // None of them was batched a second time.
+    for (leaf_index, identity) in identities.iter().enumerate() {
+        let inserted: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM identities WHERE commitment = $1")
+                .bind(identity)
+                .fetch_one(&pool)
+                .await?;
+        assert_eq!(inserted, 1);
+
+        test_inclusion_proof(&uri, &client, leaf_index, &ref_tree, identity, false).await;
+    }
+
+    shutdown();
+    app.await.unwrap();
+    reset_shutdown();
+
+    Ok(())
+}
@@ -72,8 +72,11 @@ async fn validate_proof_with_age() -> anyhow::Result<()> {
     options.server.server = Url::parse("http://127.0.0.1:0/").expect("Failed to parse URL");
 
     options.app.contracts.identity_manager_address = mock_chain.identity_manager.address();
-    options.app.ethereum.ethereum_provider =
-        Url::parse(&mock_chain.anvil.endpoint()).expect("Failed to parse ganache endpoint");
+    options.app.ethereum.ethereum_provider = mock_chain
+        .anvil
+        .endpoint()
+        .parse()
+        .expect("Failed to parse ganache endpoint");
 
     let (app, local_addr) = spawn_app(options.clone())
         .await